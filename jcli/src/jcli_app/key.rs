@@ -7,6 +7,7 @@ use chain_crypto::{
 };
 use ed25519_bip32::{DerivationError, DerivationScheme};
 use hex::FromHexError;
+use jormungandr_lib::crypto::secret_file;
 use rand::{rngs::OsRng, SeedableRng};
 use rand_chacha::ChaChaRng;
 use std::{
@@ -57,6 +58,10 @@ pub enum Error {
     },
     #[error("signature verification failed")]
     SignatureVerification,
+    #[error("environment variable {name} holding the passphrase is not set")]
+    PassphraseEnvNotSet { name: String },
+    #[error("input is already an encrypted secret file")]
+    AlreadyEncrypted,
     #[error("failed to derive from BIP32 public key")]
     Derivation(#[from] DerivationError),
     #[error("ed25519bip32 key expected, signature bech32 has invalid HRP: '{actual_hrp}', expected: '{public_hrp}' or '{private_hrp}'")]
@@ -84,6 +89,8 @@ pub enum Key {
     Verify(Verify),
     /// derive a child key from a ed25519bip32 parent key
     Derive(Derive),
+    /// encrypt a node secret file with a passphrase
+    EncryptSecret(EncryptSecret),
 }
 
 #[derive(StructOpt, Debug)]
@@ -193,6 +200,23 @@ pub struct Derive {
     child_key: OutputFile,
 }
 
+#[derive(StructOpt, Debug)]
+pub struct EncryptSecret {
+    /// path to the node secret YAML file to encrypt, if no value is passed,
+    /// standard input will be used
+    #[structopt(long = "input")]
+    input: Option<PathBuf>,
+
+    /// name of the environment variable holding the passphrase
+    #[structopt(long = "passphrase-env")]
+    passphrase_env: String,
+
+    /// path to file to write the encrypted secret into, if no value is passed,
+    /// standard output will be used
+    #[structopt(long = "output", short = "o")]
+    output: Option<PathBuf>,
+}
+
 #[derive(StructOpt, Debug)]
 struct OutputFile {
     /// output the key to the given file or to stdout if not provided
@@ -230,6 +254,7 @@ impl Key {
             Key::Sign(args) => args.exec(),
             Key::Verify(args) => args.exec(),
             Key::Derive(args) => args.exec(),
+            Key::EncryptSecret(args) => args.exec(),
         }
     }
 }
@@ -414,6 +439,23 @@ impl Derive {
     }
 }
 
+impl EncryptSecret {
+    fn exec(self) -> Result<(), Error> {
+        let passphrase =
+            std::env::var(&self.passphrase_env).map_err(|_| Error::PassphraseEnvNotSet {
+                name: self.passphrase_env.clone(),
+            })?;
+        let mut secret = Vec::new();
+        io::open_file_read(&self.input)?.read_to_end(&mut secret)?;
+        if secret_file::is_encrypted(&secret) {
+            return Err(Error::AlreadyEncrypted);
+        }
+        let encrypted = secret_file::encrypt(&mut OsRng, passphrase.as_bytes(), &secret);
+        io::open_file_write(&self.output)?.write_all(&encrypted)?;
+        Ok(())
+    }
+}
+
 fn read_hex<P: AsRef<Path>>(path: &Option<P>) -> Result<Vec<u8>, Error> {
     hex::decode(io::read_line(path)?).map_err(Into::into)
 }
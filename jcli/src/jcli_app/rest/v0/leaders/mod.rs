@@ -1,3 +1,4 @@
+use super::with_admin_token;
 use crate::jcli_app::rest::Error;
 use crate::jcli_app::utils::{io, DebugFlag, HostAddr, OutputFormat, RestApiSender};
 use std::path::PathBuf;
@@ -26,6 +27,9 @@ pub enum Leaders {
         /// If not provided, YAML will be read from stdin.
         #[structopt(short, long)]
         file: Option<PathBuf>,
+        /// File containing the REST admin token of the node
+        #[structopt(long)]
+        admin_token_file: Option<PathBuf>,
     },
     /// Delete leader
     Delete {
@@ -35,6 +39,9 @@ pub enum Leaders {
         debug: DebugFlag,
         /// ID of deleted leader
         id: u32,
+        /// File containing the REST admin token of the node
+        #[structopt(long)]
+        admin_token_file: Option<PathBuf>,
     },
 
     /// Leadership log operations
@@ -63,8 +70,18 @@ impl Leaders {
                 debug,
                 output_format,
             } => get(addr, debug, output_format),
-            Leaders::Post { addr, debug, file } => post(addr, debug, file),
-            Leaders::Delete { id, addr, debug } => delete(addr, debug, id),
+            Leaders::Post {
+                addr,
+                debug,
+                file,
+                admin_token_file,
+            } => post(addr, debug, file, admin_token_file),
+            Leaders::Delete {
+                id,
+                addr,
+                debug,
+                admin_token_file,
+            } => delete(addr, debug, id, admin_token_file),
             Leaders::Logs(GetLogs::Get {
                 addr,
                 debug,
@@ -85,9 +102,14 @@ fn get(addr: HostAddr, debug: DebugFlag, output_format: OutputFormat) -> Result<
     Ok(())
 }
 
-fn post(addr: HostAddr, debug: DebugFlag, file: Option<PathBuf>) -> Result<(), Error> {
+fn post(
+    addr: HostAddr,
+    debug: DebugFlag,
+    file: Option<PathBuf>,
+    admin_token_file: Option<PathBuf>,
+) -> Result<(), Error> {
     let url = addr.with_segments(&["v0", "leaders"])?.into_url();
-    let builder = reqwest::Client::new().post(url);
+    let builder = with_admin_token(reqwest::Client::new().post(url), admin_token_file)?;
     let input: serde_json::Value = io::read_yaml(&file)?;
     let response = RestApiSender::new(builder, &debug)
         .with_json_body(&input)?
@@ -97,11 +119,16 @@ fn post(addr: HostAddr, debug: DebugFlag, file: Option<PathBuf>) -> Result<(), E
     Ok(())
 }
 
-fn delete(addr: HostAddr, debug: DebugFlag, id: u32) -> Result<(), Error> {
+fn delete(
+    addr: HostAddr,
+    debug: DebugFlag,
+    id: u32,
+    admin_token_file: Option<PathBuf>,
+) -> Result<(), Error> {
     let url = addr
         .with_segments(&["v0", "leaders", &id.to_string()])?
         .into_url();
-    let builder = reqwest::Client::new().delete(url);
+    let builder = with_admin_token(reqwest::Client::new().delete(url), admin_token_file)?;
     let response = RestApiSender::new(builder, &debug).send()?;
     response.ok_response()?;
     println!("Success");
@@ -14,6 +14,7 @@ mod tip;
 mod utxo;
 
 use crate::jcli_app::rest::Error;
+use std::path::PathBuf;
 use structopt::StructOpt;
 
 #[derive(StructOpt)]
@@ -69,3 +70,17 @@ impl V0 {
         }
     }
 }
+
+/// authenticate the request with the REST admin token read from the file
+pub fn with_admin_token(
+    builder: reqwest::RequestBuilder,
+    admin_token_file: Option<PathBuf>,
+) -> Result<reqwest::RequestBuilder, Error> {
+    match admin_token_file {
+        Some(path) => {
+            let token = std::fs::read_to_string(path)?;
+            Ok(builder.bearer_auth(token.trim()))
+        }
+        None => Ok(builder),
+    }
+}
@@ -1,6 +1,8 @@
 mod stats;
+mod trusted_peers;
 
 use self::stats::Stats;
+use self::trusted_peers::TrustedPeers;
 use crate::jcli_app::rest::Error;
 use structopt::StructOpt;
 
@@ -9,12 +11,15 @@ use structopt::StructOpt;
 pub enum Network {
     /// Network information
     Stats(Stats),
+    /// Trusted peers operations
+    TrustedPeers(TrustedPeers),
 }
 
 impl Network {
     pub fn exec(self) -> Result<(), Error> {
         match self {
             Network::Stats(stats) => stats.exec(),
+            Network::TrustedPeers(trusted_peers) => trusted_peers.exec(),
         }
     }
 }
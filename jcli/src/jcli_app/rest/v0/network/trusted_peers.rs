@@ -0,0 +1,47 @@
+use super::super::with_admin_token;
+use crate::jcli_app::rest::Error;
+use crate::jcli_app::utils::{io, DebugFlag, HostAddr, RestApiSender};
+use std::path::PathBuf;
+use structopt::StructOpt;
+
+#[derive(StructOpt)]
+#[structopt(rename_all = "kebab-case")]
+pub enum TrustedPeers {
+    /// Replace the trusted peers of the node
+    Put {
+        #[structopt(flatten)]
+        addr: HostAddr,
+        #[structopt(flatten)]
+        debug: DebugFlag,
+        /// File containing YAML with the list of trusted peers.
+        /// It must have the same format as `p2p.trusted_peers` in the node configuration.
+        /// If not provided, YAML will be read from stdin.
+        #[structopt(short, long)]
+        file: Option<PathBuf>,
+        /// File containing the REST admin token of the node
+        #[structopt(long)]
+        admin_token_file: Option<PathBuf>,
+    },
+}
+
+impl TrustedPeers {
+    pub fn exec(self) -> Result<(), Error> {
+        let TrustedPeers::Put {
+            addr,
+            debug,
+            file,
+            admin_token_file,
+        } = self;
+        let url = addr
+            .with_segments(&["v0", "network", "p2p", "trusted_peers"])?
+            .into_url();
+        let builder = with_admin_token(reqwest::Client::new().put(url), admin_token_file)?;
+        let input: serde_json::Value = io::read_yaml(&file)?;
+        let response = RestApiSender::new(builder, &debug)
+            .with_json_body(&input)?
+            .send()?;
+        response.ok_response()?;
+        println!("Success");
+        Ok(())
+    }
+}
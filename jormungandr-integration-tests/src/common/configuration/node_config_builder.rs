@@ -1,11 +1,13 @@
 #![allow(dead_code)]
 
 use super::file_utils;
+use chain_crypto::Ed25519;
 use std::path::PathBuf;
 
+use jormungandr_lib::crypto::{key::SigningKey, node_id::node_id};
 use jormungandr_lib::interfaces::{
-    Explorer, Log, LogEntry, LogOutput, Mempool, NodeConfig, P2p, Rest, TopicsOfInterest,
-    TrustedPeer,
+    Explorer, InterestLevel, Log, LogEntry, LogOutput, Mempool, NodeConfig, P2p, Rest,
+    TopicsOfInterest, TrustedPeer,
 };
 
 #[derive(Debug, Clone)]
@@ -26,7 +28,10 @@ impl NodeConfigBuilder {
         let public_address_port = super::get_available_port();
         let listen_address_port = super::get_available_port();
         let storage_file = file_utils::get_path_in_temp("storage");
-        let public_id = poldercast::Id::generate(rand::rngs::OsRng);
+        let gossip_key = SigningKey::<Ed25519>::generate(rand::rngs::OsRng);
+        let gossip_key_file =
+            file_utils::create_file_in_temp("gossip_key.sk", &gossip_key.to_bech32_str());
+        let public_id = node_id(&gossip_key.identifier().into_public_key());
         let log = Some(Log(vec![LogEntry {
             level: "info".to_string(),
             format: "json".to_string(),
@@ -60,10 +65,11 @@ impl NodeConfigBuilder {
                 trusted_peers: vec![],
                 public_address: grpc_public_address,
                 public_id: public_id.clone(),
+                gossip_key_file: Some(gossip_key_file),
                 listen_address: grpc_listen_address,
                 topics_of_interest: Some(TopicsOfInterest {
-                    messages: String::from("high"),
-                    blocks: String::from("high"),
+                    messages: InterestLevel::High,
+                    blocks: InterestLevel::High,
                 }),
                 allow_private_addresses: false,
             },
@@ -4,7 +4,9 @@ use crate::common::{
     process_utils, startup,
 };
 
-use jormungandr_lib::interfaces::{InitialUTxO, Mempool};
+use jormungandr_lib::interfaces::{
+    FragmentPriority, InitialUTxO, Mempool, PoolMaxBytes, PoolOverflowPolicy,
+};
 use std::time::Duration;
 
 #[test]
@@ -23,7 +25,15 @@ pub fn test_log_ttl() {
         }])
         .with_mempool(Mempool {
             pool_max_entries: 10_000usize.into(),
+            pool_max_bytes: PoolMaxBytes::default(),
+            overflow_policy: PoolOverflowPolicy::RejectNew,
             fragment_ttl: Duration::from_secs(10).into(),
+            fragment_max_retries: 100u32.into(),
+            priority: FragmentPriority::Fifo,
+            rebroadcast_delay: Duration::from_secs(300).into(),
+            rebroadcast_max_fragments: 100usize.into(),
+            rest_rate_limit: None,
+            p2p_rate_limit: None,
             log_max_entries: 100_000usize.into(),
             log_ttl: Duration::from_secs(log_ttl_timeout).into(),
             garbage_collection_interval: Duration::from_secs(garbage_collection_interval).into(),
@@ -6,7 +6,10 @@ use crate::common::{
     startup,
 };
 
-use jormungandr_lib::interfaces::{ActiveSlotCoefficient, KESUpdateSpeed, Mempool};
+use jormungandr_lib::interfaces::{
+    ActiveSlotCoefficient, FragmentPriority, KESUpdateSpeed, Mempool, PoolMaxBytes,
+    PoolOverflowPolicy,
+};
 use std::time::{Duration, SystemTime};
 
 #[test]
@@ -24,7 +27,15 @@ pub fn test_blocks_are_being_created_for_48_hours() {
             .with_kes_update_speed(KESUpdateSpeed::new(43200).unwrap())
             .with_mempool(Mempool {
                 pool_max_entries: 1_000_000usize.into(),
+                pool_max_bytes: PoolMaxBytes::default(),
+                overflow_policy: PoolOverflowPolicy::RejectNew,
                 fragment_ttl: duration_48_hours.clone().into(),
+                fragment_max_retries: 100u32.into(),
+                priority: FragmentPriority::Fifo,
+                rebroadcast_delay: Duration::from_secs(300).into(),
+                rebroadcast_max_fragments: 100usize.into(),
+                rest_rate_limit: None,
+                p2p_rate_limit: None,
                 log_max_entries: 1_000_000usize.into(),
                 log_ttl: duration_48_hours.clone().into(),
                 garbage_collection_interval: duration_48_hours.clone().into(),
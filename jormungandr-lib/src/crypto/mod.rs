@@ -24,8 +24,18 @@
 //! It provides the same interfaces as for the identifier in the
 //! `key` module but limited to Account only.
 //!
+//! # Secret files
+//!
+//! The encryption of the secret files of the nodes with a passphrase.
+//!
+//! # Node identifiers
+//!
+//! The identifiers of the P2P nodes, derived from their gossip keys.
+//!
 
 pub mod account;
 pub mod hash;
 pub mod key;
+pub mod node_id;
+pub mod secret_file;
 pub(crate) mod serde;
@@ -0,0 +1,29 @@
+//! Identifiers of the P2P nodes, derived from the key the nodes sign their
+//! gossip with, so that a node cannot advertise itself under the
+//! identifier of another one.
+
+use super::hash::Hash;
+use chain_crypto::{hash::Blake2b256, Ed25519, PublicKey};
+use rand_chacha::ChaChaRng;
+use rand_core::SeedableRng;
+
+/// the identifier of the node signing its gossip with `gossip_key`
+pub fn node_id(gossip_key: &PublicKey<Ed25519>) -> poldercast::Id {
+    let seed: [u8; 32] = Hash::from(Blake2b256::new(gossip_key.as_ref())).into();
+    poldercast::Id::generate(ChaChaRng::from_seed(seed))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chain_crypto::SecretKey;
+
+    #[test]
+    fn node_id_is_bound_to_the_key() {
+        let mut rng = ChaChaRng::from_seed([0; 32]);
+        let key = SecretKey::<Ed25519>::generate(&mut rng).to_public();
+        let other = SecretKey::<Ed25519>::generate(&mut rng).to_public();
+        assert_eq!(node_id(&key), node_id(&key));
+        assert_ne!(node_id(&key), node_id(&other));
+    }
+}
@@ -0,0 +1,177 @@
+//! Encryption of the secret files of the nodes with a passphrase
+//!
+//! The file starts with a magic number, followed by the number of
+//! iterations of the key derivation as a big-endian 32-bit integer, the
+//! salt of the key derivation and the nonce of the encryption. The rest
+//! is the encrypted content followed by its authentication tag.
+//!
+//! The key is derived from the passphrase with PBKDF2-HMAC-SHA512 and the
+//! content is encrypted with ChaCha20-Poly1305.
+
+use cryptoxide::{chacha20poly1305::ChaCha20Poly1305, hmac::Hmac, pbkdf2::pbkdf2, sha2::Sha512};
+use rand_core::{CryptoRng, RngCore};
+use std::convert::TryInto;
+use thiserror::Error;
+
+/// identifies the encrypted secret files, ends with the version of the format
+const MAGIC: &[u8; 8] = b"JORMSEC\x01";
+
+/// iterations of the key derivation of the newly encrypted files
+const ITERATIONS: u32 = 100_000;
+
+/// bounds of the iterations accepted from the header of a file: fewer is
+/// too weak (and 0 is refused by the derivation), more would hold the
+/// start of the node for an unbounded time
+const MIN_ITERATIONS: u32 = 10_000;
+const MAX_ITERATIONS: u32 = 10_000_000;
+
+const SALT_SIZE: usize = 16;
+const NONCE_SIZE: usize = 12;
+const TAG_SIZE: usize = 16;
+const KEY_SIZE: usize = 32;
+const HEADER_SIZE: usize = MAGIC.len() + 4 + SALT_SIZE + NONCE_SIZE;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("not an encrypted secret file")]
+    NotEncrypted,
+    #[error("the encrypted secret file is truncated")]
+    Truncated,
+    #[error(
+        "the encrypted secret file asks for {0} iterations of the key derivation, expected between {} and {}",
+        MIN_ITERATIONS,
+        MAX_ITERATIONS
+    )]
+    InvalidIterations(u32),
+    #[error("wrong passphrase or corrupted secret file")]
+    Authentication,
+}
+
+/// whether `data` is the content of an encrypted secret file
+pub fn is_encrypted(data: &[u8]) -> bool {
+    data.starts_with(MAGIC)
+}
+
+/// encrypt the content of a secret file with `passphrase`
+pub fn encrypt<R: RngCore + CryptoRng>(rng: &mut R, passphrase: &[u8], secret: &[u8]) -> Vec<u8> {
+    encrypt_with_iterations(rng, passphrase, secret, ITERATIONS)
+}
+
+fn encrypt_with_iterations<R: RngCore + CryptoRng>(
+    rng: &mut R,
+    passphrase: &[u8],
+    secret: &[u8],
+    iterations: u32,
+) -> Vec<u8> {
+    let mut salt = [0; SALT_SIZE];
+    rng.fill_bytes(&mut salt);
+    let mut nonce = [0; NONCE_SIZE];
+    rng.fill_bytes(&mut nonce);
+    let key = derive_key(passphrase, &salt, iterations);
+
+    let mut data = Vec::with_capacity(HEADER_SIZE + secret.len() + TAG_SIZE);
+    data.extend_from_slice(MAGIC);
+    data.extend_from_slice(&iterations.to_be_bytes());
+    data.extend_from_slice(&salt);
+    data.extend_from_slice(&nonce);
+    data.resize(HEADER_SIZE + secret.len() + TAG_SIZE, 0);
+
+    let (ciphertext, tag) = data[HEADER_SIZE..].split_at_mut(secret.len());
+    ChaCha20Poly1305::new(&key, &nonce, MAGIC).encrypt(secret, ciphertext, tag);
+    data
+}
+
+/// decrypt the content of an encrypted secret file with `passphrase`
+pub fn decrypt(passphrase: &[u8], data: &[u8]) -> Result<Vec<u8>, Error> {
+    if !is_encrypted(data) {
+        return Err(Error::NotEncrypted);
+    }
+    if data.len() < HEADER_SIZE + TAG_SIZE {
+        return Err(Error::Truncated);
+    }
+    let (header, body) = data.split_at(HEADER_SIZE);
+    let iterations = u32::from_be_bytes(header[MAGIC.len()..MAGIC.len() + 4].try_into().unwrap());
+    check_iterations(iterations)?;
+    let salt = &header[MAGIC.len() + 4..MAGIC.len() + 4 + SALT_SIZE];
+    let nonce = &header[MAGIC.len() + 4 + SALT_SIZE..];
+    let (ciphertext, tag) = body.split_at(body.len() - TAG_SIZE);
+
+    let key = derive_key(passphrase, salt, iterations);
+    let mut secret = vec![0; ciphertext.len()];
+    if ChaCha20Poly1305::new(&key, nonce, MAGIC).decrypt(ciphertext, &mut secret, tag) {
+        Ok(secret)
+    } else {
+        Err(Error::Authentication)
+    }
+}
+
+fn check_iterations(iterations: u32) -> Result<(), Error> {
+    if (MIN_ITERATIONS..=MAX_ITERATIONS).contains(&iterations) {
+        Ok(())
+    } else {
+        Err(Error::InvalidIterations(iterations))
+    }
+}
+
+fn derive_key(passphrase: &[u8], salt: &[u8], iterations: u32) -> [u8; KEY_SIZE] {
+    let mut key = [0; KEY_SIZE];
+    let mut mac = Hmac::new(Sha512::new(), passphrase);
+    pbkdf2(&mut mac, salt, iterations, &mut key);
+    key
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rand_chacha::ChaChaRng;
+    use rand_core::SeedableRng;
+
+    #[test]
+    fn encrypt_decrypt() {
+        let mut rng = ChaChaRng::from_seed([0; 32]);
+        let secret = b"bft:\n  signing_key: ed25519_sk1...\n";
+        let data = encrypt(&mut rng, b"passphrase", secret);
+
+        assert!(is_encrypted(&data));
+        assert_eq!(decrypt(b"passphrase", &data).unwrap(), secret.to_vec());
+        assert!(decrypt(b"wrong passphrase", &data).is_err());
+        assert!(decrypt(b"passphrase", &data[..HEADER_SIZE]).is_err());
+        assert!(decrypt(b"passphrase", secret).is_err());
+    }
+
+    #[test]
+    fn iterations_at_the_bounds_are_accepted() {
+        assert!(check_iterations(MIN_ITERATIONS).is_ok());
+        assert!(check_iterations(MAX_ITERATIONS).is_ok());
+
+        let mut rng = ChaChaRng::from_seed([0; 32]);
+        let data = encrypt_with_iterations(&mut rng, b"passphrase", b"secret", MIN_ITERATIONS);
+        assert_eq!(decrypt(b"passphrase", &data).unwrap(), b"secret".to_vec());
+    }
+
+    #[test]
+    fn iterations_below_the_minimum_are_refused() {
+        let mut rng = ChaChaRng::from_seed([0; 32]);
+        for iterations in &[0, MIN_ITERATIONS - 1] {
+            let mut data = encrypt_with_iterations(&mut rng, b"passphrase", b"secret", 1);
+            data[MAGIC.len()..MAGIC.len() + 4].copy_from_slice(&iterations.to_be_bytes());
+            match decrypt(b"passphrase", &data) {
+                Err(Error::InvalidIterations(n)) => assert_eq!(n, *iterations),
+                other => panic!("unexpected result: {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn iterations_above_the_maximum_are_refused() {
+        let mut rng = ChaChaRng::from_seed([0; 32]);
+        for iterations in &[MAX_ITERATIONS + 1, u32::MAX] {
+            let mut data = encrypt_with_iterations(&mut rng, b"passphrase", b"secret", 1);
+            data[MAGIC.len()..MAGIC.len() + 4].copy_from_slice(&iterations.to_be_bytes());
+            match decrypt(b"passphrase", &data) {
+                Err(Error::InvalidIterations(n)) => assert_eq!(n, *iterations),
+                other => panic!("unexpected result: {:?}", other),
+            }
+        }
+    }
+}
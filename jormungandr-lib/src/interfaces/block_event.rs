@@ -0,0 +1,49 @@
+use crate::{
+    crypto::hash::Hash,
+    interfaces::BlockDate,
+    time::{Duration, SystemTime},
+};
+use serde::{Deserialize, Serialize};
+
+/// identify where an applied block came from
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BlockEventOrigin {
+    /// the block was created by one of the node's own leaders
+    Leadership,
+    /// the block was received from the network. The peer's node
+    /// identifier is known when the block was pulled from one of our
+    /// client connections.
+    Network { peer: Option<String> },
+}
+
+/// compact record of a block being applied to the node's blockchain.
+///
+/// One record is kept per applied block so it is possible to
+/// reconstruct what the node received, from whom and how long it took
+/// to validate, without having to run the node with debug logs.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BlockEvent {
+    pub hash: Hash,
+    pub parent: Hash,
+    pub date: BlockDate,
+    pub chain_length: u32,
+    /// time the block was received by the block task
+    pub received_at: SystemTime,
+    pub origin: BlockEventOrigin,
+    /// time spent checking and applying the block
+    pub validation_duration: Duration,
+    pub fragment_count: usize,
+    /// size of the serialized block, in bytes
+    pub size: usize,
+    /// the node's tip when the block was applied. If it matches `parent`
+    /// the block was appended to the main branch, otherwise to a fork.
+    pub tip: Hash,
+}
+
+impl BlockEvent {
+    #[inline]
+    pub fn extends_tip(&self) -> bool {
+        self.parent == self.tip
+    }
+}
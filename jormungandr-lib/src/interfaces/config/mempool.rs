@@ -4,18 +4,95 @@ use serde::{Deserialize, Serialize};
 #[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize, Serialize)]
 pub struct PoolMaxEntries(usize);
 
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize, Serialize)]
+pub struct PoolMaxBytes(usize);
+
 #[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize, Serialize)]
 pub struct LogMaxEntries(usize);
 
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize, Serialize)]
+pub struct FragmentMaxRetries(u32);
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize, Serialize)]
+pub struct RebroadcastMaxFragments(usize);
+
+/// order in which the fragments of the pool are selected for a block
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FragmentPriority {
+    /// the oldest fragments first
+    Fifo,
+    /// the fragments paying the highest fee per byte first
+    FeePerByte,
+    /// the fragments paying the highest fee per byte first, the fee of a
+    /// fragment weighing up to twice as much as it gets older, until the
+    /// end of its time to live
+    AgeAndFee,
+}
+
+/// what to do with a new fragment when the mempool is full
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PoolOverflowPolicy {
+    /// the new fragment is not accepted
+    RejectNew,
+    /// the fragments paying the lowest fee per byte are evicted to make
+    /// room for the new fragment, if it pays more per byte than them
+    EvictLowestFee,
+    /// the oldest fragments are evicted to make room for the new fragment
+    EvictOldest,
+}
+
+/// limit on the rate at which fragments are accepted from a single source
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct FragmentRateLimit {
+    /// number of fragments accepted per second, on average
+    pub fragments_per_second: u32,
+    /// number of fragments accepted at once from a source that has not
+    /// submitted any for a while
+    pub burst: u32,
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
 #[serde(deny_unknown_fields)]
 pub struct Mempool {
     /// maximum number of entries in the mempool
     #[serde(default)]
     pub pool_max_entries: PoolMaxEntries,
+    /// maximum total size, in bytes, of the fragments in the mempool
+    #[serde(default)]
+    pub pool_max_bytes: PoolMaxBytes,
+    /// what to do with a new fragment when the mempool is full
+    #[serde(default)]
+    pub overflow_policy: PoolOverflowPolicy,
     /// time to live in the mempool before being discarded. If the value is not applied
     /// in a block within this duration it will be discarded.
     pub fragment_ttl: Duration,
+    /// maximum number of blocks a fragment is left out of for lack of space
+    /// before being discarded. `0` keeps it until its time to live ends.
+    #[serde(default)]
+    pub fragment_max_retries: FragmentMaxRetries,
+    /// order in which the fragments are selected for a block, when more of
+    /// them are pending than fit in a block
+    #[serde(default)]
+    pub priority: FragmentPriority,
+    /// time after which a fragment still pending is propagated again to the
+    /// network, in case the first propagation did not reach the leaders
+    #[serde(default = "default_rebroadcast_delay")]
+    pub rebroadcast_delay: Duration,
+    /// maximum number of fragments propagated again at every gossip round.
+    /// `0` disables the propagation of the pending fragments.
+    #[serde(default)]
+    pub rebroadcast_max_fragments: RebroadcastMaxFragments,
+    /// limit on the rate at which the fragments submitted by a REST client
+    /// are accepted, for each client IP address. Not limited if not set.
+    #[serde(default)]
+    pub rest_rate_limit: Option<FragmentRateLimit>,
+    /// limit on the rate at which the fragments received from a peer are
+    /// accepted, for each peer. Not limited if not set.
+    #[serde(default)]
+    pub p2p_rate_limit: Option<FragmentRateLimit>,
     /// maximum number of entries in the fragment logs
     #[serde(default)]
     pub log_max_entries: LogMaxEntries,
@@ -32,17 +109,59 @@ impl Default for PoolMaxEntries {
     }
 }
 
+impl Default for PoolMaxBytes {
+    fn default() -> Self {
+        PoolMaxBytes(32 * 1024 * 1024)
+    }
+}
+
+impl Default for PoolOverflowPolicy {
+    fn default() -> Self {
+        PoolOverflowPolicy::RejectNew
+    }
+}
+
 impl Default for LogMaxEntries {
     fn default() -> Self {
         LogMaxEntries(100_000)
     }
 }
 
+impl Default for FragmentMaxRetries {
+    fn default() -> Self {
+        FragmentMaxRetries(100)
+    }
+}
+
+impl Default for RebroadcastMaxFragments {
+    fn default() -> Self {
+        RebroadcastMaxFragments(100)
+    }
+}
+
+fn default_rebroadcast_delay() -> Duration {
+    Duration::new(5 * 60, 0)
+}
+
+impl Default for FragmentPriority {
+    fn default() -> Self {
+        FragmentPriority::Fifo
+    }
+}
+
 impl Default for Mempool {
     fn default() -> Self {
         Mempool {
             pool_max_entries: PoolMaxEntries::default(),
+            pool_max_bytes: PoolMaxBytes::default(),
+            overflow_policy: PoolOverflowPolicy::default(),
             fragment_ttl: Duration::new(30 * 60, 0),
+            fragment_max_retries: FragmentMaxRetries::default(),
+            priority: FragmentPriority::default(),
+            rebroadcast_delay: default_rebroadcast_delay(),
+            rebroadcast_max_fragments: RebroadcastMaxFragments::default(),
+            rest_rate_limit: None,
+            p2p_rate_limit: None,
             log_max_entries: LogMaxEntries::default(),
             log_ttl: Duration::new(3600, 0),
             garbage_collection_interval: Duration::new(3600 / 4, 0),
@@ -62,6 +181,18 @@ impl From<PoolMaxEntries> for usize {
     }
 }
 
+impl From<usize> for PoolMaxBytes {
+    fn from(s: usize) -> Self {
+        PoolMaxBytes(s)
+    }
+}
+
+impl From<PoolMaxBytes> for usize {
+    fn from(s: PoolMaxBytes) -> Self {
+        s.0
+    }
+}
+
 impl From<usize> for LogMaxEntries {
     fn from(s: usize) -> Self {
         LogMaxEntries(s)
@@ -73,3 +204,27 @@ impl From<LogMaxEntries> for usize {
         s.0
     }
 }
+
+impl From<u32> for FragmentMaxRetries {
+    fn from(s: u32) -> Self {
+        FragmentMaxRetries(s)
+    }
+}
+
+impl From<FragmentMaxRetries> for u32 {
+    fn from(s: FragmentMaxRetries) -> Self {
+        s.0
+    }
+}
+
+impl From<usize> for RebroadcastMaxFragments {
+    fn from(s: usize) -> Self {
+        RebroadcastMaxFragments(s)
+    }
+}
+
+impl From<RebroadcastMaxFragments> for usize {
+    fn from(s: RebroadcastMaxFragments) -> Self {
+        s.0
+    }
+}
@@ -4,6 +4,9 @@ mod node;
 mod secret;
 
 pub use log::{Log, LogEntry, LogOutput};
-pub use mempool::{LogMaxEntries, Mempool, PoolMaxEntries};
-pub use node::{Explorer, NodeConfig, P2p, Rest, TopicsOfInterest, TrustedPeer};
+pub use mempool::{
+    FragmentMaxRetries, FragmentPriority, FragmentRateLimit, LogMaxEntries, Mempool, PoolMaxBytes,
+    PoolMaxEntries, PoolOverflowPolicy, RebroadcastMaxFragments,
+};
+pub use node::{Explorer, InterestLevel, NodeConfig, P2p, Rest, TopicsOfInterest, TrustedPeer};
 pub use secret::{Bft, GenesisPraos, NodeSecret};
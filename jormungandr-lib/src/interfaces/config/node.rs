@@ -13,8 +13,13 @@ pub struct P2p {
     /// The public address to which other peers may connect to
     pub public_address: poldercast::Address,
 
+    /// must be the identifier derived from the gossip key
     pub public_id: poldercast::Id,
 
+    /// the file holding the key the node signs its gossip with
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gossip_key_file: Option<PathBuf>,
+
     /// the rendezvous points for the peer to connect to in order to initiate
     /// the p2p discovery from.
     pub trusted_peers: Vec<TrustedPeer>,
@@ -28,8 +33,17 @@ pub struct P2p {
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct TopicsOfInterest {
-    pub messages: String,
-    pub blocks: String,
+    pub messages: InterestLevel,
+    pub blocks: InterestLevel,
+}
+
+/// interest of the node in a P2P topic
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum InterestLevel {
+    Low,
+    Normal,
+    High,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -24,11 +24,45 @@ pub enum FragmentStatus {
     /// the fragment is yet to be processed
     Pending,
     /// the fragment has been rejected and won't be added in a block
-    Rejected { reason: String },
+    Rejected {
+        reason: String,
+        #[serde(default)]
+        kind: RejectionKind,
+    },
     /// The fragment has been added in a block
     InABlock { date: BlockDate, block: Hash },
 }
 
+/// the category of the error a fragment was rejected for
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RejectionKind {
+    /// an input spends more than the account holds, or an account
+    /// that does not exist
+    InsufficientFunds,
+    /// a witness does not match the signed transaction
+    InvalidWitness,
+    /// the witness of an account input does not match, as happens when it
+    /// was signed with a spending counter other than the account's current
+    WrongSpendingCounter,
+    /// the fragment stayed in the pool beyond its time to live, or was left
+    /// out of too many blocks
+    Expired,
+    /// the fragment does not fit in a block
+    TooLarge,
+    /// the fragment was evicted from the full mempool to make room for
+    /// another
+    Evicted,
+    /// any other error, described by the reason
+    Other,
+}
+
+impl Default for RejectionKind {
+    fn default() -> Self {
+        RejectionKind::Other
+    }
+}
+
 /// the log associated to a given fragment
 #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 pub struct FragmentLog {
@@ -2,6 +2,7 @@ mod account_identifier;
 mod account_state;
 mod address;
 mod block0_configuration;
+mod block_event;
 mod blockdate;
 mod certificate;
 mod config;
@@ -25,13 +26,14 @@ pub use self::account_identifier::AccountIdentifier;
 pub use self::account_state::AccountState;
 pub use self::address::Address;
 pub use self::block0_configuration::*;
+pub use self::block_event::{BlockEvent, BlockEventOrigin};
 pub use self::blockdate::BlockDate;
 pub use self::certificate::{
     Certificate, CertificateFromBech32Error, CertificateFromStrError, CertificateToBech32Error,
     SignedCertificate, CERTIFICATE_HRP, SIGNED_CERTIFICATE_HRP,
 };
 pub use self::config::*;
-pub use self::fragment_log::{FragmentLog, FragmentOrigin, FragmentStatus};
+pub use self::fragment_log::{FragmentLog, FragmentOrigin, FragmentStatus, RejectionKind};
 pub use self::leadership_log::{
     EnclaveLeaderId, LeadershipLog, LeadershipLogId, LeadershipLogStatus,
 };
@@ -10,6 +10,9 @@ pub struct Stats {
     pub peer_available_cnt: Option<u32>,
     pub peer_quarantined_cnt: Option<u32>,
     pub peer_unreachable_cnt: Option<u32>,
+    pub peer_response_time_avg: Option<u32>,
+    pub peer_response_time_p90: Option<u32>,
+    pub mempool_usage_avg: Option<u32>,
     pub tx_recv_cnt: Option<u32>,
     pub block_recv_cnt: Option<u32>,
     pub uptime: Option<u32>,
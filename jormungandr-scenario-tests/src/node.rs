@@ -142,6 +142,7 @@ pub struct Node {
 const NODE_CONFIG: &str = "node_config.yaml";
 const NODE_SECRET: &str = "node_secret.yaml";
 const NODE_STORAGE: &str = "storage.db";
+const NODE_GOSSIP_KEY: &str = "gossip_key.sk";
 
 impl NodeController {
     pub fn alias(&self) -> &NodeAlias {
@@ -305,7 +306,7 @@ impl NodeController {
                         self.progress_bar
                             .log_info(format!("Fragment '{}' is still pending", check.fragment_id));
                     }
-                    Rejected { reason } => {
+                    Rejected { reason, .. } => {
                         self.progress_bar.log_info(format!(
                             "Fragment '{}' rejected: {}",
                             check.fragment_id, reason
@@ -463,6 +464,11 @@ impl Node {
 
         let config_file = dir.join(NODE_CONFIG);
         let config_secret = dir.join(NODE_SECRET);
+        let gossip_key_file = dir.join(NODE_GOSSIP_KEY);
+
+        std::fs::write(&gossip_key_file, node_settings.gossip_key.to_bech32_str())
+            .chain_err(|| format!("cannot write in {:?}", gossip_key_file))?;
+        node_settings.config.p2p.gossip_key_file = Some(gossip_key_file);
 
         if peristence_mode == PersistenceMode::Persistent {
             let path_to_storage = dir.join(NODE_STORAGE);
@@ -16,11 +16,11 @@ use chain_impl_mockchain::{
 use chain_time::DurationSeconds;
 use jormungandr_integration_tests::common::file_utils;
 use jormungandr_lib::{
-    crypto::key::SigningKey,
+    crypto::{key::SigningKey, node_id::node_id},
     interfaces::{
         Bft, Block0Configuration, BlockchainConfiguration, Explorer, GenesisPraos, Initial,
-        InitialUTxO, Log, LogEntry, LogOutput, Mempool, NodeConfig, NodeSecret, P2p, Rest,
-        TopicsOfInterest,
+        InitialUTxO, InterestLevel, Log, LogEntry, LogOutput, Mempool, NodeConfig, NodeSecret, P2p,
+        Rest, TopicsOfInterest,
     },
 };
 use rand_core::{CryptoRng, RngCore};
@@ -55,6 +55,10 @@ pub struct NodeSetting {
 
     pub config: NodeConfig,
 
+    /// key the node signs its gossip with, its `public_id` is derived
+    /// from it
+    pub gossip_key: SigningKey<Ed25519>,
+
     node_topology: NodeTemplate,
 }
 
@@ -325,10 +329,14 @@ impl NodeSetting {
     where
         RNG: RngCore + CryptoRng,
     {
+        let gossip_key = SigningKey::generate(context.rng_mut());
+        let mut config = NodeConfig::prepare(context);
+        config.p2p.public_id = node_id(&gossip_key.identifier().into_public_key());
         NodeSetting {
             alias,
-            config: NodeConfig::prepare(context),
+            config,
             secret: NodeSecret::prepare(context),
+            gossip_key,
             node_topology: template,
         }
     }
@@ -427,7 +435,9 @@ impl Prepare for P2p {
     {
         P2p {
             public_address: context.generate_new_grpc_public_address(),
+            // derived from the gossip key once generated
             public_id: poldercast::Id::generate(context.rng_mut()),
+            gossip_key_file: None,
             trusted_peers: Vec::new(),
             allow_private_addresses: true,
             listen_address: context.generate_new_grpc_public_address(),
@@ -442,8 +452,8 @@ impl Prepare for TopicsOfInterest {
         RNG: RngCore,
     {
         TopicsOfInterest {
-            messages: "high".to_string(),
-            blocks: "high".to_string(),
+            messages: InterestLevel::High,
+            blocks: InterestLevel::High,
         }
     }
 }
@@ -0,0 +1,49 @@
+use super::{Chain, CHAIN_LENGTH};
+use crate::blockcfg::Block;
+use chain_core::property::{Deserialize as _, Serialize as _};
+use chain_impl_mockchain::leadership::Verification;
+use test::Bencher;
+
+#[bench]
+fn block_deserialization(b: &mut Bencher) {
+    let chain = Chain::generate(CHAIN_LENGTH);
+    let encoded = chain
+        .blocks
+        .iter()
+        .map(|block| block.serialize_as_vec().unwrap())
+        .collect::<Vec<_>>();
+    b.iter(|| {
+        for bytes in &encoded {
+            Block::deserialize(bytes.as_slice()).unwrap();
+        }
+    });
+}
+
+#[bench]
+fn header_verification(b: &mut Bencher) {
+    let chain = Chain::generate(CHAIN_LENGTH);
+    b.iter(|| {
+        for block in &chain.blocks {
+            match chain.leadership.verify(&block.header) {
+                Verification::Success => {}
+                Verification::Failure(e) => panic!("invalid header: {}", e),
+            }
+        }
+    });
+}
+
+#[bench]
+fn ledger_block_application(b: &mut Bencher) {
+    let chain = Chain::generate(CHAIN_LENGTH);
+    let ledger_params = chain.leadership.ledger_parameters().clone();
+    b.iter(|| {
+        let mut ledger = chain.ledger0.clone();
+        for block in &chain.blocks {
+            let metadata = block.header.to_content_eval_context();
+            ledger = ledger
+                .apply_block(&ledger_params, &block.contents, &metadata)
+                .unwrap();
+        }
+        ledger
+    });
+}
@@ -0,0 +1,41 @@
+use crate::network::p2p::{Gossip, GossipAuthenticator};
+use chain_core::property::{Deserialize as _, Serialize as _};
+use chain_crypto::{Ed25519, SecretKey};
+use jormungandr_lib::crypto::node_id::node_id;
+use poldercast::{Address, NodeProfileBuilder};
+use std::net::Ipv4Addr;
+use test::Bencher;
+
+/// number of entries in a round of gossip
+const GOSSIP_ENTRIES: u32 = 100;
+
+// Entries of distinct nodes, each signed with the key of its node.
+fn signed_gossips() -> Vec<Vec<u8>> {
+    (0..GOSSIP_ENTRIES)
+        .map(|n| {
+            let key = SecretKey::<Ed25519>::generate(rand::thread_rng());
+            let mut builder = NodeProfileBuilder::new();
+            let ip = Ipv4Addr::from(0x0a00_0000 + n);
+            builder.id(node_id(&key.to_public()));
+            builder.address(Address::new(ip).ok().unwrap());
+            GossipAuthenticator::new(key, false)
+                .sign(&builder.build())
+                .serialize_as_vec()
+                .unwrap()
+        })
+        .collect()
+}
+
+#[bench]
+fn gossip_decoding_and_verification(b: &mut Bencher) {
+    let encoded = signed_gossips();
+    let key = SecretKey::<Ed25519>::generate(rand::thread_rng());
+    let auth = GossipAuthenticator::new(key, false);
+    b.iter(|| {
+        for bytes in &encoded {
+            let gossip = Gossip::deserialize(bytes.as_slice()).unwrap();
+            auth.verify(&gossip).unwrap();
+            gossip.address_scope();
+        }
+    });
+}
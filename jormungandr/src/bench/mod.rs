@@ -0,0 +1,108 @@
+//! benchmarks of the critical paths of the node
+//!
+//! The benchmarks use the unstable `test` crate, run them with
+//! `cargo +nightly bench --features with-bench`. The fixtures are
+//! generated from the blockcfg types: a BFT blockchain with a single
+//! leader, so every block of the synthetic chain is signed by it.
+
+mod block;
+mod gossip;
+
+use crate::blockcfg::{
+    Block, BlockDate, BlockVersion, ChainLength, ConfigParam, ConfigParams, ConsensusVersion,
+    Contents, ContentsBuilder, Fragment, HeaderBuilderNew, HeaderHash, Leadership, Ledger,
+};
+use chain_addr::Discrimination;
+use chain_crypto::{Ed25519, SecretKey};
+use chain_impl_mockchain::{fee::LinearFee, leadership::bft::LeaderId};
+use jormungandr_lib::interfaces::{
+    Block0Configuration, BlockchainConfiguration, ConsensusLeaderId,
+};
+
+/// number of blocks following the genesis block in the synthetic chain
+pub const CHAIN_LENGTH: usize = 100;
+
+/// A synthetic blockchain and the state it starts from.
+pub struct Chain {
+    pub ledger0: Ledger,
+    pub leadership: Leadership,
+    pub blocks: Vec<Block>,
+}
+
+impl Chain {
+    /// generate a chain of `length` blocks on top of a new genesis block
+    pub fn generate(length: usize) -> Self {
+        let leader_key = SecretKey::<Ed25519>::generate(rand::thread_rng());
+        let leader_id = LeaderId::from(leader_key.to_public());
+
+        let mut blockchain_configuration = BlockchainConfiguration::new(
+            Discrimination::Test,
+            ConsensusVersion::Bft,
+            LinearFee::new(0, 0, 0),
+        );
+        blockchain_configuration
+            .consensus_leader_ids
+            .push(ConsensusLeaderId(leader_id.clone()));
+        let block0 = Block0Configuration {
+            blockchain_configuration,
+            initial: Vec::new(),
+        }
+        .to_block();
+
+        let ledger0 =
+            Ledger::new(block0.header.hash(), block0.contents.iter()).expect("valid genesis block");
+        let leadership = Leadership::new(0, &ledger0);
+
+        let mut parent = block0.header.hash();
+        let mut blocks = Vec::with_capacity(length);
+        for slot_id in 1..=length as u32 {
+            let block = signed_block(
+                &leader_key,
+                &leader_id,
+                &parent,
+                BlockDate { epoch: 0, slot_id },
+                slot_id,
+            );
+            parent = block.header.hash();
+            blocks.push(block);
+        }
+
+        Chain {
+            ledger0,
+            leadership,
+            blocks,
+        }
+    }
+}
+
+fn signed_block(
+    leader_key: &SecretKey<Ed25519>,
+    leader_id: &LeaderId,
+    parent: &HeaderHash,
+    date: BlockDate,
+    chain_length: u32,
+) -> Block {
+    let contents: Contents = ContentsBuilder::new().into();
+    let builder = HeaderBuilderNew::new(BlockVersion::Ed25519Signed, &contents)
+        .set_parent(parent, ChainLength::from(chain_length))
+        .set_date(date)
+        .to_bft_builder()
+        .expect("valid header builder")
+        .set_consensus_data(leader_id);
+    let signature = leader_key.sign_slice(builder.get_authenticated_data());
+    let header = builder.set_signature(signature.into()).generalize();
+    Block { header, contents }
+}
+
+/// Distinct fragments for the mempool. The pool does not check the
+/// fragments it stores, so initial fragments, the cheapest to build,
+/// stand for the transactions.
+pub fn fragments(count: usize) -> Vec<Fragment> {
+    (0..count as u32)
+        .map(|n| {
+            let mut params = ConfigParams::new();
+            params.push(ConfigParam::SlotsPerEpoch(n + 1));
+            Fragment::Initial(params)
+        })
+        .collect()
+}
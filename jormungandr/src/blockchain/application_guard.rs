@@ -0,0 +1,210 @@
+use crate::blockcfg::HeaderHash;
+use futures::future::{self, Future, IntoFuture};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+use tokio::sync::lock::Lock;
+
+/// Orders the concurrent updates of the blockchain.
+///
+/// Blocks received from different peers (or from the leadership task) are
+/// processed in parallel. Two kinds of work must not interleave though:
+///
+/// * the application of blocks on top of the same parent, which would
+///   otherwise race to store the same block or to create competing
+///   entries for it in the caches; blocks extending different branches
+///   are still applied in parallel;
+/// * the chain selection, which reads the tip, compares it against the
+///   candidate and updates it. It is run one candidate at a time so the
+///   outcome only depends on the set of applied blocks.
+#[derive(Clone)]
+pub struct ApplicationGuard {
+    branches: Arc<Mutex<HashMap<HeaderHash, BranchLock>>>,
+    selection: Lock<()>,
+}
+
+struct BranchLock {
+    lock: Lock<()>,
+    users: usize,
+}
+
+/// keeps the count of users of a branch lock up to date, even if the
+/// future holding it is dropped before completion.
+struct BranchLockUser {
+    branches: Arc<Mutex<HashMap<HeaderHash, BranchLock>>>,
+    parent: HeaderHash,
+}
+
+impl ApplicationGuard {
+    pub fn new() -> Self {
+        ApplicationGuard {
+            branches: Arc::new(Mutex::new(HashMap::new())),
+            selection: Lock::new(()),
+        }
+    }
+
+    /// run the application of a block on top of the given parent once
+    /// the other applications on top of the same parent are done.
+    pub fn extending<F, R>(
+        &self,
+        parent: HeaderHash,
+        f: F,
+    ) -> impl Future<Item = R::Item, Error = R::Error>
+    where
+        F: FnOnce() -> R,
+        R: IntoFuture,
+    {
+        let (mut lock, user) = self.branch_lock(parent);
+        future::poll_fn(move || Ok(lock.poll_lock())).and_then(move |guard| {
+            f().into_future().then(move |res| {
+                drop(guard);
+                drop(user);
+                res
+            })
+        })
+    }
+
+    /// run the chain selection once the previous one is done.
+    pub fn selecting<F, R>(&self, f: F) -> impl Future<Item = R::Item, Error = R::Error>
+    where
+        F: FnOnce() -> R,
+        R: IntoFuture,
+    {
+        let mut lock = self.selection.clone();
+        future::poll_fn(move || Ok(lock.poll_lock())).and_then(move |guard| {
+            f().into_future().then(move |res| {
+                drop(guard);
+                res
+            })
+        })
+    }
+
+    fn branch_lock(&self, parent: HeaderHash) -> (Lock<()>, BranchLockUser) {
+        let mut branches = self.branches.lock().unwrap();
+        let entry = branches
+            .entry(parent.clone())
+            .or_insert_with(|| BranchLock {
+                lock: Lock::new(()),
+                users: 0,
+            });
+        entry.users += 1;
+        let user = BranchLockUser {
+            branches: self.branches.clone(),
+            parent,
+        };
+        (entry.lock.clone(), user)
+    }
+}
+
+impl Drop for BranchLockUser {
+    fn drop(&mut self) {
+        let mut branches = self.branches.lock().unwrap();
+        if let Some(entry) = branches.get_mut(&self.parent) {
+            entry.users -= 1;
+            if entry.users == 0 {
+                branches.remove(&self.parent);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::{sync::oneshot, Async};
+    use std::{cell::Cell, rc::Rc};
+
+    fn parent(name: &str) -> HeaderHash {
+        HeaderHash::hash_bytes(name.as_bytes())
+    }
+
+    // an application that completes once released through the sender
+    fn held() -> (oneshot::Sender<()>, oneshot::Receiver<()>) {
+        oneshot::channel()
+    }
+
+    fn done(ran: &Rc<Cell<bool>>) -> impl FnOnce() -> Result<(), oneshot::Canceled> {
+        let ran = ran.clone();
+        move || {
+            ran.set(true);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn applications_on_the_same_parent_are_serialized() {
+        let guard = ApplicationGuard::new();
+        let ran = Rc::new(Cell::new(false));
+        future::lazy(|| {
+            let (release, application) = held();
+            let mut first = guard.extending(parent("a"), move || application);
+            let mut second = guard.extending(parent("a"), done(&ran));
+            assert_eq!(first.poll(), Ok(Async::NotReady));
+            assert_eq!(second.poll(), Ok(Async::NotReady));
+            assert!(!ran.get());
+            release.send(()).unwrap();
+            assert_eq!(first.poll(), Ok(Async::Ready(())));
+            assert_eq!(second.poll(), Ok(Async::Ready(())));
+            assert!(ran.get());
+            Ok::<_, ()>(())
+        })
+        .wait()
+        .unwrap();
+    }
+
+    #[test]
+    fn applications_on_different_parents_run_in_parallel() {
+        let guard = ApplicationGuard::new();
+        let ran = Rc::new(Cell::new(false));
+        future::lazy(|| {
+            let (_release, application) = held();
+            let mut first = guard.extending(parent("a"), move || application);
+            let mut second = guard.extending(parent("b"), done(&ran));
+            assert_eq!(first.poll(), Ok(Async::NotReady));
+            assert_eq!(second.poll(), Ok(Async::Ready(())));
+            assert!(ran.get());
+            Ok::<_, ()>(())
+        })
+        .wait()
+        .unwrap();
+    }
+
+    #[test]
+    fn selections_are_serialized() {
+        let guard = ApplicationGuard::new();
+        let ran = Rc::new(Cell::new(false));
+        future::lazy(|| {
+            let (release, selection) = held();
+            let mut first = guard.selecting(move || selection);
+            let mut second = guard.selecting(done(&ran));
+            assert_eq!(first.poll(), Ok(Async::NotReady));
+            assert_eq!(second.poll(), Ok(Async::NotReady));
+            release.send(()).unwrap();
+            assert_eq!(first.poll(), Ok(Async::Ready(())));
+            assert_eq!(second.poll(), Ok(Async::Ready(())));
+            assert!(ran.get());
+            Ok::<_, ()>(())
+        })
+        .wait()
+        .unwrap();
+    }
+
+    #[test]
+    fn branch_lock_is_released_when_unused() {
+        let guard = ApplicationGuard::new();
+        let ran = Rc::new(Cell::new(false));
+        future::lazy(|| {
+            let (_release, application) = held();
+            let mut pending = guard.extending(parent("a"), move || application);
+            assert_eq!(pending.poll(), Ok(Async::NotReady));
+            guard.extending(parent("b"), done(&ran)).poll().unwrap();
+            assert_eq!(guard.branches.lock().unwrap().len(), 1);
+            drop(pending);
+            assert!(guard.branches.lock().unwrap().is_empty());
+            Ok::<_, ()>(())
+        })
+        .wait()
+        .unwrap();
+    }
+}
@@ -1,5 +1,8 @@
-use crate::blockchain::Ref;
-use std::{convert::Infallible, sync::Arc};
+use crate::{
+    blockcfg::{Header, HeaderHash},
+    blockchain::Ref,
+};
+use std::{convert::Infallible, sync::Arc, time::SystemTime};
 use tokio::{prelude::*, sync::lock::Lock};
 
 #[derive(Clone)]
@@ -24,6 +27,17 @@ struct BranchData {
     last_updated: std::time::SystemTime,
 }
 
+/// A branch tracked by the blockchain, as reported to the node operators.
+pub struct BranchInfo {
+    /// header of the block the branch points to
+    pub tip: Header,
+    /// the last time the branch was updated
+    pub last_updated: SystemTime,
+    /// the latest block the branch has in common with the main branch,
+    /// `None` if it is deeper than the epoch stability depth
+    pub common_ancestor: Option<HeaderHash>,
+}
+
 impl Branches {
     pub fn new() -> Self {
         Branches {
@@ -59,6 +73,15 @@ impl Branches {
         future::poll_fn(move || Ok(branches.inner.poll_lock())).and_then(|guard| guard.branches())
     }
 
+    /// the branches along with the last time they were updated
+    pub fn branches_last_updated<E>(
+        &self,
+    ) -> impl Future<Item = Vec<(Arc<Ref>, SystemTime)>, Error = E> {
+        let mut branches = self.clone();
+        future::poll_fn(move || Ok(branches.inner.poll_lock()))
+            .and_then(|guard| guard.branches_last_updated())
+    }
+
     fn apply(
         &mut self,
         candidate: Arc<Ref>,
@@ -97,6 +120,12 @@ impl BranchesData {
     pub fn branches<E>(&self) -> impl Future<Item = Vec<Arc<Ref>>, Error = E> {
         stream::futures_unordered(self.branches.iter().map(|b| b.get_ref())).collect()
     }
+
+    pub fn branches_last_updated<E>(
+        &self,
+    ) -> impl Future<Item = Vec<(Arc<Ref>, SystemTime)>, Error = E> {
+        stream::futures_unordered(self.branches.iter().map(|b| b.get_ref_last_updated())).collect()
+    }
 }
 
 impl Branch {
@@ -111,6 +140,12 @@ impl Branch {
         future::poll_fn(move || Ok(branch.poll_lock())).map(|guard| guard.reference().clone())
     }
 
+    fn get_ref_last_updated<E>(&self) -> impl Future<Item = (Arc<Ref>, SystemTime), Error = E> {
+        let mut branch = self.inner.clone();
+        future::poll_fn(move || Ok(branch.poll_lock()))
+            .map(|guard| (guard.reference(), guard.last_updated))
+    }
+
     pub fn update_ref(
         &mut self,
         new_ref: Arc<Ref>,
@@ -49,13 +49,17 @@ See Internal documentation for more details: doc/internal_design.md
 [`Branch`]: ./struct.Branch.html
 */
 
-use super::{branch::Branches, reference_cache::RefCache};
+use super::{
+    application_guard::ApplicationGuard,
+    branch::Branches,
+    reference_cache::{RefCache, RefCacheStats},
+};
 use crate::{
     blockcfg::{
         Block, Block0Error, BlockDate, ChainLength, Epoch, EpochRewardsInfo, Header, HeaderHash,
         Leadership, Ledger, LedgerParameters, RewardsInfoParameters,
     },
-    blockchain::{Branch, Checkpoints, Multiverse, Ref, Storage},
+    blockchain::{BlockEventLog, Branch, Checkpoints, LoadProgress, Multiverse, Ref, Storage},
     start_up::NodeStorage,
 };
 use chain_impl_mockchain::{leadership::Verification, ledger};
@@ -63,7 +67,7 @@ use chain_storage::error::Error as StorageError;
 use chain_time::TimeFrame;
 use slog::Logger;
 use std::{convert::Infallible, sync::Arc, time::Duration};
-use tokio::prelude::*;
+use tokio::{prelude::*, timer};
 
 // derive
 use thiserror::Error;
@@ -183,7 +187,13 @@ pub struct Blockchain {
 
     storage: Storage,
 
+    event_log: BlockEventLog,
+
+    guard: ApplicationGuard,
+
     block0: HeaderHash,
+
+    max_rollback_depth: Option<u32>,
 }
 
 pub enum PreCheckedHeader {
@@ -259,13 +269,28 @@ impl AppliedBlock {
 }
 
 impl Blockchain {
-    pub fn new(block0: HeaderHash, storage: NodeStorage, ref_cache_ttl: Duration) -> Self {
+    pub fn new(
+        block0: HeaderHash,
+        storage: NodeStorage,
+        event_log: BlockEventLog,
+        ref_cache_ttl: Duration,
+        ref_cache_max_entries: Option<usize>,
+        ref_cache_pinned_epochs: usize,
+        max_rollback_depth: Option<u32>,
+    ) -> Self {
         Blockchain {
             branches: Branches::new(),
-            ref_cache: RefCache::new(ref_cache_ttl),
+            ref_cache: RefCache::new(
+                ref_cache_ttl,
+                ref_cache_max_entries,
+                ref_cache_pinned_epochs,
+            ),
             ledgers: Multiverse::new(),
             storage: Storage::new(storage),
+            event_log,
+            guard: ApplicationGuard::new(),
             block0,
+            max_rollback_depth,
         }
     }
 
@@ -273,10 +298,29 @@ impl Blockchain {
         &self.block0
     }
 
+    /// the maximum number of blocks of the tip's branch the chain selection
+    /// may roll back, unbounded if `None`
+    pub fn max_rollback_depth(&self) -> Option<u32> {
+        self.max_rollback_depth
+    }
+
+    /// occupancy of the cache of the states of the recent blocks
+    pub fn ref_cache_stats(&self) -> impl Future<Item = RefCacheStats, Error = Infallible> {
+        self.ref_cache.stats()
+    }
+
     pub fn storage(&self) -> &Storage {
         &self.storage
     }
 
+    pub fn event_log(&self) -> &BlockEventLog {
+        &self.event_log
+    }
+
+    pub fn application_guard(&self) -> &ApplicationGuard {
+        &self.guard
+    }
+
     pub fn branches(&self) -> &Branches {
         &self.branches
     }
@@ -285,6 +329,15 @@ impl Blockchain {
         &mut self.branches
     }
 
+    /// remove the expired references from the cache and the ledgers
+    /// no longer referenced from the multiverse
+    pub fn purge(&self) -> impl Future<Item = (), Error = timer::Error> {
+        let ledgers = self.ledgers.clone();
+        self.ref_cache
+            .purge()
+            .and_then(move |()| ledgers.purge().map_err(|_: Infallible| unreachable!()))
+    }
+
     /// create and store a reference of this leader to the new
     fn create_and_store_reference(
         &self,
@@ -320,6 +373,19 @@ impl Blockchain {
             })
     }
 
+    /// the number of blocks switching from the branch ending at `tip` to
+    /// the one ending at `other` rolls back, found from the `Ref`s in the
+    /// cache. Resolves to `limit + 1` for a rollback deeper than `limit`,
+    /// and to `None` if the fork point is too old to be in the cache.
+    pub fn rollback_depth(
+        &self,
+        tip: &Ref,
+        other: &Ref,
+        limit: u32,
+    ) -> impl Future<Item = Option<u32>, Error = Infallible> {
+        self.ref_cache.rollback_depth(tip, other, limit)
+    }
+
     /// get `Ref` of the given header hash
     ///
     /// once the `Ref` is in hand, it means we have the Leadership schedule associated
@@ -444,6 +510,48 @@ impl Blockchain {
         header: Header,
         parent: Arc<Ref>,
     ) -> impl Future<Item = PostCheckedHeader, Error = Error> {
+        self.post_check_verified_header(header, parent, None)
+    }
+
+    /// same as `post_check_header`, for a header already verified against
+    /// the leadership schedule `verified_with`: the verification is not
+    /// done again if the schedule of the header is that very schedule.
+    pub fn post_check_verified_header(
+        &self,
+        header: Header,
+        parent: Arc<Ref>,
+        verified_with: Option<Arc<Leadership>>,
+    ) -> impl Future<Item = PostCheckedHeader, Error = Error> {
+        self.post_check_header_with(header, parent, |header, epoch_leadership_schedule| {
+            match verified_with {
+                Some(leadership) if Arc::ptr_eq(&leadership, epoch_leadership_schedule) => {
+                    Verification::Success
+                }
+                _ => epoch_leadership_schedule.verify(header),
+            }
+        })
+    }
+
+    /// same as `post_check_header`, without the verification of the header
+    /// against the leadership schedule, for a block known to be part of the
+    /// chain from a trusted source.
+    pub fn post_check_trusted_header(
+        &self,
+        header: Header,
+        parent: Arc<Ref>,
+    ) -> impl Future<Item = PostCheckedHeader, Error = Error> {
+        self.post_check_header_with(header, parent, |_, _| Verification::Success)
+    }
+
+    fn post_check_header_with<F>(
+        &self,
+        header: Header,
+        parent: Arc<Ref>,
+        verify: F,
+    ) -> impl Future<Item = PostCheckedHeader, Error = Error>
+    where
+        F: FnOnce(&Header, &Arc<Leadership>) -> Verification,
+    {
         let current_date = header.block_date();
 
         let (
@@ -454,7 +562,7 @@ impl Blockchain {
             previous_epoch_state,
         ) = new_epoch_leadership_from(current_date.epoch, parent);
 
-        match epoch_leadership_schedule.verify(&header) {
+        match verify(&header, &epoch_leadership_schedule) {
             Verification::Success => future::ok(PostCheckedHeader {
                 header,
                 epoch_leadership_schedule,
@@ -518,14 +626,21 @@ impl Blockchain {
         block: Block,
     ) -> impl Future<Item = AppliedBlock, Error = Error> {
         let mut storage = self.storage.clone();
-        self.apply_block(post_checked_header, &block)
-            .and_then(move |block_ref| {
-                storage.put_block(block).then(|res| match res {
-                    Ok(()) => Ok(AppliedBlock::New(block_ref)),
-                    Err(StorageError::BlockAlreadyPresent) => Ok(AppliedBlock::Existing(block_ref)),
-                    Err(e) => Err(e.into()),
+        let blockchain = self.clone();
+        let parent = post_checked_header.header.parent_id();
+        self.guard.extending(parent, move || {
+            blockchain
+                .apply_block(post_checked_header, &block)
+                .and_then(move |block_ref| {
+                    storage.put_block(block).then(|res| match res {
+                        Ok(()) => Ok(AppliedBlock::New(block_ref)),
+                        Err(StorageError::BlockAlreadyPresent) => {
+                            Ok(AppliedBlock::Existing(block_ref))
+                        }
+                        Err(e) => Err(e.into()),
+                    })
                 })
-            })
+        })
     }
 
     /// Apply the given block0 in the blockchain (updating the RefCache and the other objects)
@@ -673,6 +788,7 @@ impl Blockchain {
     pub fn load_from_storage(
         &self,
         block0: Block,
+        progress: LoadProgress,
         logger: &Logger,
     ) -> impl Future<Item = Branch, Error = Error> {
         let block0_header = block0.header.clone();
@@ -728,8 +844,10 @@ impl Blockchain {
 
                                 const PROCESS_LOGGING_DISTANCE : u64 = 2500;
                                 if processed % PROCESS_LOGGING_DISTANCE == 0 {
-                                    info!(logger, "loading from storage, currently at {} ...", block.header.description());
+                                    let status = progress.status().map(|status| status.to_string()).unwrap_or_default();
+                                    info!(logger, "loading from storage, currently at {} ...", block.header.description(); "progress" => status);
                                 }
+                                let progress = progress.clone();
 
                                 let self5 = self4.clone();
                                 let self6 = self4.clone();
@@ -754,6 +872,7 @@ impl Blockchain {
                                         self6.apply_block(post_checked_header, &block)
                                     })
                                     .and_then(move |new_ref| {
+                                        progress.block_applied(new_ref.block_date().epoch);
                                         branch
                                             .clone()
                                             .update_ref(new_ref)
@@ -865,19 +984,19 @@ pub fn new_epoch_leadership_from(
 
         // 2. now that the rewards have been distributed, prepare the schedule
         //    for the next leader
-        let epoch_state = if transition_state.consensus_version() == ConsensusVersion::GenesisPraos
-        {
+        let leadership = if transition_state.consensus_version() == ConsensusVersion::GenesisPraos {
             // if there is no parent state available this might be because it is not
             // available in memory or it is the epoch0 or epoch1
-            parent
-                .last_ref_previous_epoch()
-                .map(|r| r.ledger().clone())
-                .unwrap_or(parent_ledger_state.clone())
+            match parent.last_ref_previous_epoch() {
+                // may have been precomputed ahead of the transition,
+                // see `precompute_epoch_transitions`
+                Some(epoch_state) => epoch_state.leadership_from_state(epoch),
+                None => Arc::new(Leadership::new(epoch, &parent_ledger_state)),
+            }
         } else {
-            transition_state.clone()
+            Arc::new(Leadership::new(epoch, &transition_state))
         };
 
-        let leadership = Arc::new(Leadership::new(epoch, &epoch_state));
         let ledger_parameters = Arc::new(leadership.ledger_parameters().clone());
         let previous_epoch_state = Some(parent);
         (
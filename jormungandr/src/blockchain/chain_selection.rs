@@ -1,9 +1,15 @@
-use crate::blockchain::{Ref, Storage};
+use crate::blockcfg::ChainLength;
+use crate::blockchain::{Blockchain, Ref};
+use std::convert::Infallible;
+use tokio::prelude::*;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Hash)]
 pub enum ComparisonResult {
     PreferCurrent,
     PreferCandidate,
+    /// the candidate is better, but switching to it would roll back
+    /// more blocks than allowed
+    RollbackTooDeep,
 }
 
 /// chose which of the two Ref is the most interesting to keep as a branch
@@ -13,32 +19,114 @@ pub enum ComparisonResult {
 /// on the same block date is to a requirement to call this function as it will still
 /// work to make a choice as to which of these two Ref is the right choice.
 ///
-pub fn compare_against(storage: &Storage, current: &Ref, candidate: &Ref) -> ComparisonResult {
-    let epoch_stability_depth = current.epoch_ledger_parameters().epoch_stability_depth;
-
-    let rollback_possible =
-        check_rollback_up_to(epoch_stability_depth, storage, current, candidate);
-
-    let not_in_future = !is_in_future(candidate);
+/// Between two Refs of the same chain length, the current one is kept: a
+/// tie-break on the block hash would let the producer of the candidate
+/// grind its block to win the tie.
+///
+/// A better candidate is still refused if switching to it would roll back
+/// more than the maximum rollback depth of the blockchain. The depth is
+/// found from the `Ref`s in memory, a rollback to a fork point no longer
+/// in memory is refused.
+///
+pub fn compare_against(
+    blockchain: &Blockchain,
+    current: &Ref,
+    candidate: &Ref,
+) -> impl Future<Item = ComparisonResult, Error = Infallible> {
+    use tokio::prelude::future::Either::*;
 
-    if rollback_possible && not_in_future && current.chain_length() < candidate.chain_length() {
-        ComparisonResult::PreferCandidate
-    } else {
-        ComparisonResult::PreferCurrent
+    if !candidate_is_better(
+        current.chain_length(),
+        candidate.chain_length(),
+        is_in_future(candidate),
+    ) {
+        return A(future::ok(ComparisonResult::PreferCurrent));
+    }
+    match blockchain.max_rollback_depth() {
+        None => A(future::ok(ComparisonResult::PreferCandidate)),
+        Some(max_depth) => B(blockchain
+            .rollback_depth(current, candidate, max_depth)
+            .map(move |depth| {
+                if rollback_allowed(max_depth, depth) {
+                    ComparisonResult::PreferCandidate
+                } else {
+                    ComparisonResult::RollbackTooDeep
+                }
+            })),
     }
 }
 
+/// returns `true` if the candidate, not in the future, is longer than the
+/// current branch
+fn candidate_is_better(
+    current: ChainLength,
+    candidate: ChainLength,
+    candidate_in_future: bool,
+) -> bool {
+    !candidate_in_future && candidate > current
+}
+
 /// returns `true` is the Ref is set in what appears to be in the future
 /// relative to this node.
 fn is_in_future(node: &Ref) -> bool {
     node.elapsed().is_err()
 }
 
-fn check_rollback_up_to(
-    _epoch_stability_depth: u32,
-    _: &Storage,
-    _ref1: &Ref,
-    _ref2: &Ref,
-) -> bool {
-    true
+/// returns `true` if the rollback of `depth` blocks is at most
+/// `max_depth` blocks. A rollback whose depth is not known is not allowed.
+fn rollback_allowed(max_depth: u32, depth: Option<u32>) -> bool {
+    depth.map_or(false, |depth| depth <= max_depth)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn longer_candidate_is_preferred() {
+        assert!(candidate_is_better(
+            ChainLength::from(10),
+            ChainLength::from(11),
+            false
+        ));
+    }
+
+    #[test]
+    fn shorter_candidate_is_refused() {
+        assert!(!candidate_is_better(
+            ChainLength::from(10),
+            ChainLength::from(9),
+            false
+        ));
+    }
+
+    #[test]
+    fn current_tip_is_kept_on_a_tie() {
+        assert!(!candidate_is_better(
+            ChainLength::from(10),
+            ChainLength::from(10),
+            false
+        ));
+    }
+
+    #[test]
+    fn candidate_in_the_future_is_refused() {
+        assert!(!candidate_is_better(
+            ChainLength::from(10),
+            ChainLength::from(11),
+            true
+        ));
+    }
+
+    #[test]
+    fn rollback_up_to_the_maximum_depth_is_allowed() {
+        assert!(rollback_allowed(10, Some(0)));
+        assert!(rollback_allowed(10, Some(10)));
+        assert!(!rollback_allowed(10, Some(11)));
+    }
+
+    #[test]
+    fn rollback_of_unknown_depth_is_refused() {
+        assert!(!rollback_allowed(10, None));
+    }
 }
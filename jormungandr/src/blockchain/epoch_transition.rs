@@ -0,0 +1,77 @@
+use super::{Ref, Tip};
+use crate::{blockcfg::HeaderHash, utils::task::TokioServiceInfo};
+use chain_impl_mockchain::block::ConsensusVersion;
+use futures::{
+    future::{self, Either},
+    prelude::*,
+};
+use std::sync::Arc;
+use tokio_threadpool::Builder;
+
+/// Computes the leadership schedule of the next epoch, with its stake
+/// distribution, ahead of the transition to that epoch.
+///
+/// Under Genesis Praos, the schedule of an epoch is computed from the
+/// ledger state at the end of the epoch before the previous one, which is
+/// known as soon as the current epoch starts. Once the tip is within
+/// `window` slots of the end of its epoch, the schedule is computed from
+/// that state on a separate thread and kept in its `Ref`, where the
+/// application of the first block of the next epoch finds it.
+///
+/// The distribution of the rewards depends on the last block of the epoch
+/// and is still done at the transition.
+pub fn precompute_epoch_transitions(
+    service_info: TokioServiceInfo,
+    blockchain_tip: Tip,
+    window: u32,
+) -> impl Future<Item = (), Error = ()> {
+    let logger = service_info.logger().clone();
+    let pool = Builder::new()
+        .pool_size(1)
+        .name_prefix("epoch-transition")
+        .build();
+
+    blockchain_tip
+        .subscribe()
+        .and_then(move |_| blockchain_tip.get_ref())
+        .fold(None, move |precomputed, tip| {
+            let epoch_state = match epoch_state_to_precompute(&tip, window) {
+                Some(epoch_state) => epoch_state,
+                None => return Either::A(future::ok(precomputed)),
+            };
+            let next_epoch = tip.block_date().epoch + 1;
+            let key = (next_epoch, epoch_state.hash());
+            if precomputed == Some(key) {
+                return Either::A(future::ok(precomputed));
+            }
+
+            let logger = logger.clone();
+            Either::B(
+                pool.spawn_handle(future::lazy(move || {
+                    epoch_state.leadership_from_state(next_epoch);
+                    Ok::<_, ()>(())
+                }))
+                .map(move |()| {
+                    debug!(
+                        logger,
+                        "leadership schedule of the next epoch precomputed";
+                        "epoch" => next_epoch,
+                        "epoch_state" => %key.1,
+                    );
+                    Some(key)
+                }),
+            )
+        })
+        .map(|_: Option<(u32, HeaderHash)>| ())
+}
+
+// the `Ref` the schedule of the epoch following the one of `tip` is
+// computed from, if it is to be precomputed now
+fn epoch_state_to_precompute(tip: &Ref, window: u32) -> Option<Arc<Ref>> {
+    let slots_per_epoch = tip.epoch_leadership_schedule().era().slots_per_epoch();
+    let slots_left = slots_per_epoch.saturating_sub(tip.block_date().slot_id + 1);
+    if slots_left >= window || tip.ledger().consensus_version() != ConsensusVersion::GenesisPraos {
+        return None;
+    }
+    tip.last_ref_previous_epoch().cloned()
+}
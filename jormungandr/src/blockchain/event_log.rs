@@ -0,0 +1,484 @@
+use super::{storage::StorageError, Storage, MAIN_BRANCH_TAG};
+use crate::blockcfg::HeaderHash;
+use futures::sync::mpsc;
+use jormungandr_lib::{interfaces::BlockEvent, time::SystemTime};
+use rusqlite::{params, Connection, OpenFlags};
+use slog::Logger;
+use std::{
+    collections::{HashMap, VecDeque},
+    convert::Infallible,
+    path::Path,
+    sync::{Arc, Mutex},
+};
+use thiserror::Error;
+use tokio::{prelude::*, sync::lock::Lock};
+use tokio_threadpool::{Builder, ThreadPool};
+
+/// number of records kept, about ten days of blocks at one block every
+/// 20 seconds. The oldest records are dropped first.
+const MAX_BLOCK_EVENTS: usize = 50_000;
+
+/// number of records a subscriber can fall behind before it is dropped
+const SUBSCRIBER_BUFFER: usize = 64;
+
+/// maximum number of subscriptions to the records
+const MAX_SUBSCRIBERS: usize = 256;
+
+#[derive(Debug, Error)]
+pub enum CommitError {
+    #[error("cannot update the tag of the main branch")]
+    Storage(#[from] StorageError),
+    #[error("cannot write the block events and the tip to the block storage")]
+    Database(#[from] rusqlite::Error),
+    #[error("cannot encode a block event record")]
+    Record(#[from] serde_json::Error),
+}
+
+/// record of every block applied by the node.
+///
+/// The records are stored in the `block_events` table of the block
+/// storage so they survive a restart. They are written when the tip is
+/// updated, in the same transaction as the tag of the main branch, so
+/// the stored records never disagree with the stored tip after a crash.
+/// When the node is running with an in-memory storage the records are
+/// only kept in memory.
+///
+/// Only the latest `MAX_BLOCK_EVENTS` records are kept. The storage is
+/// written on a dedicated thread, so the writes do not block the tasks
+/// processing the blocks.
+#[derive(Clone)]
+pub struct BlockEventLog {
+    inner: Lock<BlockEventLogData>,
+}
+
+struct BlockEventLogData {
+    db: Option<EventDb>,
+    events: VecDeque<BlockEvent>,
+    /// sequence number of the first record of `events`
+    first_seq: u64,
+    by_hash: HashMap<HeaderHash, u64>,
+    subscribers: Vec<mpsc::Sender<BlockEvent>>,
+}
+
+struct EventDb {
+    conn: Arc<Mutex<Connection>>,
+    /// records of the blocks applied since the last update of the tip,
+    /// written along with the next one
+    pending: VecDeque<BlockEvent>,
+    writer: Arc<ThreadPool>,
+}
+
+impl BlockEventLog {
+    /// create an event log that is not persisted
+    pub fn memory() -> Self {
+        BlockEventLog {
+            inner: Lock::new(BlockEventLogData::new()),
+        }
+    }
+
+    /// open the event log of the block storage at `path` and load the
+    /// records already present. A `read_only` log is loaded but never
+    /// written to.
+    ///
+    /// Records that cannot be parsed are skipped with a warning.
+    pub fn open<P: AsRef<Path>>(
+        path: P,
+        read_only: bool,
+        logger: &Logger,
+    ) -> rusqlite::Result<Self> {
+        let flags = if read_only {
+            OpenFlags::SQLITE_OPEN_READ_ONLY
+        } else {
+            OpenFlags::SQLITE_OPEN_READ_WRITE
+        };
+        let conn = Connection::open_with_flags(path, flags)?;
+
+        let mut records = conn
+            .prepare("SELECT seq, record FROM block_events ORDER BY seq DESC LIMIT ?1")?
+            .query_map(params![MAX_BLOCK_EVENTS as i64], |row| {
+                Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?))
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        records.reverse();
+
+        let mut data = BlockEventLogData::new();
+        for (seq, record) in records {
+            match serde_json::from_str(&record) {
+                Ok(event) => data.push(event),
+                Err(err) => warn!(
+                    logger,
+                    "skipping invalid block event record";
+                    "seq" => seq,
+                    "reason" => %err,
+                ),
+            }
+        }
+
+        info!(
+            logger,
+            "loaded {} block event records from the storage",
+            data.events.len(),
+        );
+
+        if !read_only {
+            let writer = Builder::new()
+                .pool_size(1)
+                .name_prefix("block-event-log")
+                .build();
+            data.db = Some(EventDb {
+                conn: Arc::new(Mutex::new(conn)),
+                pending: VecDeque::new(),
+                writer: Arc::new(writer),
+            });
+        }
+
+        Ok(BlockEventLog {
+            inner: Lock::new(data),
+        })
+    }
+
+    /// add a new record to the log. The record is stored with the next
+    /// update of the tip, see `commit_tip`.
+    pub fn insert(&self, event: BlockEvent) -> impl Future<Item = (), Error = Infallible> {
+        let mut inner = self.inner.clone();
+        future::poll_fn(move || Ok(inner.poll_lock())).map(move |mut guard| guard.insert(event))
+    }
+
+    /// move the tag of the main branch to `tip`, storing the records
+    /// added since the last update in the same transaction. Without a
+    /// persistent log the tag is put through `storage`.
+    pub fn commit_tip(
+        &self,
+        mut storage: Storage,
+        tip: HeaderHash,
+    ) -> impl Future<Item = (), Error = CommitError> {
+        let mut inner = self.inner.clone();
+        future::poll_fn(move || Ok(inner.poll_lock()))
+            .map_err(|_: Infallible| unreachable!())
+            .and_then(move |mut guard| match guard.db.as_mut() {
+                None => future::Either::A(
+                    storage
+                        .put_tag(MAIN_BRANCH_TAG.to_owned(), tip)
+                        .map_err(CommitError::from),
+                ),
+                Some(db) => future::Either::B(db.commit(tip)),
+            })
+    }
+
+    /// get a stream of the records of the blocks applied from now on,
+    /// `None` if there are too many subscribers already. The stream ends
+    /// if the subscriber falls behind.
+    pub fn subscribe(
+        &self,
+    ) -> impl Future<Item = Option<mpsc::Receiver<BlockEvent>>, Error = Infallible> {
+        let mut inner = self.inner.clone();
+        future::poll_fn(move || Ok(inner.poll_lock())).map(|mut guard| guard.subscribe())
+    }
+
+    /// get the record of the block with the given hash
+    pub fn get(
+        &self,
+        hash: HeaderHash,
+    ) -> impl Future<Item = Option<BlockEvent>, Error = Infallible> {
+        let mut inner = self.inner.clone();
+        future::poll_fn(move || Ok(inner.poll_lock())).map(move |guard| guard.get(&hash).cloned())
+    }
+
+    /// get the records of the blocks received within the given time range
+    /// (bounds included), ordered by reception time.
+    pub fn range(
+        &self,
+        from: SystemTime,
+        to: SystemTime,
+    ) -> impl Future<Item = Vec<BlockEvent>, Error = Infallible> {
+        let mut inner = self.inner.clone();
+        future::poll_fn(move || Ok(inner.poll_lock())).map(move |guard| guard.range(from, to))
+    }
+}
+
+impl BlockEventLogData {
+    fn new() -> Self {
+        BlockEventLogData {
+            db: None,
+            events: VecDeque::new(),
+            first_seq: 0,
+            by_hash: HashMap::new(),
+            subscribers: Vec::new(),
+        }
+    }
+
+    fn push(&mut self, event: BlockEvent) {
+        let seq = self.first_seq + self.events.len() as u64;
+        self.by_hash.insert(event.hash.into_hash(), seq);
+        self.events.push_back(event);
+        while self.events.len() > MAX_BLOCK_EVENTS {
+            let dropped = self.events.pop_front().unwrap();
+            let hash = dropped.hash.into_hash();
+            // the block may have been recorded again since
+            if self.by_hash.get(&hash) == Some(&self.first_seq) {
+                self.by_hash.remove(&hash);
+            }
+            self.first_seq += 1;
+        }
+    }
+
+    fn insert(&mut self, event: BlockEvent) {
+        // the subscribers gone or lagging behind are dropped
+        self.subscribers = self
+            .subscribers
+            .drain(..)
+            .filter_map(|mut subscriber| {
+                subscriber.try_send(event.clone()).ok().map(|()| subscriber)
+            })
+            .collect();
+        if let Some(db) = self.db.as_mut() {
+            db.pending.push_back(event.clone());
+            // the records beyond the limit would be dropped by the commit
+            if db.pending.len() > MAX_BLOCK_EVENTS {
+                db.pending.pop_front();
+            }
+        }
+        self.push(event);
+    }
+
+    fn subscribe(&mut self) -> Option<mpsc::Receiver<BlockEvent>> {
+        if self.subscribers.len() >= MAX_SUBSCRIBERS {
+            return None;
+        }
+        let (sender, receiver) = mpsc::channel(SUBSCRIBER_BUFFER);
+        self.subscribers.push(sender);
+        Some(receiver)
+    }
+
+    fn get(&self, hash: &HeaderHash) -> Option<&BlockEvent> {
+        self.by_hash
+            .get(hash)
+            .map(|seq| &self.events[(seq - self.first_seq) as usize])
+    }
+
+    fn range(&self, from: SystemTime, to: SystemTime) -> Vec<BlockEvent> {
+        self.events
+            .iter()
+            .filter(|event| event.received_at >= from && event.received_at <= to)
+            .cloned()
+            .collect()
+    }
+}
+
+impl EventDb {
+    // The commits are run in turn on the single thread of the writer, in
+    // the order of the updates of the tip. The pending records are lost
+    // if the transaction fails, the tag is then left on the previous tip.
+    fn commit(&mut self, tip: HeaderHash) -> impl Future<Item = (), Error = CommitError> {
+        let events: Vec<_> = self.pending.drain(..).collect();
+        let conn = Arc::clone(&self.conn);
+        self.writer
+            .spawn_handle(future::lazy(move || -> Result<(), CommitError> {
+                let mut conn = conn.lock().unwrap();
+                let tx = conn.transaction()?;
+                for event in &events {
+                    let hash = event.hash.into_hash();
+                    let hash: &[u8] = hash.as_ref();
+                    // a block recorded again moves to the end of the log
+                    tx.execute(
+                        "INSERT OR REPLACE INTO block_events (hash, record) VALUES (?1, ?2)",
+                        params![hash, serde_json::to_string(event)?],
+                    )?;
+                }
+                tx.execute(
+                    "DELETE FROM block_events
+                     WHERE seq <= (SELECT max(seq) FROM block_events) - ?1",
+                    params![MAX_BLOCK_EVENTS as i64],
+                )?;
+                let tip: &[u8] = tip.as_ref();
+                tx.execute(
+                    "INSERT OR REPLACE INTO Tags (name, hash) VALUES (?1, ?2)",
+                    params![MAIN_BRANCH_TAG, tip],
+                )?;
+                tx.commit()?;
+                Ok(())
+            }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        blockcfg::{Block, BlockDate},
+        start_up::{migration, NodeStorage},
+    };
+    use chain_storage::store::BlockStore;
+    use chain_storage_sqlite_old::SQLiteBlockStore;
+    use jormungandr_lib::{interfaces::BlockEventOrigin, time::Duration};
+    use slog::{o, Discard};
+    use std::path::PathBuf;
+
+    fn hash(n: u32) -> HeaderHash {
+        HeaderHash::hash_bytes(&n.to_le_bytes())
+    }
+
+    fn event(n: u32) -> BlockEvent {
+        BlockEvent {
+            hash: hash(n).into(),
+            parent: hash(n.wrapping_sub(1)).into(),
+            date: BlockDate {
+                epoch: 0,
+                slot_id: n,
+            }
+            .into(),
+            chain_length: n,
+            received_at: SystemTime::from_secs_since_epoch(u64::from(n)),
+            origin: BlockEventOrigin::Leadership,
+            validation_duration: Duration::from_millis(1),
+            fragment_count: 0,
+            size: 0,
+            tip: hash(n.wrapping_sub(1)).into(),
+        }
+    }
+
+    #[test]
+    fn oldest_events_are_dropped() {
+        let mut data = BlockEventLogData::new();
+        for n in 0..MAX_BLOCK_EVENTS as u32 + 2 {
+            data.push(event(n));
+        }
+        assert_eq!(data.events.len(), MAX_BLOCK_EVENTS);
+        assert_eq!(data.by_hash.len(), MAX_BLOCK_EVENTS);
+        assert!(data.get(&hash(0)).is_none());
+        assert!(data.get(&hash(1)).is_none());
+        assert_eq!(data.get(&hash(2)), Some(&event(2)));
+        let last = MAX_BLOCK_EVENTS as u32 + 1;
+        assert_eq!(data.get(&hash(last)), Some(&event(last)));
+    }
+
+    #[test]
+    fn event_recorded_again_is_kept() {
+        let mut data = BlockEventLogData::new();
+        data.push(event(0));
+        for n in 1..MAX_BLOCK_EVENTS as u32 {
+            data.push(event(n));
+        }
+        data.push(event(0));
+        assert_eq!(data.events.len(), MAX_BLOCK_EVENTS);
+        assert_eq!(data.get(&hash(0)), Some(&event(0)));
+    }
+
+    #[test]
+    fn range_includes_bounds() {
+        let mut data = BlockEventLogData::new();
+        for n in 0..10 {
+            data.push(event(n));
+        }
+        let range = data.range(
+            SystemTime::from_secs_since_epoch(3),
+            SystemTime::from_secs_since_epoch(5),
+        );
+        assert_eq!(range, vec![event(3), event(4), event(5)]);
+    }
+
+    // A block storage created the way the node does, along with its
+    // scratch directory.
+    fn storage(name: &str) -> (PathBuf, PathBuf, NodeStorage) {
+        let dir = std::env::temp_dir().join(format!("jormungandr-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let logger = Logger::root(Discard, o!());
+        migration::migrate(&dir, false, &logger).unwrap();
+        let path = dir.join("blocks.sqlite");
+        let store = SQLiteBlockStore::<Block>::file(path.clone());
+        (dir, path, store)
+    }
+
+    fn open(path: &Path) -> BlockEventLog {
+        BlockEventLog::open(path, false, &Logger::root(Discard, o!())).unwrap()
+    }
+
+    fn insert(log: &BlockEventLog, n: u32) {
+        log.insert(event(n)).wait().unwrap();
+    }
+
+    // the records found by a node restarting on the storage
+    fn loaded(path: &Path) -> Vec<BlockEvent> {
+        let log = BlockEventLog::open(path, true, &Logger::root(Discard, o!())).unwrap();
+        log.range(
+            SystemTime::from_secs_since_epoch(0),
+            SystemTime::from_secs_since_epoch(u64::from(u32::max_value())),
+        )
+        .wait()
+        .unwrap()
+    }
+
+    #[test]
+    fn events_are_stored_with_the_tip() {
+        let (dir, path, store) = storage("event-log-commit");
+        let log = open(&path);
+        for n in 1..4 {
+            insert(&log, n);
+        }
+        log.commit_tip(Storage::new(store.clone()), hash(3))
+            .wait()
+            .unwrap();
+        assert_eq!(store.get_tag(MAIN_BRANCH_TAG).unwrap(), Some(hash(3)));
+        assert_eq!(loaded(&path), vec![event(1), event(2), event(3)]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn events_are_not_stored_before_the_tip() {
+        let (dir, path, store) = storage("event-log-pending");
+        let log = open(&path);
+        insert(&log, 1);
+        log.commit_tip(Storage::new(store.clone()), hash(1))
+            .wait()
+            .unwrap();
+        insert(&log, 2);
+        // the node stops before the next update of the tip
+        std::mem::drop(log);
+        assert_eq!(store.get_tag(MAIN_BRANCH_TAG).unwrap(), Some(hash(1)));
+        assert_eq!(loaded(&path), vec![event(1)]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn event_stored_again_moves_to_the_end() {
+        let (dir, path, store) = storage("event-log-again");
+        let log = open(&path);
+        for n in &[1, 2, 1] {
+            insert(&log, *n);
+        }
+        log.commit_tip(Storage::new(store), hash(1)).wait().unwrap();
+        assert_eq!(loaded(&path), vec![event(2), event(1)]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn oldest_stored_events_are_dropped() {
+        let (dir, path, store) = storage("event-log-prune");
+        let log = open(&path);
+        let last = MAX_BLOCK_EVENTS as u32 + 1;
+        for n in 0..=last {
+            insert(&log, n);
+        }
+        log.commit_tip(Storage::new(store), hash(last))
+            .wait()
+            .unwrap();
+        let events = loaded(&path);
+        assert_eq!(events.len(), MAX_BLOCK_EVENTS);
+        assert_eq!(events.first(), Some(&event(2)));
+        assert_eq!(events.last(), Some(&event(last)));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn memory_log_puts_the_tag_through_the_storage() {
+        let store = SQLiteBlockStore::<Block>::memory();
+        let log = BlockEventLog::memory();
+        insert(&log, 1);
+        log.commit_tip(Storage::new(store.clone()), hash(1))
+            .wait()
+            .unwrap();
+        assert_eq!(store.get_tag(MAIN_BRANCH_TAG).unwrap(), Some(hash(1)));
+        assert_eq!(log.get(hash(1)).wait().unwrap(), Some(event(1)));
+    }
+}
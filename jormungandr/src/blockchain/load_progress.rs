@@ -0,0 +1,85 @@
+use std::{
+    fmt,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+/// progress of the loading of the blockchain from the storage, shared
+/// with the REST interface while the node starts.
+#[derive(Clone, Default)]
+pub struct LoadProgress {
+    inner: Arc<Mutex<Option<Counters>>>,
+}
+
+struct Counters {
+    started: Instant,
+    total: u64,
+    applied: u64,
+    epoch: u32,
+}
+
+/// The progress at a point of the loading.
+#[derive(Debug, Clone)]
+pub struct LoadStatus {
+    /// number of blocks applied so far
+    pub applied: u64,
+    /// number of blocks to apply, from the genesis block to the stored tip
+    pub total: u64,
+    /// epoch of the last block applied
+    pub epoch: u32,
+    /// estimated time left, from the rate of the blocks applied so far
+    pub eta: Option<Duration>,
+}
+
+impl LoadProgress {
+    /// start counting the blocks applied, out of `total`
+    pub fn start(&self, total: u64) {
+        *self.inner.lock().unwrap() = Some(Counters {
+            started: Instant::now(),
+            total,
+            applied: 0,
+            epoch: 0,
+        });
+    }
+
+    /// record a block of the given epoch as applied
+    pub fn block_applied(&self, epoch: u32) {
+        if let Some(counters) = self.inner.lock().unwrap().as_mut() {
+            counters.applied += 1;
+            counters.epoch = epoch;
+        }
+    }
+
+    /// the current status, `None` if the loading has not started
+    pub fn status(&self) -> Option<LoadStatus> {
+        self.inner.lock().unwrap().as_ref().map(|counters| {
+            let eta = if counters.applied == 0 {
+                None
+            } else {
+                let left = counters.total.saturating_sub(counters.applied);
+                let elapsed = counters.started.elapsed();
+                Some(elapsed.mul_f64(left as f64 / counters.applied as f64))
+            };
+            LoadStatus {
+                applied: counters.applied,
+                total: counters.total,
+                epoch: counters.epoch,
+                eta,
+            }
+        })
+    }
+}
+
+impl fmt::Display for LoadStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}/{} blocks, epoch {}",
+            self.applied, self.total, self.epoch
+        )?;
+        if let Some(eta) = self.eta {
+            write!(f, ", about {}s left", eta.as_secs())?;
+        }
+        Ok(())
+    }
+}
@@ -1,14 +1,19 @@
+mod application_guard;
 mod branch;
 mod candidate;
 mod chain;
 mod chain_selection;
 mod checkpoints;
+mod epoch_transition;
+mod event_log;
+mod load_progress;
 mod multiverse;
 mod process;
 mod reference;
 mod reference_cache;
 mod storage;
 mod tip;
+mod verification;
 
 // Constants
 
@@ -25,15 +30,21 @@ mod chunk_sizes {
 // Re-exports
 
 pub use self::{
-    branch::Branch,
+    application_guard::ApplicationGuard,
+    branch::{Branch, BranchInfo, Branches},
     chain::{
-        new_epoch_leadership_from, Blockchain, Error, ErrorKind, PreCheckedHeader, MAIN_BRANCH_TAG,
+        new_epoch_leadership_from, pre_verify_link, Blockchain, Error, ErrorKind, PreCheckedHeader,
+        MAIN_BRANCH_TAG,
     },
     chain_selection::{compare_against, ComparisonResult},
     checkpoints::Checkpoints,
+    epoch_transition::precompute_epoch_transitions,
+    event_log::BlockEventLog,
+    load_progress::{LoadProgress, LoadStatus},
     multiverse::Multiverse,
     process::{process_new_ref, Process},
     reference::Ref,
+    reference_cache::RefCacheStats,
     storage::Storage,
     tip::Tip,
 };
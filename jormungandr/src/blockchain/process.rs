@@ -2,14 +2,17 @@ use super::{
     candidate,
     chain::{self, AppliedBlock},
     chain_selection::{self, ComparisonResult},
-    Blockchain, Error, ErrorKind, PreCheckedHeader, Ref, Tip, MAIN_BRANCH_TAG,
+    chunk_sizes,
+    verification::{VerificationPool, VerifiedBlock},
+    Blockchain, Error, ErrorKind, PreCheckedHeader, Ref, Tip,
 };
 use crate::{
-    blockcfg::{Block, FragmentId, Header},
+    blockcfg::{Block, FragmentId, Header, Leadership},
     blockchain::Checkpoints,
     intercom::{
         self, BlockMsg, ExplorerMsg, NetworkMsg, PropagateMsg, ReplyHandle, TransactionMsg,
     },
+    leadership::LeaderSlots,
     log,
     network::p2p::Id as NodeId,
     stats_counter::StatsCounter,
@@ -22,8 +25,13 @@ use crate::{
     },
     HeaderHash,
 };
-use chain_core::property::{Block as _, Fragment as _, HasHeader as _, Header as _};
-use jormungandr_lib::interfaces::FragmentStatus;
+use chain_core::property::{
+    Block as _, Fragment as _, HasHeader as _, Header as _, Serialize as _,
+};
+use jormungandr_lib::{
+    interfaces::{BlockEvent, BlockEventOrigin, FragmentStatus},
+    time::SystemTime,
+};
 
 use futures::future::{Either, Loop};
 use slog::Logger;
@@ -32,7 +40,11 @@ use tokio::{
     timer::{timeout, Timeout},
 };
 
-use std::{sync::Arc, time::Duration};
+use std::{
+    convert::Infallible,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
 
 type TimeoutError = timeout::Error<Error>;
 type PullHeadersScheduler = FireForgetScheduler<HeaderHash, NodeId, Checkpoints>;
@@ -40,6 +52,15 @@ type GetNextBlockScheduler = FireForgetScheduler<HeaderHash, NodeId, ()>;
 
 const BRANCH_REPROCESSING_INTERVAL: Duration = Duration::from_secs(60);
 
+/// number of blocks an announced block can be ahead of the local tip
+/// before we stop fetching announced blocks one by one and rely on
+/// pulling the chain of headers instead.
+const ANNOUNCEMENT_CATCH_UP_DISTANCE: u32 = chunk_sizes::BLOCKS as u32;
+
+/// time given to a chain pull catching up with a block announced far ahead
+/// of the tip before the following announcements are pulled again
+const CATCH_UP_PULL_TIMEOUT: Duration = Duration::from_secs(60);
+
 const DEFAULT_TIMEOUT_PROCESS_LEADERSHIP: u64 = 5;
 const DEFAULT_TIMEOUT_PROCESS_ANNOUNCEMENT: u64 = 5;
 const DEFAULT_TIMEOUT_PROCESS_BLOCKS: u64 = 60;
@@ -67,6 +88,7 @@ pub struct Process {
     pub fragment_msgbox: MessageBox<TransactionMsg>,
     pub explorer_msgbox: Option<MessageBox<ExplorerMsg>>,
     pub garbage_collection_interval: Duration,
+    pub leader_slots: LeaderSlots,
 }
 
 impl Process {
@@ -76,14 +98,19 @@ impl Process {
         input: MessageQueue<BlockMsg>,
     ) -> impl Future<Item = (), Error = ()> {
         self.start_branch_reprocessing(&service_info);
+        self.start_garbage_collector(&service_info);
         let pull_headers_scheduler = self.spawn_pull_headers_scheduler(&service_info);
         let get_next_block_scheduler = self.spawn_get_next_block_scheduler(&service_info);
+        let verification_pool = VerificationPool::new();
+        let catch_up_pull = CatchUpPull::default();
         input.for_each(move |msg| {
             self.handle_input(
                 &service_info,
                 msg,
                 &pull_headers_scheduler,
                 &get_next_block_scheduler,
+                &verification_pool,
+                &catch_up_pull,
             );
             future::ok(())
         })
@@ -95,6 +122,8 @@ impl Process {
         input: BlockMsg,
         pull_headers_scheduler: &PullHeadersScheduler,
         get_next_block_scheduler: &GetNextBlockScheduler,
+        verification_pool: &VerificationPool,
+        catch_up_pull: &CatchUpPull,
     ) {
         let blockchain = self.blockchain.clone();
         let blockchain_tip = self.blockchain_tip.clone();
@@ -114,8 +143,14 @@ impl Process {
 
                 info!(logger, "receiving block from leadership service");
 
-                let process_new_block =
-                    process_leadership_block(logger.clone(), blockchain.clone(), block.clone());
+                let event = BlockEventBuilder::new(&block, BlockEventOrigin::Leadership);
+                let process_new_block = process_leadership_block(
+                    logger.clone(),
+                    blockchain.clone(),
+                    blockchain_tip.clone(),
+                    block.clone(),
+                    event,
+                );
 
                 let fragments = block.fragments().map(|f| f.id()).collect();
 
@@ -171,6 +206,8 @@ impl Process {
                     blockchain_tip.clone(),
                     header,
                     node_id,
+                    verification_pool.clone(),
+                    catch_up_pull.clone(),
                     pull_headers_scheduler.clone(),
                     get_next_block_scheduler.clone(),
                     logger.clone(),
@@ -180,95 +217,24 @@ impl Process {
                     error!(logger, "cannot process block announcement" ; "reason" => ?err)
                 }))
             }
-            BlockMsg::NetworkBlocks(handle) => {
-                struct State<S> {
-                    stream: S,
-                    reply: ReplyHandle<()>,
-                    candidate: Option<Arc<Ref>>,
-                }
-
+            BlockMsg::NetworkBlocks(handle, node_id) => {
                 info!(info.logger(), "receiving block stream from network");
 
                 let logger = info.logger().clone();
-                let logger_fold = logger.clone();
                 let logger_err = logger.clone();
-                let blockchain_fold = blockchain.clone();
-                let (stream, reply) = handle.into_stream_and_reply();
-                let stream =
-                    stream.map_err(|()| Error::from("Error while processing block input stream"));
-                let state = State {
-                    stream,
-                    reply,
-                    candidate: None,
-                };
-                let get_next_block_scheduler = get_next_block_scheduler.clone();
-                let future = future::loop_fn(state, move |state| {
-                    let blockchain = blockchain_fold.clone();
-                    let tx_msg_box = tx_msg_box.clone();
-                    let explorer_msg_box = explorer_msg_box.clone();
-                    let stats_counter = stats_counter.clone();
-                    let logger = logger_fold.clone();
-                    let get_next_block_scheduler = get_next_block_scheduler.clone();
-                    let State {
-                        stream,
-                        reply,
-                        candidate,
-                    } = state;
-                    stream.into_future().map_err(|(e, _)| e).and_then(
-                        move |(maybe_block, stream)| match maybe_block {
-                            Some(block) => Either::A(
-                                process_network_block(
-                                    blockchain,
-                                    block,
-                                    tx_msg_box,
-                                    explorer_msg_box,
-                                    get_next_block_scheduler,
-                                    logger.clone(),
-                                )
-                                .then(move |res| match res {
-                                    Ok(Some(candidate)) => {
-                                        stats_counter.add_block_recv_cnt(1);
-                                        Ok(Loop::Continue(State {
-                                            stream,
-                                            reply,
-                                            candidate: Some(candidate),
-                                        }))
-                                    }
-                                    Ok(None) => {
-                                        reply.reply_ok(());
-                                        Ok(Loop::Break(candidate))
-                                    }
-                                    Err(e) => {
-                                        info!(
-                                            logger,
-                                            "validation of an incoming block failed";
-                                            "reason" => ?e,
-                                        );
-                                        reply.reply_error(network_block_error_into_reply(e));
-                                        Ok(Loop::Break(candidate))
-                                    }
-                                }),
-                            ),
-                            None => {
-                                reply.reply_ok(());
-                                Either::B(future::ok(Loop::Break(candidate)))
-                            }
-                        },
-                    )
-                })
-                .and_then(move |maybe_updated| match maybe_updated {
-                    Some(new_block_ref) => {
-                        let future = process_and_propagate_new_ref(
-                            logger,
-                            blockchain,
-                            blockchain_tip,
-                            Arc::clone(&new_block_ref),
-                            network_msg_box,
-                        );
-                        Either::A(future)
-                    }
-                    None => Either::B(future::ok(())),
-                });
+                let future = process_network_blocks(
+                    blockchain,
+                    blockchain_tip,
+                    handle,
+                    node_id,
+                    network_msg_box,
+                    tx_msg_box,
+                    explorer_msg_box,
+                    stats_counter,
+                    get_next_block_scheduler.clone(),
+                    verification_pool.clone(),
+                    logger,
+                );
 
                 info.spawn(
                     "process network blocks", Timeout::new(future, Duration::from_secs(DEFAULT_TIMEOUT_PROCESS_BLOCKS)).map_err(move |err: TimeoutError| {
@@ -336,6 +302,22 @@ impl Process {
         )
     }
 
+    fn start_garbage_collector(&self, info: &TokioServiceInfo) {
+        let blockchain = self.blockchain.clone();
+        let leader_slots = self.leader_slots.clone();
+
+        info.run_periodic(
+            "blockchain garbage collection",
+            self.garbage_collection_interval,
+            move || {
+                let blockchain = blockchain.clone();
+                leader_slots
+                    .wait_idle()
+                    .and_then(move |()| blockchain.purge())
+            },
+        )
+    }
+
     fn spawn_pull_headers_scheduler(&self, info: &TokioServiceInfo) -> PullHeadersScheduler {
         let network_msgbox = self.network_msgbox.clone();
         let scheduler_logger = info.logger().clone();
@@ -444,56 +426,73 @@ pub fn process_new_ref(
     use tokio::prelude::future::Either::*;
 
     let candidate_hash = candidate.hash();
-    let mut storage = blockchain.storage().clone();
+    let storage = blockchain.storage().clone();
+    let event_log = blockchain.event_log().clone();
+    let guard = blockchain.application_guard().clone();
 
-    tip.clone()
-        .get_ref()
-        .and_then(move |tip_ref| {
-            if tip_ref.hash() == candidate.block_parent_hash() {
-                info!(
-                    logger,
-                    "update current branch tip: {} -> {}",
-                    tip_ref.header().description(),
-                    candidate.header().description(),
-                );
-                A(A(tip.update_ref(candidate).map(|_| true)))
-            } else {
-                match chain_selection::compare_against(blockchain.storage(), &tip_ref, &candidate) {
-                    ComparisonResult::PreferCurrent => {
-                        info!(
-                            logger,
-                            "create new branch with tip {} | current-tip {}",
-                            candidate.header().description(),
-                            tip_ref.header().description(),
-                        );
-                        A(B(future::ok(false)))
-                    }
-                    ComparisonResult::PreferCandidate => {
-                        info!(
-                            logger,
-                            "switching branch from {} to {}",
-                            tip_ref.header().description(),
-                            candidate.header().description(),
-                        );
-                        B(blockchain
-                            .branches_mut()
-                            .apply_or_create(candidate)
-                            .and_then(move |branch| tip.swap(branch))
-                            .map(|()| true))
-                    }
+    guard.selecting(move || {
+        tip.clone()
+            .get_ref()
+            .and_then(move |tip_ref| {
+                if tip_ref.hash() == candidate.block_parent_hash() {
+                    info!(
+                        logger,
+                        "update current branch tip: {} -> {}",
+                        tip_ref.header().description(),
+                        candidate.header().description(),
+                    );
+                    A(tip.update_ref(candidate).map(|_| true))
+                } else {
+                    B(
+                        chain_selection::compare_against(&blockchain, &tip_ref, &candidate)
+                            .and_then(move |comparison| match comparison {
+                                ComparisonResult::PreferCurrent => {
+                                    info!(
+                                        logger,
+                                        "create new branch with tip {} | current-tip {}",
+                                        candidate.header().description(),
+                                        tip_ref.header().description(),
+                                    );
+                                    A(future::ok(false))
+                                }
+                                ComparisonResult::RollbackTooDeep => {
+                                    warn!(
+                                        logger,
+                                        "not switching to a better branch, it would roll back more blocks than allowed";
+                                        "max_rollback_depth" => blockchain.max_rollback_depth(),
+                                        "current_tip" => %tip_ref.header().description(),
+                                        "candidate" => %candidate.header().description(),
+                                    );
+                                    A(future::ok(false))
+                                }
+                                ComparisonResult::PreferCandidate => {
+                                    info!(
+                                        logger,
+                                        "switching branch from {} to {}",
+                                        tip_ref.header().description(),
+                                        candidate.header().description(),
+                                    );
+                                    B(blockchain
+                                        .branches_mut()
+                                        .apply_or_create(candidate)
+                                        .and_then(move |branch| tip.swap(branch))
+                                        .map(|()| true))
+                                }
+                            }),
+                    )
                 }
-            }
-        })
-        .map_err(|_: std::convert::Infallible| unreachable!())
-        .and_then(move |tip_updated| {
-            if tip_updated {
-                A(storage
-                    .put_tag(MAIN_BRANCH_TAG.to_owned(), candidate_hash)
-                    .map_err(|e| Error::with_chain(e, "Cannot update the main storage's tip")))
-            } else {
-                B(future::ok(()))
-            }
-        })
+            })
+            .map_err(|_: std::convert::Infallible| unreachable!())
+            .and_then(move |tip_updated| {
+                if tip_updated {
+                    A(event_log
+                        .commit_tip(storage, candidate_hash)
+                        .map_err(|e| Error::with_chain(e, "Cannot update the main storage's tip")))
+                } else {
+                    B(future::ok(()))
+                }
+            })
+    })
 }
 
 fn process_and_propagate_new_ref(
@@ -521,9 +520,12 @@ fn process_and_propagate_new_ref(
 pub fn process_leadership_block(
     logger: Logger,
     blockchain: Blockchain,
+    tip: Tip,
     block: Block,
+    event: BlockEventBuilder,
 ) -> impl Future<Item = Arc<Ref>, Error = Error> {
     let end_blockchain = blockchain.clone();
+    let record_blockchain = blockchain.clone();
     let header = block.header();
     let parent_hash = block.parent_id();
     let logger1 = logger.clone();
@@ -551,12 +553,22 @@ pub fn process_leadership_block(
             end_blockchain.apply_and_store_block(post_checked, block)
         })
         .map_err(|err| Error::with_chain(err, "cannot process leadership block"))
-        .map(move |applied| {
+        .and_then(move |applied| {
             let new_ref = applied
                 .new_ref()
                 .expect("block from leadership must be unique");
             info!(logger, "block from leader event successfully stored");
-            new_ref
+            tip.get_ref()
+                .and_then(move |tip_ref| {
+                    record_block_event(
+                        &record_blockchain,
+                        tip_ref.hash(),
+                        Arc::clone(&new_ref),
+                        event,
+                    )
+                    .map(move |()| new_ref)
+                })
+                .map_err(|_: Infallible| unreachable!())
         })
 }
 
@@ -565,72 +577,297 @@ fn process_block_announcement(
     blockchain_tip: Tip,
     header: Header,
     node_id: NodeId,
+    verification_pool: VerificationPool,
+    catch_up_pull: CatchUpPull,
     mut pull_headers_scheduler: PullHeadersScheduler,
     mut get_next_block_scheduler: GetNextBlockScheduler,
     logger: Logger,
 ) -> impl Future<Item = (), Error = Error> {
-    blockchain
+    if verification_pool.is_applied(&header.hash()) {
+        debug!(logger, "block has just been applied");
+        return Either::A(future::ok(()));
+    }
+    let tip_blockchain = blockchain.clone();
+    let future = blockchain
         .pre_check_header(header, false)
-        .and_then(move |pre_checked| match pre_checked {
-            PreCheckedHeader::AlreadyPresent { .. } => {
-                debug!(logger, "block is already present");
-                Either::A(future::ok(()))
+        .and_then(move |pre_checked| {
+            blockchain_tip
+                .get_ref()
+                .map(move |tip_ref| (pre_checked, tip_ref, blockchain_tip))
+        })
+        .and_then(move |(pre_checked, tip_ref, blockchain_tip)| {
+            let pull_headers = move |to, logger: Logger| {
+                tip_blockchain
+                    .get_checkpoints(blockchain_tip.branch())
+                    .map(move |from| {
+                        pull_headers_scheduler
+                            .schedule(to, node_id, from)
+                            .unwrap_or_else(move |err| {
+                                error!(
+                                    logger,
+                                    "cannot schedule pulling headers"; "reason" => ?err
+                                )
+                            });
+                    })
+            };
+            match pre_checked {
+                PreCheckedHeader::AlreadyPresent { .. } => {
+                    debug!(logger, "block is already present");
+                    Either::A(future::ok(()))
+                }
+                PreCheckedHeader::MissingParent { header, .. }
+                | PreCheckedHeader::HeaderWithCache { header, .. }
+                    if is_far_ahead(&header, &tip_ref) =>
+                {
+                    if catch_up_pull.start(header.chain_length().into()) {
+                        debug!(
+                            logger,
+                            "announced block is far ahead of the tip, pull the headers to catch up";
+                            "tip" => %tip_ref.header().description(),
+                        );
+                        Either::B(pull_headers(header.hash(), logger))
+                    } else {
+                        debug!(
+                            logger,
+                            "announced block is far ahead of the tip, already catching up";
+                            "tip" => %tip_ref.header().description(),
+                        );
+                        Either::A(future::ok(()))
+                    }
+                }
+                PreCheckedHeader::MissingParent { header, .. } => {
+                    debug!(logger, "block is missing a locally stored parent");
+                    Either::B(pull_headers(header.hash(), logger))
+                }
+                PreCheckedHeader::HeaderWithCache { header, .. } => {
+                    debug!(
+                        logger,
+                        "Announced block has a locally stored parent, fetch it"
+                    );
+                    get_next_block_scheduler
+                        .schedule(header.id(), node_id, ())
+                        .unwrap_or_else(move |err| {
+                            error!(
+                                logger,
+                                "cannot schedule getting next block"; "reason" => ?err
+                            )
+                        });
+                    Either::A(future::ok(()))
+                }
             }
-            PreCheckedHeader::MissingParent { header, .. } => {
-                debug!(logger, "block is missing a locally stored parent");
-                let to = header.hash();
-                Either::B(
-                    blockchain
-                        .get_checkpoints(blockchain_tip.branch())
-                        .map(move |from| {
-                            pull_headers_scheduler
-                                .schedule(to, node_id, from)
-                                .unwrap_or_else(move |err| {
-                                    error!(
-                                        logger,
-                                        "cannot schedule pulling headers"; "reason" => ?err
-                                    )
-                                });
-                        }),
-                )
+        })
+        .map_err(|err| Error::with_chain(err, "cannot process block announcement"));
+    Either::B(future)
+}
+
+/// check whether the given header is too far ahead of the local tip for
+/// its announcement to be followed on its own: the node is catching up,
+/// and a single chain pull retrieves the blocks of several announcements.
+fn is_far_ahead(header: &Header, tip_ref: &Ref) -> bool {
+    let header_length = u32::from(header.chain_length());
+    let tip_length = u32::from(tip_ref.chain_length());
+    header_length.saturating_sub(tip_length) > ANNOUNCEMENT_CATCH_UP_DISTANCE
+}
+
+/// The chain pull started to catch up with a block announced far ahead of
+/// the tip, shared between all the clones. While it is in progress, the
+/// announcements of the blocks shortly following that block do not start
+/// other chain pulls: the blocks left are pulled once the node is close
+/// to the tip of the network.
+#[derive(Clone, Default)]
+struct CatchUpPull(Arc<Mutex<Option<(u32, Instant)>>>);
+
+impl CatchUpPull {
+    /// returns `true` if a chain pull is to be started to catch up with the
+    /// block at the given chain length
+    fn start(&self, chain_length: u32) -> bool {
+        self.start_at(chain_length, Instant::now())
+    }
+
+    fn start_at(&self, chain_length: u32, now: Instant) -> bool {
+        let mut pull = self.0.lock().unwrap();
+        match *pull {
+            Some((target, started))
+                if now.duration_since(started) < CATCH_UP_PULL_TIMEOUT
+                    && chain_length.saturating_sub(target) <= ANNOUNCEMENT_CATCH_UP_DISTANCE =>
+            {
+                false
             }
-            PreCheckedHeader::HeaderWithCache {
-                header,
-                parent_ref: _,
-            } => {
-                debug!(
-                    logger,
-                    "Announced block has a locally stored parent, fetch it"
-                );
-                get_next_block_scheduler
-                    .schedule(header.id(), node_id, ())
-                    .unwrap_or_else(move |err| {
-                        error!(
-                            logger,
-                            "cannot schedule getting next block"; "reason" => ?err
-                        )
-                    });
-                Either::A(future::ok(()))
+            _ => {
+                *pull = Some((chain_length, now));
+                true
             }
+        }
+    }
+}
+
+/// Applies the blocks of a stream received from the network, in order,
+/// then updates the tip with the last block applied.
+///
+/// The blocks are recorded in the block event log against the tip as it
+/// will be once the blocks of the stream applied before them have been
+/// chosen as the tip: the tip itself is only updated at the end.
+fn process_network_blocks(
+    blockchain: Blockchain,
+    blockchain_tip: Tip,
+    handle: intercom::RequestStreamHandle<Block, ()>,
+    node_id: Option<NodeId>,
+    network_msg_box: MessageBox<NetworkMsg>,
+    tx_msg_box: MessageBox<TransactionMsg>,
+    explorer_msg_box: Option<MessageBox<ExplorerMsg>>,
+    stats_counter: StatsCounter,
+    get_next_block_scheduler: GetNextBlockScheduler,
+    verification_pool: VerificationPool,
+    logger: Logger,
+) -> impl Future<Item = (), Error = Error> {
+    struct State<S> {
+        stream: S,
+        reply: ReplyHandle<()>,
+        candidate: Option<Arc<Ref>>,
+        tip: HeaderHash,
+    }
+
+    let blockchain_fold = blockchain.clone();
+    let logger_fold = logger.clone();
+    let origin = BlockEventOrigin::Network {
+        peer: node_id.map(|id| id.to_string()),
+    };
+    let (stream, reply) = handle.into_stream_and_reply();
+    let stream = stream.map_err(|()| Error::from("Error while processing block input stream"));
+    blockchain_tip
+        .get_ref()
+        .and_then(move |tip| {
+            // the headers are verified ahead of the ordered application
+            let state = State {
+                stream: verification_pool.verify_stream(stream, Arc::clone(&tip)),
+                reply,
+                candidate: None,
+                tip: tip.hash(),
+            };
+            future::loop_fn(state, move |state| {
+                let State {
+                    stream,
+                    reply,
+                    candidate,
+                    tip,
+                } = state;
+                let blockchain = blockchain_fold.clone();
+                let origin = origin.clone();
+                let tx_msg_box = tx_msg_box.clone();
+                let explorer_msg_box = explorer_msg_box.clone();
+                let stats_counter = stats_counter.clone();
+                let logger = logger_fold.clone();
+                let get_next_block_scheduler = get_next_block_scheduler.clone();
+                let verification_pool = verification_pool.clone();
+                stream
+                    .into_future()
+                    .map_err(|(e, _)| e)
+                    .and_then(move |(maybe_block, stream)| {
+                        let verified = match maybe_block {
+                            Some(verified) => verified,
+                            None => {
+                                reply.reply_ok(());
+                                return Either::A(future::ok(Loop::Break(candidate)));
+                            }
+                        };
+                        let future = process_network_block(
+                            blockchain,
+                            tip,
+                            verified,
+                            origin,
+                            tx_msg_box,
+                            explorer_msg_box,
+                            get_next_block_scheduler,
+                            verification_pool,
+                            logger.clone(),
+                        )
+                        .then(move |res| match res {
+                            Ok(Some(candidate)) => {
+                                stats_counter.add_block_recv_cnt(1);
+                                let tip = tip_after_block(tip, &candidate);
+                                Ok(Loop::Continue(State {
+                                    stream,
+                                    reply,
+                                    candidate: Some(candidate),
+                                    tip,
+                                }))
+                            }
+                            Ok(None) => {
+                                reply.reply_ok(());
+                                Ok(Loop::Break(candidate))
+                            }
+                            Err(e) => {
+                                info!(
+                                    logger,
+                                    "validation of an incoming block failed";
+                                    "reason" => ?e,
+                                );
+                                reply.reply_error(network_block_error_into_reply(e));
+                                Ok(Loop::Break(candidate))
+                            }
+                        });
+                        Either::B(future)
+                    })
+            })
         })
-        .map_err(|err| Error::with_chain(err, "cannot process block announcement"))
+        .and_then(move |maybe_updated| match maybe_updated {
+            Some(new_block_ref) => Either::A(process_and_propagate_new_ref(
+                logger,
+                blockchain,
+                blockchain_tip,
+                Arc::clone(&new_block_ref),
+                network_msg_box,
+            )),
+            None => Either::B(future::ok(())),
+        })
+}
+
+// The tip once the block is applied, for the records of the blocks
+// applied after it in the same stream: a block extending the tip is
+// expected to become the tip, while a block on a fork leaves it unchanged.
+fn tip_after_block(tip: HeaderHash, block_ref: &Ref) -> HeaderHash {
+    if block_ref.header().block_parent_hash() == tip {
+        block_ref.hash()
+    } else {
+        tip
+    }
 }
 
+/// Applies a block received from the network, recorded in the block
+/// event log against `tip`.
 fn process_network_block(
     blockchain: Blockchain,
-    block: Block,
+    tip: HeaderHash,
+    verified: VerifiedBlock,
+    origin: BlockEventOrigin,
     tx_msg_box: MessageBox<TransactionMsg>,
     explorer_msg_box: Option<MessageBox<ExplorerMsg>>,
     mut get_next_block_scheduler: GetNextBlockScheduler,
+    verification_pool: VerificationPool,
     logger: Logger,
 ) -> impl Future<Item = Option<Arc<Ref>>, Error = chain::Error> {
+    let VerifiedBlock {
+        block,
+        verified_with,
+    } = verified;
     get_next_block_scheduler
         .declare_completed(block.id())
         .unwrap_or_else(
             |e| error!(logger, "get next block schedule completion failed"; "reason" => ?e),
         );
     let header = block.header();
-    blockchain
+    if verification_pool.is_applied(&header.hash()) {
+        debug!(
+            logger,
+            "block has just been applied";
+            "hash" => %header.hash(),
+            "parent" => %header.parent_id(),
+            "date" => %header.block_date(),
+        );
+        return Either::A(future::ok(None));
+    }
+    let event = BlockEventBuilder::new(&block, origin);
+    let future = blockchain
         .pre_check_header(header, false)
         .and_then(move |pre_checked| match pre_checked {
             PreCheckedHeader::AlreadyPresent { header, .. } => {
@@ -659,8 +896,11 @@ fn process_network_block(
             PreCheckedHeader::HeaderWithCache { parent_ref, .. } => {
                 let post_check_and_apply = check_and_apply_block(
                     blockchain,
+                    tip,
                     parent_ref,
                     block,
+                    verified_with,
+                    event,
                     tx_msg_box,
                     explorer_msg_box,
                     logger,
@@ -668,12 +908,21 @@ fn process_network_block(
                 Either::B(post_check_and_apply)
             }
         })
+        .inspect(move |applied| {
+            if let Some(block_ref) = applied {
+                verification_pool.mark_applied(block_ref.hash());
+            }
+        });
+    Either::B(future)
 }
 
 fn check_and_apply_block(
     blockchain: Blockchain,
+    tip: HeaderHash,
     parent_ref: Arc<Ref>,
     block: Block,
+    verified_with: Option<Arc<Leadership>>,
+    event: BlockEventBuilder,
     tx_msg_box: MessageBox<TransactionMsg>,
     explorer_msg_box: Option<MessageBox<ExplorerMsg>>,
     logger: Logger,
@@ -685,7 +934,7 @@ fn check_and_apply_block(
     let logger = logger.clone();
     let header = block.header();
     blockchain
-        .post_check_header(header, parent_ref)
+        .post_check_verified_header(header, parent_ref, verified_with)
         .and_then(move |post_checked| {
             let header = post_checked.header();
             let block_hash = header.hash();
@@ -706,6 +955,13 @@ fn check_and_apply_block(
                 .apply_and_store_block(post_checked, block)
                 .and_then(move |applied_block| {
                     if let AppliedBlock::New(block_ref) = applied_block {
+                        let record = record_block_event(
+                            &blockchain1,
+                            tip,
+                            Arc::clone(&block_ref),
+                            event,
+                        )
+                        .map_err(|_: Infallible| unreachable!());
                         let header = block_ref.header();
                         debug!(
                             logger,
@@ -724,19 +980,71 @@ fn check_and_apply_block(
                                     error!(logger, "cannot add block to explorer: {}", err)
                                 });
                         }
-                        Ok(Some(block_ref))
+                        Either::A(record.map(move |()| Some(block_ref)))
                     } else {
                         debug!(
                             logger,
                             "block is already present in storage, not applied";
                             "hash" => %block_hash,
                         );
-                        Ok(None)
+                        Either::B(future::ok(None))
                     }
                 })
         })
 }
 
+/// information on a block collected when it is received, completed
+/// into a [`BlockEvent`] once the block has been applied.
+pub struct BlockEventBuilder {
+    received_at: SystemTime,
+    started: Instant,
+    origin: BlockEventOrigin,
+    fragment_count: usize,
+    size: usize,
+}
+
+impl BlockEventBuilder {
+    pub fn new(block: &Block, origin: BlockEventOrigin) -> Self {
+        BlockEventBuilder {
+            received_at: SystemTime::now(),
+            started: Instant::now(),
+            origin,
+            fragment_count: block.fragments().count(),
+            size: block
+                .serialize_as_vec()
+                .map(|bytes| bytes.len())
+                .unwrap_or(0),
+        }
+    }
+
+    fn build(self, block_ref: &Ref, tip: HeaderHash) -> BlockEvent {
+        let header = block_ref.header();
+        BlockEvent {
+            hash: header.hash().into(),
+            parent: header.block_parent_hash().into(),
+            date: header.block_date().clone().into(),
+            chain_length: header.chain_length().into(),
+            received_at: self.received_at,
+            origin: self.origin,
+            validation_duration: self.started.elapsed().into(),
+            fragment_count: self.fragment_count,
+            size: self.size,
+            tip: tip.into(),
+        }
+    }
+}
+
+/// record the event of the newly applied block in the block event log.
+/// The record is stored with the next update of the tip.
+fn record_block_event(
+    blockchain: &Blockchain,
+    tip: HeaderHash,
+    block_ref: Arc<Ref>,
+    event: BlockEventBuilder,
+) -> impl Future<Item = (), Error = Infallible> {
+    blockchain.event_log().insert(event.build(&block_ref, tip))
+}
+
 fn network_block_error_into_reply(err: chain::Error) -> intercom::Error {
     use super::chain::ErrorKind::*;
 
@@ -763,3 +1071,29 @@ fn chain_header_error_into_reply(err: candidate::Error) -> intercom::Error {
         _ => intercom::Error::failed(err.to_string()),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn catch_up_pull_is_not_repeated() {
+        let pull = CatchUpPull::default();
+        let now = Instant::now();
+        assert!(pull.start_at(1000, now));
+        assert!(!pull.start_at(1001, now));
+        assert!(!pull.start_at(1000 + ANNOUNCEMENT_CATCH_UP_DISTANCE, now));
+    }
+
+    #[test]
+    fn catch_up_pull_is_restarted() {
+        let pull = CatchUpPull::default();
+        let now = Instant::now();
+        assert!(pull.start_at(1000, now));
+        assert!(pull.start_at(1001 + ANNOUNCEMENT_CATCH_UP_DISTANCE, now));
+        assert!(pull.start_at(
+            1001 + ANNOUNCEMENT_CATCH_UP_DISTANCE,
+            now + CATCH_UP_PULL_TIMEOUT
+        ));
+    }
+}
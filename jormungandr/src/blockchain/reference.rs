@@ -1,5 +1,6 @@
 use crate::blockcfg::{
-    BlockDate, ChainLength, Header, HeaderHash, Leadership, Ledger, LedgerParameters,
+    BlockDate, ChainLength, Epoch as LedgerEpoch, Header, HeaderHash, Leadership, Ledger,
+    LedgerParameters,
 };
 use chain_impl_mockchain::multiverse::GCRoot;
 use chain_time::{
@@ -7,7 +8,7 @@ use chain_time::{
     Epoch, Slot, TimeFrame,
 };
 use std::{
-    sync::Arc,
+    sync::{Arc, Mutex},
     time::{Duration, SystemTime},
 };
 
@@ -41,6 +42,12 @@ pub struct Ref {
     /// the parent `Ref`. Otherwise it will be copied from `Ref` to `Ref`.
     ///
     previous_epoch_state: Option<Arc<Ref>>,
+
+    /// the leadership schedule of an epoch computed from the ledger state
+    /// of this `Ref`, kept for the transition to that epoch
+    ///
+    /// this object will be shared between the clones of this `Ref`
+    leadership_from_state: Arc<Mutex<Option<(LedgerEpoch, Arc<Leadership>)>>>,
 }
 
 impl Ref {
@@ -67,6 +74,7 @@ impl Ref {
             epoch_ledger_parameters,
             header,
             previous_epoch_state,
+            leadership_from_state: Arc::new(Mutex::new(None)),
         }
     }
 
@@ -118,6 +126,16 @@ impl Ref {
         self.previous_epoch_state.as_ref()
     }
 
+    /// the leadership schedule of `epoch` computed from the ledger state
+    /// of this `Ref`. The schedule is computed without holding the lock,
+    /// so callers asking for it at the same time may all compute it; the
+    /// first one stored is kept and returned to all of them.
+    pub fn leadership_from_state(&self, epoch: LedgerEpoch) -> Arc<Leadership> {
+        computed_once(&self.leadership_from_state, epoch, || {
+            Leadership::new(epoch, &self.ledger)
+        })
+    }
+
     /// get the chain_time's `Slot`. This allows to compute an accurate
     /// block time via a given time_frame or a precise block time
     pub fn slot(&self) -> Slot {
@@ -150,3 +168,78 @@ impl Ref {
         SystemTime::now().duration_since(self.time())
     }
 }
+
+/// the value of `epoch` in `slot`, or else the one computed with `compute`
+/// out of the lock and stored unless another one was stored meanwhile
+fn computed_once<T, F>(
+    slot: &Mutex<Option<(LedgerEpoch, Arc<T>)>>,
+    epoch: LedgerEpoch,
+    compute: F,
+) -> Arc<T>
+where
+    F: FnOnce() -> T,
+{
+    if let Some((computed_epoch, value)) = slot.lock().unwrap().as_ref() {
+        if *computed_epoch == epoch {
+            return value.clone();
+        }
+    }
+    let value = Arc::new(compute());
+    let mut stored = slot.lock().unwrap();
+    match stored.as_ref() {
+        Some((computed_epoch, stored_value)) if *computed_epoch == epoch => stored_value.clone(),
+        _ => {
+            *stored = Some((epoch, value.clone()));
+            value
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn value_is_computed_once_per_epoch() {
+        let slot = Mutex::new(None);
+        assert_eq!(*computed_once(&slot, 1, || 10), 10);
+        assert_eq!(*computed_once(&slot, 1, || panic!("computed again")), 10);
+        assert_eq!(*computed_once(&slot, 2, || 20), 20);
+        assert_eq!(*computed_once(&slot, 2, || panic!("computed again")), 20);
+    }
+
+    #[test]
+    fn value_is_computed_out_of_the_lock() {
+        let slot = Mutex::new(None);
+        let value = computed_once(&slot, 1, || {
+            assert!(slot.try_lock().is_ok(), "computed with the lock held");
+            10
+        });
+        assert_eq!(*value, 10);
+    }
+
+    #[test]
+    fn first_value_stored_is_kept() {
+        let slot = Mutex::new(None);
+        // another caller stores its value while this one computes
+        let value = computed_once(&slot, 1, || {
+            assert_eq!(*computed_once(&slot, 1, || 11), 11);
+            10
+        });
+        assert_eq!(*value, 11);
+        assert_eq!(*computed_once(&slot, 1, || 12), 11);
+    }
+
+    #[test]
+    fn value_of_an_older_epoch_is_replaced() {
+        let slot = Mutex::new(None);
+        computed_once(&slot, 2, || 20);
+        // a value computed meanwhile for another epoch does not count
+        let value = computed_once(&slot, 3, || {
+            computed_once(&slot, 1, || 10);
+            30
+        });
+        assert_eq!(*value, 30);
+        assert_eq!(*computed_once(&slot, 3, || 31), 30);
+    }
+}
@@ -1,5 +1,14 @@
-use crate::{blockcfg::HeaderHash, blockchain::Ref};
-use std::{collections::HashMap, convert::Infallible, sync::Arc, time::Duration};
+use crate::{
+    blockcfg::{ChainLength, HeaderHash},
+    blockchain::Ref,
+};
+use linked_hash_map::LinkedHashMap;
+use std::{
+    collections::{BTreeMap, HashMap},
+    convert::Infallible,
+    sync::Arc,
+    time::Duration,
+};
 use tokio::{
     prelude::*,
     sync::lock::Lock,
@@ -13,6 +22,15 @@ use tokio::{
 /// The cache expired [`Ref`] will be removed only if the [`Ref`]'s
 /// TTL has expired and [`purge`] has been called and has completed.
 ///
+/// If the cache is given a maximum number of entries, the least
+/// recently accessed [`Ref`] is removed as soon as a new one would
+/// exceed it.
+///
+/// The [`Ref`] of the last block of an epoch, whose ledger state the
+/// leadership of the following epochs is computed from, is pinned for
+/// the given number of the latest epochs: it stays in the cache
+/// regardless of its TTL and of the maximum number of entries.
+///
 /// [`Ref`]: ./struct.Ref.html
 /// [`purge`]: ./struct.Ref.html#method.purge
 #[derive(Clone)]
@@ -22,18 +40,45 @@ pub struct RefCache {
 
 /// cache of already loaded in-memory block `Ref`
 struct RefCacheData {
-    entries: HashMap<HeaderHash, (Arc<Ref>, delay_queue::Key)>,
+    // ordered from the least to the most recently accessed
+    entries: LinkedHashMap<HeaderHash, (Arc<Ref>, delay_queue::Key)>,
     expirations: DelayQueue<HeaderHash>,
 
+    // the `Ref`s of the last blocks of the latest epochs, by epoch
+    pinned: BTreeMap<u32, HashMap<HeaderHash, Arc<Ref>>>,
+
     ttl: Duration,
+    max_entries: Option<usize>,
+    pinned_epochs: usize,
+
+    expired: u64,
+    evicted: u64,
+}
+
+/// Occupancy of the cache and number of [`Ref`]s dropped from it.
+///
+/// [`Ref`]: ./struct.Ref.html
+#[derive(Clone, Copy, Debug)]
+pub struct RefCacheStats {
+    /// number of [`Ref`]s in the cache, the pinned ones excluded
+    pub entries: usize,
+    /// number of [`Ref`]s pinned as the last blocks of their epoch
+    pub pinned: usize,
+    /// number of [`Ref`]s dropped as their TTL expired
+    pub expired: u64,
+    /// number of [`Ref`]s dropped to stay within the maximum number of
+    /// entries
+    pub evicted: u64,
 }
 
 impl RefCache {
-    /// create a new `RefCache` with the given expiration `Duration`.
+    /// create a new `RefCache` with the given expiration `Duration`,
+    /// optionally the maximum number of entries, and the number of the
+    /// latest epochs whose last `Ref` is pinned.
     ///
-    pub fn new(ttl: Duration) -> Self {
+    pub fn new(ttl: Duration, max_entries: Option<usize>, pinned_epochs: usize) -> Self {
         RefCache {
-            inner: Lock::new(RefCacheData::new(ttl)),
+            inner: Lock::new(RefCacheData::new(ttl, max_entries, pinned_epochs)),
         }
     }
 
@@ -80,6 +125,31 @@ impl RefCache {
         future::poll_fn(move || Ok(inner.poll_lock())).map(move |mut guard| guard.remove(&key))
     }
 
+    /// return a future to the number of blocks of the branch ending at
+    /// `tip` above its fork point with the branch ending at `other`,
+    /// walking back their parents in the cache. The walk stops past
+    /// `limit` blocks, resolving to `limit + 1`, and resolves to `None`
+    /// if it reaches a block no longer in the cache.
+    ///
+    pub fn rollback_depth(
+        &self,
+        tip: &Ref,
+        other: &Ref,
+        limit: u32,
+    ) -> impl Future<Item = Option<u32>, Error = Infallible> {
+        let mut inner = self.inner.clone();
+        let tip = (tip.hash(), tip.chain_length());
+        let other = (other.hash(), other.chain_length());
+
+        future::poll_fn(move || Ok(inner.poll_lock())).map(move |guard| {
+            rollback_depth(tip, other, limit, |hash| {
+                guard
+                    .peek(hash)
+                    .map(|reference| (reference.block_parent_hash(), reference.chain_length()))
+            })
+        })
+    }
+
     /// return a future that will remove every expired [`Ref`] from the cache
     ///
     pub fn purge(&self) -> impl Future<Item = (), Error = timer::Error> {
@@ -88,37 +158,103 @@ impl RefCache {
         future::poll_fn(move || Ok(inner.poll_lock()))
             .and_then(|mut guard| future::poll_fn(move || guard.poll_purge()))
     }
+
+    /// return a future to get the occupancy of the cache and the number
+    /// of [`Ref`]s dropped from it so far
+    ///
+    pub fn stats(&self) -> impl Future<Item = RefCacheStats, Error = Infallible> {
+        let mut inner = self.inner.clone();
+
+        future::poll_fn(move || Ok(inner.poll_lock())).map(|guard| guard.stats())
+    }
 }
 
 impl RefCacheData {
-    fn new(ttl: Duration) -> Self {
+    fn new(ttl: Duration, max_entries: Option<usize>, pinned_epochs: usize) -> Self {
         RefCacheData {
-            entries: HashMap::new(),
+            entries: LinkedHashMap::new(),
             expirations: DelayQueue::new(),
+            pinned: BTreeMap::new(),
             ttl,
+            max_entries,
+            pinned_epochs,
+            expired: 0,
+            evicted: 0,
         }
     }
 
     fn insert(&mut self, key: HeaderHash, value: Arc<Ref>) {
+        // the first block of an epoch points to the last one of the
+        // previous epoch
+        if let Some(boundary) = value.last_ref_previous_epoch() {
+            self.pin(Arc::clone(boundary));
+        }
+
         let delay = self.expirations.insert(key.clone(), self.ttl);
 
-        self.entries.insert(key, (value, delay));
+        if let Some((_, replaced)) = self.entries.insert(key, (value, delay)) {
+            self.expirations.remove(&replaced);
+        }
+        self.evict_excess();
+    }
+
+    fn evict_excess(&mut self) {
+        let max_entries = match self.max_entries {
+            None => return,
+            Some(max_entries) => max_entries,
+        };
+        while self.entries.len() > max_entries {
+            if let Some((_, (_, cache_key))) = self.entries.pop_front() {
+                self.expirations.remove(&cache_key);
+                self.evicted += 1;
+            }
+        }
+    }
+
+    fn pin(&mut self, reference: Arc<Ref>) {
+        if self.pinned_epochs == 0 {
+            return;
+        }
+        self.pinned
+            .entry(reference.block_date().epoch)
+            .or_default()
+            .insert(reference.hash(), reference);
+        while self.pinned.len() > self.pinned_epochs {
+            let oldest = *self.pinned.keys().next().unwrap();
+            self.pinned.remove(&oldest);
+        }
     }
 
     fn get(&mut self, key: &HeaderHash) -> Option<&Arc<Ref>> {
-        if let Some((v, k)) = self.entries.get(key) {
+        if let Some((v, k)) = self.entries.get_refresh(key) {
             self.expirations.reset(k, self.ttl);
 
-            Some(v)
+            Some(&*v)
         } else {
-            None
+            self.pinned
+                .values()
+                .rev()
+                .find_map(|references| references.get(key))
         }
     }
 
+    /// the [`Ref`] of `key`, without resetting its TTL
+    fn peek(&self, key: &HeaderHash) -> Option<&Arc<Ref>> {
+        self.entries.get(key).map(|(v, _)| v).or_else(|| {
+            self.pinned
+                .values()
+                .rev()
+                .find_map(|references| references.get(key))
+        })
+    }
+
     fn remove(&mut self, key: &HeaderHash) {
         if let Some((_, cache_key)) = self.entries.remove(key) {
             self.expirations.remove(&cache_key);
         }
+        for references in self.pinned.values_mut() {
+            references.remove(key);
+        }
     }
 
     pub fn poll_purge(&mut self) -> Poll<(), timer::Error> {
@@ -128,8 +264,125 @@ impl RefCacheData {
                 Async::Ready(None) => return Ok(Async::Ready(())),
                 Async::Ready(Some(entry)) => {
                     self.entries.remove(entry.get_ref());
+                    self.expired += 1;
                 }
             }
         }
     }
+
+    fn stats(&self) -> RefCacheStats {
+        RefCacheStats {
+            entries: self.entries.len(),
+            pinned: self.pinned.values().map(HashMap::len).sum(),
+            expired: self.expired,
+            evicted: self.evicted,
+        }
+    }
+}
+
+/// the number of blocks of the branch ending at `tip` above its fork point
+/// with the branch ending at `other`, both given as their hash and chain
+/// length. The parent of a block and its chain length are found with
+/// `parent_of`.
+fn rollback_depth<F>(
+    tip: (HeaderHash, ChainLength),
+    other: (HeaderHash, ChainLength),
+    limit: u32,
+    mut parent_of: F,
+) -> Option<u32>
+where
+    F: FnMut(&HeaderHash) -> Option<(HeaderHash, ChainLength)>,
+{
+    let tip_length = u32::from(tip.1);
+    let (mut tip, mut other) = (tip, other);
+    while other.1 > tip.1 {
+        other = parent_of(&other.0)?;
+    }
+    while tip.0 != other.0 {
+        if tip_length - u32::from(tip.1) > limit {
+            return Some(limit.saturating_add(1));
+        }
+        if tip.1 == other.1 {
+            other = parent_of(&other.0)?;
+        }
+        tip = parent_of(&tip.0)?;
+    }
+    Some(tip_length - u32::from(tip.1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// the parents of the blocks of a main branch of `length` blocks and
+    /// of a side branch forking from it after `fork` blocks
+    fn chain(
+        length: u32,
+        fork: u32,
+        side_length: u32,
+    ) -> HashMap<HeaderHash, (HeaderHash, ChainLength)> {
+        let mut parents = HashMap::new();
+        for n in 1..=length {
+            parents.insert(
+                block("main", n),
+                (block("main", n - 1), ChainLength::from(n - 1)),
+            );
+        }
+        for n in fork + 1..=side_length {
+            let parent = if n == fork + 1 {
+                block("main", fork)
+            } else {
+                block("side", n - 1)
+            };
+            parents.insert(block("side", n), (parent, ChainLength::from(n - 1)));
+        }
+        parents
+    }
+
+    fn block(branch: &str, n: u32) -> HeaderHash {
+        HeaderHash::hash_bytes(format!("{}-{}", branch, n).as_bytes())
+    }
+
+    fn depth(
+        parents: &HashMap<HeaderHash, (HeaderHash, ChainLength)>,
+        tip: (&str, u32),
+        other: (&str, u32),
+        limit: u32,
+    ) -> Option<u32> {
+        rollback_depth(
+            (block(tip.0, tip.1), ChainLength::from(tip.1)),
+            (block(other.0, other.1), ChainLength::from(other.1)),
+            limit,
+            |hash| parents.get(hash).cloned(),
+        )
+    }
+
+    #[test]
+    fn extending_the_tip_rolls_nothing_back() {
+        let parents = chain(10, 10, 10);
+        assert_eq!(depth(&parents, ("main", 10), ("main", 10), 5), Some(0));
+        assert_eq!(depth(&parents, ("main", 7), ("main", 10), 5), Some(0));
+    }
+
+    #[test]
+    fn rollback_depth_is_the_height_above_the_fork_point() {
+        let parents = chain(10, 7, 12);
+        assert_eq!(depth(&parents, ("main", 10), ("side", 12), 5), Some(3));
+        assert_eq!(depth(&parents, ("main", 10), ("side", 8), 5), Some(3));
+        assert_eq!(depth(&parents, ("side", 12), ("main", 10), 5), Some(5));
+    }
+
+    #[test]
+    fn walk_stops_past_the_limit() {
+        let parents = chain(20, 5, 25);
+        assert_eq!(depth(&parents, ("main", 20), ("side", 25), 15), Some(15));
+        assert_eq!(depth(&parents, ("main", 20), ("side", 25), 10), Some(11));
+    }
+
+    #[test]
+    fn walk_out_of_the_cache_has_no_depth() {
+        let mut parents = chain(10, 5, 12);
+        parents.remove(&block("main", 7));
+        assert_eq!(depth(&parents, ("main", 10), ("side", 12), 10), None);
+    }
 }
@@ -185,6 +185,36 @@ impl Storage {
         }
     }
 
+    /// the number of blocks of the branch ending at `tip` above its common
+    /// ancestor with the branch ending at `other`, which is the number of
+    /// blocks switching from `tip` to `other` rolls back. The walk stops
+    /// past `limit` blocks, resolving to `limit + 1`.
+    pub fn rollback_depth(
+        &self,
+        tip: HeaderHash,
+        other: HeaderHash,
+        limit: u64,
+    ) -> impl Future<Item = u64, Error = StorageError> {
+        future::result(rollback_depth(&self.read_connection, tip, other, limit))
+    }
+
+    /// the latest block the chains ending with the given blocks have in
+    /// common, or `None` if it is more than `max_depth` blocks below
+    /// either of them
+    pub fn common_ancestor(
+        &self,
+        tip: HeaderHash,
+        other: HeaderHash,
+        max_depth: u64,
+    ) -> impl Future<Item = Option<HeaderHash>, Error = StorageError> {
+        future::result(common_ancestor(
+            &self.read_connection,
+            tip,
+            other,
+            max_depth,
+        ))
+    }
+
     pub fn find_closest_ancestor(
         &self,
         checkpoints: Vec<HeaderHash>,
@@ -224,6 +254,60 @@ impl Storage {
     }
 }
 
+fn rollback_depth(
+    store: &NodeStorage,
+    tip: HeaderHash,
+    other: HeaderHash,
+    limit: u64,
+) -> Result<u64, StorageError> {
+    let mut tip_info = store.get_block_info(&tip)?;
+    let mut other_info = store.get_block_info(&other)?;
+    while other_info.depth > tip_info.depth {
+        other_info = store.get_block_info(&other_info.parent_id())?;
+    }
+    let mut depth = 0;
+    while tip_info.block_hash != other_info.block_hash && depth <= limit {
+        if tip_info.depth == other_info.depth {
+            other_info = store.get_block_info(&other_info.parent_id())?;
+        }
+        tip_info = store.get_block_info(&tip_info.parent_id())?;
+        depth += 1;
+    }
+    Ok(depth)
+}
+
+fn common_ancestor(
+    store: &NodeStorage,
+    tip: HeaderHash,
+    other: HeaderHash,
+    max_depth: u64,
+) -> Result<Option<HeaderHash>, StorageError> {
+    let mut tip_info = store.get_block_info(&tip)?;
+    let mut other_info = store.get_block_info(&other)?;
+    let (tip_depth, other_depth) = (tip_info.depth, other_info.depth);
+    let too_deep = |info: &BlockInfo<HeaderHash>, from: u64| from - info.depth > max_depth;
+    while tip_info.depth > other_info.depth {
+        if too_deep(&tip_info, tip_depth) {
+            return Ok(None);
+        }
+        tip_info = store.get_block_info(&tip_info.parent_id())?;
+    }
+    while other_info.depth > tip_info.depth {
+        if too_deep(&other_info, other_depth) {
+            return Ok(None);
+        }
+        other_info = store.get_block_info(&other_info.parent_id())?;
+    }
+    while tip_info.block_hash != other_info.block_hash {
+        if too_deep(&tip_info, tip_depth) || too_deep(&other_info, other_depth) {
+            return Ok(None);
+        }
+        tip_info = store.get_block_info(&tip_info.parent_id())?;
+        other_info = store.get_block_info(&other_info.parent_id())?;
+    }
+    Ok(Some(tip_info.block_hash))
+}
+
 impl Stream for BlockStream {
     type Item = Block;
     type Error = StorageError;
@@ -1,15 +1,30 @@
+use crate::blockcfg::Header;
 use crate::blockchain::{Branch, Ref};
-use std::{convert::Infallible, sync::Arc};
+use futures::sync::mpsc;
+use std::{
+    convert::Infallible,
+    sync::{Arc, Mutex},
+};
 use tokio::prelude::*;
 
+/// number of tip updates a subscriber can fall behind before it is dropped
+const SUBSCRIBER_BUFFER: usize = 64;
+
+/// maximum number of subscriptions to the tip updates outside of the node
+const MAX_SUBSCRIBERS: usize = 256;
+
 #[derive(Clone)]
 pub struct Tip {
     branch: Branch,
+    subscribers: Arc<Mutex<Vec<mpsc::Sender<Header>>>>,
 }
 
 impl Tip {
     pub fn new(branch: Branch) -> Self {
-        Tip { branch }
+        Tip {
+            branch,
+            subscribers: Arc::new(Mutex::new(Vec::new())),
+        }
     }
 
     pub fn get_ref<E>(&self) -> impl Future<Item = Arc<Ref>, Error = E> {
@@ -20,19 +35,88 @@ impl Tip {
         &mut self,
         new_ref: Arc<Ref>,
     ) -> impl Future<Item = Arc<Ref>, Error = Infallible> {
-        self.branch.update_ref(new_ref)
+        let tip = self.clone();
+        let header = new_ref.header().clone();
+        self.branch.update_ref(new_ref).map(move |old_ref| {
+            tip.notify(header);
+            old_ref
+        })
     }
 
     pub fn swap(&mut self, mut branch: Branch) -> impl Future<Item = (), Error = Infallible> {
+        let tip = self.clone();
         let mut tip_branch = self.branch.clone();
         self.branch()
             .get_ref()
             .and_then(move |tr| branch.update_ref(tr))
-            .and_then(move |br| tip_branch.update_ref(br))
-            .map(|_| ())
+            .and_then(move |br| {
+                let header = br.header().clone();
+                tip_branch.update_ref(br).map(move |_| tip.notify(header))
+            })
     }
 
     pub fn branch(&self) -> &Branch {
         &self.branch
     }
+
+    /// get a stream of the headers of the tips the node moves to from now
+    /// on, for the services of the node. The updates are skipped while
+    /// the service falls behind.
+    pub fn subscribe(&self) -> TipUpdates {
+        TipUpdates {
+            tip: self.clone(),
+            receiver: self.add_subscriber(),
+        }
+    }
+
+    /// get a stream of the headers of the tips the node moves to from now
+    /// on, `None` if there are too many subscribers already. The stream
+    /// ends if the subscriber falls behind.
+    pub fn try_subscribe(&self) -> Option<mpsc::Receiver<Header>> {
+        if self.subscribers.lock().unwrap().len() >= MAX_SUBSCRIBERS {
+            return None;
+        }
+        Some(self.add_subscriber())
+    }
+
+    fn add_subscriber(&self) -> mpsc::Receiver<Header> {
+        let (sender, receiver) = mpsc::channel(SUBSCRIBER_BUFFER);
+        self.subscribers.lock().unwrap().push(sender);
+        receiver
+    }
+
+    fn notify(&self, header: Header) {
+        let mut subscribers = self.subscribers.lock().unwrap();
+        // the subscribers gone or lagging behind are dropped
+        *subscribers = subscribers
+            .drain(..)
+            .filter_map(|mut subscriber| {
+                subscriber
+                    .try_send(header.clone())
+                    .ok()
+                    .map(|()| subscriber)
+            })
+            .collect();
+    }
+}
+
+/// Stream of the tip updates for a service of the node, subscribing again
+/// when it falls behind.
+pub struct TipUpdates {
+    tip: Tip,
+    receiver: mpsc::Receiver<Header>,
+}
+
+impl Stream for TipUpdates {
+    type Item = Header;
+    type Error = ();
+
+    fn poll(&mut self) -> Poll<Option<Header>, ()> {
+        loop {
+            match self.receiver.poll()? {
+                Async::Ready(None) => self.receiver = self.tip.add_subscriber(),
+                ready_or_not => return Ok(ready_or_not),
+            }
+        }
+    }
 }
@@ -0,0 +1,187 @@
+use super::{Error, Ref};
+use crate::blockcfg::{Block, HeaderHash, Leadership};
+use chain_impl_mockchain::leadership::Verification;
+use futures::{future, prelude::*};
+use std::{
+    collections::{HashSet, VecDeque},
+    sync::{Arc, Mutex},
+};
+use tokio_threadpool::{Builder, ThreadPool};
+
+/// number of blocks of a stream verified ahead of their application
+const PIPELINE_DEPTH: usize = 32;
+
+/// number of the latest applied blocks remembered, for the copies of them
+/// received from other peers to be ignored
+const APPLIED_BLOCKS_CAPACITY: usize = 1024;
+
+/// Verifies the headers of the blocks received from the network on a pool
+/// of worker threads, ahead of their application by the blockchain task.
+///
+/// The leadership schedule a header is verified against depends on the
+/// ledger state of its parent, which is not known before the blocks
+/// preceding it in the stream are applied. The blocks of a stream are
+/// therefore verified against the schedule of the tip at the time the
+/// stream is received, when they belong to the same epoch. The application
+/// skips the verification of the header only if its parent resolves to that
+/// very schedule, and verifies it again otherwise.
+///
+/// The pool also remembers the hashes of the latest blocks verified and
+/// applied, so that a block received again from another peer, as happens
+/// when it is both announced and solicited, is ignored without being
+/// verified or looked up in the storage.
+#[derive(Clone)]
+pub struct VerificationPool {
+    pool: Arc<ThreadPool>,
+    applied: Arc<Mutex<AppliedBlocks>>,
+}
+
+/// A block along with the schedule its header has been verified against.
+pub struct VerifiedBlock {
+    pub block: Block,
+    pub verified_with: Option<Arc<Leadership>>,
+}
+
+impl VerificationPool {
+    pub fn new() -> Self {
+        let pool = Builder::new().name_prefix("block-verification").build();
+        VerificationPool {
+            pool: Arc::new(pool),
+            applied: Default::default(),
+        }
+    }
+
+    /// whether the block of the given hash is one of the latest applied
+    pub fn is_applied(&self, hash: &HeaderHash) -> bool {
+        self.applied.lock().unwrap().contains(hash)
+    }
+
+    /// remember the block of the given hash as verified and applied
+    pub fn mark_applied(&self, hash: HeaderHash) {
+        self.applied.lock().unwrap().insert(hash)
+    }
+
+    /// verify the blocks of `stream` concurrently, yielding them in the
+    /// order they are received.
+    pub fn verify_stream<S>(
+        &self,
+        stream: S,
+        tip: Arc<Ref>,
+    ) -> impl Stream<Item = VerifiedBlock, Error = Error>
+    where
+        S: Stream<Item = Block, Error = Error>,
+    {
+        let pool = self.pool.clone();
+        let applied = self.applied.clone();
+        stream
+            .map(move |block| {
+                if applied.lock().unwrap().contains(&block.header.hash()) {
+                    // ignored when it comes to be applied
+                    return future::Either::A(future::ok(VerifiedBlock {
+                        block,
+                        verified_with: None,
+                    }));
+                }
+                let tip = tip.clone();
+                future::Either::B(
+                    pool.spawn_handle(future::lazy(move || Ok(verify_header(block, &tip)))),
+                )
+            })
+            .buffered(PIPELINE_DEPTH)
+    }
+}
+
+/// Hashes of the latest applied blocks, the oldest forgotten first.
+#[derive(Default)]
+struct AppliedBlocks {
+    hashes: HashSet<HeaderHash>,
+    order: VecDeque<HeaderHash>,
+}
+
+impl AppliedBlocks {
+    fn contains(&self, hash: &HeaderHash) -> bool {
+        self.hashes.contains(hash)
+    }
+
+    fn insert(&mut self, hash: HeaderHash) {
+        if !self.hashes.insert(hash) {
+            return;
+        }
+        if self.order.len() >= APPLIED_BLOCKS_CAPACITY {
+            if let Some(oldest) = self.order.pop_front() {
+                self.hashes.remove(&oldest);
+            }
+        }
+        self.order.push_back(hash);
+    }
+}
+
+fn verify_header(block: Block, tip: &Ref) -> VerifiedBlock {
+    let leadership = tip.epoch_leadership_schedule();
+    let verified = block.header.block_date().epoch == tip.block_date().epoch
+        && match leadership.verify(&block.header) {
+            Verification::Success => true,
+            // reported when the block is applied, the schedule of its
+            // parent may differ from the one of the tip
+            Verification::Failure(_) => false,
+        };
+    VerifiedBlock {
+        block,
+        verified_with: if verified {
+            Some(leadership.clone())
+        } else {
+            None
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hash(n: usize) -> HeaderHash {
+        HeaderHash::hash_bytes(&n.to_be_bytes())
+    }
+
+    #[test]
+    fn unknown_block_is_not_applied() {
+        let mut applied = AppliedBlocks::default();
+        applied.insert(hash(0));
+        assert!(!applied.contains(&hash(1)));
+    }
+
+    #[test]
+    fn applied_block_is_remembered() {
+        let mut applied = AppliedBlocks::default();
+        applied.insert(hash(0));
+        applied.insert(hash(1));
+        assert!(applied.contains(&hash(0)));
+        assert!(applied.contains(&hash(1)));
+    }
+
+    #[test]
+    fn block_applied_twice_is_remembered_once() {
+        let mut applied = AppliedBlocks::default();
+        applied.insert(hash(0));
+        applied.insert(hash(0));
+        assert_eq!(applied.order.len(), 1);
+        assert_eq!(applied.hashes.len(), 1);
+    }
+
+    #[test]
+    fn oldest_block_is_forgotten_beyond_capacity() {
+        let mut applied = AppliedBlocks::default();
+        for n in 0..APPLIED_BLOCKS_CAPACITY {
+            applied.insert(hash(n));
+        }
+        assert!(applied.contains(&hash(0)));
+        // applying it again does not make it the latest
+        applied.insert(hash(0));
+        applied.insert(hash(APPLIED_BLOCKS_CAPACITY));
+        assert!(!applied.contains(&hash(0)));
+        assert!(applied.contains(&hash(1)));
+        assert!(applied.contains(&hash(APPLIED_BLOCKS_CAPACITY)));
+        assert_eq!(applied.hashes.len(), APPLIED_BLOCKS_CAPACITY);
+        assert_eq!(applied.order.len(), APPLIED_BLOCKS_CAPACITY);
+    }
+}
@@ -1,7 +1,10 @@
 use crate::blockcfg::{Block, Header, HeaderHash};
-use crate::blockchain::{Storage, Tip};
+use crate::blockchain::{BranchInfo, Branches, Storage, Tip};
 use crate::intercom::{ClientMsg, Error, ReplySendError, ReplyStreamHandle};
-use crate::utils::task::{Input, TokioServiceInfo};
+use crate::utils::{
+    bandwidth::BandwidthLimiter,
+    task::{Input, TokioServiceInfo},
+};
 use chain_core::property::HasHeader;
 
 use futures::future::Either;
@@ -15,10 +18,14 @@ const PROCESS_TIMEOUT_GET_HEADERS: u64 = 5 * 60;
 const PROCESS_TIMEOUT_GET_HEADERS_RANGE: u64 = 5 * 60;
 const PROCESS_TIMEOUT_GET_BLOCKS: u64 = 10 * 60;
 const PROCESS_TIMEOUT_PULL_BLOCKS_TO_TIP: u64 = 60 * 60;
+const PROCESS_TIMEOUT_GET_BRANCHES: u64 = 5;
 
 pub struct TaskData {
     pub storage: Storage,
     pub blockchain_tip: Tip,
+    pub branches: Branches,
+    /// limits the rate of the blocks streamed to the peers, if set
+    pub blocks_rate_limiter: Option<BandwidthLimiter>,
 }
 
 pub fn handle_input(
@@ -80,8 +87,17 @@ pub fn handle_input(
                 ),
             );
         }
-        ClientMsg::GetBlocks(ids, handle) => {
-            let fut = handle.async_reply(get_blocks(task_data.storage.clone(), ids));
+        ClientMsg::GetBlocks(ids, max_depth, handle) => {
+            let blocks = get_blocks(
+                task_data.storage.clone(),
+                &task_data.blockchain_tip,
+                ids,
+                max_depth,
+            );
+            let fut = match &task_data.blocks_rate_limiter {
+                Some(limiter) => Either::A(handle.async_reply(limiter.throttle(blocks))),
+                None => Either::B(handle.async_reply(blocks)),
+            };
             let logger = info.logger().new(o!("request" => "GetBlocks"));
             info.spawn(
                 "get blocks",
@@ -96,8 +112,8 @@ pub fn handle_input(
                 ),
             );
         }
-        ClientMsg::PullBlocksToTip(from, handle) => {
-            let fut = handle_pull_blocks_to_tip(task_data, from, handle);
+        ClientMsg::PullBlocksToTip(from, max_depth, handle) => {
+            let fut = handle_pull_blocks_to_tip(task_data, from, max_depth, handle);
             let logger = info.logger().new(o!("request" => "PullBlocksToTip"));
             info.spawn(
                 "PullBlocksToTip",
@@ -112,6 +128,26 @@ pub fn handle_input(
                 ),
             );
         }
+        ClientMsg::GetBranches(handle) => {
+            let fut = handle.async_reply(get_branches(
+                task_data.storage.clone(),
+                &task_data.blockchain_tip,
+                &task_data.branches,
+            ));
+            let logger = info.logger().new(o!("request" => "GetBranches"));
+            info.spawn(
+                "get branches",
+                Timeout::new(fut, Duration::from_secs(PROCESS_TIMEOUT_GET_BRANCHES)).map_err(
+                    move |e| {
+                        error!(
+                            logger,
+                            "request timed out or failed unexpectedly";
+                            "error" => ?e,
+                        );
+                    },
+                ),
+            );
+        }
     }
     Ok(())
 }
@@ -122,6 +158,33 @@ fn get_block_tip(blockchain_tip: &Tip) -> impl Future<Item = Header, Error = Err
         .and_then(|tip| Ok(tip.header().clone()))
 }
 
+fn get_branches(
+    storage: Storage,
+    blockchain_tip: &Tip,
+    branches: &Branches,
+) -> impl Future<Item = Vec<BranchInfo>, Error = Error> {
+    blockchain_tip
+        .get_ref()
+        .join(branches.branches_last_updated())
+        .and_then(move |(tip, branches)| {
+            stream::iter_ok(branches)
+                .and_then(move |(reference, last_updated)| {
+                    // forks deeper than the epoch stability depth cannot be
+                    // switched to, their ancestor is not looked for
+                    let max_depth = tip.epoch_ledger_parameters().epoch_stability_depth;
+                    storage
+                        .common_ancestor(tip.hash(), reference.hash(), u64::from(max_depth))
+                        .map_err(Into::into)
+                        .map(move |common_ancestor| BranchInfo {
+                            tip: reference.header().clone(),
+                            last_updated,
+                            common_ancestor,
+                        })
+                })
+                .collect()
+        })
+}
+
 fn handle_get_headers_range(
     task_data: &TaskData,
     checkpoints: Vec<HeaderHash>,
@@ -148,18 +211,47 @@ fn handle_get_headers_range(
         })
 }
 
-fn get_blocks(storage: Storage, ids: Vec<HeaderHash>) -> impl Stream<Item = Block, Error = Error> {
-    stream::iter_ok(ids).and_then(move |id| {
-        storage
-            .get(id)
-            .map_err(Into::into)
-            .and_then(move |maybe_block| match maybe_block {
-                Some(block) => Ok(block),
-                None => Err(Error::not_found(format!(
-                    "block {} is not known to this node",
-                    id
-                ))),
+fn get_blocks(
+    storage: Storage,
+    blockchain_tip: &Tip,
+    ids: Vec<HeaderHash>,
+    max_depth: Option<u64>,
+) -> impl Stream<Item = Block, Error = Error> {
+    min_chain_length(blockchain_tip, max_depth)
+        .map(move |min_length| {
+            stream::iter_ok(ids).and_then(move |id| {
+                storage
+                    .get(id)
+                    .map_err(Into::into)
+                    .and_then(move |maybe_block| match maybe_block {
+                        Some(block) => {
+                            if u64::from(u32::from(block.header.chain_length())) < min_length {
+                                Err(Error::failed_precondition(format!(
+                                    "block {} is too deep in the chain to be served to this peer",
+                                    id
+                                )))
+                            } else {
+                                Ok(block)
+                            }
+                        }
+                        None => Err(Error::not_found(format!(
+                            "block {} is not known to this node",
+                            id
+                        ))),
+                    })
             })
+        })
+        .flatten_stream()
+}
+
+// The chain length of the deepest block that may be served.
+fn min_chain_length(
+    blockchain_tip: &Tip,
+    max_depth: Option<u64>,
+) -> impl Future<Item = u64, Error = Error> {
+    blockchain_tip.get_ref().map(move |tip| match max_depth {
+        Some(max_depth) => u64::from(u32::from(tip.chain_length())).saturating_sub(max_depth),
+        None => 0,
     })
 }
 
@@ -184,9 +276,11 @@ fn get_headers(
 fn handle_pull_blocks_to_tip(
     task_data: &TaskData,
     checkpoints: Vec<HeaderHash>,
+    max_depth: Option<u64>,
     handle: ReplyStreamHandle<Block>,
 ) -> impl Future<Item = (), Error = ()> {
     let storage = task_data.storage.clone();
+    let limiter = task_data.blocks_rate_limiter.clone();
     task_data
         .blockchain_tip
         .get_ref()
@@ -201,7 +295,24 @@ fn handle_pull_blocks_to_tip(
         })
         .then(move |res| match res {
             Ok((storage, to, depth)) => {
-                Either::A(storage.send_branch(to, depth, handle).then(|_| Ok(())))
+                if let Some(max_depth) = max_depth {
+                    // Without a common ancestor, the whole chain is pulled.
+                    if depth.map_or(true, |depth| depth > max_depth) {
+                        let e = Error::failed_precondition(format!(
+                            "chain pulls deeper than {} blocks from the tip \
+                             are not served to this peer",
+                            max_depth
+                        ));
+                        return Either::B(handle.async_error(e));
+                    }
+                }
+                let fut = match limiter {
+                    Some(limiter) => {
+                        Either::A(storage.send_branch(to, depth, limiter.throttle(handle)))
+                    }
+                    None => Either::B(storage.send_branch(to, depth, handle)),
+                };
+                Either::A(fut.then(|_| Ok(())))
             }
             Err(e) => Either::B(handle.async_error(e.into())),
         })
@@ -0,0 +1,367 @@
+//! Detection of the skew of the system clock.
+//!
+//! The slots are computed from the system clock, so a node whose clock is
+//! off creates its blocks at the wrong time, and the other nodes reject
+//! them. The system clock is periodically compared with the clocks of the
+//! NTP servers given in the configuration, queried with SNTP (RFC 4330).
+//! An alert is raised when the median of the measured skews is beyond the
+//! configured maximum. The skew is unknown while none of the servers
+//! answers.
+
+use crate::settings::start::ClockCheck;
+use crate::utils::task::TokioServiceInfo;
+
+use futures::future;
+use futures::prelude::*;
+use slog::Logger;
+use thiserror::Error;
+use tokio::net::UdpSocket;
+use tokio::timer::{Interval, Timeout};
+use tokio_threadpool::Builder;
+
+use std::convert::TryInto;
+use std::io;
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr, ToSocketAddrs};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// time the NTP servers have to answer
+const QUERY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// seconds from the NTP epoch, 1900-01-01, to the UNIX epoch
+const NTP_UNIX_OFFSET: u64 = 2_208_988_800;
+
+const NTP_PACKET_SIZE: usize = 48;
+
+#[derive(Error, Debug)]
+enum Error {
+    #[error("I/O error")]
+    Io {
+        #[from]
+        source: io::Error,
+    },
+    #[error("the host name does not resolve to any address")]
+    Unresolved,
+    #[error("no answer within {0:?}")]
+    Timeout(Duration),
+    #[error("invalid answer")]
+    InvalidAnswer,
+}
+
+/// The latest skew of the system clock measured, shared between all the
+/// clones.
+#[derive(Clone, Default)]
+pub struct ClockSkew(Arc<Mutex<Option<Measure>>>);
+
+#[derive(Clone, Copy, Debug)]
+pub struct Measure {
+    /// difference, in milliseconds, between the system clock and the
+    /// clocks of the NTP servers, positive when the system clock is ahead
+    pub skew_ms: i64,
+    /// whether the skew is beyond the configured maximum
+    pub exceeded: bool,
+    pub measured_at: SystemTime,
+}
+
+impl ClockSkew {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// the latest measure, none while no NTP server answers
+    pub fn latest(&self) -> Option<Measure> {
+        *self.0.lock().unwrap()
+    }
+
+    /// whether the latest measured skew is beyond the configured maximum
+    pub fn exceeded(&self) -> bool {
+        self.latest().map_or(false, |measure| measure.exceeded)
+    }
+
+    fn set(&self, measure: Option<Measure>) {
+        *self.0.lock().unwrap() = measure;
+    }
+}
+
+pub fn check_clock_skew(
+    service_info: TokioServiceInfo,
+    config: ClockCheck,
+    clock_skew: ClockSkew,
+) -> impl Future<Item = (), Error = ()> {
+    let logger = service_info.logger().clone();
+    let err_logger = logger.clone();
+    // the host names are resolved out of the executor threads
+    let resolver = Builder::new()
+        .pool_size(1)
+        .name_prefix("ntp-resolver")
+        .build();
+
+    Interval::new_interval(config.check_interval)
+        .map_err(move |e| error!(err_logger, "timer error: {}", e))
+        .for_each(move |_| {
+            let queries = config.ntp_servers.iter().map(|server| {
+                let logger = logger.new(o!("ntp_server" => server.clone()));
+                let server = server.clone();
+                let resolved = resolver.spawn_handle(future::lazy(move || resolve(&server)));
+                resolved
+                    .and_then(query_ntp_server)
+                    .then(move |res| match res {
+                        Ok(skew) => {
+                            debug!(logger, "NTP server queried"; "skew_us" => skew);
+                            Ok::<_, ()>(Some(skew))
+                        }
+                        Err(e) => {
+                            info!(logger, "failed to query the NTP server"; "reason" => %e);
+                            Ok(None)
+                        }
+                    })
+            });
+            let logger = logger.clone();
+            let max_skew = config.max_skew;
+            let clock_skew = clock_skew.clone();
+            future::join_all(queries)
+                .map(move |skews| report(skews, max_skew, &clock_skew, &logger))
+        })
+}
+
+// Logs the outcome of a check and records the median of the skews
+// measured with the NTP servers that answered.
+fn report(skews: Vec<Option<i64>>, max_skew: Duration, clock_skew: &ClockSkew, logger: &Logger) {
+    let mut skews: Vec<i64> = skews.into_iter().flatten().collect();
+    if skews.is_empty() {
+        warn!(
+            logger,
+            "none of the NTP servers could be queried, the clock skew is unknown"
+        );
+        clock_skew.set(None);
+        return;
+    }
+    skews.sort();
+    let skew_ms = skews[skews.len() / 2] / 1000;
+    let exceeded = u128::from(skew_ms.abs() as u64) > max_skew.as_millis();
+
+    if exceeded {
+        error!(
+            logger,
+            "system clock is skewed from the clocks of the NTP servers";
+            "skew_ms" => skew_ms,
+            "max_skew_ms" => max_skew.as_millis() as u64,
+            "queried" => skews.len(),
+        );
+    } else if clock_skew.exceeded() {
+        info!(
+            logger,
+            "system clock agrees with the NTP servers again";
+            "skew_ms" => skew_ms,
+        );
+    } else {
+        debug!(logger, "system clock checked"; "skew_ms" => skew_ms);
+    }
+    clock_skew.set(Some(Measure {
+        skew_ms,
+        exceeded,
+        measured_at: SystemTime::now(),
+    }));
+}
+
+fn resolve(server: &str) -> Result<SocketAddr, Error> {
+    server.to_socket_addrs()?.next().ok_or(Error::Unresolved)
+}
+
+// Returns the skew of the system clock from the clock of the server, in
+// microseconds.
+fn query_ntp_server(server: SocketAddr) -> impl Future<Item = i64, Error = Error> {
+    let bind_addr: SocketAddr = if server.is_ipv4() {
+        (Ipv4Addr::UNSPECIFIED, 0).into()
+    } else {
+        (Ipv6Addr::UNSPECIFIED, 0).into()
+    };
+
+    let query = future::lazy(move || {
+        let socket = UdpSocket::bind(&bind_addr)?;
+        // the server echoes the transmit timestamp of the request, which
+        // identifies its answer
+        let sent_at = to_ntp_timestamp(SystemTime::now());
+        let mut request = [0; NTP_PACKET_SIZE];
+        // leap indicator 0, version 4, mode 3 (client)
+        request[0] = 0b00_100_011;
+        request[40..48].copy_from_slice(&sent_at.to_be_bytes());
+        Ok::<_, Error>((socket, request, sent_at))
+    })
+    .and_then(move |(socket, request, sent_at)| {
+        socket
+            .send_dgram(request, &server)
+            .map(move |(socket, _)| (socket, sent_at))
+            .map_err(Error::from)
+    })
+    .and_then(|(socket, sent_at)| {
+        socket
+            .recv_dgram([0; NTP_PACKET_SIZE])
+            .map(move |(_, answer, len, _)| {
+                let received_at = to_ntp_timestamp(SystemTime::now());
+                (answer, len, sent_at, received_at)
+            })
+            .map_err(Error::from)
+    })
+    .and_then(|(answer, len, sent_at, received_at)| skew(&answer[..len], sent_at, received_at));
+
+    Timeout::new(query, QUERY_TIMEOUT)
+        .map_err(|e| e.into_inner().unwrap_or(Error::Timeout(QUERY_TIMEOUT)))
+}
+
+fn skew(answer: &[u8], sent_at: u64, received_at: u64) -> Result<i64, Error> {
+    let timestamp =
+        |offset: usize| u64::from_be_bytes(answer[offset..offset + 8].try_into().unwrap());
+    // an answer in server mode, not a kiss-of-death (stratum 0), to the
+    // request sent
+    if answer.len() < NTP_PACKET_SIZE
+        || answer[0] & 0b111 != 4
+        || answer[1] == 0
+        || timestamp(24) != sent_at
+    {
+        return Err(Error::InvalidAnswer);
+    }
+    let t1 = to_micros(sent_at);
+    let t2 = to_micros(timestamp(32));
+    let t3 = to_micros(timestamp(40));
+    let t4 = to_micros(received_at);
+    // the offset of the clock of the server from the system clock
+    let offset = ((t2 - t1) + (t3 - t4)) / 2;
+    Ok(-offset)
+}
+
+fn to_ntp_timestamp(time: SystemTime) -> u64 {
+    let since_unix_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = since_unix_epoch.as_secs() + NTP_UNIX_OFFSET;
+    let fraction = (u64::from(since_unix_epoch.subsec_nanos()) << 32) / 1_000_000_000;
+    (secs << 32) | fraction
+}
+
+fn to_micros(timestamp: u64) -> i64 {
+    let secs = (timestamp >> 32) as i64 - NTP_UNIX_OFFSET as i64;
+    let fraction = ((timestamp & 0xffff_ffff) * 1_000_000) >> 32;
+    secs * 1_000_000 + fraction as i64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(secs: u64, millis: u64) -> u64 {
+        to_ntp_timestamp(UNIX_EPOCH + Duration::from_secs(secs) + Duration::from_millis(millis))
+    }
+
+    // an answer of a server in mode 4, stratum 1, to the request sent at
+    // `sent_at`
+    fn answer(sent_at: u64, received: u64, transmitted: u64) -> [u8; NTP_PACKET_SIZE] {
+        let mut answer = [0; NTP_PACKET_SIZE];
+        answer[0] = 0b00_100_100;
+        answer[1] = 1;
+        answer[24..32].copy_from_slice(&sent_at.to_be_bytes());
+        answer[32..40].copy_from_slice(&received.to_be_bytes());
+        answer[40..48].copy_from_slice(&transmitted.to_be_bytes());
+        answer
+    }
+
+    fn logger() -> Logger {
+        Logger::root(slog::Discard, o!())
+    }
+
+    #[test]
+    fn ntp_timestamp_counts_from_1900() {
+        assert_eq!(to_ntp_timestamp(UNIX_EPOCH), NTP_UNIX_OFFSET << 32);
+        assert_eq!(at(1, 500), ((NTP_UNIX_OFFSET + 1) << 32) | 0x8000_0000);
+    }
+
+    #[test]
+    fn micros_count_from_the_unix_epoch() {
+        assert_eq!(to_micros(to_ntp_timestamp(UNIX_EPOCH)), 0);
+        assert_eq!(to_micros(at(1_600_000_000, 250)), 1_600_000_000_250_000);
+        // the timestamps before the UNIX epoch are negative
+        assert_eq!(to_micros(0), -(NTP_UNIX_OFFSET as i64) * 1_000_000);
+        // the fraction is truncated to the microsecond
+        let time = UNIX_EPOCH + Duration::from_micros(1_234_567);
+        let micros = to_micros(to_ntp_timestamp(time));
+        assert!((micros - 1_234_567).abs() <= 1);
+    }
+
+    #[test]
+    fn system_clock_behind_gives_negative_skew() {
+        // the server is one second ahead, the messages take 250 ms each way
+        let sent_at = at(1_600_000_000, 0);
+        let answer = answer(sent_at, at(1_600_000_001, 250), at(1_600_000_001, 250));
+        let received_at = at(1_600_000_000, 500);
+        assert_eq!(skew(&answer, sent_at, received_at).unwrap(), -1_000_000);
+    }
+
+    #[test]
+    fn system_clock_ahead_gives_positive_skew() {
+        // the server is two seconds behind, takes 250 ms to answer and
+        // the messages take 250 ms each way
+        let sent_at = at(1_600_000_002, 0);
+        let answer = answer(sent_at, at(1_600_000_000, 250), at(1_600_000_000, 500));
+        let received_at = at(1_600_000_002, 750);
+        assert_eq!(skew(&answer, sent_at, received_at).unwrap(), 2_000_000);
+    }
+
+    #[test]
+    fn invalid_answers_are_refused() {
+        let sent_at = at(1_600_000_000, 0);
+        let valid = answer(sent_at, sent_at, sent_at);
+        let received_at = at(1_600_000_000, 500);
+        let refused = |answer: &[u8]| skew(answer, sent_at, received_at).is_err();
+
+        assert!(refused(&valid[..NTP_PACKET_SIZE - 1]));
+        let mut client_mode = valid;
+        client_mode[0] = 0b00_100_011;
+        assert!(refused(&client_mode[..]));
+        let mut kiss_of_death = valid;
+        kiss_of_death[1] = 0;
+        assert!(refused(&kiss_of_death[..]));
+        let other_request = answer(at(1_599_999_999, 0), sent_at, sent_at);
+        assert!(refused(&other_request[..]));
+    }
+
+    #[test]
+    fn median_skew_is_recorded() {
+        let clock_skew = ClockSkew::new();
+        let skews = vec![Some(3_000), None, Some(-500_000), Some(1_000)];
+        report(skews, Duration::from_millis(100), &clock_skew, &logger());
+        let measure = clock_skew.latest().unwrap();
+        assert_eq!(measure.skew_ms, 1);
+        assert!(!measure.exceeded);
+    }
+
+    #[test]
+    fn negative_skew_beyond_the_maximum_is_exceeded() {
+        let clock_skew = ClockSkew::new();
+        report(
+            vec![Some(-200_000)],
+            Duration::from_millis(100),
+            &clock_skew,
+            &logger(),
+        );
+        assert_eq!(clock_skew.latest().unwrap().skew_ms, -200);
+        assert!(clock_skew.exceeded());
+    }
+
+    #[test]
+    fn skew_is_unknown_without_answers() {
+        let clock_skew = ClockSkew::new();
+        report(
+            vec![Some(200_000)],
+            Duration::from_millis(100),
+            &clock_skew,
+            &logger(),
+        );
+        assert!(clock_skew.exceeded());
+        report(
+            vec![None, None],
+            Duration::from_millis(100),
+            &clock_skew,
+            &logger(),
+        );
+        assert!(clock_skew.latest().is_none());
+        assert!(!clock_skew.exceeded());
+    }
+}
@@ -0,0 +1,33 @@
+use super::pool::internal::Pool;
+use crate::bench::fragments;
+use jormungandr_lib::interfaces::{FragmentPriority, PoolOverflowPolicy};
+use std::time::Duration;
+use test::Bencher;
+use tokio::{prelude::future, runtime::current_thread::Runtime};
+
+/// number of fragments inserted in the pool on every iteration
+const POOL_FRAGMENTS: usize = 1000;
+
+#[bench]
+fn mempool_insertion(b: &mut Bencher) {
+    let fragments = fragments(POOL_FRAGMENTS);
+    // the expiration queue of the pool needs a timer
+    let mut runtime = Runtime::new().unwrap();
+    b.iter(|| {
+        let fragments = fragments.clone();
+        runtime
+            .block_on(future::lazy(move || {
+                let mut pool = Pool::new(
+                    POOL_FRAGMENTS,
+                    usize::max_value(),
+                    PoolOverflowPolicy::RejectNew,
+                    Duration::from_secs(3600),
+                    0,
+                    FragmentPriority::Fifo,
+                );
+                let inserted = pool.insert_all(fragments).len();
+                Ok::<_, ()>(inserted)
+            }))
+            .unwrap()
+    });
+}
@@ -2,6 +2,7 @@ use crate::{
     blockcfg::{Value, ValueError},
     fragment::{Fragment, FragmentId},
 };
+use chain_impl_mockchain::transaction::Transaction;
 use std::time::SystemTime;
 
 pub struct PoolEntry {
@@ -30,14 +31,14 @@ pub struct PoolEntry {
 
 impl PoolEntry {
     pub fn new(fragment: &Fragment) -> Self {
+        Self::with_fee(fragment, fee(fragment))
+    }
+
+    /// the entry of `fragment` paying `fragment_fee`
+    pub fn with_fee(fragment: &Fragment, fragment_fee: Value) -> Self {
         let raw = fragment.to_raw();
         let fragment_size = raw.size_bytes_plus_size();
         let fragment_ref = raw.id();
-        // TODO: the fragment fee is not yet computed. Yet we should
-        // have an explicit fee in the message. So we need to be able
-        // to extract this information without the need to compute the
-        // fee from the ledger's fee settings.
-        let fragment_fee = Value::zero();
 
         PoolEntry {
             fragment_ref: fragment_ref,
@@ -90,3 +91,25 @@ impl PoolEntry {
         self.ancestors_size + self.fragment_size
     }
 }
+
+// the difference between the inputs and the outputs of a transaction,
+// zero for the other fragments or an unbalanced transaction
+fn fee(fragment: &Fragment) -> Value {
+    fn balance<E>(tx: &Transaction<E>) -> Result<Value, ValueError> {
+        tx.total_input()? - tx.total_output()?
+    }
+
+    let balance = match fragment {
+        Fragment::Transaction(tx) => balance(tx),
+        Fragment::OwnerStakeDelegation(tx) => balance(tx),
+        Fragment::StakeDelegation(tx) => balance(tx),
+        Fragment::PoolRegistration(tx) => balance(tx),
+        Fragment::PoolRetirement(tx) => balance(tx),
+        Fragment::PoolUpdate(tx) => balance(tx),
+        Fragment::Initial(_)
+        | Fragment::OldUtxoDeclaration(_)
+        | Fragment::UpdateProposal(_)
+        | Fragment::UpdateVote(_) => return Value::zero(),
+    };
+    balance.unwrap_or_else(|_| Value::zero())
+}
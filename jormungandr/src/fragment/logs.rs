@@ -1,6 +1,10 @@
 use crate::fragment::FragmentId;
-use jormungandr_lib::interfaces::{FragmentLog, FragmentStatus};
-use std::time::Duration;
+use futures::sync::mpsc;
+use jormungandr_lib::{
+    crypto::hash::Hash,
+    interfaces::{FragmentLog, FragmentStatus},
+};
+use std::{collections::HashSet, time::Duration};
 use tokio::{
     prelude::*,
     sync::lock::{Lock, LockGuard},
@@ -61,6 +65,17 @@ impl Logs {
         })
     }
 
+    pub fn modify_each(
+        &mut self,
+        statuses: impl IntoIterator<Item = (FragmentId, FragmentStatus)>,
+    ) -> impl Future<Item = (), Error = ()> {
+        self.run_on_inner(move |inner| {
+            for (fragment_id, status) in statuses {
+                inner.modify(&fragment_id.into(), status)
+            }
+        })
+    }
+
     pub fn poll_purge(&mut self) -> impl Future<Item = (), Error = timer::Error> {
         self.inner()
             .and_then(move |mut guard| future::poll_fn(move || guard.poll_purge()))
@@ -70,6 +85,18 @@ impl Logs {
         self.run_on_inner(move |inner| inner.logs().cloned().collect())
     }
 
+    /// Returns the current logs of the given fragments, and a stream of
+    /// their logs every time their status changes from now on, `None` if
+    /// there are too many subscribers already. The stream ends if the
+    /// subscriber falls behind.
+    pub fn subscribe(
+        &self,
+        fragment_ids: HashSet<Hash>,
+    ) -> impl Future<Item = Option<(Vec<FragmentLog>, mpsc::Receiver<FragmentLog>)>, Error = ()>
+    {
+        self.run_on_inner(move |inner| inner.subscribe(fragment_ids))
+    }
+
     fn run_on_inner<O>(
         &self,
         run: impl FnOnce(&mut internal::Logs) -> O,
@@ -84,13 +111,24 @@ impl Logs {
     }
 }
 
+/// number of logs a subscriber can fall behind before it is dropped
+const SUBSCRIBER_BUFFER: usize = 64;
+
+/// maximum number of subscriptions to the logs
+const MAX_SUBSCRIBERS: usize = 256;
+
 pub(super) mod internal {
+    use super::{MAX_SUBSCRIBERS, SUBSCRIBER_BUFFER};
+    use futures::sync::mpsc;
     use jormungandr_lib::{
         crypto::hash::Hash,
         interfaces::{FragmentLog, FragmentOrigin, FragmentStatus},
     };
     use std::{
-        collections::hash_map::{Entry, HashMap},
+        collections::{
+            hash_map::{Entry, HashMap},
+            HashSet,
+        },
         time::{Duration, Instant},
     };
     use tokio::{
@@ -103,6 +141,7 @@ pub(super) mod internal {
         entries: HashMap<Hash, (FragmentLog, delay_queue::Key)>,
         expirations: DelayQueue<Hash>,
         ttl: Duration,
+        subscribers: Vec<(HashSet<Hash>, mpsc::Sender<FragmentLog>)>,
     }
 
     impl Logs {
@@ -112,6 +151,7 @@ pub(super) mod internal {
                 entries: HashMap::new(),
                 expirations: DelayQueue::new(),
                 ttl,
+                subscribers: Vec::new(),
             }
         }
 
@@ -137,6 +177,7 @@ pub(super) mod internal {
                     Entry::Vacant(entry) => entry,
                 };
                 let delay = self.expirations.insert(fragment_id, self.ttl);
+                notify(&mut self.subscribers, &log);
                 entry.insert((log, delay));
                 true
             }
@@ -163,6 +204,7 @@ pub(super) mod internal {
 
                     self.expirations
                         .reset_at(&entry.get().1, Instant::now() + self.ttl);
+                    notify(&mut self.subscribers, &entry.get().0);
                 }
                 Entry::Vacant(entry) => {
                     // while a log modification, if the log was not already present in the
@@ -199,5 +241,41 @@ pub(super) mod internal {
         pub fn logs<'a>(&'a self) -> impl Iterator<Item = &'a FragmentLog> {
             self.entries.values().map(|(v, _)| v)
         }
+
+        pub fn subscribe(
+            &mut self,
+            fragment_ids: HashSet<Hash>,
+        ) -> Option<(Vec<FragmentLog>, mpsc::Receiver<FragmentLog>)> {
+            if self.subscribers.len() >= MAX_SUBSCRIBERS {
+                return None;
+            }
+            let logs = fragment_ids
+                .iter()
+                .filter_map(|fragment_id| self.entries.get(fragment_id))
+                .map(|(log, _)| log.clone())
+                .collect();
+            let (sender, receiver) = mpsc::channel(SUBSCRIBER_BUFFER);
+            self.subscribers.push((fragment_ids, sender));
+            Some((logs, receiver))
+        }
+    }
+
+    // sends the log to the subscribers to its fragment, dropping the
+    // subscribers gone or lagging behind
+    fn notify(
+        subscribers: &mut Vec<(HashSet<Hash>, mpsc::Sender<FragmentLog>)>,
+        log: &FragmentLog,
+    ) {
+        *subscribers = subscribers
+            .drain(..)
+            .filter_map(|(fragment_ids, mut subscriber)| {
+                if fragment_ids.contains(log.fragment_id())
+                    && subscriber.try_send(log.clone()).is_err()
+                {
+                    return None;
+                }
+                Some((fragment_ids, subscriber))
+            })
+            .collect();
     }
 }
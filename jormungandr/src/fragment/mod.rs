@@ -1,12 +1,17 @@
+#[cfg(all(test, feature = "with-bench"))]
+mod bench;
 mod entry;
 mod logs;
+mod persistence;
 mod pool;
 mod process;
+mod rejection;
 pub mod selection;
 
 pub use self::entry::PoolEntry;
 pub use self::logs::Logs;
-pub use self::pool::Pool;
-pub use self::process::Process;
+pub use self::persistence::persist_on_shutdown;
+pub use self::pool::{Pool, PoolStats};
+pub use self::process::{PendingPropagation, Process};
 
 pub use crate::blockcfg::{Fragment, FragmentId};
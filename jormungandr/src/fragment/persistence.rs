@@ -0,0 +1,173 @@
+//! persistence of the fragment pool in the storage directory, for the
+//! pending fragments to survive a restart of the node
+//!
+//! The file starts with a magic number and holds the fragments of the
+//! pool, oldest first, each serialized and prefixed by its length as a
+//! big-endian 32-bit integer. The file is read and written on threads
+//! of its own, out of the executor threads.
+
+use crate::fragment::{Fragment, Pool};
+use chain_core::property::{Deserialize as _, Serialize as _};
+use slog::Logger;
+use std::{
+    convert::TryFrom,
+    fs::File,
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+use tokio::prelude::*;
+use tokio_threadpool::ThreadPool;
+
+/// identifies the fragment pool files, ends with the version of the format
+const MAGIC: &[u8; 8] = b"JORMFRG\x01";
+
+/// write the fragments of `pool` to the file at `path` on the `io` threads
+pub fn persist(
+    pool: &Pool,
+    path: PathBuf,
+    io: Arc<ThreadPool>,
+    logger: Logger,
+) -> impl Future<Item = (), Error = ()> {
+    pool.fragments().and_then(move |fragments| {
+        io.spawn_handle(future::lazy(move || {
+            log_saved(save(&path, &fragments), fragments.len(), &path, &logger);
+            Ok::<_, ()>(())
+        }))
+    })
+}
+
+/// write the fragments of `pool` to the file at `path` on the calling
+/// thread, once the executor has stopped
+pub fn persist_on_shutdown(pool: &Pool, path: PathBuf, logger: Logger) {
+    if let Ok(fragments) = pool.fragments::<()>().wait() {
+        log_saved(save(&path, &fragments), fragments.len(), &path, &logger);
+    }
+}
+
+fn log_saved(saved: io::Result<()>, count: usize, path: &Path, logger: &Logger) {
+    match saved {
+        Ok(()) => debug!(logger, "fragment pool persisted"; "fragments" => count),
+        Err(e) => {
+            warn!(logger, "cannot persist the fragment pool"; "path" => ?path, "reason" => %e)
+        }
+    }
+}
+
+/// write `fragments` to the file at `path`. The file is written to a
+/// scratch file renamed once complete, so a node stopped in the middle
+/// of the write keeps the fragments persisted before.
+pub fn save(path: &Path, fragments: &[Fragment]) -> io::Result<()> {
+    let mut scratch = path.as_os_str().to_owned();
+    scratch.push(".new");
+    let scratch = PathBuf::from(scratch);
+
+    let mut writer = BufWriter::new(File::create(&scratch)?);
+    writer.write_all(MAGIC)?;
+    for fragment in fragments {
+        let bytes = fragment.serialize_as_vec()?;
+        let len = u32::try_from(bytes.len()).expect("fragment size overflows the file format");
+        writer.write_all(&len.to_be_bytes())?;
+        writer.write_all(&bytes)?;
+    }
+    writer.flush()?;
+    std::mem::drop(writer);
+    std::fs::rename(&scratch, path)
+}
+
+/// read the fragments of the file at `path`, oldest first. No fragments
+/// are returned if the file does not exist. A fragment longer than
+/// `max_fragment_size` fails the read before it is allocated.
+pub fn load(path: &Path, max_fragment_size: u32) -> io::Result<Vec<Fragment>> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    let mut reader = BufReader::new(file);
+    let mut magic = [0; 8];
+    reader.read_exact(&mut magic)?;
+    if &magic != MAGIC {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "not a fragment pool file",
+        ));
+    }
+
+    let mut fragments = Vec::new();
+    loop {
+        let mut len = [0; 4];
+        match reader.read_exact(&mut len) {
+            Ok(()) => {}
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(fragments),
+            Err(e) => return Err(e),
+        }
+        let len = u32::from_be_bytes(len);
+        if len > max_fragment_size {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("fragment of {} bytes exceeds the maximum size", len),
+            ));
+        }
+        let mut bytes = vec![0; len as usize];
+        reader.read_exact(&mut bytes)?;
+        fragments.push(Fragment::deserialize(bytes.as_slice())?);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockcfg::{ConfigParam, ConfigParams};
+    use chain_core::property::Fragment as _;
+
+    fn fragment(n: u32) -> Fragment {
+        let mut params = ConfigParams::new();
+        params.push(ConfigParam::SlotsPerEpoch(n));
+        Fragment::Initial(params)
+    }
+
+    fn scratch_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("jormungandr-{}-{}", name, std::process::id()))
+    }
+
+    #[test]
+    fn fragments_round_trip() {
+        let path = scratch_path("fragments-round-trip");
+        let fragments = vec![fragment(1), fragment(2), fragment(3)];
+        save(&path, &fragments).unwrap();
+        let loaded = load(&path, 1024).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let ids = |fragments: &[Fragment]| fragments.iter().map(|f| f.id()).collect::<Vec<_>>();
+        assert_eq!(ids(&loaded), ids(&fragments));
+    }
+
+    #[test]
+    fn missing_file_has_no_fragments() {
+        let path = scratch_path("fragments-missing");
+        assert!(load(&path, 1024).unwrap().is_empty());
+    }
+
+    #[test]
+    fn truncated_file_is_refused() {
+        let path = scratch_path("fragments-truncated");
+        save(&path, &[fragment(1), fragment(2)]).unwrap();
+        let len = std::fs::metadata(&path).unwrap().len();
+        let file = std::fs::OpenOptions::new().write(true).open(&path).unwrap();
+        file.set_len(len - 1).unwrap();
+        let loaded = load(&path, 1024);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn oversized_fragment_is_refused() {
+        let path = scratch_path("fragments-oversized");
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend_from_slice(&u32::max_value().to_be_bytes());
+        std::fs::write(&path, bytes).unwrap();
+        let loaded = load(&path, 1024);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+}
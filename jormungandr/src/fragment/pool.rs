@@ -1,14 +1,17 @@
 use crate::{
     blockcfg::{BlockDate, Ledger, LedgerParameters},
-    fragment::{selection::FragmentSelectionAlgorithm, Fragment, FragmentId, Logs},
+    blockchain::Ref,
+    fragment::{rejection, selection::FragmentSelectionAlgorithm, Fragment, FragmentId, Logs},
     intercom::{NetworkMsg, PropagateMsg},
     utils::async_msg::MessageBox,
 };
 use chain_core::property::Fragment as _;
 use chain_impl_mockchain::transaction::Transaction;
-use jormungandr_lib::interfaces::{FragmentLog, FragmentOrigin, FragmentStatus};
+use jormungandr_lib::interfaces::{
+    FragmentLog, FragmentOrigin, FragmentPriority, FragmentStatus, PoolOverflowPolicy,
+};
 use slog::Logger;
-use std::time::Duration;
+use std::{sync::Arc, time::Duration};
 use tokio::{
     prelude::{
         future::{
@@ -21,6 +24,16 @@ use tokio::{
     timer,
 };
 
+/// A snapshot of the occupation of the pool.
+#[derive(Debug)]
+pub struct PoolStats {
+    pub max_entries: usize,
+    pub bytes: usize,
+    pub max_bytes: usize,
+    /// the fragments with the time they were received, oldest first
+    pub fragments: Vec<(FragmentId, std::time::SystemTime)>,
+}
+
 #[derive(Clone)]
 pub struct Pool {
     logs: Logs,
@@ -31,13 +44,24 @@ pub struct Pool {
 impl Pool {
     pub fn new(
         max_entries: usize,
+        max_bytes: usize,
+        overflow_policy: PoolOverflowPolicy,
         ttl: Duration,
+        max_retries: u32,
+        priority: FragmentPriority,
         logs: Logs,
         network_msg_box: MessageBox<NetworkMsg>,
     ) -> Self {
         Pool {
             logs,
-            pool: Lock::new(internal::Pool::new(max_entries, ttl)),
+            pool: Lock::new(internal::Pool::new(
+                max_entries,
+                max_bytes,
+                overflow_policy,
+                ttl,
+                max_retries,
+                priority,
+            )),
             network_msg_box,
         }
     }
@@ -71,6 +95,7 @@ impl Pool {
                         .filter(|(_, exists_in_logs)| !exists_in_logs)
                         .map(|(fragment, _)| fragment);
                     let new_fragments = pool.insert_all(new_fragments);
+                    let evicted = evicted_statuses(pool.take_evicted());
                     let count = new_fragments.len();
                     let fragment_logs = new_fragments
                         .iter()
@@ -84,13 +109,22 @@ impl Pool {
                         .map_err(move |err: <MessageBox<_> as Sink>::SinkError| {
                             error!(logger, "cannot propagate fragment to network: {}", err)
                         })
-                        .and_then(move |_| logs.insert_all(fragment_logs))
+                        .and_then(move |_| {
+                            logs.insert_all(fragment_logs)
+                                .and_then(move |_| logs.modify_each(evicted))
+                        })
                         .map(move |_| count)
                 })
             }),
         )
     }
 
+    /// Returns the number of fragments in the pool
+    pub fn size<E>(&self) -> impl Future<Item = usize, Error = E> {
+        let mut pool_lock = self.pool.clone();
+        future::poll_fn(move || Ok(pool_lock.poll_lock())).map(|pool| pool.size())
+    }
+
     pub fn remove_added_to_block(
         &mut self,
         fragment_ids: Vec<FragmentId>,
@@ -106,12 +140,136 @@ impl Pool {
             .and_then(move |fragment_ids| logs.modify_all(fragment_ids, status))
     }
 
+    /// Re-validates the fragments against the ledger of the tip once
+    /// the tip has moved to a new epoch, as the fees and the limits
+    /// of the new epoch may invalidate fragments accepted before.
+    /// The invalidated fragments are removed and logged as rejected.
+    pub fn revalidate_on_new_epoch(
+        &mut self,
+        tip: Arc<Ref>,
+        logger: Logger,
+    ) -> impl Future<Item = (), Error = ()> {
+        let mut pool_lock = self.pool.clone();
+        let mut logs = self.logs.clone();
+        future::poll_fn(move || Ok(pool_lock.poll_lock())).and_then(move |mut pool| {
+            let date = tip.block_date();
+            if !pool.enter_epoch(date.epoch) {
+                return A(future::ok(()));
+            }
+            let context = format!("no longer valid from epoch {}", date.epoch);
+            let rejected = pool.revalidate(
+                tip.ledger(),
+                tip.epoch_ledger_parameters(),
+                date,
+                Some(&context),
+            );
+            info!(
+                logger,
+                "revalidated the fragment pool for the new epoch";
+                "epoch" => date.epoch,
+                "rejected" => rejected.len(),
+                "remaining" => pool.size(),
+            );
+            B(logs.modify_each(rejected))
+        })
+    }
+
+    /// Propagates again up to `max_fragments` of the fragments still
+    /// pending `delay` after they were last propagated, oldest first,
+    /// in case the earlier propagation did not reach the leaders.
+    pub fn propagate_pending(
+        &mut self,
+        delay: Duration,
+        max_fragments: usize,
+        logger: Logger,
+    ) -> impl Future<Item = (), Error = ()> {
+        let mut pool_lock = self.pool.clone();
+        let network_msg_box = self.network_msg_box.clone();
+        future::poll_fn(move || Ok(pool_lock.poll_lock())).and_then(move |mut pool| {
+            let fragments = pool.due_for_propagation(delay, max_fragments);
+            std::mem::drop(pool);
+            if !fragments.is_empty() {
+                debug!(logger, "propagating the pending fragments again"; "count" => fragments.len());
+            }
+            stream::iter_ok(fragments)
+                .map(|fragment| {
+                    NetworkMsg::Propagate(PropagateMsg::FragmentRebroadcast(fragment))
+                })
+                .fold(network_msg_box, |network_msg_box, fragment_msg| {
+                    network_msg_box.send(fragment_msg)
+                })
+                .map(|_| ())
+                .map_err(move |err: <MessageBox<_> as Sink>::SinkError| {
+                    error!(logger, "cannot propagate fragment to network: {}", err)
+                })
+        })
+    }
+
+    /// Returns the fragments of the pool, oldest first
+    pub fn fragments<E>(&self) -> impl Future<Item = Vec<Fragment>, Error = E> {
+        let mut pool_lock = self.pool.clone();
+        future::poll_fn(move || Ok(pool_lock.poll_lock()))
+            .map(|pool| pool.fragments().cloned().collect())
+    }
+
+    /// Returns a snapshot of the occupation of the pool
+    pub fn stats<E>(&self) -> impl Future<Item = PoolStats, Error = E> {
+        let mut pool_lock = self.pool.clone();
+        future::poll_fn(move || Ok(pool_lock.poll_lock())).map(|pool| pool.stats())
+    }
+
+    /// Puts back the fragments persisted by a previous run of the node,
+    /// keeping the ones still valid against the ledger of the tip.
+    /// The fragments were propagated when first received, so they are
+    /// only logged as pending, from the network as their origin is not
+    /// persisted. Returns the number of fragments restored.
+    pub fn restore(
+        &mut self,
+        mut fragments: Vec<Fragment>,
+        tip: Arc<Ref>,
+        logger: Logger,
+    ) -> impl Future<Item = usize, Error = ()> {
+        fragments.retain(is_fragment_valid);
+        let mut pool_lock = self.pool.clone();
+        let mut logs = self.logs.clone();
+        future::poll_fn(move || Ok(pool_lock.poll_lock())).and_then(move |mut pool| {
+            let date = tip.block_date();
+            let inserted = pool.insert_all(fragments).len();
+            // only fragments restored before are evicted, none are logged
+            pool.take_evicted();
+            let rejected = pool.revalidate(tip.ledger(), tip.epoch_ledger_parameters(), date, None);
+            pool.enter_epoch(date.epoch);
+            let restored = inserted - rejected.len();
+            info!(
+                logger,
+                "restored the fragment pool";
+                "restored" => restored,
+                "rejected" => rejected.len(),
+            );
+            let fragment_logs = pool
+                .fragments()
+                .map(|fragment| FragmentLog::new(fragment.id().into(), FragmentOrigin::Network))
+                .collect::<Vec<_>>();
+            logs.insert_all(fragment_logs).map(move |_| restored)
+        })
+    }
+
+    /// Removes the fragments that outlived their time to live,
+    /// logging them as rejected.
     pub fn poll_purge(&mut self) -> impl Future<Item = (), Error = timer::Error> {
         let mut lock = self.pool.clone();
+        let mut logs = self.logs.clone();
         let purge_logs = self.logs.poll_purge();
         future::poll_fn(move || Ok(lock.poll_lock()))
             .and_then(move |mut guard| future::poll_fn(move || guard.poll_purge()))
-            .and_then(move |()| purge_logs)
+            .and_then(move |expired| {
+                let statuses = expired.into_iter().map(|fragment_id| {
+                    let reason = "expired".to_owned();
+                    (fragment_id, rejection::expired(reason))
+                });
+                // the logs cannot fail to be modified
+                logs.modify_each(statuses).then(|_| purge_logs)
+            })
     }
 
     pub fn select<SelectAlg>(
@@ -137,6 +295,14 @@ impl Pool {
     }
 }
 
+fn evicted_statuses(
+    fragment_ids: Vec<FragmentId>,
+) -> impl Iterator<Item = (FragmentId, FragmentStatus)> {
+    fragment_ids
+        .into_iter()
+        .map(|fragment_id| (fragment_id, rejection::evicted()))
+}
+
 fn is_fragment_valid(fragment: &Fragment) -> bool {
     match fragment {
         // never valid in the pool, only acceptable in genesis
@@ -161,48 +327,199 @@ fn is_transaction_valid<E>(tx: &Transaction<E>) -> bool {
 
 pub(super) mod internal {
     use super::*;
-    use crate::fragment::PoolEntry;
+    use crate::{blockcfg::Epoch, fragment::PoolEntry};
     use std::{
-        collections::{hash_map::Entry, HashMap, VecDeque},
+        cmp::Reverse,
+        collections::{BTreeMap, HashMap, VecDeque},
         sync::Arc,
+        time::{Instant, SystemTime},
     };
     use tokio::timer::{delay_queue, DelayQueue};
 
     pub struct Pool {
         max_entries: usize,
+        max_bytes: usize,
+        /// total size of the fragments
+        bytes: usize,
+        overflow_policy: PoolOverflowPolicy,
+        /// fragments evicted to make room for others, yet to be logged
+        evicted: Vec<FragmentId>,
         entries: HashMap<FragmentId, (Arc<PoolEntry>, Fragment, delay_queue::Key)>,
         entries_by_time: VecDeque<FragmentId>,
+        /// the fragments by fee per byte, oldest first among the ones
+        /// paying the same
+        entries_by_fee: BTreeMap<u128, VecDeque<FragmentId>>,
         expirations: DelayQueue<FragmentId>,
         ttl: Duration,
+        /// number of the blocks a fragment was left out of, for the
+        /// fragments left out at least once
+        retries: HashMap<FragmentId, u32>,
+        max_retries: u32,
+        /// when the fragments were last propagated to the network
+        propagated_at: HashMap<FragmentId, Instant>,
+        priority: FragmentPriority,
+        epoch: Option<Epoch>,
     }
 
     impl Pool {
-        pub fn new(max_entries: usize, ttl: Duration) -> Self {
+        pub fn new(
+            max_entries: usize,
+            max_bytes: usize,
+            overflow_policy: PoolOverflowPolicy,
+            ttl: Duration,
+            max_retries: u32,
+            priority: FragmentPriority,
+        ) -> Self {
             Pool {
                 max_entries,
+                max_bytes,
+                bytes: 0,
+                overflow_policy,
+                evicted: Vec::new(),
                 entries: HashMap::new(),
                 entries_by_time: VecDeque::new(),
+                entries_by_fee: BTreeMap::new(),
                 expirations: DelayQueue::new(),
                 ttl,
+                retries: HashMap::new(),
+                max_retries,
+                propagated_at: HashMap::new(),
+                priority,
+                epoch: None,
+            }
+        }
+
+        /// Records the epoch of the tip, returning true if the pool
+        /// was last validated in an earlier epoch.
+        pub fn enter_epoch(&mut self, epoch: Epoch) -> bool {
+            match self.epoch.replace(epoch) {
+                Some(previous) => previous < epoch,
+                None => false,
+            }
+        }
+
+        /// Applies the fragments, oldest first, on top of the ledger,
+        /// removing the ones that fail to apply or that cannot fit in
+        /// a block. Returns the removed fragments and their statuses,
+        /// the reasons prefixed with `context` if any.
+        pub fn revalidate(
+            &mut self,
+            ledger: &Ledger,
+            ledger_params: &LedgerParameters,
+            block_date: BlockDate,
+            context: Option<&str>,
+        ) -> Vec<(FragmentId, FragmentStatus)> {
+            let mut ledger_simulation = ledger.clone();
+            let mut rejected = Vec::new();
+            for fragment_id in self.entries_by_time.clone() {
+                let (_, fragment, _) = &self.entries[&fragment_id];
+                let fragment_size = fragment.to_raw().size_bytes_plus_size() as u32;
+                if fragment_size > ledger_params.block_content_max_size {
+                    let status = rejection::too_large(
+                        fragment_size,
+                        ledger_params.block_content_max_size,
+                        context,
+                    );
+                    rejected.push((fragment_id, status));
+                    continue;
+                }
+                match ledger_simulation.apply_fragment(ledger_params, fragment, block_date) {
+                    Ok(ledger_new) => ledger_simulation = ledger_new,
+                    Err(error) => {
+                        let status = rejection::ledger_rejection(&error, context);
+                        rejected.push((fragment_id, status));
+                    }
+                }
             }
+            self.remove_all(rejected.iter().map(|(fragment_id, _)| *fragment_id));
+            rejected
         }
 
-        /// Returns clone of fragment if it was registered
+        /// Returns clone of fragment if it was registered. When the pool
+        /// is full, fragments may be evicted to make room for it, as set
+        /// by the overflow policy.
         pub fn insert(&mut self, fragment: Fragment) -> Option<Fragment> {
-            if self.max_entries < self.entries.len() {
-                None
-            } else {
-                let fragment_id = fragment.id();
-                let entry = match self.entries.entry(fragment_id) {
-                    Entry::Occupied(_) => return None,
-                    Entry::Vacant(vacant) => vacant,
+            let pool_entry = PoolEntry::new(&fragment);
+            self.insert_entry(fragment, pool_entry)
+        }
+
+        fn insert_entry(&mut self, fragment: Fragment, pool_entry: PoolEntry) -> Option<Fragment> {
+            let fragment_id = fragment.id();
+            if self.entries.contains_key(&fragment_id) {
+                return None;
+            }
+            let pool_entry = Arc::new(pool_entry);
+            let size = *pool_entry.fragment_size();
+            if size > self.max_bytes || !self.make_room(&pool_entry) {
+                return None;
+            }
+            let delay = self.expirations.insert(fragment_id, self.ttl);
+            self.entries_by_fee
+                .entry(fee_per_byte(&pool_entry))
+                .or_default()
+                .push_back(fragment_id);
+            self.entries
+                .insert(fragment_id, (pool_entry, fragment.clone(), delay));
+            self.entries_by_time.push_back(fragment_id);
+            self.propagated_at.insert(fragment_id, Instant::now());
+            self.bytes += size;
+            Some(fragment)
+        }
+
+        // evicts fragments until the fragment of `entry` fits, if the
+        // overflow policy allows. Returns true if it fits.
+        fn make_room(&mut self, entry: &PoolEntry) -> bool {
+            while self.entries.len() >= self.max_entries
+                || self.bytes + entry.fragment_size() > self.max_bytes
+            {
+                let evicted = match self.overflow_policy {
+                    PoolOverflowPolicy::RejectNew => None,
+                    PoolOverflowPolicy::EvictOldest => self.entries_by_time.front().cloned(),
+                    PoolOverflowPolicy::EvictLowestFee => self
+                        .lowest_fee()
+                        .filter(|(_, lowest)| *lowest < fee_per_byte(entry))
+                        .map(|(fragment_id, _)| fragment_id),
                 };
-                let pool_entry = Arc::new(PoolEntry::new(&fragment));
-                let delay = self.expirations.insert(fragment_id, self.ttl);
-                entry.insert((pool_entry, fragment.clone(), delay));
-                self.entries_by_time.push_back(fragment_id);
-                Some(fragment)
+                match evicted {
+                    Some(fragment_id) => {
+                        self.remove(&fragment_id);
+                        self.evicted.push(fragment_id);
+                    }
+                    None => return false,
+                }
             }
+            true
+        }
+
+        // the fragment paying the lowest fee per byte, the newest
+        // among the ones paying the same
+        fn lowest_fee(&self) -> Option<(FragmentId, u128)> {
+            self.entries_by_fee
+                .iter()
+                .next()
+                .and_then(|(fee_per_byte, fragment_ids)| {
+                    fragment_ids
+                        .back()
+                        .map(|fragment_id| (*fragment_id, *fee_per_byte))
+                })
+        }
+
+        fn remove_from_fee_index(&mut self, fragment_id: &FragmentId, entry: &PoolEntry) {
+            let fee_per_byte = fee_per_byte(entry);
+            if let Some(fragment_ids) = self.entries_by_fee.get_mut(&fee_per_byte) {
+                if let Some(position) = fragment_ids.iter().position(|id| id == fragment_id) {
+                    fragment_ids.remove(position);
+                }
+                if fragment_ids.is_empty() {
+                    self.entries_by_fee.remove(&fee_per_byte);
+                }
+            }
+        }
+
+        /// Returns the fragments evicted to make room for new ones since
+        /// the last call
+        pub fn take_evicted(&mut self) -> Vec<FragmentId> {
+            std::mem::replace(&mut self.evicted, Vec::new())
         }
 
         /// Returns clones of registered fragments
@@ -212,17 +529,120 @@ pub(super) mod internal {
         ) -> Vec<Fragment> {
             fragments
                 .into_iter()
-                .take(
-                    self.max_entries
-                        .checked_sub(self.entries.len())
-                        .unwrap_or(0),
-                )
                 .filter_map(|fragment| self.insert(fragment))
                 .collect()
         }
 
+        pub fn size(&self) -> usize {
+            self.entries.len()
+        }
+
+        pub fn stats(&self) -> super::PoolStats {
+            super::PoolStats {
+                max_entries: self.max_entries,
+                bytes: self.bytes,
+                max_bytes: self.max_bytes,
+                fragments: self
+                    .entries_by_time
+                    .iter()
+                    .map(|fragment_id| {
+                        let entry = &self.entries[fragment_id].0;
+                        (*fragment_id, *entry.received_at())
+                    })
+                    .collect(),
+            }
+        }
+
+        /// Returns the fragments, oldest first
+        pub fn fragments(&self) -> impl Iterator<Item = &Fragment> {
+            self.entries_by_time
+                .iter()
+                .map(move |fragment_id| &self.entries[fragment_id].1)
+        }
+
+        /// Returns the identifiers of the fragments in the order they
+        /// are to be selected for a block, the oldest first among the
+        /// fragments of the same priority
+        pub fn fragment_ids_by_priority(&self) -> Vec<FragmentId> {
+            let mut fragment_ids = self.entries_by_time.iter().cloned().collect::<Vec<_>>();
+            if self.priority != FragmentPriority::Fifo {
+                let now = SystemTime::now();
+                // the sort is stable, keeping the oldest first
+                fragment_ids
+                    .sort_by_cached_key(|fragment_id| Reverse(self.priority_of(fragment_id, now)));
+            }
+            fragment_ids
+        }
+
+        // the fee per byte, in millionths of a lovelace, weighing up to
+        // twice as much at the end of the time to live of the fragment
+        // for the age and fee priority
+        fn priority_of(&self, fragment_id: &FragmentId, now: SystemTime) -> u128 {
+            let entry = &self.entries[fragment_id].0;
+            let fee_per_byte = fee_per_byte(entry);
+            match self.priority {
+                FragmentPriority::Fifo | FragmentPriority::FeePerByte => fee_per_byte,
+                FragmentPriority::AgeAndFee => {
+                    let ttl = std::cmp::max(self.ttl.as_millis(), 1);
+                    let age = now
+                        .duration_since(*entry.received_at())
+                        .map(|age| std::cmp::min(age.as_millis(), ttl))
+                        .unwrap_or(0);
+                    fee_per_byte * (ttl + age) / ttl
+                }
+            }
+        }
+
+        pub fn get(&self, fragment_id: &FragmentId) -> Option<&Fragment> {
+            self.entries
+                .get(fragment_id)
+                .map(|(_, fragment, _)| fragment)
+        }
+
+        pub fn max_retries(&self) -> u32 {
+            self.max_retries
+        }
+
+        /// Returns up to `max_fragments` of the fragments not propagated
+        /// since `delay`, oldest first, recording them as propagated now
+        pub fn due_for_propagation(
+            &mut self,
+            delay: Duration,
+            max_fragments: usize,
+        ) -> Vec<Fragment> {
+            let now = Instant::now();
+            let mut due = Vec::new();
+            for fragment_id in &self.entries_by_time {
+                if due.len() >= max_fragments {
+                    break;
+                }
+                let propagated_at = self.propagated_at.entry(*fragment_id).or_insert(now);
+                if now.duration_since(*propagated_at) >= delay {
+                    *propagated_at = now;
+                    due.push(self.entries[fragment_id].1.clone());
+                }
+            }
+            due
+        }
+
+        /// Records that the fragment was left out of a block, removing
+        /// it once left out of the maximum number of blocks, if any.
+        /// Returns true if the fragment was removed.
+        pub fn retry_later(&mut self, fragment_id: &FragmentId) -> bool {
+            let retries = self.retries.entry(*fragment_id).or_insert(0);
+            *retries += 1;
+            if self.max_retries == 0 || *retries < self.max_retries {
+                return false;
+            }
+            self.remove(fragment_id).is_some()
+        }
+
         pub fn remove(&mut self, fragment_id: &FragmentId) -> Option<Fragment> {
-            if let Some((_, fragment, cache_key)) = self.entries.remove(fragment_id) {
+            self.retries.remove(fragment_id);
+            self.propagated_at.remove(fragment_id);
+            if let Some((pool_entry, fragment, cache_key)) = self.entries.remove(fragment_id) {
+                self.bytes -= pool_entry.fragment_size();
+                self.remove_from_fee_index(fragment_id, &pool_entry);
                 self.entries_by_time
                     .iter()
                     .position(|id| id == fragment_id)
@@ -243,23 +663,21 @@ pub(super) mod internal {
             }
         }
 
-        pub fn remove_oldest(&mut self) -> Option<Fragment> {
-            let fragment_id = self.entries_by_time.pop_front()?;
-            let (_, fragment, cache_key) = self
-                .entries
-                .remove(&fragment_id)
-                .expect("Pool lost fragment ID consistency");
-            self.expirations.remove(&cache_key);
-            Some(fragment)
-        }
-
-        pub fn poll_purge(&mut self) -> Poll<(), timer::Error> {
+        /// Removes the expired fragments, returning their identifiers
+        pub fn poll_purge(&mut self) -> Poll<Vec<FragmentId>, timer::Error> {
+            let mut expired = Vec::new();
             loop {
                 match self.expirations.poll()? {
-                    Async::NotReady => return Ok(Async::Ready(())),
-                    Async::Ready(None) => return Ok(Async::Ready(())),
+                    Async::NotReady => return Ok(Async::Ready(expired)),
+                    Async::Ready(None) => return Ok(Async::Ready(expired)),
                     Async::Ready(Some(entry)) => {
-                        self.entries.remove(entry.get_ref());
+                        if let Some((pool_entry, _, _)) = self.entries.remove(entry.get_ref()) {
+                            self.bytes -= pool_entry.fragment_size();
+                            self.remove_from_fee_index(entry.get_ref(), &pool_entry);
+                        }
+                        self.retries.remove(entry.get_ref());
+                        self.propagated_at.remove(entry.get_ref());
+                        expired.push(*entry.get_ref());
                         self.entries_by_time
                             .iter()
                             .position(|id| id == entry.get_ref())
@@ -271,4 +689,250 @@ pub(super) mod internal {
             }
         }
     }
+
+    // the fee per byte of the fragment, in millionths of a lovelace
+    fn fee_per_byte(entry: &PoolEntry) -> u128 {
+        let fee = u128::from(entry.fragment_fee().0);
+        let size = std::cmp::max(*entry.fragment_size(), 1) as u128;
+        fee * 1_000_000 / size
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::blockcfg::{ConfigParam, ConfigParams, Value};
+        use jormungandr_lib::interfaces::RejectionKind;
+        use tokio::runtime::current_thread::Runtime;
+
+        fn fragment(n: u32) -> Fragment {
+            let mut params = ConfigParams::new();
+            params.push(ConfigParam::SlotsPerEpoch(n));
+            Fragment::Initial(params)
+        }
+
+        fn pool(
+            max_entries: usize,
+            overflow_policy: PoolOverflowPolicy,
+            max_retries: u32,
+            priority: FragmentPriority,
+        ) -> Pool {
+            Pool::new(
+                max_entries,
+                usize::max_value(),
+                overflow_policy,
+                Duration::from_secs(3600),
+                max_retries,
+                priority,
+            )
+        }
+
+        // inserts the fragment as if paying `fee`, returning its id if
+        // it entered the pool
+        fn insert(pool: &mut Pool, fragment: Fragment, fee: u64) -> Option<FragmentId> {
+            let entry = PoolEntry::with_fee(&fragment, Value(fee));
+            pool.insert_entry(fragment, entry)
+                .map(|fragment| fragment.id())
+        }
+
+        // the expiration queue of the pool needs a timer
+        fn with_timer<R>(f: impl FnOnce() -> R) -> R {
+            Runtime::new()
+                .unwrap()
+                .block_on(future::lazy(move || Ok::<_, ()>(f())))
+                .unwrap()
+        }
+
+        #[test]
+        fn lowest_fee_is_evicted_newest_first() {
+            with_timer(|| {
+                let mut pool = pool(
+                    3,
+                    PoolOverflowPolicy::EvictLowestFee,
+                    0,
+                    FragmentPriority::Fifo,
+                );
+                let high = insert(&mut pool, fragment(1), 10).unwrap();
+                let low_old = insert(&mut pool, fragment(2), 1).unwrap();
+                let low_new = insert(&mut pool, fragment(3), 1).unwrap();
+                let first = insert(&mut pool, fragment(4), 5).unwrap();
+                assert_eq!(pool.take_evicted(), vec![low_new]);
+                insert(&mut pool, fragment(5), 5).unwrap();
+                assert_eq!(pool.take_evicted(), vec![low_old]);
+                assert_eq!(pool.size(), 3);
+                assert!(pool.get(&high).is_some());
+                assert!(pool.get(&first).is_some());
+            })
+        }
+
+        #[test]
+        fn fragment_not_paying_more_than_the_lowest_is_refused() {
+            with_timer(|| {
+                let mut pool = pool(
+                    2,
+                    PoolOverflowPolicy::EvictLowestFee,
+                    0,
+                    FragmentPriority::Fifo,
+                );
+                let first = insert(&mut pool, fragment(1), 3).unwrap();
+                let second = insert(&mut pool, fragment(2), 5).unwrap();
+                assert!(insert(&mut pool, fragment(3), 3).is_none());
+                assert!(pool.take_evicted().is_empty());
+                assert!(pool.get(&first).is_some());
+                assert!(pool.get(&second).is_some());
+            })
+        }
+
+        #[test]
+        fn removed_fragments_leave_the_fee_index() {
+            with_timer(|| {
+                let mut pool = pool(
+                    2,
+                    PoolOverflowPolicy::EvictLowestFee,
+                    0,
+                    FragmentPriority::Fifo,
+                );
+                let low = insert(&mut pool, fragment(1), 1).unwrap();
+                let high = insert(&mut pool, fragment(2), 10).unwrap();
+                pool.remove(&low);
+                insert(&mut pool, fragment(3), 5).unwrap();
+                assert!(pool.take_evicted().is_empty());
+                insert(&mut pool, fragment(4), 7).unwrap();
+                assert_eq!(pool.size(), 2);
+                assert!(pool.get(&high).is_some());
+                assert!(pool.entries_by_fee.values().all(|ids| !ids.contains(&low)));
+            })
+        }
+
+        #[test]
+        fn oldest_is_evicted() {
+            with_timer(|| {
+                let mut pool = pool(
+                    2,
+                    PoolOverflowPolicy::EvictOldest,
+                    0,
+                    FragmentPriority::Fifo,
+                );
+                let oldest = insert(&mut pool, fragment(1), 10).unwrap();
+                let second = insert(&mut pool, fragment(2), 1).unwrap();
+                let third = insert(&mut pool, fragment(3), 1).unwrap();
+                assert_eq!(pool.take_evicted(), vec![oldest]);
+                assert!(pool.get(&second).is_some());
+                assert!(pool.get(&third).is_some());
+            })
+        }
+
+        #[test]
+        fn new_fragment_is_rejected_when_full() {
+            with_timer(|| {
+                let mut pool = pool(2, PoolOverflowPolicy::RejectNew, 0, FragmentPriority::Fifo);
+                insert(&mut pool, fragment(1), 1).unwrap();
+                insert(&mut pool, fragment(2), 1).unwrap();
+                assert!(insert(&mut pool, fragment(3), 10).is_none());
+                assert!(pool.take_evicted().is_empty());
+                assert_eq!(pool.size(), 2);
+            })
+        }
+
+        fn by_priority(priority: FragmentPriority) -> (Vec<FragmentId>, Vec<FragmentId>) {
+            with_timer(|| {
+                let mut pool = pool(10, PoolOverflowPolicy::RejectNew, 0, priority);
+                let inserted = vec![
+                    insert(&mut pool, fragment(1), 1).unwrap(),
+                    insert(&mut pool, fragment(2), 10).unwrap(),
+                    insert(&mut pool, fragment(3), 5).unwrap(),
+                    insert(&mut pool, fragment(4), 10).unwrap(),
+                ];
+                (inserted, pool.fragment_ids_by_priority())
+            })
+        }
+
+        #[test]
+        fn fifo_selects_oldest_first() {
+            let (inserted, selected) = by_priority(FragmentPriority::Fifo);
+            assert_eq!(selected, inserted);
+        }
+
+        #[test]
+        fn fee_per_byte_selects_highest_fee_first() {
+            let (inserted, selected) = by_priority(FragmentPriority::FeePerByte);
+            assert_eq!(
+                selected,
+                vec![inserted[1], inserted[3], inserted[2], inserted[0]]
+            );
+        }
+
+        #[test]
+        fn age_and_fee_selects_highest_fee_first_among_fresh_fragments() {
+            let (inserted, selected) = by_priority(FragmentPriority::AgeAndFee);
+            assert_eq!(
+                selected,
+                vec![inserted[1], inserted[3], inserted[2], inserted[0]]
+            );
+        }
+
+        #[test]
+        fn revalidation_removes_the_rejected_fragments() {
+            let (ledger, mut params) = crate::fragment::selection::tests::genesis();
+            let small = fragment(1);
+            let mut large_params = ConfigParams::new();
+            large_params.push(ConfigParam::SlotsPerEpoch(2));
+            large_params.push(ConfigParam::SlotsPerEpoch(3));
+            let large = Fragment::Initial(large_params);
+            params.block_content_max_size = small.to_raw().size_bytes_plus_size() as u32;
+            let date = BlockDate {
+                epoch: 1,
+                slot_id: 0,
+            };
+            with_timer(move || {
+                let mut pool = pool(10, PoolOverflowPolicy::RejectNew, 0, FragmentPriority::Fifo);
+                let small_id = insert(&mut pool, small, 0).unwrap();
+                let large_id = insert(&mut pool, large, 0).unwrap();
+                let rejected = pool.revalidate(&ledger, &params, date, Some("epoch 1"));
+                assert_eq!(pool.size(), 0);
+                let kinds = rejected
+                    .into_iter()
+                    .map(|(fragment_id, status)| match status {
+                        FragmentStatus::Rejected { reason, kind } => {
+                            assert!(reason.starts_with("epoch 1: "));
+                            (fragment_id, kind)
+                        }
+                        status => panic!("unexpected status {:?}", status),
+                    })
+                    .collect::<Vec<_>>();
+                // the initial fragments are only accepted in the genesis block
+                assert_eq!(
+                    kinds,
+                    vec![
+                        (small_id, RejectionKind::Other),
+                        (large_id, RejectionKind::TooLarge)
+                    ]
+                );
+            })
+        }
+
+        #[test]
+        fn fragment_is_removed_after_max_retries() {
+            with_timer(|| {
+                let mut pool = pool(10, PoolOverflowPolicy::RejectNew, 3, FragmentPriority::Fifo);
+                let id = insert(&mut pool, fragment(1), 1).unwrap();
+                assert!(!pool.retry_later(&id));
+                assert!(!pool.retry_later(&id));
+                assert!(pool.retry_later(&id));
+                assert!(pool.get(&id).is_none());
+                assert_eq!(pool.size(), 0);
+            })
+        }
+
+        #[test]
+        fn fragment_is_kept_without_max_retries() {
+            with_timer(|| {
+                let mut pool = pool(10, PoolOverflowPolicy::RejectNew, 0, FragmentPriority::Fifo);
+                let id = insert(&mut pool, fragment(1), 1).unwrap();
+                for _ in 0..100 {
+                    assert!(!pool.retry_later(&id));
+                }
+                assert!(pool.get(&id).is_some());
+            })
+        }
+    }
 }
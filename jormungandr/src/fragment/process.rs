@@ -1,38 +1,85 @@
 use crate::{
-    fragment::{Logs, Pool},
+    blockchain::Tip,
+    fragment::{persistence, Logs, Pool},
     intercom::{NetworkMsg, TransactionMsg},
+    leadership::LeaderSlots,
     stats_counter::StatsCounter,
     utils::{
         async_msg::{MessageBox, MessageQueue},
         task::TokioServiceInfo,
     },
 };
-use std::time::Duration;
+use jormungandr_lib::interfaces::{FragmentPriority, PoolOverflowPolicy};
+use std::{path::PathBuf, sync::Arc, time::Duration};
 use tokio::prelude::{
-    future::Either::{A, B},
+    future::{
+        self,
+        Either::{A, B},
+    },
     Future, Stream,
 };
+use tokio_threadpool::{Builder, ThreadPool};
+
+/// how often the epoch of the tip is checked to revalidate the pool
+const EPOCH_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// how often the pool is written to its file in the storage directory
+const PERSISTENCE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Bounds of the propagation of the fragments still pending.
+pub struct PendingPropagation {
+    /// time after which a pending fragment is propagated again
+    pub delay: Duration,
+    /// maximum number of fragments propagated again per gossip round
+    pub max_fragments: usize,
+    pub gossip_interval: Duration,
+}
 
 pub struct Process {
     pool: Pool,
     logs: Logs,
     garbage_collection_interval: Duration,
+    propagation: PendingPropagation,
+    leader_slots: LeaderSlots,
+    blockchain_tip: Tip,
+    persistence_path: Option<PathBuf>,
 }
 
 impl Process {
     pub fn new(
         pool_max_entries: usize,
+        pool_max_bytes: usize,
+        pool_overflow_policy: PoolOverflowPolicy,
         pool_ttl: Duration,
+        pool_max_retries: u32,
+        pool_priority: FragmentPriority,
         logs_max_entries: usize,
         logs_ttl: Duration,
         garbage_collection_interval: Duration,
+        propagation: PendingPropagation,
         network_msg_box: MessageBox<NetworkMsg>,
+        leader_slots: LeaderSlots,
+        blockchain_tip: Tip,
+        persistence_path: Option<PathBuf>,
     ) -> Self {
         let logs = Logs::new(logs_max_entries, logs_ttl);
         Process {
-            pool: Pool::new(pool_max_entries, pool_ttl, logs.clone(), network_msg_box),
+            pool: Pool::new(
+                pool_max_entries,
+                pool_max_bytes,
+                pool_overflow_policy,
+                pool_ttl,
+                pool_max_retries,
+                pool_priority,
+                logs.clone(),
+                network_msg_box,
+            ),
             logs,
             garbage_collection_interval,
+            propagation,
+            leader_slots,
+            blockchain_tip,
+            persistence_path,
         }
     }
 
@@ -49,7 +96,30 @@ impl Process {
         stats_counter: StatsCounter,
         input: MessageQueue<TransactionMsg>,
     ) -> impl Future<Item = (), Error = ()> {
+        // the file of the pool is read and written out of the executor
+        // threads
+        let io = Arc::new(
+            Builder::new()
+                .pool_size(1)
+                .name_prefix("fragment-pool-file")
+                .build(),
+        );
+        let restore = self.restore_pool(&service_info, io.clone());
         self.start_pool_garbage_collector(&service_info);
+        self.start_pool_revalidation(&service_info);
+        self.start_pool_persistence(&service_info, io);
+        self.start_pending_propagation(&service_info);
+        // the fragments submitted while the persisted ones are restored
+        // wait in the queue
+        restore.and_then(move |()| self.process_input(service_info, stats_counter, input))
+    }
+
+    fn process_input(
+        self,
+        service_info: TokioServiceInfo,
+        stats_counter: StatsCounter,
+        input: MessageQueue<TransactionMsg>,
+    ) -> impl Future<Item = (), Error = ()> {
         input.for_each(move |input| {
             match input {
                 TransactionMsg::SendTransaction(origin, txs) => {
@@ -65,26 +135,123 @@ impl Process {
                     // put them in another pool.
 
                     let stats_counter = stats_counter.clone();
-                    A(self
+                    let pool = self.pool.clone();
+                    A(A(self
                         .pool
                         .clone()
                         .insert_and_propagate_all(origin, txs, service_info.logger().clone())
-                        .map(move |count| stats_counter.add_tx_recv_cnt(count)))
+                        .and_then(move |count| {
+                            stats_counter.add_tx_recv_cnt(count);
+                            pool.size()
+                                .map(move |size| stats_counter.observe_mempool_usage(size))
+                        })))
                 }
-                TransactionMsg::RemoveTransactions(fragment_ids, status) => B(self
+                TransactionMsg::RemoveTransactions(fragment_ids, status) => A(B(self
                     .pool
                     .clone()
-                    .remove_added_to_block(fragment_ids, status)),
+                    .remove_added_to_block(fragment_ids, status))),
+                TransactionMsg::GetPoolStats(handle) => {
+                    B(self.pool.stats().map(move |stats| handle.reply_ok(stats)))
+                }
             }
         })
     }
 
+    fn restore_pool(
+        &self,
+        service_info: &TokioServiceInfo,
+        io: Arc<ThreadPool>,
+    ) -> impl Future<Item = (), Error = ()> {
+        let path = match &self.persistence_path {
+            Some(path) => path.clone(),
+            None => return A(future::ok(())),
+        };
+        let mut pool = self.pool.clone();
+        let logger = service_info.logger().clone();
+        B(self.blockchain_tip.get_ref().and_then(move |tip| {
+            // the pool only holds fragments fitting in a block
+            let max_fragment_size = tip.epoch_ledger_parameters().block_content_max_size;
+            let load_path = path.clone();
+            io.spawn_handle(future::lazy(move || {
+                Ok::<_, ()>(persistence::load(&load_path, max_fragment_size))
+            }))
+            .and_then(move |loaded| match loaded {
+                Ok(fragments) if fragments.is_empty() => A(future::ok(())),
+                Ok(fragments) => B(pool.restore(fragments, tip, logger).map(|_| ())),
+                Err(e) => {
+                    warn!(
+                        logger,
+                        "cannot read the persisted fragment pool";
+                        "path" => ?path,
+                        "reason" => %e,
+                    );
+                    A(future::ok(()))
+                }
+            })
+        }))
+    }
+
     fn start_pool_garbage_collector(&self, service_info: &TokioServiceInfo) {
-        let mut pool = self.pool().clone();
+        let pool = self.pool().clone();
+        let leader_slots = self.leader_slots.clone();
         service_info.run_periodic(
             "pool garbage collection",
             self.garbage_collection_interval,
-            move || pool.poll_purge(),
+            move || {
+                let mut pool = pool.clone();
+                leader_slots
+                    .wait_idle()
+                    .and_then(move |()| pool.poll_purge())
+            },
+        )
+    }
+
+    fn start_pool_revalidation(&self, service_info: &TokioServiceInfo) {
+        let pool = self.pool().clone();
+        let leader_slots = self.leader_slots.clone();
+        let blockchain_tip = self.blockchain_tip.clone();
+        let logger = service_info.logger().clone();
+        service_info.run_periodic("pool revalidation", EPOCH_CHECK_INTERVAL, move || {
+            let mut pool = pool.clone();
+            let blockchain_tip = blockchain_tip.clone();
+            let logger = logger.clone();
+            leader_slots
+                .wait_idle()
+                .then(move |_| blockchain_tip.get_ref())
+                .and_then(move |tip| pool.revalidate_on_new_epoch(tip, logger))
+        })
+    }
+
+    fn start_pool_persistence(&self, service_info: &TokioServiceInfo, io: Arc<ThreadPool>) {
+        let path = match &self.persistence_path {
+            Some(path) => path.clone(),
+            None => return,
+        };
+        let pool = self.pool().clone();
+        let logger = service_info.logger().clone();
+        service_info.run_periodic("pool persistence", PERSISTENCE_INTERVAL, move || {
+            persistence::persist(&pool, path.clone(), io.clone(), logger.clone())
+        })
+    }
+
+    fn start_pending_propagation(&self, service_info: &TokioServiceInfo) {
+        let PendingPropagation {
+            delay,
+            max_fragments,
+            gossip_interval,
+        } = self.propagation;
+        if max_fragments == 0 {
+            return;
+        }
+        let pool = self.pool().clone();
+        let logger = service_info.logger().clone();
+        service_info.run_periodic(
+            "pending fragments propagation",
+            gossip_interval,
+            move || {
+                pool.clone()
+                    .propagate_pending(delay, max_fragments, logger.clone())
+            },
         )
     }
 }
@@ -0,0 +1,132 @@
+use chain_impl_mockchain::ledger::Error as LedgerError;
+use jormungandr_lib::interfaces::{FragmentStatus, RejectionKind};
+use std::error::Error as _;
+
+/// The status of a fragment failing to apply to the ledger with `error`,
+/// the reason prefixed with `context` if any.
+pub fn ledger_rejection(error: &LedgerError, context: Option<&str>) -> FragmentStatus {
+    let kind = match error {
+        LedgerError::Account(_) => RejectionKind::InsufficientFunds,
+        // the witness of an account input signs the spending counter
+        LedgerError::AccountInvalidSignature { .. } => RejectionKind::WrongSpendingCounter,
+        LedgerError::UtxoInvalidSignature { .. }
+        | LedgerError::OldUtxoInvalidSignature { .. }
+        | LedgerError::MultisigInvalidSignature { .. } => RejectionKind::InvalidWitness,
+        _ => RejectionKind::Other,
+    };
+    let mut reason = match context {
+        Some(context) => format!("{}: {}", context, error),
+        None => error.to_string(),
+    };
+    if let Some(source) = error.source() {
+        reason = format!("{}: {}", reason, source);
+    }
+    FragmentStatus::Rejected { reason, kind }
+}
+
+/// The status of a fragment too large to fit in a block, the reason
+/// prefixed with `context` if any.
+pub fn too_large(
+    fragment_size: u32,
+    block_content_max_size: u32,
+    context: Option<&str>,
+) -> FragmentStatus {
+    let mut reason = format!(
+        "fragment size {} exceeds the maximum block content size {}",
+        fragment_size, block_content_max_size
+    );
+    if let Some(context) = context {
+        reason = format!("{}: {}", context, reason);
+    }
+    FragmentStatus::Rejected {
+        reason,
+        kind: RejectionKind::TooLarge,
+    }
+}
+
+/// The status of a fragment expired from the pool.
+pub fn expired(reason: String) -> FragmentStatus {
+    FragmentStatus::Rejected {
+        reason,
+        kind: RejectionKind::Expired,
+    }
+}
+
+/// The status of a fragment evicted from the full pool.
+pub fn evicted() -> FragmentStatus {
+    FragmentStatus::Rejected {
+        reason: "evicted from the full mempool".to_owned(),
+        kind: RejectionKind::Evicted,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        blockcfg::{BlockDate, ConfigParams, Fragment},
+        fragment::selection::tests::genesis,
+    };
+
+    // the initial fragments are only accepted in the genesis block
+    fn ledger_error() -> LedgerError {
+        let (ledger, params) = genesis();
+        let date = BlockDate {
+            epoch: 0,
+            slot_id: 1,
+        };
+        match ledger.apply_fragment(&params, &Fragment::Initial(ConfigParams::new()), date) {
+            Ok(_) => panic!("initial fragment applied after the genesis block"),
+            Err(error) => error,
+        }
+    }
+
+    fn rejected(status: FragmentStatus) -> (String, RejectionKind) {
+        match status {
+            FragmentStatus::Rejected { reason, kind } => (reason, kind),
+            status => panic!("unexpected status {:?}", status),
+        }
+    }
+
+    #[test]
+    fn ledger_rejection_reports_the_error() {
+        let error = ledger_error();
+        let (reason, kind) = rejected(ledger_rejection(&error, None));
+        assert!(reason.starts_with(&error.to_string()));
+        assert_eq!(kind, RejectionKind::Other);
+    }
+
+    #[test]
+    fn ledger_rejection_is_prefixed_with_the_context() {
+        let error = ledger_error();
+        let (reason, _) = rejected(ledger_rejection(
+            &error,
+            Some("no longer valid from epoch 2"),
+        ));
+        assert!(reason.starts_with(&format!("no longer valid from epoch 2: {}", error)));
+    }
+
+    #[test]
+    fn too_large_reports_the_sizes() {
+        let (reason, kind) = rejected(too_large(2048, 1024, None));
+        assert_eq!(
+            reason,
+            "fragment size 2048 exceeds the maximum block content size 1024"
+        );
+        assert_eq!(kind, RejectionKind::TooLarge);
+        let (reason, _) = rejected(too_large(2048, 1024, Some("no longer valid from epoch 2")));
+        assert_eq!(
+            reason,
+            "no longer valid from epoch 2: fragment size 2048 exceeds the maximum block content size 1024"
+        );
+    }
+
+    #[test]
+    fn expired_and_evicted_have_their_kind() {
+        let (reason, kind) = rejected(expired("expired".to_owned()));
+        assert_eq!(reason, "expired");
+        assert_eq!(kind, RejectionKind::Expired);
+        let (_, kind) = rejected(evicted());
+        assert_eq!(kind, RejectionKind::Evicted);
+    }
+}
@@ -2,10 +2,8 @@ use super::logs::internal::Logs;
 use super::pool::internal::Pool;
 use crate::{
     blockcfg::{BlockDate, Contents, ContentsBuilder, Ledger, LedgerParameters},
-    fragment::FragmentId,
+    fragment::{rejection, FragmentId},
 };
-use chain_core::property::Fragment as _;
-use jormungandr_lib::interfaces::FragmentStatus;
 
 pub enum SelectionOutput {
     Commit { fragment_id: FragmentId },
@@ -27,21 +25,23 @@ pub trait FragmentSelectionAlgorithm {
     fn finalize(self) -> Contents;
 }
 
-pub struct OldestFirst {
+/// Selects the fragments in the order of their priority in the pool,
+/// as configured by the mempool `priority` setting.
+pub struct HighestPriorityFirst {
     builder: ContentsBuilder,
     current_total_size: u32,
 }
 
-impl OldestFirst {
+impl HighestPriorityFirst {
     pub fn new() -> Self {
-        OldestFirst {
+        HighestPriorityFirst {
             builder: ContentsBuilder::new(),
             current_total_size: 0,
         }
     }
 }
 
-impl FragmentSelectionAlgorithm for OldestFirst {
+impl FragmentSelectionAlgorithm for HighestPriorityFirst {
     fn finalize(self) -> Contents {
         self.builder.into()
     }
@@ -56,26 +56,24 @@ impl FragmentSelectionAlgorithm for OldestFirst {
     ) {
         let mut ledger_simulation = ledger.clone();
 
-        while let Some(fragment) = pool.remove_oldest() {
-            let id = fragment.id();
+        for id in pool.fragment_ids_by_priority() {
+            let fragment = match pool.get(&id) {
+                Some(fragment) => fragment.clone(),
+                None => continue,
+            };
             let fragment_raw = fragment.to_raw(); // TODO: replace everything to FragmentRaw in the node
             let fragment_size = fragment_raw.size_bytes_plus_size() as u32;
             let total_size = self.current_total_size + fragment_size;
 
             if total_size <= ledger_params.block_content_max_size {
+                pool.remove(&id);
                 match ledger_simulation.apply_fragment(ledger_params, &fragment, block_date) {
                     Ok(ledger_new) => {
                         self.builder.push(fragment);
                         ledger_simulation = ledger_new;
                     }
                     Err(error) => {
-                        use std::error::Error as _;
-                        let error = if let Some(source) = error.source() {
-                            format!("{}: {}", error, source)
-                        } else {
-                            error.to_string()
-                        };
-                        logs.modify(&id.into(), FragmentStatus::Rejected { reason: error })
+                        logs.modify(&id.into(), rejection::ledger_rejection(&error, None))
                     }
                 }
 
@@ -84,7 +82,122 @@ impl FragmentSelectionAlgorithm for OldestFirst {
                 if total_size == ledger_params.block_content_max_size {
                     break;
                 }
+            } else if pool.retry_later(&id) {
+                let reason = format!(
+                    "expired: left out of {} blocks for lack of space",
+                    pool.max_retries()
+                );
+                logs.modify(&id.into(), rejection::expired(reason))
             }
         }
     }
 }
+
+#[cfg(test)]
+pub(super) mod tests {
+    use super::*;
+    use crate::blockcfg::{ConfigParam, ConfigParams, ConsensusVersion, Fragment, Leadership};
+    use chain_addr::Discrimination;
+    use chain_core::property::Fragment as _;
+    use chain_crypto::{Ed25519, SecretKey};
+    use chain_impl_mockchain::{fee::LinearFee, leadership::bft::LeaderId};
+    use jormungandr_lib::interfaces::{
+        Block0Configuration, BlockchainConfiguration, ConsensusLeaderId, FragmentLog,
+        FragmentOrigin, FragmentPriority, FragmentStatus, PoolOverflowPolicy, RejectionKind,
+    };
+    use std::time::Duration;
+    use tokio::{prelude::future, runtime::current_thread::Runtime};
+
+    /// the ledger of a new BFT blockchain and its parameters
+    pub fn genesis() -> (Ledger, LedgerParameters) {
+        let leader_key = SecretKey::<Ed25519>::generate(rand::thread_rng());
+        let mut blockchain_configuration = BlockchainConfiguration::new(
+            Discrimination::Test,
+            ConsensusVersion::Bft,
+            LinearFee::new(0, 0, 0),
+        );
+        blockchain_configuration
+            .consensus_leader_ids
+            .push(ConsensusLeaderId(LeaderId::from(leader_key.to_public())));
+        let block0 = Block0Configuration {
+            blockchain_configuration,
+            initial: Vec::new(),
+        }
+        .to_block();
+        let ledger =
+            Ledger::new(block0.header.hash(), block0.contents.iter()).expect("valid genesis block");
+        let params = Leadership::new(0, &ledger).ledger_parameters().clone();
+        (ledger, params)
+    }
+
+    fn fragment(slots_per_epoch: &[u32]) -> Fragment {
+        let mut params = ConfigParams::new();
+        for n in slots_per_epoch {
+            params.push(ConfigParam::SlotsPerEpoch(*n));
+        }
+        Fragment::Initial(params)
+    }
+
+    fn size(fragment: &Fragment) -> u32 {
+        fragment.to_raw().size_bytes_plus_size() as u32
+    }
+
+    fn status(logs: &Logs, fragment_id: &FragmentId) -> FragmentStatus {
+        let fragment_id = (*fragment_id).into();
+        logs.logs()
+            .find(|log| *log.fragment_id() == fragment_id)
+            .map(|log| log.status().clone())
+            .unwrap()
+    }
+
+    #[test]
+    fn fragment_left_out_of_max_retries_blocks_expires() {
+        let (ledger, mut params) = genesis();
+        let large = fragment(&[1, 2, 3]);
+        let small = fragment(&[4]);
+        // only the small fragment fits in a block
+        params.block_content_max_size = size(&small);
+        let (large_id, small_id) = (large.id(), small.id());
+        let block_date = BlockDate {
+            epoch: 0,
+            slot_id: 1,
+        };
+
+        // the expiration queues of the pool and the logs need a timer
+        Runtime::new()
+            .unwrap()
+            .block_on(future::lazy(move || {
+                let mut pool = Pool::new(
+                    10,
+                    usize::max_value(),
+                    PoolOverflowPolicy::RejectNew,
+                    Duration::from_secs(3600),
+                    2,
+                    FragmentPriority::Fifo,
+                );
+                let mut logs = Logs::new(10, Duration::from_secs(3600));
+                for fragment in vec![large, small] {
+                    logs.insert(FragmentLog::new(fragment.id().into(), FragmentOrigin::Rest));
+                    pool.insert(fragment).unwrap();
+                }
+
+                HighestPriorityFirst::new()
+                    .select(&ledger, &params, block_date, &mut logs, &mut pool);
+                assert!(pool.get(&small_id).is_none());
+                assert!(pool.get(&large_id).is_some());
+                assert_eq!(status(&logs, &large_id), FragmentStatus::Pending);
+
+                HighestPriorityFirst::new()
+                    .select(&ledger, &params, block_date, &mut logs, &mut pool);
+                assert_eq!(pool.size(), 0);
+                match status(&logs, &large_id) {
+                    FragmentStatus::Rejected { kind, .. } => {
+                        assert_eq!(kind, RejectionKind::Expired)
+                    }
+                    status => panic!("unexpected status {:?}", status),
+                }
+                Ok::<_, ()>(())
+            }))
+            .unwrap();
+    }
+}
@@ -1,7 +1,9 @@
 use crate::blockcfg::{Block, Fragment, FragmentId, Header, HeaderHash};
-use crate::blockchain::Checkpoints;
-use crate::network::p2p::comm::PeerInfo;
+use crate::blockchain::{BranchInfo, Checkpoints};
+use crate::fragment::PoolStats;
+use crate::network::p2p::comm::{PeerDebugInfo, PeerInfo};
 use crate::network::p2p::Id as NodeId;
+use crate::settings::start::network::TrustedPeer;
 use crate::utils::async_msg::{self, MessageBox, MessageQueue};
 use futures::prelude::*;
 use futures::sync::{mpsc, oneshot};
@@ -460,6 +462,7 @@ pub fn stream_request<T, R, E>(
 pub enum TransactionMsg {
     SendTransaction(FragmentOrigin, Vec<Fragment>),
     RemoveTransactions(Vec<FragmentId>, FragmentStatus),
+    GetPoolStats(ReplyHandle<PoolStats>),
 }
 
 /// Client messages, mainly requests from connected peers to our node.
@@ -468,8 +471,15 @@ pub enum ClientMsg {
     GetBlockTip(ReplyHandle<Header>),
     GetHeaders(Vec<HeaderHash>, ReplyStreamHandle<Header>),
     GetHeadersRange(Vec<HeaderHash>, HeaderHash, ReplyStreamHandle<Header>),
-    GetBlocks(Vec<HeaderHash>, ReplyStreamHandle<Block>),
-    PullBlocksToTip(Vec<HeaderHash>, ReplyStreamHandle<Block>),
+    /// The blocks with the given identifiers; the limit, if given,
+    /// is on how far back from the tip the blocks may be.
+    GetBlocks(Vec<HeaderHash>, Option<u64>, ReplyStreamHandle<Block>),
+    /// The blocks from the latest of the checkpoints known to the node
+    /// to the tip; the limit, if given, is on how far back from the tip
+    /// the pull may start.
+    PullBlocksToTip(Vec<HeaderHash>, Option<u64>, ReplyStreamHandle<Block>),
+    /// The branches tracked by the blockchain, including the main one.
+    GetBranches(ReplyHandle<Vec<BranchInfo>>),
 }
 
 impl Debug for ClientMsg {
@@ -490,14 +500,20 @@ impl Debug for ClientMsg {
                 .field(to)
                 .field(&format_args!("_"))
                 .finish(),
-            ClientMsg::GetBlocks(ids, _) => f
+            ClientMsg::GetBlocks(ids, max_depth, _) => f
                 .debug_tuple("GetBlocks")
                 .field(ids)
+                .field(max_depth)
                 .field(&format_args!("_"))
                 .finish(),
-            ClientMsg::PullBlocksToTip(from, _) => f
+            ClientMsg::PullBlocksToTip(from, max_depth, _) => f
                 .debug_tuple("PullBlocksToTip")
                 .field(from)
+                .field(max_depth)
+                .field(&format_args!("_"))
+                .finish(),
+            ClientMsg::GetBranches(_) => f
+                .debug_tuple("GetBranches")
                 .field(&format_args!("_"))
                 .finish(),
         }
@@ -512,7 +528,9 @@ pub enum BlockMsg {
     /// A untrusted block Header has been received from the network task
     AnnouncedBlock(Header, NodeId),
     /// A stream of untrusted blocks has been received from the network task.
-    NetworkBlocks(RequestStreamHandle<Block, ()>),
+    /// The node identifier of the peer is given when the blocks
+    /// were solicited from it.
+    NetworkBlocks(RequestStreamHandle<Block, ()>, Option<NodeId>),
     /// The stream of headers for missing chain blocks has been received
     /// from the network in response to a PullHeaders request or a Missing
     /// solicitation event.
@@ -524,6 +542,9 @@ pub enum BlockMsg {
 pub enum PropagateMsg {
     Block(Header),
     Fragment(Fragment),
+    /// a fragment propagated again, because it is still pending some
+    /// time after its first propagation
+    FragmentRebroadcast(Fragment),
 }
 
 /// Messages to the network task.
@@ -538,6 +559,14 @@ pub enum NetworkMsg {
         to: HeaderHash,
     },
     PeerInfo(ReplyHandle<Vec<PeerInfo>>),
+    /// the communication state with one peer, for debugging
+    PeerDebug(NodeId, ReplyHandle<PeerDebugInfo>),
+    /// the communication state with all the peers
+    PeerList(ReplyHandle<Vec<PeerDebugInfo>>),
+    /// force a reset of the topology layers
+    ResetTopology(ReplyHandle<()>),
+    /// replace the trusted peers given in the configuration
+    SetTrustedPeers(Vec<TrustedPeer>, ReplyHandle<()>),
 }
 
 /// Messages to the explorer task
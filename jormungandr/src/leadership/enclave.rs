@@ -4,16 +4,23 @@ use crate::{
         HeaderBft, HeaderBftBuilder, HeaderGenesisPraos, HeaderGenesisPraosBuilder,
         HeaderSetConsensusSignature, Leadership,
     },
-    secure::enclave::Enclave as SecureEnclave,
+    secure::{enclave::Enclave as SecureEnclave, SignerError},
 };
 use jormungandr_lib::interfaces::EnclaveLeaderId as LeaderId;
 use std::sync::Arc;
+use std::time::Instant;
 use thiserror::Error;
 
 #[derive(Debug, Clone, Error)]
 pub enum EnclaveError {
     #[error("This leader {id} is not in the enclave")]
     NotInEnclave { id: LeaderId },
+    #[error("The block of leader {id} could not be signed: {source}")]
+    Signing {
+        id: LeaderId,
+        #[source]
+        source: SignerError,
+    },
 }
 
 /// represent the client side of an enclave. From there we will query the
@@ -64,10 +71,9 @@ impl Enclave {
         block_builder: HeaderBftBuilder<HeaderSetConsensusSignature>,
         id: LeaderId,
     ) -> Result<HeaderBft, EnclaveError> {
-        if let Some(block) = self.inner.create_header_bft(block_builder, id).await {
-            Ok(block)
-        } else {
-            Err(EnclaveError::NotInEnclave { id })
+        match self.inner.create_header_bft(block_builder, id).await {
+            Some(header) => header.map_err(|source| EnclaveError::Signing { id, source }),
+            None => Err(EnclaveError::NotInEnclave { id }),
         }
     }
 
@@ -76,19 +82,21 @@ impl Enclave {
     ///
     /// TODO: for now we are querying the whole with the block builder but on the long
     ///       run we will only need the block signing data.
+    ///
+    /// A remote signer must answer before `deadline`, the end of the slot.
     pub async fn query_header_genesis_praos_finalize(
         &self,
         block_builder: HeaderGenesisPraosBuilder<HeaderSetConsensusSignature>,
         id: LeaderId,
+        deadline: Instant,
     ) -> Result<HeaderGenesisPraos, EnclaveError> {
-        if let Some(block) = self
+        match self
             .inner
-            .create_header_genesis_praos(block_builder, id)
+            .create_header_genesis_praos(block_builder, id, deadline)
             .await
         {
-            Ok(block)
-        } else {
-            Err(EnclaveError::NotInEnclave { id })
+            Some(header) => header.map_err(|source| EnclaveError::Signing { id, source }),
+            None => Err(EnclaveError::NotInEnclave { id }),
         }
     }
 }
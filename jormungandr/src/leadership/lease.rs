@@ -0,0 +1,318 @@
+//! hot-standby of the block producers holding the same leader keys
+//!
+//! The nodes coordinate through a lease file on a storage they share. The
+//! file names the node holding the lease and when the lease expires. Every
+//! heartbeat, the holder renews the lease and the other nodes check whether
+//! it expired, in which case they claim it by writing their own name. A
+//! claim is confirmed at the next heartbeat if the file still names the
+//! node, so of two nodes claiming the lease at the same time only the last
+//! one to write takes it over.
+//!
+//! A node only signs blocks while it holds the lease, and gives it up one
+//! heartbeat before the lease expires for the other nodes in case its
+//! renewals are late. The clocks of the nodes are compared through the
+//! expiry date, so they have to be synchronized.
+//!
+//! The lease file, possibly on a network filesystem, is read and written
+//! on a thread of its own, out of the executor threads.
+
+use crate::settings::start::Standby;
+use slog::Logger;
+use std::{
+    fs::File,
+    io::{self, BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant, SystemTime},
+};
+use tokio::prelude::*;
+use tokio_threadpool::{Builder, ThreadPool};
+
+/// The lease of the node, shared between all the clones.
+#[derive(Clone)]
+pub struct Lease {
+    lease_file: PathBuf,
+    node_name: String,
+    lease_duration: Duration,
+    heartbeat_interval: Duration,
+    state: Arc<Mutex<State>>,
+    io: Arc<ThreadPool>,
+}
+
+#[derive(Clone, Copy)]
+enum State {
+    Standby,
+    /// the lease was free and the node wrote its name in the lease file,
+    /// not confirmed yet
+    Claimed,
+    Held {
+        renewed_at: Instant,
+    },
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct LeaseRecord {
+    holder: String,
+    expires_at: jormungandr_lib::time::SystemTime,
+}
+
+impl Lease {
+    pub fn new(standby: Standby) -> Self {
+        Lease {
+            lease_file: standby.lease_file,
+            node_name: standby.node_name,
+            lease_duration: standby.lease_duration,
+            heartbeat_interval: standby.heartbeat_interval,
+            state: Arc::new(Mutex::new(State::Standby)),
+            io: Arc::new(
+                Builder::new()
+                    .pool_size(1)
+                    .name_prefix("leader-lease-file")
+                    .build(),
+            ),
+        }
+    }
+
+    pub fn heartbeat_interval(&self) -> Duration {
+        self.heartbeat_interval
+    }
+
+    /// whether the node holds the lease and can sign blocks
+    pub fn is_held(&self) -> bool {
+        self.is_held_at(Instant::now())
+    }
+
+    fn is_held_at(&self, now: Instant) -> bool {
+        match *self.state.lock().unwrap() {
+            State::Held { renewed_at } => {
+                now.duration_since(renewed_at) + self.heartbeat_interval < self.lease_duration
+            }
+            State::Standby | State::Claimed => false,
+        }
+    }
+
+    /// renew the lease held by the node, confirm its claim or claim the
+    /// lease if it expired. The lease file is accessed on the thread of
+    /// the lease, the state is only locked to be updated.
+    pub fn heartbeat(&self, logger: &Logger) -> impl Future<Item = (), Error = ()> {
+        let lease = self.clone();
+        let logger = logger.clone();
+        self.io.spawn_handle(future::lazy(move || {
+            let state = *lease.state.lock().unwrap();
+            let next_state = lease.renew(state, SystemTime::now(), Instant::now(), &logger);
+            *lease.state.lock().unwrap() = next_state;
+            Ok::<_, ()>(())
+        }))
+    }
+
+    /// the state of the node after the heartbeat in `state`, reading and
+    /// writing the lease file
+    fn renew(&self, state: State, now: SystemTime, now_instant: Instant, logger: &Logger) -> State {
+        let record = match load(&self.lease_file) {
+            Ok(record) => record,
+            Err(e) => {
+                warn!(logger, "cannot read the leader lease file"; "path" => ?self.lease_file, "reason" => %e);
+                return state;
+            }
+        };
+
+        let next_state = match transition(state, record.as_ref(), &self.node_name, now, now_instant)
+        {
+            Some(next_state) => next_state,
+            None => {
+                match state {
+                    State::Standby => {}
+                    State::Claimed | State::Held { .. } => {
+                        warn!(logger, "leader lease taken over by another node, standing by"; "holder" => record.map(|r| r.holder))
+                    }
+                }
+                return State::Standby;
+            }
+        };
+
+        let record = LeaseRecord {
+            holder: self.node_name.clone(),
+            expires_at: (now + self.lease_duration).into(),
+        };
+        if let Err(e) = save(&self.lease_file, &self.node_name, &record) {
+            warn!(logger, "cannot write the leader lease file"; "path" => ?self.lease_file, "reason" => %e);
+            return state;
+        }
+
+        match (state, next_state) {
+            (State::Claimed, State::Held { .. }) => {
+                info!(logger, "leader lease taken, the node now signs blocks")
+            }
+            (State::Standby, State::Claimed) | (State::Held { .. }, State::Claimed) => {
+                debug!(logger, "leader lease claimed")
+            }
+            _ => {}
+        }
+        next_state
+    }
+}
+
+/// the state the node moves to once it wrote its name in the lease file,
+/// or `None` if the lease is held by another node and the node stands by
+fn transition(
+    state: State,
+    record: Option<&LeaseRecord>,
+    node_name: &str,
+    now: SystemTime,
+    now_instant: Instant,
+) -> Option<State> {
+    match record {
+        Some(record) if record.holder != node_name && *record.expires_at.as_ref() > now => None,
+        Some(record) if record.holder == node_name => match state {
+            State::Claimed | State::Held { .. } => Some(State::Held {
+                renewed_at: now_instant,
+            }),
+            // a lease left by a previous run of the node
+            State::Standby => Some(State::Claimed),
+        },
+        _ => Some(State::Claimed),
+    }
+}
+
+fn load(path: &Path) -> io::Result<Option<LeaseRecord>> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    serde_json::from_reader(BufReader::new(file))
+        .map(Some)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+/// the lease is written to a scratch file of the node renamed once
+/// complete, so the other nodes never read a partial lease
+fn save(path: &Path, node_name: &str, record: &LeaseRecord) -> io::Result<()> {
+    let mut scratch = path.as_os_str().to_owned();
+    scratch.push(format!(".{}.new", node_name));
+    let scratch = PathBuf::from(scratch);
+
+    let mut writer = BufWriter::new(File::create(&scratch)?);
+    serde_json::to_writer(&mut writer, record)
+        .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+    writer.flush()?;
+    std::mem::drop(writer);
+    std::fs::rename(&scratch, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LEASE_DURATION: Duration = Duration::from_secs(30);
+    const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(5);
+
+    fn lease_file(name: &str) -> PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("jormungandr-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        path
+    }
+
+    fn lease(lease_file: &Path, node_name: &str) -> Lease {
+        Lease::new(Standby {
+            lease_file: lease_file.to_owned(),
+            node_name: node_name.to_owned(),
+            lease_duration: LEASE_DURATION,
+            heartbeat_interval: HEARTBEAT_INTERVAL,
+        })
+    }
+
+    fn beat_at(lease: &Lease, now: SystemTime) {
+        let logger = Logger::root(slog::Discard, o!());
+        let state = *lease.state.lock().unwrap();
+        let next_state = lease.renew(state, now, Instant::now(), &logger);
+        *lease.state.lock().unwrap() = next_state;
+    }
+
+    fn beat(lease: &Lease) {
+        beat_at(lease, SystemTime::now())
+    }
+
+    fn state(lease: &Lease) -> &'static str {
+        match *lease.state.lock().unwrap() {
+            State::Standby => "standby",
+            State::Claimed => "claimed",
+            State::Held { .. } => "held",
+        }
+    }
+
+    #[test]
+    fn free_lease_is_claimed_then_held() {
+        let path = lease_file("lease-claimed-then-held");
+        let node = lease(&path, "node-a");
+
+        assert_eq!(state(&node), "standby");
+        beat(&node);
+        assert_eq!(state(&node), "claimed");
+        assert!(!node.is_held());
+        beat(&node);
+        assert_eq!(state(&node), "held");
+        assert!(node.is_held());
+        beat(&node);
+        assert_eq!(state(&node), "held");
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn lease_of_another_node_is_not_claimed() {
+        let path = lease_file("lease-of-another-node");
+        let holder = lease(&path, "node-a");
+        let standby = lease(&path, "node-b");
+
+        beat(&holder);
+        beat(&standby);
+        assert_eq!(state(&standby), "standby");
+        beat(&holder);
+        assert_eq!(state(&holder), "held");
+        beat(&standby);
+        assert_eq!(state(&standby), "standby");
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn expired_lease_is_taken_over() {
+        let path = lease_file("lease-taken-over");
+        let holder = lease(&path, "node-a");
+        let standby = lease(&path, "node-b");
+        let now = SystemTime::now();
+
+        beat_at(&holder, now);
+        beat_at(&holder, now);
+        assert_eq!(state(&holder), "held");
+
+        // the holder stopped renewing the lease
+        let later = now + LEASE_DURATION + Duration::from_secs(1);
+        beat_at(&standby, later);
+        assert_eq!(state(&standby), "claimed");
+        beat_at(&standby, later);
+        assert_eq!(state(&standby), "held");
+
+        // the holder comes back and finds the lease taken
+        beat_at(&holder, later);
+        assert_eq!(state(&holder), "standby");
+        assert!(!holder.is_held());
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn held_lease_is_given_up_a_heartbeat_before_expiry() {
+        let node = lease(&lease_file("lease-given-up"), "node-a");
+        let renewed_at = Instant::now();
+        *node.state.lock().unwrap() = State::Held { renewed_at };
+
+        assert!(node.is_held_at(renewed_at));
+        assert!(node.is_held_at(renewed_at + LEASE_DURATION - HEARTBEAT_INTERVAL * 2));
+        assert!(!node.is_held_at(renewed_at + LEASE_DURATION - HEARTBEAT_INTERVAL));
+        assert!(!node.is_held_at(renewed_at + LEASE_DURATION));
+    }
+}
@@ -1,9 +1,25 @@
 use futures03::future::poll_fn;
 pub use jormungandr_lib::interfaces::LeadershipLogStatus;
 use jormungandr_lib::interfaces::{LeadershipLog, LeadershipLogId};
-use std::{sync::Arc, time::Duration};
+use slog::Logger;
+use std::{
+    collections::VecDeque,
+    fs::{File, OpenOptions},
+    io::{self, BufRead, BufReader, BufWriter, Write},
+    path::PathBuf,
+    sync::{
+        mpsc::{self, Receiver, SyncSender, TrySendError},
+        Arc,
+    },
+    thread,
+    time::Duration,
+};
 use tokio02::{sync::RwLock, time};
 
+/// number of the updates waiting to be written to the archive file, the
+/// logs archived in excess are only kept in memory
+const MAX_PENDING_FILE_UPDATES: usize = 1024;
+
 /// all leadership logs, allow for following up on the different entity
 /// of the blockchain
 #[derive(Clone)]
@@ -59,9 +75,10 @@ impl Logs {
     /// the `ttl` can be any sensible value the user will see appropriate. The log will
     /// live at least its scheduled time + `ttl`.
     ///
-    /// On changes, the log's TTL will be reset to this `ttl`.
-    pub fn new(ttl: Duration) -> Self {
-        Logs(Arc::new(RwLock::new(internal::Logs::new(ttl))))
+    /// On changes, the log's TTL will be reset to this `ttl`. The logs
+    /// reaching the end of their TTL are moved to the `archive`, if any.
+    pub fn new(ttl: Duration, archive: Option<Archive>) -> Self {
+        Logs(Arc::new(RwLock::new(internal::Logs::new(ttl, archive))))
     }
 
     pub async fn insert(&self, log: LeadershipLog) -> Result<LeadershipLogHandle, ()> {
@@ -97,15 +114,186 @@ impl Logs {
         poll_fn(move |mut cx| guard.poll_purge(&mut cx)).await
     }
 
-    pub async fn logs(&self) -> Vec<LeadershipLog> {
+    /// a page of the logs still within their TTL and the archived ones
+    /// matching `filter`, ordered by their scheduled date. Only the logs
+    /// of the page are cloned.
+    pub async fn logs<F>(&self, filter: F, offset: usize, limit: usize) -> Vec<LeadershipLog>
+    where
+        F: Fn(&LeadershipLog) -> bool,
+    {
         let inner = self.0.clone();
         let guard = inner.read().await;
-        guard.logs().cloned().collect()
+        let mut logs: Vec<&LeadershipLog> = guard.logs().filter(|log| filter(log)).collect();
+        logs.sort_by_key(|log| (*log.scheduled_at_date(), *log.enclave_leader_id()));
+        logs.into_iter().skip(offset).take(limit).cloned().collect()
+    }
+}
+
+/// The leadership logs past their TTL, kept for a number of epochs and
+/// appended, one JSON object per line, to a file in the storage directory
+/// if the node has one. The file is written by a thread of its own, out
+/// of the lock of the logs.
+pub struct Archive {
+    logs: VecDeque<LeadershipLog>,
+    retention_epochs: u32,
+    latest_epoch: u32,
+    file_updates: Option<SyncSender<FileUpdate>>,
+    logger: Logger,
+}
+
+enum FileUpdate {
+    Append(LeadershipLog),
+    /// drop the logs of the epochs before the given one
+    Prune(u32),
+}
+
+impl Archive {
+    /// create an archive that is not persisted
+    pub fn memory(retention_epochs: u32, logger: Logger) -> Self {
+        Archive {
+            logs: VecDeque::new(),
+            retention_epochs,
+            latest_epoch: 0,
+            file_updates: None,
+            logger,
+        }
+    }
+
+    /// open (or create) the archive file at `path` and load the logs
+    /// already archived. Lines that cannot be parsed are skipped with a
+    /// warning.
+    pub fn open(path: PathBuf, retention_epochs: u32, logger: Logger) -> io::Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .read(true)
+            .append(true)
+            .open(&path)?;
+
+        let mut archive = Archive::memory(retention_epochs, logger);
+        for (line_number, line) in BufReader::new(&file).lines().enumerate() {
+            let line = line?;
+            if line.is_empty() {
+                continue;
+            }
+            match serde_json::from_str(&line) {
+                Ok(log) => archive.insert(log),
+                Err(err) => warn!(
+                    archive.logger,
+                    "skipping invalid leadership log record";
+                    "file" => ?path,
+                    "line" => line_number + 1,
+                    "reason" => %err,
+                ),
+            }
+        }
+        let (sender, receiver) = mpsc::sync_channel(MAX_PENDING_FILE_UPDATES);
+        let logger = archive.logger.clone();
+        thread::Builder::new()
+            .name("leadership-logs-archive".into())
+            .spawn(move || write_file_updates(path, file, receiver, logger))?;
+        archive.file_updates = Some(sender);
+        archive.prune();
+        Ok(archive)
+    }
+
+    fn push(&mut self, log: LeadershipLog) {
+        if self.file_updates.is_some() {
+            self.send(FileUpdate::Append(log.clone()));
+        }
+        let latest_epoch = self.latest_epoch;
+        self.insert(log);
+        if self.latest_epoch > latest_epoch {
+            self.prune();
+        }
+    }
+
+    fn insert(&mut self, log: LeadershipLog) {
+        self.latest_epoch = std::cmp::max(self.latest_epoch, epoch_of(&log));
+        self.logs.push_back(log);
+    }
+
+    // drop the logs older than the retained epochs, and have the file
+    // rewritten without them
+    fn prune(&mut self) {
+        let oldest_epoch = (self.latest_epoch + 1).saturating_sub(self.retention_epochs);
+        let len = self.logs.len();
+        self.logs.retain(|log| epoch_of(log) >= oldest_epoch);
+        if self.logs.len() != len && self.file_updates.is_some() {
+            self.send(FileUpdate::Prune(oldest_epoch));
+        }
+    }
+
+    fn send(&self, update: FileUpdate) {
+        let sent = match &self.file_updates {
+            Some(file_updates) => file_updates.try_send(update),
+            None => return,
+        };
+        match sent {
+            Ok(()) => {}
+            Err(TrySendError::Full(_)) => warn!(
+                self.logger,
+                "the leadership log archive file is behind, the log is only kept in memory"
+            ),
+            Err(TrySendError::Disconnected(_)) => warn!(
+                self.logger,
+                "the leadership log archive file is not written anymore"
+            ),
+        }
+    }
+}
+
+fn epoch_of(log: &LeadershipLog) -> u32 {
+    log.scheduled_at_date().as_ref().epoch
+}
+
+// Runs on the thread of the archive file until the archive is dropped.
+fn write_file_updates(
+    path: PathBuf,
+    mut file: File,
+    updates: Receiver<FileUpdate>,
+    logger: Logger,
+) {
+    for update in updates {
+        let result = match update {
+            FileUpdate::Append(log) => serde_json::to_string(&log)
+                .map_err(io::Error::from)
+                .and_then(|line| writeln!(file, "{}", line)),
+            FileUpdate::Prune(oldest_epoch) => {
+                rewrite(&path, oldest_epoch).map(|rewritten| file = rewritten)
+            }
+        };
+        if let Err(err) = result {
+            warn!(logger, "cannot write the leadership log archive"; "path" => ?path, "reason" => %err);
+        }
+    }
+}
+
+// write the logs of the archive from `oldest_epoch` to a scratch file
+// renamed over the archive, returning the archive opened for appending
+fn rewrite(path: &PathBuf, oldest_epoch: u32) -> io::Result<File> {
+    let mut scratch = path.as_os_str().to_owned();
+    scratch.push(".new");
+    let scratch = PathBuf::from(scratch);
+
+    let reader = BufReader::new(File::open(path)?);
+    let mut writer = BufWriter::new(File::create(&scratch)?);
+    for line in reader.lines() {
+        let line = line?;
+        // the invalid records have been reported on load
+        let retained = serde_json::from_str::<LeadershipLog>(&line)
+            .map_or(false, |log| epoch_of(&log) >= oldest_epoch);
+        if retained {
+            writeln!(writer, "{}", line)?;
+        }
     }
+    writer.flush()?;
+    std::mem::drop(writer);
+    std::fs::rename(&scratch, path)?;
+    OpenOptions::new().append(true).open(path)
 }
 
 pub(super) mod internal {
-    use super::{LeadershipLog, LeadershipLogId, LeadershipLogStatus};
+    use super::{Archive, LeadershipLog, LeadershipLogId, LeadershipLogStatus};
     use futures03::{
         task::{Context, Poll},
         Stream,
@@ -121,14 +309,16 @@ pub(super) mod internal {
         entries: HashMap<LeadershipLogId, (LeadershipLog, delay_queue::Key)>,
         expirations: Pin<Box<DelayQueue<LeadershipLogId>>>,
         ttl: Duration,
+        archive: Option<Archive>,
     }
 
     impl Logs {
-        pub fn new(ttl: Duration) -> Self {
+        pub fn new(ttl: Duration, archive: Option<Archive>) -> Self {
             Logs {
                 entries: HashMap::new(),
                 expirations: Box::pin(DelayQueue::new()),
                 ttl,
+                archive,
             }
         }
 
@@ -193,7 +383,10 @@ pub(super) mod internal {
             loop {
                 match self.expirations.as_mut().poll_next(cx) {
                     Poll::Ready(Some(Ok(entry))) => {
-                        self.entries.remove(entry.get_ref());
+                        let removed = self.entries.remove(entry.get_ref());
+                        if let (Some(archive), Some((log, _))) = (&mut self.archive, removed) {
+                            archive.push(log);
+                        }
                     }
                     Poll::Ready(Some(Err(e))) => return Poll::Ready(Err(e)),
                     Poll::Ready(None) => return Poll::Ready(Ok(())),
@@ -203,7 +396,8 @@ pub(super) mod internal {
         }
 
         pub fn logs<'a>(&'a self) -> impl Iterator<Item = &'a LeadershipLog> {
-            self.entries.values().map(|(v, _)| v)
+            let archived = self.archive.iter().flat_map(|archive| archive.logs.iter());
+            self.entries.values().map(|(v, _)| v).chain(archived)
         }
     }
 }
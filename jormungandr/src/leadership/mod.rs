@@ -58,9 +58,17 @@
 //!
 
 mod enclave;
+mod lease;
 mod logs;
+mod preview;
 mod process;
+mod slots;
+mod stats;
 
 pub use self::enclave::{Enclave, EnclaveError, LeaderEvent};
-pub use self::logs::{LeadershipLogHandle, Logs};
+pub use self::lease::Lease;
+pub use self::logs::{Archive, LeadershipLogHandle, Logs};
+pub use self::preview::upcoming_events;
 pub use self::process::Module;
+pub use self::slots::LeaderSlots;
+pub use self::stats::{EpochStats, MissReason, MissedBlocks, Stats};
@@ -0,0 +1,65 @@
+use crate::{
+    blockcfg::{ConsensusVersion, Leadership},
+    blockchain::Ref,
+    leadership::{Enclave, EnclaveError, LeaderEvent},
+};
+use chain_time::{
+    era::{EpochPosition, EpochSlotOffset},
+    Epoch,
+};
+use jormungandr_lib::time::SystemTime;
+use std::sync::Arc;
+
+/// The leader events of the enclave still to come in the epoch of the tip
+/// and, once its schedule no longer depends on the blocks to come, in the
+/// next epoch, along with the time of their slot. The events are computed
+/// the same way the leadership task schedules them.
+///
+/// Under Genesis Praos, the schedule of the next epoch is computed from the
+/// ledger state at the end of the epoch before the one of the tip, known as
+/// soon as the tip enters its epoch. The schedule computed here is kept in
+/// the `Ref` of that state for the epoch transition to use.
+pub async fn upcoming_events(
+    enclave: &Enclave,
+    tip: Arc<Ref>,
+) -> Result<Vec<(LeaderEvent, SystemTime)>, EnclaveError> {
+    let now = SystemTime::now();
+    let mut schedules = vec![tip.epoch_leadership_schedule().clone()];
+    schedules.extend(next_epoch_leadership(&tip));
+
+    let mut events = Vec::new();
+    for leadership in schedules {
+        let era = leadership.era();
+        let nb_slots = era.slots_per_epoch();
+        for event in enclave
+            .query_schedules(leadership.clone(), 0, nb_slots)
+            .await?
+        {
+            let slot = era.from_era_to_slot(EpochPosition {
+                epoch: Epoch(event.date.epoch),
+                slot: EpochSlotOffset(event.date.slot_id),
+            });
+            let slot_time = match tip.time_frame().slot_to_systemtime(slot) {
+                Some(slot_time) => SystemTime::from(slot_time),
+                None => continue,
+            };
+            if slot_time >= now {
+                events.push((event, slot_time));
+            }
+        }
+    }
+    Ok(events)
+}
+
+// the schedule of the epoch following the one of `tip`, if it can be
+// computed before the tip enters that epoch
+fn next_epoch_leadership(tip: &Ref) -> Option<Arc<Leadership>> {
+    let next_epoch = tip.block_date().epoch + 1;
+    match tip.ledger().consensus_version() {
+        ConsensusVersion::GenesisPraos => tip
+            .last_ref_previous_epoch()
+            .map(|epoch_state| epoch_state.leadership_from_state(next_epoch)),
+        // the BFT leaders take turns in a fixed order
+        ConsensusVersion::Bft => Some(Arc::new(Leadership::new(next_epoch, tip.ledger()))),
+    }
+}
@@ -4,11 +4,12 @@ use crate::{
         Ledger, LedgerParameters,
     },
     blockchain::{new_epoch_leadership_from, Ref, Tip},
+    clock_check::ClockSkew,
     fragment,
     intercom::BlockMsg,
     leadership::{
         enclave::{Enclave, EnclaveError, LeaderEvent},
-        LeadershipLogHandle, Logs,
+        LeaderSlots, LeadershipLogHandle, Lease, Logs, MissReason, Stats,
     },
     utils::{async_msg::MessageBox, task::TokioServiceInfo},
 };
@@ -28,7 +29,7 @@ use std::{
     time::{Duration, Instant},
 };
 use thiserror::Error;
-use tokio02::time::{delay_until, timeout_at, Instant as TokioInstant};
+use tokio02::time::{delay_for, delay_until, timeout_at, Instant as TokioInstant};
 
 #[derive(Error, Debug)]
 pub enum LeadershipError {
@@ -74,6 +75,10 @@ pub struct Module {
     pool: fragment::Pool,
     enclave: Enclave,
     block_message: MessageBox<BlockMsg>,
+    leader_slots: LeaderSlots,
+    stats: Stats,
+    lease: Option<Lease>,
+    clock_guard: Option<ClockSkew>,
 }
 
 impl Module {
@@ -85,18 +90,37 @@ impl Module {
         pool: fragment::Pool,
         enclave: Enclave,
         block_message: MessageBox<BlockMsg>,
+        leader_slots: LeaderSlots,
+        stats: Stats,
+        lease: Option<Lease>,
+        clock_guard: Option<ClockSkew>,
     ) -> Result<Self, LeadershipError> {
         let logs_to_purge = logs.clone();
+        let gc_leader_slots = leader_slots.clone();
 
         service_info.run_periodic(
             "garbage collection",
             garbage_collection_interval,
             move || {
                 let mut logs_to_purge_local = logs_to_purge.clone();
-                Box::pin(async move { logs_to_purge_local.poll_purge().await }).compat()
+                let busy_for = gc_leader_slots.busy_for();
+                Box::pin(async move {
+                    if let Some(duration) = busy_for {
+                        delay_for(duration).await;
+                    }
+                    logs_to_purge_local.poll_purge().await
+                })
+                .compat()
             },
         );
 
+        if let Some(lease) = lease.clone() {
+            let logger = service_info.logger().clone();
+            service_info.run_periodic("leader lease", lease.heartbeat_interval(), move || {
+                lease.heartbeat(&logger)
+            });
+        }
+
         tip.get_ref().compat().await.map(move |tip_ref| Self {
             schedule: Schedule::default(),
             service_info,
@@ -106,6 +130,10 @@ impl Module {
             pool,
             enclave,
             block_message,
+            leader_slots,
+            stats,
+            lease,
+            clock_guard,
         })
     }
 
@@ -308,6 +336,8 @@ impl Module {
                 logger,
                 "Eek... Too late, we missed an event schedule, system time might be off?"
             );
+            self.stats
+                .block_missed(entry.event.date.epoch, MissReason::Late);
 
             entry
                 .log
@@ -348,6 +378,7 @@ impl Module {
         event_end: SystemTime,
     ) -> Result<Self, LeadershipError> {
         let event_logs = entry.log.clone();
+        let epoch = entry.event.date.epoch;
         let now = SystemTime::now();
 
         // we can safely unwrap here as we just proved that `now <= event_end`
@@ -371,7 +402,7 @@ impl Module {
 
         let res = timeout_at(
             TokioInstant::from_std(deadline),
-            self.action_run_entry_build_block(entry, logger),
+            self.action_run_entry_build_block(entry, deadline, logger),
         )
         .await;
 
@@ -379,6 +410,7 @@ impl Module {
             Ok(future_res) => future_res,
             Err(timeout_error) => {
                 error!(timed_out_log, "Eek... took too long to process the event..." ; "reason" => %timeout_error);
+                self.stats.block_missed(epoch, MissReason::Late);
                 event_logs
                     .set_status(LeadershipLogStatus::Rejected {
                         reason: "Failed to compute the schedule within time boundaries".to_owned(),
@@ -392,11 +424,45 @@ impl Module {
     async fn action_run_entry_build_block(
         &self,
         entry: Entry,
+        deadline: Instant,
         logger: Logger,
     ) -> Result<(), LeadershipError> {
         let event = entry.event;
         let event_logs = entry.log;
 
+        if let Some(lease) = &self.lease {
+            if !lease.is_held() {
+                info!(
+                    logger,
+                    "standing by, the leader lease is held by another node"
+                );
+                event_logs
+                    .set_status(LeadershipLogStatus::Rejected {
+                        reason: "Standing by: the leader lease is not held by this node".to_owned(),
+                    })
+                    .await;
+                return Ok(());
+            }
+        }
+
+        if let Some(clock_skew) = &self.clock_guard {
+            if clock_skew.exceeded() {
+                warn!(
+                    logger,
+                    "not signing the block, the system clock is skewed from the NTP servers"
+                );
+                self.stats
+                    .block_missed(event.date.epoch, MissReason::ClockSkew);
+                event_logs
+                    .set_status(LeadershipLogStatus::Rejected {
+                        reason: "Clock skew: the system clock is off the clocks of the NTP servers"
+                            .to_owned(),
+                    })
+                    .await;
+                return Ok(());
+            }
+        }
+
         let enclave = self.enclave.clone();
         let sender = self.block_message.clone();
         let pool = self.pool.clone();
@@ -430,6 +496,8 @@ impl Module {
                         reason: "Not computing this schedule because of invalid state against the network blockchain".to_owned()
                     }
                 ).await;
+            self.stats
+                .block_missed(event.date.epoch, MissReason::NotSynced);
 
             return Ok(());
         };
@@ -485,7 +553,7 @@ impl Module {
                         .expect("Valid Header Builder")
                         .set_consensus_data(&node_id, &vrfproof.into());
                     enclave
-                        .query_header_genesis_praos_finalize(final_builder, event.id)
+                        .query_header_genesis_praos_finalize(final_builder, event.id, deadline)
                         .map_ok(|h| {
                             Some(Block {
                                 header: h.generalize(),
@@ -507,6 +575,7 @@ impl Module {
 
         match signing {
             Ok(maybe_block) => {
+                let epoch = event.date.epoch;
                 if let Some(block) = maybe_block {
                     let id = block.header.hash();
                     let chain_length: u32 = block.header.chain_length().into();
@@ -521,7 +590,11 @@ impl Module {
                             chain_length,
                         })
                         .await;
-                };
+                    self.stats.block_produced(epoch);
+                } else {
+                    // the status of the log is set with the signing error
+                    self.stats.block_missed(epoch, MissReason::Error);
+                }
                 Ok(())
             }
             Err(e) => Err(e),
@@ -601,13 +674,20 @@ impl Module {
             let epoch = Epoch(schedule.date.epoch);
             let slot = EpochSlotOffset(schedule.date.slot_id);
             let scheduled_at_time = module.slot_time(epoch, slot);
+            module.leader_slots.insert(
+                scheduled_at_time,
+                module.event_following_slot_time(&schedule),
+            );
             let log = LeadershipLog::new(schedule.id, schedule.date.into(), scheduled_at_time);
 
             match module.logs.insert(log).await {
-                Ok(log) => module.schedule.push(Entry {
-                    event: schedule,
-                    log,
-                }),
+                Ok(log) => {
+                    module.stats.slot_assigned(epoch.0);
+                    module.schedule.push(Entry {
+                        event: schedule,
+                        log,
+                    })
+                }
                 Err(()) => return Err(LeadershipError::CannotUpdateLogs),
             }
         }
@@ -644,9 +724,9 @@ async fn prepare_block(
     ledger: Arc<Ledger>,
     epoch_parameters: Arc<LedgerParameters>,
 ) -> Result<Contents, LeadershipError> {
-    use crate::fragment::selection::{FragmentSelectionAlgorithm as _, OldestFirst};
+    use crate::fragment::selection::{FragmentSelectionAlgorithm as _, HighestPriorityFirst};
 
-    let selection_algorithm = OldestFirst::new();
+    let selection_algorithm = HighestPriorityFirst::new();
     fragment_pool
         .select(
             ledger.as_ref().clone(),
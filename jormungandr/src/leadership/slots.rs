@@ -0,0 +1,66 @@
+use futures::future::{self, Either, Future};
+use jormungandr_lib::time::SystemTime;
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use tokio::timer::{self, Delay};
+
+/// maintenance work is not started if one of our leader slots starts
+/// within this time
+const LEADER_SLOT_GUARD: Duration = Duration::from_secs(1);
+
+/// the time windows of the slots the node is scheduled to be leader of.
+///
+/// The leadership module records the slots as it schedules its events,
+/// so the maintenance tasks (garbage collection of the pool, of the caches,
+/// etc.) can wait for these slots to be over before running, instead of
+/// competing with the block creation for the locks and the CPU.
+#[derive(Clone, Default)]
+pub struct LeaderSlots {
+    windows: Arc<Mutex<BTreeMap<std::time::SystemTime, std::time::SystemTime>>>,
+}
+
+impl LeaderSlots {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// record a leader slot starting at `start` and ending at `end`
+    pub fn insert(&self, start: SystemTime, end: SystemTime) {
+        let now = std::time::SystemTime::now();
+        let mut windows = self.windows.lock().unwrap();
+        windows.retain(|_, end| *end > now);
+        windows.insert(*start.as_ref(), *end.as_ref());
+    }
+
+    /// how long to wait for the node to be done with its leader slots,
+    /// `None` if there is no slot happening now or starting soon.
+    pub fn busy_for(&self) -> Option<Duration> {
+        let now = std::time::SystemTime::now();
+        let windows = self.windows.lock().unwrap();
+        let mut busy_until = now;
+        for (start, end) in windows.iter() {
+            if *end <= busy_until {
+                continue;
+            }
+            if *start > busy_until + LEADER_SLOT_GUARD {
+                break;
+            }
+            busy_until = *end;
+        }
+        busy_until
+            .duration_since(now)
+            .ok()
+            .filter(|d| *d > Duration::from_secs(0))
+    }
+
+    /// a future resolving once the node is not busy with its leader slots
+    pub fn wait_idle(&self) -> impl Future<Item = (), Error = timer::Error> {
+        match self.busy_for() {
+            None => Either::A(future::ok(())),
+            Some(duration) => Either::B(Delay::new(Instant::now() + duration)),
+        }
+    }
+}
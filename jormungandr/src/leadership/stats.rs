@@ -0,0 +1,89 @@
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex},
+};
+
+/// number of the latest epochs the statistics are kept for
+const MAX_EPOCHS: usize = 64;
+
+/// Counters of the leader events of the node, by epoch, shared between
+/// all the clones.
+#[derive(Clone, Default)]
+pub struct Stats(Arc<Mutex<BTreeMap<u32, EpochStats>>>);
+
+/// Counters of the leader events of an epoch.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct EpochStats {
+    pub epoch: u32,
+    /// number of the slots the node was elected leader of
+    pub slots_assigned: u32,
+    /// number of the blocks created and sent to the blockchain task
+    pub blocks_produced: u32,
+    pub blocks_missed: MissedBlocks,
+}
+
+/// Number of the leader slots no block was created for, by reason.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct MissedBlocks {
+    /// the tip was already at or past the slot of the event
+    pub not_synced: u32,
+    /// the event was processed too late or could not complete before the
+    /// end of its slot
+    pub late: u32,
+    /// the block could not be signed
+    pub error: u32,
+    /// the block was not signed as the system clock was skewed
+    pub clock_skew: u32,
+}
+
+/// Why no block was created for a leader slot.
+#[derive(Debug, Clone, Copy)]
+pub enum MissReason {
+    NotSynced,
+    Late,
+    Error,
+    ClockSkew,
+}
+
+impl Stats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn slot_assigned(&self, epoch: u32) {
+        self.update(epoch, |stats| stats.slots_assigned += 1)
+    }
+
+    pub fn block_produced(&self, epoch: u32) {
+        self.update(epoch, |stats| stats.blocks_produced += 1)
+    }
+
+    pub fn block_missed(&self, epoch: u32, reason: MissReason) {
+        self.update(epoch, |stats| {
+            let missed = &mut stats.blocks_missed;
+            match reason {
+                MissReason::NotSynced => missed.not_synced += 1,
+                MissReason::Late => missed.late += 1,
+                MissReason::Error => missed.error += 1,
+                MissReason::ClockSkew => missed.clock_skew += 1,
+            }
+        })
+    }
+
+    /// the counters of the latest epochs, the oldest first
+    pub fn epochs(&self) -> Vec<EpochStats> {
+        self.0.lock().unwrap().values().cloned().collect()
+    }
+
+    fn update(&self, epoch: u32, f: impl FnOnce(&mut EpochStats)) {
+        let mut epochs = self.0.lock().unwrap();
+        f(epochs.entry(epoch).or_insert_with(|| EpochStats {
+            epoch,
+            ..EpochStats::default()
+        }));
+        while epochs.len() > MAX_EPOCHS {
+            let oldest = *epochs.keys().next().unwrap();
+            epochs.remove(&oldest);
+        }
+    }
+}
@@ -1,6 +1,7 @@
 // Rustc default type_length_limit is too low for complex futures, which generate deeply nested
 // monomorphized structured with long signatures. This value is enough for current project.
 #![type_length_limit = "10000000"]
+#![cfg_attr(all(test, feature = "with-bench"), feature(test))]
 
 #[macro_use]
 extern crate error_chain;
@@ -20,15 +21,17 @@ extern crate slog_gelf;
 extern crate slog_journald;
 #[cfg(unix)]
 extern crate slog_syslog;
+#[cfg(all(test, feature = "with-bench"))]
+extern crate test;
 
 use crate::{
-    blockcfg::{HeaderHash, Leader},
+    blockcfg::HeaderHash,
     blockchain::Blockchain,
     diagnostic::Diagnostic,
     network::p2p::P2pTopology,
-    secure::enclave::Enclave,
+    secure::enclave::{Enclave, EnclaveLeader},
     settings::start::Settings,
-    utils::{async_msg, task::Services},
+    utils::{async_msg, bandwidth::BandwidthLimiter, rate_limit::RateLimiter, task::Services},
 };
 use futures03::{executor::block_on, future::TryFutureExt};
 use jormungandr_lib::interfaces::NodeState;
@@ -36,9 +39,12 @@ use settings::{start::RawSettings, CommandLine};
 use slog::Logger;
 use std::time::Duration;
 
+#[cfg(all(test, feature = "with-bench"))]
+mod bench;
 pub mod blockcfg;
 pub mod blockchain;
 pub mod client;
+pub mod clock_check;
 pub mod diagnostic;
 pub mod explorer;
 pub mod fragment;
@@ -49,11 +55,13 @@ pub mod network;
 pub mod rest;
 pub mod secure;
 pub mod settings;
+pub mod snapshot;
 pub mod start_up;
 pub mod state;
 mod stats_counter;
 pub mod stuck_notifier;
 pub mod utils;
+pub mod webhooks;
 
 use stats_counter::StatsCounter;
 
@@ -75,6 +83,7 @@ pub struct BootstrappedNode {
     rest_context: Option<rest::Context>,
     services: Services,
     diagnostic: Diagnostic,
+    leaders: Vec<EnclaveLeader>,
 }
 
 const BLOCK_TASK_QUEUE_LEN: usize = 32;
@@ -88,6 +97,13 @@ fn start_services(bootstrapped_node: BootstrappedNode) -> Result<(), start_up::E
     }
 
     let mut services = bootstrapped_node.services;
+    let read_only = bootstrapped_node.settings.read_only;
+    if read_only {
+        info!(
+            bootstrapped_node.logger,
+            "read-only mode: the network, leadership, chain snapshot and stuck notifier tasks are not started"
+        );
+    }
 
     // initialize the network propagation channel
     let (network_msgbox, network_queue) = async_msg::channel(NETWORK_TASK_QUEUE_LEN);
@@ -95,10 +111,35 @@ fn start_services(bootstrapped_node: BootstrappedNode) -> Result<(), start_up::E
     let (fragment_msgbox, fragment_queue) = async_msg::channel(FRAGMENT_TASK_QUEUE_LEN);
     let blockchain_tip = bootstrapped_node.blockchain_tip;
     let blockchain = bootstrapped_node.blockchain;
-    let leadership_logs =
-        leadership::Logs::new(bootstrapped_node.settings.leadership.log_ttl.into());
+    let leadership_log_archive = match (
+        bootstrapped_node.settings.leadership.log_retention_epochs,
+        bootstrapped_node.settings.leadership_log_file.clone(),
+    ) {
+        (None, _) => None,
+        (Some(epochs), path) => {
+            let logger = bootstrapped_node
+                .logger
+                .new(o!(log::KEY_TASK => "leadership"));
+            match path.filter(|_| !read_only) {
+                Some(path) => Some(leadership::Archive::open(path, epochs, logger).map_err(
+                    |source| start_up::Error::IO {
+                        source,
+                        reason: start_up::ErrorKind::LeadershipLogs,
+                    },
+                )?),
+                None => Some(leadership::Archive::memory(epochs, logger)),
+            }
+        }
+    };
+    let leadership_logs = leadership::Logs::new(
+        bootstrapped_node.settings.leadership.log_ttl.into(),
+        leadership_log_archive,
+    );
     let leadership_garbage_collection_interval =
         bootstrapped_node.settings.leadership.log_ttl.into();
+    let leader_slots = leadership::LeaderSlots::new();
+    let clock_skew = clock_check::ClockSkew::new();
+    let leadership_stats = leadership::Stats::new();
 
     let topology = P2pTopology::new(
         &bootstrapped_node.settings.network,
@@ -109,11 +150,26 @@ fn start_services(bootstrapped_node: BootstrappedNode) -> Result<(), start_up::E
 
     let stats_counter = StatsCounter::default();
 
+    // the node rejects the fragments in read-only mode
+    let mempool_file = bootstrapped_node
+        .settings
+        .mempool_file
+        .clone()
+        .filter(|_| !read_only);
+
     let (fragment_pool, pool_logs) = {
         let stats_counter = stats_counter.clone();
         let process = fragment::Process::new(
             bootstrapped_node.settings.mempool.pool_max_entries.into(),
+            bootstrapped_node.settings.mempool.pool_max_bytes.into(),
+            bootstrapped_node.settings.mempool.overflow_policy,
             bootstrapped_node.settings.mempool.fragment_ttl.into(),
+            bootstrapped_node
+                .settings
+                .mempool
+                .fragment_max_retries
+                .into(),
+            bootstrapped_node.settings.mempool.priority,
             bootstrapped_node.settings.mempool.log_max_entries.into(),
             bootstrapped_node.settings.mempool.log_ttl.into(),
             bootstrapped_node
@@ -121,7 +177,19 @@ fn start_services(bootstrapped_node: BootstrappedNode) -> Result<(), start_up::E
                 .mempool
                 .garbage_collection_interval
                 .into(),
+            fragment::PendingPropagation {
+                delay: bootstrapped_node.settings.mempool.rebroadcast_delay.into(),
+                max_fragments: bootstrapped_node
+                    .settings
+                    .mempool
+                    .rebroadcast_max_fragments
+                    .into(),
+                gossip_interval: bootstrapped_node.settings.network.gossip_interval,
+            },
             network_msgbox.clone(),
+            leader_slots.clone(),
+            blockchain_tip.clone(),
+            mempool_file.clone(),
         );
 
         let pool = process.pool().clone();
@@ -163,6 +231,7 @@ fn start_services(bootstrapped_node: BootstrappedNode) -> Result<(), start_up::E
         // TODO: we should get this value from the configuration
         let block_cache_ttl: Duration = Duration::from_secs(120);
         let stats_counter = stats_counter.clone();
+        let leader_slots = leader_slots.clone();
         services.spawn_future("block", move |info| {
             let process = blockchain::Process {
                 blockchain,
@@ -172,6 +241,7 @@ fn start_services(bootstrapped_node: BootstrappedNode) -> Result<(), start_up::E
                 fragment_msgbox,
                 explorer_msgbox,
                 garbage_collection_interval: block_cache_ttl,
+                leader_slots,
             };
             process.start(info, block_queue)
         });
@@ -181,6 +251,12 @@ fn start_services(bootstrapped_node: BootstrappedNode) -> Result<(), start_up::E
         let mut task_data = client::TaskData {
             storage: blockchain.storage().clone(),
             blockchain_tip: blockchain_tip.clone(),
+            branches: blockchain.branches().clone(),
+            blocks_rate_limiter: bootstrapped_node
+                .settings
+                .network
+                .outbound_blocks_rate_limit
+                .map(BandwidthLimiter::new),
         };
 
         services.spawn_future_with_inputs("client-query", move |info, input| {
@@ -188,7 +264,12 @@ fn start_services(bootstrapped_node: BootstrappedNode) -> Result<(), start_up::E
         })
     };
 
-    {
+    let (network_shutdown, shutdown_signal) = network::shutdown_channel();
+
+    if read_only {
+        // the requests to the network task fail rather than wait forever
+        std::mem::drop(network_queue);
+    } else {
         let client_msgbox = client_task.clone();
         let fragment_msgbox = fragment_msgbox.clone();
         let block_msgbox = block_msgbox.clone();
@@ -200,6 +281,7 @@ fn start_services(bootstrapped_node: BootstrappedNode) -> Result<(), start_up::E
             block_box: block_msgbox,
         };
         let topology = topology.clone();
+        let stats_counter = stats_counter.clone();
 
         services.spawn_future("network", move |info| {
             let params = network::TaskParams {
@@ -207,32 +289,33 @@ fn start_services(bootstrapped_node: BootstrappedNode) -> Result<(), start_up::E
                 block0_hash,
                 input: network_queue,
                 channels,
+                stats_counter,
+                shutdown: shutdown_signal,
             };
             network::start(info, params, topology)
         });
     }
 
-    let leader_secrets: Result<Vec<Leader>, start_up::Error> = bootstrapped_node
-        .settings
-        .secrets
-        .iter()
-        .map(|secret_path| {
-            let secret = secure::NodeSecret::load_from_file(secret_path.as_path())?;
-            Ok(Leader {
-                bft_leader: secret.bft(),
-                genesis_leader: secret.genesis(),
-            })
-        })
-        .collect();
-    let leader_secrets = leader_secrets?;
-    let enclave = block_on(Enclave::from_vec(leader_secrets));
+    let enclave = block_on(Enclave::from_vec(bootstrapped_node.leaders));
 
-    {
+    if !read_only {
         let leadership_logs = leadership_logs.clone();
+        let leadership_stats = leadership_stats.clone();
         let fragment_pool = fragment_pool.clone();
         let block_msgbox = block_msgbox.clone();
         let blockchain_tip = blockchain_tip.clone();
         let enclave = leadership::Enclave::new(enclave.clone());
+        let leader_lease = bootstrapped_node
+            .settings
+            .standby
+            .clone()
+            .map(leadership::Lease::new);
+        let clock_guard = bootstrapped_node
+            .settings
+            .clock_check
+            .as_ref()
+            .filter(|check| check.refuse_to_sign)
+            .map(|_| clock_skew.clone());
 
         services.spawn_future("leadership", move |info| {
             let fut = leadership::Module::new(
@@ -243,6 +326,10 @@ fn start_services(bootstrapped_node: BootstrappedNode) -> Result<(), start_up::E
                 fragment_pool,
                 enclave,
                 block_msgbox,
+                leader_slots,
+                leadership_stats,
+                leader_lease,
+                clock_guard,
             )
             .and_then(|module| module.run())
             .map_err(|e| unimplemented!("error in leadership {}", e));
@@ -251,6 +338,68 @@ fn start_services(bootstrapped_node: BootstrappedNode) -> Result<(), start_up::E
         });
     }
 
+    if let Some(divergence_check) = bootstrapped_node.settings.divergence_check.clone() {
+        let storage = blockchain.storage().clone();
+        let blockchain_tip = blockchain_tip.clone();
+        let outbound_bind_address = bootstrapped_node.settings.network.outbound_bind_address;
+
+        services.spawn_future("divergence_notifier", move |info| {
+            network::divergence::check_tip_divergence(
+                info,
+                storage,
+                blockchain_tip,
+                divergence_check,
+                outbound_bind_address,
+            )
+        });
+    }
+
+    if let Some(clock_check) = bootstrapped_node.settings.clock_check.clone() {
+        let clock_skew = clock_skew.clone();
+
+        services.spawn_future("clock_check", move |info| {
+            clock_check::check_clock_skew(info, clock_check, clock_skew)
+        });
+    }
+
+    if let Some(chain_snapshot) = bootstrapped_node
+        .settings
+        .chain_snapshot
+        .clone()
+        .filter(|_| !read_only)
+    {
+        let storage = blockchain.storage().clone();
+        let blockchain_tip = blockchain_tip.clone();
+        let block0_hash = bootstrapped_node.block0_hash;
+
+        services.spawn_future("chain_snapshot", move |info| {
+            snapshot::write_periodically(info, storage, blockchain_tip, block0_hash, chain_snapshot)
+        });
+    }
+
+    if bootstrapped_node.settings.epoch_transition_window > 0 {
+        let blockchain_tip = blockchain_tip.clone();
+        let window = bootstrapped_node.settings.epoch_transition_window;
+
+        services.spawn_future("epoch_transition", move |info| {
+            blockchain::precompute_epoch_transitions(info, blockchain_tip, window)
+        });
+    }
+
+    if let Some(webhooks) = bootstrapped_node
+        .settings
+        .webhooks
+        .clone()
+        .filter(|_| !read_only)
+    {
+        let storage = blockchain.storage().clone();
+        let blockchain_tip = blockchain_tip.clone();
+
+        services.spawn_future("webhooks", move |info| {
+            webhooks::notify(info, storage, blockchain_tip, webhooks)
+        });
+    }
+
     if let Some(rest_context) = bootstrapped_node.rest_context {
         let full_context = rest::FullContext {
             stats_counter,
@@ -258,12 +407,28 @@ fn start_services(bootstrapped_node: BootstrappedNode) -> Result<(), start_up::E
             blockchain_tip: blockchain_tip.clone(),
             network_task: network_msgbox,
             transaction_task: fragment_msgbox,
+            client_task: client_task.clone(),
             logs: pool_logs,
             leadership_logs,
+            leadership_stats,
+            clock_skew,
             enclave,
             p2p: topology,
             explorer: explorer.as_ref().map(|(_msg_box, context)| context.clone()),
             diagnostic: bootstrapped_node.diagnostic,
+            chain_snapshot: bootstrapped_node
+                .settings
+                .chain_snapshot
+                .as_ref()
+                .map(|snapshot| snapshot.path.clone()),
+            read_only,
+            fragment_rate_limiter: bootstrapped_node
+                .settings
+                .mempool
+                .rest_rate_limit
+                .filter(|limit| limit.fragments_per_second > 0)
+                .map(|limit| RateLimiter::new(limit.fragments_per_second, limit.burst)),
+            admin_token: bootstrapped_node.settings.rest_admin_token.clone(),
         };
         block_on(async {
             rest_context.set_full(full_context).await;
@@ -271,7 +436,7 @@ fn start_services(bootstrapped_node: BootstrappedNode) -> Result<(), start_up::E
         })
     };
 
-    {
+    if !read_only {
         let blockchain_tip = blockchain_tip.clone();
         let no_blockchain_updates_warning_interval = bootstrapped_node
             .settings
@@ -287,6 +452,27 @@ fn start_services(bootstrapped_node: BootstrappedNode) -> Result<(), start_up::E
         });
     }
 
+    if !read_only && !bootstrapped_node.settings.network.block_gateways.is_empty() {
+        let gateways = bootstrapped_node.settings.network.block_gateways.clone();
+        let outbound_bind_address = bootstrapped_node.settings.network.outbound_bind_address;
+        let blockchain_tip = blockchain_tip.clone();
+        let block_msgbox = block_msgbox.clone();
+        let behind_after = bootstrapped_node
+            .settings
+            .no_blockchain_updates_warning_interval;
+
+        services.spawn_future("gateway_catch_up", move |info| {
+            network::catch_up::catch_up(
+                info,
+                gateways,
+                outbound_bind_address,
+                blockchain_tip,
+                block_msgbox,
+                behind_after,
+            )
+        });
+    }
+
     match services.wait_any_finished() {
         Err(err) => {
             crit!(
@@ -298,6 +484,13 @@ fn start_services(bootstrapped_node: BootstrappedNode) -> Result<(), start_up::E
         }
         Ok(true) => {
             info!(bootstrapped_node.logger, "Shutting down node");
+            network_shutdown.shutdown();
+            if let Some(mempool_file) = mempool_file {
+                let logger = bootstrapped_node
+                    .logger
+                    .new(o!(log::KEY_TASK => "fragment"));
+                fragment::persist_on_shutdown(&fragment_pool, mempool_file, logger);
+            }
             Ok(())
         }
         Ok(false) => {
@@ -326,11 +519,13 @@ fn bootstrap(initialized_node: InitializedNode) -> Result<BootstrappedNode, star
     let InitializedNode {
         settings,
         block0,
-        storage,
+        mut storage,
+        block_event_log,
         logger,
         rest_context,
         services,
         diagnostic,
+        leaders,
     } = initialized_node;
 
     if let Some(context) = rest_context.as_ref() {
@@ -343,14 +538,26 @@ fn bootstrap(initialized_node: InitializedNode) -> Result<BootstrappedNode, star
 
     let block0_explorer = block0.clone();
 
-    // TODO: we should get this value from the configuration
-    let block_cache_ttl: Duration = Duration::from_secs(5 * 24 * 3600);
+    start_up::import_chain_snapshot(&settings, &mut storage, &block0, &bootstrap_logger)?;
+
+    let load_progress = blockchain::LoadProgress::default();
+    if let Some(context) = rest_context.as_ref() {
+        block_on(context.set_load_progress(load_progress.clone()))
+    }
 
-    let (blockchain, blockchain_tip) =
-        start_up::load_blockchain(block0, storage, block_cache_ttl, &bootstrap_logger)?;
+    let (blockchain, blockchain_tip) = start_up::load_blockchain(
+        block0,
+        storage,
+        block_event_log,
+        &settings.block_cache,
+        settings.max_rollback_depth,
+        load_progress,
+        &bootstrap_logger,
+    )?;
 
+    // a read-only node serves the blockchain of its storage as it is
     let mut bootstrap_attempt: usize = 0;
-    loop {
+    while !settings.read_only {
         bootstrap_attempt += 1;
 
         // If we have exceeded the maximum number of bootstrap attempts, then we break out of the
@@ -404,6 +611,7 @@ fn bootstrap(initialized_node: InitializedNode) -> Result<BootstrappedNode, star
         rest_context,
         services,
         diagnostic,
+        leaders,
     })
 }
 
@@ -411,10 +619,12 @@ pub struct InitializedNode {
     pub settings: Settings,
     pub block0: blockcfg::Block,
     pub storage: start_up::NodeStorage,
+    pub block_event_log: blockchain::BlockEventLog,
     pub logger: Logger,
     pub rest_context: Option<rest::Context>,
     pub services: Services,
     pub diagnostic: Diagnostic,
+    pub leaders: Vec<EnclaveLeader>,
 }
 
 fn initialize_node() -> Result<InitializedNode, start_up::Error> {
@@ -428,6 +638,11 @@ fn initialize_node() -> Result<InitializedNode, start_up::Error> {
         std::process::exit(0);
     }
 
+    let self_test = command_line.self_test;
+    let export_chain = command_line.export_chain.clone();
+    let import_chain = command_line.import_chain.clone();
+    let verify_storage_only = command_line.verify_storage_only;
+    let rollback_to = command_line.rollback_to;
     let raw_settings = RawSettings::load(command_line)?;
 
     let log_settings = raw_settings.log_settings();
@@ -440,6 +655,16 @@ fn initialize_node() -> Result<InitializedNode, start_up::Error> {
     debug!(init_logger, "system settings are: {}", diagnostic);
 
     let settings = raw_settings.try_into_settings(&init_logger)?;
+
+    if self_test {
+        let report = start_up::self_test::run(&settings, &init_logger);
+        print!("{}", report);
+        if report.passed() {
+            std::process::exit(0);
+        }
+        return Err(start_up::Error::SelfTestFailed);
+    }
+
     let mut services = Services::new(logger.clone());
 
     let rest_context = match settings.rest.clone() {
@@ -461,6 +686,7 @@ fn initialize_node() -> Result<InitializedNode, start_up::Error> {
         block_on(context.set_node_state(NodeState::PreparingStorage))
     }
     let storage = start_up::prepare_storage(&settings, &init_logger)?;
+    let block_event_log = start_up::prepare_block_event_log(&settings, &init_logger)?;
 
     // TODO: load network module here too (if needed)
 
@@ -472,34 +698,105 @@ fn initialize_node() -> Result<InitializedNode, start_up::Error> {
         &storage,
         &init_logger, /* add network to fetch block0 */
     )?;
+    let (mut storage, block_event_log) = if settings.reinitialize_storage {
+        start_up::reinitialize_storage(
+            &settings,
+            storage,
+            block_event_log,
+            block0.header.hash(),
+            &init_logger,
+        )?
+    } else {
+        (storage, block_event_log)
+    };
+
+    if verify_storage_only {
+        let report = start_up::verify_storage::run(
+            &mut storage,
+            &block0,
+            settings.truncate_corrupted_storage,
+            &init_logger,
+        )?;
+        print!("{}", report);
+        report.into_result()?;
+        std::process::exit(0);
+    }
+    if settings.verify_storage {
+        start_up::verify_storage::run(
+            &mut storage,
+            &block0,
+            settings.truncate_corrupted_storage,
+            &init_logger,
+        )?
+        .into_result()?;
+    }
+
+    if let Some(path) = export_chain {
+        start_up::chain_archive::export(&storage, &block0, &path, &init_logger)?;
+        std::process::exit(0);
+    }
+    if let Some(path) = import_chain {
+        start_up::chain_archive::import(
+            &mut storage,
+            &block0,
+            &settings.block_cache,
+            &path,
+            &init_logger,
+        )?;
+        std::process::exit(0);
+    }
+    if let Some(target) = rollback_to {
+        start_up::rollback::run(&mut storage, target, &init_logger)?;
+        std::process::exit(0);
+    }
+
+    // the secrets are loaded, and the passphrase asked for, before the
+    // bootstrap: a wrong passphrase fails the start right away
+    let leaders = load_leaders(&settings)?;
 
     Ok(InitializedNode {
         settings,
         block0,
         storage,
+        block_event_log,
         logger,
         rest_context,
         services,
         diagnostic,
+        leaders,
     })
 }
 
+fn load_leaders(settings: &Settings) -> Result<Vec<EnclaveLeader>, start_up::Error> {
+    let mut passphrase = secure::Passphrase::new(settings.secret_passphrase.clone());
+    settings
+        .secrets
+        .iter()
+        .map(|secret_path| {
+            let secret =
+                secure::NodeSecret::load_from_file(secret_path.as_path(), &mut passphrase)?;
+            Ok(secret.leader())
+        })
+        .collect()
+}
+
 fn main() {
     use std::error::Error;
 
     if let Err(error) = start() {
-        eprintln!("{}", error);
+        eprintln!("error E{:02}: {}", error.code(), error);
         let mut source = error.source();
         while let Some(err) = source {
             eprintln!(" |-> {}", err);
             source = err.source();
         }
+        eprintln!("hint: {}", error.remediation());
 
         // TODO: https://github.com/rust-lang/rust/issues/43301
         //
         // as soon as #43301 is stabilized it would be nice to no use
         // `exit` but the more appropriate:
         // https://doc.rust-lang.org/stable/std/process/trait.Termination.html
-        std::process::exit(error.code());
+        std::process::exit(error.category().exit_code());
     }
 }
@@ -0,0 +1,302 @@
+//! Download of missing blocks from several peers at once.
+//!
+//! A range of blocks wanted by the blockchain task is split in chunks,
+//! which are solicited in turn from the peers the node has a client
+//! connection with. The client connections hand the blocks received
+//! for a chunk back to the download, which releases the chunks in the
+//! order of the range, so the blockchain task gets a single stream
+//! where every block follows its parent.
+
+use super::{buffer_sizes, GlobalStateR};
+use crate::{
+    blockcfg::{Block, HeaderHash},
+    intercom::{self, BlockMsg},
+    utils::async_msg::MessageBox,
+};
+use futures::future::{self, Either};
+use futures::prelude::*;
+use futures::sync::mpsc;
+use slog::Logger;
+use tokio::timer::Timeout;
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// number of blocks solicited from a peer in one request
+const CHUNK_SIZE: usize = 64;
+
+/// maximum number of peers a range of blocks is downloaded from
+const MAX_PEERS: usize = 4;
+
+/// time given to the peers to deliver all the chunks of a range
+const DOWNLOAD_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Chunks solicited from the peers and not yet received,
+/// keyed by the hash of the first block in the chunk.
+#[derive(Clone, Default)]
+pub struct BlockFetch {
+    slots: Arc<Mutex<HashMap<HeaderHash, (ChunkSlot, Instant)>>>,
+}
+
+impl BlockFetch {
+    /// Takes the slot of the chunk starting with the given block,
+    /// if the solicitation has been made by a parallel download.
+    pub fn claim(&self, first: &HeaderHash) -> Option<ChunkSlot> {
+        let mut slots = self.slots.lock().unwrap();
+        slots.remove(first).map(|(slot, _)| slot)
+    }
+
+    fn register(&self, chunks: &[Vec<HeaderHash>]) -> mpsc::Receiver<Block> {
+        let (download, receiver) = Download::new(chunks.iter().map(Vec::len));
+        let download = Arc::new(Mutex::new(download));
+        let now = Instant::now();
+        let mut slots = self.slots.lock().unwrap();
+        // Slots left unclaimed this long belong to solicitations
+        // that were lost along with the connection to the peer.
+        slots.retain(|_, (_, registered)| now.duration_since(*registered) < DOWNLOAD_TIMEOUT);
+        for (index, chunk) in chunks.iter().enumerate() {
+            let slot = ChunkSlot {
+                index,
+                download: download.clone(),
+                completed: false,
+            };
+            slots.insert(chunk[0], (slot, now));
+        }
+        receiver
+    }
+}
+
+/// The place of a chunk in a download. Dropping the slot without
+/// completing it ends the download, as the chunks following the
+/// missing one cannot be applied.
+pub struct ChunkSlot {
+    index: usize,
+    download: Arc<Mutex<Download<Block>>>,
+    completed: bool,
+}
+
+impl ChunkSlot {
+    pub fn complete(mut self, blocks: Vec<Block>) {
+        self.completed = true;
+        self.download.lock().unwrap().complete(self.index, blocks);
+    }
+}
+
+impl Drop for ChunkSlot {
+    fn drop(&mut self) {
+        if !self.completed {
+            self.download.lock().unwrap().fail();
+        }
+    }
+}
+
+/// Reassembly of the chunks of a download in the order of the range.
+/// The channel to the blockchain task has room for all the blocks of the
+/// range, which the peers cannot exceed as a chunk is only completed with
+/// the number of blocks requested.
+struct Download<T> {
+    received: BTreeMap<usize, Vec<T>>,
+    next: usize,
+    total: usize,
+    sender: Option<mpsc::Sender<T>>,
+}
+
+impl<T> Download<T> {
+    /// a download of chunks of the given sizes
+    fn new<I>(chunk_sizes: I) -> (Self, mpsc::Receiver<T>)
+    where
+        I: ExactSizeIterator<Item = usize>,
+    {
+        let total = chunk_sizes.len();
+        let (sender, receiver) = mpsc::channel(chunk_sizes.sum());
+        let download = Download {
+            received: BTreeMap::new(),
+            next: 0,
+            total,
+            sender: Some(sender),
+        };
+        (download, receiver)
+    }
+
+    fn complete(&mut self, index: usize, blocks: Vec<T>) {
+        self.received.insert(index, blocks);
+        while let Some(blocks) = self.received.remove(&self.next) {
+            self.next += 1;
+            let sent = match &mut self.sender {
+                Some(sender) => blocks
+                    .into_iter()
+                    .all(|block| sender.try_send(block).is_ok()),
+                None => false,
+            };
+            if !sent {
+                self.fail();
+                return;
+            }
+        }
+        if self.next == self.total {
+            // all chunks have been released, end the stream
+            self.sender = None;
+        }
+    }
+
+    fn fail(&mut self) {
+        self.sender = None;
+        self.received.clear();
+    }
+}
+
+/// Fetches the blocks with the given hashes. A range longer than a chunk
+/// is split among the peers that have a client connection with this node,
+/// if there are at least two of them; otherwise the whole range
+/// is solicited from a single peer.
+pub fn fetch_blocks(
+    state: GlobalStateR,
+    block_box: MessageBox<BlockMsg>,
+    hashes: Vec<HeaderHash>,
+) -> impl Future<Item = (), Error = ()> {
+    if hashes.len() <= CHUNK_SIZE {
+        return Either::A(state.peers.fetch_blocks(hashes));
+    }
+    Either::B(
+        state
+            .peers
+            .peers_for_block_fetch(MAX_PEERS)
+            .and_then(move |peers| {
+                if peers.len() < 2 {
+                    return Either::A(state.peers.fetch_blocks(hashes));
+                }
+                debug!(
+                    state.logger(),
+                    "fetching blocks from several peers";
+                    "blocks" => hashes.len(),
+                    "peers" => peers.len(),
+                );
+                let chunks = hashes
+                    .chunks(CHUNK_SIZE)
+                    .map(<[_]>::to_vec)
+                    .collect::<Vec<_>>();
+                let receiver = state.block_fetch.register(&chunks);
+                state.spawn(forward_blocks(receiver, block_box, state.logger().clone()));
+                // each chunk goes to the next peer in turn, or to the
+                // following ones if that peer cannot take it
+                let solicitations = chunks
+                    .into_iter()
+                    .enumerate()
+                    .map(|(index, chunk)| {
+                        let mut node_ids = peers.clone();
+                        node_ids.rotate_left(index % peers.len());
+                        let block_fetch = state.block_fetch.clone();
+                        let first = chunk[0];
+                        state
+                            .peers
+                            .solicit_blocks_from_any(node_ids, chunk)
+                            .map(move |sent| {
+                                if !sent {
+                                    // no peer takes the chunk, dropping
+                                    // its slot ends the download
+                                    block_fetch.claim(&first);
+                                }
+                            })
+                    })
+                    .collect::<Vec<_>>();
+                Either::B(future::join_all(solicitations).map(|_| ()))
+            }),
+    )
+}
+
+// Passes the blocks released by a download to the blockchain task.
+fn forward_blocks(
+    receiver: mpsc::Receiver<Block>,
+    block_box: MessageBox<BlockMsg>,
+    logger: Logger,
+) -> impl Future<Item = (), Error = ()> {
+    let (handle, sink) = intercom::stream_request::<Block, (), intercom::Error>(
+        buffer_sizes::inbound::BLOCKS,
+        logger.clone(),
+    );
+    let err_logger = logger.clone();
+    let blocks = receiver.map_err(|()| intercom::Error::aborted("block download was interrupted"));
+    let download = Timeout::new(sink.send_all(blocks), DOWNLOAD_TIMEOUT)
+        .map(|_| ())
+        .map_err(move |e| match e.into_inner() {
+            Some(e) => info!(err_logger, "block download failed"; "reason" => %e),
+            None => info!(err_logger, "block download timed out"),
+        });
+    block_box
+        .send(BlockMsg::NetworkBlocks(handle, None))
+        .map_err(move |e| {
+            error!(
+                logger,
+                "failed to enqueue request for processing";
+                "reason" => %e,
+            );
+        })
+        .and_then(move |_mbox| download)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// the items released so far, and whether the download has ended
+    fn released(receiver: &mut mpsc::Receiver<u32>) -> (Vec<u32>, bool) {
+        future::lazy(|| {
+            let mut items = Vec::new();
+            loop {
+                match receiver.poll() {
+                    Ok(Async::Ready(Some(item))) => items.push(item),
+                    Ok(Async::Ready(None)) | Err(()) => return Ok::<_, ()>((items, true)),
+                    Ok(Async::NotReady) => return Ok((items, false)),
+                }
+            }
+        })
+        .wait()
+        .unwrap()
+    }
+
+    #[test]
+    fn chunks_are_released_in_order() {
+        let (mut download, mut receiver) = Download::new(vec![2, 2, 1].into_iter());
+
+        download.complete(1, vec![3, 4]);
+        assert_eq!(released(&mut receiver), (vec![], false));
+        download.complete(0, vec![1, 2]);
+        assert_eq!(released(&mut receiver), (vec![1, 2, 3, 4], false));
+        download.complete(2, vec![5]);
+        assert_eq!(released(&mut receiver), (vec![5], true));
+    }
+
+    #[test]
+    fn chunks_received_last_to_first_are_all_released() {
+        let (mut download, mut receiver) = Download::new(vec![1, 1, 1, 1].into_iter());
+
+        for index in (1..4).rev() {
+            download.complete(index, vec![index as u32]);
+            assert_eq!(released(&mut receiver), (vec![], false));
+        }
+        download.complete(0, vec![0]);
+        assert_eq!(released(&mut receiver), (vec![0, 1, 2, 3], true));
+    }
+
+    #[test]
+    fn failed_chunk_ends_the_download() {
+        let (mut download, mut receiver) = Download::new(vec![1, 1, 1].into_iter());
+
+        download.complete(0, vec![0]);
+        download.complete(2, vec![2]);
+        download.fail();
+        assert_eq!(released(&mut receiver), (vec![0], true));
+        download.complete(1, vec![1]);
+        assert_eq!(released(&mut receiver), (vec![], true));
+    }
+
+    #[test]
+    fn channel_holds_all_the_blocks_of_the_range() {
+        let (mut download, mut receiver) = Download::new(vec![3, 3].into_iter());
+
+        download.complete(0, vec![0, 1, 2]);
+        download.complete(1, vec![3, 4, 5]);
+        assert_eq!(released(&mut receiver), (vec![0, 1, 2, 3, 4, 5], true));
+    }
+}
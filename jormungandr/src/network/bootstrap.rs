@@ -1,20 +1,42 @@
-use super::{grpc, BlockConfig};
-use crate::blockcfg::{Block, HeaderHash};
-use crate::blockchain::{self, Blockchain, Error as BlockchainError, PreCheckedHeader, Ref, Tip};
-use crate::settings::start::network::Peer;
+use super::gateway::{self, Gateway};
+use super::grpc;
+use crate::blockcfg::{Block, Header, HeaderHash};
+use crate::blockchain::{
+    self, Blockchain, Checkpoints, Error as BlockchainError, PreCheckedHeader, Ref, Tip,
+};
+use crate::settings::start::network::{Peer, TrustedCheckpoint};
 use chain_core::property::HasHeader;
+use chain_impl_mockchain::leadership::Verification;
 use network_core::client::{BlockService, Client as _};
 use network_core::error::Error as NetworkError;
-use network_grpc::client::Connection;
 use slog::Logger;
 use thiserror::Error;
 use tokio::prelude::future::Either;
 use tokio::prelude::*;
+use tokio::timer::{timeout, Timeout};
 use tokio_compat::runtime::Runtime;
 
+use std::cmp::Reverse;
+use std::collections::HashSet;
+use std::convert::Infallible;
 use std::fmt::Debug;
 use std::io;
+use std::net::{IpAddr, SocketAddr};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// number of headers pulled from every sync peer in a round of the sync
+const SYNC_HEADERS_PER_ROUND: u64 = 2000;
+
+/// number of blocks requested at once from the peer selected in a round
+const SYNC_BLOCKS_PER_REQUEST: usize = 100;
+
+/// time a sync peer is given to answer the tip and header requests
+const SYNC_PEER_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// time a sync peer is given to send the headers up to the last trusted
+/// checkpoint
+const TRUSTED_CHAIN_TIMEOUT: Duration = Duration::from_secs(10 * 60);
 
 #[derive(Error, Debug)]
 pub enum Error {
@@ -42,48 +64,581 @@ pub enum Error {
     ApplyBlockFailed { source: BlockchainError },
     #[error("failed to select the new tip")]
     ChainSelectionFailed { source: BlockchainError },
+    #[error("block gateway request failed")]
+    Gateway { source: gateway::Error },
+    #[error("none of the checkpoints is known to the block gateway")]
+    GatewayCheckpointsNotFound,
+    #[error("none of the trusted peers could be used to sync")]
+    NoSyncPeers,
+    #[error("tip request failed")]
+    TipRequestFailed { source: NetworkError },
+    #[error("the sync peer did not respond in time")]
+    SyncPeerTimeout,
+    #[error("header {0} does not follow the previous header sent by the sync peer")]
+    HeaderChainBroken(HeaderHash),
+    #[error("header {header} sent by the sync peer is invalid: {reason}")]
+    HeaderInvalid { header: HeaderHash, reason: String },
+    #[error(
+        "block {received} at chain length {chain_length} is not the trusted checkpoint {expected}"
+    )]
+    CheckpointMismatch {
+        chain_length: u32,
+        expected: HeaderHash,
+        received: HeaderHash,
+    },
+    #[error("the headers sent by the sync peer do not lead to its tip {0}")]
+    HeaderChainIncomplete(HeaderHash),
+    #[error("block request failed")]
+    GetBlocksFailed { source: NetworkError },
+}
+
+struct SyncPeer {
+    addr: SocketAddr,
+    client: grpc::Connection,
+}
+
+/// the chain of a sync peer, as far as it was pulled in a round
+struct Candidate {
+    peer: usize,
+    tip: HeaderHash,
+    tip_length: u32,
+    headers: Vec<Header>,
+    /// the time the peer took to answer the tip request
+    response_time: Duration,
 }
 
-pub fn bootstrap_from_peer(
-    peer: Peer,
+/// Catches up with the chain of the given peers.
+///
+/// The node connects to up to `max_sync_peers` of the peers and proceeds in
+/// rounds: the headers following the node's checkpoints are pulled from
+/// every peer ahead of the node's tip and verified, and the blocks are
+/// downloaded only for the chain with the longest tip, the tip reported by
+/// the most peers breaking ties. The block requests are spread over the
+/// peers reporting that tip and run concurrently, the blocks being applied
+/// in the order of the chain.
+/// A peer sending an inconsistent header chain, stalling or
+/// serving invalid blocks is dropped, and the round is run again with the
+/// others. The sync completes when none of the peers is ahead of the node.
+///
+/// The headers leading to the last of the `trusted_checkpoints` are first
+/// pulled from a sync peer. The blocks proven that way to be ancestors of
+/// the checkpoint are only checked to link to each other, their headers are
+/// not verified against the leadership schedule. All the other blocks are
+/// verified.
+pub fn bootstrap_from_peers(
+    peers: Vec<Peer>,
+    max_sync_peers: usize,
+    bind_address: Option<IpAddr>,
+    trusted_checkpoints: &[TrustedCheckpoint],
     blockchain: Blockchain,
     tip: Tip,
     logger: Logger,
 ) -> Result<(), Error> {
-    info!(logger, "connecting to bootstrap peer {}", peer.connection);
-
     let mut runtime = Runtime::new().map_err(|e| Error::RuntimeInit { source: e })?;
 
-    let bootstrap = grpc::connect(peer.address(), None, runtime.executor())
-        .map_err(|e| Error::Connect { source: e })
-        .and_then(|client: Connection<BlockConfig>| {
-            client
-                .ready()
-                .map_err(|e| Error::ClientNotReady { source: e })
+    let connects = peers
+        .into_iter()
+        .map(|peer| {
+            let addr = peer.address();
+            let logger = logger.new(o!("peer_addr" => addr.to_string()));
+            info!(logger, "connecting to bootstrap peer");
+            grpc::connect(addr, bind_address, None, runtime.executor())
+                .map_err(|e| Error::Connect { source: e })
+                .and_then(|client: grpc::Connection| {
+                    client
+                        .ready()
+                        .map_err(|e| Error::ClientNotReady { source: e })
+                })
+                .then(move |res| match res {
+                    Ok(client) => Ok(Some(SyncPeer { addr, client })),
+                    Err(e) => {
+                        warn!(logger, "unable to reach peer for initial bootstrap"; "reason" => %e);
+                        Ok::<_, Error>(None)
+                    }
+                })
         })
-        .join(
+        .collect::<Vec<_>>();
+    let mut sync_peers = runtime
+        .block_on(future::join_all(connects))?
+        .into_iter()
+        .filter_map(|peer| peer)
+        .take(max_sync_peers)
+        .collect::<Vec<_>>();
+    let trusted_checkpoints = Arc::new(trusted_checkpoints.to_vec());
+
+    let trusted_chain = if sync_peers.is_empty() {
+        HashSet::new()
+    } else {
+        let checkpoints = runtime.block_on(
             blockchain
                 .get_checkpoints(tip.branch())
                 .map_err(|e| Error::GetCheckpointsFailed { source: e }),
+        )?;
+        pull_trusted_chain(
+            &mut runtime,
+            &mut sync_peers,
+            &checkpoints,
+            &trusted_checkpoints,
+            &logger,
+        )
+    };
+    let trusted_chain = Arc::new(trusted_chain);
+
+    loop {
+        if sync_peers.is_empty() {
+            return Err(Error::NoSyncPeers);
+        }
+
+        let (checkpoints, local_tip) = runtime.block_on(
+            blockchain
+                .get_checkpoints(tip.branch())
+                .map_err(|e| Error::GetCheckpointsFailed { source: e })
+                .join(tip.get_ref().map_err(|_: Infallible| unreachable!())),
+        )?;
+        let local_length = u32::from(local_tip.chain_length());
+
+        let (candidates, mut failed) = pull_candidates(
+            &mut runtime,
+            &mut sync_peers,
+            &blockchain,
+            &checkpoints,
+            &trusted_checkpoints,
+            &trusted_chain,
+            local_length,
+        )?;
+
+        match select_candidate(candidates) {
+            Some((best, sources)) => {
+                debug!(
+                    logger,
+                    "downloading blocks from the sync peers with the best chain";
+                    "peer_addr" => %sync_peers[best.peer].addr,
+                    "tip" => %best.tip,
+                    "chain_length" => best.tip_length,
+                    "headers" => best.headers.len(),
+                    "response_time" => ?best.response_time,
+                    "sources" => sources.len(),
+                );
+                let downloads = download_blocks(&mut sync_peers, &best, &sources);
+                let blockchain = blockchain.clone();
+                let tip = tip.clone();
+                let trusted_checkpoints = trusted_checkpoints.clone();
+                let trusted_chain = trusted_chain.clone();
+                let logger = logger.clone();
+                let res = runtime.block_on(downloads.for_each(move |(peer, blocks)| {
+                    bootstrap_from_stream(
+                        blockchain.clone(),
+                        tip.clone(),
+                        stream::iter_ok(blocks),
+                        trusted_checkpoints.clone(),
+                        trusted_chain.clone(),
+                        logger.clone(),
+                    )
+                    .map_err(move |e| (peer, e))
+                }));
+                if let Err(failure) = res {
+                    failed.push(failure);
+                }
+            }
+            None if failed.len() < sync_peers.len() => {
+                info!(logger, "none of the sync peers is ahead of the node");
+                return Ok(());
+            }
+            None => {}
+        }
+
+        drop_sync_peers(&mut sync_peers, failed, &logger);
+    }
+}
+
+// Pulls the headers from the checkpoints of the node up to the last trusted
+// checkpoint, from the sync peers in turn until one of them sends a chain
+// linking up to it. Returns the hashes of the blocks of that chain, which
+// are proven to be ancestors of the checkpoint, or none if none of the
+// peers sent such a chain.
+fn pull_trusted_chain(
+    runtime: &mut Runtime,
+    sync_peers: &mut [SyncPeer],
+    checkpoints: &Checkpoints,
+    trusted_checkpoints: &[TrustedCheckpoint],
+    logger: &Logger,
+) -> HashSet<HeaderHash> {
+    let last = match trusted_checkpoints.last() {
+        Some(last) => *last,
+        None => return HashSet::new(),
+    };
+    if checkpoints
+        .iter()
+        .any(|checkpoint| *checkpoint == last.hash)
+    {
+        return HashSet::new();
+    }
+
+    for peer in sync_peers.iter_mut() {
+        let pull = peer
+            .client
+            .pull_headers(checkpoints.as_slice(), &last.hash)
+            .map_err(|e| Error::PullRequestFailed { source: e })
+            .and_then(move |stream| {
+                stream
+                    .take(u64::from(last.chain_length))
+                    .map_err(|e| Error::PullStreamFailed { source: e })
+                    .fold(
+                        (HashSet::new(), None),
+                        |(mut hashes, parent): (HashSet<HeaderHash>, Option<HeaderHash>),
+                         header: Header| {
+                            if parent.map_or(false, |parent| header.block_parent_hash() != parent) {
+                                return Err(Error::HeaderChainBroken(header.hash()));
+                            }
+                            let hash = header.hash();
+                            hashes.insert(hash);
+                            Ok((hashes, Some(hash)))
+                        },
+                    )
+            })
+            .and_then(move |(hashes, last_received)| {
+                if last_received == Some(last.hash) {
+                    Ok(hashes)
+                } else {
+                    Err(Error::HeaderChainIncomplete(last.hash))
+                }
+            });
+        match runtime.block_on(Timeout::new(pull, TRUSTED_CHAIN_TIMEOUT).map_err(sync_timeout)) {
+            Ok(hashes) => {
+                info!(
+                    logger,
+                    "pulled the chain up to the last trusted checkpoint";
+                    "peer_addr" => %peer.addr,
+                    "checkpoint" => %last.hash,
+                    "headers" => hashes.len(),
+                );
+                return hashes;
+            }
+            Err(e) => {
+                info!(
+                    logger,
+                    "failed to pull the chain up to the last trusted checkpoint";
+                    "peer_addr" => %peer.addr,
+                    "reason" => %e,
+                );
+            }
+        }
+    }
+    warn!(
+        logger,
+        "none of the sync peers sent the chain up to the last trusted checkpoint, verifying all the blocks"
+    );
+    HashSet::new()
+}
+
+// Pulls the headers following the checkpoints from the sync peers ahead
+// of the node. Returns the chains received, and the peers that failed
+// along with the reason.
+fn pull_candidates(
+    runtime: &mut Runtime,
+    sync_peers: &mut [SyncPeer],
+    blockchain: &Blockchain,
+    checkpoints: &Checkpoints,
+    trusted_checkpoints: &[TrustedCheckpoint],
+    trusted_chain: &HashSet<HeaderHash>,
+    local_length: u32,
+) -> Result<(Vec<Candidate>, Vec<(usize, Error)>), Error> {
+    let tips = runtime.block_on(future::join_all(sync_peers.iter_mut().map(|peer| {
+        let started = Instant::now();
+        Timeout::new(
+            peer.client
+                .tip()
+                .map(move |header| (header, started.elapsed()))
+                .map_err(|e| Error::TipRequestFailed { source: e }),
+            SYNC_PEER_TIMEOUT,
         )
-        .and_then(move |(mut client, checkpoints)| {
-            debug!(
-                logger,
-                "pulling blocks starting from checkpoints: {:?}", checkpoints
-            );
-            client
-                .pull_blocks_to_tip(checkpoints.as_slice())
-                .map_err(|e| Error::PullRequestFailed { source: e })
-                .and_then(move |stream| bootstrap_from_stream(blockchain, tip, stream, logger))
+        .map_err(sync_timeout)
+        .then(Ok::<_, Error>)
+    })))?;
+
+    let mut failed = Vec::new();
+    let mut ahead = Vec::new();
+    for (peer, res) in tips.into_iter().enumerate() {
+        match res {
+            Ok((header, response_time)) => {
+                if u32::from(header.chain_length()) > local_length {
+                    ahead.push((peer, header, response_time));
+                }
+            }
+            Err(e) => failed.push((peer, e)),
+        }
+    }
+
+    let pulls = ahead
+        .iter()
+        .map(|(peer, header, _)| {
+            Timeout::new(
+                sync_peers[*peer]
+                    .client
+                    .pull_headers(checkpoints.as_slice(), &header.hash())
+                    .map_err(|e| Error::PullRequestFailed { source: e })
+                    .and_then(|stream| {
+                        stream
+                            .take(SYNC_HEADERS_PER_ROUND)
+                            .collect()
+                            .map_err(|e| Error::PullStreamFailed { source: e })
+                    }),
+                SYNC_PEER_TIMEOUT,
+            )
+            .map_err(sync_timeout)
+            .then(Ok::<_, Error>)
+        })
+        .collect::<Vec<_>>();
+    let pulled = runtime.block_on(future::join_all(pulls))?;
+
+    let mut candidates = Vec::new();
+    for ((peer, tip_header, response_time), res) in ahead.into_iter().zip(pulled) {
+        let tip = tip_header.hash();
+        let checked = res.and_then(|headers| {
+            check_header_chain(&headers, tip)?;
+            check_trusted_checkpoints(&headers, trusted_checkpoints)?;
+            let landing = match headers.first() {
+                Some(first) => runtime
+                    .block_on(blockchain.get_ref(first.block_parent_hash()))
+                    .map_err(|e| Error::HeaderCheckFailed { source: e })?,
+                None => None,
+            };
+            if let Some(landing) = landing {
+                check_header_leadership(&headers, &landing, trusted_chain)?;
+            }
+            Ok(headers)
         });
+        match checked {
+            Ok(headers) => candidates.push(Candidate {
+                peer,
+                tip,
+                tip_length: u32::from(tip_header.chain_length()),
+                headers,
+                response_time,
+            }),
+            Err(e) => failed.push((peer, e)),
+        }
+    }
+    Ok((candidates, failed))
+}
+
+// The headers must link to each other, and lead to the tip of the peer
+// unless the stream was cut at the end of the round.
+fn check_header_chain(headers: &[Header], tip: HeaderHash) -> Result<(), Error> {
+    for pair in headers.windows(2) {
+        let (parent, header) = (&pair[0], &pair[1]);
+        if header.block_parent_hash() != parent.hash() {
+            return Err(Error::HeaderChainBroken(header.hash()));
+        }
+        blockchain::pre_verify_link(header, parent).map_err(|e| Error::HeaderInvalid {
+            header: header.hash(),
+            reason: e.to_string(),
+        })?;
+    }
+    match headers.last() {
+        Some(last) if last.hash() == tip => Ok(()),
+        Some(_) if headers.len() as u64 == SYNC_HEADERS_PER_ROUND => Ok(()),
+        _ => Err(Error::HeaderChainIncomplete(tip)),
+    }
+}
+
+// A header at the chain length of a trusted checkpoint must be that block.
+fn check_trusted_checkpoints(
+    headers: &[Header],
+    trusted_checkpoints: &[TrustedCheckpoint],
+) -> Result<(), Error> {
+    for header in headers {
+        let chain_length = u32::from(header.chain_length());
+        let checkpoint = trusted_checkpoints
+            .iter()
+            .find(|checkpoint| checkpoint.chain_length == chain_length);
+        match checkpoint {
+            Some(checkpoint) if checkpoint.hash != header.hash() => {
+                return Err(Error::CheckpointMismatch {
+                    chain_length,
+                    expected: checkpoint.hash,
+                    received: header.hash(),
+                })
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+// The blocks proven to be ancestors of the last trusted checkpoint are not
+// verified against the leadership schedule.
+fn is_trusted(header: &Header, trusted_chain: &HashSet<HeaderHash>) -> bool {
+    trusted_chain.contains(&header.hash())
+}
+
+// The headers in the epoch of the block the chain lands on have the
+// leadership schedule of that block, so they are verified before their
+// blocks are downloaded. The headers of the following epochs need the
+// ledger state their blocks lead to, and are verified as they are applied.
+fn check_header_leadership(
+    headers: &[Header],
+    landing: &Ref,
+    trusted_chain: &HashSet<HeaderHash>,
+) -> Result<(), Error> {
+    let epoch = landing.block_date().epoch;
+    let leadership = landing.epoch_leadership_schedule();
+    for header in headers
+        .iter()
+        .take_while(|header| header.block_date().epoch == epoch)
+        .filter(|header| !is_trusted(header, trusted_chain))
+    {
+        if let Verification::Failure(e) = leadership.verify(header) {
+            return Err(Error::HeaderInvalid {
+                header: header.hash(),
+                reason: e.to_string(),
+            });
+        }
+    }
+    Ok(())
+}
+
+// The chain with the longest tip is selected, ties are broken by the
+// number of peers reporting the same tip. The peers reporting the
+// selected tip are returned along with it, the fastest to answer first,
+// which is the one the selected chain was pulled from.
+fn select_candidate(candidates: Vec<Candidate>) -> Option<(Candidate, Vec<usize>)> {
+    let support = |tip: &HeaderHash| candidates.iter().filter(|c| c.tip == *tip).count();
+    let (best, _) = candidates
+        .iter()
+        .enumerate()
+        .max_by_key(|(_, c)| (c.tip_length, support(&c.tip), Reverse(c.response_time)))?;
+    let mut sources = candidates
+        .iter()
+        .filter(|c| c.tip == candidates[best].tip)
+        .collect::<Vec<_>>();
+    sources.sort_by_key(|c| c.response_time);
+    let sources = sources.into_iter().map(|c| c.peer).collect();
+    candidates.into_iter().nth(best).map(|best| (best, sources))
+}
+
+// Requests the blocks of the selected chain from its sources in turn,
+// a request per chunk of headers. The requests are run concurrently, up to
+// one per source, and the blocks are yielded in the order of the chain
+// along with the peer they came from. A failure is reported with the peer.
+fn download_blocks(
+    sync_peers: &mut [SyncPeer],
+    best: &Candidate,
+    sources: &[usize],
+) -> impl Stream<Item = (usize, Vec<Block>), Error = (usize, Error)> {
+    let requests = best
+        .headers
+        .chunks(SYNC_BLOCKS_PER_REQUEST)
+        .zip(sources.iter().cycle())
+        .map(|(headers, &peer)| {
+            let ids = headers
+                .iter()
+                .map(|header| header.hash())
+                .collect::<Vec<_>>();
+            sync_peers[peer]
+                .client
+                .get_blocks(&ids)
+                .map_err(|e| Error::GetBlocksFailed { source: e })
+                .and_then(|stream| {
+                    stream
+                        .collect()
+                        .map_err(|e| Error::PullStreamFailed { source: e })
+                })
+                .map(move |blocks| (peer, blocks))
+                .map_err(move |e| (peer, e))
+        })
+        .collect::<Vec<_>>();
+    stream::iter_ok(requests).buffered(sources.len())
+}
+
+fn drop_sync_peers(
+    sync_peers: &mut Vec<SyncPeer>,
+    mut failed: Vec<(usize, Error)>,
+    logger: &Logger,
+) {
+    failed.sort_by(|a, b| b.0.cmp(&a.0));
+    failed.dedup_by_key(|(peer, _)| *peer);
+    for (peer, e) in failed {
+        let peer = sync_peers.remove(peer);
+        warn!(logger, "dropping sync peer"; "peer_addr" => %peer.addr, "reason" => %e);
+    }
+}
+
+fn sync_timeout(e: timeout::Error<Error>) -> Error {
+    e.into_inner().unwrap_or(Error::SyncPeerTimeout)
+}
+
+pub fn bootstrap_from_gateway(
+    gateway: &Gateway,
+    trusted_checkpoints: &[TrustedCheckpoint],
+    blockchain: Blockchain,
+    tip: Tip,
+    logger: Logger,
+) -> Result<(), Error> {
+    let mut runtime = Runtime::new().map_err(|e| Error::RuntimeInit { source: e })?;
+    let trusted_checkpoints = Arc::new(trusted_checkpoints.to_vec());
 
-    runtime.block_on(bootstrap)
+    let checkpoints = runtime.block_on(
+        blockchain
+            .get_checkpoints(tip.branch())
+            .map_err(|e| Error::GetCheckpointsFailed { source: e }),
+    )?;
+
+    // The gateway only serves the blocks following a block of its own chain,
+    // start from the latest checkpoint it knows.
+    let mut ids = None;
+    for checkpoint in checkpoints.iter() {
+        match gateway.next_ids(*checkpoint, gateway::MAX_NEXT_IDS) {
+            Ok(next_ids) => {
+                debug!(
+                    logger,
+                    "pulling blocks starting from checkpoint {}", checkpoint
+                );
+                ids = Some(next_ids);
+                break;
+            }
+            Err(gateway::Error::NotFound(_)) => {}
+            Err(e) => return Err(Error::Gateway { source: e }),
+        }
+    }
+    let mut ids = ids.ok_or(Error::GatewayCheckpointsNotFound)?;
+
+    while let Some(&last) = ids.last() {
+        let blocks = ids
+            .iter()
+            .map(|id| gateway.fetch_block(*id))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| Error::Gateway { source: e })?;
+        debug!(
+            logger,
+            "fetched {} blocks from the block gateway",
+            blocks.len()
+        );
+        runtime.block_on(bootstrap_from_stream(
+            blockchain.clone(),
+            tip.clone(),
+            stream::iter_ok(blocks),
+            trusted_checkpoints.clone(),
+            // the gateway does not serve headers, the blocks cannot be
+            // proven to be ancestors of the checkpoints before they are
+            // applied
+            Arc::new(HashSet::new()),
+            logger.clone(),
+        ))?;
+        ids = gateway
+            .next_ids(last, gateway::MAX_NEXT_IDS)
+            .map_err(|e| Error::Gateway { source: e })?;
+    }
+
+    Ok(())
 }
 
 fn bootstrap_from_stream<S>(
     blockchain: Blockchain,
     branch: Tip,
     stream: S,
+    trusted_checkpoints: Arc<Vec<TrustedCheckpoint>>,
+    trusted_chain: Arc<HashSet<HeaderHash>>,
     logger: Logger,
 ) -> impl Future<Item = (), Error = Error>
 where
@@ -102,7 +657,14 @@ where
             None,
             move |parent_tip: Option<Arc<Ref>>, block_or_err| match block_or_err {
                 Ok(block) => {
-                    let fut = handle_block(blockchain.clone(), block, logger.clone()).map(Some);
+                    let fut = handle_block(
+                        blockchain.clone(),
+                        block,
+                        &trusted_checkpoints,
+                        &trusted_chain,
+                        logger.clone(),
+                    )
+                    .map(Some);
                     Either::A(fut)
                 }
                 Err(e) => {
@@ -137,10 +699,16 @@ where
 fn handle_block(
     blockchain: Blockchain,
     block: Block,
+    trusted_checkpoints: &[TrustedCheckpoint],
+    trusted_chain: &HashSet<HeaderHash>,
     logger: Logger,
 ) -> impl Future<Item = Arc<Ref>, Error = Error> {
     let header = block.header();
-    blockchain
+    if let Err(e) = check_trusted_checkpoints(std::slice::from_ref(&header), trusted_checkpoints) {
+        return Either::A(future::err(e));
+    }
+    let trusted = is_trusted(&header, trusted_chain);
+    let future = blockchain
         .pre_check_header(header, true)
         .map_err(|e| Error::HeaderCheckFailed { source: e })
         .and_then(move |pre_checked| match pre_checked {
@@ -156,8 +724,12 @@ fn handle_block(
                 Either::A(future::err(Error::BlockMissingParent(header.hash())))
             }
             PreCheckedHeader::HeaderWithCache { header, parent_ref } => {
-                let future = blockchain
-                    .post_check_header(header, parent_ref)
+                let post_check = if trusted {
+                    Either::A(blockchain.post_check_trusted_header(header, parent_ref))
+                } else {
+                    Either::B(blockchain.post_check_header(header, parent_ref))
+                };
+                let future = post_check
                     .map_err(|e| Error::HeaderCheckFailed { source: e })
                     .and_then(move |post_checked| {
                         debug!(
@@ -173,5 +745,6 @@ fn handle_block(
                     });
                 Either::B(future)
             }
-        })
+        });
+    Either::B(future)
 }
@@ -0,0 +1,214 @@
+//! Catch-up through the block gateways while the node is running.
+//!
+//! When the tip of the node has not moved for a while, e.g. because the
+//! gRPC connections to the peers have been blocked since the bootstrap,
+//! the blocks following the tip are downloaded from the block gateways
+//! given in the configuration and passed to the blockchain task, which
+//! checks them as the blocks received from the peers.
+
+use super::{
+    buffer_sizes,
+    gateway::{self, Gateway},
+};
+use crate::{
+    blockcfg::Block,
+    blockchain::{Checkpoints, Ref, Tip},
+    intercom::{self, BlockMsg},
+    utils::{async_msg::MessageBox, task::TokioServiceInfo},
+};
+use chain_time::{
+    era::{EpochPosition, EpochSlotOffset},
+    Epoch,
+};
+use futures::future;
+use futures::prelude::*;
+use futures::sync::mpsc;
+use reqwest::Url;
+use slog::Logger;
+use thiserror::Error;
+use tokio::timer::Interval;
+use tokio_threadpool::Builder;
+
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+/// how often the tip is checked
+const CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+/// maximum number of blocks downloaded in one catch-up,
+/// the next check resumes from the new tip
+const MAX_BLOCKS: usize = 10 * gateway::MAX_NEXT_IDS;
+
+#[derive(Error, Debug)]
+enum Error {
+    #[error("block gateway request failed")]
+    Gateway {
+        #[from]
+        source: gateway::Error,
+    },
+    #[error("none of the checkpoints is known to the block gateway")]
+    CheckpointsNotFound,
+    #[error("the blockchain task stopped taking the blocks")]
+    Interrupted,
+}
+
+pub fn catch_up(
+    service_info: TokioServiceInfo,
+    gateways: Vec<Url>,
+    outbound_bind_address: Option<IpAddr>,
+    blockchain_tip: Tip,
+    block_box: MessageBox<BlockMsg>,
+    behind_after: Duration,
+) -> impl Future<Item = (), Error = ()> {
+    let logger = service_info.logger().clone();
+    let err_logger = logger.clone();
+    // the gateway client blocks, the downloads are run out of the
+    // executor threads
+    let pool = Builder::new()
+        .pool_size(1)
+        .name_prefix("gateway-catch-up")
+        .build();
+    let gateways = Arc::new(gateways);
+
+    Interval::new_interval(CHECK_INTERVAL)
+        .map_err(move |e| error!(err_logger, "timer error: {}", e))
+        .and_then(move |_| blockchain_tip.get_ref())
+        .filter(move |tip| is_behind(tip, behind_after))
+        .for_each(move |tip| {
+            info!(
+                logger,
+                "the tip has not moved for a while, catching up through the block gateways";
+                "tip" => %tip.hash(),
+            );
+            let checkpoints = Checkpoints::new_from(tip);
+            let (sender, receiver) = mpsc::channel(buffer_sizes::inbound::BLOCKS);
+            let gateways = gateways.clone();
+            let download_logger = logger.clone();
+            let download = pool.spawn_handle(future::lazy(move || {
+                download(
+                    &gateways,
+                    outbound_bind_address,
+                    &checkpoints,
+                    sender,
+                    &download_logger,
+                );
+                Ok::<_, ()>(())
+            }));
+            // a failed catch-up is tried again at the next check
+            forward_blocks(receiver, block_box.clone(), logger.clone())
+                .join(download)
+                .then(|_| Ok(()))
+        })
+}
+
+fn is_behind(tip: &Ref, behind_after: Duration) -> bool {
+    let tip_date = tip.block_date();
+    let slot = tip
+        .epoch_leadership_schedule()
+        .era()
+        .from_era_to_slot(EpochPosition {
+            epoch: Epoch(tip_date.epoch),
+            slot: EpochSlotOffset(tip_date.slot_id),
+        });
+    tip.time_frame()
+        .slot_to_systemtime(slot)
+        .and_then(|tip_time| SystemTime::now().duration_since(tip_time).ok())
+        .map_or(false, |since_tip| since_tip > behind_after)
+}
+
+// Downloads the blocks following the tip from the first gateway
+// that has some.
+fn download(
+    gateways: &[Url],
+    outbound_bind_address: Option<IpAddr>,
+    checkpoints: &Checkpoints,
+    sender: mpsc::Sender<Block>,
+    logger: &Logger,
+) {
+    for url in gateways {
+        let logger = logger.new(o!("gateway" => url.to_string()));
+        let result = Gateway::new(url.clone(), outbound_bind_address)
+            .map_err(Error::from)
+            .and_then(|gateway| download_from(&gateway, checkpoints, sender.clone()));
+        match result {
+            Ok(0) => debug!(logger, "no block follows the tip on the block gateway"),
+            Ok(downloaded) => {
+                info!(
+                    logger,
+                    "downloaded blocks from the block gateway";
+                    "count" => downloaded,
+                );
+                return;
+            }
+            Err(e) => warn!(logger, "catch-up through the block gateway failed"; "reason" => %e),
+        }
+    }
+}
+
+fn download_from(
+    gateway: &Gateway,
+    checkpoints: &Checkpoints,
+    mut sender: mpsc::Sender<Block>,
+) -> Result<usize, Error> {
+    // The gateway only serves the blocks following a block of its own chain,
+    // start from the latest checkpoint it knows.
+    let mut ids = None;
+    for checkpoint in checkpoints.iter() {
+        match gateway.next_ids(*checkpoint, gateway::MAX_NEXT_IDS) {
+            Ok(next_ids) => {
+                ids = Some(next_ids);
+                break;
+            }
+            Err(gateway::Error::NotFound(_)) => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
+    let mut ids = ids.ok_or(Error::CheckpointsNotFound)?;
+
+    let mut downloaded = 0;
+    while let Some(&last) = ids.last() {
+        for id in ids {
+            let block = gateway.fetch_block(id)?;
+            sender = sender.send(block).wait().map_err(|_| Error::Interrupted)?;
+            downloaded += 1;
+        }
+        if downloaded >= MAX_BLOCKS {
+            break;
+        }
+        ids = gateway.next_ids(last, gateway::MAX_NEXT_IDS)?;
+    }
+    Ok(downloaded)
+}
+
+// Passes the downloaded blocks to the blockchain task.
+fn forward_blocks(
+    receiver: mpsc::Receiver<Block>,
+    block_box: MessageBox<BlockMsg>,
+    logger: Logger,
+) -> impl Future<Item = (), Error = ()> {
+    let (handle, sink) = intercom::stream_request::<Block, (), intercom::Error>(
+        buffer_sizes::inbound::BLOCKS,
+        logger.clone(),
+    );
+    let err_logger = logger.clone();
+    let blocks = receiver.map_err(|()| intercom::Error::aborted("block download was interrupted"));
+    block_box
+        .send(BlockMsg::NetworkBlocks(handle, None))
+        .map_err(move |e| {
+            error!(
+                logger,
+                "failed to enqueue request for processing";
+                "reason" => %e,
+            );
+        })
+        .and_then(move |_mbox| {
+            sink.send_all(blocks).map(|_| ()).map_err(move |e| {
+                info!(
+                    err_logger,
+                    "blocks from the block gateway were refused";
+                    "reason" => %e,
+                );
+            })
+        })
+}
@@ -33,8 +33,14 @@ pub fn connect(
     let builder = Some(ClientBuilder {
         channels,
         logger: state.logger,
+        remote_addr: addr,
     });
-    let cf = grpc::connect(addr, Some(node_id), state.global.executor.clone());
+    let cf = grpc::connect(
+        addr,
+        state.global.config.outbound_bind_address,
+        Some(node_id),
+        state.global.executor.clone(),
+    );
     let handle = ConnectHandle { receiver };
     let future = ConnectFuture {
         sender: Some(sender),
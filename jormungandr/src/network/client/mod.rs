@@ -1,10 +1,11 @@
 mod connect;
 
 use super::{
+    block_fetch::ChunkSlot,
     buffer_sizes,
     p2p::{
-        comm::{OutboundSubscription, PeerComms},
-        Gossip as NodeData, Id,
+        comm::{ItemKind, OutboundSubscription, PeerComms},
+        Gossip as NodeData, Id, PeerEvent,
     },
     subscription::{BlockAnnouncementProcessor, FragmentProcessor, GossipProcessor},
     Channels, GlobalStateR,
@@ -12,7 +13,8 @@ use super::{
 use crate::{
     blockcfg::{Block, Fragment, Header, HeaderHash},
     intercom::{self, BlockMsg, ClientMsg},
-    utils::async_msg::MessageBox,
+    settings::start::network::Keepalive,
+    utils::{async_msg::MessageBox, bandwidth::WireSize},
 };
 use network_core::client as core_client;
 use network_core::client::{BlockService, FragmentService, GossipService, P2pService};
@@ -21,9 +23,49 @@ use network_core::subscription::{BlockEvent, ChainPullRequest};
 
 use futures::prelude::*;
 use slog::Logger;
+use tokio::timer::Delay;
+
+use std::net::SocketAddr;
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+use std::time::{Duration, Instant};
 
 pub use self::connect::{connect, ConnectError, ConnectFuture, ConnectHandle};
 
+/// A peer taking longer than this to start responding to a request
+/// is considered slow.
+const SLOW_RESPONSE_THRESHOLD: Duration = Duration::from_secs(5);
+
+/// Items received on a response stream and their size on the wire,
+/// recorded for the peer once when the stream is over.
+#[derive(Clone, Default)]
+struct ReceivedTally {
+    count: Arc<AtomicUsize>,
+    bytes: Arc<AtomicUsize>,
+}
+
+impl ReceivedTally {
+    fn add<T: WireSize>(&self, item: &T) {
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.bytes.fetch_add(item.wire_size(), Ordering::Relaxed);
+    }
+
+    fn record(&self, global_state: &GlobalStateR, node_id: Id, kind: ItemKind) {
+        let count = self.count.load(Ordering::Relaxed);
+        if count == 0 {
+            return;
+        }
+        let bytes = self.bytes.load(Ordering::Relaxed);
+        global_state.spawn(
+            global_state
+                .peers
+                .record_received(node_id, kind, count, bytes),
+        );
+    }
+}
+
 #[must_use = "Client must be polled"]
 pub struct Client<S>
 where
@@ -42,11 +84,18 @@ where
     incoming_block_announcement: Option<Header>,
     incoming_solicitation: Option<ClientMsg>,
     incoming_fragment: Option<Fragment>,
+    keepalive: Keepalive,
+    // when something was last received from the peer; what the node
+    // sends does not tell whether the peer is still there
+    last_activity: Instant,
+    keepalive_timer: Delay,
+    keepalive_request: Option<<S as BlockService>::TipFuture>,
 }
 
 struct ClientBuilder {
     pub logger: Logger,
     pub channels: Channels,
+    pub remote_addr: SocketAddr,
 }
 
 impl<S> Client<S>
@@ -91,6 +140,7 @@ where
         let fragment_sink = FragmentProcessor::new(
             builder.channels.transaction_box,
             remote_node_id,
+            builder.remote_addr.ip(),
             global_state.clone(),
             logger.new(o!("stream" => "fragments", "direction" => "in")),
         );
@@ -100,6 +150,9 @@ where
             logger.new(o!("stream" => "gossip", "direction" => "in")),
         );
 
+        let keepalive = global_state.config.keepalive;
+        let now = Instant::now();
+
         Client {
             service: inner,
             logger,
@@ -114,6 +167,10 @@ where
             incoming_block_announcement: None,
             incoming_solicitation: None,
             incoming_fragment: None,
+            keepalive,
+            last_activity: now,
+            keepalive_timer: Delay::new(now + keepalive.interval),
+            keepalive_request: None,
         }
     }
 }
@@ -204,7 +261,10 @@ where
             );
         }));
         let event = match maybe_event {
-            Some(event) => event,
+            Some(event) => {
+                self.last_activity = Instant::now();
+                event
+            }
             None => {
                 debug!(self.logger, "block event subscription ended by the peer");
                 return Ok(Disconnect.into());
@@ -232,7 +292,10 @@ where
             self.logger.new(o!("solicitation" => "UploadBlocks")),
         );
         debug_assert!(self.incoming_solicitation.is_none());
-        self.incoming_solicitation = Some(ClientMsg::GetBlocks(block_ids, reply_handle));
+        let max_depth = self
+            .global_state
+            .pull_depth_limit_for_id(self.remote_node_id());
+        self.incoming_solicitation = Some(ClientMsg::GetBlocks(block_ids, max_depth, reply_handle));
         let done_logger = self.logger.clone();
         let err_logger = self.logger.clone();
         self.global_state.spawn(
@@ -293,9 +356,15 @@ where
 {
     fn pull_headers(&mut self, req: ChainPullRequest<HeaderHash>) {
         let block_box = self.block_sink.message_box();
+        let node_id = self.remote_node_id();
         let logger = self.logger.new(o!("request" => "PullHeaders"));
         let req_err_logger = logger.clone();
         let res_logger = logger.clone();
+        let reputation = self.global_state.topology.reputation().clone();
+        let req_err_reputation = reputation.clone();
+        let stats_counter = self.global_state.stats_counter.clone();
+        let global_state = self.global_state.clone();
+        let started = Instant::now();
         let (handle, sink) = intercom::stream_request::<Header, (), core_error::Error>(
             buffer_sizes::inbound::HEADERS,
             logger.clone(),
@@ -325,15 +394,32 @@ where
                         "request failed";
                         "reason" => %e,
                     );
+                    req_err_reputation.record(node_id, PeerEvent::StreamDropped);
                 })
                 .and_then(move |stream| {
-                    sink.send_all(stream)
+                    let elapsed = started.elapsed();
+                    stats_counter.observe_peer_response_time(elapsed);
+                    global_state.spawn(global_state.peers.record_response_time(node_id, elapsed));
+                    if elapsed > SLOW_RESPONSE_THRESHOLD {
+                        reputation.record(node_id, PeerEvent::SlowResponse);
+                    }
+                    let tally = ReceivedTally::default();
+                    let received = {
+                        let tally = tally.clone();
+                        stream.inspect(move |header| tally.add(header))
+                    };
+                    sink.send_all(received)
+                        .then(move |res| {
+                            tally.record(&global_state, node_id, ItemKind::Header);
+                            res
+                        })
                         .map_err(move |e| {
                             info!(
                                 res_logger,
                                 "response stream failed";
                                 "reason" => %e,
                             );
+                            reputation.record(node_id, response_failure_event(&e));
                         })
                         .map(|_| ())
                 }),
@@ -349,10 +435,25 @@ where
     S::GetBlocksStream: Send + 'static,
 {
     fn solicit_blocks(&mut self, block_ids: &[HeaderHash]) {
+        let first = match block_ids.first() {
+            Some(first) => first,
+            None => return,
+        };
+        if let Some(slot) = self.global_state.block_fetch.claim(first) {
+            self.fetch_chunk(block_ids, slot);
+            return;
+        }
         let block_box = self.block_sink.message_box();
+        let node_id = self.remote_node_id();
         let logger = self.logger.new(o!("request" => "GetBlocks"));
         let req_err_logger = logger.clone();
         let res_logger = logger.clone();
+        let reputation = self.global_state.topology.reputation().clone();
+        let req_err_reputation = reputation.clone();
+        let res_err_reputation = reputation.clone();
+        let stats_counter = self.global_state.stats_counter.clone();
+        let global_state = self.global_state.clone();
+        let started = Instant::now();
         let (handle, sink) = intercom::stream_request::<Block, (), core_error::Error>(
             buffer_sizes::inbound::BLOCKS,
             logger.clone(),
@@ -363,7 +464,7 @@ where
         // https://github.com/input-output-hk/jormungandr/issues/1034
         self.global_state.spawn(
             block_box
-                .send(BlockMsg::NetworkBlocks(handle))
+                .send(BlockMsg::NetworkBlocks(handle, Some(node_id)))
                 .map_err(move |e| {
                     error!(
                         logger,
@@ -382,17 +483,106 @@ where
                         "request failed";
                         "reason" => %e,
                     );
+                    req_err_reputation.record(node_id, PeerEvent::StreamDropped);
                 })
                 .and_then(move |stream| {
-                    sink.send_all(stream)
+                    let elapsed = started.elapsed();
+                    stats_counter.observe_peer_response_time(elapsed);
+                    global_state.spawn(global_state.peers.record_response_time(node_id, elapsed));
+                    if elapsed > SLOW_RESPONSE_THRESHOLD {
+                        reputation.record(node_id, PeerEvent::SlowResponse);
+                    }
+                    let tally = ReceivedTally::default();
+                    let received = {
+                        let tally = tally.clone();
+                        stream.inspect(move |block| tally.add(block))
+                    };
+                    sink.send_all(received)
+                        .then(move |res| {
+                            tally.record(&global_state, node_id, ItemKind::Block);
+                            res
+                        })
                         .map_err(move |e| {
                             info!(
                                 res_logger,
                                 "response stream failed";
                                 "reason" => %e,
                             );
+                            res_err_reputation.record(node_id, response_failure_event(&e));
+                        })
+                        .map(move |_| {
+                            reputation.record(node_id, PeerEvent::BlocksServed);
+                        })
+                }),
+        );
+    }
+
+    // Downloads a chunk of a range of blocks fetched from several peers,
+    // handing the blocks over to the download once all are received.
+    fn fetch_chunk(&mut self, block_ids: &[HeaderHash], slot: ChunkSlot) {
+        let node_id = self.remote_node_id();
+        let logger = self.logger.new(o!("request" => "GetBlocks"));
+        let req_err_logger = logger.clone();
+        let res_err_logger = logger.clone();
+        let reputation = self.global_state.topology.reputation().clone();
+        let req_err_reputation = reputation.clone();
+        let res_err_reputation = reputation.clone();
+        let stats_counter = self.global_state.stats_counter.clone();
+        let global_state = self.global_state.clone();
+        let started = Instant::now();
+        let expected = block_ids.len();
+        self.global_state.spawn(
+            self.service
+                .get_blocks(block_ids)
+                .map_err(move |e| {
+                    info!(
+                        req_err_logger,
+                        "request failed";
+                        "reason" => %e,
+                    );
+                    req_err_reputation.record(node_id, PeerEvent::StreamDropped);
+                })
+                .and_then(move |stream| {
+                    let elapsed = started.elapsed();
+                    stats_counter.observe_peer_response_time(elapsed);
+                    global_state.spawn(global_state.peers.record_response_time(node_id, elapsed));
+                    if elapsed > SLOW_RESPONSE_THRESHOLD {
+                        reputation.record(node_id, PeerEvent::SlowResponse);
+                    }
+                    // one block past the expected ones is enough to
+                    // tell the peer sent too many
+                    stream
+                        .take(expected as u64 + 1)
+                        .collect()
+                        .map_err(move |e| {
+                            info!(
+                                res_err_logger,
+                                "response stream failed";
+                                "reason" => %e,
+                            );
+                            res_err_reputation.record(node_id, response_failure_event(&e));
+                        })
+                        .map(move |blocks| {
+                            let bytes = blocks.iter().map(WireSize::wire_size).sum();
+                            global_state.spawn(global_state.peers.record_received(
+                                node_id,
+                                ItemKind::Block,
+                                blocks.len(),
+                                bytes,
+                            ));
+                            if blocks.len() == expected {
+                                reputation.record(node_id, PeerEvent::BlocksServed);
+                                slot.complete(blocks);
+                            } else {
+                                // dropping the slot ends the download
+                                info!(
+                                    logger,
+                                    "peer did not send all the requested blocks";
+                                    "expected" => expected,
+                                    "received" => blocks.len(),
+                                );
+                            }
                         })
-                        .map(|_| ())
                 }),
         );
     }
@@ -431,7 +621,15 @@ where
                 "error" => %e,
             );
         }));
+        if maybe_fragment.is_some() {
+            self.last_activity = Instant::now();
+        }
         match maybe_fragment {
+            Some(_) if !self.global_state.config.topics.messages => {
+                // The node is not subscribed to the fragments,
+                // drop them.
+                Ok(Continue.into())
+            }
             Some(fragment) => {
                 debug_assert!(self.incoming_fragment.is_none());
                 self.incoming_fragment = Some(fragment);
@@ -461,6 +659,9 @@ where
                 "error" => %e,
             );
         }));
+        if maybe_gossip.is_some() {
+            self.last_activity = Instant::now();
+        }
         match maybe_gossip {
             Some(gossip) => {
                 self.gossip_processor.process_item(gossip);
@@ -474,6 +675,72 @@ where
     }
 }
 
+impl<S> Client<S>
+where
+    S: BlockService + FragmentService + GossipService,
+{
+    // Subscriptions to a peer that went away without closing the connection,
+    // e.g. behind a NAT dropping idle connections, stay silent rather than
+    // fail. A tip request is sent after a while without activity,
+    // and the peer is given up on if nothing comes in until the timeout.
+    fn process_keepalive(&mut self) -> Poll<ProcessingOutcome, ()> {
+        use self::ProcessingOutcome::*;
+
+        let node_id = self.inbound.node_id;
+        if let Some(request) = &mut self.keepalive_request {
+            match request.poll() {
+                Ok(Async::NotReady) => {}
+                Ok(Async::Ready(_)) => {
+                    self.keepalive_request = None;
+                    self.last_activity = Instant::now();
+                    return Ok(Continue.into());
+                }
+                Err(e) => {
+                    info!(
+                        self.logger,
+                        "keepalive request failed";
+                        "reason" => %e,
+                    );
+                    self.global_state
+                        .topology
+                        .reputation()
+                        .record(node_id, PeerEvent::StreamDropped);
+                    return Ok(Disconnect.into());
+                }
+            }
+        }
+
+        let logger = &self.logger;
+        try_ready!(self.keepalive_timer.poll().map_err(|e| {
+            error!(logger, "keepalive timer error: {:?}", e);
+        }));
+        let idle = self.last_activity.elapsed();
+        if idle >= self.keepalive.timeout {
+            info!(
+                self.logger,
+                "peer is unresponsive";
+                "idle" => ?idle,
+            );
+            self.global_state
+                .topology
+                .reputation()
+                .record(node_id, PeerEvent::StreamDropped);
+            return Ok(Disconnect.into());
+        }
+        if idle >= self.keepalive.interval && self.keepalive_request.is_none() {
+            debug!(self.logger, "sending keepalive"; "idle" => ?idle);
+            self.keepalive_request = Some(self.service.tip());
+        }
+        let deadline = if self.keepalive_request.is_some() {
+            self.last_activity + self.keepalive.timeout
+        } else {
+            self.last_activity + self.keepalive.interval
+        };
+        self.keepalive_timer.reset(deadline);
+        Ok(Continue.into())
+    }
+}
+
 impl<S> Future for Client<S>
 where
     S: core_client::Client,
@@ -498,11 +765,17 @@ where
         loop {
             // Drive any pending activity of the gRPC client until it is ready
             // to process another request.
+            let node_id = self.inbound.node_id;
+            let global_state = &self.global_state;
             try_ready!(self.service.poll_ready().map_err(|e| {
                 info!(
                     self.logger,
                     "client connection broke down";
                     "error" => ?e);
+                global_state
+                    .topology
+                    .reputation()
+                    .record(node_id, PeerEvent::StreamDropped);
             }));
 
             let mut progress = Progress(None);
@@ -510,6 +783,7 @@ where
             progress.update(self.process_block_event()?);
             progress.update(self.process_fragments()?);
             progress.update(self.process_gossip()?);
+            progress.update(self.process_keepalive()?);
 
             // Block solicitations and chain pulls are special:
             // they are handled with client requests on the client side,
@@ -553,3 +827,12 @@ where
         }
     }
 }
+
+// A response stream failing because the node rejected the data tells more
+// about the peer than a stream failing for other reasons.
+fn response_failure_event(e: &core_error::Error) -> PeerEvent {
+    match e.code() {
+        core_error::Code::InvalidArgument => PeerEvent::InvalidData,
+        _ => PeerEvent::StreamDropped,
+    }
+}
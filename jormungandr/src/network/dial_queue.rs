@@ -0,0 +1,181 @@
+//! Queue of the dials to peers the node has no connection with.
+//!
+//! A propagation or a round of gossip may select many peers the node is not
+//! connected to, right after a restart most of them. Rather than dialling
+//! them all at once, the dials are queued and at most a configured number
+//! of them is in progress at any time; the next ones are started as the
+//! earlier ones establish their connection or fail.
+//!
+//! A peer is queued only once: an item to deliver to a peer already waiting
+//! in the queue is added to its pending dial, and an item to deliver to a
+//! peer being dialled is kept until the dial completes, to be sent over
+//! the connection once established.
+
+use super::p2p::{comm::ConnectOptions, Id};
+
+use std::collections::{HashMap, VecDeque};
+use std::net::SocketAddr;
+use std::sync::Mutex;
+
+/// A dial to be started.
+pub struct Dial {
+    pub node_id: Id,
+    pub addr: SocketAddr,
+    pub options: ConnectOptions,
+}
+
+pub struct DialQueue {
+    inner: Mutex<Inner>,
+}
+
+struct Inner {
+    max_concurrent: usize,
+    /// the items to deliver over the connections being established
+    in_progress: HashMap<Id, ConnectOptions>,
+    order: VecDeque<Id>,
+    queued: HashMap<Id, (SocketAddr, ConnectOptions)>,
+}
+
+impl DialQueue {
+    pub fn new(max_concurrent: usize) -> Self {
+        DialQueue {
+            inner: Mutex::new(Inner {
+                max_concurrent: std::cmp::max(max_concurrent, 1),
+                in_progress: HashMap::new(),
+                order: VecDeque::new(),
+                queued: HashMap::new(),
+            }),
+        }
+    }
+
+    /// Queues a dial to the peer and returns the dials that can be
+    /// started right away. The items of a peer being dialled are kept
+    /// for its connection.
+    pub fn push(&self, node_id: Id, addr: SocketAddr, options: ConnectOptions) -> Vec<Dial> {
+        let mut inner = self.inner.lock().unwrap();
+        if let Some(pending_options) = inner.in_progress.get_mut(&node_id) {
+            merge_options(pending_options, options);
+            return Vec::new();
+        }
+        match inner.queued.get_mut(&node_id) {
+            Some((_, queued_options)) => merge_options(queued_options, options),
+            None => {
+                inner.order.push_back(node_id);
+                inner.queued.insert(node_id, (addr, options));
+            }
+        }
+        inner.start_dials()
+    }
+
+    /// Marks the dial to the peer as completed, whether it has succeeded
+    /// or not. Returns the items given for the peer while it was being
+    /// dialled, and the dials that can be started in its place.
+    pub fn complete(&self, node_id: Id) -> (ConnectOptions, Vec<Dial>) {
+        let mut inner = self.inner.lock().unwrap();
+        let pending_options = inner.in_progress.remove(&node_id).unwrap_or_default();
+        (pending_options, inner.start_dials())
+    }
+}
+
+impl Inner {
+    fn start_dials(&mut self) -> Vec<Dial> {
+        let mut dials = Vec::new();
+        while self.in_progress.len() < self.max_concurrent {
+            let node_id = match self.order.pop_front() {
+                Some(node_id) => node_id,
+                None => break,
+            };
+            let (addr, options) = self
+                .queued
+                .remove(&node_id)
+                .expect("queued dial without its options");
+            self.in_progress.insert(node_id, ConnectOptions::default());
+            dials.push(Dial {
+                node_id,
+                addr,
+                options,
+            });
+        }
+        dials
+    }
+}
+
+// The latest items supersede the ones waiting for the same dial.
+fn merge_options(queued: &mut ConnectOptions, newer: ConnectOptions) {
+    if newer.pending_block_announcement.is_some() {
+        queued.pending_block_announcement = newer.pending_block_announcement;
+    }
+    if newer.pending_fragment.is_some() {
+        queued.pending_fragment = newer.pending_fragment;
+    }
+    if newer.pending_gossip.is_some() {
+        queued.pending_gossip = newer.pending_gossip;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::p2p::Gossip;
+    use network_core::gossip;
+    use poldercast::NodeProfileBuilder;
+
+    fn new_id() -> Id {
+        (*NodeProfileBuilder::new().build().id()).into()
+    }
+
+    fn addr() -> SocketAddr {
+        "127.0.0.1:3000".parse().unwrap()
+    }
+
+    fn with_gossip() -> ConnectOptions {
+        let node = Gossip::from(NodeProfileBuilder::new().build());
+        ConnectOptions {
+            pending_gossip: Some(gossip::Gossip::from_nodes(vec![node])),
+            ..Default::default()
+        }
+    }
+
+    fn dialled(dials: &[Dial]) -> Vec<Id> {
+        dials.iter().map(|dial| dial.node_id).collect()
+    }
+
+    #[test]
+    fn at_most_max_concurrent_dials() {
+        let queue = DialQueue::new(2);
+        let (a, b, c) = (new_id(), new_id(), new_id());
+        assert_eq!(dialled(&queue.push(a, addr(), Default::default())), vec![a]);
+        assert_eq!(dialled(&queue.push(b, addr(), Default::default())), vec![b]);
+        assert!(queue.push(c, addr(), Default::default()).is_empty());
+        let (_, next) = queue.complete(a);
+        assert_eq!(dialled(&next), vec![c]);
+        let (_, next) = queue.complete(b);
+        assert!(next.is_empty());
+    }
+
+    #[test]
+    fn queued_peer_is_dialled_once_with_its_items() {
+        let queue = DialQueue::new(1);
+        let (a, b) = (new_id(), new_id());
+        queue.push(a, addr(), Default::default());
+        assert!(queue.push(b, addr(), Default::default()).is_empty());
+        assert!(queue.push(b, addr(), with_gossip()).is_empty());
+        let (_, next) = queue.complete(a);
+        assert_eq!(dialled(&next), vec![b]);
+        assert!(next[0].options.pending_gossip.is_some());
+    }
+
+    #[test]
+    fn items_for_peer_being_dialled_are_kept() {
+        let queue = DialQueue::new(1);
+        let a = new_id();
+        queue.push(a, addr(), Default::default());
+        assert!(queue.push(a, addr(), with_gossip()).is_empty());
+        let (pending_options, _) = queue.complete(a);
+        assert!(pending_options.pending_gossip.is_some());
+        // the items are only delivered once
+        queue.push(a, addr(), Default::default());
+        let (pending_options, _) = queue.complete(a);
+        assert!(pending_options.pending_gossip.is_none());
+    }
+}
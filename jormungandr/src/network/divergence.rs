@@ -0,0 +1,386 @@
+//! Detection of the node forking off the chain followed by the network.
+//!
+//! The tip of the node is periodically compared with the tips of reference
+//! nodes given in the configuration. A reference node agrees with the node
+//! when one of the tips descends from the other; otherwise the headers
+//! following the latest common checkpoint are pulled from the reference
+//! node to find the block where the chains part. An alert is raised when
+//! the majority of the reference nodes disagrees with the node for longer
+//! than the configured number of slots.
+
+use super::grpc;
+use crate::blockcfg::{ChainLength, Header, HeaderHash};
+use crate::blockchain::{Checkpoints, Ref, Storage, Tip};
+use crate::settings::start::network::DivergenceCheck;
+use crate::utils::task::TokioServiceInfo;
+use chain_storage::error::Error as StorageError;
+use network_core::client::{BlockService, Client as _};
+use network_core::error::{Code, Error as NetworkError};
+
+use futures::future::{self, Either};
+use futures::prelude::*;
+use slog::Logger;
+use thiserror::Error;
+use tokio::timer::Interval;
+use tokio_compat::runtime::TaskExecutor;
+
+use std::net::{IpAddr, SocketAddr};
+use std::time::Instant;
+
+#[derive(Error, Debug)]
+enum Error {
+    #[error("failed to connect to the reference node")]
+    Connect { source: grpc::ConnectError },
+    #[error("connection broken")]
+    ClientNotReady { source: NetworkError },
+    #[error("tip request failed")]
+    TipRequestFailed { source: NetworkError },
+    #[error("header pull request failed")]
+    PullRequestFailed { source: NetworkError },
+    #[error("header pull stream failed")]
+    PullStreamFailed { source: NetworkError },
+    #[error("storage lookup failed")]
+    Storage { source: StorageError },
+}
+
+/// The tip of the node being checked, as reported.
+struct CheckedTip {
+    hash: HeaderHash,
+    chain_length: ChainLength,
+    slot_duration: u64,
+}
+
+impl CheckedTip {
+    fn new(tip: &Ref) -> Self {
+        CheckedTip {
+            hash: tip.hash(),
+            chain_length: tip.chain_length(),
+            slot_duration: tip.time_frame().slot_duration(),
+        }
+    }
+}
+
+/// The position of the tip of a reference node relative to the node's tip.
+#[derive(Clone, Copy, Debug)]
+enum Verdict {
+    /// one of the tips descends from the other
+    Agree,
+    /// the chains have parted, after the given block if it could be found
+    Diverge { fork_point: Option<HeaderHash> },
+    /// the reference node could not be queried
+    Unknown,
+}
+
+pub fn check_tip_divergence(
+    service_info: TokioServiceInfo,
+    storage: Storage,
+    blockchain_tip: Tip,
+    config: DivergenceCheck,
+    outbound_bind_address: Option<IpAddr>,
+) -> impl Future<Item = (), Error = ()> {
+    let logger = service_info.logger().clone();
+    let err_logger = logger.clone();
+    let executor = service_info.executor().clone();
+
+    Interval::new_interval(config.check_interval)
+        .map_err(move |e| error!(err_logger, "timer error: {}", e))
+        .and_then(move |_| blockchain_tip.get_ref())
+        .fold(None, move |diverging_since, tip| {
+            let checkpoints = Checkpoints::new_from(tip.clone());
+            let stability_depth = tip.epoch_ledger_parameters().epoch_stability_depth;
+            let queries = config.reference_nodes.iter().map(|addr| {
+                let logger = logger.new(o!("reference_node" => addr.to_string()));
+                query_reference_node(
+                    *addr,
+                    outbound_bind_address,
+                    tip.hash(),
+                    checkpoints.clone(),
+                    stability_depth,
+                    storage.clone(),
+                    executor.clone(),
+                    logger,
+                )
+            });
+            let logger = logger.clone();
+            let max_divergence_slots = config.max_divergence_slots;
+            future::join_all(queries).map(move |verdicts| {
+                report(
+                    &CheckedTip::new(&tip),
+                    &verdicts,
+                    max_divergence_slots,
+                    diverging_since,
+                    Instant::now(),
+                    &logger,
+                )
+            })
+        })
+        .map(|_| ())
+}
+
+// Logs the outcome of a check, returns the time since which the tip
+// differs from the one of the majority of the reference nodes.
+fn report(
+    tip: &CheckedTip,
+    verdicts: &[Verdict],
+    max_divergence_slots: u64,
+    diverging_since: Option<Instant>,
+    now: Instant,
+    logger: &Logger,
+) -> Option<Instant> {
+    let mut agreeing = 0;
+    let mut diverging = 0;
+    let mut fork_point = None;
+    for verdict in verdicts {
+        match verdict {
+            Verdict::Agree => agreeing += 1,
+            Verdict::Diverge { fork_point: point } => {
+                diverging += 1;
+                fork_point = fork_point.or(*point);
+            }
+            Verdict::Unknown => {}
+        }
+    }
+
+    if agreeing + diverging == 0 {
+        warn!(logger, "none of the reference nodes could be queried");
+        return diverging_since;
+    }
+    if diverging * 2 <= agreeing + diverging {
+        if diverging_since.is_some() {
+            info!(
+                logger,
+                "chain tip agrees with the reference nodes again";
+                "tip" => %tip.hash,
+            );
+        }
+        return None;
+    }
+
+    let since = diverging_since.unwrap_or(now);
+    let slot_duration = std::cmp::max(tip.slot_duration, 1);
+    let slots = now.duration_since(since).as_secs() / slot_duration;
+    if slots > max_divergence_slots {
+        let fork_point = fork_point.map_or_else(|| "unknown".to_owned(), |hash| hash.to_string());
+        error!(
+            logger,
+            "chain tip diverges from the majority of the reference nodes";
+            "tip" => %tip.hash,
+            "chain_length" => %tip.chain_length,
+            "diverging" => diverging,
+            "queried" => agreeing + diverging,
+            "slots" => slots,
+            "fork_point" => fork_point,
+        );
+    } else {
+        debug!(
+            logger,
+            "chain tip differs from the majority of the reference nodes";
+            "tip" => %tip.hash,
+            "slots" => slots,
+        );
+    }
+    Some(since)
+}
+
+fn query_reference_node(
+    addr: SocketAddr,
+    outbound_bind_address: Option<IpAddr>,
+    our_tip: HeaderHash,
+    checkpoints: Checkpoints,
+    stability_depth: u32,
+    storage: Storage,
+    executor: TaskExecutor,
+    logger: Logger,
+) -> impl Future<Item = Verdict, Error = ()> {
+    grpc::connect(addr, outbound_bind_address, None, executor)
+        .map_err(|e| Error::Connect { source: e })
+        .and_then(|client: grpc::Connection| {
+            client
+                .ready()
+                .map_err(|e| Error::ClientNotReady { source: e })
+        })
+        .and_then(|mut client| {
+            client
+                .tip()
+                .map_err(|e| Error::TipRequestFailed { source: e })
+                .map(move |header| (client, header.hash()))
+        })
+        .and_then(move |(client, their_tip)| {
+            if their_tip == our_tip {
+                return Either::A(future::ok(Verdict::Agree));
+            }
+            // The reference node may be lagging behind on the node's chain.
+            let fut = storage
+                .find_closest_ancestor(vec![their_tip], our_tip)
+                .map_err(|e| Error::Storage { source: e })
+                .and_then(move |ancestor| match ancestor {
+                    Some(_) => Either::A(future::ok(Verdict::Agree)),
+                    None => Either::B(find_fork_point(
+                        client,
+                        our_tip,
+                        their_tip,
+                        checkpoints,
+                        stability_depth,
+                        storage,
+                    )),
+                });
+            Either::B(fut)
+        })
+        .then(move |res| match res {
+            Ok(verdict) => {
+                debug!(logger, "reference node checked"; "verdict" => ?verdict);
+                Ok(verdict)
+            }
+            Err(e) => {
+                info!(
+                    logger,
+                    "failed to check the tip of the reference node";
+                    "reason" => %e,
+                );
+                Ok(Verdict::Unknown)
+            }
+        })
+}
+
+// The reference node streams its chain from the latest of the node's
+// checkpoints it knows; the first header of that stream missing from
+// the storage follows the block where the chains part. A fork point
+// deeper than the epoch stability depth is not looked for.
+fn find_fork_point(
+    mut client: grpc::Connection,
+    our_tip: HeaderHash,
+    their_tip: HeaderHash,
+    checkpoints: Checkpoints,
+    stability_depth: u32,
+    storage: Storage,
+) -> impl Future<Item = Verdict, Error = Error> {
+    client
+        .pull_headers(checkpoints.as_slice(), &their_tip)
+        .map_err(|e| Error::PullRequestFailed { source: e })
+        .and_then(move |stream| {
+            stream
+                .take(u64::from(stability_depth))
+                .map_err(|e| Error::PullStreamFailed { source: e })
+                .and_then(move |header: Header| {
+                    storage
+                        .block_exists(header.hash())
+                        .map_err(|e| Error::Storage { source: e })
+                        .map(move |known| (header, known))
+                })
+                .skip_while(|(_, known)| Ok(*known))
+                .into_future()
+                .map_err(|(e, _)| e)
+        })
+        .then(move |res| match res {
+            Ok((Some((header, _)), _)) => {
+                let parent = header.block_parent_hash();
+                if parent == our_tip {
+                    Ok(Verdict::Agree)
+                } else {
+                    Ok(Verdict::Diverge {
+                        fork_point: Some(parent),
+                    })
+                }
+            }
+            // All the blocks of the reference node are known, but its
+            // tip is on another branch, or the chains part deeper than
+            // the stability depth.
+            Ok((None, _)) => Ok(Verdict::Diverge { fork_point: None }),
+            Err(Error::PullRequestFailed { source }) => match source.code() {
+                // None of the checkpoints are known to the reference node.
+                Code::NotFound => Ok(Verdict::Diverge { fork_point: None }),
+                _ => Err(Error::PullRequestFailed { source }),
+            },
+            Err(e) => Err(e),
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    const SLOT_DURATION: u64 = 2;
+    const MAX_DIVERGENCE_SLOTS: u64 = 10;
+
+    fn tip() -> CheckedTip {
+        CheckedTip {
+            hash: HeaderHash::hash_bytes(b"tip"),
+            chain_length: ChainLength::from(100),
+            slot_duration: SLOT_DURATION,
+        }
+    }
+
+    fn diverge() -> Verdict {
+        Verdict::Diverge {
+            fork_point: Some(HeaderHash::hash_bytes(b"fork point")),
+        }
+    }
+
+    fn report_at(
+        verdicts: &[Verdict],
+        diverging_since: Option<Instant>,
+        now: Instant,
+    ) -> Option<Instant> {
+        let logger = Logger::root(slog::Discard, o!());
+        report(
+            &tip(),
+            verdicts,
+            MAX_DIVERGENCE_SLOTS,
+            diverging_since,
+            now,
+            &logger,
+        )
+    }
+
+    #[test]
+    fn agreeing_majority_ends_the_divergence() {
+        let now = Instant::now();
+        let verdicts = [Verdict::Agree, Verdict::Agree, diverge()];
+        assert_eq!(report_at(&verdicts, None, now), None);
+        assert_eq!(report_at(&verdicts, Some(now), now), None);
+    }
+
+    #[test]
+    fn diverging_majority_starts_the_divergence() {
+        let now = Instant::now();
+        let verdicts = [Verdict::Agree, diverge(), diverge()];
+        assert_eq!(report_at(&verdicts, None, now), Some(now));
+    }
+
+    #[test]
+    fn divergence_is_dated_from_its_start() {
+        let since = Instant::now();
+        let verdicts = [diverge(), diverge()];
+        // before and past the maximum divergence, the start is kept
+        let before = since + Duration::from_secs(SLOT_DURATION * MAX_DIVERGENCE_SLOTS);
+        assert_eq!(report_at(&verdicts, Some(since), before), Some(since));
+        let past = since + Duration::from_secs(SLOT_DURATION * (MAX_DIVERGENCE_SLOTS + 1));
+        assert_eq!(report_at(&verdicts, Some(since), past), Some(since));
+    }
+
+    #[test]
+    fn tie_is_not_a_divergence() {
+        let now = Instant::now();
+        let verdicts = [Verdict::Agree, diverge()];
+        assert_eq!(report_at(&verdicts, Some(now), now), None);
+    }
+
+    #[test]
+    fn unknown_verdicts_are_not_counted() {
+        let now = Instant::now();
+        let verdicts = [Verdict::Unknown, Verdict::Unknown, diverge()];
+        assert_eq!(report_at(&verdicts, None, now), Some(now));
+        let verdicts = [Verdict::Unknown, Verdict::Unknown, Verdict::Agree];
+        assert_eq!(report_at(&verdicts, Some(now), now), None);
+    }
+
+    #[test]
+    fn no_answer_keeps_the_state() {
+        let now = Instant::now();
+        let verdicts = [Verdict::Unknown, Verdict::Unknown];
+        assert_eq!(report_at(&verdicts, None, now), None);
+        assert_eq!(report_at(&verdicts, Some(now), now), Some(now));
+        assert_eq!(report_at(&[], Some(now), now), Some(now));
+    }
+}
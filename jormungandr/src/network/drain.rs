@@ -0,0 +1,185 @@
+//! Graceful shutdown of the network service.
+//!
+//! When the node stops, the network service closes its listening socket,
+//! sends a last round of gossip to the connected peers and lets the block
+//! streams being served to them complete before closing the connections.
+//! The streams still in flight once the drain timeout has elapsed
+//! are canceled.
+
+use super::GlobalStateR;
+use crate::log;
+use futures::future::{self, Either, Loop};
+use futures::prelude::*;
+use futures::stream;
+use futures::sync::oneshot;
+use tokio::timer::{Delay, Timeout};
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// interval at which the block streams in flight are counted while draining
+const IN_FLIGHT_CHECK_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Handle to shut the network service down, kept by the node's main thread.
+pub struct ShutdownHandle {
+    request: oneshot::Sender<()>,
+    done: oneshot::Receiver<()>,
+}
+
+/// The end of the `ShutdownHandle` given to the network service.
+pub struct ShutdownSignal {
+    request: oneshot::Receiver<()>,
+    done: oneshot::Sender<()>,
+}
+
+pub fn shutdown_channel() -> (ShutdownHandle, ShutdownSignal) {
+    let (request_sender, request_receiver) = oneshot::channel();
+    let (done_sender, done_receiver) = oneshot::channel();
+    let handle = ShutdownHandle {
+        request: request_sender,
+        done: done_receiver,
+    };
+    let signal = ShutdownSignal {
+        request: request_receiver,
+        done: done_sender,
+    };
+    (handle, signal)
+}
+
+impl ShutdownHandle {
+    /// Requests the network service to shut down, blocking the calling
+    /// thread until the connections are drained. Returns immediately if
+    /// the network service has already terminated.
+    pub fn shutdown(self) {
+        if self.request.send(()).is_ok() {
+            let _ = self.done.wait();
+        }
+    }
+}
+
+impl ShutdownSignal {
+    /// the future resolved when the shutdown is requested, and the sender
+    /// to notify once the network service is done
+    pub(super) fn split(self) -> (impl Future<Item = (), Error = ()>, oneshot::Sender<()>) {
+        // Dropping the handle does not request the shutdown.
+        let requested = self.request.or_else(|_| future::empty());
+        (requested, self.done)
+    }
+}
+
+/// Signals to the listener, sent while draining.
+pub struct ListenerSignals {
+    stop_accepting: oneshot::Sender<()>,
+    close: oneshot::Sender<()>,
+}
+
+/// The listener's end of the `ListenerSignals`.
+pub struct ListenerControl {
+    /// resolved when no more connections must be accepted
+    pub stop_accepting: oneshot::Receiver<()>,
+    /// resolved when the established connections must be closed
+    pub close: oneshot::Receiver<()>,
+}
+
+pub fn listener_channel() -> (ListenerSignals, ListenerControl) {
+    let (stop_accepting_sender, stop_accepting_receiver) = oneshot::channel();
+    let (close_sender, close_receiver) = oneshot::channel();
+    let signals = ListenerSignals {
+        stop_accepting: stop_accepting_sender,
+        close: close_sender,
+    };
+    let control = ListenerControl {
+        stop_accepting: stop_accepting_receiver,
+        close: close_receiver,
+    };
+    (signals, control)
+}
+
+/// Number of the block streams being served to the peers.
+#[derive(Clone, Default)]
+pub struct InFlight(Arc<AtomicUsize>);
+
+impl InFlight {
+    pub fn track<S>(&self, stream: S) -> Tracked<S> {
+        self.0.fetch_add(1, Ordering::SeqCst);
+        Tracked {
+            inner: stream,
+            count: self.0.clone(),
+        }
+    }
+
+    pub fn count(&self) -> usize {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+/// A stream counted in flight until it is dropped.
+pub struct Tracked<S> {
+    inner: S,
+    count: Arc<AtomicUsize>,
+}
+
+impl<S: Stream> Stream for Tracked<S> {
+    type Item = S::Item;
+    type Error = S::Error;
+
+    fn poll(&mut self) -> Poll<Option<S::Item>, S::Error> {
+        self.inner.poll()
+    }
+}
+
+impl<S> Drop for Tracked<S> {
+    fn drop(&mut self) {
+        self.count.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+pub(super) fn drain(
+    state: GlobalStateR,
+    listener: ListenerSignals,
+) -> impl Future<Item = (), Error = ()> {
+    let logger = state.logger().new(o!(log::KEY_SUB_TASK => "drain"));
+    let timeout = state.config.drain_timeout;
+    info!(logger, "draining the P2P connections"; "timeout" => ?timeout);
+
+    let _ = listener.stop_accepting.send(());
+
+    let in_flight = state.block_streams.clone();
+    let gossip_and_wait = send_final_gossip(state.clone()).and_then(move |()| {
+        future::loop_fn((), move |()| {
+            if in_flight.count() == 0 {
+                Either::A(future::ok(Loop::Break(())))
+            } else {
+                let wait = Delay::new(Instant::now() + IN_FLIGHT_CHECK_INTERVAL)
+                    .map(|()| Loop::Continue(()))
+                    .map_err(|_| ());
+                Either::B(wait)
+            }
+        })
+    });
+
+    Timeout::new(gossip_and_wait, timeout).then(move |res| {
+        if res.is_err() {
+            warn!(
+                logger,
+                "drain timeout elapsed, canceling the block streams in flight";
+                "count" => state.block_streams.count(),
+            );
+        }
+        let _ = listener.close.send(());
+        state.peers.clear()
+    })
+}
+
+fn send_final_gossip(state: GlobalStateR) -> impl Future<Item = (), Error = ()> {
+    let topology = state.topology.clone();
+    state.peers.infos().and_then(move |infos| {
+        stream::iter_ok(infos).for_each(move |info| {
+            let peers = state.peers.clone();
+            topology.initiate_gossips(info.id).and_then(move |gossips| {
+                peers.propagate_gossip_to(info.id, gossips).then(|_| Ok(()))
+            })
+        })
+    })
+}
@@ -0,0 +1,178 @@
+//! Download of blocks over HTTP(S) from the REST interface of gateway nodes.
+//!
+//! This is a fallback for the environments where the gRPC connections to
+//! the peers are blocked. The blocks are fetched one by one by their hash,
+//! and go through the same checks as the blocks received from the peers.
+
+use crate::blockcfg::{Block, HeaderHash};
+use chain_core::property::Deserialize as _;
+use chain_crypto::Blake2b256;
+use reqwest::{Client, StatusCode, Url};
+use thiserror::Error;
+
+use std::fs::File;
+use std::io::{self, Read as _};
+use std::net::IpAddr;
+use std::path::Path;
+use std::time::Duration;
+
+/// the REST interface serves at most this many block identifiers
+/// in one response
+pub const MAX_NEXT_IDS: usize = 100;
+
+/// the responses of the gateway, at most a block, are read up to this size
+const MAX_BLOCK_SIZE: u64 = 16 * 1024 * 1024;
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// time given to the download of a chain snapshot, holding the whole chain
+const SNAPSHOT_DOWNLOAD_TIMEOUT: Duration = Duration::from_secs(3600);
+
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("HTTP client initialization failed")]
+    ClientInit { source: reqwest::Error },
+    #[error("request to the block gateway failed")]
+    Request { source: reqwest::Error },
+    #[error("block {0} is not known to the block gateway")]
+    NotFound(HeaderHash),
+    #[error("malformed response from the block gateway")]
+    Malformed { source: io::Error },
+    #[error("cannot write the downloaded chain snapshot")]
+    SnapshotFile { source: io::Error },
+    #[error(
+        "response from the block gateway is larger than {} bytes",
+        MAX_BLOCK_SIZE
+    )]
+    TooLarge,
+    #[error("requested at most {requested} block identifiers, received {received}")]
+    TooManyIds { requested: usize, received: usize },
+    #[error("requested block {requested}, received block {received}")]
+    UnexpectedBlock {
+        requested: HeaderHash,
+        received: HeaderHash,
+    },
+}
+
+/// Client of the REST interface of a gateway node.
+pub struct Gateway {
+    base: Url,
+    local_address: Option<IpAddr>,
+    client: Client,
+}
+
+impl Gateway {
+    /// the URL is the base of the REST API of the gateway,
+    /// the one followed by `/v0/...`. The connections are bound to
+    /// `local_address` if given.
+    pub fn new(base: Url, local_address: Option<IpAddr>) -> Result<Self, Error> {
+        let client = Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .local_address(local_address)
+            .build()
+            .map_err(|e| Error::ClientInit { source: e })?;
+        Ok(Gateway {
+            base,
+            local_address,
+            client,
+        })
+    }
+
+    pub fn fetch_block(&self, id: HeaderHash) -> Result<Block, Error> {
+        let body = self.get(&["v0", "block", &id.to_string()], id, &[])?;
+        let block =
+            Block::deserialize(body.as_slice()).map_err(|e| Error::Malformed { source: e })?;
+        let received = block.header.hash();
+        if received != id {
+            return Err(Error::UnexpectedBlock {
+                requested: id,
+                received,
+            });
+        }
+        Ok(block)
+    }
+
+    /// the identifiers of the blocks following the given one on the chain
+    /// of the gateway, up to `count` of them
+    pub fn next_ids(&self, from: HeaderHash, count: usize) -> Result<Vec<HeaderHash>, Error> {
+        let body = self.get(
+            &["v0", "block", &from.to_string(), "next_id"],
+            from,
+            &[("count", count)],
+        )?;
+        let ids = body
+            .chunks(Blake2b256::HASH_SIZE)
+            .map(|id| HeaderHash::deserialize(id).map_err(|e| Error::Malformed { source: e }))
+            .collect::<Result<Vec<_>, _>>()?;
+        if ids.len() > count {
+            return Err(Error::TooManyIds {
+                requested: count,
+                received: ids.len(),
+            });
+        }
+        Ok(ids)
+    }
+
+    /// download the chain snapshot served by the gateway to the file
+    /// at `path`. Returns `false` if the gateway serves no snapshot.
+    pub fn fetch_chain_snapshot(&self, path: &Path) -> Result<bool, Error> {
+        let client = Client::builder()
+            .timeout(SNAPSHOT_DOWNLOAD_TIMEOUT)
+            .local_address(self.local_address)
+            .build()
+            .map_err(|e| Error::ClientInit { source: e })?;
+        let response = client
+            .get(self.url(&["v0", "chain", "snapshot"]))
+            .send()
+            .map_err(|e| Error::Request { source: e })?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(false);
+        }
+        let mut response = response
+            .error_for_status()
+            .map_err(|e| Error::Request { source: e })?;
+        let mut file = File::create(path).map_err(|e| Error::SnapshotFile { source: e })?;
+        response
+            .copy_to(&mut file)
+            .map_err(|e| Error::Request { source: e })?;
+        Ok(true)
+    }
+
+    fn url(&self, segments: &[&str]) -> Url {
+        let mut url = self.base.clone();
+        url.path_segments_mut()
+            .expect("block gateway URL must be a base")
+            .pop_if_empty()
+            .extend(segments);
+        url
+    }
+
+    fn get(
+        &self,
+        segments: &[&str],
+        id: HeaderHash,
+        query: &[(&str, usize)],
+    ) -> Result<Vec<u8>, Error> {
+        let response = self
+            .client
+            .get(self.url(segments))
+            .query(query)
+            .send()
+            .map_err(|e| Error::Request { source: e })?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Err(Error::NotFound(id));
+        }
+        let response = response
+            .error_for_status()
+            .map_err(|e| Error::Request { source: e })?;
+        let mut body = Vec::new();
+        response
+            .take(MAX_BLOCK_SIZE + 1)
+            .read_to_end(&mut body)
+            .map_err(|e| Error::Malformed { source: e })?;
+        if body.len() as u64 > MAX_BLOCK_SIZE {
+            return Err(Error::TooLarge);
+        }
+        Ok(body)
+    }
+}
@@ -0,0 +1,145 @@
+use super::p2p;
+use crate::settings::start::network::AdaptiveGossip;
+use crate::utils::stats::Ema;
+use std::cmp;
+use std::collections::HashSet;
+use std::time::Duration;
+
+/// Share of the view that may change between two gossip rounds for
+/// the topology to still be considered stable.
+const MAX_STABLE_CHURN: f64 = 0.25;
+
+/// Number of gossip rounds the churn is averaged over, so that a single
+/// round with an unusual view does not reset the interval.
+const CHURN_PERIOD: usize = 4;
+
+/// Adjusts the interval between gossip rounds to the state of the topology.
+///
+/// The interval is halved after every round where the view holds fewer
+/// peers than the node wants to be connected to, or where a significant
+/// share of the peers, averaged over the latest rounds, differ from one
+/// round to the next. Otherwise the topology is considered stable and the
+/// interval grows by half.
+pub struct AdaptiveInterval {
+    bounds: AdaptiveGossip,
+    current: Duration,
+    min_view_size: usize,
+    last_view: HashSet<p2p::Id>,
+    churn: Ema,
+}
+
+impl AdaptiveInterval {
+    pub fn new(initial: Duration, bounds: AdaptiveGossip, min_view_size: usize) -> Self {
+        let current = cmp::min(cmp::max(initial, bounds.min_interval), bounds.max_interval);
+        AdaptiveInterval {
+            bounds,
+            current,
+            min_view_size,
+            last_view: HashSet::new(),
+            churn: Ema::with_period(CHURN_PERIOD),
+        }
+    }
+
+    pub fn current(&self) -> Duration {
+        self.current
+    }
+
+    /// Updates the interval with the peers of the view used for the
+    /// latest gossip round and returns the time to wait until the next.
+    pub fn next(&mut self, view: impl IntoIterator<Item = p2p::Id>) -> Duration {
+        let view: HashSet<p2p::Id> = view.into_iter().collect();
+        let changed = view.symmetric_difference(&self.last_view).count();
+        self.churn
+            .observe(changed as f64 / cmp::max(view.len() + self.last_view.len(), 1) as f64);
+        let churn = self.churn.value().unwrap_or(0.0);
+
+        self.current = if view.len() < self.min_view_size || churn > MAX_STABLE_CHURN {
+            cmp::max(self.current / 2, self.bounds.min_interval)
+        } else {
+            cmp::min(self.current * 3 / 2, self.bounds.max_interval)
+        };
+        self.last_view = view;
+        self.current
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use poldercast::NodeProfileBuilder;
+
+    const MIN_VIEW_SIZE: usize = 3;
+
+    fn new_id() -> p2p::Id {
+        (*NodeProfileBuilder::new().build().id()).into()
+    }
+
+    fn new_view(size: usize) -> Vec<p2p::Id> {
+        (0..size).map(|_| new_id()).collect()
+    }
+
+    fn interval(initial: u64) -> AdaptiveInterval {
+        AdaptiveInterval::new(
+            Duration::from_secs(initial),
+            AdaptiveGossip {
+                min_interval: Duration::from_secs(1),
+                max_interval: Duration::from_secs(60),
+            },
+            MIN_VIEW_SIZE,
+        )
+    }
+
+    #[test]
+    fn small_view_halves_the_interval() {
+        let mut interval = interval(60);
+        let view = new_view(MIN_VIEW_SIZE - 1);
+        // past the first rounds the view does not churn, it is only small
+        for _ in 0..4 {
+            interval.next(view.clone());
+        }
+        let current = interval.current();
+        assert_eq!(interval.next(view.clone()), current / 2);
+    }
+
+    #[test]
+    fn high_churn_halves_the_interval() {
+        let mut interval = interval(16);
+        assert_eq!(interval.next(new_view(4)), Duration::from_secs(8));
+        assert_eq!(interval.next(new_view(4)), Duration::from_secs(4));
+        assert_eq!(interval.next(new_view(4)), Duration::from_secs(2));
+    }
+
+    #[test]
+    fn stable_view_grows_the_interval() {
+        let mut interval = interval(16);
+        let view = new_view(4);
+        // the churn of the first round, from an empty view, takes a few
+        // stable rounds to fade out of the average
+        for _ in 0..5 {
+            interval.next(view.clone());
+        }
+        let current = interval.current();
+        assert_eq!(interval.next(view.clone()), current * 3 / 2);
+    }
+
+    #[test]
+    fn interval_stays_within_the_bounds() {
+        let mut interval = interval(16);
+        let view = new_view(4);
+        for _ in 0..20 {
+            interval.next(view.clone());
+        }
+        assert_eq!(interval.current(), Duration::from_secs(60));
+
+        for _ in 0..20 {
+            interval.next(new_view(4));
+        }
+        assert_eq!(interval.current(), Duration::from_secs(1));
+    }
+
+    #[test]
+    fn initial_interval_is_clamped_to_the_bounds() {
+        assert_eq!(interval(0).current(), Duration::from_secs(1));
+        assert_eq!(interval(3600).current(), Duration::from_secs(60));
+    }
+}
@@ -1,8 +1,8 @@
 use crate::{
     blockcfg::{Block, HeaderHash},
     network::{p2p::Id, BlockConfig},
-    settings::start::network::Peer,
 };
+use futures::future;
 use futures::prelude::*;
 use http::{HttpTryFrom, Uri};
 use hyper::client::connect::{Destination, HttpConnector};
@@ -31,6 +31,13 @@ pub enum FetchBlockError {
     GetBlocksStream { source: core_error::Error },
     #[error("no blocks received")]
     NoBlocks,
+    #[error("requested block {requested}, received block {received}")]
+    UnexpectedBlock {
+        requested: HeaderHash,
+        received: HeaderHash,
+    },
+    #[error("no peers to fetch the block from")]
+    NoPeers,
 }
 
 pub type Connection = network_grpc::client::Connection<BlockConfig>;
@@ -38,10 +45,16 @@ pub type ConnectFuture =
     network_grpc::client::ConnectFuture<BlockConfig, HttpConnector, TaskExecutor>;
 pub type ConnectError = network_grpc::client::ConnectError<io::Error>;
 
-pub fn connect(addr: SocketAddr, node_id: Option<Id>, executor: TaskExecutor) -> ConnectFuture {
+pub fn connect(
+    addr: SocketAddr,
+    local_addr: Option<IpAddr>,
+    node_id: Option<Id>,
+    executor: TaskExecutor,
+) -> ConnectFuture {
     let uri = destination_uri(addr);
     let mut connector = HttpConnector::new(2);
     connector.set_nodelay(true);
+    connector.set_local_address(local_addr);
     let mut builder = Connect::with_executor(connector, executor);
     if let Some(id) = node_id {
         builder.node_id(id);
@@ -58,16 +71,53 @@ fn destination_uri(addr: SocketAddr) -> Uri {
     HttpTryFrom::try_from(uri).unwrap()
 }
 
-// Fetches a block from a network peer in a one-off, blocking call.
+// Fetches a block from network peers in a one-off, blocking call.
+// The peers are queried concurrently, the first one to provide the block
+// wins and the requests to the other peers are canceled.
 // This function is used during node bootstrap to fetch the genesis block.
 pub fn fetch_block(
-    peer: Peer,
+    peers: &[SocketAddr],
+    local_addr: Option<IpAddr>,
     hash: HeaderHash,
     logger: &Logger,
 ) -> Result<Block, FetchBlockError> {
+    if peers.is_empty() {
+        return Err(FetchBlockError::NoPeers);
+    }
     info!(logger, "fetching block {}", hash);
     let mut runtime = Runtime::new().map_err(|e| FetchBlockError::RuntimeInit { source: e })?;
-    let fetch = connect(peer.address(), None, runtime.executor())
+    let executor = runtime.executor();
+    let fetches = peers.iter().map(|addr| {
+        let logger = logger.new(o!("peer_address" => addr.to_string()));
+        let err_logger = logger.clone();
+        fetch_block_from(*addr, local_addr, hash, executor.clone())
+            .map(move |block| {
+                info!(logger, "block fetched");
+                block
+            })
+            .map_err(move |e| {
+                match &e {
+                    FetchBlockError::Connect { source } => warn!(
+                        err_logger,
+                        "unable to reach peer for block download";
+                        "reason" => %source,
+                    ),
+                    _ => warn!(err_logger, "failed to download block"; "error" => ?e),
+                }
+                e
+            })
+    });
+    let fetch = future::select_ok(fetches).map(|(block, _)| block);
+    runtime.block_on(fetch)
+}
+
+fn fetch_block_from(
+    addr: SocketAddr,
+    local_addr: Option<IpAddr>,
+    hash: HeaderHash,
+    executor: TaskExecutor,
+) -> impl Future<Item = Block, Error = FetchBlockError> {
+    connect(addr, local_addr, None, executor)
         .map_err(|err| FetchBlockError::Connect { source: err })
         .and_then(move |client: Connection| {
             client
@@ -84,9 +134,18 @@ pub fn fetch_block(
                 .into_future()
                 .map_err(|(err, _)| FetchBlockError::GetBlocksStream { source: err })
         })
-        .and_then(|(maybe_block, _)| match maybe_block {
+        .and_then(move |(maybe_block, _)| match maybe_block {
             None => Err(FetchBlockError::NoBlocks),
-            Some(block) => Ok(block),
-        });
-    runtime.block_on(fetch)
+            Some(block) => {
+                let received = block.header.hash();
+                if received == hash {
+                    Ok(block)
+                } else {
+                    Err(FetchBlockError::UnexpectedBlock {
+                        requested: hash,
+                        received,
+                    })
+                }
+            }
+        })
 }
@@ -1,15 +1,22 @@
-use super::super::{service::NodeService, Channels, GlobalStateR, ListenError};
+use super::super::{
+    drain::ListenerControl, service::NodeService, Channels, GlobalStateR, ListenError,
+};
 use crate::settings::start::network::Listen;
 use network_grpc::server::{self, TcpListen};
 
 use futures::stream::FuturesUnordered;
+use futures::sync::oneshot;
 use slog::Logger;
 use tokio::net::TcpStream;
 use tokio::prelude::*;
+use tokio::timer::Delay;
 use tokio_threadpool::{Shutdown, ThreadPool};
 
 use std::any::Any;
-use std::net::SocketAddr;
+use std::collections::{hash_map::Entry, HashMap};
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
 type Server = server::Server<NodeService>;
 
@@ -17,6 +24,7 @@ pub fn run_listen_socket(
     listen: &Listen,
     state: GlobalStateR,
     channels: Channels,
+    control: ListenerControl,
 ) -> Result<impl Future<Item = (), Error = ()>, ListenError> {
     let sockaddr = listen.address();
 
@@ -27,8 +35,11 @@ pub fn run_listen_socket(
         Err(e) => Err(ListenError { cause: e, sockaddr }),
         Ok(listen) => {
             let capacity = state.config.max_connections;
-            let node_server = NodeService::new(channels, state);
-            let server = Server::new(node_server);
+            let max_per_ip = state.config.max_inbound_connections_per_ip;
+            let handshake_timeout = state.config.handshake_timeout;
+            let max_half_open = state.config.max_half_open_connections;
+            let idle_timeout = state.config.idle_connection_timeout;
+            let service = NodeService::new(channels, state);
             let panic_logger = logger.clone();
 
             let thread_pool = tokio_threadpool::Builder::new()
@@ -37,11 +48,19 @@ pub fn run_listen_socket(
                 .build();
 
             let conn_mgr = Connections {
-                listen,
-                server,
+                listen: Some(listen),
+                service,
                 capacity,
+                max_per_ip,
+                handshake_timeout,
+                max_half_open,
+                idle_timeout,
+                inbound_addrs: InboundAddrs::default(),
+                handshakes: FuturesUnordered::new(),
                 conn_set: FuturesUnordered::new(),
                 thread_pool: Some(thread_pool),
+                stop_accepting: Some(control.stop_accepting),
+                close: control.close,
                 logger: logger.clone(),
             };
 
@@ -58,23 +77,186 @@ fn handle_task_panic(err: &(dyn Any + Send), logger: &Logger) {
     }
 }
 
+/// Number of the inbound connections currently served per remote IP address.
+#[derive(Clone, Default)]
+struct InboundAddrs {
+    counts: Arc<Mutex<HashMap<IpAddr, usize>>>,
+}
+
+/// A connection slot taken for a remote IP address, released on drop.
+struct InboundSlot {
+    addrs: InboundAddrs,
+    ip: IpAddr,
+}
+
+impl InboundAddrs {
+    /// Takes a slot for a new connection from the given address,
+    /// unless the limit of connections from that address is reached.
+    fn try_acquire(&self, ip: IpAddr, limit: Option<usize>) -> Option<InboundSlot> {
+        let mut counts = self.counts.lock().unwrap();
+        let count = counts.get(&ip).cloned().unwrap_or(0);
+        if let Some(limit) = limit {
+            if count >= limit {
+                return None;
+            }
+        }
+        counts.insert(ip, count + 1);
+        Some(InboundSlot {
+            addrs: self.clone(),
+            ip,
+        })
+    }
+}
+
+impl Drop for InboundSlot {
+    fn drop(&mut self) {
+        let mut counts = self.addrs.counts.lock().unwrap();
+        if let Entry::Occupied(mut entry) = counts.entry(self.ip) {
+            *entry.get_mut() -= 1;
+            if *entry.get() == 0 {
+                entry.remove();
+            }
+        }
+    }
+}
+
+/// The connection preface every HTTP/2 client starts with.
+const HTTP2_PREFACE: &[u8] = b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n";
+
+/// Interval at which the received bytes are checked again when only part
+/// of the preface has arrived.
+const PREFACE_RECHECK_INTERVAL: Duration = Duration::from_millis(100);
+
+/// An accepted connection waiting for the client to send the HTTP/2
+/// connection preface.
+///
+/// The preface is only peeked at, not consumed, so the stream is handed
+/// over to the HTTP/2 server as it was accepted. Connections failing to
+/// send it before the deadline are dropped, so clients opening connections
+/// and never speaking do not hold resources for long.
+struct Handshake {
+    stream: Option<TcpStream>,
+    peer_addr: SocketAddr,
+    slot: Option<InboundSlot>,
+    deadline: Delay,
+    recheck: Option<Delay>,
+    logger: Logger,
+}
+
+struct Accepted {
+    stream: TcpStream,
+    peer_addr: SocketAddr,
+    slot: InboundSlot,
+}
+
+impl Handshake {
+    fn new(
+        stream: TcpStream,
+        peer_addr: SocketAddr,
+        slot: InboundSlot,
+        timeout: Duration,
+        logger: &Logger,
+    ) -> Self {
+        Handshake {
+            stream: Some(stream),
+            peer_addr,
+            slot: Some(slot),
+            deadline: Delay::new(Instant::now() + timeout),
+            recheck: None,
+            logger: logger.new(o!("peer_addr" => peer_addr)),
+        }
+    }
+
+    fn poll_preface(&mut self) -> Poll<bool, std::io::Error> {
+        loop {
+            if let Some(recheck) = self.recheck.as_mut() {
+                match recheck.poll() {
+                    Ok(Async::NotReady) => return Ok(Async::NotReady),
+                    Ok(Async::Ready(())) | Err(_) => self.recheck = None,
+                }
+            }
+            let stream = self
+                .stream
+                .as_mut()
+                .expect("handshake polled after completion");
+            let mut buf = [0; HTTP2_PREFACE.len()];
+            let n = try_ready!(stream.poll_peek(&mut buf));
+            if n == 0 || buf[..n] != HTTP2_PREFACE[..n] {
+                return Ok(Async::Ready(false));
+            }
+            if n == HTTP2_PREFACE.len() {
+                return Ok(Async::Ready(true));
+            }
+            // Peeking does not wait for more bytes to arrive
+            // than are already buffered, so check again later.
+            self.recheck = Some(Delay::new(Instant::now() + PREFACE_RECHECK_INTERVAL));
+        }
+    }
+}
+
+impl Future for Handshake {
+    type Item = Option<Accepted>;
+    type Error = ();
+
+    fn poll(&mut self) -> Poll<Option<Accepted>, ()> {
+        match self.deadline.poll() {
+            Ok(Async::NotReady) => {}
+            Ok(Async::Ready(())) | Err(_) => {
+                info!(
+                    self.logger,
+                    "closing connection, the handshake has not been completed in time"
+                );
+                return Ok(Async::Ready(None));
+            }
+        }
+        match self.poll_preface() {
+            Ok(Async::NotReady) => Ok(Async::NotReady),
+            Ok(Async::Ready(true)) => Ok(Async::Ready(Some(Accepted {
+                stream: self.stream.take().unwrap(),
+                peer_addr: self.peer_addr,
+                slot: self.slot.take().unwrap(),
+            }))),
+            Ok(Async::Ready(false)) => {
+                info!(self.logger, "closing connection, invalid HTTP/2 preface");
+                Ok(Async::Ready(None))
+            }
+            Err(e) => {
+                info!(
+                    self.logger,
+                    "connection failed during the handshake";
+                    "reason" => %e,
+                );
+                Ok(Async::Ready(None))
+            }
+        }
+    }
+}
+
 struct Connection {
     inner: server::Connection,
+    idle_timeout: Duration,
+    idle: Delay,
     logger: Logger,
+    _slot: InboundSlot,
 }
 
 impl Connection {
     fn serve(
-        server: &mut Server,
-        stream: TcpStream,
-        peer_addr: SocketAddr,
+        service: &NodeService,
+        accepted: Accepted,
+        idle_timeout: Duration,
         logger: &Logger,
     ) -> Self {
-        let logger = logger.new(o!("peer_addr" => peer_addr));
+        let logger = logger.new(o!("peer_addr" => accepted.peer_addr));
         info!(logger, "accepted connection");
+        // Each connection is served with the limits applying to its peer.
+        let mut server = Server::new(service.for_peer(accepted.peer_addr));
         Connection {
-            inner: server.serve(stream),
+            inner: server.serve(accepted.stream),
+            idle_timeout,
+            idle: Delay::new(Instant::now() + idle_timeout),
             logger,
+            _slot: accepted.slot,
         }
     }
 }
@@ -86,8 +268,18 @@ impl Future for Connection {
     fn poll(&mut self) -> Poll<(), ()> {
         use network_grpc::server::Error;
 
+        // The idle timer is reset every time the connection is polled,
+        // so it only fires if nothing has woken the connection since.
+        if let Ok(Async::Ready(())) = self.idle.poll() {
+            info!(self.logger, "closing idle connection");
+            return Ok(Async::Ready(()));
+        }
+
         match self.inner.poll() {
-            Ok(Async::NotReady) => return Ok(Async::NotReady),
+            Ok(Async::NotReady) => {
+                self.idle.reset(Instant::now() + self.idle_timeout);
+                return Ok(Async::NotReady);
+            }
             Ok(Async::Ready(())) => {
                 info!(self.logger, "connection closed");
             }
@@ -114,19 +306,76 @@ impl Future for Connection {
 type ConnHandle = tokio_threadpool::SpawnHandle<(), ()>;
 
 struct Connections {
-    listen: TcpListen,
-    server: Server,
+    // `None` once the node is shutting down and stopped accepting connections
+    listen: Option<TcpListen>,
+    service: NodeService,
     capacity: usize,
+    max_per_ip: Option<usize>,
+    handshake_timeout: Duration,
+    max_half_open: usize,
+    idle_timeout: Duration,
+    inbound_addrs: InboundAddrs,
+    handshakes: FuturesUnordered<Handshake>,
     conn_set: FuturesUnordered<ConnHandle>,
     thread_pool: Option<ThreadPool>,
+    stop_accepting: Option<oneshot::Receiver<()>>,
+    close: oneshot::Receiver<()>,
     logger: Logger,
 }
 
+impl Connections {
+    fn poll_shutdown(&mut self) -> Option<Shutdown> {
+        if let Some(stop_accepting) = self.stop_accepting.as_mut() {
+            match stop_accepting.poll() {
+                Ok(Async::NotReady) => {}
+                Ok(Async::Ready(())) | Err(_) => {
+                    info!(self.logger, "no longer accepting connections");
+                    self.stop_accepting = None;
+                    self.listen = None;
+                    self.handshakes = FuturesUnordered::new();
+                }
+            }
+        }
+        match self.close.poll() {
+            Ok(Async::NotReady) => return None,
+            Ok(Async::Ready(())) | Err(_) => {}
+        }
+        info!(
+            self.logger,
+            "closing the inbound connections";
+            "active_connections" => self.conn_set.len(),
+        );
+        let thread_pool = self
+            .thread_pool
+            .take()
+            .expect("server polled after shutdown");
+        Some(thread_pool.shutdown_now())
+    }
+}
+
 impl Future for Connections {
     type Item = Shutdown;
     type Error = ();
 
     fn poll(&mut self) -> Poll<Shutdown, ()> {
+        if let Some(shutdown) = self.poll_shutdown() {
+            return Ok(Async::Ready(shutdown));
+        }
+        if self.listen.is_none() {
+            // Only the established connections are served while draining.
+            loop {
+                match self.conn_set.poll() {
+                    Ok(Async::Ready(Some(()))) => debug!(
+                        self.logger,
+                        "a client peer connection has been closed";
+                        "active_connections" => self.conn_set.len(),
+                    ),
+                    Ok(Async::Ready(None)) | Ok(Async::NotReady) | Err(()) => {
+                        return Ok(Async::NotReady)
+                    }
+                }
+            }
+        }
         loop {
             if !self.conn_set.is_empty() {
                 match self.conn_set.poll() {
@@ -142,18 +391,61 @@ impl Future for Connections {
                     Err(()) => {}
                 }
             }
-            match self.listen.poll() {
-                Ok(Async::NotReady) => return Ok(Async::NotReady),
-                Ok(Async::Ready(Some((stream, peer_addr)))) => {
-                    if self.conn_set.len() < self.capacity {
-                        let conn =
-                            Connection::serve(&mut self.server, stream, peer_addr, &self.logger);
+            while !self.handshakes.is_empty() {
+                match self.handshakes.poll() {
+                    Ok(Async::Ready(Some(Some(accepted)))) => {
+                        let conn = Connection::serve(
+                            &self.service,
+                            accepted,
+                            self.idle_timeout,
+                            &self.logger,
+                        );
                         let thread_pool = self
                             .thread_pool
                             .as_ref()
                             .expect("server polled after shutdown");
                         let handle = thread_pool.spawn_handle(conn);
                         self.conn_set.push(handle);
+                    }
+                    Ok(Async::Ready(Some(None))) => {}
+                    Ok(Async::Ready(None)) | Ok(Async::NotReady) | Err(()) => break,
+                }
+            }
+            let listen = self.listen.as_mut().expect("listener polled after closing");
+            match listen.poll() {
+                Ok(Async::NotReady) => return Ok(Async::NotReady),
+                Ok(Async::Ready(Some((stream, peer_addr)))) => {
+                    if self.handshakes.len() >= self.max_half_open {
+                        info!(
+                            self.logger,
+                            "rejecting connection, too many connections in the handshake phase";
+                            "peer_addr" => peer_addr,
+                        );
+                    } else if self.conn_set.len() + self.handshakes.len() < self.capacity {
+                        match self
+                            .inbound_addrs
+                            .try_acquire(peer_addr.ip(), self.max_per_ip)
+                        {
+                            Some(slot) => {
+                                let handshake = Handshake::new(
+                                    stream,
+                                    peer_addr,
+                                    slot,
+                                    self.handshake_timeout,
+                                    &self.logger,
+                                );
+                                self.handshakes.push(handshake);
+                            }
+                            None => {
+                                // Too many connections from this address,
+                                // reject by dropping the stream.
+                                info!(
+                                    self.logger,
+                                    "rejecting connection, too many connections from the address";
+                                    "peer_addr" => peer_addr,
+                                );
+                            }
+                        }
                     } else {
                         // The pool of managed connections is full.
                         // Reject this connection by dropping the stream,
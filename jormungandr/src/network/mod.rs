@@ -5,11 +5,20 @@
 //! transactions...);
 //!
 
+mod block_fetch;
 pub mod bootstrap;
+pub mod catch_up;
 mod client;
+mod dial_queue;
+pub mod divergence;
+mod drain;
+mod gateway;
+mod gossip_interval;
 mod grpc;
 mod inbound;
 pub mod p2p;
+mod preferred;
+mod recruitment;
 mod service;
 mod subscription;
 
@@ -43,15 +52,25 @@ mod buffer_sizes {
     }
 }
 
+use self::block_fetch::BlockFetch;
 use self::client::ConnectError;
-use self::p2p::{comm::Peers, P2pTopology};
+use self::dial_queue::{Dial, DialQueue};
+use self::gateway::Gateway;
+use self::gossip_interval::AdaptiveInterval;
+use self::p2p::{
+    comm::{DialBackoff, Peers},
+    P2pTopology, SeenFragments,
+};
 use crate::blockcfg::{Block, HeaderHash};
 use crate::blockchain::{Blockchain as NewBlockchain, Tip};
-use crate::intercom::{BlockMsg, ClientMsg, NetworkMsg, PropagateMsg, TransactionMsg};
+use crate::intercom::{self, BlockMsg, ClientMsg, NetworkMsg, PropagateMsg, TransactionMsg};
 use crate::log;
-use crate::settings::start::network::{Configuration, Peer, Protocol};
+use crate::settings::start::network::{AdaptiveGossip, Configuration, Peer, Protocol, TrustedPeer};
+use crate::stats_counter::StatsCounter;
 use crate::utils::{
     async_msg::{MessageBox, MessageQueue},
+    bandwidth::BandwidthLimiter,
+    rate_limit::RateLimiter,
     task::TokioServiceInfo,
 };
 use futures::future;
@@ -59,23 +78,25 @@ use futures::future::Either::{A, B};
 use futures::prelude::*;
 use futures::stream;
 use network_core::gossip::{Gossip, Node};
-use poldercast::StrikeReason;
 use rand::seq::SliceRandom;
 use slog::Logger;
-use tokio::timer::Interval;
+use tokio::timer::{Delay, Interval};
 use tokio_compat::runtime::TaskExecutor;
 
+use std::collections::HashSet;
 use std::convert::Infallible;
 use std::error;
 use std::fmt;
 use std::io;
 use std::iter;
-use std::net::SocketAddr;
+use std::net::{IpAddr, SocketAddr};
+use std::path::Path;
 use std::sync::atomic::{self, AtomicUsize};
-use std::sync::Arc;
-use std::time::Duration;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
 
 pub use self::bootstrap::Error as BootstrapError;
+pub use self::drain::{shutdown_channel, ShutdownHandle, ShutdownSignal};
 
 #[derive(Debug)]
 pub struct ListenError {
@@ -128,6 +149,21 @@ pub struct GlobalState {
     pub peers: Peers,
     pub executor: TaskExecutor,
     pub logger: Logger,
+    pub upload_rate_limiter: Option<BandwidthLimiter>,
+    /// limits the rate of the fragments received from each peer address,
+    /// as a peer chooses its identifier
+    pub fragment_rate_limiter: Option<RateLimiter<IpAddr>>,
+    pub stats_counter: StatsCounter,
+    /// block streams being served to the peers
+    pub block_streams: drain::InFlight,
+    /// fragments recently received or propagated
+    pub seen_fragments: SeenFragments,
+    /// chunks of block downloads split among several peers
+    pub block_fetch: BlockFetch,
+    /// dials to the peers not connected yet
+    pub dial_queue: DialQueue,
+    /// the trusted peers, given in the configuration or replaced at runtime
+    trusted_peers: RwLock<Vec<TrustedPeer>>,
     client_count: AtomicUsize,
 }
 
@@ -139,10 +175,27 @@ impl GlobalState {
         block0_hash: HeaderHash,
         config: Configuration,
         topology: P2pTopology,
+        stats_counter: StatsCounter,
         executor: TaskExecutor,
         logger: Logger,
     ) -> Self {
-        let peers = Peers::new(config.max_connections, logger.clone());
+        let peers = Peers::new(
+            config.max_connections,
+            config.preferred_peers.iter().map(|peer| peer.id).collect(),
+            config.trusted_peers.iter().map(|peer| peer.id).collect(),
+            config.reserved_sync_connections,
+            config.eviction_policy,
+            topology.reputation().clone(),
+            DialBackoff::new(config.reconnect_backoff, config.rng.clone()),
+            config.rng.clone(),
+            logger.clone(),
+        );
+        let upload_rate_limiter = config.inbound_blocks_rate_limit.map(BandwidthLimiter::new);
+        let fragment_rate_limiter = config
+            .fragment_rate_limit
+            .map(|limit| RateLimiter::new(limit.fragments_per_second, limit.burst));
+        let dial_queue = DialQueue::new(config.max_concurrent_dials);
+        let trusted_peers = RwLock::new(config.trusted_peers.clone());
 
         GlobalState {
             block0_hash,
@@ -151,10 +204,58 @@ impl GlobalState {
             peers,
             executor,
             logger,
+            upload_rate_limiter,
+            fragment_rate_limiter,
+            stats_counter,
+            block_streams: Default::default(),
+            seen_fragments: SeenFragments::new(),
+            block_fetch: BlockFetch::default(),
+            dial_queue,
+            trusted_peers,
             client_count: AtomicUsize::new(0),
         }
     }
 
+    /// the trusted peers currently in effect
+    pub fn trusted_peers(&self) -> Vec<TrustedPeer> {
+        self.trusted_peers.read().unwrap().clone()
+    }
+
+    /// the limit on the depth of the chain served to the peer at the given
+    /// address, `None` for the trusted peers
+    pub fn pull_depth_limit_for_addr(&self, addr: SocketAddr) -> Option<u64> {
+        // Inbound connections come from an ephemeral port,
+        // so the trusted peers are recognized by their IP address.
+        let trusted = self
+            .trusted_peers
+            .read()
+            .unwrap()
+            .iter()
+            .filter_map(|peer| peer.address.to_socketaddr())
+            .any(|trusted_addr| trusted_addr.ip() == addr.ip());
+        if trusted {
+            None
+        } else {
+            self.config.max_untrusted_pull_depth
+        }
+    }
+
+    /// the limit on the depth of the chain served to the given peer,
+    /// `None` for the trusted peers
+    pub fn pull_depth_limit_for_id(&self, id: p2p::Id) -> Option<u64> {
+        let trusted = self
+            .trusted_peers
+            .read()
+            .unwrap()
+            .iter()
+            .any(|peer| peer.id == id);
+        if trusted {
+            None
+        } else {
+            self.config.max_untrusted_pull_depth
+        }
+    }
+
     pub fn logger(&self) -> &Logger {
         &self.logger
     }
@@ -227,6 +328,8 @@ pub struct TaskParams {
     pub block0_hash: HeaderHash,
     pub input: MessageQueue<NetworkMsg>,
     pub channels: Channels,
+    pub stats_counter: StatsCounter,
+    pub shutdown: ShutdownSignal,
 }
 
 pub fn start(
@@ -239,10 +342,13 @@ pub fn start(
     // * the ID needs to be consistent between restart;
     let input = params.input;
     let channels = params.channels;
+    let (shutdown_requested, done) = params.shutdown.split();
+    let (listener_signals, listener_control) = drain::listener_channel();
     let global_state = Arc::new(GlobalState::new(
         params.block0_hash,
         params.config,
         topology,
+        params.stats_counter,
         service_info.executor().clone(),
         service_info.logger().clone(),
     ));
@@ -253,7 +359,12 @@ pub fn start(
     let listener = if let Some(listen) = listen {
         match listen.protocol {
             Protocol::Grpc => {
-                match grpc::run_listen_socket(&listen, global_state.clone(), channels.clone()) {
+                match grpc::run_listen_socket(
+                    &listen,
+                    global_state.clone(),
+                    channels.clone(),
+                    listener_control,
+                ) {
                     Ok(future) => Either::A(future),
                     Err(e) => {
                         error!(
@@ -272,6 +383,16 @@ pub fn start(
 
     global_state.spawn(start_gossiping(global_state.clone(), channels.clone()));
 
+    if let Some(min_connections) = global_state.config.min_client_connections {
+        global_state.spawn(recruitment::run(
+            global_state.clone(),
+            channels.clone(),
+            min_connections,
+        ));
+    }
+
+    preferred::run(global_state.clone(), channels.clone());
+
     let handle_cmds = handle_network_input(input, global_state.clone(), channels.clone());
 
     let gossip_err_logger = global_state.logger.clone();
@@ -283,13 +404,56 @@ pub fn start(
         });
     }
 
-    let gossip = Interval::new_interval(global_state.config.gossip_interval.clone())
-        .map_err(move |e| {
-            error!(gossip_err_logger, "interval timer error: {:?}", e);
-        })
-        .for_each(move |_| send_gossip(global_state.clone(), channels.clone()));
+    let tp2p = global_state.topology.clone();
+    let stats_counter = global_state.stats_counter.clone();
+    let batch_logger = global_state.logger.clone();
+    service_info.run_periodic(
+        "apply gossip",
+        global_state.config.topology_update_interval,
+        move || {
+            let stats_counter = stats_counter.clone();
+            let logger = batch_logger.clone();
+            tp2p.apply_queued_gossips::<Infallible>()
+                .map(move |applied| {
+                    if let Some((count, elapsed)) = applied {
+                        stats_counter.observe_topology_update_time(elapsed);
+                        debug!(
+                            logger,
+                            "gossip applied to the topology";
+                            "messages" => count,
+                            "duration" => ?elapsed,
+                        );
+                    }
+                })
+        },
+    );
+
+    let state = global_state.clone();
+    let gossip = match global_state.config.adaptive_gossip.clone() {
+        None => A(
+            Interval::new_interval(global_state.config.gossip_interval.clone())
+                .map_err(move |e| {
+                    error!(gossip_err_logger, "interval timer error: {:?}", e);
+                })
+                .for_each(move |_| send_gossip(global_state.clone(), channels.clone()).map(|_| ())),
+        ),
+        Some(bounds) => B(gossip_adaptively(global_state, channels, bounds)),
+    };
 
-    listener.join3(handle_cmds, gossip).map(|_| ())
+    let service = handle_cmds
+        .join(gossip)
+        .map(|_| ())
+        .select2(shutdown_requested)
+        .then(move |res| match res {
+            Ok(Either::A(((), _))) => A(future::ok(())),
+            Err(Either::A(((), _))) | Err(Either::B(((), _))) => A(future::err(())),
+            Ok(Either::B(((), _))) => B(drain::drain(state, listener_signals)),
+        });
+
+    listener.join(service).then(move |res| {
+        let _ = done.send(());
+        res.map(|_| ())
+    })
 }
 
 fn handle_network_input(
@@ -303,15 +467,45 @@ fn handle_network_input(
             state.clone(),
             channels.clone(),
         ))),
-        NetworkMsg::GetBlocks(block_ids) => A(B(state.peers.fetch_blocks(block_ids))),
+        NetworkMsg::GetBlocks(block_ids) => A(B(block_fetch::fetch_blocks(
+            state.clone(),
+            channels.block_box.clone(),
+            block_ids,
+        ))),
         NetworkMsg::GetNextBlock(node_id, block_id) => {
             B(A(state.peers.solicit_blocks(node_id, vec![block_id])))
         }
         NetworkMsg::PullHeaders { node_id, from, to } => {
             B(B(A(state.peers.pull_headers(node_id, from.into(), to))))
         }
-        NetworkMsg::PeerInfo(reply) => {
-            B(B(B(state.peers.infos().map(|infos| reply.reply_ok(infos)))))
+        NetworkMsg::PeerInfo(reply) => B(B(B(A(state
+            .peers
+            .infos()
+            .map(|infos| reply.reply_ok(infos)))))),
+        NetworkMsg::PeerDebug(node_id, reply) => B(B(B(B(A(state
+            .peers
+            .debug_info(node_id)
+            .map(|info| match info {
+                Some(info) => reply.reply_ok(info),
+                None => reply.reply_error(intercom::Error::not_found(
+                    "the peer is not in the peer map",
+                )),
+            })))))),
+        NetworkMsg::ResetTopology(reply) => {
+            info!(state.logger(), "resetting the topology layers on request");
+            B(B(B(B(B(A(state
+                .topology
+                .force_reset_layers()
+                .map(|()| reply.reply_ok(()))))))))
+        }
+        NetworkMsg::PeerList(reply) => B(B(B(B(B(B(A(state
+            .peers
+            .debug_infos()
+            .map(|infos| reply.reply_ok(infos))))))))),
+        NetworkMsg::SetTrustedPeers(peers, reply) => {
+            B(B(B(B(B(B(B(
+                set_trusted_peers(state.clone(), peers).map(|()| reply.reply_ok(()))
+            )))))))
         }
     })
 }
@@ -321,6 +515,14 @@ fn handle_propagation_msg(
     state: GlobalStateR,
     channels: Channels,
 ) -> impl Future<Item = (), Error = ()> {
+    let topic = match msg {
+        PropagateMsg::Block(_) => p2p::topic::BLOCKS,
+        PropagateMsg::Fragment(_) | PropagateMsg::FragmentRebroadcast(_) => p2p::topic::MESSAGES,
+    };
+    if !state.config.topics.contains(topic) {
+        return A(future::ok(()));
+    }
+
     let prop_state = state.clone();
     let send_to_peers = match msg {
         PropagateMsg::Block(ref header) => {
@@ -334,7 +536,24 @@ fn handle_propagation_msg(
                 .and_then(move |view| prop_state.peers.propagate_block(view.peers, header));
             A(future)
         }
-        PropagateMsg::Fragment(ref fragment) => {
+        PropagateMsg::Fragment(ref fragment) | PropagateMsg::FragmentRebroadcast(ref fragment) => {
+            let started = match msg {
+                PropagateMsg::FragmentRebroadcast(_) => {
+                    Some(state.seen_fragments.restart_propagation(fragment.id()))
+                }
+                _ => state.seen_fragments.start_propagation(fragment.id()),
+            };
+            let senders = match started {
+                Some(senders) => senders,
+                None => {
+                    debug!(
+                        state.logger(),
+                        "fragment already propagated";
+                        "hash" => %fragment.hash(),
+                    );
+                    return A(future::ok(()));
+                }
+            };
             debug!(state.logger(), "fragment to propagate"; "hash" => %fragment.hash());
             let fragment = fragment.clone();
             let future = state
@@ -342,14 +561,22 @@ fn handle_propagation_msg(
                 .view(poldercast::Selection::Topic {
                     topic: p2p::topic::MESSAGES,
                 })
-                .and_then(move |view| prop_state.peers.propagate_fragment(view.peers, fragment));
+                .and_then(move |view| {
+                    // Do not echo the fragment back to the peers it came from.
+                    let peers = view
+                        .peers
+                        .into_iter()
+                        .filter(|node| !senders.contains(&node.id()))
+                        .collect();
+                    prop_state.peers.propagate_fragment(peers, fragment)
+                });
             B(future)
         }
     };
     // If any nodes selected for propagation are not in the
     // active subscriptions map, connect to them and deliver
     // the item.
-    send_to_peers.then(move |res| {
+    let future = send_to_peers.then(move |res| {
         if let Err(mut unreached_nodes) = res {
             unreached_nodes.truncate(state.config.max_client_connections);
             debug!(
@@ -363,7 +590,8 @@ fn handle_propagation_msg(
                     PropagateMsg::Block(header) => {
                         options.pending_block_announcement = Some(header.clone());
                     }
-                    PropagateMsg::Fragment(fragment) => {
+                    PropagateMsg::Fragment(fragment)
+                    | PropagateMsg::FragmentRebroadcast(fragment) => {
                         options.pending_fragment = Some(fragment.clone());
                     }
                 };
@@ -371,7 +599,23 @@ fn handle_propagation_msg(
             }
         }
         Ok(())
-    })
+    });
+    B(future)
+}
+
+// Gossips standing for the trusted peers, as if they had announced themselves.
+fn trusted_peer_gossips<'a>(peers: impl IntoIterator<Item = &'a TrustedPeer>) -> p2p::Gossips {
+    peers
+        .into_iter()
+        .map(|tp| {
+            let mut builder = poldercast::NodeProfileBuilder::new();
+            builder.id(tp.id.clone().into());
+            builder.address(tp.address.clone().into());
+            builder.build()
+        })
+        .map(p2p::Gossip::from)
+        .collect::<Vec<p2p::Gossip>>()
+        .into()
 }
 
 fn start_gossiping(state: GlobalStateR, channels: Channels) -> impl Future<Item = (), Error = ()> {
@@ -383,18 +627,7 @@ fn start_gossiping(state: GlobalStateR, channels: Channels) -> impl Future<Item
     topology
         .accept_gossips(
             (*config.profile.id()).into(),
-            config
-                .trusted_peers
-                .iter()
-                .map(|tp| {
-                    let mut builder = poldercast::NodeProfileBuilder::new();
-                    builder.id(tp.id.clone().into());
-                    builder.address(tp.address.clone().into());
-                    builder.build()
-                })
-                .map(p2p::Gossip::from)
-                .collect::<Vec<p2p::Gossip>>()
-                .into(),
+            trusted_peer_gossips(&config.trusted_peers),
         )
         .and_then(move |()| topology.view(poldercast::Selection::Any))
         .and_then(move |view| {
@@ -411,38 +644,108 @@ fn start_gossiping(state: GlobalStateR, channels: Channels) -> impl Future<Item
         })
 }
 
-fn send_gossip(state: GlobalStateR, channels: Channels) -> impl Future<Item = (), Error = ()> {
+// Replaces the trusted peers at runtime. The new ones are injected
+// in the topology like the trusted peers are at startup; the removed ones
+// lose the privileges of trusted peers, but remain known to the topology
+// like any peer learned through gossip.
+fn set_trusted_peers(
+    state: GlobalStateR,
+    peers: Vec<TrustedPeer>,
+) -> impl Future<Item = (), Error = ()> {
+    let self_id = state.topology.node_id();
+    let peers = peers
+        .into_iter()
+        .filter(|peer| peer.id != self_id)
+        .collect::<Vec<_>>();
+    let ids = peers.iter().map(|peer| peer.id).collect::<HashSet<_>>();
+    *state.trusted_peers.write().unwrap() = peers.clone();
+    let topology = state.topology.clone();
+    let logger = state.logger().clone();
+    state
+        .peers
+        .replace_trusted(ids.clone())
+        .and_then(move |previous| {
+            let added = peers
+                .iter()
+                .filter(|peer| !previous.contains(&peer.id))
+                .collect::<Vec<_>>();
+            info!(
+                logger,
+                "replacing the trusted peers";
+                "added" => added.len(),
+                "removed" => previous.difference(&ids).count(),
+            );
+            topology.accept_gossips(self_id, trusted_peer_gossips(added))
+        })
+}
+
+fn gossip_adaptively(
+    state: GlobalStateR,
+    channels: Channels,
+    bounds: AdaptiveGossip,
+) -> impl Future<Item = (), Error = ()> {
+    let interval = AdaptiveInterval::new(
+        state.config.gossip_interval,
+        bounds,
+        state.config.max_client_connections,
+    );
+    let logger = state.logger().new(o!(log::KEY_SUB_TASK => "send_gossip"));
+    future::loop_fn(interval, move |mut interval| {
+        let logger = logger.clone();
+        let err_logger = logger.clone();
+        send_gossip(state.clone(), channels.clone()).and_then(move |view| {
+            let previous = interval.current();
+            let next = interval.next(view);
+            if next != previous {
+                debug!(logger, "gossip interval adjusted"; "interval" => ?next);
+            }
+            Delay::new(Instant::now() + next)
+                .map_err(move |e| {
+                    error!(err_logger, "gossip timer error: {:?}", e);
+                })
+                .map(move |()| future::Loop::<(), _>::Continue(interval))
+        })
+    })
+}
+
+// Resolves to the identifiers of the peers in the view selected
+// for the gossip round.
+fn send_gossip(
+    state: GlobalStateR,
+    channels: Channels,
+) -> impl Future<Item = Vec<p2p::Id>, Error = ()> {
     let topology = state.topology.clone();
     let logger = state.logger().new(o!(log::KEY_SUB_TASK => "send_gossip"));
     topology
         .view(poldercast::Selection::Any)
         .and_then(move |view| {
+            let view_ids = view.peers.iter().map(|node| node.id()).collect::<Vec<_>>();
             let mut peers = view.peers;
             peers.truncate(state.config.max_client_connections);
             debug!(logger, "sending gossip to {} peers", peers.len());
-            stream::iter_ok(peers).for_each(move |node| {
-                let peer_id = node.id();
-                let state_prop = state.clone();
-                let state_err = state.clone();
-                let channels_err = channels.clone();
-                topology
-                    .initiate_gossips(peer_id)
-                    .and_then(move |gossips| {
-                        state_prop
-                            .peers
-                            .propagate_gossip_to(peer_id, Gossip::from(gossips))
-                    })
-                    .then(move |res| {
-                        if let Err(gossip) = res {
-                            let options = p2p::comm::ConnectOptions {
-                                pending_gossip: Some(gossip),
-                                ..Default::default()
-                            };
-                            connect_and_propagate(node, state_err, channels_err, options);
-                        }
-                        Ok(())
-                    })
-            })
+            stream::iter_ok(peers)
+                .for_each(move |node| {
+                    let peer_id = node.id();
+                    let state_prop = state.clone();
+                    let state_err = state.clone();
+                    let channels_err = channels.clone();
+                    topology
+                        .initiate_gossips(peer_id)
+                        .and_then(move |gossips| {
+                            state_prop.peers.propagate_gossip_to(peer_id, gossips)
+                        })
+                        .then(move |res| {
+                            if let Err(gossip) = res {
+                                let options = p2p::comm::ConnectOptions {
+                                    pending_gossip: Some(gossip),
+                                    ..Default::default()
+                                };
+                                connect_and_propagate(node, state_err, channels_err, options);
+                            }
+                            Ok(())
+                        })
+                })
+                .map(move |()| view_ids)
         })
 }
 
@@ -450,7 +753,7 @@ fn connect_and_propagate(
     node: p2p::Node,
     state: GlobalStateR,
     channels: Channels,
-    mut options: p2p::comm::ConnectOptions,
+    options: p2p::comm::ConnectOptions,
 ) {
     let addr = match node.address() {
         Some(addr) => addr,
@@ -463,8 +766,80 @@ fn connect_and_propagate(
             return;
         }
     };
+    connect_and_propagate_to(node.id(), addr, state, channels, options)
+}
+
+fn connect_and_propagate_to(
+    node_id: p2p::Id,
+    addr: SocketAddr,
+    state: GlobalStateR,
+    channels: Channels,
+    options: p2p::comm::ConnectOptions,
+) {
+    if let Some(remaining) = state.peers.dial_backoff(node_id) {
+        debug!(
+            state.logger(),
+            "not connecting to the peer after recent failures";
+            "node_id" => %node_id,
+            "retry_in" => ?remaining,
+        );
+        return;
+    }
+    for dial in state.dial_queue.push(node_id, addr, options) {
+        start_dial(dial, state.clone(), channels.clone());
+    }
+}
+
+// Spawns a dial from the queue, and the next queued ones
+// as soon as it completes.
+fn start_dial(dial_params: Dial, state: GlobalStateR, channels: Channels) {
+    let Dial {
+        node_id,
+        addr,
+        options,
+    } = dial_params;
+    let spawn_state = state.clone();
+    let queue_state = state.clone();
+    let queue_channels = channels.clone();
+    let cf = dial(node_id, addr, state, channels, options)
+        .then(move |res| {
+            let (pending_options, next) = queue_state.dial_queue.complete(node_id);
+            for next in next {
+                start_dial(next, queue_state.clone(), queue_channels.clone());
+            }
+            // the items given while the peer was being dialled
+            let delivered = match res {
+                Ok(_) => A(queue_state.peers.deliver(node_id, pending_options)),
+                Err(()) => B(future::ok(())),
+            };
+            delivered.then(move |_: Result<(), ()>| res)
+        })
+        .and_then(|connection| connection);
+    spawn_state.spawn(cf);
+}
+
+// Connects to the peer and resolves when the client connection
+// is closed or has failed.
+fn connect_to(
+    node_id: p2p::Id,
+    addr: SocketAddr,
+    state: GlobalStateR,
+    channels: Channels,
+    options: p2p::comm::ConnectOptions,
+) -> impl Future<Item = (), Error = ()> {
+    dial(node_id, addr, state, channels, options).and_then(|connection| connection)
+}
+
+// Dials the peer and resolves, once the client connection is established,
+// to the future running the connection until it is closed.
+fn dial(
+    node_id: p2p::Id,
+    addr: SocketAddr,
+    state: GlobalStateR,
+    channels: Channels,
+    mut options: p2p::comm::ConnectOptions,
+) -> impl Future<Item = impl Future<Item = (), Error = ()>, Error = ()> {
     options.evict_clients = state.num_clients_to_bump();
-    let node_id = node.id();
     assert_ne!(
         node_id,
         state.topology.node_id(),
@@ -477,9 +852,8 @@ fn connect_and_propagate(
         .new(o!("node_id" => node_id.to_string()));
     info!(conn_logger, "connecting to peer");
     let (handle, connecting) = client::connect(conn_state, channels.clone());
-    let spawn_state = state.clone();
     let conn_err_state = state.clone();
-    let cf = state.peers.add_connecting(node_id, handle, options)
+    state.peers.add_connecting(node_id, handle, options)
         .and_then(|()| connecting)
         .or_else(move |e| {
             let benign = match e {
@@ -503,9 +877,15 @@ fn connect_and_propagate(
                 }
             };
             if !benign {
+                let delay = conn_err_state.peers.record_dial_failure(node_id);
+                debug!(conn_logger, "will not connect to the peer again for a while"; "delay" => ?delay);
+                conn_err_state
+                    .topology
+                    .reputation()
+                    .record(node_id, p2p::PeerEvent::CannotConnect);
                 let future = conn_err_state
                     .topology
-                    .report_node(node_id, StrikeReason::CannotConnect)
+                    .report_node(node_id, p2p::Strike::CannotConnect)
                     .join(conn_err_state.peers.remove_peer(node_id))
                     .and_then(|_| future::err(()));
                 A(future)
@@ -520,13 +900,20 @@ fn connect_and_propagate(
                     client.logger(),
                     "peer node ID differs from the expected {}", node_id
                 );
+                state.peers.record_dial_failure(node_id);
+                state
+                    .topology
+                    .reputation()
+                    .record(node_id, p2p::PeerEvent::InvalidPublicId);
                 let report_and_fail = state
                     .topology
-                    .report_node(node_id, StrikeReason::InvalidPublicId)
+                    .report_node(node_id, p2p::Strike::InvalidPublicId)
                     .join(state.peers.remove_peer(node_id))
                     .and_then(|_| future::err(()));
                 A(report_and_fail)
             } else {
+                state.peers.record_dial_success(node_id);
+                state.topology.clear_strikes(node_id);
                 state.inc_client_count();
                 debug!(
                     client.logger(),
@@ -537,10 +924,9 @@ fn connect_and_propagate(
                     state.dec_client_count();
                     res
                 });
-                B(future)
+                B(future::ok(future))
             }
-        });
-    spawn_state.spawn(cf);
+        })
 }
 
 fn trusted_peers_shuffled(config: &Configuration) -> Vec<SocketAddr> {
@@ -549,8 +935,7 @@ fn trusted_peers_shuffled(config: &Configuration) -> Vec<SocketAddr> {
         .iter()
         .filter_map(|peer| peer.address.to_socketaddr())
         .collect::<Vec<_>>();
-    let mut rng = rand::thread_rng();
-    peers.shuffle(&mut rng);
+    config.rng.with_rng(|rng| peers.shuffle(rng));
     peers
 }
 
@@ -566,32 +951,60 @@ pub fn bootstrap(
 
     let mut bootstrapped = false;
 
-    if config.trusted_peers.is_empty() {
+    if config.trusted_peers.is_empty() && config.block_gateways.is_empty() {
         warn!(logger, "No trusted peers joinable to bootstrap the network");
         bootstrapped = true;
     }
 
-    for address in trusted_peers_shuffled(&config) {
-        let logger = logger.new(o!("peer_addr" => address.to_string()));
-        let peer = Peer::new(address, Protocol::Grpc);
-        let res = bootstrap::bootstrap_from_peer(
-            peer,
+    let peers = trusted_peers_shuffled(&config)
+        .into_iter()
+        .map(|address| Peer::new(address, Protocol::Grpc))
+        .collect::<Vec<_>>();
+    if !peers.is_empty() {
+        let res = bootstrap::bootstrap_from_peers(
+            peers,
+            config.sync_peers,
+            config.outbound_bind_address,
+            &config.trusted_checkpoints,
             blockchain.clone(),
             branch.clone(),
             logger.clone(),
         );
 
         match res {
-            Err(bootstrap::Error::Connect { source: e }) => {
-                warn!(logger, "unable to reach peer for initial bootstrap"; "reason" => %e);
-            }
             Err(e) => {
                 warn!(logger, "initial bootstrap failed"; "error" => ?e);
             }
             Ok(()) => {
                 info!(logger, "initial bootstrap completed");
                 bootstrapped = true;
-                break;
+            }
+        }
+    }
+
+    if !bootstrapped {
+        for url in config.block_gateways.iter() {
+            let logger = logger.new(o!("gateway" => url.to_string()));
+            let res = Gateway::new(url.clone(), config.outbound_bind_address)
+                .map_err(|e| bootstrap::Error::Gateway { source: e })
+                .and_then(|gateway| {
+                    bootstrap::bootstrap_from_gateway(
+                        &gateway,
+                        &config.trusted_checkpoints,
+                        blockchain.clone(),
+                        branch.clone(),
+                        logger.clone(),
+                    )
+                });
+            match res {
+                Err(e) => {
+                    warn!(logger, "initial bootstrap from block gateway failed"; "error" => ?e);
+                }
+                Ok(()) => {
+                    info!(logger, "initial bootstrap from block gateway completed");
+                    bootstrapped = true;
+                    break;
+                }
             }
         }
     }
@@ -599,12 +1012,37 @@ pub fn bootstrap(
     Ok(bootstrapped)
 }
 
+/// Downloads the chain snapshot of the first block gateway serving one
+/// to the file at `path`. Returns `false` if none of them could provide it.
+pub fn fetch_chain_snapshot(config: &Configuration, path: &Path, logger: &Logger) -> bool {
+    for url in config.block_gateways.iter() {
+        let logger = logger.new(o!("gateway" => url.to_string()));
+        match Gateway::new(url.clone(), config.outbound_bind_address)
+            .and_then(|gateway| gateway.fetch_chain_snapshot(path))
+        {
+            Err(e) => {
+                warn!(logger, "failed to download the chain snapshot from block gateway"; "error" => ?e);
+            }
+            Ok(false) => {
+                debug!(logger, "block gateway serves no chain snapshot");
+            }
+            Ok(true) => {
+                info!(logger, "chain snapshot downloaded from block gateway");
+                return true;
+            }
+        }
+    }
+    false
+}
+
 /// Queries the trusted peers for a block identified with the hash.
 /// The calling thread is blocked until the block is retrieved.
 /// This function is called during blockchain initialization
-/// to retrieve the genesis block.
+/// to retrieve the genesis block, from the given trusted peers or the block
+/// gateways.
 pub fn fetch_block(
     config: &Configuration,
+    trusted_peers: &[TrustedPeer],
     hash: HeaderHash,
     logger: &Logger,
 ) -> Result<Block, FetchBlockError> {
@@ -612,7 +1050,7 @@ pub fn fetch_block(
         unimplemented!()
     }
 
-    if config.trusted_peers.is_empty() {
+    if trusted_peers.is_empty() && config.block_gateways.is_empty() {
         return Err(FetchBlockError::NoTrustedPeers);
     }
 
@@ -620,20 +1058,36 @@ pub fn fetch_block(
 
     let logger = logger.new(o!("block" => hash.to_string()));
 
-    for address in trusted_peers_shuffled(&config) {
-        let logger = logger.new(o!("peer_address" => address.to_string()));
-        let peer = Peer::new(address, Protocol::Grpc);
-        match grpc::fetch_block(peer, hash, &logger) {
-            Err(grpc::FetchBlockError::Connect { source: e }) => {
-                warn!(logger, "unable to reach peer for block download"; "reason" => %e);
-            }
+    let peers = trusted_peers
+        .iter()
+        .filter_map(|peer| peer.address.to_socketaddr())
+        .collect::<Vec<_>>();
+    if !peers.is_empty() {
+        match grpc::fetch_block(&peers, config.outbound_bind_address, hash, &logger) {
             Err(e) => {
-                warn!(logger, "failed to download block"; "error" => ?e);
+                warn!(logger, "failed to download block from the trusted peers"; "reason" => %e);
             }
             Ok(b) => {
                 info!(logger, "genesis block fetched");
                 block = Some(b);
-                break;
+            }
+        }
+    }
+
+    if block.is_none() {
+        for url in config.block_gateways.iter() {
+            let logger = logger.new(o!("gateway" => url.to_string()));
+            match Gateway::new(url.clone(), config.outbound_bind_address)
+                .and_then(|gateway| gateway.fetch_block(hash))
+            {
+                Err(e) => {
+                    warn!(logger, "failed to download block from block gateway"; "error" => ?e);
+                }
+                Ok(b) => {
+                    info!(logger, "genesis block fetched from block gateway");
+                    block = Some(b);
+                    break;
+                }
             }
         }
     }
@@ -649,7 +1103,7 @@ pub fn fetch_block(
 
 #[derive(Debug, Error)]
 pub enum FetchBlockError {
-    #[error("no trusted peers specified")]
+    #[error("no trusted peers or block gateways specified")]
     NoTrustedPeers,
     #[error("could not download block hash {block}")]
     CouldNotDownloadBlock { block: HeaderHash },
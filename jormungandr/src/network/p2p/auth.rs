@@ -0,0 +1,290 @@
+//! authentication of the gossip entries
+//!
+//! Every node signs the gossip entry describing itself with its gossip
+//! key, and the node identifier is derived from the public key of that
+//! key. An entry is only accepted if it is signed with the key its node
+//! identifier is derived from, so a node cannot advertise itself under
+//! the identifier of another one.
+//!
+//! Signed entries of other nodes are kept so they can be relayed to
+//! our peers along with their signature. The node's own entry is signed
+//! again whenever its profile changes.
+
+use crate::network::p2p::{Gossip, GossipAuth, Id};
+use chain_crypto::{Ed25519, PublicKey, SecretKey, Signature, Verification};
+use jormungandr_lib::crypto::node_id::node_id;
+use network_core::gossip::Node as _;
+use poldercast::NodeProfile;
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+/// how long the signed entry of a node is kept without receiving it again
+const SIGNED_ENTRY_DURATION: Duration = Duration::from_secs(3600);
+
+/// limit on the number of nodes for which a signed entry is kept, same as
+/// the limit on the number of nodes in the topology
+const MAX_SIGNED_ENTRIES: usize = 10000;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AuthError {
+    /// the entry is not signed and unsigned entries are not accepted,
+    /// or a signed entry is known for the node
+    Unsigned,
+    /// the public key or the signature cannot be decoded
+    Malformed,
+    /// the signature does not match the entry
+    InvalidSignature,
+    /// the node identifier is not the one derived from the key the entry
+    /// is signed with
+    KeyMismatch,
+}
+
+impl fmt::Display for AuthError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let msg = match self {
+            AuthError::Unsigned => "gossip entry is not signed",
+            AuthError::Malformed => "malformed gossip signature",
+            AuthError::InvalidSignature => "invalid gossip signature",
+            AuthError::KeyMismatch => "gossip entry is not signed with the key of the node",
+        };
+        f.write_str(msg)
+    }
+}
+
+/// Signs the node's own gossip and verifies the gossip received from peers.
+#[derive(Clone)]
+pub struct GossipAuthenticator {
+    inner: Arc<Mutex<Inner>>,
+    accept_unsigned: bool,
+}
+
+struct Inner {
+    key: SecretKey<Ed25519>,
+    own: Option<Gossip>,
+    signed: HashMap<Id, SignedEntry>,
+}
+
+struct SignedEntry {
+    gossip: Gossip,
+    last_seen: Instant,
+}
+
+impl GossipAuthenticator {
+    pub fn new(key: SecretKey<Ed25519>, accept_unsigned: bool) -> Self {
+        GossipAuthenticator {
+            inner: Arc::new(Mutex::new(Inner {
+                key,
+                own: None,
+                signed: HashMap::new(),
+            })),
+            accept_unsigned,
+        }
+    }
+
+    /// the public key the node's own gossip is signed with
+    pub fn public_key(&self) -> PublicKey<Ed25519> {
+        self.inner.lock().unwrap().key.to_public()
+    }
+
+    /// get the signed gossip entry for the node's own profile
+    pub fn sign(&self, profile: &NodeProfile) -> Gossip {
+        let mut inner = self.inner.lock().unwrap();
+        if let Some(gossip) = &inner.own {
+            if gossip.profile() == profile {
+                return gossip.clone();
+            }
+        }
+        let unsigned = Gossip::from(profile.clone());
+        let signature = inner.key.sign(&unsigned.signed_data());
+        let auth = GossipAuth {
+            public_key: inner.key.to_public().as_ref().to_vec(),
+            signature: signature.as_ref().to_vec(),
+        };
+        let gossip = Gossip::new(profile.clone(), Some(auth));
+        inner.own = Some(gossip.clone());
+        gossip
+    }
+
+    /// get the gossip entry to relay for the given profile, signed if
+    /// the node described by the profile signed it.
+    ///
+    /// The node's own entry is always signed with the current profile,
+    /// an entry of the node relayed back by the peers is never used.
+    pub fn attach(&self, profile: NodeProfile, self_id: Id) -> Gossip {
+        let id: Id = (*profile.id()).into();
+        if id == self_id {
+            return self.sign(&profile);
+        }
+        let inner = self.inner.lock().unwrap();
+        match inner.signed.get(&id) {
+            Some(entry) if *entry.gossip.profile() == profile => entry.gossip.clone(),
+            _ => Gossip::from(profile),
+        }
+    }
+
+    /// check the entry received from a peer before it is accepted
+    /// into the topology.
+    pub fn verify(&self, gossip: &Gossip) -> Result<(), AuthError> {
+        let id = gossip.id();
+        let mut inner = self.inner.lock().unwrap();
+        let now = Instant::now();
+
+        let auth = match gossip.auth() {
+            Some(auth) => auth,
+            None => {
+                // Once the node has been seen signing its entries, an
+                // unsigned entry for it can only come from another node.
+                let known_signed = inner.signed.get(&id).map_or(false, |entry| {
+                    now.duration_since(entry.last_seen) < SIGNED_ENTRY_DURATION
+                });
+                return if self.accept_unsigned && !known_signed {
+                    Ok(())
+                } else {
+                    Err(AuthError::Unsigned)
+                };
+            }
+        };
+
+        let public_key = PublicKey::<Ed25519>::from_binary(&auth.public_key)
+            .map_err(|_| AuthError::Malformed)?;
+        if Id::from(node_id(&public_key)) != id {
+            return Err(AuthError::KeyMismatch);
+        }
+        let signature = Signature::<Vec<u8>, Ed25519>::from_binary(&auth.signature)
+            .map_err(|_| AuthError::Malformed)?;
+        match signature.verify(&public_key, &gossip.signed_data()) {
+            Verification::Success => {}
+            Verification::Failed => return Err(AuthError::InvalidSignature),
+        }
+
+        if inner.signed.len() >= MAX_SIGNED_ENTRIES && !inner.signed.contains_key(&id) {
+            inner.prune(now);
+            if inner.signed.len() >= MAX_SIGNED_ENTRIES {
+                // The signature is valid, the entry is only not relayed
+                // with it.
+                return Ok(());
+            }
+        }
+        inner.signed.insert(
+            id,
+            SignedEntry {
+                gossip: gossip.clone(),
+                last_seen: now,
+            },
+        );
+        Ok(())
+    }
+}
+
+impl Inner {
+    fn prune(&mut self, now: Instant) {
+        self.signed
+            .retain(|_, entry| now.duration_since(entry.last_seen) < SIGNED_ENTRY_DURATION);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use poldercast::{Address, NodeProfileBuilder};
+    use std::net::Ipv4Addr;
+
+    fn new_key() -> SecretKey<Ed25519> {
+        SecretKey::<Ed25519>::generate(rand::thread_rng())
+    }
+
+    // The profile of the node with the gossip key `key`, at an address
+    // numbered `n`.
+    fn profile(key: &SecretKey<Ed25519>, n: u32) -> NodeProfile {
+        let mut builder = NodeProfileBuilder::new();
+        builder.id(node_id(&key.to_public()));
+        builder.address(Address::new(Ipv4Addr::from(0x0a00_0000 + n)).ok().unwrap());
+        builder.build()
+    }
+
+    // The entry of the node with the gossip key `key`, signed with it.
+    fn signed(key: SecretKey<Ed25519>, n: u32) -> Gossip {
+        let profile = profile(&key, n);
+        GossipAuthenticator::new(key, false).sign(&profile)
+    }
+
+    #[test]
+    fn entry_signed_with_the_key_of_the_node_is_accepted() {
+        let gossip = signed(new_key(), 1);
+
+        let verifier = GossipAuthenticator::new(new_key(), false);
+        assert_eq!(verifier.verify(&gossip), Ok(()));
+    }
+
+    #[test]
+    fn entry_signed_with_another_key_is_refused() {
+        let key = new_key();
+        let other_key = new_key();
+        // the entry claims the identifier of the node of `key`
+        let gossip = GossipAuthenticator::new(other_key, false).sign(&profile(&key, 1));
+
+        let verifier = GossipAuthenticator::new(new_key(), true);
+        assert_eq!(verifier.verify(&gossip), Err(AuthError::KeyMismatch));
+    }
+
+    #[test]
+    fn entry_altered_after_signing_is_refused() {
+        let key = new_key();
+        let altered_profile = profile(&key, 2);
+        let gossip = signed(key, 1);
+        let altered = Gossip::new(altered_profile, gossip.auth().cloned());
+
+        let verifier = GossipAuthenticator::new(new_key(), true);
+        assert_eq!(verifier.verify(&altered), Err(AuthError::InvalidSignature));
+    }
+
+    #[test]
+    fn malformed_signature_is_refused() {
+        let gossip = signed(new_key(), 1);
+        let mut auth = gossip.auth().cloned().unwrap();
+        auth.signature.truncate(10);
+        let gossip = Gossip::new(gossip.profile().clone(), Some(auth));
+
+        let verifier = GossipAuthenticator::new(new_key(), true);
+        assert_eq!(verifier.verify(&gossip), Err(AuthError::Malformed));
+    }
+
+    #[test]
+    fn unsigned_entry_is_accepted_only_if_configured() {
+        let key = new_key();
+        let gossip = Gossip::from(profile(&key, 1));
+
+        let strict = GossipAuthenticator::new(new_key(), false);
+        assert_eq!(strict.verify(&gossip), Err(AuthError::Unsigned));
+        let lenient = GossipAuthenticator::new(new_key(), true);
+        assert_eq!(lenient.verify(&gossip), Ok(()));
+    }
+
+    #[test]
+    fn unsigned_entry_after_a_signed_one_is_refused() {
+        let key = new_key();
+        let unsigned = Gossip::from(profile(&key, 2));
+        let gossip = signed(key, 1);
+
+        let verifier = GossipAuthenticator::new(new_key(), true);
+        assert_eq!(verifier.verify(&gossip), Ok(()));
+        assert_eq!(verifier.verify(&unsigned), Err(AuthError::Unsigned));
+    }
+
+    #[test]
+    fn signed_entry_is_relayed_with_its_signature() {
+        let key = new_key();
+        let other_profile = profile(&key, 2);
+        let gossip = signed(key, 1);
+
+        let verifier = GossipAuthenticator::new(new_key(), true);
+        let self_id: Id = node_id(&verifier.public_key()).into();
+        verifier.verify(&gossip).unwrap();
+        assert_eq!(verifier.attach(gossip.profile().clone(), self_id), gossip);
+        assert!(verifier.attach(other_profile, self_id).auth().is_none());
+    }
+}
@@ -1,34 +1,44 @@
+mod backoff;
 mod peer_map;
 
+pub use backoff::DialBackoff;
 use peer_map::{CommStatus, PeerMap};
 
 use crate::blockcfg::{Block, Fragment, Header, HeaderHash};
 use crate::network::{
     client::ConnectHandle,
-    p2p::{Gossip as NodeData, Id, Node as NodeRef},
+    p2p::{Gossip as NodeData, Gossips, Id, Node as NodeRef, PeerEvent, Reputation},
 };
+use crate::utils::{bandwidth::WireSize, rng::RngProvider};
 use futures::prelude::*;
 use futures::stream;
 use futures::sync::mpsc;
 use network_core::error as core_error;
 use network_core::gossip::{Gossip, Node};
 use network_core::subscription::{BlockEvent, ChainPullRequest};
+use serde::{Deserialize, Serialize};
 use slog::Logger;
 use tokio::{
     prelude::future,
     sync::lock::{Lock, LockGuard},
 };
 
+use std::collections::{HashSet, VecDeque};
 use std::fmt;
 use std::mem;
 use std::net::SocketAddr;
-use std::time::SystemTime;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
 
 // Buffer size determines the number of stream items pending processing that
 // can be buffered before back pressure is applied to the inbound half of
 // a gRPC subscription stream.
 const BUFFER_LEN: usize = 8;
 
+// Number of the latest headers announced to a peer that are remembered
+// to skip announcing them again.
+const ANNOUNCED_HEADERS_PER_PEER: usize = 16;
+
 #[derive(Debug)]
 pub struct PropagateError<T> {
     kind: ErrorKind,
@@ -105,21 +115,59 @@ pub type GossipSubscription = OutboundSubscription<Gossip<NodeData>>;
 pub struct CommHandle<T> {
     state: SubscriptionState<T>,
     direction: SubscriptionDirection,
+    last_error: Option<(ErrorKind, SystemTime)>,
 }
 
 /// Indicates whether this subscription belongs to a client or a server
 /// connection.
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Debug)]
 pub enum SubscriptionDirection {
     Client,
     Server,
 }
 
+impl fmt::Display for SubscriptionDirection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SubscriptionDirection::Client => f.write_str("client"),
+            SubscriptionDirection::Server => f.write_str("server"),
+        }
+    }
+}
+
+/// The state of a subscription as reported for debugging.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SubscriptionStatus {
+    NotSubscribed,
+    Pending,
+    Subscribed,
+}
+
+impl fmt::Display for SubscriptionStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SubscriptionStatus::NotSubscribed => f.write_str("not subscribed"),
+            SubscriptionStatus::Pending => f.write_str("pending"),
+            SubscriptionStatus::Subscribed => f.write_str("subscribed"),
+        }
+    }
+}
+
+/// Snapshot of an outbound subscription to a peer.
+#[derive(Clone, Debug)]
+pub struct SubscriptionInfo {
+    pub status: SubscriptionStatus,
+    pub direction: SubscriptionDirection,
+    /// the latest failure to send an item and when it occurred
+    pub last_error: Option<(ErrorKind, SystemTime)>,
+}
+
 impl<T> Default for CommHandle<T> {
     fn default() -> Self {
         CommHandle {
             state: SubscriptionState::NotSubscribed,
             direction: SubscriptionDirection::Server,
+            last_error: None,
         }
     }
 }
@@ -131,6 +179,7 @@ impl<T> CommHandle<T> {
         CommHandle {
             state: SubscriptionState::Pending(item),
             direction: SubscriptionDirection::Client,
+            last_error: None,
         }
     }
 
@@ -193,6 +242,19 @@ impl<T> CommHandle<T> {
         }
     }
 
+    pub fn info(&self) -> SubscriptionInfo {
+        let status = match self.state {
+            SubscriptionState::NotSubscribed => SubscriptionStatus::NotSubscribed,
+            SubscriptionState::Pending(_) => SubscriptionStatus::Pending,
+            SubscriptionState::Subscribed(_) => SubscriptionStatus::Subscribed,
+        };
+        SubscriptionInfo {
+            status,
+            direction: self.direction,
+            last_error: self.last_error,
+        }
+    }
+
     // Try sending an item to the subscriber.
     // Sending is done as best effort: if the stream buffer is full due to a
     // blockage downstream, a `StreamOverflow` error is returned and
@@ -200,6 +262,14 @@ impl<T> CommHandle<T> {
     // If the subscription is in the pending state with an item already waiting
     // to be sent, the new item replaces the previous pending item.
     pub fn try_send(&mut self, item: T) -> Result<(), PropagateError<T>> {
+        let res = self.try_send_inner(item);
+        if let Err(e) = &res {
+            self.last_error = Some((e.kind(), SystemTime::now()));
+        }
+        res
+    }
+
+    fn try_send_inner(&mut self, item: T) -> Result<(), PropagateError<T>> {
         match self.state {
             SubscriptionState::NotSubscribed => Err(PropagateError {
                 kind: ErrorKind::NotSubscribed,
@@ -237,6 +307,27 @@ enum SubscriptionState<T> {
     Subscribed(mpsc::Sender<T>),
 }
 
+/// Hashes of the latest headers announced to a peer.
+#[derive(Default)]
+struct AnnouncedHeaders(VecDeque<HeaderHash>);
+
+impl AnnouncedHeaders {
+    fn contains(&self, hash: &HeaderHash) -> bool {
+        self.0.contains(hash)
+    }
+
+    fn clear(&mut self) {
+        self.0.clear();
+    }
+
+    fn insert(&mut self, hash: HeaderHash) {
+        if self.0.len() >= ANNOUNCED_HEADERS_PER_PEER {
+            self.0.pop_front();
+        }
+        self.0.push_back(hash);
+    }
+}
+
 /// State of the communication streams that a single peer connection polls
 /// for outbound data and commands.
 ///
@@ -250,6 +341,19 @@ pub struct PeerComms {
     chain_pulls: CommHandle<ChainPullRequest<HeaderHash>>,
     fragments: CommHandle<Fragment>,
     gossip: CommHandle<Gossip<NodeData>>,
+    announced_headers: AnnouncedHeaders,
+}
+
+/// Snapshot of the communication state with a peer.
+#[derive(Clone, Debug)]
+pub struct PeerCommsInfo {
+    pub block_announcements: SubscriptionInfo,
+    pub block_solicitations: SubscriptionInfo,
+    pub chain_pulls: SubscriptionInfo,
+    pub fragments: SubscriptionInfo,
+    pub gossip: SubscriptionInfo,
+    /// the latest headers announced to the peer, oldest first
+    pub announced_headers: Vec<HeaderHash>,
 }
 
 impl PeerComms {
@@ -257,6 +361,17 @@ impl PeerComms {
         Default::default()
     }
 
+    pub fn info(&self) -> PeerCommsInfo {
+        PeerCommsInfo {
+            block_announcements: self.block_announcements.info(),
+            block_solicitations: self.block_solicitations.info(),
+            chain_pulls: self.chain_pulls.info(),
+            fragments: self.fragments.info(),
+            gossip: self.gossip.info(),
+            announced_headers: self.announced_headers.0.iter().cloned().collect(),
+        }
+    }
+
     pub fn has_client_subscriptions(&self) -> bool {
         self.block_announcements.is_client()
             || self.fragments.is_client()
@@ -275,6 +390,8 @@ impl PeerComms {
     }
 
     pub fn clear_pending(&mut self) {
+        // A pending announcement may have been counted as sent.
+        self.announced_headers.clear();
         self.block_announcements.clear_pending();
         self.fragments.clear_pending();
         self.gossip.clear_pending();
@@ -298,7 +415,15 @@ impl PeerComms {
         &mut self,
         header: Header,
     ) -> Result<(), PropagateError<Header>> {
-        self.block_announcements.try_send(header)
+        // The same header may come to be announced through different paths,
+        // the peer only needs it once.
+        let hash = header.hash();
+        if self.announced_headers.contains(&hash) {
+            return Ok(());
+        }
+        self.block_announcements.try_send(header)?;
+        self.announced_headers.insert(hash);
+        Ok(())
     }
 
     pub fn try_send_fragment(
@@ -380,12 +505,48 @@ pub struct ConnectOptions {
     pub evict_clients: usize,
 }
 
+/// The kinds of items exchanged with the peers.
+#[derive(Copy, Clone, Debug)]
+pub enum ItemKind {
+    Block,
+    Header,
+    Fragment,
+    Gossip,
+}
+
+/// Counts of the items exchanged with a peer in one direction,
+/// and of the bytes they took on the wire.
+#[derive(Clone, Debug, Default)]
+pub struct TrafficCounters {
+    pub bytes: u64,
+    pub blocks: u64,
+    pub headers: u64,
+    pub fragments: u64,
+    pub gossip: u64,
+}
+
+impl TrafficCounters {
+    fn add(&mut self, kind: ItemKind, count: usize, bytes: usize) {
+        let counter = match kind {
+            ItemKind::Block => &mut self.blocks,
+            ItemKind::Header => &mut self.headers,
+            ItemKind::Fragment => &mut self.fragments,
+            ItemKind::Gossip => &mut self.gossip,
+        };
+        *counter += count as u64;
+        self.bytes += bytes as u64;
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct PeerStats {
     created: SystemTime,
     last_block_received: Option<SystemTime>,
     last_fragment_received: Option<SystemTime>,
     last_gossip_received: Option<SystemTime>,
+    response_time: Option<Duration>,
+    received: TrafficCounters,
+    sent: TrafficCounters,
 }
 
 impl Default for PeerStats {
@@ -395,6 +556,9 @@ impl Default for PeerStats {
             last_block_received: None,
             last_fragment_received: None,
             last_gossip_received: None,
+            response_time: None,
+            received: TrafficCounters::default(),
+            sent: TrafficCounters::default(),
         }
     }
 }
@@ -412,6 +576,23 @@ impl PeerStats {
         self.last_gossip_received.clone()
     }
 
+    /// The smoothed time the peer takes to start responding
+    /// to the block and header requests of the node.
+    pub fn response_time(&self) -> Option<Duration> {
+        self.response_time
+    }
+
+    /// The items received from the peer.
+    pub fn received(&self) -> &TrafficCounters {
+        &self.received
+    }
+
+    /// The items sent to the peer, not counting the blocks served
+    /// in response to its requests.
+    pub fn sent(&self) -> &TrafficCounters {
+        &self.sent
+    }
+
     fn update_last_block_received(&mut self, timestamp: SystemTime) {
         update_last_timestamp(&mut self.last_block_received, timestamp)
     }
@@ -424,6 +605,15 @@ impl PeerStats {
         update_last_timestamp(&mut self.last_gossip_received, timestamp)
     }
 
+    // Smoothed like the round-trip time estimate of TCP,
+    // the latest measurement having a weight of 1/8.
+    fn update_response_time(&mut self, time: Duration) {
+        self.response_time = Some(match self.response_time {
+            None => time,
+            Some(smoothed) => (smoothed * 7 + time) / 8,
+        });
+    }
+
     pub fn connection_established(&self) -> SystemTime {
         self.created
     }
@@ -461,6 +651,39 @@ pub struct PeerInfo {
     pub stats: PeerStats,
 }
 
+/// Everything known about the connection with a peer, for debugging.
+#[derive(Debug)]
+pub struct PeerDebugInfo {
+    pub info: PeerInfo,
+    /// a client connection to the peer is being established
+    pub connecting: bool,
+    pub comms: PeerCommsInfo,
+    pub reputation: f64,
+    /// the time left before the peer can be dialled again
+    pub dial_backoff: Option<Duration>,
+}
+
+/// How the peers are picked when connections have to be closed to make
+/// room for new ones. Whatever the policy, the preferred peers are never
+/// evicted, and the synchronization peers only after all the others.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EvictionPolicy {
+    /// the peers with the worst reputation, the least recently
+    /// active first among equal scores
+    LeastUseful,
+    /// the peers connected the longest time ago
+    Oldest,
+    /// peers picked at random
+    Random,
+}
+
+impl Default for EvictionPolicy {
+    fn default() -> Self {
+        EvictionPolicy::LeastUseful
+    }
+}
+
 /// The collection of currently connected peer nodes.
 ///
 /// This object uses internal locking and is shared between
@@ -468,13 +691,35 @@ pub struct PeerInfo {
 #[derive(Clone)]
 pub struct Peers {
     mutex: Lock<peer_map::PeerMap>,
+    reputation: Reputation,
+    backoff: Arc<Mutex<DialBackoff>>,
     logger: Logger,
 }
 
 impl Peers {
-    pub fn new(capacity: usize, logger: Logger) -> Self {
+    pub fn new(
+        capacity: usize,
+        preferred: HashSet<Id>,
+        trusted: HashSet<Id>,
+        reserved_sync: usize,
+        eviction: EvictionPolicy,
+        reputation: Reputation,
+        backoff: DialBackoff,
+        rng: RngProvider,
+        logger: Logger,
+    ) -> Self {
         Peers {
-            mutex: Lock::new(peer_map::PeerMap::new(capacity)),
+            mutex: Lock::new(peer_map::PeerMap::new(
+                capacity,
+                preferred,
+                trusted,
+                reserved_sync,
+                eviction,
+                reputation.clone(),
+                rng,
+            )),
+            reputation,
+            backoff: Arc::new(Mutex::new(backoff)),
             logger,
         }
     }
@@ -522,6 +767,71 @@ impl Peers {
         })
     }
 
+    /// Sends the items to the peer, or sets them to be sent once its
+    /// subscriptions are established.
+    pub fn deliver<E>(&self, id: Id, options: ConnectOptions) -> impl Future<Item = (), Error = E> {
+        let logger = self.logger.clone();
+        self.inner().map(move |mut map| {
+            let mut entry = match map.entry(id) {
+                Some(entry) => entry,
+                None => return,
+            };
+            match entry.update_comm_status() {
+                CommStatus::Connecting(comms) => {
+                    if let Some(header) = options.pending_block_announcement {
+                        comms.set_pending_block_announcement(header);
+                    }
+                    if let Some(fragment) = options.pending_fragment {
+                        comms.set_pending_fragment(fragment);
+                    }
+                    if let Some(gossip) = options.pending_gossip {
+                        comms.set_pending_gossip(gossip);
+                    }
+                }
+                CommStatus::Established(comms) => {
+                    let mut results = Vec::new();
+                    if let Some(header) = options.pending_block_announcement {
+                        results.push(
+                            comms
+                                .try_send_block_announcement(header)
+                                .map_err(|e| e.kind()),
+                        );
+                    }
+                    if let Some(fragment) = options.pending_fragment {
+                        results.push(comms.try_send_fragment(fragment).map_err(|e| e.kind()));
+                    }
+                    if let Some(gossip) = options.pending_gossip {
+                        results.push(comms.try_send_gossip(gossip).map_err(|e| e.kind()));
+                    }
+                    for e in results.into_iter().filter_map(Result::err) {
+                        debug!(
+                            logger,
+                            "failed to send the items given while connecting";
+                            "node_id" => %id,
+                            "reason" => %e,
+                        );
+                    }
+                }
+            }
+        })
+    }
+
+    /// The time left before the peer can be dialled again after failures
+    /// to connect to it, `None` if it can be dialled now.
+    pub fn dial_backoff(&self, id: Id) -> Option<Duration> {
+        self.backoff.lock().unwrap().remaining(&id)
+    }
+
+    /// Records a failure to connect to the peer and returns the delay
+    /// before it can be dialled again.
+    pub fn record_dial_failure(&self, id: Id) -> Duration {
+        self.backoff.lock().unwrap().record_failure(id)
+    }
+
+    pub fn record_dial_success(&self, id: Id) {
+        self.backoff.lock().unwrap().record_success(&id)
+    }
+
     pub fn remove_peer<E>(&self, id: Id) -> impl Future<Item = Option<PeerComms>, Error = E> {
         self.inner().map(move |mut map| map.remove_peer(id))
     }
@@ -536,12 +846,15 @@ impl Peers {
     fn propagate_with<T, F>(
         &self,
         nodes: Vec<NodeRef>,
+        kind: ItemKind,
+        bytes: usize,
         f: F,
     ) -> impl Future<Item = (), Error = Vec<NodeRef>>
     where
         for<'a> F: Fn(CommStatus<'a>) -> Result<(), PropagateError<T>>,
     {
         let logger = self.logger.clone();
+        let reputation = self.reputation.clone();
         self.inner().and_then(move |mut map| {
             let unreached_nodes = nodes
                 .into_iter()
@@ -549,7 +862,10 @@ impl Peers {
                     let id = node.id();
                     if let Some(mut entry) = map.entry(id) {
                         match f(entry.update_comm_status()) {
-                            Ok(()) => false,
+                            Ok(()) => {
+                                entry.stats().sent.add(kind, 1, bytes);
+                                false
+                            }
                             Err(e) => {
                                 debug!(
                                     logger,
@@ -557,6 +873,7 @@ impl Peers {
                                     "node_id" => %id,
                                     "reason" => %e.kind()
                                 );
+                                reputation.record(id, PeerEvent::StreamDropped);
                                 entry.remove();
                                 true
                             }
@@ -584,7 +901,8 @@ impl Peers {
             "propagating block";
             "hash" => %header.hash(),
         );
-        self.propagate_with(nodes, move |status| match status {
+        let bytes = header.wire_size();
+        self.propagate_with(nodes, ItemKind::Header, bytes, move |status| match status {
             CommStatus::Established(comms) => comms.try_send_block_announcement(header.clone()),
             CommStatus::Connecting(comms) => {
                 comms.set_pending_block_announcement(header.clone());
@@ -602,19 +920,25 @@ impl Peers {
             self.logger,
             "propagating fragment";
         );
-        self.propagate_with(nodes, move |status| match status {
-            CommStatus::Established(comms) => comms.try_send_fragment(fragment.clone()),
-            CommStatus::Connecting(comms) => {
-                comms.set_pending_fragment(fragment.clone());
-                Ok(())
-            }
-        })
+        let bytes = fragment.wire_size();
+        self.propagate_with(
+            nodes,
+            ItemKind::Fragment,
+            bytes,
+            move |status| match status {
+                CommStatus::Established(comms) => comms.try_send_fragment(fragment.clone()),
+                CommStatus::Connecting(comms) => {
+                    comms.set_pending_fragment(fragment.clone());
+                    Ok(())
+                }
+            },
+        )
     }
 
     pub fn propagate_gossip_to(
         &self,
         target: Id,
-        gossip: Gossip<NodeData>,
+        gossips: Gossips,
     ) -> impl Future<Item = (), Error = Gossip<NodeData>> {
         debug!(
             self.logger,
@@ -622,6 +946,9 @@ impl Peers {
             "node_id" => %target,
         );
         let logger = self.logger.clone();
+        let reputation = self.reputation.clone();
+        let bytes = gossips.wire_size();
+        let gossip = Gossip::from(gossips);
         self.inner().and_then(move |mut map| {
             if let Some(mut entry) = map.entry(target) {
                 let res = match entry.update_comm_status() {
@@ -631,6 +958,9 @@ impl Peers {
                         Ok(())
                     }
                 };
+                if res.is_ok() {
+                    entry.stats().sent.add(ItemKind::Gossip, 1, bytes);
+                }
                 res.map_err(|e| {
                     debug!(
                         logger,
@@ -638,6 +968,7 @@ impl Peers {
                         "node_id" => %target,
                         "reason" => %e.kind(),
                     );
+                    reputation.record(target, PeerEvent::StreamDropped);
                     entry.remove();
                     e.into_item()
                 })
@@ -647,44 +978,91 @@ impl Peers {
         })
     }
 
-    pub fn refresh_peer_on_block<E>(&self, node_id: Id) -> impl Future<Item = bool, Error = E> {
+    /// Updates the statistics of the peer on a block announcement
+    /// received from it, with the size of the header.
+    pub fn refresh_peer_on_block<E>(
+        &self,
+        node_id: Id,
+        bytes: usize,
+    ) -> impl Future<Item = bool, Error = E> {
         let timestamp = SystemTime::now();
         self.inner()
             .map(move |mut map| match map.refresh_peer(&node_id) {
                 Some(stats) => {
                     stats.update_last_block_received(timestamp);
+                    stats.received.add(ItemKind::Header, 1, bytes);
                     true
                 }
                 None => false,
             })
     }
 
-    pub fn refresh_peer_on_fragment<E>(&self, node_id: Id) -> impl Future<Item = bool, Error = E> {
+    pub fn refresh_peer_on_fragment<E>(
+        &self,
+        node_id: Id,
+        count: usize,
+        bytes: usize,
+    ) -> impl Future<Item = bool, Error = E> {
         let timestamp = SystemTime::now();
         self.inner()
             .map(move |mut map| match map.refresh_peer(&node_id) {
                 Some(stats) => {
                     stats.update_last_fragment_received(timestamp);
+                    stats.received.add(ItemKind::Fragment, count, bytes);
                     true
                 }
                 None => false,
             })
     }
 
-    pub fn refresh_peer_on_gossip<E>(&self, node_id: Id) -> impl Future<Item = bool, Error = E> {
+    pub fn refresh_peer_on_gossip<E>(
+        &self,
+        node_id: Id,
+        bytes: usize,
+    ) -> impl Future<Item = bool, Error = E> {
         let timestamp = SystemTime::now();
         self.inner()
             .map(move |mut map| match map.refresh_peer(&node_id) {
                 Some(stats) => {
                     stats.update_last_gossip_received(timestamp);
+                    stats.received.add(ItemKind::Gossip, 1, bytes);
                     true
                 }
                 None => false,
             })
     }
 
+    /// Counts items received from the peer in response to a request.
+    pub fn record_received<E>(
+        &self,
+        node_id: Id,
+        kind: ItemKind,
+        count: usize,
+        bytes: usize,
+    ) -> impl Future<Item = (), Error = E> {
+        self.inner().map(move |mut map| {
+            if let Some(stats) = map.peer_stats(&node_id) {
+                stats.received.add(kind, count, bytes);
+            }
+        })
+    }
+
+    /// Records the time the peer took to start responding to a request.
+    pub fn record_response_time<E>(
+        &self,
+        node_id: Id,
+        time: Duration,
+    ) -> impl Future<Item = (), Error = E> {
+        self.inner().map(move |mut map| {
+            if let Some(stats) = map.peer_stats(&node_id) {
+                stats.update_response_time(time);
+            }
+        })
+    }
+
     pub fn fetch_blocks<E>(&self, hashes: Vec<HeaderHash>) -> impl Future<Item = (), Error = E> {
         let logger = self.logger.clone();
+        let reputation = self.reputation.clone();
         self.inner().map(move |mut map| {
             if let Some((node_id, comms)) = map.next_peer_for_block_fetch() {
                 debug!(logger, "fetching blocks from {}", node_id);
@@ -694,6 +1072,7 @@ impl Peers {
                     .unwrap_or_else(|e| {
                         debug!(logger, "block fetch from {} failed: {:?}", node_id, e);
                         debug!(logger, "unsubscribing peer {}", node_id);
+                        reputation.record(node_id, PeerEvent::StreamDropped);
                         map.remove_peer(node_id);
                     });
             } else {
@@ -702,12 +1081,20 @@ impl Peers {
         })
     }
 
+    /// Returns up to `max` peers that blocks can be solicited from
+    /// over a client connection.
+    pub fn peers_for_block_fetch<E>(&self, max: usize) -> impl Future<Item = Vec<Id>, Error = E> {
+        self.inner()
+            .map(move |mut map| map.peers_for_block_fetch(max))
+    }
+
     pub fn solicit_blocks<E>(
         &self,
         node_id: Id,
         hashes: Vec<HeaderHash>,
     ) -> impl Future<Item = (), Error = E> {
         let logger = self.logger.clone();
+        let reputation = self.reputation.clone();
         self.inner().map(move |mut map| {
             match map.peer_comms(&node_id) {
                 Some(comms) => {
@@ -722,6 +1109,7 @@ impl Peers {
                                 "block solicitation from {} failed: {:?}", node_id, e
                             );
                             debug!(logger, "unsubscribing peer {}", node_id);
+                            reputation.record(node_id, PeerEvent::StreamDropped);
                             map.remove_peer(node_id);
                         });
                 }
@@ -736,6 +1124,42 @@ impl Peers {
         })
     }
 
+    /// Solicits the blocks from the first of the given peers that takes
+    /// the solicitation, returns `false` if none of them did.
+    pub fn solicit_blocks_from_any<E>(
+        &self,
+        node_ids: Vec<Id>,
+        hashes: Vec<HeaderHash>,
+    ) -> impl Future<Item = bool, Error = E> {
+        let logger = self.logger.clone();
+        let reputation = self.reputation.clone();
+        self.inner().map(move |mut map| {
+            let mut hashes = hashes;
+            for node_id in node_ids {
+                let comms = match map.peer_comms(&node_id) {
+                    Some(comms) => comms,
+                    None => continue,
+                };
+                debug!(logger, "sending block solicitation to {}", node_id;
+                    "hashes" => ?hashes);
+                match comms.block_solicitations.try_send(hashes) {
+                    Ok(()) => return true,
+                    Err(e) => {
+                        debug!(
+                            logger,
+                            "block solicitation from {} failed: {:?}", node_id, e
+                        );
+                        debug!(logger, "unsubscribing peer {}", node_id);
+                        reputation.record(node_id, PeerEvent::StreamDropped);
+                        map.remove_peer(node_id);
+                        hashes = e.into_item();
+                    }
+                }
+            }
+            false
+        })
+    }
+
     pub fn pull_headers<E>(
         &self,
         node_id: Id,
@@ -743,6 +1167,7 @@ impl Peers {
         to: HeaderHash,
     ) -> impl Future<Item = (), Error = E> {
         let logger = self.logger.clone();
+        let reputation = self.reputation.clone();
         self.inner().map(move |mut map| {
         match map.peer_comms(&node_id) {
             Some(comms) => {
@@ -759,6 +1184,7 @@ impl Peers {
                             "sending header pull solicitation to {} failed: {:?}", node_id, e
                         );
                         debug!(logger, "unsubscribing peer {}", node_id);
+                        reputation.record(node_id, PeerEvent::StreamDropped);
                         map.remove_peer(node_id);
                     });
             }
@@ -774,9 +1200,33 @@ impl Peers {
         })
     }
 
+    /// Replaces the set of trusted peers, resolving to the previous one.
+    pub fn replace_trusted<E>(
+        &self,
+        trusted: HashSet<Id>,
+    ) -> impl Future<Item = HashSet<Id>, Error = E> {
+        self.inner()
+            .map(move |mut map| map.replace_trusted(trusted))
+    }
+
     pub fn infos<E>(&self) -> impl Future<Item = Vec<PeerInfo>, Error = E> {
         self.inner().map(|map| map.infos())
     }
+
+    /// The communication state with the peer, `None` if the peer
+    /// is not in the peer map.
+    pub fn debug_info<E>(&self, id: Id) -> impl Future<Item = Option<PeerDebugInfo>, Error = E> {
+        let dial_backoff = self.dial_backoff(id);
+        self.inner()
+            .map(move |mut map| map.debug_info(&id, dial_backoff))
+    }
+
+    /// The communication state with all the peers in the peer map.
+    pub fn debug_infos<E>(&self) -> impl Future<Item = Vec<PeerDebugInfo>, Error = E> {
+        let backoff = self.backoff.clone();
+        self.inner()
+            .map(move |mut map| map.debug_infos(|id| backoff.lock().unwrap().remaining(id)))
+    }
 }
 
 pub struct LockServerComms {
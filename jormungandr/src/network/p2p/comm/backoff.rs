@@ -0,0 +1,111 @@
+use crate::network::p2p::Id;
+use crate::settings::start::network::ReconnectBackoff;
+use crate::utils::rng::RngProvider;
+use rand::Rng;
+use std::cmp;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// limit on the number of peers for which the dial failures are kept
+const MAX_TRACKED_PEERS: usize = 4096;
+
+/// the delay stops doubling after this many consecutive failures,
+/// to avoid overflows; it is capped by the configured maximum anyway
+const MAX_DOUBLINGS: u32 = 16;
+
+/// Failures to connect to the peers, delaying the next attempts
+/// to dial them.
+///
+/// Every consecutive failure doubles the delay before the peer can be
+/// dialled again, up to the configured maximum. The delay is randomly
+/// shortened by up to a half, so the nodes that lost the same peer do not
+/// all dial it again at the same time.
+pub struct DialBackoff {
+    config: ReconnectBackoff,
+    failures: HashMap<Id, Failures>,
+    rng: RngProvider,
+}
+
+struct Failures {
+    count: u32,
+    retry_at: Instant,
+}
+
+impl DialBackoff {
+    pub fn new(config: ReconnectBackoff, rng: RngProvider) -> Self {
+        DialBackoff {
+            config,
+            failures: HashMap::new(),
+            rng,
+        }
+    }
+
+    /// the time left before the peer can be dialled again,
+    /// `None` if it can be dialled now
+    pub fn remaining(&self, id: &Id) -> Option<Duration> {
+        let failures = self.failures.get(id)?;
+        let now = Instant::now();
+        if failures.retry_at > now {
+            Some(failures.retry_at - now)
+        } else {
+            None
+        }
+    }
+
+    /// record a failure to connect to the peer, returns the delay
+    /// before it can be dialled again
+    pub fn record_failure(&mut self, id: Id) -> Duration {
+        let now = Instant::now();
+        if self.failures.len() >= MAX_TRACKED_PEERS && !self.failures.contains_key(&id) {
+            self.failures.retain(|_, failures| failures.retry_at > now);
+        }
+        let config = &self.config;
+        let failures = self.failures.entry(id).or_insert(Failures {
+            count: 0,
+            retry_at: now,
+        });
+        failures.count = failures.count.saturating_add(1);
+        let doublings = cmp::min(failures.count - 1, MAX_DOUBLINGS);
+        let delay = cmp::min(config.initial_delay * 2u32.pow(doublings), config.max_delay);
+        let delay = delay.mul_f64(self.rng.with_rng(|rng| rng.gen_range(0.5, 1.0)));
+        failures.retry_at = now + delay;
+        delay
+    }
+
+    /// forget the failures to connect to the peer
+    pub fn record_success(&mut self, id: &Id) {
+        self.failures.remove(id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use poldercast::NodeProfileBuilder;
+
+    #[test]
+    fn delay_grows_up_to_the_maximum() {
+        let mut backoff = DialBackoff::new(
+            ReconnectBackoff {
+                initial_delay: Duration::from_secs(1),
+                max_delay: Duration::from_secs(10),
+            },
+            RngProvider::random(),
+        );
+        let id: Id = (*NodeProfileBuilder::new().build().id()).into();
+        assert!(backoff.remaining(&id).is_none());
+
+        let first = backoff.record_failure(id);
+        assert!(first >= Duration::from_millis(500) && first <= Duration::from_secs(1));
+        assert!(backoff.remaining(&id).is_some());
+
+        for _ in 0..10 {
+            backoff.record_failure(id);
+        }
+        let last = backoff.record_failure(id);
+        assert!(last >= Duration::from_secs(5) && last <= Duration::from_secs(10));
+
+        backoff.record_success(&id);
+        assert!(backoff.remaining(&id).is_none());
+    }
+}
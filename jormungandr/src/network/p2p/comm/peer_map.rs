@@ -1,16 +1,27 @@
 use crate::network::{
     client::ConnectHandle,
     p2p::{
-        comm::{PeerComms, PeerInfo, PeerStats},
-        Id,
+        comm::{EvictionPolicy, PeerComms, PeerDebugInfo, PeerInfo, PeerStats},
+        Id, Reputation,
     },
 };
+use crate::utils::rng::RngProvider;
 use linked_hash_map::LinkedHashMap;
+use rand::seq::SliceRandom;
+use std::cmp::Ordering;
+use std::collections::HashSet;
 use std::net::SocketAddr;
+use std::time::Duration;
 
 pub struct PeerMap {
     map: LinkedHashMap<Id, PeerData>,
     capacity: usize,
+    preferred: HashSet<Id>,
+    trusted: HashSet<Id>,
+    reserved_sync: usize,
+    eviction: EvictionPolicy,
+    reputation: Reputation,
+    rng: RngProvider,
 }
 
 #[derive(Default)]
@@ -36,6 +47,29 @@ impl PeerData {
         }
     }
 
+    fn debug_info(
+        &mut self,
+        id: Id,
+        reputation: f64,
+        dial_backoff: Option<Duration>,
+    ) -> PeerDebugInfo {
+        let connecting = match self.update_comm_status() {
+            CommStatus::Connecting(_) => true,
+            CommStatus::Established(_) => false,
+        };
+        PeerDebugInfo {
+            info: PeerInfo {
+                id,
+                addr: self.addr,
+                stats: self.stats.clone(),
+            },
+            connecting,
+            comms: self.comms.info(),
+            reputation,
+            dial_backoff,
+        }
+    }
+
     fn update_comm_status(&mut self) -> CommStatus<'_> {
         if let Some(ref mut handle) = self.connecting {
             match handle.try_complete() {
@@ -52,6 +86,13 @@ impl PeerData {
         CommStatus::Established(&mut self.comms)
     }
 
+    fn is_established(&mut self) -> bool {
+        match self.update_comm_status() {
+            CommStatus::Established(_) => true,
+            CommStatus::Connecting(_) => false,
+        }
+    }
+
     fn server_comms(&mut self) -> &mut PeerComms {
         // This method is called when a subscription request is received
         // by the server, normally at the beginning of the peer connecting
@@ -72,13 +113,32 @@ impl<'a> CommStatus<'a> {
 }
 
 impl PeerMap {
-    pub fn new(capacity: usize) -> Self {
+    pub fn new(
+        capacity: usize,
+        preferred: HashSet<Id>,
+        trusted: HashSet<Id>,
+        reserved_sync: usize,
+        eviction: EvictionPolicy,
+        reputation: Reputation,
+        rng: RngProvider,
+    ) -> Self {
         PeerMap {
             map: LinkedHashMap::new(),
             capacity,
+            preferred,
+            trusted,
+            reserved_sync,
+            eviction,
+            reputation,
+            rng,
         }
     }
 
+    /// replace the set of trusted peers, returning the previous one
+    pub fn replace_trusted(&mut self, trusted: HashSet<Id>) -> HashSet<Id> {
+        std::mem::replace(&mut self.trusted, trusted)
+    }
+
     pub fn entry<'a>(&'a mut self, id: Id) -> Option<Entry<'a>> {
         use linked_hash_map::Entry::*;
 
@@ -97,6 +157,10 @@ impl PeerMap {
         self.map.get_refresh(&id).map(|data| &mut data.stats)
     }
 
+    pub fn peer_stats(&mut self, id: &Id) -> Option<&mut PeerStats> {
+        self.map.get_mut(id).map(|data| &mut data.stats)
+    }
+
     pub fn peer_comms(&mut self, id: &Id) -> Option<&mut PeerComms> {
         self.map
             .get_mut(id)
@@ -105,7 +169,7 @@ impl PeerMap {
 
     fn ensure_peer(&mut self, id: Id) -> &mut PeerData {
         if !self.map.contains_key(&id) {
-            self.evict_if_full();
+            self.make_room_for(&id);
         }
         self.map.entry(id).or_insert_with(Default::default)
     }
@@ -115,7 +179,9 @@ impl PeerMap {
     }
 
     pub fn insert_peer(&mut self, id: Id, comms: PeerComms, addr: SocketAddr) {
-        self.evict_if_full();
+        if !self.map.contains_key(&id) {
+            self.make_room_for(&id);
+        }
         let data = PeerData::new(comms, addr);
         self.map.insert(id, data);
     }
@@ -135,15 +201,46 @@ impl PeerMap {
         })
     }
 
+    /// Among the peers with an established connection, picks one whose
+    /// response time has not been measured yet, so that it gets measured,
+    /// or else the fastest one. Ties go to the most recently added peer.
     pub fn next_peer_for_block_fetch(&mut self) -> Option<(Id, &mut PeerComms)> {
-        let mut iter = self.map.iter_mut();
-        while let Some((&id, data)) = iter.next_back() {
-            match data.update_comm_status() {
-                CommStatus::Established(comms) => return Some((id, comms)),
-                CommStatus::Connecting(_) => {}
-            }
-        }
-        None
+        let (id, _) = self
+            .map
+            .iter_mut()
+            .rev()
+            .filter_map(|(&id, data)| {
+                if data.is_established() {
+                    Some((id, data.stats.response_time()))
+                } else {
+                    None
+                }
+            })
+            .min_by_key(|&(_, response_time)| response_time)?;
+        self.peer_comms(&id).map(|comms| (id, comms))
+    }
+
+    /// Up to `max` peers with an established client connection, in the
+    /// order of preference of `next_peer_for_block_fetch`.
+    pub fn peers_for_block_fetch(&mut self, max: usize) -> Vec<Id> {
+        let mut peers = self
+            .map
+            .iter_mut()
+            .rev()
+            .filter_map(|(&id, data)| {
+                let is_client = match data.update_comm_status() {
+                    CommStatus::Established(comms) => comms.block_solicitations.is_client(),
+                    CommStatus::Connecting(_) => false,
+                };
+                if is_client {
+                    Some((id, data.stats.response_time()))
+                } else {
+                    None
+                }
+            })
+            .collect::<Vec<_>>();
+        peers.sort_by_key(|&(_, response_time)| response_time);
+        peers.into_iter().take(max).map(|(id, _)| id).collect()
     }
 
     pub fn infos(&self) -> Vec<PeerInfo> {
@@ -157,21 +254,116 @@ impl PeerMap {
             .collect()
     }
 
+    pub fn debug_info(&mut self, id: &Id, dial_backoff: Option<Duration>) -> Option<PeerDebugInfo> {
+        let reputation = self.reputation.score(id);
+        let data = self.map.get_mut(id)?;
+        Some(data.debug_info(*id, reputation, dial_backoff))
+    }
+
+    pub fn debug_infos<F>(&mut self, dial_backoff: F) -> Vec<PeerDebugInfo>
+    where
+        F: Fn(&Id) -> Option<Duration>,
+    {
+        let reputation = &self.reputation;
+        self.map
+            .iter_mut()
+            .map(|(&id, data)| data.debug_info(id, reputation.score(&id), dial_backoff(&id)))
+            .collect()
+    }
+
+    /// evict the client connections of peers picked by the eviction
+    /// policy. The synchronization peers are evicted after all the others,
+    /// and the preferred peers are never evicted.
     pub fn evict_clients(&mut self, num: usize) {
-        for entry in self
-            .map
-            .entries()
-            .filter(|entry| entry.get().comms.has_client_subscriptions())
-            .take(num)
-        {
-            entry.remove();
+        let (sync, other) = self.evictable_peers(|data| data.comms.has_client_subscriptions());
+        let mut victims = self.eviction_order(other);
+        victims.extend(self.eviction_order(sync));
+        for id in victims.into_iter().take(num) {
+            self.map.remove(&id);
+        }
+    }
+
+    // Evicts a peer to admit the new one, if the map is full or if the
+    // new peer is not a synchronization peer and would take one of the
+    // slots reserved for them.
+    fn make_room_for(&mut self, id: &Id) {
+        let (sync, other) = self.evictable_peers(|_| true);
+        let over_budget = !self.trusted.contains(id)
+            && other.len() >= self.capacity.saturating_sub(self.reserved_sync);
+        let candidates = if over_budget && !other.is_empty() {
+            other
+        } else if self.map.len() >= self.capacity {
+            if other.is_empty() {
+                sync
+            } else {
+                other
+            }
+        } else {
+            return;
+        };
+        if let Some(id) = self.eviction_order(candidates).into_iter().next() {
+            self.map.remove(&id);
         }
     }
 
-    fn evict_if_full(&mut self) {
-        if self.map.len() >= self.capacity {
-            self.map.pop_front();
+    // The peers that are not preferred and match the filter, split into
+    // the synchronization peers and the others.
+    fn evictable_peers<F>(&self, filter: F) -> (Vec<Id>, Vec<Id>)
+    where
+        F: Fn(&PeerData) -> bool,
+    {
+        let mut sync = Vec::new();
+        let mut other = Vec::new();
+        for (id, data) in self.map.iter() {
+            if self.preferred.contains(id) || !filter(data) {
+                continue;
+            }
+            if self.is_sync_peer(id, data) {
+                sync.push(*id);
+            } else {
+                other.push(*id);
+            }
         }
+        (sync, other)
+    }
+
+    // The trusted peers and the peers that have announced blocks are
+    // the ones the node bootstraps and catches up from.
+    fn is_sync_peer(&self, id: &Id, data: &PeerData) -> bool {
+        self.trusted.contains(id) || data.stats.last_block_received().is_some()
+    }
+
+    // Orders the peers from the first to evict to the last,
+    // according to the eviction policy.
+    fn eviction_order(&self, mut ids: Vec<Id>) -> Vec<Id> {
+        match self.eviction {
+            EvictionPolicy::LeastUseful => self.worst_peers(ids),
+            EvictionPolicy::Oldest => {
+                ids.sort_by_key(|id| {
+                    self.map
+                        .get(id)
+                        .map(|data| data.stats.connection_established())
+                });
+                ids
+            }
+            EvictionPolicy::Random => {
+                self.rng.with_rng(|rng| ids.shuffle(rng));
+                ids
+            }
+        }
+    }
+
+    // Sorts the peers from the worst reputation to the best. The sort is
+    // stable, so peers with equal scores stay in least recently used order.
+    fn worst_peers(&self, ids: Vec<Id>) -> Vec<Id> {
+        let mut scored = self
+            .reputation
+            .scores(&ids)
+            .into_iter()
+            .zip(ids)
+            .collect::<Vec<_>>();
+        scored.sort_by(|(a, _), (b, _)| a.partial_cmp(b).unwrap_or(Ordering::Equal));
+        scored.into_iter().map(|(_, id)| id).collect()
     }
 }
 
@@ -184,7 +376,170 @@ impl<'a> Entry<'a> {
         self.inner.get_mut().update_comm_status()
     }
 
+    pub fn stats(&mut self) -> &mut PeerStats {
+        &mut self.inner.get_mut().stats
+    }
+
     pub fn remove(self) {
         self.inner.remove();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockcfg::{ConfigParams, Fragment};
+    use crate::network::p2p::PeerEvent;
+    use poldercast::NodeProfileBuilder;
+    use std::time::SystemTime;
+
+    fn new_id() -> Id {
+        (*NodeProfileBuilder::new().build().id()).into()
+    }
+
+    fn peer_map(
+        capacity: usize,
+        reserved_sync: usize,
+        eviction: EvictionPolicy,
+        preferred: &[Id],
+        trusted: &[Id],
+    ) -> (PeerMap, Reputation) {
+        let reputation = Reputation::new();
+        let map = PeerMap::new(
+            capacity,
+            preferred.iter().cloned().collect(),
+            trusted.iter().cloned().collect(),
+            reserved_sync,
+            eviction,
+            reputation.clone(),
+            RngProvider::seeded(0),
+        );
+        (map, reputation)
+    }
+
+    fn add(map: &mut PeerMap, id: Id) {
+        map.insert_peer(id, PeerComms::new(), "127.0.0.1:3000".parse().unwrap());
+    }
+
+    // the peer has announced a block, making it a synchronization peer
+    fn add_sync(map: &mut PeerMap, id: Id) {
+        add(map, id);
+        map.peer_stats(&id)
+            .unwrap()
+            .update_last_block_received(SystemTime::now());
+    }
+
+    // the node has subscribed to the peer as a client
+    fn add_client(map: &mut PeerMap, id: Id) {
+        add(map, id);
+        map.peer_comms(&id)
+            .unwrap()
+            .set_pending_fragment(Fragment::Initial(ConfigParams::new()));
+    }
+
+    fn ids(map: &PeerMap) -> Vec<Id> {
+        map.infos().into_iter().map(|info| info.id).collect()
+    }
+
+    #[test]
+    fn peer_with_worst_reputation_is_evicted() {
+        let (mut map, reputation) = peer_map(3, 0, EvictionPolicy::LeastUseful, &[], &[]);
+        let (a, b, c, d) = (new_id(), new_id(), new_id(), new_id());
+        for id in &[a, b, c] {
+            add(&mut map, *id);
+        }
+        reputation.record(b, PeerEvent::InvalidData);
+        add(&mut map, d);
+        assert_eq!(ids(&map), vec![a, c, d]);
+    }
+
+    #[test]
+    fn least_recently_used_is_evicted_among_equal_scores() {
+        let (mut map, _) = peer_map(3, 0, EvictionPolicy::LeastUseful, &[], &[]);
+        let (a, b, c, d) = (new_id(), new_id(), new_id(), new_id());
+        for id in &[a, b, c] {
+            add(&mut map, *id);
+        }
+        map.refresh_peer(&a);
+        add(&mut map, d);
+        assert_eq!(ids(&map), vec![c, a, d]);
+    }
+
+    #[test]
+    fn oldest_connection_is_evicted() {
+        let (mut map, _) = peer_map(3, 0, EvictionPolicy::Oldest, &[], &[]);
+        let (a, b, c, d) = (new_id(), new_id(), new_id(), new_id());
+        let now = SystemTime::now();
+        for (id, age) in &[(a, 2), (b, 3), (c, 1)] {
+            add(&mut map, *id);
+            map.peer_stats(id).unwrap().created = now - Duration::from_secs(*age);
+        }
+        add(&mut map, d);
+        assert_eq!(ids(&map), vec![a, c, d]);
+    }
+
+    #[test]
+    fn sync_peers_are_evicted_last() {
+        let (mut map, reputation) = peer_map(3, 0, EvictionPolicy::LeastUseful, &[], &[]);
+        let (a, b, c, d) = (new_id(), new_id(), new_id(), new_id());
+        add_sync(&mut map, a);
+        add(&mut map, b);
+        add(&mut map, c);
+        reputation.record(a, PeerEvent::InvalidData);
+        add(&mut map, d);
+        assert_eq!(ids(&map), vec![a, c, d]);
+    }
+
+    #[test]
+    fn sync_peer_is_evicted_when_there_are_no_others() {
+        let (mut map, _) = peer_map(2, 0, EvictionPolicy::LeastUseful, &[], &[]);
+        let (a, b, c) = (new_id(), new_id(), new_id());
+        add_sync(&mut map, a);
+        add_sync(&mut map, b);
+        add(&mut map, c);
+        assert_eq!(ids(&map), vec![b, c]);
+    }
+
+    #[test]
+    fn preferred_peers_are_never_evicted() {
+        let a = new_id();
+        let (mut map, reputation) = peer_map(2, 0, EvictionPolicy::LeastUseful, &[a], &[]);
+        let (b, c) = (new_id(), new_id());
+        add(&mut map, a);
+        reputation.record(a, PeerEvent::InvalidData);
+        add(&mut map, b);
+        add(&mut map, c);
+        assert_eq!(ids(&map), vec![a, c]);
+    }
+
+    #[test]
+    fn other_peers_leave_the_reserved_slots_to_sync_peers() {
+        let trusted = new_id();
+        let (mut map, _) = peer_map(3, 1, EvictionPolicy::LeastUseful, &[], &[trusted]);
+        let (a, b, c) = (new_id(), new_id(), new_id());
+        add(&mut map, a);
+        add(&mut map, b);
+        // the map is not full, but the third slot is reserved
+        add(&mut map, c);
+        assert_eq!(ids(&map), vec![b, c]);
+        add(&mut map, trusted);
+        assert_eq!(ids(&map), vec![b, c, trusted]);
+    }
+
+    #[test]
+    fn client_connections_are_evicted_sync_peers_last() {
+        let (mut map, _) = peer_map(10, 0, EvictionPolicy::LeastUseful, &[], &[]);
+        let (a, b, c) = (new_id(), new_id(), new_id());
+        add_client(&mut map, a);
+        map.peer_stats(&a)
+            .unwrap()
+            .update_last_block_received(SystemTime::now());
+        add_client(&mut map, b);
+        add(&mut map, c);
+        map.evict_clients(1);
+        assert_eq!(ids(&map), vec![a, c]);
+        // the peers without a client connection are left
+        map.evict_clients(5);
+        assert_eq!(ids(&map), vec![c]);
+    }
+}
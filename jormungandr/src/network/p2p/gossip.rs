@@ -1,17 +1,77 @@
 use crate::network::p2p::{limits, Id};
+use crate::utils::bandwidth::{serialized_size, WireSize};
 use bincode;
 use chain_core::property;
 use network_core::gossip::{self, Node as _};
 use serde::{Deserialize, Serialize};
-use std::net::{IpAddr, SocketAddr};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
 
 #[derive(Clone, Debug, Serialize, Deserialize, Hash, PartialEq, Eq)]
-pub struct Gossip(poldercast::NodeProfile);
+pub struct Gossip {
+    profile: poldercast::NodeProfile,
+    auth: Option<GossipAuth>,
+    #[serde(skip)]
+    wire_version: WireVersion,
+}
+
+/// The wire format an item was decoded from.
+///
+/// During an upgrade of the network, the nodes still running the previous
+/// release send their items in the previous format. These are decoded as
+/// well and tagged, so they can be told apart once the transition window
+/// is over.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub enum WireVersion {
+    /// gossip entries made of the node profile alone
+    Legacy,
+    Current,
+}
+
+impl Default for WireVersion {
+    fn default() -> Self {
+        WireVersion::Current
+    }
+}
+
+/// Signature of a gossip entry by the node it describes.
+///
+/// The public key and the signature are kept in their binary encoding,
+/// they are only decoded when the entry is verified.
+#[derive(Clone, Debug, Serialize, Deserialize, Hash, PartialEq, Eq)]
+pub struct GossipAuth {
+    pub public_key: Vec<u8>,
+    pub signature: Vec<u8>,
+}
 
 #[derive(Clone, Debug, PartialEq, Eq)]
-pub struct Gossips(poldercast::Gossips);
+pub struct Gossips(Vec<Gossip>);
 
 impl Gossip {
+    pub fn new(profile: poldercast::NodeProfile, auth: Option<GossipAuth>) -> Self {
+        Gossip {
+            profile,
+            auth,
+            wire_version: WireVersion::Current,
+        }
+    }
+
+    pub fn profile(&self) -> &poldercast::NodeProfile {
+        &self.profile
+    }
+
+    pub fn auth(&self) -> Option<&GossipAuth> {
+        self.auth.as_ref()
+    }
+
+    pub fn wire_version(&self) -> WireVersion {
+        self.wire_version
+    }
+
+    /// the bytes covered by the signature of the entry
+    pub fn signed_data(&self) -> Vec<u8> {
+        bincode::serialize(&self.profile).expect("node profile serialization cannot fail")
+    }
+
     pub fn has_valid_address(&self) -> bool {
         let addr = match self.address() {
             None => return false,
@@ -47,89 +107,113 @@ impl Gossip {
     }
 
     /// Check if the bind address is a global address
-    /// Note: This method relies on IPV4 checks even for IPV6 addresses. If the IPV6 address
-    /// can not be transformed into a IPV4 one then the private and link_local checks are not performed on it.
     pub fn is_global(&self) -> bool {
+        self.address_scope() == Some(AddressScope::Global)
+    }
+
+    /// the scope of the advertised address, `None` if the entry does not
+    /// advertise a valid address.
+    pub fn address_scope(&self) -> Option<AddressScope> {
         if !self.has_valid_address() {
-            return false;
+            return None;
         }
+        self.address().map(|addr| AddressScope::of(addr.ip()))
+    }
+}
 
-        let addr = match self.address() {
-            None => return false,
-            Some(addr) => addr,
-        };
+/// Where an address advertised in gossip can be reached from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AddressScope {
+    /// routable on the public internet
+    Global,
+    /// only reachable from the host itself
+    Loopback,
+    /// private networks (RFC 1918) and IPv6 unique local addresses
+    Private,
+    /// only reachable on the local link
+    LinkLocal,
+}
 
-        match addr.ip() {
-            IpAddr::V4(ip) => {
-                if ip.is_private() {
-                    return false;
-                }
-                if ip.is_loopback() {
-                    return false;
-                }
-                if ip.is_link_local() {
-                    return false;
-                }
-            }
+impl AddressScope {
+    /// Note: besides the loopback, unique local and link-local ranges,
+    /// IPV6 addresses are checked as the IPV4 address they embed, if any.
+    pub fn of(ip: IpAddr) -> Self {
+        match ip {
+            IpAddr::V4(ip) => Self::of_ipv4(ip),
             IpAddr::V6(ip) => {
                 if ip.is_loopback() {
-                    return false;
+                    return AddressScope::Loopback;
                 }
-                // Check using same methods by trying to cast address to ipv4
                 // FIXME: use Ipv6 tests when Ipv6Addr convenience methods get stabilized:
                 // https://github.com/rust-lang/rust/issues/27709
+                let first_segment = ip.segments()[0];
+                if first_segment & 0xfe00 == 0xfc00 {
+                    return AddressScope::Private;
+                }
+                if first_segment & 0xffc0 == 0xfe80 {
+                    return AddressScope::LinkLocal;
+                }
                 match ip.to_ipv4() {
-                    Some(ipv4) => {
-                        if ipv4.is_private() {
-                            return false;
-                        }
-                        if ipv4.is_link_local() {
-                            return false;
-                        }
-                    }
-                    None => {}
+                    Some(ipv4) => Self::of_ipv4(ipv4),
+                    None => AddressScope::Global,
                 }
             }
         }
+    }
 
-        true
+    fn of_ipv4(ip: Ipv4Addr) -> Self {
+        if ip.is_loopback() {
+            AddressScope::Loopback
+        } else if ip.is_private() {
+            AddressScope::Private
+        } else if ip.is_link_local() {
+            AddressScope::LinkLocal
+        } else {
+            AddressScope::Global
+        }
     }
 }
 
 impl From<Gossip> for poldercast::NodeProfile {
     fn from(gossip: Gossip) -> Self {
-        gossip.0
+        gossip.profile
     }
 }
 
 impl From<poldercast::NodeProfile> for Gossip {
     fn from(profile: poldercast::NodeProfile) -> Self {
-        Gossip(profile)
+        Gossip::new(profile, None)
+    }
+}
+
+impl Gossips {
+    pub fn into_vec(self) -> Vec<Gossip> {
+        self.0
     }
 }
 
 impl From<Gossips> for network_core::gossip::Gossip<Gossip> {
     fn from(gossips: Gossips) -> Self {
-        network_core::gossip::Gossip::from_nodes(gossips.0.into_iter().map(Gossip))
+        network_core::gossip::Gossip::from_nodes(gossips.0)
     }
 }
 
 impl From<poldercast::Gossips> for Gossips {
     fn from(gossips: poldercast::Gossips) -> Gossips {
-        Gossips(gossips)
+        Gossips(gossips.into_iter().map(Gossip::from).collect())
     }
 }
 
 impl From<Gossips> for poldercast::Gossips {
     fn from(gossips: Gossips) -> poldercast::Gossips {
-        gossips.0
+        let v: Vec<_> = gossips.0.into_iter().map(|gossip| gossip.profile).collect();
+        poldercast::Gossips::from(v)
     }
 }
 
 impl From<Vec<Gossip>> for Gossips {
     fn from(gossips: Vec<Gossip>) -> Self {
-        let v: Vec<_> = gossips.into_iter().map(|gossip| gossip.0).collect();
-        Gossips(poldercast::Gossips::from(v))
+        Gossips(gossips)
     }
 }
 
@@ -138,12 +222,12 @@ impl gossip::Node for Gossip {
 
     #[inline]
     fn id(&self) -> Self::Id {
-        (*self.0.id()).into()
+        (*self.profile.id()).into()
     }
 
     #[inline]
     fn address(&self) -> Option<SocketAddr> {
-        if let Some(address) = self.0.address() {
+        if let Some(address) = self.profile.address() {
             address.to_socketaddr()
         } else {
             None
@@ -154,22 +238,52 @@ impl gossip::Node for Gossip {
 impl property::Serialize for Gossip {
     type Error = bincode::Error;
 
-    fn serialize<W: std::io::Write>(&self, writer: W) -> Result<(), Self::Error> {
+    fn serialize<W: std::io::Write>(&self, mut writer: W) -> Result<(), Self::Error> {
         let mut config = bincode::config();
         config.limit(limits::MAX_GOSSIP_SIZE);
 
-        config.serialize_into(writer, &self.0)
+        config.serialize_into(&mut writer, &self.profile)?;
+        config.serialize_into(writer, &self.auth)
+    }
+}
+
+impl WireSize for Gossip {
+    fn wire_size(&self) -> usize {
+        serialized_size(self)
+    }
+}
+
+impl WireSize for Gossips {
+    fn wire_size(&self) -> usize {
+        self.0.iter().map(WireSize::wire_size).sum()
     }
 }
 
 impl property::Deserialize for Gossip {
     type Error = bincode::Error;
 
-    fn deserialize<R: std::io::BufRead>(reader: R) -> Result<Self, Self::Error> {
+    fn deserialize<R: std::io::BufRead>(mut reader: R) -> Result<Self, Self::Error> {
         let mut config = bincode::config();
         config.limit(limits::MAX_GOSSIP_SIZE);
 
-        config.deserialize_from(reader).map(Gossip)
+        let profile = config.deserialize_from(&mut reader)?;
+        // Entries in the legacy format end after the profile.
+        let (auth, wire_version) = match config.deserialize_from(reader) {
+            Ok(auth) => (auth, WireVersion::Current),
+            Err(e) => match *e {
+                bincode::ErrorKind::Io(ref io_err)
+                    if io_err.kind() == std::io::ErrorKind::UnexpectedEof =>
+                {
+                    (None, WireVersion::Legacy)
+                }
+                _ => return Err(e),
+            },
+        };
+        Ok(Gossip {
+            profile,
+            auth,
+            wire_version,
+        })
     }
 }
 
@@ -177,7 +291,7 @@ impl property::Deserialize for Gossip {
 mod tests {
     use super::*;
     use poldercast::{Address, NodeProfile, NodeProfileBuilder};
-    use std::net::{Ipv4Addr, Ipv6Addr};
+    use std::net::Ipv6Addr;
     use std::str::FromStr;
 
     #[test]
@@ -246,4 +360,42 @@ mod tests {
         let node: Gossip = Gossip::from(builder.build());
         assert!(!node.is_global());
     }
+
+    #[test]
+    fn address_scope_ipv6_unique_local_and_link_local() {
+        let unique_local = Ipv6Addr::new(0xfd12, 0x3456, 0x789a, 1, 0, 0, 0, 1);
+        assert_eq!(AddressScope::of(unique_local.into()), AddressScope::Private);
+        let link_local = Ipv6Addr::new(0xfe80, 0, 0, 0, 0x1c2, 0, 0, 1);
+        assert_eq!(AddressScope::of(link_local.into()), AddressScope::LinkLocal);
+        let global = Ipv6Addr::new(0x2001, 0x4860, 0x4860, 0, 0, 0, 0, 0x8888);
+        assert_eq!(AddressScope::of(global.into()), AddressScope::Global);
+    }
+
+    #[test]
+    fn gossip_without_auth_decodes_as_unsigned() {
+        let mut builder: NodeProfileBuilder = NodeProfileBuilder::new();
+        builder.address(Address::new(Ipv4Addr::new(1, 2, 3, 4)).ok().unwrap());
+        let profile = builder.build();
+        let encoded = bincode::serialize(&profile).unwrap();
+        let gossip = <Gossip as property::Deserialize>::deserialize(encoded.as_slice()).unwrap();
+        assert_eq!(gossip.profile(), &profile);
+        assert_eq!(gossip.auth(), None);
+        assert_eq!(gossip.wire_version(), WireVersion::Legacy);
+    }
+
+    #[test]
+    fn gossip_with_auth_roundtrip() {
+        let mut builder: NodeProfileBuilder = NodeProfileBuilder::new();
+        builder.address(Address::new(Ipv4Addr::new(1, 2, 3, 4)).ok().unwrap());
+        let auth = GossipAuth {
+            public_key: vec![1; 32],
+            signature: vec![2; 64],
+        };
+        let gossip = Gossip::new(builder.build(), Some(auth));
+        let mut encoded = Vec::new();
+        property::Serialize::serialize(&gossip, &mut encoded).unwrap();
+        let decoded = <Gossip as property::Deserialize>::deserialize(encoded.as_slice()).unwrap();
+        assert_eq!(decoded, gossip);
+        assert_eq!(decoded.wire_version(), WireVersion::Current);
+    }
 }
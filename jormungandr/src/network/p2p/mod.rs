@@ -1,14 +1,20 @@
+mod auth;
 pub mod comm;
 mod gossip;
 mod id;
 mod node;
 mod policy;
+mod reputation;
+mod seen;
 mod topology;
 
-pub use self::gossip::{Gossip, Gossips};
+pub use self::auth::{AuthError, GossipAuthenticator};
+pub use self::gossip::{AddressScope, Gossip, GossipAuth, Gossips, WireVersion};
 pub use self::id::Id;
 pub use self::node::Node;
-pub use self::policy::{Policy, PolicyConfig};
+pub use self::policy::{Policy, PolicyConfig, Strike, StrikeCounter};
+pub use self::reputation::{PeerEvent, Reputation};
+pub use self::seen::SeenFragments;
 pub use self::topology::P2pTopology;
 
 /**
@@ -1,11 +1,18 @@
+use super::Id;
 use jormungandr_lib::time::Duration;
-use poldercast::{Node, PolicyReport};
+use poldercast::{Node, PolicyReport, StrikeReason};
 use serde::{Deserialize, Serialize};
 use slog::Logger;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
 
 /// default quarantine duration is 30min
 const DEFAULT_QUARANTINE_DURATION: std::time::Duration = std::time::Duration::from_secs(1800);
 
+/// by default, a single strike of any kind quarantines a node
+const DEFAULT_STRIKES_TO_QUARANTINE: u32 = 1;
+
 /// This is the P2P policy. Right now it is very similar to the default policy
 /// defined in `poldercast` crate.
 ///
@@ -20,6 +27,62 @@ pub struct Policy {
 #[serde(deny_unknown_fields, rename_all = "snake_case")]
 pub struct PolicyConfig {
     quarantine_duration: Duration,
+    #[serde(default)]
+    strikes_to_quarantine: StrikesToQuarantine,
+    /// whether the failures to connect to a node count as strikes,
+    /// which quarantines the nodes that are only temporarily offline
+    #[serde(default = "default_count_connection_failures")]
+    count_connection_failures: bool,
+}
+
+/// The number of strikes of each kind that quarantine a node.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "snake_case")]
+pub struct StrikesToQuarantine {
+    cannot_connect: u32,
+    invalid_public_id: u32,
+}
+
+/// The misbehaviours of the nodes counted towards their quarantine.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Strike {
+    /// the node could not be connected to
+    CannotConnect,
+    /// the node has identified itself with another ID than the one
+    /// it is known by
+    InvalidPublicId,
+}
+
+impl Strike {
+    fn reason(self) -> StrikeReason {
+        match self {
+            Strike::CannotConnect => StrikeReason::CannotConnect,
+            Strike::InvalidPublicId => StrikeReason::InvalidPublicId,
+        }
+    }
+}
+
+/// Counts the strikes against the nodes until a node gets as many
+/// strikes of a kind as the policy allows, when the strike is recorded
+/// in the topology and the node gets quarantined. The strikes are
+/// forgotten once no other strike of the kind has been counted against
+/// the node for the quarantine duration.
+#[derive(Clone)]
+pub struct StrikeCounter {
+    thresholds: StrikesToQuarantine,
+    count_connection_failures: bool,
+    expiry: std::time::Duration,
+    counts: Arc<Mutex<Counts>>,
+}
+
+struct Counts {
+    strikes: HashMap<(Id, Strike), Strikes>,
+    next_prune: Instant,
+}
+
+struct Strikes {
+    count: u32,
+    last: Instant,
 }
 
 impl Policy {
@@ -35,7 +98,84 @@ impl Default for PolicyConfig {
     fn default() -> Self {
         Self {
             quarantine_duration: Duration::from(DEFAULT_QUARANTINE_DURATION),
+            strikes_to_quarantine: StrikesToQuarantine::default(),
+            count_connection_failures: default_count_connection_failures(),
+        }
+    }
+}
+
+fn default_count_connection_failures() -> bool {
+    true
+}
+
+impl Default for StrikesToQuarantine {
+    fn default() -> Self {
+        Self {
+            cannot_connect: DEFAULT_STRIKES_TO_QUARANTINE,
+            invalid_public_id: DEFAULT_STRIKES_TO_QUARANTINE,
+        }
+    }
+}
+
+impl StrikeCounter {
+    pub fn new(pc: &PolicyConfig) -> Self {
+        let expiry = pc.quarantine_duration.into();
+        Self {
+            thresholds: pc.strikes_to_quarantine.clone(),
+            count_connection_failures: pc.count_connection_failures,
+            expiry,
+            counts: Arc::new(Mutex::new(Counts {
+                strikes: HashMap::new(),
+                next_prune: Instant::now() + expiry,
+            })),
+        }
+    }
+
+    /// Counts a strike against the node. Returns the reason to record
+    /// in the topology if the node has had enough strikes of the kind
+    /// to be quarantined, which starts the count over.
+    pub fn strike(&self, node: Id, strike: Strike) -> Option<StrikeReason> {
+        self.strike_at(node, strike, Instant::now())
+    }
+
+    fn strike_at(&self, node: Id, strike: Strike, now: Instant) -> Option<StrikeReason> {
+        let threshold = match strike {
+            Strike::CannotConnect if !self.count_connection_failures => return None,
+            Strike::CannotConnect => self.thresholds.cannot_connect,
+            Strike::InvalidPublicId => self.thresholds.invalid_public_id,
+        };
+        let expiry = self.expiry;
+        let mut counts = self.counts.lock().unwrap();
+        if now >= counts.next_prune {
+            counts
+                .strikes
+                .retain(|_, strikes| now.duration_since(strikes.last) < expiry);
+            counts.next_prune = now + expiry;
+        }
+        let strikes = counts.strikes.entry((node, strike)).or_insert(Strikes {
+            count: 0,
+            last: now,
+        });
+        if now.duration_since(strikes.last) >= expiry {
+            strikes.count = 0;
         }
+        strikes.count += 1;
+        strikes.last = now;
+        if strikes.count >= threshold {
+            counts.strikes.remove(&(node, strike));
+            Some(strike.reason())
+        } else {
+            None
+        }
+    }
+
+    /// Forgets the strikes against the node, once it has behaved.
+    pub fn clear(&self, node: Id) {
+        self.counts
+            .lock()
+            .unwrap()
+            .strikes
+            .retain(|(id, _), _| *id != node);
     }
 }
 
@@ -77,3 +217,93 @@ impl poldercast::Policy for Policy {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use poldercast::NodeProfileBuilder;
+
+    fn new_id() -> Id {
+        (*NodeProfileBuilder::new().build().id()).into()
+    }
+
+    fn counter(cannot_connect: u32) -> StrikeCounter {
+        StrikeCounter::new(&PolicyConfig {
+            quarantine_duration: Duration::from(std::time::Duration::from_secs(60)),
+            strikes_to_quarantine: StrikesToQuarantine {
+                cannot_connect,
+                invalid_public_id: 1,
+            },
+            count_connection_failures: true,
+        })
+    }
+
+    fn secs(secs: u64) -> std::time::Duration {
+        std::time::Duration::from_secs(secs)
+    }
+
+    #[test]
+    fn quarantined_at_threshold() {
+        let counter = counter(3);
+        let node = new_id();
+        let start = Instant::now();
+        assert!(counter
+            .strike_at(node, Strike::CannotConnect, start)
+            .is_none());
+        assert!(counter
+            .strike_at(node, Strike::CannotConnect, start)
+            .is_none());
+        assert!(counter
+            .strike_at(node, Strike::InvalidPublicId, start)
+            .is_some());
+        assert!(counter
+            .strike_at(node, Strike::CannotConnect, start)
+            .is_some());
+        // the count starts over
+        assert!(counter
+            .strike_at(node, Strike::CannotConnect, start)
+            .is_none());
+    }
+
+    #[test]
+    fn strikes_expire() {
+        let counter = counter(2);
+        let node = new_id();
+        let start = Instant::now();
+        assert!(counter
+            .strike_at(node, Strike::CannotConnect, start)
+            .is_none());
+        assert!(counter
+            .strike_at(node, Strike::CannotConnect, start + secs(60))
+            .is_none());
+        assert!(counter
+            .strike_at(node, Strike::CannotConnect, start + secs(100))
+            .is_some());
+    }
+
+    #[test]
+    fn expired_strikes_are_pruned() {
+        let counter = counter(2);
+        let start = Instant::now();
+        for _ in 0..100 {
+            counter.strike_at(new_id(), Strike::CannotConnect, start);
+        }
+        assert_eq!(counter.counts.lock().unwrap().strikes.len(), 100);
+        counter.strike_at(new_id(), Strike::CannotConnect, start + secs(60));
+        assert_eq!(counter.counts.lock().unwrap().strikes.len(), 1);
+    }
+
+    #[test]
+    fn cleared_strikes_are_forgotten() {
+        let counter = counter(2);
+        let node = new_id();
+        let start = Instant::now();
+        assert!(counter
+            .strike_at(node, Strike::CannotConnect, start)
+            .is_none());
+        counter.clear(node);
+        assert!(counter
+            .strike_at(node, Strike::CannotConnect, start)
+            .is_none());
+    }
+}
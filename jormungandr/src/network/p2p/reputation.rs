@@ -0,0 +1,170 @@
+//! reputation of the peers
+//!
+//! Besides the strikes registered in the topology, which quarantine a node
+//! at once, the node keeps a score for every peer it deals with. Events
+//! observed on the connections with a peer add to or subtract from its
+//! score according to their weight, and the score decays back to neutral
+//! over time so that old events are eventually forgiven.
+//!
+//! The score is used to prefer the well behaving peers when selecting the
+//! view, to leave out the peers with a bad score altogether, and to choose
+//! which connections to evict first.
+
+use crate::network::p2p::{Id, Node};
+use network_core::gossip::Node as _;
+use std::{
+    cmp::Ordering,
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+/// time for a score to decay to half of its value
+const SCORE_HALF_LIFE: Duration = Duration::from_secs(600);
+
+/// bound on the absolute value of a score, so a long good behavior
+/// does not hide a sudden misbehavior for too long
+const MAX_SCORE: f64 = 100.0;
+
+/// peers with a score at or below this value are left out of the view
+pub const EXCLUSION_SCORE: f64 = -50.0;
+
+/// limit on the number of peers for which a score is kept
+const MAX_TRACKED_PEERS: usize = 10000;
+
+/// scores closer to neutral than this are forgotten when pruning
+const NEGLIGIBLE_SCORE: f64 = 1.0;
+
+/// Events affecting the reputation of a peer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PeerEvent {
+    /// the peer sent a block or a header failing validation
+    InvalidData,
+    /// the peer identified itself with an unexpected node id
+    InvalidPublicId,
+    /// the peer could not be connected to
+    CannotConnect,
+    /// a stream with the peer failed
+    StreamDropped,
+    /// the peer was slow to respond to a request
+    SlowResponse,
+    /// the peer served blocks the node requested
+    BlocksServed,
+}
+
+impl PeerEvent {
+    fn weight(self) -> f64 {
+        match self {
+            PeerEvent::InvalidData => -50.0,
+            PeerEvent::InvalidPublicId => -50.0,
+            PeerEvent::CannotConnect => -20.0,
+            PeerEvent::StreamDropped => -10.0,
+            PeerEvent::SlowResponse => -5.0,
+            PeerEvent::BlocksServed => 5.0,
+        }
+    }
+}
+
+/// Scores of the peers, shared between the clones.
+#[derive(Clone, Default)]
+pub struct Reputation {
+    scores: Arc<Mutex<HashMap<Id, Score>>>,
+}
+
+struct Score {
+    value: f64,
+    updated: Instant,
+}
+
+impl Score {
+    fn current(&self, now: Instant) -> f64 {
+        let elapsed = now.duration_since(self.updated).as_secs_f64();
+        self.value * 0.5f64.powf(elapsed / SCORE_HALF_LIFE.as_secs_f64())
+    }
+}
+
+impl Reputation {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// record an event for the peer and return its updated score
+    pub fn record(&self, id: Id, event: PeerEvent) -> f64 {
+        let now = Instant::now();
+        let mut scores = self.scores.lock().unwrap();
+        if scores.len() >= MAX_TRACKED_PEERS && !scores.contains_key(&id) {
+            scores.retain(|_, score| score.current(now).abs() >= NEGLIGIBLE_SCORE);
+        }
+        let score = scores.entry(id).or_insert(Score {
+            value: 0.0,
+            updated: now,
+        });
+        let value = (score.current(now) + event.weight())
+            .max(-MAX_SCORE)
+            .min(MAX_SCORE);
+        score.value = value;
+        score.updated = now;
+        value
+    }
+
+    /// the current score of the peer, 0 for peers without recorded events
+    pub fn score(&self, id: &Id) -> f64 {
+        let scores = self.scores.lock().unwrap();
+        scores
+            .get(id)
+            .map(|score| score.current(Instant::now()))
+            .unwrap_or(0.0)
+    }
+
+    /// the current scores of the given peers, in the same order
+    pub fn scores<'a>(&self, ids: impl IntoIterator<Item = &'a Id>) -> Vec<f64> {
+        let now = Instant::now();
+        let scores = self.scores.lock().unwrap();
+        ids.into_iter()
+            .map(|id| scores.get(id).map(|s| s.current(now)).unwrap_or(0.0))
+            .collect()
+    }
+
+    /// leave out the nodes with a bad score and order the others from the
+    /// best score to the worst, keeping the order of the selection between
+    /// nodes with equal scores.
+    pub fn rank(&self, nodes: Vec<Node>) -> Vec<Node> {
+        let ids: Vec<Id> = nodes.iter().map(|node| node.id()).collect();
+        let mut ranked: Vec<(f64, Node)> = self
+            .scores(&ids)
+            .into_iter()
+            .zip(nodes)
+            .filter(|(score, _)| *score > EXCLUSION_SCORE)
+            .collect();
+        ranked.sort_by(|(a, _), (b, _)| b.partial_cmp(a).unwrap_or(Ordering::Equal));
+        ranked.into_iter().map(|(_, node)| node).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use poldercast::NodeProfileBuilder;
+
+    fn new_id() -> Id {
+        (*NodeProfileBuilder::new().build().id()).into()
+    }
+
+    #[test]
+    fn unknown_peer_is_neutral() {
+        let reputation = Reputation::new();
+        assert_eq!(reputation.score(&new_id()), 0.0);
+    }
+
+    #[test]
+    fn score_is_bounded() {
+        let reputation = Reputation::new();
+        let id = new_id();
+        for _ in 0..10 {
+            reputation.record(id, PeerEvent::InvalidData);
+        }
+        let score = reputation.score(&id);
+        assert!(score >= -MAX_SCORE);
+        assert!(score <= EXCLUSION_SCORE);
+    }
+}
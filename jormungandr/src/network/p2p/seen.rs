@@ -0,0 +1,126 @@
+//! fragments recently seen on the network
+//!
+//! In a dense topology, a fragment sent by a peer reaches the node again
+//! from the other peers the fragment has been propagated to. To stop these
+//! echoes, the node remembers the peers it received each recent fragment
+//! from, does not send the fragment back to them, and propagates it
+//! only once.
+
+use crate::blockcfg::FragmentId;
+use crate::network::p2p::Id;
+use linked_hash_map::LinkedHashMap;
+use std::sync::{Arc, Mutex};
+
+/// limit on the number of fragments remembered, the least recently seen
+/// are forgotten first
+const MAX_SEEN_FRAGMENTS: usize = 10000;
+
+/// Fragments recently seen, shared between the clones.
+#[derive(Clone, Default)]
+pub struct SeenFragments {
+    fragments: Arc<Mutex<LinkedHashMap<FragmentId, Seen>>>,
+}
+
+#[derive(Default)]
+struct Seen {
+    senders: Vec<Id>,
+    propagated: bool,
+}
+
+impl SeenFragments {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// record the fragment as received from the peer
+    pub fn record_received(&self, fragment_id: FragmentId, sender: Id) {
+        let mut fragments = self.fragments.lock().unwrap();
+        let seen = fragments.entry(fragment_id).or_insert_with(Seen::default);
+        if !seen.senders.contains(&sender) {
+            seen.senders.push(sender);
+        }
+        fragments.get_refresh(&fragment_id);
+        evict_excess(&mut fragments);
+    }
+
+    /// Marks the fragment as propagated, returning the peers it has been
+    /// received from, or `None` if the fragment has already been propagated.
+    pub fn start_propagation(&self, fragment_id: FragmentId) -> Option<Vec<Id>> {
+        let mut fragments = self.fragments.lock().unwrap();
+        let seen = fragments.entry(fragment_id).or_insert_with(Seen::default);
+        if seen.propagated {
+            return None;
+        }
+        seen.propagated = true;
+        let senders = seen.senders.clone();
+        fragments.get_refresh(&fragment_id);
+        evict_excess(&mut fragments);
+        Some(senders)
+    }
+
+    /// Marks the fragment as propagated again, returning the peers it has
+    /// been received from, whether or not it has already been propagated.
+    pub fn restart_propagation(&self, fragment_id: FragmentId) -> Vec<Id> {
+        let mut fragments = self.fragments.lock().unwrap();
+        let seen = fragments.entry(fragment_id).or_insert_with(Seen::default);
+        seen.propagated = true;
+        let senders = seen.senders.clone();
+        fragments.get_refresh(&fragment_id);
+        evict_excess(&mut fragments);
+        senders
+    }
+}
+
+fn evict_excess(fragments: &mut LinkedHashMap<FragmentId, Seen>) {
+    while fragments.len() > MAX_SEEN_FRAGMENTS {
+        fragments.pop_front();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use poldercast::NodeProfileBuilder;
+
+    fn new_id() -> Id {
+        (*NodeProfileBuilder::new().build().id()).into()
+    }
+
+    fn fragment_id(n: u32) -> FragmentId {
+        FragmentId::hash_bytes(&n.to_le_bytes())
+    }
+
+    #[test]
+    fn propagated_once_excluding_senders() {
+        let seen = SeenFragments::new();
+        let sender = new_id();
+        seen.record_received(fragment_id(0), sender);
+        seen.record_received(fragment_id(0), sender);
+        assert_eq!(seen.start_propagation(fragment_id(0)), Some(vec![sender]));
+        assert_eq!(seen.start_propagation(fragment_id(0)), None);
+        assert_eq!(seen.start_propagation(fragment_id(1)), Some(vec![]));
+    }
+
+    #[test]
+    fn propagated_again_excluding_senders() {
+        let seen = SeenFragments::new();
+        let sender = new_id();
+        seen.record_received(fragment_id(0), sender);
+        assert_eq!(seen.start_propagation(fragment_id(0)), Some(vec![sender]));
+        assert_eq!(seen.restart_propagation(fragment_id(0)), vec![sender]);
+        assert_eq!(seen.restart_propagation(fragment_id(0)), vec![sender]);
+        assert_eq!(seen.start_propagation(fragment_id(0)), None);
+        assert_eq!(seen.restart_propagation(fragment_id(1)), vec![]);
+        assert_eq!(seen.start_propagation(fragment_id(1)), None);
+    }
+
+    #[test]
+    fn least_recently_seen_are_forgotten() {
+        let seen = SeenFragments::new();
+        for n in 0..=MAX_SEEN_FRAGMENTS as u32 {
+            seen.start_propagation(fragment_id(n));
+        }
+        assert_eq!(seen.start_propagation(fragment_id(0)), Some(vec![]));
+        assert_eq!(seen.start_propagation(fragment_id(2)), None);
+    }
+}
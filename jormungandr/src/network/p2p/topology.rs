@@ -3,22 +3,33 @@
 
 use crate::{
     log::KEY_SUB_TASK,
-    network::p2p::{Gossips, Id, Node, Policy, PolicyConfig},
+    network::p2p::{
+        AuthError, Gossip, GossipAuthenticator, Gossips, Id, Node, Policy, PolicyConfig,
+        Reputation, Strike, StrikeCounter,
+    },
     settings::start::network::Configuration,
 };
+use chain_crypto::{Ed25519, PublicKey};
 use poldercast::{
     custom_layers,
     poldercast::{Cyclon, Rings, Vicinity},
-    NodeProfile, PolicyReport, StrikeReason, Topology,
+    NodeProfile, PolicyReport, Topology,
 };
 use slog::Logger;
-use tokio::prelude::future::{self, Future};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::prelude::future::{self, Either, Future};
 use tokio::sync::lock::{Lock, LockGuard};
 
 const DEFAULT_MAX_NODES: usize = 10000;
 
+/// maximum number of gossip messages waiting to be applied to the
+/// topology, the messages received past it are dropped
+const MAX_QUEUED_GOSSIPS: usize = 1024;
+
 pub struct View {
-    pub self_node: NodeProfile,
+    /// the node's own gossip entry, signed with its gossip key
+    pub self_node: Gossip,
     pub peers: Vec<Node>,
 }
 
@@ -28,6 +39,11 @@ pub struct P2pTopology {
     max_nodes: usize,
     lock: Lock<Topology>,
     node_id: Id,
+    auth: GossipAuthenticator,
+    reputation: Reputation,
+    strikes: StrikeCounter,
+    /// gossip received from the peers, waiting to be applied in a batch
+    queued_gossips: Arc<Mutex<Vec<(Id, Gossips)>>>,
     logger: Logger,
 }
 
@@ -35,6 +51,8 @@ pub struct P2pTopology {
 struct Builder {
     max_nodes: usize,
     topology: Topology,
+    auth: Option<GossipAuthenticator>,
+    strikes: Option<StrikeCounter>,
     logger: Logger,
 }
 
@@ -44,6 +62,8 @@ impl Builder {
         Builder {
             max_nodes: DEFAULT_MAX_NODES,
             topology: Topology::new(node),
+            auth: None,
+            strikes: None,
             logger,
         }
     }
@@ -54,6 +74,7 @@ impl Builder {
     }
 
     fn set_policy(mut self, policy: PolicyConfig) -> Self {
+        self.strikes = Some(StrikeCounter::new(&policy));
         self.topology.set_policy(Policy::new(
             policy,
             self.logger.new(o!(KEY_SUB_TASK => "policy")),
@@ -80,12 +101,24 @@ impl Builder {
         self
     }
 
+    fn set_gossip_auth(mut self, config: &Configuration) -> Self {
+        self.auth = Some(GossipAuthenticator::new(
+            config.gossip_key.clone(),
+            config.accept_unsigned_gossip,
+        ));
+        self
+    }
+
     fn build(self) -> P2pTopology {
         let node_id = self.topology.profile().id().clone();
         P2pTopology {
             max_nodes: self.max_nodes,
             lock: Lock::new(self.topology),
             node_id: node_id.into(),
+            auth: self.auth.expect("gossip authentication is not set"),
+            reputation: Reputation::new(),
+            strikes: self.strikes.expect("policy is not set"),
+            queued_gossips: Arc::new(Mutex::new(Vec::new())),
             logger: self.logger,
         }
     }
@@ -97,6 +130,7 @@ impl P2pTopology {
             .set_poldercast_modules()
             .set_custom_modules(&config)
             .set_policy(config.policy.clone())
+            .set_gossip_auth(&config)
             .set_max_nodes(DEFAULT_MAX_NODES)
             .build()
     }
@@ -113,24 +147,56 @@ impl P2pTopology {
     }
 
     /// Returns a list of neighbors selected in this turn
-    /// to contact for event dissemination.
+    /// to contact for event dissemination, the peers with the best
+    /// reputation first.
     pub fn view<E>(&self, selection: poldercast::Selection) -> impl Future<Item = View, Error = E> {
+        let auth = self.auth.clone();
+        let reputation = self.reputation.clone();
         self.write().map(move |mut topology| {
             let peers = topology
                 .view(None, selection)
                 .into_iter()
                 .map(Node::new)
                 .collect();
+            let peers = reputation.rank(peers);
             View {
-                self_node: topology.profile().clone(),
+                self_node: auth.sign(topology.profile()),
                 peers,
             }
         })
     }
 
+    /// Returns the node's own gossip entry, as advertised to the peers.
+    pub fn self_gossip<E>(&self) -> impl Future<Item = Gossip, Error = E> {
+        let auth = self.auth.clone();
+        self.read()
+            .map(move |topology| auth.sign(topology.profile()))
+    }
+
+    /// Returns the public key the node's own gossip is signed with.
+    pub fn gossip_public_key(&self) -> PublicKey<Ed25519> {
+        self.auth.public_key()
+    }
+
+    /// Returns the gossip entries to send to the given node, with the
+    /// signatures of the nodes they describe when known.
     pub fn initiate_gossips<E>(&self, with: Id) -> impl Future<Item = Gossips, Error = E> {
-        self.write()
-            .map(move |mut topology| topology.initiate_gossips(with.into()).into())
+        let auth = self.auth.clone();
+        let node_id = self.node_id;
+        self.write().map(move |mut topology| {
+            topology
+                .initiate_gossips(with.into())
+                .into_iter()
+                .map(|profile| auth.attach(profile, node_id))
+                .collect::<Vec<_>>()
+                .into()
+        })
+    }
+
+    /// Checks the signature of a gossip entry received from a peer.
+    /// Only the entries passing this check are to be accepted.
+    pub fn verify_gossip(&self, gossip: &Gossip) -> Result<(), AuthError> {
+        self.auth.verify(gossip)
     }
 
     pub fn accept_gossips<E>(
@@ -145,6 +211,40 @@ impl P2pTopology {
         })
     }
 
+    /// Queues the gossip received from a peer, to be accepted into the
+    /// topology with the next batch applied by `apply_queued_gossips`.
+    pub fn queue_gossips(&self, from: Id, gossips: Gossips) {
+        let mut queued = self.queued_gossips.lock().unwrap();
+        if queued.len() < MAX_QUEUED_GOSSIPS {
+            queued.push((from, gossips));
+        } else {
+            debug!(self.logger, "gossip queue is full, dropping gossip"; "from" => %from);
+        }
+    }
+
+    /// Accepts all the queued gossip into the topology under a single
+    /// lock. Resolves to the number of gossip messages applied and the
+    /// time the update took, or `None` if the queue was empty.
+    pub fn apply_queued_gossips<E>(
+        &self,
+    ) -> impl Future<Item = Option<(usize, Duration)>, Error = E> {
+        let batch = std::mem::replace(&mut *self.queued_gossips.lock().unwrap(), Vec::new());
+        if batch.is_empty() {
+            return Either::A(future::ok(None));
+        }
+        Either::B(self.write().map(move |mut topology| {
+            let started = Instant::now();
+            let count = batch.len();
+            for (from, gossips) in batch {
+                if topology.nodes().node_count().all_count >= DEFAULT_MAX_NODES {
+                    break;
+                }
+                topology.accept_gossips(from.into(), gossips.into());
+            }
+            Some((count, started.elapsed()))
+        }))
+    }
+
     pub fn exchange_gossips<E>(
         &mut self,
         with: Id,
@@ -161,6 +261,10 @@ impl P2pTopology {
         self.node_id
     }
 
+    pub fn reputation(&self) -> &Reputation {
+        &self.reputation
+    }
+
     pub fn node<E>(&self) -> impl Future<Item = NodeProfile, Error = E> {
         self.read().map(|topology| topology.profile().clone())
     }
@@ -209,17 +313,28 @@ impl P2pTopology {
 
     /// register a strike against the given node id
     ///
-    /// the function returns `None` if the node was not even in the
+    /// the strike is only recorded in the topology once the node has had
+    /// as many strikes of the kind as the policy allows. The function
+    /// returns `None` until then, or if the node was not even in the
     /// the topology (not even quarantined).
     pub fn report_node<E>(
         &self,
         node: Id,
-        issue: StrikeReason,
+        issue: Strike,
     ) -> impl Future<Item = Option<PolicyReport>, Error = E> {
-        self.write().map(move |mut topology| {
-            topology.update_node(node.into(), |node| {
-                node.record_mut().strike(issue);
-            })
-        })
+        match self.strikes.strike(node, issue) {
+            None => Either::A(future::ok(None)),
+            Some(reason) => Either::B(self.write().map(move |mut topology| {
+                topology.update_node(node.into(), |node| {
+                    node.record_mut().strike(reason);
+                })
+            })),
+        }
+    }
+
+    /// forget the strikes counted against the node
+    /// that have not quarantined it yet
+    pub fn clear_strikes(&self, node: Id) {
+        self.strikes.clear(node)
     }
 }
@@ -0,0 +1,100 @@
+//! Connections to the preferred peers.
+//!
+//! The node keeps a client connection open to each of the preferred peers
+//! listed in the configuration, independently of the view selected by the
+//! topology. The connection is reestablished whenever it breaks down, with
+//! an exponentially growing delay while the attempts keep failing.
+//! The preferred peers are also exempt from the eviction of client
+//! connections, so topology churn does not drop them.
+
+use super::{connect_to, Channels, GlobalStateR};
+use crate::log;
+use crate::settings::start::network::TrustedPeer;
+use futures::future::{self, Loop};
+use futures::prelude::*;
+use slog::Logger;
+use tokio::timer::Delay;
+
+use std::cmp;
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+
+/// delay before reconnecting after the first failure
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+
+/// the reconnection delay stops doubling past this value
+const MAX_BACKOFF: Duration = Duration::from_secs(120);
+
+/// a connection lasting at least this long resets the reconnection delay
+const STABLE_CONNECTION: Duration = Duration::from_secs(60);
+
+/// spawn the tasks maintaining the connections to the preferred peers
+pub fn run(state: GlobalStateR, channels: Channels) {
+    let logger = state
+        .logger()
+        .new(o!(log::KEY_SUB_TASK => "preferred_peers"));
+    for peer in state.config.preferred_peers.iter() {
+        if peer.id == state.topology.node_id() {
+            warn!(logger, "ignoring this node listed as a preferred peer");
+            continue;
+        }
+        match peer.address.to_socketaddr() {
+            Some(addr) => {
+                let logger = logger.new(o!("node_id" => peer.id.to_string()));
+                state.spawn(maintain(
+                    peer,
+                    addr,
+                    state.clone(),
+                    channels.clone(),
+                    logger,
+                ));
+            }
+            None => {
+                warn!(
+                    logger,
+                    "ignoring preferred peer without an IP address";
+                    "address" => %peer.address,
+                );
+            }
+        }
+    }
+}
+
+fn maintain(
+    peer: &TrustedPeer,
+    addr: SocketAddr,
+    state: GlobalStateR,
+    channels: Channels,
+    logger: Logger,
+) -> impl Future<Item = (), Error = ()> {
+    let node_id = peer.id;
+    future::loop_fn(INITIAL_BACKOFF, move |backoff| {
+        let started = Instant::now();
+        let logger = logger.clone();
+        let err_logger = logger.clone();
+        connect_to(
+            node_id,
+            addr,
+            state.clone(),
+            channels.clone(),
+            Default::default(),
+        )
+        .then(move |_| {
+            let delay = if started.elapsed() >= STABLE_CONNECTION {
+                INITIAL_BACKOFF
+            } else {
+                backoff
+            };
+            info!(
+                logger,
+                "connection to preferred peer is down, reconnecting";
+                "delay" => ?delay,
+            );
+            Delay::new(Instant::now() + delay)
+                .map_err(move |e| {
+                    error!(err_logger, "timer error: {:?}", e);
+                })
+                .map(move |()| Loop::<(), _>::Continue(cmp::min(delay * 2, MAX_BACKOFF)))
+        })
+    })
+}
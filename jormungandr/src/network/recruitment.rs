@@ -0,0 +1,144 @@
+//! Active recruitment of peers.
+//!
+//! The node normally learns about new peers and connects to them as part
+//! of gossiping. When the number of client connections drops below the
+//! configured minimum, waiting for the next gossip rounds may leave the
+//! node poorly connected for a long time, so instead it dials candidates
+//! from the trusted peers and the nodes known to the topology right away.
+//!
+//! Each consecutive check finding the node still below the minimum raises
+//! the urgency: twice as many candidates are dialled as in the previous
+//! round, and after a few rounds an alert is logged.
+
+use super::{connect_and_propagate_to, p2p, Channels, GlobalStateR};
+use crate::log;
+use futures::future::{self, Either};
+use futures::prelude::*;
+use network_core::gossip::{Gossip, Node};
+use slog::Logger;
+use tokio::timer::Interval;
+
+use std::cmp;
+use std::collections::HashSet;
+use std::iter;
+use std::net::SocketAddr;
+
+/// urgency level from which the failure to meet the minimum is alerted
+const ALERT_URGENCY: u32 = 3;
+
+/// the number of dialled candidates stops doubling past this urgency level
+const MAX_URGENCY: u32 = 8;
+
+pub fn run(
+    state: GlobalStateR,
+    channels: Channels,
+    min_connections: usize,
+) -> impl Future<Item = (), Error = ()> {
+    let logger = state
+        .logger()
+        .new(o!(log::KEY_SUB_TASK => "peer_recruitment"));
+    let err_logger = logger.clone();
+    Interval::new_interval(state.config.peer_recruitment_interval)
+        .map_err(move |e| {
+            error!(err_logger, "interval timer error: {:?}", e);
+        })
+        .fold(0, move |urgency, _| {
+            recruit(
+                state.clone(),
+                channels.clone(),
+                min_connections,
+                urgency,
+                logger.clone(),
+            )
+        })
+        .map(|_| ())
+}
+
+// Checks the number of client connections and dials candidates if it is
+// below the minimum. Resolves to the urgency level for the next round.
+fn recruit(
+    state: GlobalStateR,
+    channels: Channels,
+    min_connections: usize,
+    urgency: u32,
+    logger: Logger,
+) -> impl Future<Item = u32, Error = ()> {
+    let client_count = state.client_count();
+    if client_count >= min_connections {
+        if urgency > 0 {
+            info!(
+                logger,
+                "minimum number of peer connections restored";
+                "client_count" => client_count,
+            );
+        }
+        return Either::A(future::ok(0));
+    }
+
+    let urgency = cmp::min(urgency + 1, MAX_URGENCY);
+    let deficit = min_connections - client_count;
+    let dial_count = cmp::min(
+        deficit << (urgency - 1),
+        state.config.max_client_connections - client_count,
+    );
+
+    let topology = state.topology.clone();
+    let future = state
+        .peers
+        .infos()
+        .join(topology.view(poldercast::Selection::Any))
+        .map(move |(infos, view)| {
+            let mut known: HashSet<p2p::Id> = infos.into_iter().map(|info| info.id).collect();
+            known.insert(state.topology.node_id());
+
+            let trusted = state
+                .trusted_peers()
+                .into_iter()
+                .filter_map(|tp| tp.address.to_socketaddr().map(|addr| (tp.id, addr)));
+            let discovered = view
+                .peers
+                .iter()
+                .filter_map(|node| node.address().map(|addr| (node.id(), addr)));
+            let candidates: Vec<(p2p::Id, SocketAddr)> = trusted
+                .chain(discovered)
+                .filter(|(id, _)| known.insert(*id))
+                .take(dial_count)
+                .collect();
+
+            if urgency >= ALERT_URGENCY {
+                error!(
+                    logger,
+                    "unable to maintain the minimum number of peer connections";
+                    "client_count" => client_count,
+                    "min_client_connections" => min_connections,
+                    "urgency" => urgency,
+                );
+            } else {
+                warn!(
+                    logger,
+                    "number of peer connections is below the minimum";
+                    "client_count" => client_count,
+                    "min_client_connections" => min_connections,
+                    "urgency" => urgency,
+                );
+            }
+
+            if candidates.is_empty() {
+                warn!(logger, "no candidate peers to recruit");
+                return urgency;
+            }
+
+            debug!(logger, "recruiting {} peers", candidates.len());
+            for (node_id, addr) in candidates {
+                let self_node = view.self_node.clone();
+                let gossip = Gossip::from_nodes(iter::once(self_node.into()));
+                let options = p2p::comm::ConnectOptions {
+                    pending_gossip: Some(gossip),
+                    ..Default::default()
+                };
+                connect_and_propagate_to(node_id, addr, state.clone(), channels.clone(), options);
+            }
+            urgency
+        });
+    Either::B(future)
+}
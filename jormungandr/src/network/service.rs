@@ -1,5 +1,6 @@
 use super::{
     buffer_sizes,
+    drain::Tracked,
     p2p::comm::{BlockEventSubscription, OutboundSubscription},
     p2p::{Gossip as NodeData, Id},
     subscription::{
@@ -8,7 +9,10 @@ use super::{
     Channels, GlobalStateR,
 };
 use crate::blockcfg::{Block, BlockDate, Fragment, FragmentId, Header, HeaderHash};
-use crate::intercom::{self, BlockMsg, ClientMsg, ReplyStream, RequestFuture, RequestSink};
+use crate::intercom::{
+    self, BlockMsg, ClientMsg, ReplyStream, RequestFuture, RequestSink, RequestStreamHandle,
+};
+use crate::utils::bandwidth::BandwidthLimiter;
 use futures::future::{self, FutureResult};
 use futures::prelude::*;
 use network_core::error as core_error;
@@ -16,10 +20,16 @@ use network_core::gossip::Gossip;
 use network_core::server::{BlockService, FragmentService, GossipService, Node, P2pService};
 use slog::Logger;
 
+use std::net::{Ipv4Addr, SocketAddr};
+
 #[derive(Clone)]
 pub struct NodeService {
     channels: Channels,
     global_state: GlobalStateR,
+    /// limit on the depth of the chain served to the peer
+    pull_depth_limit: Option<u64>,
+    /// the address of the peer, known once the connection is accepted
+    peer_addr: Option<SocketAddr>,
     logger: Logger,
 }
 
@@ -31,6 +41,18 @@ impl NodeService {
                 .logger()
                 .new(o!(crate::log::KEY_SUB_TASK => "server")),
             global_state,
+            pull_depth_limit: None,
+            peer_addr: None,
+        }
+    }
+
+    /// the service serving a connection from the given address,
+    /// with the limits applying to that peer
+    pub fn for_peer(&self, addr: SocketAddr) -> Self {
+        NodeService {
+            pull_depth_limit: self.global_state.pull_depth_limit_for_addr(addr),
+            peer_addr: Some(addr),
+            ..self.clone()
         }
     }
 
@@ -39,6 +61,39 @@ impl NodeService {
     }
 }
 
+impl NodeService {
+    // The server connections are served in a thread pool which does not
+    // provide timers, so the uploaded blocks are relayed through a task
+    // on the node's runtime to be throttled there.
+    fn throttle_upload(
+        &self,
+        handle: RequestStreamHandle<Block, ()>,
+        limiter: &BandwidthLimiter,
+        logger: Logger,
+    ) -> RequestStreamHandle<Block, ()> {
+        let (relay_handle, mut relay_sink) = intercom::stream_request::<_, _, intercom::Error>(
+            buffer_sizes::inbound::BLOCKS,
+            logger,
+        );
+        let (stream, reply) = handle.into_stream_and_reply();
+        let relay_reply = relay_sink.take_reply_future();
+        let forward = relay_sink
+            .send_all(
+                limiter
+                    .throttle(stream)
+                    .map_err(|()| intercom::Error::failed("upload stream failed")),
+            )
+            .then(|_| Ok::<_, ()>(()));
+        let reply = relay_reply.then(move |res| {
+            reply.reply(res);
+            Ok(())
+        });
+        self.global_state
+            .spawn(forward.join(reply).map(|((), ())| ()));
+        relay_handle
+    }
+}
+
 impl NodeService
 where
     Self: P2pService,
@@ -80,10 +135,10 @@ impl BlockService for NodeService {
     type Block = Block;
     type TipFuture = RequestFuture<ClientMsg, Header, core_error::Error>;
     type Header = Header;
-    type PullBlocksStream = ReplyStream<Block, core_error::Error>;
+    type PullBlocksStream = Tracked<ReplyStream<Block, core_error::Error>>;
     type PullBlocksFuture = FutureResult<Self::PullBlocksStream, core_error::Error>;
     type PullBlocksToTipFuture = FutureResult<Self::PullBlocksStream, core_error::Error>;
-    type GetBlocksStream = ReplyStream<Block, core_error::Error>;
+    type GetBlocksStream = Tracked<ReplyStream<Block, core_error::Error>>;
     type GetBlocksFuture = FutureResult<Self::GetBlocksStream, core_error::Error>;
     type PullHeadersStream = ReplyStream<Header, core_error::Error>;
     type PullHeadersFuture = FutureResult<Self::PullHeadersStream, core_error::Error>;
@@ -114,10 +169,11 @@ impl BlockService for NodeService {
         // TODO: make sure that a limit on the number of requests in flight
         // per service connection prevents unlimited spawning of these tasks.
         // https://github.com/input-output-hk/jormungandr/issues/1034
-        self.global_state.spawn(
-            client_box.into_send_task(ClientMsg::PullBlocksToTip(from.into(), handle), logger),
-        );
-        future::ok(stream)
+        self.global_state.spawn(client_box.into_send_task(
+            ClientMsg::PullBlocksToTip(from.into(), self.pull_depth_limit, handle),
+            logger,
+        ));
+        future::ok(self.global_state.block_streams.track(stream))
     }
 
     fn get_blocks(&mut self, ids: &[Self::BlockId]) -> Self::GetBlocksFuture {
@@ -128,9 +184,11 @@ impl BlockService for NodeService {
         // TODO: make sure that a limit on the number of requests in flight
         // per service connection prevents unlimited spawning of these tasks.
         // https://github.com/input-output-hk/jormungandr/issues/1034
-        self.global_state
-            .spawn(client_box.into_send_task(ClientMsg::GetBlocks(ids.into(), handle), logger));
-        future::ok(stream)
+        self.global_state.spawn(client_box.into_send_task(
+            ClientMsg::GetBlocks(ids.into(), self.pull_depth_limit, handle),
+            logger,
+        ));
+        future::ok(self.global_state.block_streams.track(stream))
     }
 
     fn get_headers(&mut self, ids: &[Self::BlockId]) -> Self::GetHeadersFuture {
@@ -203,13 +261,17 @@ impl BlockService for NodeService {
         let logger = self.logger.new(o!("request" => "UploadBlocks"));
         let (handle, sink) =
             intercom::stream_request(buffer_sizes::inbound::BLOCKS, logger.clone());
+        let handle = match &self.global_state.upload_rate_limiter {
+            Some(limiter) => self.throttle_upload(handle, limiter, logger.clone()),
+            None => handle,
+        };
         let block_box = self.channels.block_box.clone();
         // TODO: make sure that a limit on the number of requests in flight
         // per service connection prevents unlimited spawning of these tasks.
         // https://github.com/input-output-hk/jormungandr/issues/1034
         self.global_state.spawn(
             block_box
-                .send(BlockMsg::NetworkBlocks(handle))
+                .send(BlockMsg::NetworkBlocks(handle, None))
                 .map_err(move |e| {
                     error!(
                         logger,
@@ -262,9 +324,15 @@ impl FragmentService for NodeService {
             .subscription_logger(subscriber)
             .new(o!("stream" => "fragments"));
 
+        // the fragments of connections of unknown origin share the rate
+        // limit of the unspecified address
+        let remote_addr = self
+            .peer_addr
+            .map_or(Ipv4Addr::UNSPECIFIED.into(), |addr| addr.ip());
         let sink = FragmentProcessor::new(
             self.channels.transaction_box.clone(),
             subscriber,
+            remote_addr,
             self.global_state.clone(),
             logger.new(o!("direction" => "in")),
         );
@@ -3,14 +3,17 @@ use super::{
     p2p::comm::{
         BlockEventSubscription, FragmentSubscription, GossipSubscription, LockServerComms,
     },
-    p2p::{Gossip as NodeData, Id},
+    p2p::{Gossip as NodeData, Id, WireVersion},
     GlobalStateR,
 };
 use crate::{
     blockcfg::{Fragment, Header},
     intercom::{BlockMsg, TransactionMsg},
     settings::start::network::Configuration,
-    utils::async_msg::{self, MessageBox},
+    utils::{
+        async_msg::{self, MessageBox},
+        bandwidth::WireSize,
+    },
 };
 use jormungandr_lib::interfaces::FragmentOrigin;
 use network_core::error as core_error;
@@ -22,6 +25,7 @@ use futures::prelude::*;
 use slog::Logger;
 
 use std::fmt::Debug;
+use std::net::IpAddr;
 
 #[must_use = "`ServeBlockEvents` needs to be plugged into a service trait implementation"]
 pub struct ServeBlockEvents<In> {
@@ -250,10 +254,9 @@ impl<In, Out> MapResponse for Subscription<In, Out> {
 }
 
 fn filter_gossip_node(node: &NodeData, config: &Configuration) -> bool {
-    if config.allow_private_addresses {
-        node.has_valid_address()
-    } else {
-        node.is_global()
+    match node.address_scope() {
+        Some(scope) => config.address_filter.allows(scope),
+        None => false,
     }
 }
 
@@ -296,12 +299,12 @@ impl BlockAnnouncementProcessor {
         core_error::Error::new(core_error::Code::Internal, err)
     }
 
-    fn refresh_stat(&self) {
+    fn refresh_stat(&self, bytes: usize) {
         let refresh_logger = self.logger.clone();
         self.global_state.spawn(
             self.global_state
                 .peers
-                .refresh_peer_on_block(self.node_id)
+                .refresh_peer_on_block(self.node_id, bytes)
                 .and_then(move |refreshed| {
                     if !refreshed {
                         debug!(
@@ -319,6 +322,7 @@ impl BlockAnnouncementProcessor {
 pub struct FragmentProcessor {
     mbox: MessageBox<TransactionMsg>,
     node_id: Id,
+    remote_addr: IpAddr,
     global_state: GlobalStateR,
     logger: Logger,
     buffered_fragments: Vec<Fragment>,
@@ -328,24 +332,26 @@ impl FragmentProcessor {
     pub fn new(
         mbox: MessageBox<TransactionMsg>,
         node_id: Id,
+        remote_addr: IpAddr,
         global_state: GlobalStateR,
         logger: Logger,
     ) -> Self {
         FragmentProcessor {
             mbox,
             node_id,
+            remote_addr,
             global_state,
             logger,
             buffered_fragments: Vec::new(),
         }
     }
 
-    fn refresh_stat(&self) {
+    fn refresh_stat(&self, count: usize, bytes: usize) {
         let refresh_logger = self.logger.clone();
         self.global_state.spawn(
             self.global_state
                 .peers
-                .refresh_peer_on_fragment(self.node_id)
+                .refresh_peer_on_fragment(self.node_id, count, bytes)
                 .and_then(move |refreshed| {
                     if !refreshed {
                         debug!(
@@ -375,18 +381,42 @@ impl GossipProcessor {
     }
 
     pub fn process_item(&self, gossip: Gossip<NodeData>) {
+        let config = &self.global_state.config;
+        let accepts_legacy = config.accepts_legacy_wire();
         let (nodes, filtered_out): (Vec<_>, Vec<_>) = gossip.into_nodes().partition(|node| {
-            filter_gossip_node(node, &self.global_state.config)
+            (accepts_legacy || node.wire_version() != WireVersion::Legacy)
+                && filter_gossip_node(node, config)
                 || (node.id() == self.node_id && node.address().is_none())
         });
+        let bytes = nodes
+            .iter()
+            .chain(&filtered_out)
+            .map(WireSize::wire_size)
+            .sum();
         if filtered_out.len() > 0 {
             debug!(self.logger, "nodes dropped from gossip: {:?}", filtered_out);
         }
+        let topology = &self.global_state.topology;
+        let nodes = nodes
+            .into_iter()
+            .filter(|node| match topology.verify_gossip(node) {
+                Ok(()) => true,
+                Err(e) => {
+                    info!(
+                        self.logger,
+                        "gossip entry rejected";
+                        "node_id" => %node.id(),
+                        "reason" => %e,
+                    );
+                    false
+                }
+            })
+            .collect::<Vec<_>>();
         let refresh_logger = self.logger.clone();
         self.global_state.spawn(
             self.global_state
                 .peers
-                .refresh_peer_on_gossip(self.node_id)
+                .refresh_peer_on_gossip(self.node_id, bytes)
                 .and_then(move |refreshed| {
                     if !refreshed {
                         debug!(
@@ -397,11 +427,9 @@ impl GossipProcessor {
                     Ok(())
                 }),
         );
-        self.global_state.spawn(
-            self.global_state
-                .topology
-                .accept_gossips(self.node_id, nodes.into()),
-        );
+        self.global_state
+            .topology
+            .queue_gossips(self.node_id, nodes.into());
     }
 }
 
@@ -414,13 +442,14 @@ impl Sink for BlockAnnouncementProcessor {
         if polled_ready.is_not_ready() {
             return Ok(AsyncSink::NotReady(header));
         }
+        let bytes = header.wire_size();
         let polled = self
             .mbox
             .start_send(BlockMsg::AnnouncedBlock(header, self.node_id))
             .map_err(|e| self.mbox_error(e))?;
         match polled {
             AsyncSink::Ready => {
-                self.refresh_stat();
+                self.refresh_stat(bytes);
                 Ok(AsyncSink::Ready)
             }
             AsyncSink::NotReady(BlockMsg::AnnouncedBlock(header, _)) => {
@@ -458,9 +487,26 @@ impl Sink for FragmentProcessor {
     type SinkError = core_error::Error;
 
     fn start_send(&mut self, fragment: Fragment) -> StartSend<Fragment, core_error::Error> {
+        if !self.global_state.config.topics.messages {
+            // The node is not subscribed to the fragments, drop them.
+            return Ok(AsyncSink::Ready);
+        }
         if self.buffered_fragments.len() >= buffer_sizes::inbound::FRAGMENTS {
             return Ok(AsyncSink::NotReady(fragment));
         }
+        if let Some(limiter) = &self.global_state.fragment_rate_limiter {
+            if limiter.admit(self.remote_addr, 1) == 0 {
+                debug!(
+                    self.logger,
+                    "fragment dropped over the rate limit of the peer";
+                    "hash" => %fragment.id(),
+                );
+                return Ok(AsyncSink::Ready);
+            }
+        }
+        self.global_state
+            .seen_fragments
+            .record_received(fragment.id(), self.node_id);
         self.buffered_fragments.push(fragment);
         let async_send = self.try_send_fragments()?;
         Ok(async_send.map(|()| self.buffered_fragments.pop().unwrap()))
@@ -499,6 +545,8 @@ impl Sink for FragmentProcessor {
 impl FragmentProcessor {
     fn try_send_fragments(&mut self) -> Result<AsyncSink<()>, core_error::Error> {
         let fragments = self.buffered_fragments.split_off(0);
+        let count = fragments.len();
+        let bytes = fragments.iter().map(WireSize::wire_size).sum();
         let polled = self
             .mbox
             .start_send(TransactionMsg::SendTransaction(
@@ -515,7 +563,7 @@ impl FragmentProcessor {
             })?;
         match polled {
             AsyncSink::Ready => {
-                self.refresh_stat();
+                self.refresh_stat(count, bytes);
                 Ok(AsyncSink::Ready)
             }
             AsyncSink::NotReady(TransactionMsg::SendTransaction(_, fragments)) => {
@@ -5,24 +5,30 @@ mod server;
 pub mod explorer;
 pub mod v0;
 
+pub(crate) use self::server::load_rustls_config;
 pub use self::server::{Error, Server, ServerStopper};
 
 use actix_web::error::{Error as ActixError, ErrorInternalServerError, ErrorServiceUnavailable};
+use actix_web::http::header::AUTHORIZATION;
 use actix_web::web::ServiceConfig;
+use actix_web::HttpRequest;
 
 use slog::Logger;
+use std::net::{IpAddr, SocketAddr};
 use std::sync::Arc;
 
-use crate::blockchain::{Blockchain, Tip};
+use crate::blockchain::{Blockchain, LoadProgress, Tip};
+use crate::clock_check::ClockSkew;
 use crate::fragment::Logs;
-use crate::leadership::Logs as LeadershipLogs;
+use crate::leadership::{Logs as LeadershipLogs, Stats as LeadershipStats};
 use crate::network::p2p::P2pTopology;
 use crate::secure::enclave::Enclave;
 use crate::settings::start::{Error as ConfigError, Rest};
 use crate::stats_counter::StatsCounter;
 
-use crate::intercom::{NetworkMsg, TransactionMsg};
+use crate::intercom::{ClientMsg, NetworkMsg, TransactionMsg};
 use crate::utils::async_msg::MessageBox;
+use crate::utils::rate_limit::RateLimiter;
 
 use futures03::executor::block_on;
 use jormungandr_lib::interfaces::NodeState;
@@ -33,6 +39,7 @@ pub struct Context {
     full: Arc<RwLock<Option<Arc<FullContext>>>>,
     server_stopper: Arc<RwLock<Option<ServerStopper>>>,
     node_state: Arc<RwLock<NodeState>>,
+    load_progress: Arc<RwLock<Option<LoadProgress>>>,
     logger: Arc<RwLock<Option<Logger>>>,
 }
 
@@ -42,6 +49,7 @@ impl Context {
             full: Default::default(),
             server_stopper: Default::default(),
             node_state: Arc::new(RwLock::new(NodeState::StartingRestServer)),
+            load_progress: Default::default(),
             logger: Default::default(),
         }
     }
@@ -78,6 +86,14 @@ impl Context {
         self.node_state.read().await.clone()
     }
 
+    pub async fn set_load_progress(&self, load_progress: LoadProgress) {
+        *self.load_progress.write().await = Some(load_progress);
+    }
+
+    pub async fn load_progress(&self) -> Option<LoadProgress> {
+        self.load_progress.read().await.clone()
+    }
+
     pub async fn set_logger(&self, logger: Logger) {
         *self.logger.write().await = Some(logger);
     }
@@ -98,12 +114,56 @@ pub struct FullContext {
     pub blockchain_tip: Tip,
     pub network_task: MessageBox<NetworkMsg>,
     pub transaction_task: MessageBox<TransactionMsg>,
+    pub client_task: MessageBox<ClientMsg>,
     pub logs: Logs,
     pub leadership_logs: LeadershipLogs,
+    pub leadership_stats: LeadershipStats,
+    pub clock_skew: ClockSkew,
     pub enclave: Enclave,
     pub p2p: P2pTopology,
     pub explorer: Option<crate::explorer::Explorer>,
     pub diagnostic: crate::diagnostic::Diagnostic,
+    pub chain_snapshot: Option<std::path::PathBuf>,
+    pub read_only: bool,
+    /// limits the rate of the fragments submitted by each client address
+    pub fragment_rate_limiter: Option<RateLimiter<IpAddr>>,
+    /// authorizes the requests managing the node
+    pub admin_token: Option<String>,
+}
+
+impl FullContext {
+    /// whether a fragment submitted by the client at `addr` is within the
+    /// rate limit of the client, if any
+    pub fn admit_fragment(&self, addr: Option<SocketAddr>) -> bool {
+        match (&self.fragment_rate_limiter, addr) {
+            (Some(limiter), Some(addr)) => limiter.admit(addr.ip(), 1) > 0,
+            _ => true,
+        }
+    }
+
+    /// whether the request is allowed to manage the node: it carries the
+    /// admin token as a bearer token or, without an admin token configured,
+    /// it comes from the host of the node
+    pub fn authorize_admin(&self, request: &HttpRequest) -> bool {
+        match &self.admin_token {
+            Some(token) => request
+                .headers()
+                .get(AUTHORIZATION)
+                .and_then(|value| value.to_str().ok())
+                .filter(|value| value.starts_with("Bearer "))
+                .map_or(false, |value| {
+                    constant_time_eq(value["Bearer ".len()..].as_bytes(), token.as_bytes())
+                }),
+            None => request
+                .peer_addr()
+                .map_or(false, |addr| addr.ip().is_loopback()),
+        }
+    }
+}
+
+// compares the tokens in a time independent of where they differ
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
 }
 
 pub fn start_rest_server(
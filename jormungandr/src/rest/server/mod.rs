@@ -90,7 +90,7 @@ impl ServerStopper {
     }
 }
 
-fn load_rustls_config(config: TlsConfig) -> ServerResult<ServerConfig> {
+pub(crate) fn load_rustls_config(config: TlsConfig) -> ServerResult<ServerConfig> {
     let certs = load_certs(&config.cert_file)?;
     let priv_key = load_priv_key(&config.priv_key_file)?;
     let mut config = ServerConfig::new(NoClientAuth::new());
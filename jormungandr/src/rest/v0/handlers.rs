@@ -1,29 +1,38 @@
 use jormungandr_lib::interfaces::{
-    AccountState, Address, EnclaveLeaderId, FragmentOrigin, Rewards as StakePoolRewards,
-    StakePoolStats, TaxTypeSerde,
+    AccountState, Address, EnclaveLeaderId, FragmentOrigin, LeadershipLog, LeadershipLogStatus,
+    Rewards as StakePoolRewards, StakePoolStats, TaxTypeSerde,
 };
-use jormungandr_lib::time::SystemTime;
+use jormungandr_lib::time::{Duration, SystemTime};
 
-use actix_web::error::{ErrorBadRequest, ErrorInternalServerError, ErrorNotFound};
+use actix_web::error::{
+    ErrorBadRequest, ErrorForbidden, ErrorInternalServerError, ErrorNotFound,
+    ErrorServiceUnavailable, ErrorTooManyRequests, ErrorUnauthorized,
+};
 use actix_web::web::{Bytes, BytesMut, Data, Json, Path, Query};
-use actix_web::{Error, HttpResponse, Responder};
+use actix_web::{Error, HttpRequest, HttpResponse, Responder};
 use chain_core::property::{Block, Deserialize, Serialize as _};
 use chain_crypto::{bech32::Bech32, Blake2b256, PublicKey};
 use chain_impl_mockchain::account::{AccountAlg, Identifier};
 use chain_impl_mockchain::fragment::{Fragment, FragmentId};
 use chain_impl_mockchain::key::Hash;
-use chain_impl_mockchain::leadership::{Leader, LeadershipConsensus};
+use chain_impl_mockchain::leadership::LeadershipConsensus;
 use chain_impl_mockchain::stake::StakeDistribution;
 use chain_impl_mockchain::transaction::Transaction;
 use chain_impl_mockchain::value::{Value, ValueError};
 use chain_storage::error::Error as StorageError;
 
 use crate::blockchain::Ref;
-use crate::intercom::{self, NetworkMsg, TransactionMsg};
+use crate::intercom::{self, ClientMsg, NetworkMsg, TransactionMsg};
+use crate::leadership;
+use crate::network::p2p::comm::{
+    PeerDebugInfo, SubscriptionDirection, SubscriptionInfo, SubscriptionStatus, TrafficCounters,
+};
 use crate::secure::NodeSecret;
+use crate::settings::start::config;
 use futures::Stream;
 use futures03::compat::Future01CompatExt;
 use jormungandr_lib::interfaces::NodeState;
+use std::convert::Infallible;
 use std::str::FromStr;
 use std::sync::Arc;
 
@@ -79,12 +88,97 @@ pub async fn get_message_logs(context: Data<Context>) -> Result<impl Responder,
         .map(Json)
 }
 
-pub async fn post_message(context: Data<Context>, message: Bytes) -> Result<impl Responder, Error> {
+/// upper bounds of the age ranges the fragments of the pool are counted in,
+/// the older fragments being counted in a last range
+const FRAGMENT_AGE_RANGES: [std::time::Duration; 5] = [
+    std::time::Duration::from_secs(60),
+    std::time::Duration::from_secs(10 * 60),
+    std::time::Duration::from_secs(30 * 60),
+    std::time::Duration::from_secs(60 * 60),
+    std::time::Duration::from_secs(6 * 60 * 60),
+];
+
+#[derive(Deserialize)]
+pub struct FragmentPoolQuery {
+    #[serde(default)]
+    fragment_ids: bool,
+}
+
+pub async fn get_fragment_pool(
+    context: Data<Context>,
+    query: Query<FragmentPoolQuery>,
+) -> Result<impl Responder, Error> {
+    let full_context = context.try_full().await?;
+    let stats = intercom::unary_future(
+        full_context.transaction_task.clone(),
+        context.logger().await?,
+        |reply_handle| TransactionMsg::GetPoolStats(reply_handle),
+    )
+    .compat()
+    .await
+    .map_err(|e: intercom::Error| ErrorInternalServerError(e))?;
+
+    let now = std::time::SystemTime::now();
+    let ages = stats
+        .fragments
+        .iter()
+        .map(|(_, received_at)| {
+            let age = now.duration_since(*received_at).unwrap_or_default();
+            std::time::Duration::from_secs(age.as_secs())
+        })
+        .collect::<Vec<_>>();
+    let mut counts = vec![0; FRAGMENT_AGE_RANGES.len() + 1];
+    for age in &ages {
+        let range = FRAGMENT_AGE_RANGES
+            .iter()
+            .position(|up_to| age < up_to)
+            .unwrap_or(FRAGMENT_AGE_RANGES.len());
+        counts[range] += 1;
+    }
+    let age_distribution = counts
+        .into_iter()
+        .enumerate()
+        .map(|(range, count)| {
+            json!({
+                "upTo": FRAGMENT_AGE_RANGES.get(range).cloned().map(Duration::from),
+                "count": count,
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let mut pool = json!({
+        "size": stats.fragments.len(),
+        "maxEntries": stats.max_entries,
+        "bytes": stats.bytes,
+        "maxBytes": stats.max_bytes,
+        "oldestAge": ages.first().cloned().map(Duration::from),
+        "ageDistribution": age_distribution,
+    });
+    if query.fragment_ids {
+        pool["fragmentIds"] = json!(stats
+            .fragments
+            .iter()
+            .map(|(fragment_id, _)| fragment_id.to_string())
+            .collect::<Vec<_>>());
+    }
+    Ok(Json(pool))
+}
+
+pub async fn post_message(
+    request: HttpRequest,
+    context: Data<Context>,
+    message: Bytes,
+) -> Result<impl Responder, Error> {
     let fragment = Fragment::deserialize(&*message).map_err(ErrorBadRequest)?;
     let msg = TransactionMsg::SendTransaction(FragmentOrigin::Rest, vec![fragment]);
-    context
-        .try_full()
-        .await?
+    let full_context = context.try_full().await?;
+    if full_context.read_only {
+        return Err(ErrorForbidden("The node is read-only"));
+    }
+    if !full_context.admit_fragment(request.peer_addr()) {
+        return Err(ErrorTooManyRequests("Fragment rate limit exceeded"));
+    }
+    full_context
         .transaction_task
         .clone()
         .try_send(msg)
@@ -92,30 +186,114 @@ pub async fn post_message(context: Data<Context>, message: Bytes) -> Result<impl
     Ok(HttpResponse::Ok().finish())
 }
 
+pub async fn get_chain_branches(context: Data<Context>) -> Result<impl Responder, Error> {
+    let full_context = context.try_full().await?;
+    let branches = intercom::unary_future(
+        full_context.client_task.clone(),
+        context.logger().await?,
+        |reply_handle| ClientMsg::GetBranches(reply_handle),
+    )
+    .compat()
+    .await
+    .map_err(|e: intercom::Error| ErrorInternalServerError(e))?;
+    let branches = branches
+        .into_iter()
+        .map(|branch| {
+            let age = std::time::SystemTime::now()
+                .duration_since(branch.last_updated)
+                .map(|age| std::time::Duration::from_secs(age.as_secs()))
+                .unwrap_or_default();
+            json!({
+                "tip": branch.tip.hash().to_string(),
+                "chainLength": u32::from(branch.tip.chain_length()),
+                "date": branch.tip.block_date().to_string(),
+                "commonAncestor": branch.common_ancestor.map(|hash| hash.to_string()),
+                "lastUpdated": SystemTime::from(branch.last_updated),
+                "age": Duration::from(age),
+            })
+        })
+        .collect::<Vec<_>>();
+    Ok(Json(branches))
+}
+
 pub async fn get_tip(context: Data<Context>) -> Result<impl Responder, Error> {
     chain_tip(&context).await.map(|tip| tip.hash().to_string())
 }
 
+#[derive(Serialize)]
+struct TipEventDto {
+    hash: String,
+    parent: String,
+    #[serde(rename = "chainLength")]
+    chain_length: u32,
+    date: String,
+}
+
+/// stream of the tips the node moves to, as server-sent events. The stream
+/// ends if the client does not keep up with the updates.
+pub async fn get_tip_events(context: Data<Context>) -> Result<impl Responder, Error> {
+    use futures03::compat::Stream01CompatExt;
+    use futures03::{future, StreamExt};
+
+    let headers = context
+        .try_full()
+        .await?
+        .blockchain_tip
+        .try_subscribe()
+        .ok_or_else(|| ErrorServiceUnavailable("Too many subscriptions to the tip events"))?;
+    let events = headers
+        .compat()
+        .filter_map(|header| future::ready(header.ok()))
+        .map(|header| {
+            let event = TipEventDto {
+                hash: header.hash().to_string(),
+                parent: header.block_parent_hash().to_string(),
+                chain_length: header.chain_length().into(),
+                date: header.block_date().to_string(),
+            };
+            let data = serde_json::to_string(&event).map_err(ErrorInternalServerError)?;
+            Ok::<_, Error>(Bytes::from(format!("data: {}\n\n", data)))
+        });
+    Ok(HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .streaming(Box::pin(events)))
+}
+
 #[derive(Serialize)]
 struct NodeStatsDto {
     version: &'static str,
     state: NodeState,
+    #[serde(rename = "loadProgress", skip_serializing_if = "Option::is_none")]
+    load_progress: Option<serde_json::Value>,
     #[serde(flatten)]
     stats: Option<serde_json::Value>,
 }
 
 pub async fn get_stats_counter(context: Data<Context>) -> Result<impl Responder, Error> {
-    let stats = match context.try_full().await {
-        Ok(full_context) => Some(create_stats(&*full_context).await?),
-        Err(_) => None,
+    let (stats, load_progress) = match context.try_full().await {
+        Ok(full_context) => (Some(create_stats(&*full_context).await?), None),
+        Err(_) => (None, create_load_progress(&context).await),
     };
     Ok(Json(NodeStatsDto {
         version: env!("SIMPLE_VERSION"),
         state: context.node_state().await,
+        load_progress,
         stats,
     }))
 }
 
+// The progress of the loading of the blockchain, reported until the
+// node is up and serves the full statistics.
+async fn create_load_progress(context: &Context) -> Option<serde_json::Value> {
+    let status = context.load_progress().await?.status()?;
+    Some(json!({
+        "appliedBlocks": status.applied,
+        "totalBlocks": status.total,
+        "epoch": status.epoch,
+        "etaSecs": status.eta.map(|eta| eta.as_secs()),
+    }))
+}
+
 async fn create_stats(context: &FullContext) -> Result<serde_json::Value, Error> {
     let tip = chain_tip_from_full(context).await?;
     let mut block_tx_count = 0u64;
@@ -159,7 +337,17 @@ async fn create_stats(context: &FullContext) -> Result<serde_json::Value, Error>
     let nodes_count = &context.p2p.nodes_count::<Error>().compat().await?;
     let tip_header = tip.header();
     let stats = &context.stats_counter;
+    let peer_response_time = stats.peer_response_time();
+    let mempool_usage = stats.mempool_usage();
+    let topology_update_time = stats.topology_update_time();
+    let clock_skew = context.clock_skew.latest();
     let node_id = &context.p2p.node_id().to_string();
+    let block_cache = context
+        .blockchain
+        .ref_cache_stats()
+        .compat()
+        .await
+        .unwrap_or_else(|e: Infallible| match e {});
     Ok(json!({
         "txRecvCnt": stats.tx_recv_cnt(),
         "blockRecvCnt": stats.block_recv_cnt(),
@@ -177,7 +365,18 @@ async fn create_stats(context: &FullContext) -> Result<serde_json::Value, Error>
         "peerAvailableCnt": nodes_count.available_count,
         "peerUnreachableCnt": nodes_count.not_reachable_count,
         "peerQuarantinedCnt": nodes_count.quarantined_count,
+        "peerResponseTimeAvg": peer_response_time.map(|time| time.average.round() as u64),
+        "peerResponseTimeP90": peer_response_time.map(|time| time.p90.round() as u64),
+        "mempoolUsageAvg": mempool_usage.map(|usage| usage.average.round() as u64),
+        "topologyUpdateTimeAvg": topology_update_time.map(|time| time.average.round() as u64),
+        "topologyUpdateTimeP90": topology_update_time.map(|time| time.p90.round() as u64),
+        "clockSkewMs": clock_skew.map(|measure| measure.skew_ms),
+        "clockSkewExceeded": clock_skew.map(|measure| measure.exceeded),
         "nodeId": node_id,
+        "blockCacheEntries": block_cache.entries,
+        "blockCachePinned": block_cache.pinned,
+        "blockCacheExpiredCnt": block_cache.expired,
+        "blockCacheEvictedCnt": block_cache.evicted,
     }))
 }
 
@@ -229,6 +428,49 @@ pub async fn get_block_next_id(
         .await
 }
 
+pub async fn get_block_event(
+    context: Data<Context>,
+    block_id_hex: Path<String>,
+) -> Result<impl Responder, Error> {
+    context
+        .try_full()
+        .await?
+        .blockchain
+        .event_log()
+        .get(parse_block_hash(&block_id_hex)?)
+        .compat()
+        .await
+        .unwrap_or_else(|e: Infallible| match e {})
+        .ok_or(ErrorNotFound("No event recorded for this block"))
+        .map(Json)
+}
+
+#[derive(Deserialize)]
+pub struct BlockEventsQuery {
+    from: Option<SystemTime>,
+    to: Option<SystemTime>,
+}
+
+pub async fn get_block_events(
+    context: Data<Context>,
+    query: Query<BlockEventsQuery>,
+) -> Result<impl Responder, Error> {
+    let from = query
+        .from
+        .unwrap_or_else(|| std::time::SystemTime::UNIX_EPOCH.into());
+    let to = query.to.unwrap_or_else(SystemTime::now);
+    let events = context
+        .try_full()
+        .await?
+        .blockchain
+        .event_log()
+        .range(from, to)
+        .compat()
+        .await
+        .unwrap_or_else(|e: Infallible| match e {});
+    Ok(Json(events))
+}
+
 const MAX_COUNT: u64 = 100;
 
 #[derive(Deserialize)]
@@ -321,35 +563,105 @@ pub async fn get_leaders(context: Data<Context>) -> Result<impl Responder, Error
 }
 
 pub async fn post_leaders(
+    request: HttpRequest,
     secret: Json<NodeSecret>,
     context: Data<Context>,
 ) -> Result<impl Responder, Error> {
-    let leader = Leader {
-        bft_leader: secret.bft(),
-        genesis_leader: secret.genesis(),
-    };
-    let leader_id = context.try_full().await?.enclave.add_leader(leader).await;
+    let full_context = context.try_full().await?;
+    if !full_context.authorize_admin(&request) {
+        return Err(ErrorUnauthorized("Not authorized to manage the leaders"));
+    }
+    secret.validate().map_err(ErrorBadRequest)?;
+    let leader_id = full_context.enclave.add_leader(secret.leader()).await;
     Ok(Json(leader_id))
 }
 
 pub async fn delete_leaders(
+    request: HttpRequest,
     context: Data<Context>,
     leader_id: Path<EnclaveLeaderId>,
 ) -> Result<impl Responder, Error> {
-    match context
-        .try_full()
-        .await?
-        .enclave
-        .remove_leader(*leader_id)
-        .await
-    {
+    let full_context = context.try_full().await?;
+    if !full_context.authorize_admin(&request) {
+        return Err(ErrorUnauthorized("Not authorized to manage the leaders"));
+    }
+    match full_context.enclave.remove_leader(*leader_id).await {
         true => Ok(HttpResponse::Ok().finish()),
         false => Err(ErrorNotFound("Leader with given ID not found")),
     }
 }
 
-pub async fn get_leaders_logs(context: Data<Context>) -> Result<impl Responder, Error> {
-    Ok(Json(context.try_full().await?.leadership_logs.logs().await))
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum LeadersLogsStatus {
+    Pending,
+    Rejected,
+    Block,
+}
+
+/// number of the leadership logs returned when the request has no `limit`
+const DEFAULT_LEADERS_LOGS_LIMIT: usize = 100;
+
+#[derive(Deserialize)]
+pub struct LeadersLogsQuery {
+    epoch: Option<u32>,
+    status: Option<LeadersLogsStatus>,
+    #[serde(default)]
+    offset: usize,
+    limit: Option<usize>,
+}
+
+impl LeadersLogsQuery {
+    fn matches(&self, log: &LeadershipLog) -> bool {
+        let status = match log.status() {
+            LeadershipLogStatus::Pending => LeadersLogsStatus::Pending,
+            LeadershipLogStatus::Rejected { .. } => LeadersLogsStatus::Rejected,
+            LeadershipLogStatus::Block { .. } => LeadersLogsStatus::Block,
+        };
+        self.epoch.map_or(true, |epoch| {
+            log.scheduled_at_date().as_ref().epoch == epoch
+        }) && self.status.map_or(true, |expected| status == expected)
+    }
+}
+
+pub async fn get_leaders_logs(
+    context: Data<Context>,
+    query: Query<LeadersLogsQuery>,
+) -> Result<impl Responder, Error> {
+    let logs = context
+        .try_full()
+        .await?
+        .leadership_logs
+        .logs(
+            |log| query.matches(log),
+            query.offset,
+            query.limit.unwrap_or(DEFAULT_LEADERS_LOGS_LIMIT),
+        )
+        .await;
+    Ok(Json(logs))
+}
+
+pub async fn get_leaders_stats(context: Data<Context>) -> Result<impl Responder, Error> {
+    Ok(Json(context.try_full().await?.leadership_stats.epochs()))
+}
+
+pub async fn get_leaders_schedule(context: Data<Context>) -> Result<impl Responder, Error> {
+    let full_context = context.try_full().await?;
+    let tip = chain_tip_from_full(&full_context).await?;
+    let enclave = leadership::Enclave::new(full_context.enclave.clone());
+    let schedule = leadership::upcoming_events(&enclave, tip)
+        .await
+        .map_err(ErrorInternalServerError)?
+        .into_iter()
+        .map(|(event, scheduled_at_time)| {
+            json!({
+                "enclave_leader_id": event.id,
+                "scheduled_at_date": event.date.to_string(),
+                "scheduled_at_time": scheduled_at_time,
+            })
+        })
+        .collect::<Vec<_>>();
+    Ok(Json(schedule))
 }
 
 pub async fn get_stake_pools(context: Data<Context>) -> Result<impl Responder, Error> {
@@ -383,12 +695,139 @@ pub async fn get_network_stats(context: Data<Context>) -> Result<impl Responder,
                 "lastBlockReceived": info.stats.last_block_received().map(SystemTime::from),
                 "lastFragmentReceived": info.stats.last_fragment_received().map(SystemTime::from),
                 "lastGossipReceived": info.stats.last_gossip_received().map(SystemTime::from),
+                "responseTime": info.stats.response_time().map(|time| time.as_millis() as u64),
+                "received": traffic_json(info.stats.received()),
+                "sent": traffic_json(info.stats.sent()),
             })
         })
         .collect::<Vec<_>>();
     Ok(Json(network_stats))
 }
 
+fn traffic_json(counters: &TrafficCounters) -> serde_json::Value {
+    json!({
+        "bytes": counters.bytes,
+        "blocks": counters.blocks,
+        "headers": counters.headers,
+        "fragments": counters.fragments,
+        "gossip": counters.gossip,
+    })
+}
+
+pub async fn get_network_peer_debug(
+    context: Data<Context>,
+    node_id: Path<String>,
+) -> Result<impl Responder, Error> {
+    use network_core::error::Code;
+
+    let node_id = node_id.parse().map_err(ErrorBadRequest)?;
+    let full_context = context.try_full().await?;
+    let debug_info = intercom::unary_future(
+        full_context.network_task.clone(),
+        context.logger().await?,
+        |reply_handle| NetworkMsg::PeerDebug(node_id, reply_handle),
+    )
+    .compat()
+    .await
+    .map_err(|e: intercom::Error| match e.code() {
+        Code::NotFound => ErrorNotFound(e),
+        _ => ErrorInternalServerError(e),
+    })?;
+    let subscription = |info: &SubscriptionInfo| {
+        json!({
+            "status": info.status.to_string(),
+            "direction": info.direction.to_string(),
+            "lastError": info.last_error.map(|(kind, _)| kind.to_string()),
+            "lastErrorAt": info.last_error.map(|(_, at)| SystemTime::from(at)),
+        })
+    };
+    let comms = &debug_info.comms;
+    let stats = &debug_info.info.stats;
+    Ok(Json(json!({
+        "nodeId": debug_info.info.id.to_string(),
+        "addr": debug_info.info.addr,
+        "connecting": debug_info.connecting,
+        "reputation": debug_info.reputation,
+        "dialBackoff": debug_info.dial_backoff.map(Duration::from),
+        "establishedAt": SystemTime::from(stats.connection_established()),
+        "lastBlockReceived": stats.last_block_received().map(SystemTime::from),
+        "lastFragmentReceived": stats.last_fragment_received().map(SystemTime::from),
+        "lastGossipReceived": stats.last_gossip_received().map(SystemTime::from),
+        "responseTime": stats.response_time().map(|time| time.as_millis() as u64),
+        "received": traffic_json(stats.received()),
+        "sent": traffic_json(stats.sent()),
+        "subscriptions": {
+            "blockAnnouncements": subscription(&comms.block_announcements),
+            "blockSolicitations": subscription(&comms.block_solicitations),
+            "chainPulls": subscription(&comms.chain_pulls),
+            "fragments": subscription(&comms.fragments),
+            "gossip": subscription(&comms.gossip),
+        },
+        "announcedHeaders": comms
+            .announced_headers
+            .iter()
+            .map(|hash| hash.to_string())
+            .collect::<Vec<_>>(),
+    })))
+}
+
+pub async fn get_network_p2p_peers(context: Data<Context>) -> Result<impl Responder, Error> {
+    let full_context = context.try_full().await?;
+    let debug_infos = intercom::unary_future(
+        full_context.network_task.clone(),
+        context.logger().await?,
+        |reply_handle| NetworkMsg::PeerList(reply_handle),
+    )
+    .compat()
+    .await
+    .map_err(|e: intercom::Error| ErrorInternalServerError(e))?;
+    let peers = debug_infos
+        .iter()
+        .map(peer_connection_json)
+        .collect::<Vec<_>>();
+    Ok(Json(peers))
+}
+
+fn peer_connection_json(debug_info: &PeerDebugInfo) -> serde_json::Value {
+    let comms = &debug_info.comms;
+    let stats = &debug_info.info.stats;
+    let subscriptions = [
+        ("blockAnnouncements", &comms.block_announcements),
+        ("blockSolicitations", &comms.block_solicitations),
+        ("chainPulls", &comms.chain_pulls),
+        ("fragments", &comms.fragments),
+        ("gossip", &comms.gossip),
+    ];
+    // The node is the client of the connection it has initiated,
+    // whose subscriptions all have the client direction.
+    let outbound = debug_info.connecting
+        || subscriptions.iter().any(|(_, info)| match info.direction {
+            SubscriptionDirection::Client => true,
+            SubscriptionDirection::Server => false,
+        });
+    let established = stats.connection_established();
+    let age = std::time::SystemTime::now()
+        .duration_since(established)
+        .unwrap_or_default();
+    json!({
+        "nodeId": debug_info.info.id.to_string(),
+        "addr": debug_info.info.addr,
+        "direction": if outbound { "outbound" } else { "inbound" },
+        "connecting": debug_info.connecting,
+        "establishedAt": SystemTime::from(established),
+        "connectionAge": Duration::from(age),
+        "subscriptions": subscriptions
+            .iter()
+            .filter(|(_, info)| info.status == SubscriptionStatus::Subscribed)
+            .map(|(name, _)| *name)
+            .collect::<Vec<_>>(),
+        "lastActivity": SystemTime::from(stats.last_activity()),
+        "lastBlockReceived": stats.last_block_received().map(SystemTime::from),
+        "lastFragmentReceived": stats.last_fragment_received().map(SystemTime::from),
+        "lastGossipReceived": stats.last_gossip_received().map(SystemTime::from),
+    })
+}
+
 pub async fn get_utxo(
     context: Data<Context>,
     path_params: Path<(String, u8)>,
@@ -474,6 +913,16 @@ pub async fn get_network_p2p_available(context: Data<Context>) -> Result<impl Re
     Ok(Json(json!(list)))
 }
 
+pub async fn get_network_self(context: Data<Context>) -> Result<impl Responder, Error> {
+    let ctx = context.try_full().await?;
+    let gossip = ctx.p2p.self_gossip::<Error>().compat().await?;
+    Ok(Json(json!({
+        "nodeId": ctx.p2p.node_id().to_string(),
+        "profile": gossip.profile(),
+        "gossipPublicKey": ctx.p2p.gossip_public_key().to_bech32_str(),
+    })))
+}
+
 pub async fn get_network_p2p_view(context: Data<Context>) -> Result<impl Responder, Error> {
     let ctx = context.try_full().await?;
     let view = ctx
@@ -510,3 +959,83 @@ pub async fn get_network_p2p_view_topic(
     let node_infos: Vec<poldercast::NodeInfo> = view.peers.into_iter().map(Into::into).collect();
     Ok(Json(json!(node_infos)))
 }
+
+pub async fn post_network_p2p_reset(
+    request: HttpRequest,
+    context: Data<Context>,
+) -> Result<impl Responder, Error> {
+    let full_context = context.try_full().await?;
+    if !full_context.authorize_admin(&request) {
+        return Err(ErrorUnauthorized("Not authorized to reset the topology"));
+    }
+    intercom::unary_future(
+        full_context.network_task.clone(),
+        context.logger().await?,
+        |reply_handle| NetworkMsg::ResetTopology(reply_handle),
+    )
+    .compat()
+    .await
+    .map_err(|e: intercom::Error| ErrorInternalServerError(e))?;
+    Ok(HttpResponse::Ok().finish())
+}
+
+pub async fn put_network_p2p_trusted_peers(
+    request: HttpRequest,
+    peers: Json<Vec<config::TrustedPeer>>,
+    context: Data<Context>,
+) -> Result<impl Responder, Error> {
+    let full_context = context.try_full().await?;
+    if !full_context.authorize_admin(&request) {
+        return Err(ErrorUnauthorized(
+            "Not authorized to replace the trusted peers",
+        ));
+    }
+    let peers = peers.into_inner().into_iter().map(Into::into).collect();
+    intercom::unary_future(
+        full_context.network_task.clone(),
+        context.logger().await?,
+        |reply_handle| NetworkMsg::SetTrustedPeers(peers, reply_handle),
+    )
+    .compat()
+    .await
+    .map_err(|e: intercom::Error| ErrorInternalServerError(e))?;
+    Ok(HttpResponse::Ok().finish())
+}
+
+/// size of the chunks the chain snapshot is streamed in
+const CHAIN_SNAPSHOT_CHUNK_SIZE: usize = 64 * 1024;
+
+pub async fn get_chain_snapshot(context: Data<Context>) -> Result<impl Responder, Error> {
+    use futures03::stream;
+    use std::io::Read as _;
+
+    let path = context
+        .try_full()
+        .await?
+        .chain_snapshot
+        .clone()
+        .ok_or(ErrorNotFound("Chain snapshots are not enabled"))?;
+    // The file may be replaced by the next snapshot while it is streamed,
+    // the open file still being the previous one.
+    let file = actix_threadpool::run(move || std::fs::File::open(path))
+        .await
+        .map_err(|_| ErrorNotFound("No chain snapshot has been written yet"))?;
+    let chunks = stream::unfold(Some(file), |file| async move {
+        let mut file = file?;
+        let read = actix_threadpool::run(move || {
+            let mut chunk = vec![0; CHAIN_SNAPSHOT_CHUNK_SIZE];
+            let len = file.read(&mut chunk)?;
+            chunk.truncate(len);
+            Ok::<_, std::io::Error>((file, chunk))
+        })
+        .await;
+        match read {
+            Ok((_, chunk)) if chunk.is_empty() => None,
+            Ok((file, chunk)) => Some((Ok(Bytes::from(chunk)), Some(file))),
+            Err(e) => Some((Err(ErrorInternalServerError(e)), None)),
+        }
+    });
+    Ok(HttpResponse::Ok()
+        .content_type("application/octet-stream")
+        .streaming(Box::pin(chunks)))
+}
@@ -1,8 +1,9 @@
 mod handlers;
+mod ws;
 
 use actix_web::{
     dev::HttpServiceFactory,
-    web::{delete, get, post, resource, scope},
+    web::{delete, get, post, put, resource, scope},
 };
 
 pub fn service(root_path: &str) -> impl HttpServiceFactory {
@@ -16,18 +17,37 @@ pub fn service(root_path: &str) -> impl HttpServiceFactory {
             "/block/{block_id}/next_id",
             get().to(handlers::get_block_next_id),
         )
+        .route(
+            "/block/{block_id}/event",
+            get().to(handlers::get_block_event),
+        )
+        .route("/block_events", get().to(handlers::get_block_events))
+        .route("/chain/branches", get().to(handlers::get_chain_branches))
+        .route("/chain/snapshot", get().to(handlers::get_chain_snapshot))
         .route("/fragment/logs", get().to(handlers::get_message_logs))
+        .route("/fragment/statuses", get().to(ws::fragment_statuses))
+        .route("/fragments/pool", get().to(handlers::get_fragment_pool))
         .service(
             resource("/leaders")
                 .route(get().to(handlers::get_leaders))
                 .route(post().to(handlers::post_leaders)),
         )
         .route("/leaders/logs", get().to(handlers::get_leaders_logs))
+        .route(
+            "/leaders/logs/schedule",
+            get().to(handlers::get_leaders_schedule),
+        )
+        .route("/leaders/stats", get().to(handlers::get_leaders_stats))
         .route(
             "/leaders/{leader_id}",
             delete().to(handlers::delete_leaders),
         )
         .route("/network/stats", get().to(handlers::get_network_stats))
+        .route("/network/self", get().to(handlers::get_network_self))
+        .route(
+            "/network/peers/{node_id}/debug",
+            get().to(handlers::get_network_peer_debug),
+        )
         .route(
             "/network/p2p/quarantined",
             get().to(handlers::get_network_p2p_quarantined),
@@ -48,6 +68,18 @@ pub fn service(root_path: &str) -> impl HttpServiceFactory {
             "/network/p2p/view/{topic}",
             get().to(handlers::get_network_p2p_view_topic),
         )
+        .route(
+            "/network/p2p/peers",
+            get().to(handlers::get_network_p2p_peers),
+        )
+        .route(
+            "/network/p2p/trusted_peers",
+            put().to(handlers::put_network_p2p_trusted_peers),
+        )
+        .route(
+            "/network/p2p/reset",
+            post().to(handlers::post_network_p2p_reset),
+        )
         .route("/settings", get().to(handlers::get_settings))
         .route("/stake", get().to(handlers::get_stake_distribution))
         .route("/stake_pools", get().to(handlers::get_stake_pools))
@@ -56,9 +88,11 @@ pub fn service(root_path: &str) -> impl HttpServiceFactory {
         .route("/message", post().to(handlers::post_message))
         .route("/node/stats", get().to(handlers::get_stats_counter))
         .route("/tip", get().to(handlers::get_tip))
+        .route("/tip/events", get().to(handlers::get_tip_events))
         .route(
             "/utxo/{fragment_id}/{output_index}",
             get().to(handlers::get_utxo),
         )
         .route("/diagnostic", get().to(handlers::get_diagnostic))
+        .route("/ws", get().to(ws::connect))
 }
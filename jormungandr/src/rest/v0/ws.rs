@@ -0,0 +1,259 @@
+//! WebSocket bridge for light clients.
+//!
+//! A client connected to the endpoint receives a text message with the
+//! record of every block applied by the node, in the JSON format of the
+//! `block_events` endpoint. It can submit fragments by sending them
+//! serialized in binary messages, each answered with a text message
+//! carrying the ID of the fragment or the reason it was rejected.
+//! A read-only node rejects all the fragments, and a node limiting the rate
+//! of the fragments of each client rejects the ones in excess.
+//!
+//! A client connected to the fragment statuses endpoint receives a text
+//! message with the log of each of the fragments it asked for, in the
+//! JSON format of the `fragment/logs` endpoint: first the current log of
+//! the ones known to the node, then a new one every time the status of
+//! one of them changes.
+
+use crate::intercom::TransactionMsg;
+use crate::rest::{Context, FullContext};
+use crate::utils::async_msg::MessageBox;
+
+use actix_codec::{Decoder, Encoder};
+use actix_http::ws::{self, CloseCode, CloseReason, Codec, Frame, Message};
+use actix_web::error::{ErrorBadRequest, ErrorInternalServerError, ErrorServiceUnavailable};
+use actix_web::web::{BytesMut, Data, Payload, Query};
+use actix_web::{Error, HttpRequest, HttpResponse};
+use chain_core::property::{Deserialize, Fragment as _};
+use chain_impl_mockchain::fragment::{Fragment, FragmentId};
+use futures03::channel::mpsc;
+use futures03::compat::{Future01CompatExt, Stream01CompatExt};
+use futures03::future::{self, FutureExt};
+use futures03::prelude::*;
+use jormungandr_lib::interfaces::FragmentOrigin;
+use serde::Serialize;
+use std::collections::HashSet;
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::str::FromStr;
+use std::sync::Arc;
+
+/// number of messages waiting to be sent to a client, the records and the
+/// answers to the client wait for the client to take them
+const MESSAGE_BUFFER: usize = 64;
+
+pub async fn connect(
+    request: HttpRequest,
+    payload: Payload,
+    context: Data<Context>,
+) -> Result<HttpResponse, Error> {
+    let mut response = ws::handshake(request.head())?;
+    let full_context = context.try_full().await?;
+    let block_events = full_context
+        .blockchain
+        .event_log()
+        .subscribe()
+        .compat()
+        .await
+        .unwrap_or_else(|e: Infallible| match e {})
+        .ok_or_else(|| ErrorServiceUnavailable("Too many subscribers to the block events"))?;
+    // a read-only node accepts no fragment
+    let transaction_task = if full_context.read_only {
+        None
+    } else {
+        Some(full_context.transaction_task.clone())
+    };
+    let peer_addr = request.peer_addr();
+
+    let (sender, receiver) = mpsc::channel(MESSAGE_BUFFER);
+    let announcements = send_as_text(
+        block_events
+            .compat()
+            .filter_map(|event| future::ready(event.ok())),
+        sender.clone(),
+    );
+    let requests = process_requests(payload, transaction_task, full_context, peer_addr, sender);
+    // Once the client closes the connection, the announcements are
+    // dropped too, ending the response stream.
+    actix_rt::spawn(
+        future::select(announcements.boxed_local(), requests.boxed_local()).map(|_| ()),
+    );
+
+    let mut codec = Codec::new();
+    let messages = receiver.map(move |message| {
+        let mut frame = BytesMut::new();
+        codec.encode(message, &mut frame)?;
+        Ok::<_, ws::ProtocolError>(frame.freeze())
+    });
+    Ok(response.streaming(messages))
+}
+
+#[derive(Deserialize)]
+pub struct FragmentStatusesQuery {
+    /// comma-separated IDs of the fragments to follow
+    fragment_ids: String,
+}
+
+pub async fn fragment_statuses(
+    request: HttpRequest,
+    payload: Payload,
+    query: Query<FragmentStatusesQuery>,
+    context: Data<Context>,
+) -> Result<HttpResponse, Error> {
+    let fragment_ids = query
+        .fragment_ids
+        .split(',')
+        .filter(|id| !id.is_empty())
+        .map(|id| FragmentId::from_str(id).map(Into::into))
+        .collect::<Result<HashSet<_>, _>>()
+        .map_err(ErrorBadRequest)?;
+    if fragment_ids.is_empty() {
+        return Err(ErrorBadRequest("No fragment IDs to follow"));
+    }
+    let mut response = ws::handshake(request.head())?;
+    let (current, updates) = context
+        .try_full()
+        .await?
+        .logs
+        .subscribe(fragment_ids)
+        .compat()
+        .await
+        .map_err(|()| ErrorInternalServerError("Failed to get logs"))?
+        .ok_or_else(|| ErrorServiceUnavailable("Too many subscribers to the fragment logs"))?;
+
+    let (sender, receiver) = mpsc::channel(MESSAGE_BUFFER);
+    let logs = send_as_text(
+        stream::iter(current.into_iter().map(Ok))
+            .chain(updates.compat())
+            .filter_map(|log| future::ready(log.ok())),
+        sender.clone(),
+    );
+    let requests = process_control_frames(payload, sender);
+    // Once the client closes the connection, the updates are dropped
+    // too, ending the response stream.
+    actix_rt::spawn(future::select(logs.boxed_local(), requests.boxed_local()).map(|_| ()));
+
+    let mut codec = Codec::new();
+    let messages = receiver.map(move |message| {
+        let mut frame = BytesMut::new();
+        codec.encode(message, &mut frame)?;
+        Ok::<_, ws::ProtocolError>(frame.freeze())
+    });
+    Ok(response.streaming(messages))
+}
+
+// sends the values in JSON text messages until the client is gone,
+// closing the connection with the error if one cannot be serialized
+async fn send_as_text<S>(values: S, mut sender: mpsc::Sender<Message>)
+where
+    S: Stream,
+    S::Item: Serialize,
+{
+    let mut values = Box::pin(values);
+    while let Some(value) = values.next().await {
+        let message = match serde_json::to_string(&value) {
+            Ok(text) => Message::Text(text),
+            Err(e) => {
+                let reason = CloseReason {
+                    code: CloseCode::Error,
+                    description: Some(e.to_string()),
+                };
+                let _ = sender.send(Message::Close(Some(reason))).await;
+                return;
+            }
+        };
+        if sender.send(message).await.is_err() {
+            return;
+        }
+    }
+}
+
+// answers the pings of the client until it closes the connection,
+// ignoring its other messages
+async fn process_control_frames(mut payload: Payload, mut sender: mpsc::Sender<Message>) {
+    let mut codec = Codec::new();
+    let mut buf = BytesMut::new();
+    while let Some(Ok(chunk)) = payload.next().await {
+        buf.extend_from_slice(&chunk);
+        loop {
+            let reply = match codec.decode(&mut buf) {
+                Ok(None) => break,
+                Ok(Some(Frame::Ping(bytes))) => Message::Pong(bytes),
+                Ok(Some(Frame::Close(reason))) => {
+                    let _ = sender.send(Message::Close(reason)).await;
+                    return;
+                }
+                Ok(Some(_)) => continue,
+                Err(_) => {
+                    let _ = sender.send(Message::Close(None)).await;
+                    return;
+                }
+            };
+            if sender.send(reply).await.is_err() {
+                return;
+            }
+        }
+    }
+}
+
+async fn process_requests(
+    mut payload: Payload,
+    mut transaction_task: Option<MessageBox<TransactionMsg>>,
+    full_context: Arc<FullContext>,
+    peer_addr: Option<SocketAddr>,
+    mut sender: mpsc::Sender<Message>,
+) {
+    let mut codec = Codec::new();
+    let mut buf = BytesMut::new();
+    while let Some(Ok(chunk)) = payload.next().await {
+        buf.extend_from_slice(&chunk);
+        loop {
+            let reply = match codec.decode(&mut buf) {
+                Ok(None) => break,
+                Ok(Some(Frame::Binary(bytes))) => {
+                    let reply = if full_context.admit_fragment(peer_addr) {
+                        submit_fragment(&bytes, &mut transaction_task)
+                    } else {
+                        json!({ "error": "fragment rate limit exceeded" })
+                    };
+                    Message::Text(reply.to_string())
+                }
+                Ok(Some(Frame::Text(_))) => Message::Text(
+                    json!({ "error": "fragments are expected in binary messages" }).to_string(),
+                ),
+                Ok(Some(Frame::Ping(bytes))) => Message::Pong(bytes),
+                Ok(Some(Frame::Pong(_))) | Ok(Some(Frame::Continuation(_))) => continue,
+                Ok(Some(Frame::Close(reason))) => {
+                    let _ = sender.send(Message::Close(reason)).await;
+                    return;
+                }
+                Err(_) => {
+                    let _ = sender.send(Message::Close(None)).await;
+                    return;
+                }
+            };
+            if sender.send(reply).await.is_err() {
+                return;
+            }
+        }
+    }
+}
+
+fn submit_fragment(
+    bytes: &[u8],
+    transaction_task: &mut Option<MessageBox<TransactionMsg>>,
+) -> serde_json::Value {
+    let transaction_task = match transaction_task {
+        Some(transaction_task) => transaction_task,
+        None => return json!({ "error": "the node is read-only" }),
+    };
+    let fragment = match Fragment::deserialize(bytes) {
+        Ok(fragment) => fragment,
+        Err(e) => return json!({ "error": format!("invalid fragment: {}", e) }),
+    };
+    let fragment_id = fragment.id();
+    let msg = TransactionMsg::SendTransaction(FragmentOrigin::Rest, vec![fragment]);
+    match transaction_task.try_send(msg) {
+        Ok(()) => json!({ "fragmentId": fragment_id.to_string() }),
+        Err(e) => json!({ "error": e.to_string() }),
+    }
+}
@@ -2,15 +2,45 @@ use crate::blockcfg::{
     BlockDate, HeaderBft, HeaderBftBuilder, HeaderGenesisPraos, HeaderGenesisPraosBuilder,
     HeaderSetConsensusSignature, SlotId,
 };
-use chain_impl_mockchain::leadership::{Leader, LeaderOutput, Leadership};
+#[cfg(unix)]
+use crate::secure::signer::RemoteSigner;
+use crate::secure::signer::SignerError;
+#[cfg(unix)]
+use chain_crypto::{Curve25519_2HashDH, SecretKey};
+use chain_impl_mockchain::leadership::{
+    Error as LeadershipError, Leader, LeaderOutput, Leadership,
+};
+#[cfg(unix)]
+use chain_impl_mockchain::{certificate::PoolId, leadership::LeadershipConsensus};
 use jormungandr_lib::interfaces::EnclaveLeaderId as LeaderId;
 use std::collections::BTreeMap;
 use std::sync::Arc;
+use std::time::Instant;
 use tokio02::sync::RwLock;
 
 #[derive(Clone)]
 pub struct Enclave {
-    leaders: Arc<RwLock<BTreeMap<LeaderId, Leader>>>,
+    leaders: Arc<RwLock<BTreeMap<LeaderId, EnclaveLeader>>>,
+}
+
+/// a leader of the enclave, with the keys to evaluate its leader elections
+/// and to sign its blocks
+#[derive(Clone)]
+pub enum EnclaveLeader {
+    /// a leader whose keys are all held by the node
+    Local(Arc<Leader>),
+    /// a genesis leader whose KES key is held by a remote signing service
+    #[cfg(unix)]
+    Remote(Arc<RemoteLeader>),
+}
+
+/// a genesis leader signing its blocks with a remote signer: the node
+/// only holds the keys to evaluate the leader elections
+#[cfg(unix)]
+pub struct RemoteLeader {
+    pub node_id: PoolId,
+    pub vrf_key: SecretKey<Curve25519_2HashDH>,
+    pub signer: RemoteSigner,
 }
 
 pub struct LeaderEvent {
@@ -23,6 +53,30 @@ fn get_maximum_id<A>(leaders: &BTreeMap<LeaderId, A>) -> LeaderId {
     leaders.keys().last().copied().unwrap_or(LeaderId::new())
 }
 
+impl EnclaveLeader {
+    fn is_leader_for_date(
+        &self,
+        leadership: &Leadership,
+        date: BlockDate,
+    ) -> Result<LeaderOutput, LeadershipError> {
+        match self {
+            EnclaveLeader::Local(leader) => leadership.is_leader_for_date(leader, date),
+            #[cfg(unix)]
+            EnclaveLeader::Remote(leader) => match leadership.consensus() {
+                LeadershipConsensus::GenesisPraos(genesis) => Ok(
+                    match genesis.leader(&leader.node_id, &leader.vrf_key, date)? {
+                        Some(witness) => {
+                            LeaderOutput::GenesisPraos(leader.node_id.clone(), witness)
+                        }
+                        None => LeaderOutput::None,
+                    },
+                ),
+                _ => Ok(LeaderOutput::None),
+            },
+        }
+    }
+}
+
 impl Enclave {
     pub fn new() -> Self {
         Enclave {
@@ -30,7 +84,7 @@ impl Enclave {
         }
     }
 
-    pub async fn from_vec(leaders: Vec<Leader>) -> Self {
+    pub async fn from_vec(leaders: Vec<EnclaveLeader>) -> Self {
         let e = Self::new();
         for leader in leaders {
             e.add_leader(leader).await;
@@ -43,7 +97,7 @@ impl Enclave {
         leaders.keys().map(|v| v.clone()).collect()
     }
 
-    pub async fn add_leader(&self, leader: Leader) -> LeaderId {
+    pub async fn add_leader(&self, leader: EnclaveLeader) -> LeaderId {
         let mut leaders = self.leaders.write().await;
         let next_leader_id = get_maximum_id(&leaders).next();
         // This panic case should never happens in practice, as this structure is
@@ -72,9 +126,9 @@ impl Enclave {
             return None;
         }
 
-        leaders.get(leader_id).and_then(|leader| {
+        leaders.get(leader_id).and_then(|entry| {
             let date = leadership.date_at_slot(slot);
-            match leadership.is_leader_for_date(&leader, date) {
+            match entry.is_leader_for_date(leadership, date) {
                 Ok(LeaderOutput::None) => None,
                 Ok(leader_output) => Some(LeaderEvent {
                     id: *leader_id,
@@ -103,8 +157,8 @@ impl Enclave {
         let mut output = Vec::new();
         for slot_idx in slot_start..slot_start + nb_slots {
             let date = leadership.date_at_slot(slot_idx);
-            for (id, leader) in leaders.iter() {
-                match leadership.is_leader_for_date(&leader, date) {
+            for (id, entry) in leaders.iter() {
+                match entry.is_leader_for_date(leadership, date) {
                     Ok(LeaderOutput::None) => (),
                     Ok(leader_output) => output.push(LeaderEvent {
                         id: *id,
@@ -120,35 +174,53 @@ impl Enclave {
         output
     }
 
+    /// sign the header with the KES key of the leader, `None` if the leader
+    /// is not in the enclave. A remote signer gives up at `deadline`.
     pub async fn create_header_genesis_praos(
         &self,
         header_builder: HeaderGenesisPraosBuilder<HeaderSetConsensusSignature>,
         id: LeaderId,
-    ) -> Option<HeaderGenesisPraos> {
-        let leaders = self.leaders.read().await;
-        let leader = leaders.get(&id)?;
-        if let Some(genesis_leader) = &leader.genesis_leader {
-            let data = header_builder.get_authenticated_data();
-            let signature = genesis_leader.sig_key.sign_slice(data);
-            Some(header_builder.set_signature(signature.into()))
-        } else {
-            None
-        }
+        deadline: Instant,
+    ) -> Option<Result<HeaderGenesisPraos, SignerError>> {
+        let leader = self.leader(id).await?;
+        let data = header_builder.get_authenticated_data();
+        let signature = match leader {
+            EnclaveLeader::Local(leader) => match &leader.genesis_leader {
+                Some(genesis) => Ok(genesis.sig_key.sign_slice(data)),
+                None => Err(SignerError::NoKey),
+            },
+            #[cfg(unix)]
+            EnclaveLeader::Remote(leader) => leader.signer.sign(data, deadline).await,
+        };
+        #[cfg(not(unix))]
+        let _ = deadline;
+        Some(signature.map(|signature| header_builder.set_signature(signature.into())))
     }
 
+    /// sign the header with the BFT key of the leader, `None` if the leader
+    /// is not in the enclave
     pub async fn create_header_bft(
         &self,
         header_builder: HeaderBftBuilder<HeaderSetConsensusSignature>,
         id: LeaderId,
-    ) -> Option<HeaderBft> {
+    ) -> Option<Result<HeaderBft, SignerError>> {
+        let leader = self.leader(id).await?;
+        let data = header_builder.get_authenticated_data();
+        let signature = match leader {
+            EnclaveLeader::Local(leader) => match &leader.bft_leader {
+                Some(bft) => Ok(bft.sig_key.sign_slice(data)),
+                None => Err(SignerError::NoKey),
+            },
+            #[cfg(unix)]
+            EnclaveLeader::Remote(_) => Err(SignerError::NoKey),
+        };
+        Some(signature.map(|signature| header_builder.set_signature(signature.into())))
+    }
+
+    // the lock is not held while the leader, possibly with a remote
+    // signer, signs
+    async fn leader(&self, id: LeaderId) -> Option<EnclaveLeader> {
         let leaders = self.leaders.read().await;
-        let leader = leaders.get(&id)?;
-        if let Some(ref leader) = &leader.bft_leader {
-            let data = header_builder.get_authenticated_data();
-            let signature = leader.sig_key.sign_slice(data);
-            Some(header_builder.set_signature(signature.into()))
-        } else {
-            None
-        }
+        leaders.get(&id).cloned()
     }
 }
@@ -1,14 +1,26 @@
+use self::enclave::EnclaveLeader;
+#[cfg(unix)]
+use self::enclave::RemoteLeader;
 use chain_crypto::{Blake2b256, Curve25519_2HashDH, Ed25519, PublicKey, SumEd25519_12};
-use chain_impl_mockchain::leadership::{BftLeader, GenesisLeader};
+use chain_impl_mockchain::leadership::{BftLeader, GenesisLeader, Leader};
 use jormungandr_lib::crypto::{
     hash::Hash,
     key::{Identifier, SigningKey},
+    secret_file,
 };
 use serde::Deserialize;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 use thiserror::Error;
 
 pub mod enclave;
+mod passphrase;
+mod signer;
+
+pub use self::passphrase::{Passphrase, PassphraseError, PassphraseSource};
+#[cfg(unix)]
+pub use self::signer::RemoteSigner;
+pub use self::signer::SignerError;
 
 /// hold the node's bft secret setting
 #[derive(Clone, Deserialize)]
@@ -21,7 +33,9 @@ pub struct Bft {
 #[derive(Clone, Deserialize)]
 pub struct GenesisPraos {
     node_id: Hash,
-    sig_key: SigningKey<SumEd25519_12>,
+    /// not set if the blocks are signed by a remote signer
+    #[serde(default)]
+    sig_key: Option<SigningKey<SumEd25519_12>>,
     vrf_key: SigningKey<Curve25519_2HashDH>,
 }
 
@@ -43,11 +57,23 @@ pub struct StakePoolInfo {
     initial_key: GenesisPraosPublic,
 }
 
+/// the signing service holding the KES key of the genesis leader
+#[derive(Clone, Deserialize)]
+pub struct RemoteSignerConfig {
+    socket_path: PathBuf,
+    key_id: String,
+    /// the public KES key of the leader, the signatures of the service are
+    /// checked against it
+    sig_public_key: Identifier<SumEd25519_12>,
+}
+
 /// Node Secret(s)
 #[derive(Clone, Deserialize)]
 pub struct NodeSecret {
     pub bft: Option<Bft>,
     pub genesis: Option<GenesisPraos>,
+    #[serde(default)]
+    pub remote_signer: Option<RemoteSignerConfig>,
 }
 
 /// Node Secret's Public parts
@@ -62,12 +88,84 @@ pub enum NodeSecretFromFileError {
     Io(#[from] std::io::Error),
     #[error("Invalid Node secret file: {0}")]
     Format(#[from] serde_yaml::Error),
+    #[error("No passphrase for the encrypted node's secrets: {0}")]
+    Passphrase(#[from] PassphraseError),
+    #[error("Cannot decrypt node's secrets: {0}")]
+    Decrypt(#[from] secret_file::Error),
+    #[error("Invalid Node secret: {0}")]
+    Invalid(#[from] InvalidNodeSecret),
+}
+
+#[derive(Debug, Error)]
+pub enum InvalidNodeSecret {
+    #[error("the genesis leader has no `sig_key` nor `remote_signer`")]
+    MissingSigKey,
+    #[error("the genesis leader has both a `sig_key` and a `remote_signer`")]
+    SigKeyWithRemoteSigner,
+    #[error("a `remote_signer` only signs for a genesis leader")]
+    RemoteSignerWithoutGenesis,
+    #[error("a `remote_signer` is only supported on unix")]
+    RemoteSignerNotSupported,
 }
 
 impl NodeSecret {
-    pub fn load_from_file<P: AsRef<Path>>(path: P) -> Result<NodeSecret, NodeSecretFromFileError> {
-        let file = std::fs::File::open(path)?;
-        Ok(serde_yaml::from_reader(file)?)
+    /// load the secrets of the file at `path`, decrypting them with the
+    /// passphrase if the file is encrypted
+    pub fn load_from_file<P: AsRef<Path>>(
+        path: P,
+        passphrase: &mut Passphrase,
+    ) -> Result<NodeSecret, NodeSecretFromFileError> {
+        let data = std::fs::read(path)?;
+        let secret: NodeSecret = if !secret_file::is_encrypted(&data) {
+            serde_yaml::from_slice(&data)?
+        } else {
+            let mut plain = secret_file::decrypt(passphrase.get()?, &data)?;
+            let secret = serde_yaml::from_slice(&plain);
+            wipe(&mut plain);
+            secret?
+        };
+        secret.validate()?;
+        Ok(secret)
+    }
+
+    /// check the genesis leader has exactly one way to sign its blocks
+    pub fn validate(&self) -> Result<(), InvalidNodeSecret> {
+        match (&self.genesis, &self.remote_signer) {
+            (Some(genesis), None) if genesis.sig_key.is_none() => {
+                Err(InvalidNodeSecret::MissingSigKey)
+            }
+            (Some(genesis), Some(_)) if genesis.sig_key.is_some() => {
+                Err(InvalidNodeSecret::SigKeyWithRemoteSigner)
+            }
+            (_, Some(_)) if self.genesis.is_none() || self.bft.is_some() => {
+                Err(InvalidNodeSecret::RemoteSignerWithoutGenesis)
+            }
+            (_, Some(_)) if cfg!(not(unix)) => Err(InvalidNodeSecret::RemoteSignerNotSupported),
+            _ => Ok(()),
+        }
+    }
+
+    /// the leader to add to the enclave: a genesis leader with a remote
+    /// signer only holds the keys of the leader elections
+    pub fn leader(&self) -> EnclaveLeader {
+        #[cfg(unix)]
+        {
+            if let (Some(genesis), Some(config)) = (&self.genesis, &self.remote_signer) {
+                return EnclaveLeader::Remote(Arc::new(RemoteLeader {
+                    node_id: Blake2b256::from(genesis.node_id).into(),
+                    vrf_key: genesis.vrf_key.clone().into_secret_key(),
+                    signer: RemoteSigner::new(
+                        config.socket_path.clone(),
+                        config.key_id.clone(),
+                        config.sig_public_key.clone().into_public_key(),
+                    ),
+                }));
+            }
+        }
+        EnclaveLeader::Local(Arc::new(Leader {
+            bft_leader: self.bft(),
+            genesis_leader: self.genesis(),
+        }))
     }
 
     pub fn bft(&self) -> Option<BftLeader> {
@@ -76,11 +174,22 @@ impl NodeSecret {
         })
     }
 
+    /// the genesis leader signing its own blocks, `None` if its blocks are
+    /// signed by a remote signer
     pub fn genesis(&self) -> Option<GenesisLeader> {
-        self.genesis.clone().map(|genesis| GenesisLeader {
+        let genesis = self.genesis.clone()?;
+        Some(GenesisLeader {
             node_id: Blake2b256::from(genesis.node_id).into(),
-            sig_key: genesis.sig_key.into_secret_key(),
+            sig_key: genesis.sig_key?.into_secret_key(),
             vrf_key: genesis.vrf_key.into_secret_key(),
         })
     }
 }
+
+/// overwrite the secret bytes with zeros, not optimized away as the bytes
+/// are not read afterwards
+fn wipe(bytes: &mut [u8]) {
+    for byte in bytes.iter_mut() {
+        unsafe { std::ptr::write_volatile(byte, 0) };
+    }
+}
@@ -0,0 +1,135 @@
+//! passphrase of the encrypted secret files, read at most once from the
+//! source chosen on the command line and only when an encrypted file is
+//! loaded
+
+use std::io::{self, BufRead, Write};
+use thiserror::Error;
+
+/// Where the passphrase of the encrypted secret files is read from.
+#[derive(Debug, Clone)]
+pub enum PassphraseSource {
+    /// the environment variable of the given name, removed from the
+    /// environment once read
+    Env(String),
+    /// the given file descriptor, read to its end
+    Fd(i32),
+    /// prompted on the terminal
+    Prompt,
+}
+
+#[derive(Debug, Error)]
+pub enum PassphraseError {
+    #[error("The environment variable {0} holding the secret passphrase is not set")]
+    EnvNotSet(String),
+    #[error("Cannot read the secret passphrase: {0}")]
+    Io(#[from] io::Error),
+    #[cfg(unix)]
+    #[error("Cannot prompt for the secret passphrase without a terminal, give it with `--secret-passphrase-env` or `--secret-passphrase-fd`")]
+    NoTerminal,
+    #[cfg(not(unix))]
+    #[error(
+        "Reading the secret passphrase from a file descriptor is not supported on this platform"
+    )]
+    FdNotSupported,
+}
+
+/// The passphrase of the encrypted secret files, wiped from memory when
+/// dropped.
+pub struct Passphrase {
+    source: PassphraseSource,
+    value: Option<Vec<u8>>,
+}
+
+impl Passphrase {
+    pub fn new(source: PassphraseSource) -> Self {
+        Passphrase {
+            source,
+            value: None,
+        }
+    }
+
+    /// the passphrase, read from its source the first time
+    pub fn get(&mut self) -> Result<&[u8], PassphraseError> {
+        if self.value.is_none() {
+            let mut value = match &self.source {
+                PassphraseSource::Env(name) => read_env(name)?,
+                PassphraseSource::Fd(fd) => read_fd(*fd)?,
+                PassphraseSource::Prompt => prompt()?,
+            };
+            trim_newline(&mut value);
+            self.value = Some(value.into_bytes());
+        }
+        Ok(self.value.as_ref().unwrap())
+    }
+}
+
+impl Drop for Passphrase {
+    fn drop(&mut self) {
+        if let Some(value) = &mut self.value {
+            super::wipe(value);
+        }
+    }
+}
+
+fn read_env(name: &str) -> Result<String, PassphraseError> {
+    let value = std::env::var(name).map_err(|_| PassphraseError::EnvNotSet(name.to_owned()))?;
+    // not to pass the passphrase on to the child processes
+    std::env::remove_var(name);
+    Ok(value)
+}
+
+#[cfg(unix)]
+fn read_fd(fd: i32) -> Result<String, PassphraseError> {
+    use std::io::Read as _;
+    use std::os::unix::io::FromRawFd as _;
+
+    // the file descriptor is handed over to the node, closed once read
+    let mut file = unsafe { std::fs::File::from_raw_fd(fd) };
+    let mut value = String::new();
+    file.read_to_string(&mut value)?;
+    Ok(value)
+}
+
+#[cfg(not(unix))]
+fn read_fd(_fd: i32) -> Result<String, PassphraseError> {
+    Err(PassphraseError::FdNotSupported)
+}
+
+#[cfg(unix)]
+fn prompt() -> Result<String, PassphraseError> {
+    use nix::sys::termios::{tcgetattr, tcsetattr, LocalFlags, SetArg};
+
+    const STDIN: i32 = 0;
+    if !nix::unistd::isatty(STDIN).unwrap_or(false) {
+        return Err(PassphraseError::NoTerminal);
+    }
+    let to_io = |e: nix::Error| io::Error::new(io::ErrorKind::Other, e);
+    let termios = tcgetattr(STDIN).map_err(to_io)?;
+    let mut no_echo = termios.clone();
+    no_echo.local_flags.remove(LocalFlags::ECHO);
+    tcsetattr(STDIN, SetArg::TCSANOW, &no_echo).map_err(to_io)?;
+
+    let value = read_prompt();
+    tcsetattr(STDIN, SetArg::TCSANOW, &termios).map_err(to_io)?;
+    eprintln!();
+    value
+}
+
+#[cfg(not(unix))]
+fn prompt() -> Result<String, PassphraseError> {
+    read_prompt()
+}
+
+fn read_prompt() -> Result<String, PassphraseError> {
+    eprint!("Passphrase of the secret files: ");
+    io::stderr().flush()?;
+    let mut value = String::new();
+    io::stdin().lock().read_line(&mut value)?;
+    Ok(value)
+}
+
+fn trim_newline(value: &mut String) {
+    while value.ends_with('\n') || value.ends_with('\r') {
+        value.pop();
+    }
+}
@@ -0,0 +1,145 @@
+//! signing of the block headers of the genesis leaders by an external
+//! signing service holding their KES key
+//!
+//! A remote signer is a service listening on a unix socket. For each block,
+//! the node connects to the socket and sends a JSON request on one line:
+//!
+//! ```json
+//! {"keyId":"pool1","scheme":"sumed25519_12","data":"<hex>"}
+//! ```
+//!
+//! where `data` is the authenticated data of the block header to sign with
+//! the key `keyId` of the signing service. The service answers on one line
+//! with either `{"signature":"<hex>"}` or `{"error":"<reason>"}`. The answer
+//! must come before the end of the slot of the block, and the signature is
+//! checked against the public KES key of the leader before it is used.
+
+use thiserror::Error;
+
+#[derive(Debug, Clone, Error)]
+pub enum SignerError {
+    #[error("no signing key for this consensus")]
+    NoKey,
+    #[error("cannot reach the remote signer: {0}")]
+    Unreachable(String),
+    #[error("the remote signer did not answer before the end of the slot")]
+    Timeout,
+    #[error("the remote signer refused to sign: {0}")]
+    Refused(String),
+    #[error("invalid answer of the remote signer: {0}")]
+    InvalidAnswer(String),
+    #[error("the remote signer signed with another key than the leader's")]
+    InvalidSignature,
+}
+
+#[cfg(unix)]
+pub use self::remote::RemoteSigner;
+
+#[cfg(unix)]
+mod remote {
+    use super::SignerError;
+    use chain_crypto::{PublicKey, Signature, SumEd25519_12, Verification};
+    use std::{path::PathBuf, time::Instant};
+    use tokio02::{
+        io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+        net::UnixStream,
+        time::{timeout_at, Instant as TokioInstant},
+    };
+
+    /// limit on the size of the answer of the signing service, far above
+    /// the size of an answer with a KES signature
+    const MAX_ANSWER_SIZE: u64 = 16 * 1024;
+
+    /// Signs with the KES key of a genesis leader held by a signing service
+    /// listening on a unix socket, see the module documentation for the
+    /// protocol.
+    pub struct RemoteSigner {
+        socket_path: PathBuf,
+        key_id: String,
+        public_key: PublicKey<SumEd25519_12>,
+    }
+
+    #[derive(Serialize)]
+    #[serde(rename_all = "camelCase")]
+    struct Request<'a> {
+        key_id: &'a str,
+        scheme: &'static str,
+        data: String,
+    }
+
+    #[derive(Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    enum Answer {
+        Signature(String),
+        Error(String),
+    }
+
+    impl RemoteSigner {
+        pub fn new(
+            socket_path: PathBuf,
+            key_id: String,
+            public_key: PublicKey<SumEd25519_12>,
+        ) -> Self {
+            RemoteSigner {
+                socket_path,
+                key_id,
+                public_key,
+            }
+        }
+
+        /// sign the authenticated data of a Genesis Praos block header,
+        /// giving up at `deadline`
+        pub async fn sign(
+            &self,
+            data: &[u8],
+            deadline: Instant,
+        ) -> Result<Signature<[u8], SumEd25519_12>, SignerError> {
+            let answer = timeout_at(TokioInstant::from_std(deadline), self.query(data))
+                .await
+                .map_err(|_| SignerError::Timeout)??;
+            let signature = match serde_json::from_str(&answer) {
+                Ok(Answer::Signature(signature)) => signature,
+                Ok(Answer::Error(reason)) => return Err(SignerError::Refused(reason)),
+                Err(e) => return Err(SignerError::InvalidAnswer(e.to_string())),
+            };
+            let bytes =
+                hex::decode(signature).map_err(|e| SignerError::InvalidAnswer(e.to_string()))?;
+            let signature = Signature::<[u8], SumEd25519_12>::from_binary(&bytes)
+                .map_err(|e| SignerError::InvalidAnswer(e.to_string()))?;
+            match signature.verify_slice(&self.public_key, data) {
+                Verification::Success => Ok(signature),
+                Verification::Failed => Err(SignerError::InvalidSignature),
+            }
+        }
+
+        // sends the request and reads the line of the answer
+        async fn query(&self, data: &[u8]) -> Result<String, SignerError> {
+            let unreachable = |e: std::io::Error| SignerError::Unreachable(e.to_string());
+
+            let mut request = serde_json::to_vec(&Request {
+                key_id: &self.key_id,
+                scheme: "sumed25519_12",
+                data: hex::encode(data),
+            })
+            .expect("signing request serialization");
+            request.push(b'\n');
+
+            let mut stream = UnixStream::connect(&self.socket_path)
+                .await
+                .map_err(unreachable)?;
+            stream.write_all(&request).await.map_err(unreachable)?;
+            let mut answer = String::new();
+            BufReader::new(stream.take(MAX_ANSWER_SIZE))
+                .read_line(&mut answer)
+                .await
+                .map_err(unreachable)?;
+            if !answer.ends_with('\n') {
+                return Err(SignerError::InvalidAnswer(format!(
+                    "no complete line within {} bytes",
+                    MAX_ANSWER_SIZE
+                )));
+            }
+            Ok(answer)
+        }
+    }
+}
@@ -7,6 +7,7 @@ use structopt::StructOpt;
 use crate::{
     blockcfg::HeaderHash,
     settings::logging::{LogFormat, LogOutput},
+    start_up::rollback::RollbackTarget,
 };
 
 #[derive(StructOpt, Debug)]
@@ -24,6 +25,20 @@ pub struct StartArguments {
     #[structopt(long = "secret", parse(from_os_str))]
     pub secret: Vec<PathBuf>,
 
+    /// Read the passphrase of the encrypted secret files from the given
+    /// environment variable. By default, the passphrase is prompted on the
+    /// terminal if a secret file is encrypted.
+    #[structopt(
+        long = "secret-passphrase-env",
+        raw(conflicts_with = "\"secret_passphrase_fd\"")
+    )]
+    pub secret_passphrase_env: Option<String>,
+
+    /// Read the passphrase of the encrypted secret files from the given
+    /// file descriptor, e.g. a pipe opened by the process starting the node.
+    #[structopt(long = "secret-passphrase-fd")]
+    pub secret_passphrase_fd: Option<i32>,
+
     /// Path to the genesis block (the block0) of the blockchain
     #[structopt(long = "genesis-block", parse(try_from_str))]
     pub block_0_path: Option<PathBuf>,
@@ -41,9 +56,35 @@ pub struct StartArguments {
     #[structopt(long = "genesis-block-hash", parse(try_from_str))]
     pub block_0_hash: Option<HeaderHash>,
 
+    /// Archive the blocks in the storage directory and start from scratch
+    /// if they belong to the blockchain of another genesis block.
+    #[structopt(long = "reinitialize-storage")]
+    pub reinitialize_storage: bool,
+
+    /// Refuse to start if the storage was written by a previous version
+    /// of the node and must be migrated, instead of migrating it.
+    #[structopt(long = "no-migrate")]
+    pub no_migrate: bool,
+
+    /// Check the blocks of the main branch in the storage before starting,
+    /// failing if one of them is missing or corrupted.
+    #[structopt(long = "verify-storage")]
+    pub verify_storage: bool,
+
+    /// Check the blocks of the main branch in the storage before starting,
+    /// moving the tip back below the first missing or corrupted block.
+    #[structopt(long = "truncate-corrupted-storage")]
+    pub truncate_corrupted_storage: bool,
+
     /// Start the explorer task and enable associated query endpoints.
     #[structopt(long = "enable-explorer")]
     pub explorer_enabled: bool,
+
+    /// Serve the blockchain found in the storage directory over the REST
+    /// interface and the explorer, without connecting to the network,
+    /// producing blocks or writing to the storage.
+    #[structopt(long = "read-only")]
+    pub read_only: bool,
 }
 
 #[derive(StructOpt, Debug)]
@@ -94,6 +135,35 @@ pub struct CommandLine {
     /// this option is useful for scripting retrieving the logs of the version of this application.
     #[structopt(long = "source-version")]
     pub source_version: bool,
+
+    /// check the storage, the secret key files, the listen ports and the
+    /// trusted peer addresses, print a report and exit. The exit code is
+    /// non-zero if any of the checks failed.
+    #[structopt(long = "self-test")]
+    pub self_test: bool,
+
+    /// write the blocks of the main branch, from the genesis block to the
+    /// tip, to the given archive file and exit.
+    #[structopt(long = "export-chain", parse(from_os_str))]
+    pub export_chain: Option<PathBuf>,
+
+    /// store the blocks of the given archive file, written with
+    /// `--export-chain` by a node of the same blockchain, and exit.
+    /// The blocks are validated when the node is started next.
+    #[structopt(long = "import-chain", parse(from_os_str))]
+    pub import_chain: Option<PathBuf>,
+
+    /// check the blocks of the main branch in the storage, print a report
+    /// and exit. The exit code is non-zero if the storage is corrupted,
+    /// unless `--truncate-corrupted-storage` is also given.
+    #[structopt(long = "verify-storage-only")]
+    pub verify_storage_only: bool,
+
+    /// move the tip of the main branch in the storage back to the given
+    /// block, by hash or chain length, and exit. The blocks above it are
+    /// downloaded again from the network when the node is started next.
+    #[structopt(long = "rollback-to")]
+    pub rollback_to: Option<RollbackTarget>,
 }
 
 impl CommandLine {
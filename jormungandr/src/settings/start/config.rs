@@ -1,17 +1,31 @@
+use super::profile::Profile;
 use crate::{
-    network::p2p::{topic, Id, PolicyConfig},
+    network::p2p::{comm::EvictionPolicy, topic, Id, PolicyConfig},
     settings::logging::{LogFormat, LogOutput},
     settings::LOG_FILTER_LEVEL_POSSIBLE_VALUES,
 };
-use jormungandr_lib::{interfaces::Mempool, time::Duration};
+use jormungandr_lib::{
+    crypto::hash::Hash,
+    interfaces::Mempool,
+    time::{Duration, SystemTime},
+};
 use poldercast;
 use serde::{de::Error as _, de::Visitor, Deserialize, Deserializer, Serialize, Serializer};
 use slog::FilterLevel;
-use std::{collections::BTreeMap, fmt, net::SocketAddr, path::PathBuf};
+use std::{
+    fmt,
+    net::{IpAddr, SocketAddr},
+    path::PathBuf,
+};
 
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(deny_unknown_fields)]
 pub struct Config {
+    /// preset of the settings for the role of the node, providing the
+    /// defaults of the settings not given in the configuration
+    #[serde(default)]
+    pub profile: Option<Profile>,
+
     #[serde(default)]
     pub secret_files: Vec<PathBuf>,
     pub storage: Option<PathBuf>,
@@ -19,7 +33,7 @@ pub struct Config {
 
     /// setting of the mempool, fragment logs and related data
     #[serde(default)]
-    pub mempool: Mempool,
+    pub mempool: Option<Mempool>,
 
     #[serde(default)]
     pub leadership: Leadership,
@@ -34,6 +48,37 @@ pub struct Config {
     /// the time interval with no blockchain updates after which alerts are thrown
     #[serde(default)]
     pub no_blockchain_updates_warning_interval: Option<Duration>,
+
+    /// reference nodes the chain tip is compared with, to detect the node
+    /// forking off the chain followed by the network
+    #[serde(default)]
+    pub divergence_check: Option<DivergenceCheckConfig>,
+
+    /// NTP servers the system clock is compared with, to detect the node
+    /// computing the slots from a skewed clock
+    #[serde(default)]
+    pub clock_check: Option<ClockCheckConfig>,
+
+    /// bounds of the in-memory cache of the states of the recent blocks
+    #[serde(default)]
+    pub block_cache: Option<BlockCacheConfig>,
+
+    /// periodic snapshot of the blockchain served to the bootstrapping nodes
+    #[serde(default)]
+    pub chain_snapshot: Option<ChainSnapshotConfig>,
+
+    /// rules of the selection of the chain followed by the node
+    #[serde(default)]
+    pub blockchain: Option<BlockchainConfig>,
+
+    /// HTTP endpoints notified of the chain events
+    #[serde(default)]
+    pub webhooks: Option<WebhooksConfig>,
+
+    /// seed of the random choices of the node, making its runs reproducible.
+    /// This is meant for the tests only.
+    #[serde(default)]
+    pub rng_seed: Option<u64>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
@@ -56,6 +101,11 @@ pub struct Rest {
     pub tls: Option<Tls>,
     /// Enables CORS if provided
     pub cors: Option<Cors>,
+    /// Path to the file holding the token authorizing the management of the
+    /// node, such as adding or removing leader secrets. Without it, only the
+    /// clients on the host of the node are authorized.
+    #[serde(default)]
+    pub admin_token_file: Option<PathBuf>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
@@ -89,17 +139,47 @@ pub struct P2pConfig {
     /// all network interfaces.
     pub listen_address: Option<Address>,
 
+    /// The local IP address the outbound connections to the peers
+    /// are made from, for hosts with several network interfaces.
+    #[serde(default)]
+    pub outbound_bind_address: Option<IpAddr>,
+
+    /// Keep the node unreachable from the network: it does not listen
+    /// for connections and does not advertise an address in its gossip,
+    /// only connecting out to its peers. This is meant for block producers
+    /// sheltered behind relay nodes. The default is false.
+    #[serde(default)]
+    pub private: bool,
+
     pub public_id: Option<Id>,
 
     /// the rendezvous points for the peer to connect to in order to initiate
     /// the p2p discovery from.
     pub trusted_peers: Option<Vec<TrustedPeer>>,
+
+    /// the peers the node always keeps a client connection to, regardless
+    /// of the view selected by the topology.
+    #[serde(default)]
+    pub preferred_peers: Option<Vec<TrustedPeer>>,
+
+    /// base URLs of the REST interfaces of nodes to download the blocks
+    /// from over HTTP(S), when they cannot be retrieved from the trusted
+    /// peers, e.g. because gRPC is blocked.
+    #[serde(default)]
+    pub block_gateways: Option<Vec<String>>,
+
     /// the topic subscriptions
     ///
     /// When connecting to different nodes we will expose these too in order to
     /// help the different modules of the P2P topology engine to determine the
     /// best possible neighborhood.
-    pub topics_of_interest: Option<BTreeMap<Topic, InterestLevel>>,
+    #[serde(default)]
+    pub topics_of_interest: Option<TopicsOfInterest>,
+
+    /// the topics the node subscribes to and relays. The default is to
+    /// subscribe to all of them.
+    #[serde(default)]
+    pub subscribed_topics: Option<Vec<Topic>>,
 
     /// Limit on the number of simultaneous connections.
     /// If not specified, an internal default limit is used.
@@ -109,14 +189,120 @@ pub struct P2pConfig {
     /// If not specified, an internal default limit is used.
     pub max_client_connections: Option<usize>,
 
+    /// Minimum number of client connections the node tries to maintain.
+    /// When the number of connected peers drops below it, the node
+    /// actively dials known and trusted peers instead of waiting for
+    /// gossip. If not specified, no minimum is enforced.
+    #[serde(default)]
+    pub min_client_connections: Option<usize>,
+
+    /// Number of connection slots kept for the peers the node synchronizes
+    /// the blockchain from: the trusted peers and the peers that announced
+    /// blocks. The default value is 4.
+    #[serde(default)]
+    pub reserved_sync_connections: Option<usize>,
+
+    /// Which peers get disconnected when connections have to be closed
+    /// to admit new ones. The default is `least_useful`.
+    #[serde(default)]
+    pub eviction_policy: Option<EvictionPolicy>,
+
+    /// interval at which the number of client connections is checked
+    /// against `min_client_connections`. The default value is 10seconds.
+    #[serde(default)]
+    pub peer_recruitment_interval: Option<Duration>,
+
     /// This setting is not used and is left for backward compatibility.
     pub max_connections_threshold: Option<usize>,
 
+    /// Limit on the number of simultaneous inbound connections accepted
+    /// from a single IP address. If not specified, the number of
+    /// connections from one address is only bounded by `max_connections`.
+    #[serde(default)]
+    pub max_inbound_connections_per_ip: Option<usize>,
+
+    /// Time allowed to an inbound connection to send the HTTP/2 connection
+    /// preface after being accepted. The default value is 10seconds.
+    #[serde(default)]
+    pub handshake_timeout: Option<Duration>,
+
+    /// Limit on the number of inbound connections that are accepted but
+    /// have not completed the handshake yet. The default value is 64.
+    #[serde(default)]
+    pub max_half_open_connections: Option<usize>,
+
+    /// Limit on the number of outbound connections being dialled at a time
+    /// to deliver propagated items or gossip. The default value is 16.
+    #[serde(default)]
+    pub max_concurrent_dials: Option<usize>,
+
+    /// Inbound connections without any activity for this long are closed.
+    /// The default value is 10minutes.
+    #[serde(default)]
+    pub idle_connection_timeout: Option<Duration>,
+
+    /// Delay before dialling again the peers that could not be connected
+    /// to. The default is to wait 5seconds after a first failure, doubling
+    /// the delay with every consecutive failure up to 10minutes.
+    #[serde(default)]
+    pub reconnect_backoff: Option<ReconnectBackoffConfig>,
+
+    /// Keepalive requests sent on the idle client connections, so the
+    /// connections dropped silently on the way (e.g. by a NAT) are detected
+    /// and closed. The default is to send a keepalive after 30seconds
+    /// without activity and to close connections idle for 90seconds.
+    #[serde(default)]
+    pub keepalive: Option<KeepaliveConfig>,
+
+    /// Limit, in bytes per second, on the rate at which blocks are sent
+    /// to the peers in response to their block requests. The limit is shared
+    /// by all the peers. If not specified, the rate is not limited.
+    #[serde(default)]
+    pub outbound_blocks_rate_limit: Option<u64>,
+
+    /// Limit, in bytes per second, on the rate at which blocks uploaded
+    /// by the peers are accepted. The limit is shared by all the peers.
+    /// If not specified, the rate is not limited.
+    #[serde(default)]
+    pub inbound_blocks_rate_limit: Option<u64>,
+
+    /// Limit on the number of blocks back from the tip that the chain pulls
+    /// and block requests of peers other than the trusted peers may reach.
+    /// If not specified, the whole chain is served to any peer.
+    #[serde(default)]
+    pub max_untrusted_pull_depth: Option<u64>,
+
+    /// Path to the file holding the key the node signs its gossip with,
+    /// an Ed25519 secret key in bech32 as generated by `jcli key generate`.
+    /// The node identifier is derived from the key. If not specified, a key
+    /// is generated in the storage directory on the first start.
+    #[serde(default)]
+    pub gossip_key_file: Option<PathBuf>,
+
+    /// Whether to accept gossip entries that are not signed by the node they
+    /// describe. The default is to accept them, for compatibility with the
+    /// nodes not signing their gossip.
+    #[serde(default)]
+    pub accept_unsigned_gossip: Option<bool>,
+
+    /// End of the transition window during which the items received in
+    /// the previous wire format are accepted, as a RFC 3339 date and time.
+    /// The default is to accept them with no time limit.
+    #[serde(default)]
+    pub legacy_wire_format_until: Option<SystemTime>,
+
     /// Whether to allow non-public IP addresses on the network.
     /// The default is to not allow advertising non-public IP addresses.
+    /// Setting this allows all the kinds of addresses listed in
+    /// `address_filter`.
     #[serde(default)]
     pub allow_private_addresses: bool,
 
+    /// The kinds of non-public IP addresses to accept in gossip, for
+    /// test networks. The default is to accept none of them.
+    #[serde(default)]
+    pub address_filter: Option<AddressFilterConfig>,
+
     /// setting for the policy
     #[serde(default)]
     pub policy: PolicyConfig,
@@ -139,6 +325,13 @@ pub struct P2pConfig {
     #[serde(default)]
     pub gossip_interval: Option<Duration>,
 
+    /// If this value is set, the gossip interval adapts to the state of the
+    /// topology: the node gossips more often while its view is small or the
+    /// peers in it are changing, and less often once it is stable. The
+    /// `gossip_interval` is then only the initial interval.
+    #[serde(default)]
+    pub adaptive_gossip: Option<AdaptiveGossipConfig>,
+
     /// If this value is set, it will trigger a force reset of the topology
     /// layers. The default is to not do force the reset. It is recommended
     /// to let the protocol handle it.
@@ -146,6 +339,14 @@ pub struct P2pConfig {
     #[serde(default)]
     pub topology_force_reset_interval: Option<Duration>,
 
+    /// The interval at which the gossip received from the peers is
+    /// accepted into the topology, in a single batch. This caps the
+    /// frequency of the topology updates.
+    ///
+    /// The default value is 500ms.
+    #[serde(default)]
+    pub topology_update_interval: Option<Duration>,
+
     /// The number of times to retry bootstrapping from trusted peers. The default
     /// value of None will result in the bootstrap process retrying indefinitely. A
     /// value of zero will skip bootstrap all together -- even if trusted peers are
@@ -156,6 +357,28 @@ pub struct P2pConfig {
     /// gossip with the trusted peers if any are defined.
     #[serde(default)]
     pub max_bootstrap_attempts: Option<usize>,
+
+    /// The number of trusted peers the headers are pulled from when the node
+    /// catches up with the blockchain at start-up. The blocks are downloaded
+    /// from the peer with the best chain.
+    ///
+    /// The default value is 3.
+    #[serde(default)]
+    pub sync_peers: Option<usize>,
+
+    /// Blocks of the chain known to be good, given by chain length and
+    /// hash. The blocks up to the last of them are not verified against
+    /// the leadership schedule when the node catches up at start-up, only
+    /// linked by hash, and a peer sending another block at the chain
+    /// length of a checkpoint is dropped.
+    #[serde(default)]
+    pub trusted_checkpoints: Option<Vec<TrustedCheckpoint>>,
+
+    /// Time allowed, when the node shuts down, for the block streams
+    /// being served to the peers to complete before the connections
+    /// are closed. The default is 10seconds.
+    #[serde(default)]
+    pub drain_timeout: Option<Duration>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -165,6 +388,13 @@ pub struct TrustedPeer {
     pub id: Id,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TrustedCheckpoint {
+    pub chain_length: u32,
+    pub hash: Hash,
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
 #[serde(deny_unknown_fields)]
 pub struct Leadership {
@@ -173,14 +403,40 @@ pub struct Leadership {
     pub log_ttl: Duration,
     /// interval between 2 garbage collection check logs
     pub garbage_collection_interval: Duration,
+    /// number of the latest epochs the logs past their TTL are kept for,
+    /// in the storage directory if the node has one. The logs are
+    /// discarded at the end of the TTL if not set.
+    #[serde(default)]
+    pub log_retention_epochs: Option<u32>,
+    /// hot-standby with other nodes holding the same leader keys, only
+    /// the node holding the lease signing blocks
+    #[serde(default)]
+    pub standby: Option<StandbyConfig>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct StandbyConfig {
+    /// path to the lease file, on a storage shared by the nodes
+    pub lease_file: PathBuf,
+
+    /// name of the node in the lease file, different for each node
+    pub node_name: String,
+
+    /// time the lease lasts after it is last renewed, after which another
+    /// node takes it over. The default value is 30seconds.
+    #[serde(default)]
+    pub lease_duration: Option<Duration>,
+
+    /// interval between two renewals of the lease. The default value is
+    /// 5seconds.
+    #[serde(default)]
+    pub heartbeat_interval: Option<Duration>,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Address(pub poldercast::Address);
 
-#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
-pub struct Topic(pub poldercast::Topic);
-
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct InterestLevel(pub poldercast::InterestLevel);
 
@@ -190,19 +446,223 @@ pub struct Explorer {
     pub enabled: bool,
 }
 
-pub fn default_interests() -> BTreeMap<Topic, InterestLevel> {
-    use std::iter::FromIterator as _;
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct DivergenceCheckConfig {
+    /// P2P addresses of the reference nodes
+    pub reference_nodes: Vec<Address>,
 
-    BTreeMap::from_iter(vec![
-        (
-            Topic(topic::MESSAGES),
-            InterestLevel(poldercast::InterestLevel::Low),
-        ),
-        (
-            Topic(topic::BLOCKS),
-            InterestLevel(poldercast::InterestLevel::Normal),
-        ),
-    ])
+    /// interval between two checks. The default value is 1minute.
+    #[serde(default)]
+    pub check_interval: Option<Duration>,
+
+    /// number of slots the tip may differ from the one of the majority of
+    /// the reference nodes before an alert is raised. The default value is
+    /// 10 slots.
+    #[serde(default)]
+    pub max_divergence_slots: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct ClockCheckConfig {
+    /// addresses of the NTP servers, in the format `host:port`
+    pub ntp_servers: Vec<String>,
+
+    /// interval between two checks. The default value is 5minutes.
+    #[serde(default)]
+    pub check_interval: Option<Duration>,
+
+    /// difference between the system clock and the one of the NTP servers
+    /// beyond which an alert is raised. The default value is 500ms.
+    #[serde(default)]
+    pub max_skew: Option<Duration>,
+
+    /// do not sign blocks while the skew of the system clock is beyond
+    /// `max_skew`, the slots being possibly computed at the wrong time.
+    /// The default is false.
+    #[serde(default)]
+    pub refuse_to_sign: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct BlockCacheConfig {
+    /// time after which the state of a block not accessed since is
+    /// dropped from the cache. The default value is 5days.
+    #[serde(default)]
+    pub ttl: Option<Duration>,
+
+    /// maximum number of block states kept in the cache, the least
+    /// recently accessed ones being dropped first. Unbounded by default.
+    #[serde(default)]
+    pub max_entries: Option<usize>,
+
+    /// number of the latest epochs whose last block state is kept in the
+    /// cache regardless of `ttl` and `max_entries`. The default value is 2.
+    #[serde(default)]
+    pub pinned_epochs: Option<usize>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct BlockchainConfig {
+    /// maximum number of blocks of the current chain a switch to another
+    /// branch may roll back. Unbounded by default.
+    #[serde(default)]
+    pub max_rollback_depth: Option<u32>,
+
+    /// number of slots before the end of an epoch from which the leadership
+    /// schedule of the next epoch is computed ahead of its start.
+    #[serde(default)]
+    pub epoch_transition_window: Option<u32>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct WebhooksConfig {
+    /// the URLs the events are POSTed to
+    pub urls: Vec<String>,
+
+    /// number of blocks a switch to another branch must roll back to be
+    /// reported as a deep reorg. The default value is 3 blocks.
+    #[serde(default)]
+    pub deep_reorg_depth: Option<u32>,
+
+    /// number of attempts to deliver an event to a URL before giving up.
+    /// The default value is 5 attempts.
+    #[serde(default)]
+    pub max_attempts: Option<u32>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct ChainSnapshotConfig {
+    /// interval between two snapshots. The default value is 6h.
+    #[serde(default)]
+    pub interval: Option<Duration>,
+}
+
+/// interest levels of the node in the P2P topics, used to build
+/// the rings of the topology.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct TopicsOfInterest {
+    /// interest in the fragments (transactions, certificates...)
+    #[serde(default = "TopicsOfInterest::default_messages")]
+    pub messages: InterestLevel,
+    /// interest in the new blocks
+    #[serde(default = "TopicsOfInterest::default_blocks")]
+    pub blocks: InterestLevel,
+}
+
+impl TopicsOfInterest {
+    fn default_messages() -> InterestLevel {
+        InterestLevel(poldercast::InterestLevel::Low)
+    }
+
+    fn default_blocks() -> InterestLevel {
+        InterestLevel(poldercast::InterestLevel::Normal)
+    }
+
+    pub fn subscriptions(self) -> impl Iterator<Item = poldercast::Subscription> {
+        vec![
+            poldercast::Subscription {
+                topic: topic::MESSAGES,
+                interest: self.messages.0,
+            },
+            poldercast::Subscription {
+                topic: topic::BLOCKS,
+                interest: self.blocks.0,
+            },
+        ]
+        .into_iter()
+    }
+}
+
+impl Default for TopicsOfInterest {
+    fn default() -> Self {
+        TopicsOfInterest {
+            messages: Self::default_messages(),
+            blocks: Self::default_blocks(),
+        }
+    }
+}
+
+/// the node's own gossip profile, persisted in the storage directory
+/// so the node advertises itself consistently across restarts.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PersistedProfile {
+    pub id: Id,
+    pub topics_of_interest: TopicsOfInterest,
+    #[serde(default)]
+    pub subscribed_topics: Option<Vec<Topic>>,
+}
+
+/// a P2P topic, as named in the configuration
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Topic {
+    /// the fragments (transactions, certificates...)
+    Messages,
+    /// the blocks
+    Blocks,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ReconnectBackoffConfig {
+    /// the delay after a first failure to connect. The default value is 5seconds.
+    #[serde(default)]
+    pub initial_delay: Option<Duration>,
+
+    /// the longest delay between two attempts. The default value is 10minutes.
+    #[serde(default)]
+    pub max_delay: Option<Duration>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct KeepaliveConfig {
+    /// time without activity on a connection after which a keepalive is
+    /// sent. The default value is 30seconds.
+    #[serde(default)]
+    pub interval: Option<Duration>,
+
+    /// time without activity after which the peer is considered dead and
+    /// the connection is closed. The default value is 90seconds.
+    #[serde(default)]
+    pub timeout: Option<Duration>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AdaptiveGossipConfig {
+    /// the shortest interval between gossip rounds. The default value is 2seconds.
+    #[serde(default)]
+    pub min_interval: Option<Duration>,
+
+    /// the longest interval between gossip rounds. The default value is 1minute.
+    #[serde(default)]
+    pub max_interval: Option<Duration>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AddressFilterConfig {
+    /// accept loopback addresses (`127.0.0.0/8`, `::1`)
+    #[serde(default)]
+    pub allow_loopback: bool,
+
+    /// accept private network addresses (`10.0.0.0/8`, `172.16.0.0/12`,
+    /// `192.168.0.0/16`, `fc00::/7`)
+    #[serde(default)]
+    pub allow_private: bool,
+
+    /// accept link-local addresses (`169.254.0.0/16`, `fe80::/10`)
+    #[serde(default)]
+    pub allow_link_local: bool,
 }
 
 impl Default for P2pConfig {
@@ -210,18 +670,46 @@ impl Default for P2pConfig {
         P2pConfig {
             public_address: None,
             listen_address: None,
+            outbound_bind_address: None,
+            private: false,
             public_id: None,
             trusted_peers: None,
+            preferred_peers: None,
+            block_gateways: None,
             topics_of_interest: None,
+            subscribed_topics: None,
             max_connections: None,
             max_client_connections: None,
+            min_client_connections: None,
+            reserved_sync_connections: None,
+            eviction_policy: None,
+            peer_recruitment_interval: None,
             max_connections_threshold: None,
+            max_inbound_connections_per_ip: None,
+            handshake_timeout: None,
+            max_half_open_connections: None,
+            max_concurrent_dials: None,
+            idle_connection_timeout: None,
+            reconnect_backoff: None,
+            keepalive: None,
+            outbound_blocks_rate_limit: None,
+            inbound_blocks_rate_limit: None,
+            max_untrusted_pull_depth: None,
+            gossip_key_file: None,
+            accept_unsigned_gossip: None,
+            legacy_wire_format_until: None,
             allow_private_addresses: false,
+            address_filter: None,
             policy: PolicyConfig::default(),
             max_unreachable_nodes_to_connect_per_event: None,
             gossip_interval: None,
+            adaptive_gossip: None,
             topology_force_reset_interval: None,
+            topology_update_interval: None,
             max_bootstrap_attempts: None,
+            sync_peers: None,
+            trusted_checkpoints: None,
+            drain_timeout: None,
         }
     }
 }
@@ -231,6 +719,8 @@ impl Default for Leadership {
         Leadership {
             log_ttl: Duration::new(3600, 0),
             garbage_collection_interval: Duration::new(3600 / 4, 0),
+            log_retention_epochs: None,
+            standby: None,
         }
     }
 }
@@ -279,21 +769,6 @@ impl Serialize for Address {
         serializer.serialize_str(&format!("{}", self.0))
     }
 }
-impl Serialize for Topic {
-    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
-    where
-        S: Serializer,
-    {
-        use serde::ser::Error;
-        if self.0 == topic::MESSAGES.into() {
-            serializer.serialize_str("messages")
-        } else if self.0 == topic::BLOCKS.into() {
-            serializer.serialize_str("blocks")
-        } else {
-            Err(S::Error::custom("invalid state... should not happen"))
-        }
-    }
-}
 impl Serialize for InterestLevel {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
@@ -335,36 +810,6 @@ impl<'de> Deserialize<'de> for Address {
     }
 }
 
-impl<'de> Deserialize<'de> for Topic {
-    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
-    where
-        D: Deserializer<'de>,
-    {
-        struct TopicVisitor;
-        impl<'de> Visitor<'de> for TopicVisitor {
-            type Value = Topic;
-
-            fn expecting(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
-                write!(fmt, "Topic: messages or blocks")
-            }
-
-            fn visit_str<'a, E>(self, v: &'a str) -> std::result::Result<Self::Value, E>
-            where
-                E: serde::de::Error,
-            {
-                use serde::de::Unexpected;
-
-                match v {
-                    "messages" => Ok(Topic(topic::MESSAGES.into())),
-                    "blocks" => Ok(Topic(topic::BLOCKS.into())),
-                    err => Err(E::invalid_value(Unexpected::Str(err), &self)),
-                }
-            }
-        }
-        deserializer.deserialize_str(TopicVisitor)
-    }
-}
-
 impl<'de> Deserialize<'de> for InterestLevel {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
     where
@@ -1,13 +1,18 @@
 pub mod config;
 pub mod network;
+pub mod profile;
 
 use self::config::{Config, Leadership};
 pub use self::config::{Cors, Rest, Tls};
 use self::network::Protocol;
+use crate::network::p2p::Id;
 use crate::rest::Error as RestError;
+use crate::secure::PassphraseSource;
 use crate::settings::logging::{LogFormat, LogOutput, LogSettings, LogSettingsEntry};
 use crate::settings::{command_arguments::*, Block0Info};
-use jormungandr_lib::interfaces::Mempool;
+use crate::utils::rng::RngProvider;
+use chain_crypto::{bech32::Bech32 as _, Ed25519, SecretKey};
+use jormungandr_lib::{crypto::node_id::node_id, interfaces::Mempool};
 use slog::{FilterLevel, Logger};
 use std::{fs::File, path::PathBuf};
 use thiserror::Error;
@@ -16,6 +21,35 @@ const DEFAULT_FILTER_LEVEL: FilterLevel = FilterLevel::Info;
 const DEFAULT_LOG_FORMAT: LogFormat = LogFormat::Plain;
 const DEFAULT_LOG_OUTPUT: LogOutput = LogOutput::Stderr;
 const DEFAULT_NO_BLOCKCHAIN_UPDATES_WARNING_INTERVAL: u64 = 1800; // 30 min
+const DEFAULT_BLOCK_CACHE_TTL: u64 = 5 * 24 * 3600; // 5 days
+const DEFAULT_BLOCK_CACHE_PINNED_EPOCHS: usize = 2;
+const DEFAULT_CHAIN_SNAPSHOT_INTERVAL: u64 = 6 * 3600; // 6 hours
+const DEFAULT_EPOCH_TRANSITION_WINDOW: u32 = 100;
+const DEFAULT_WEBHOOK_DEEP_REORG_DEPTH: u32 = 3;
+const DEFAULT_WEBHOOK_MAX_ATTEMPTS: u32 = 5;
+const DEFAULT_STANDBY_LEASE_DURATION: std::time::Duration = std::time::Duration::from_secs(30);
+const DEFAULT_STANDBY_HEARTBEAT_INTERVAL: std::time::Duration = std::time::Duration::from_secs(5);
+const DEFAULT_CLOCK_CHECK_INTERVAL: std::time::Duration = std::time::Duration::from_secs(5 * 60);
+const DEFAULT_CLOCK_MAX_SKEW: std::time::Duration = std::time::Duration::from_millis(500);
+
+/// name of the file, in the storage directory, where the node's own
+/// gossip profile is persisted
+const PERSISTED_PROFILE_FILE: &str = "p2p_profile.yaml";
+
+/// name of the file, in the storage directory, where the key the node
+/// signs its gossip with is generated when no gossip key file is given
+const GOSSIP_KEY_FILE: &str = "gossip_key.sk";
+
+/// name of the file, in the storage directory, where the snapshot
+/// of the blockchain served to the bootstrapping nodes is written
+const CHAIN_SNAPSHOT_FILE: &str = "chain.snapshot";
+
+/// name of the file, in the storage directory, where the fragments
+/// of the pool are persisted across restarts
+const MEMPOOL_FILE: &str = "mempool.bin";
+/// name of the file, in the storage directory, where the leadership logs
+/// past their TTL are archived
+const LEADERSHIP_LOG_FILE: &str = "leadership_logs.log";
 
 #[derive(Debug, Error)]
 pub enum Error {
@@ -31,19 +65,137 @@ pub enum Error {
     TooMuchBlock0Info,
     #[error("In the node configuration file, the `p2p.listen_address` value is not a valid address. Use format `/ip4/x.x.x.x/tcp/4920")]
     ListenAddressNotValid,
+    #[error("In the node configuration file, the reference node address `{0}` is not a valid address. Use format `/ip4/x.x.x.x/tcp/4920")]
+    ReferenceNodeAddressNotValid(String),
+    #[error("In the node configuration file, the NTP server address `{0}` is not valid. Use format `host:port`")]
+    NtpServerAddressNotValid(String),
+    #[error("In the node configuration file, the block gateway `{0}` is not a valid HTTP(S) URL")]
+    BlockGatewayUrlNotValid(String),
+    #[error("In the node configuration file, the webhook `{0}` is not a valid HTTP(S) URL")]
+    WebhookUrlNotValid(String),
+    #[error("In the node configuration file, `p2p.adaptive_gossip.min_interval` is larger than `p2p.adaptive_gossip.max_interval`")]
+    AdaptiveGossipIntervalNotValid,
+    #[error("In the node configuration file, `p2p.reconnect_backoff.initial_delay` is larger than `p2p.reconnect_backoff.max_delay`")]
+    ReconnectBackoffNotValid,
+    #[error("In the node configuration file, `p2p.keepalive.interval` is not shorter than `p2p.keepalive.timeout`")]
+    KeepaliveNotValid,
+    #[error("In the node configuration file, `leadership.standby.heartbeat_interval` is not shorter than `leadership.standby.lease_duration`")]
+    StandbyNotValid,
+    #[error("In the node configuration file, `{0}` cannot be zero")]
+    ZeroInterval(&'static str),
+    #[error("`--read-only` cannot be used with `{0}`, which writes to the storage")]
+    ReadOnlyConflict(&'static str),
+    #[error("`--read-only` needs the storage directory of the blockchain to serve")]
+    ReadOnlyWithoutStorage,
+    #[error("Cannot read the gossip key file {path}")]
+    GossipKeyIo {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+    #[error("The gossip key in {path} is not a valid bech32 Ed25519 secret key")]
+    GossipKeyInvalid {
+        path: PathBuf,
+        #[source]
+        source: chain_crypto::bech32::Error,
+    },
+    #[error(
+        "The node needs a persisted gossip key: set `p2p.gossip_key_file` or the storage directory"
+    )]
+    GossipKeyRequired,
+    #[error("`p2p.public_id` is {configured} but the identifier derived from the gossip key is {derived}")]
+    PublicIdMismatch { configured: Id, derived: Id },
+    #[error("Cannot read the REST admin token file {path}")]
+    AdminTokenIo {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+    #[error("The REST admin token file {path} is empty")]
+    AdminTokenEmpty { path: PathBuf },
+    #[error("Cannot read or write the persisted node profile {path}")]
+    PersistedProfileIo {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+    #[error("The persisted node profile {path} is not valid")]
+    PersistedProfileInvalid {
+        path: PathBuf,
+        #[source]
+        source: serde_yaml::Error,
+    },
 }
 
 /// Overall Settings for node
 pub struct Settings {
     pub network: network::Configuration,
     pub storage: Option<PathBuf>,
+    pub reinitialize_storage: bool,
+    pub no_migrate: bool,
+    pub verify_storage: bool,
+    pub truncate_corrupted_storage: bool,
+    pub read_only: bool,
     pub block_0: Block0Info,
     pub secrets: Vec<PathBuf>,
+    pub secret_passphrase: PassphraseSource,
     pub rest: Option<Rest>,
+    pub rest_admin_token: Option<String>,
     pub mempool: Mempool,
+    pub mempool_file: Option<PathBuf>,
+    pub leadership_log_file: Option<PathBuf>,
     pub leadership: Leadership,
     pub explorer: bool,
     pub no_blockchain_updates_warning_interval: std::time::Duration,
+    pub divergence_check: Option<network::DivergenceCheck>,
+    pub block_cache: BlockCache,
+    pub chain_snapshot: Option<ChainSnapshot>,
+    pub max_rollback_depth: Option<u32>,
+    pub epoch_transition_window: u32,
+    pub webhooks: Option<Webhooks>,
+    pub standby: Option<Standby>,
+    pub clock_check: Option<ClockCheck>,
+}
+
+/// Bounds of the cache of the states of the recent blocks
+pub struct BlockCache {
+    pub ttl: std::time::Duration,
+    pub max_entries: Option<usize>,
+    pub pinned_epochs: usize,
+}
+
+/// Notifications of the chain events POSTed to HTTP endpoints
+#[derive(Clone)]
+pub struct Webhooks {
+    pub urls: Vec<reqwest::Url>,
+    pub deep_reorg_depth: u32,
+    pub max_attempts: u32,
+}
+
+/// Hot-standby of the block producers holding the same leader keys
+#[derive(Clone)]
+pub struct Standby {
+    pub lease_file: PathBuf,
+    pub node_name: String,
+    pub lease_duration: std::time::Duration,
+    pub heartbeat_interval: std::time::Duration,
+}
+
+/// Comparison of the system clock with the one of NTP servers
+#[derive(Clone)]
+pub struct ClockCheck {
+    /// the addresses of the NTP servers, as `host:port`
+    pub ntp_servers: Vec<String>,
+    pub check_interval: std::time::Duration,
+    pub max_skew: std::time::Duration,
+    pub refuse_to_sign: bool,
+}
+
+/// Periodic snapshot of the blockchain served over the REST interface
+#[derive(Clone)]
+pub struct ChainSnapshot {
+    pub path: PathBuf,
+    pub interval: std::time::Duration,
 }
 
 pub struct RawSettings {
@@ -54,7 +206,11 @@ pub struct RawSettings {
 impl RawSettings {
     pub fn load(command_line: CommandLine) -> Result<Self, Error> {
         let config = if let Some(node_config) = &command_line.start_arguments.node_config {
-            Some(serde_yaml::from_reader(File::open(node_config)?)?)
+            let mut config: Config = serde_yaml::from_reader(File::open(node_config)?)?;
+            if let Some(profile) = config.profile {
+                profile.apply(&mut config);
+            }
+            Some(config)
         } else {
             None
         };
@@ -113,6 +269,7 @@ impl RawSettings {
                 listen: cmd_listen,
                 tls: None,
                 cors: None,
+                admin_token_file: None,
             }),
             (None, None) => None,
         }
@@ -125,12 +282,18 @@ impl RawSettings {
     /// This function will print&exit if anything is not as it should be.
     pub fn try_into_settings(self, logger: &Logger) -> Result<Settings, Error> {
         let rest = self.rest_config();
+        let rest_admin_token = match rest
+            .as_ref()
+            .and_then(|rest| rest.admin_token_file.as_ref())
+        {
+            Some(path) => Some(read_admin_token(path)?),
+            None => None,
+        };
         let RawSettings {
             command_line,
             config,
         } = self;
         let command_arguments = &command_line.start_arguments;
-        let network = generate_network(&command_arguments, &config, &logger)?;
 
         let storage = match (
             command_arguments.storage.as_ref(),
@@ -141,6 +304,28 @@ impl RawSettings {
             (None, None) => None,
         };
 
+        if command_arguments.read_only {
+            check_read_only(&command_line, storage.as_ref())?;
+        }
+
+        let network = generate_network(&command_arguments, &config, storage.as_ref(), &logger)?;
+
+        let divergence_check = generate_divergence_check(&config)?;
+
+        let chain_snapshot = generate_chain_snapshot(&config, storage.as_ref(), &logger)?;
+
+        let mempool_file = storage.as_ref().map(|storage| storage.join(MEMPOOL_FILE));
+
+        let leadership_log_file = storage
+            .as_ref()
+            .map(|storage| storage.join(LEADERSHIP_LOG_FILE));
+
+        let webhooks = generate_webhooks(&config)?;
+
+        let standby = generate_standby(&config)?;
+
+        let clock_check = generate_clock_check(&config)?;
+
         let mut secrets = command_arguments.secret.clone();
         if let Some(secret_files) = config.as_ref().map(|cfg| cfg.secret_files.clone()) {
             secrets.extend(secret_files);
@@ -172,13 +357,31 @@ impl RawSettings {
 
         Ok(Settings {
             storage,
+            reinitialize_storage: command_arguments.reinitialize_storage,
+            no_migrate: command_arguments.no_migrate,
+            verify_storage: command_arguments.verify_storage
+                || command_arguments.truncate_corrupted_storage,
+            truncate_corrupted_storage: command_arguments.truncate_corrupted_storage,
+            read_only: command_arguments.read_only,
             block_0,
             network,
             secrets,
+            secret_passphrase: match (
+                &command_arguments.secret_passphrase_env,
+                command_arguments.secret_passphrase_fd,
+            ) {
+                (Some(name), _) => PassphraseSource::Env(name.clone()),
+                (None, Some(fd)) => PassphraseSource::Fd(fd),
+                (None, None) => PassphraseSource::Prompt,
+            },
             rest,
+            rest_admin_token,
             mempool: config
                 .as_ref()
-                .map_or(Mempool::default(), |cfg| cfg.mempool.clone()),
+                .and_then(|cfg| cfg.mempool.clone())
+                .unwrap_or_default(),
+            mempool_file,
+            leadership_log_file,
             leadership: config
                 .as_ref()
                 .map_or(Leadership::default(), |cfg| cfg.leadership.clone()),
@@ -190,13 +393,214 @@ impl RawSettings {
                 .unwrap_or(std::time::Duration::from_secs(
                     DEFAULT_NO_BLOCKCHAIN_UPDATES_WARNING_INTERVAL,
                 )),
+            divergence_check,
+            block_cache: generate_block_cache(&config),
+            chain_snapshot,
+            max_rollback_depth: config
+                .as_ref()
+                .and_then(|cfg| cfg.blockchain.as_ref())
+                .and_then(|blockchain| blockchain.max_rollback_depth),
+            epoch_transition_window: config
+                .as_ref()
+                .and_then(|cfg| cfg.blockchain.as_ref())
+                .and_then(|blockchain| blockchain.epoch_transition_window)
+                .unwrap_or(DEFAULT_EPOCH_TRANSITION_WINDOW),
+            webhooks,
+            standby,
+            clock_check,
+        })
+    }
+}
+
+fn parse_block_gateway(url: &str) -> Result<reqwest::Url, Error> {
+    match reqwest::Url::parse(url) {
+        Ok(url)
+            if (url.scheme() == "http" || url.scheme() == "https") && !url.cannot_be_a_base() =>
+        {
+            Ok(url)
+        }
+        _ => Err(Error::BlockGatewayUrlNotValid(url.to_owned())),
+    }
+}
+
+fn check_read_only(command_line: &CommandLine, storage: Option<&PathBuf>) -> Result<(), Error> {
+    let arguments = &command_line.start_arguments;
+    let conflicts = [
+        (arguments.reinitialize_storage, "--reinitialize-storage"),
+        (
+            arguments.truncate_corrupted_storage,
+            "--truncate-corrupted-storage",
+        ),
+        (command_line.import_chain.is_some(), "--import-chain"),
+        (command_line.rollback_to.is_some(), "--rollback-to"),
+    ];
+    if let Some((_, option)) = conflicts.iter().find(|(given, _)| *given) {
+        return Err(Error::ReadOnlyConflict(*option));
+    }
+    if storage.is_none() {
+        return Err(Error::ReadOnlyWithoutStorage);
+    }
+    Ok(())
+}
+
+fn generate_block_cache(config: &Option<Config>) -> BlockCache {
+    let config = config.as_ref().and_then(|cfg| cfg.block_cache.as_ref());
+    BlockCache {
+        ttl: config
+            .and_then(|cache| cache.ttl.clone())
+            .map(|d| d.into())
+            .unwrap_or(std::time::Duration::from_secs(DEFAULT_BLOCK_CACHE_TTL)),
+        max_entries: config.and_then(|cache| cache.max_entries),
+        pinned_epochs: config
+            .and_then(|cache| cache.pinned_epochs)
+            .unwrap_or(DEFAULT_BLOCK_CACHE_PINNED_EPOCHS),
+    }
+}
+
+fn generate_chain_snapshot(
+    config: &Option<Config>,
+    storage: Option<&PathBuf>,
+    logger: &Logger,
+) -> Result<Option<ChainSnapshot>, Error> {
+    let config = match config.as_ref().and_then(|cfg| cfg.chain_snapshot.as_ref()) {
+        Some(config) => config,
+        None => return Ok(None),
+    };
+    let interval = non_zero_interval(config.interval.clone(), "chain_snapshot.interval")?
+        .unwrap_or(std::time::Duration::from_secs(
+            DEFAULT_CHAIN_SNAPSHOT_INTERVAL,
+        ));
+    let storage = match storage {
+        Some(storage) => storage,
+        None => {
+            warn!(
+                logger,
+                "chain snapshots are written in the storage directory, disabled with the storage in memory"
+            );
+            return Ok(None);
+        }
+    };
+    Ok(Some(ChainSnapshot {
+        path: storage.join(CHAIN_SNAPSHOT_FILE),
+        interval,
+    }))
+}
+
+fn generate_webhooks(config: &Option<Config>) -> Result<Option<Webhooks>, Error> {
+    let webhooks = match config.as_ref().and_then(|cfg| cfg.webhooks.as_ref()) {
+        Some(webhooks) if !webhooks.urls.is_empty() => webhooks,
+        _ => return Ok(None),
+    };
+    let urls = webhooks
+        .urls
+        .iter()
+        .map(|url| match reqwest::Url::parse(url) {
+            Ok(parsed) if parsed.scheme() == "http" || parsed.scheme() == "https" => Ok(parsed),
+            _ => Err(Error::WebhookUrlNotValid(url.clone())),
         })
+        .collect::<Result<_, _>>()?;
+    Ok(Some(Webhooks {
+        urls,
+        deep_reorg_depth: webhooks
+            .deep_reorg_depth
+            .unwrap_or(DEFAULT_WEBHOOK_DEEP_REORG_DEPTH),
+        max_attempts: webhooks
+            .max_attempts
+            .unwrap_or(DEFAULT_WEBHOOK_MAX_ATTEMPTS)
+            .max(1),
+    }))
+}
+
+fn generate_standby(config: &Option<Config>) -> Result<Option<Standby>, Error> {
+    let standby = match config
+        .as_ref()
+        .and_then(|cfg| cfg.leadership.standby.as_ref())
+    {
+        Some(standby) => standby,
+        None => return Ok(None),
+    };
+    let lease_duration = standby
+        .lease_duration
+        .map(|d| d.into())
+        .unwrap_or(DEFAULT_STANDBY_LEASE_DURATION);
+    let heartbeat_interval = non_zero_interval(
+        standby.heartbeat_interval,
+        "leadership.standby.heartbeat_interval",
+    )?
+    .unwrap_or(DEFAULT_STANDBY_HEARTBEAT_INTERVAL);
+    if heartbeat_interval >= lease_duration {
+        return Err(Error::StandbyNotValid);
+    }
+    Ok(Some(Standby {
+        lease_file: standby.lease_file.clone(),
+        node_name: standby.node_name.clone(),
+        lease_duration,
+        heartbeat_interval,
+    }))
+}
+
+fn generate_clock_check(config: &Option<Config>) -> Result<Option<ClockCheck>, Error> {
+    let check = match config.as_ref().and_then(|cfg| cfg.clock_check.as_ref()) {
+        Some(check) if !check.ntp_servers.is_empty() => check,
+        _ => return Ok(None),
+    };
+    // the host names are resolved at every check, only the format is
+    // checked here
+    for server in &check.ntp_servers {
+        let valid = match server.rfind(':') {
+            Some(colon) => colon > 0 && server[colon + 1..].parse::<u16>().is_ok(),
+            None => false,
+        };
+        if !valid {
+            return Err(Error::NtpServerAddressNotValid(server.clone()));
+        }
     }
+    Ok(Some(ClockCheck {
+        ntp_servers: check.ntp_servers.clone(),
+        check_interval: non_zero_interval(check.check_interval, "clock_check.check_interval")?
+            .unwrap_or(DEFAULT_CLOCK_CHECK_INTERVAL),
+        max_skew: check
+            .max_skew
+            .map(|d| d.into())
+            .unwrap_or(DEFAULT_CLOCK_MAX_SKEW),
+        refuse_to_sign: check.refuse_to_sign,
+    }))
+}
+
+fn generate_divergence_check(
+    config: &Option<Config>,
+) -> Result<Option<network::DivergenceCheck>, Error> {
+    let check = match config
+        .as_ref()
+        .and_then(|cfg| cfg.divergence_check.as_ref())
+    {
+        Some(check) if !check.reference_nodes.is_empty() => check,
+        _ => return Ok(None),
+    };
+    let reference_nodes = check
+        .reference_nodes
+        .iter()
+        .map(|address| {
+            address
+                .0
+                .to_socketaddr()
+                .ok_or_else(|| Error::ReferenceNodeAddressNotValid(address.0.to_string()))
+        })
+        .collect::<Result<_, _>>()?;
+    Ok(Some(network::DivergenceCheck {
+        reference_nodes,
+        check_interval: non_zero_interval(check.check_interval, "divergence_check.check_interval")?
+            .unwrap_or(network::DEFAULT_DIVERGENCE_CHECK_INTERVAL),
+        max_divergence_slots: check
+            .max_divergence_slots
+            .unwrap_or(network::DEFAULT_MAX_DIVERGENCE_SLOTS),
+    }))
 }
 
 fn generate_network(
     command_arguments: &StartArguments,
     config: &Option<Config>,
+    storage: Option<&PathBuf>,
     logger: &Logger,
 ) -> Result<network::Configuration, Error> {
     let mut p2p = if let Some(cfg) = config {
@@ -213,24 +617,75 @@ fn generate_network(
         p2p.trusted_peers = Some(command_arguments.trusted_peer.clone())
     }
 
-    let mut profile = poldercast::NodeProfileBuilder::new();
+    // The node identifier is derived from the gossip key, so that the
+    // peers can check the entries about the node are signed by it.
+    let gossip_key = gossip_key(&p2p, storage)?;
+    let public_id = Id::from(node_id(&gossip_key.to_public()));
+    if let Some(configured) = p2p.public_id {
+        if configured != public_id {
+            return Err(Error::PublicIdMismatch {
+                configured,
+                derived: public_id,
+            });
+        }
+    }
 
-    if let Some(id) = p2p.public_id {
-        profile.id(id.into());
+    let persisted_profile_path = storage.map(|storage| storage.join(PERSISTED_PROFILE_FILE));
+    if let Some(path) = &persisted_profile_path {
+        if let Some(persisted) = read_persisted_profile(path)? {
+            if p2p.topics_of_interest.is_none() {
+                p2p.topics_of_interest = Some(persisted.topics_of_interest);
+            }
+            if p2p.subscribed_topics.is_none() {
+                p2p.subscribed_topics = persisted.subscribed_topics;
+            }
+        }
+    }
+
+    let topics = match &p2p.subscribed_topics {
+        None => network::Topics::all(),
+        Some(topics) => network::Topics {
+            messages: topics.contains(&config::Topic::Messages),
+            blocks: topics.contains(&config::Topic::Blocks),
+        },
     };
+    if !topics.messages && !topics.blocks {
+        warn!(
+            logger,
+            "p2p.subscribed_topics is empty, the node will not relay any fragment or block"
+        );
+    }
 
-    if let Some(address) = p2p.public_address {
-        profile.address(address.clone().0);
+    let mut profile = poldercast::NodeProfileBuilder::new();
+
+    profile.id(public_id.into());
+
+    match &p2p.public_address {
+        Some(_) if p2p.private => {
+            warn!(
+                logger,
+                "p2p.public_address is ignored, the node is private and does not advertise an address"
+            );
+        }
+        Some(address) => {
+            profile.address(address.clone().0);
+        }
+        None => {}
+    }
+    if p2p.private && p2p.listen_address.is_some() {
+        warn!(
+            logger,
+            "p2p.listen_address is ignored, the node is private and does not listen for connections"
+        );
     }
 
-    for (topic, interest_level) in p2p
+    for sub in p2p
         .topics_of_interest
-        .unwrap_or(config::default_interests())
+        .clone()
+        .unwrap_or_default()
+        .subscriptions()
+        .filter(|sub| topics.contains(sub.topic))
     {
-        let sub = poldercast::Subscription {
-            topic: topic.0,
-            interest: interest_level.0,
-        };
         profile.add_subscription(sub);
     }
 
@@ -246,6 +701,8 @@ fn generate_network(
                 }
             }
         },
+        outbound_bind_address: p2p.outbound_bind_address,
+        private: p2p.private,
         trusted_peers: p2p
             .trusted_peers
             .clone()
@@ -253,6 +710,19 @@ fn generate_network(
             .into_iter()
             .map(Into::into)
             .collect(),
+        preferred_peers: p2p
+            .preferred_peers
+            .clone()
+            .unwrap_or(vec![])
+            .into_iter()
+            .map(Into::into)
+            .collect(),
+        block_gateways: p2p
+            .block_gateways
+            .iter()
+            .flatten()
+            .map(|url| parse_block_gateway(url))
+            .collect::<Result<_, _>>()?,
         protocol: Protocol::Grpc,
         policy: p2p.policy.clone(),
         max_connections: p2p
@@ -261,15 +731,147 @@ fn generate_network(
         max_client_connections: p2p
             .max_client_connections
             .unwrap_or(network::DEFAULT_MAX_CLIENT_CONNECTIONS),
+        min_client_connections: p2p.min_client_connections.filter(|&min| min > 0),
+        reserved_sync_connections: p2p
+            .reserved_sync_connections
+            .unwrap_or(network::DEFAULT_RESERVED_SYNC_CONNECTIONS),
+        eviction_policy: p2p.eviction_policy.unwrap_or_default(),
+        peer_recruitment_interval: non_zero_interval(
+            p2p.peer_recruitment_interval,
+            "p2p.peer_recruitment_interval",
+        )?
+        .unwrap_or(std::time::Duration::from_secs(10)),
+        max_inbound_connections_per_ip: p2p.max_inbound_connections_per_ip,
+        handshake_timeout: p2p
+            .handshake_timeout
+            .map(|d| d.into())
+            .unwrap_or(std::time::Duration::from_secs(10)),
+        max_half_open_connections: p2p
+            .max_half_open_connections
+            .unwrap_or(network::DEFAULT_MAX_HALF_OPEN_CONNECTIONS),
+        max_concurrent_dials: p2p
+            .max_concurrent_dials
+            .unwrap_or(network::DEFAULT_MAX_CONCURRENT_DIALS),
+        idle_connection_timeout: p2p
+            .idle_connection_timeout
+            .map(|d| d.into())
+            .unwrap_or(std::time::Duration::from_secs(600)),
+        reconnect_backoff: {
+            let backoff = p2p.reconnect_backoff.clone().unwrap_or_default();
+            let initial_delay = backoff
+                .initial_delay
+                .map(|d| d.into())
+                .unwrap_or(network::DEFAULT_RECONNECT_INITIAL_DELAY);
+            let max_delay = backoff
+                .max_delay
+                .map(|d| d.into())
+                .unwrap_or(network::DEFAULT_RECONNECT_MAX_DELAY);
+            if initial_delay > max_delay {
+                return Err(Error::ReconnectBackoffNotValid);
+            }
+            network::ReconnectBackoff {
+                initial_delay,
+                max_delay,
+            }
+        },
+        keepalive: {
+            let keepalive = p2p.keepalive.clone().unwrap_or_default();
+            let interval = keepalive
+                .interval
+                .map(|d| d.into())
+                .unwrap_or(network::DEFAULT_KEEPALIVE_INTERVAL);
+            let timeout = keepalive
+                .timeout
+                .map(|d| d.into())
+                .unwrap_or(network::DEFAULT_KEEPALIVE_TIMEOUT);
+            if interval >= timeout {
+                return Err(Error::KeepaliveNotValid);
+            }
+            network::Keepalive { interval, timeout }
+        },
+        outbound_blocks_rate_limit: p2p.outbound_blocks_rate_limit.filter(|&rate| rate > 0),
+        inbound_blocks_rate_limit: p2p.inbound_blocks_rate_limit.filter(|&rate| rate > 0),
+        fragment_rate_limit: config
+            .as_ref()
+            .and_then(|cfg| cfg.mempool.as_ref())
+            .and_then(|mempool| mempool.p2p_rate_limit)
+            .filter(|limit| limit.fragments_per_second > 0),
+        max_untrusted_pull_depth: p2p.max_untrusted_pull_depth,
         timeout: std::time::Duration::from_secs(15),
-        allow_private_addresses: p2p.allow_private_addresses,
+        gossip_key,
+        accept_unsigned_gossip: p2p.accept_unsigned_gossip.unwrap_or(true),
+        legacy_wire_until: p2p.legacy_wire_format_until.map(|t| t.into()),
+        address_filter: if p2p.allow_private_addresses {
+            network::AddressFilter::allow_all()
+        } else {
+            p2p.address_filter
+                .as_ref()
+                .map(|filter| network::AddressFilter {
+                    allow_loopback: filter.allow_loopback,
+                    allow_private: filter.allow_private,
+                    allow_link_local: filter.allow_link_local,
+                })
+                .unwrap_or_default()
+        },
+        topics,
         max_unreachable_nodes_to_connect_per_event: p2p.max_unreachable_nodes_to_connect_per_event,
         gossip_interval: p2p
             .gossip_interval
             .map(|d| d.into())
             .unwrap_or(std::time::Duration::from_secs(10)),
+        adaptive_gossip: match &p2p.adaptive_gossip {
+            None => None,
+            Some(adaptive) => {
+                let min_interval =
+                    non_zero_interval(adaptive.min_interval, "p2p.adaptive_gossip.min_interval")?
+                        .unwrap_or(network::DEFAULT_MIN_GOSSIP_INTERVAL);
+                let max_interval = adaptive
+                    .max_interval
+                    .map(|d| d.into())
+                    .unwrap_or(network::DEFAULT_MAX_GOSSIP_INTERVAL);
+                if min_interval > max_interval {
+                    return Err(Error::AdaptiveGossipIntervalNotValid);
+                }
+                Some(network::AdaptiveGossip {
+                    min_interval,
+                    max_interval,
+                })
+            }
+        },
         topology_force_reset_interval: p2p.topology_force_reset_interval.map(|d| d.into()),
+        topology_update_interval: non_zero_interval(
+            p2p.topology_update_interval,
+            "p2p.topology_update_interval",
+        )?
+        .unwrap_or(network::DEFAULT_TOPOLOGY_UPDATE_INTERVAL),
         max_bootstrap_attempts: p2p.max_bootstrap_attempts,
+        sync_peers: p2p.sync_peers.unwrap_or(network::DEFAULT_SYNC_PEERS),
+        trusted_checkpoints: {
+            let mut checkpoints = p2p
+                .trusted_checkpoints
+                .clone()
+                .unwrap_or(vec![])
+                .into_iter()
+                .map(network::TrustedCheckpoint::from)
+                .collect::<Vec<_>>();
+            checkpoints.sort_by_key(|checkpoint| checkpoint.chain_length);
+            checkpoints
+        },
+        rng: match config.as_ref().and_then(|cfg| cfg.rng_seed) {
+            None => RngProvider::random(),
+            Some(seed) => {
+                warn!(
+                    logger,
+                    "the random choices of the node are seeded from the configuration, \
+                     this is only meant for tests"
+                );
+                RngProvider::seeded(seed)
+            }
+        },
+        drain_timeout: p2p
+            .drain_timeout
+            .map(|d| d.into())
+            .unwrap_or(network::DEFAULT_DRAIN_TIMEOUT),
     };
 
     if network.max_client_connections > network.max_connections {
@@ -282,5 +884,159 @@ fn generate_network(
         network.max_client_connections = network.max_connections;
     }
 
+    if network.reserved_sync_connections > network.max_connections {
+        warn!(
+            logger,
+            "p2p.reserved_sync_connections is larger than p2p.max_connections, decreasing from {} to {}",
+            network.reserved_sync_connections,
+            network.max_connections
+        );
+        network.reserved_sync_connections = network.max_connections;
+    }
+
+    if let Some(min) = network.min_client_connections {
+        if min > network.max_client_connections {
+            warn!(
+                logger,
+                "p2p.min_client_connections is larger than p2p.max_client_connections, decreasing from {} to {}",
+                min,
+                network.max_client_connections
+            );
+            network.min_client_connections = Some(network.max_client_connections);
+        }
+    }
+
+    if network.sync_peers == 0 {
+        warn!(logger, "p2p.sync_peers is 0, increasing to 1");
+        network.sync_peers = 1;
+    }
+
+    if let Some(path) = &persisted_profile_path {
+        write_persisted_profile(
+            path,
+            &config::PersistedProfile {
+                id: network.public_id(),
+                topics_of_interest: p2p.topics_of_interest.clone().unwrap_or_default(),
+                subscribed_topics: p2p.subscribed_topics.clone(),
+            },
+        )?;
+    }
+
     Ok(network)
 }
+
+// the timers cannot tick at a zero interval
+fn non_zero_interval(
+    interval: Option<jormungandr_lib::time::Duration>,
+    name: &'static str,
+) -> Result<Option<std::time::Duration>, Error> {
+    match interval.map(std::time::Duration::from) {
+        Some(interval) if interval == std::time::Duration::from_secs(0) => {
+            Err(Error::ZeroInterval(name))
+        }
+        interval => Ok(interval),
+    }
+}
+
+fn read_persisted_profile(path: &PathBuf) -> Result<Option<config::PersistedProfile>, Error> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(source) => {
+            return Err(Error::PersistedProfileIo {
+                path: path.clone(),
+                source,
+            })
+        }
+    };
+    serde_yaml::from_reader(file)
+        .map(Some)
+        .map_err(|source| Error::PersistedProfileInvalid {
+            path: path.clone(),
+            source,
+        })
+}
+
+fn write_persisted_profile(
+    path: &PathBuf,
+    profile: &config::PersistedProfile,
+) -> Result<(), Error> {
+    let io_error = |source| Error::PersistedProfileIo {
+        path: path.clone(),
+        source,
+    };
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).map_err(io_error)?;
+    }
+    let file = File::create(path).map_err(io_error)?;
+    serde_yaml::to_writer(file, profile).map_err(|source| Error::PersistedProfileInvalid {
+        path: path.clone(),
+        source,
+    })
+}
+
+// The key given in the configuration, or else the key persisted in the
+// storage directory, generated on the first start.
+fn gossip_key(
+    p2p: &config::P2pConfig,
+    storage: Option<&PathBuf>,
+) -> Result<SecretKey<Ed25519>, Error> {
+    if let Some(path) = &p2p.gossip_key_file {
+        return read_gossip_key(path);
+    }
+    let path = storage
+        .ok_or(Error::GossipKeyRequired)?
+        .join(GOSSIP_KEY_FILE);
+    if path.exists() {
+        return read_gossip_key(&path);
+    }
+    let key = SecretKey::<Ed25519>::generate(rand::rngs::OsRng);
+    let io_error = |source| Error::GossipKeyIo {
+        path: path.clone(),
+        source,
+    };
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).map_err(io_error)?;
+    }
+    write_secret_file(&path, key.to_bech32_str().as_bytes()).map_err(io_error)?;
+    Ok(key)
+}
+
+// A new file only readable and writable by the user running the node.
+fn write_secret_file(path: &PathBuf, content: &[u8]) -> std::io::Result<()> {
+    use std::io::Write as _;
+
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt as _;
+        options.mode(0o600);
+    }
+    let mut file = options.open(path)?;
+    file.write_all(content)?;
+    file.sync_all()
+}
+
+fn read_gossip_key(path: &PathBuf) -> Result<SecretKey<Ed25519>, Error> {
+    let bech32_str = std::fs::read_to_string(path).map_err(|source| Error::GossipKeyIo {
+        path: path.clone(),
+        source,
+    })?;
+    SecretKey::try_from_bech32_str(bech32_str.trim()).map_err(|source| Error::GossipKeyInvalid {
+        path: path.clone(),
+        source,
+    })
+}
+
+fn read_admin_token(path: &PathBuf) -> Result<String, Error> {
+    let token = std::fs::read_to_string(path).map_err(|source| Error::AdminTokenIo {
+        path: path.clone(),
+        source,
+    })?;
+    let token = token.trim();
+    if token.is_empty() {
+        return Err(Error::AdminTokenEmpty { path: path.clone() });
+    }
+    Ok(token.to_owned())
+}
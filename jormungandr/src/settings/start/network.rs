@@ -1,6 +1,15 @@
-use crate::network::p2p::{Id, PolicyConfig};
+use crate::blockcfg::HeaderHash;
+use crate::network::p2p::{comm::EvictionPolicy, topic, AddressScope, Id, PolicyConfig};
+use crate::utils::rng::RngProvider;
+use chain_crypto::{Ed25519, SecretKey};
+use jormungandr_lib::interfaces::FragmentRateLimit;
 use poldercast::NodeProfile;
-use std::{net::SocketAddr, str, time::Duration};
+use reqwest::Url;
+use std::{
+    net::{IpAddr, SocketAddr},
+    str,
+    time::{Duration, SystemTime},
+};
 
 /// Protocol to use for a connection.
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
@@ -37,12 +46,53 @@ pub struct Listen {
 /// used unless the corresponding configuration option is specified.
 pub const DEFAULT_MAX_CONNECTIONS: usize = 256;
 
+/// The limit on the number of inbound connections in the handshake phase
+/// used unless the corresponding configuration option is specified.
+pub const DEFAULT_MAX_HALF_OPEN_CONNECTIONS: usize = 64;
+
+/// The limit on the number of outbound connections being dialled at a time
+/// used unless the corresponding configuration option is specified.
+pub const DEFAULT_MAX_CONCURRENT_DIALS: usize = 16;
+
 /// The limit on the number of simultaneous P2P client connections
 /// used unless the corresponding configuration option is specified.
 pub const DEFAULT_MAX_CLIENT_CONNECTIONS: usize = 8;
 
+/// The number of connection slots reserved for the synchronization peers
+/// used unless the corresponding configuration option is specified.
+pub const DEFAULT_RESERVED_SYNC_CONNECTIONS: usize = 4;
+
 const DEFAULT_TIMEOUT_MICROSECONDS: u64 = 500_000;
 
+/// The bounds of the delay before dialling again a peer that could not
+/// be connected to, used unless the corresponding configuration options
+/// are specified.
+pub const DEFAULT_RECONNECT_INITIAL_DELAY: Duration = Duration::from_secs(5);
+pub const DEFAULT_RECONNECT_MAX_DELAY: Duration = Duration::from_secs(600);
+
+pub const DEFAULT_KEEPALIVE_INTERVAL: Duration = Duration::from_secs(30);
+pub const DEFAULT_KEEPALIVE_TIMEOUT: Duration = Duration::from_secs(90);
+
+pub const DEFAULT_DIVERGENCE_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+pub const DEFAULT_MAX_DIVERGENCE_SLOTS: u64 = 10;
+
+/// Time allowed for the connections to drain on shutdown, used unless
+/// the corresponding configuration option is specified.
+pub const DEFAULT_DRAIN_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The bounds of the adaptive gossip interval used unless
+/// the corresponding configuration options are specified.
+pub const DEFAULT_MIN_GOSSIP_INTERVAL: Duration = Duration::from_secs(2);
+pub const DEFAULT_MAX_GOSSIP_INTERVAL: Duration = Duration::from_secs(60);
+
+/// The interval between batched topology updates used unless
+/// the corresponding configuration option is specified.
+pub const DEFAULT_TOPOLOGY_UPDATE_INTERVAL: Duration = Duration::from_millis(500);
+
+/// The number of trusted peers to sync from at start-up used unless
+/// the corresponding configuration option is specified.
+pub const DEFAULT_SYNC_PEERS: usize = 3;
+
 ///
 /// The network static configuration settings
 #[derive(Clone)]
@@ -52,11 +102,24 @@ pub struct Configuration {
     /// network interfaces.
     pub listen_address: Option<SocketAddr>,
 
+    /// Local IP address to make the outbound connections from.
+    pub outbound_bind_address: Option<IpAddr>,
+
+    /// The node neither listens for connections nor advertises an address.
+    pub private: bool,
+
     pub profile: NodeProfile,
 
     /// list of trusted addresses
     pub trusted_peers: Vec<TrustedPeer>,
 
+    /// peers to keep a client connection to at all times
+    pub preferred_peers: Vec<TrustedPeer>,
+
+    /// REST interfaces to download the blocks from if the trusted peers
+    /// cannot be reached
+    pub block_gateways: Vec<Url>,
+
     /// the protocol to utilise for the p2p network
     pub protocol: Protocol,
 
@@ -66,21 +129,192 @@ pub struct Configuration {
     /// Maximum allowed number of client connections.
     pub max_client_connections: usize,
 
+    /// Minimum number of client connections to actively maintain.
+    pub min_client_connections: Option<usize>,
+
+    /// Number of connection slots reserved for the peers the blockchain
+    /// is synchronized from.
+    pub reserved_sync_connections: usize,
+
+    /// How the peers to disconnect are picked.
+    pub eviction_policy: EvictionPolicy,
+
+    /// Interval between the checks of the minimum number of client connections.
+    pub peer_recruitment_interval: Duration,
+
+    /// Maximum allowed number of inbound connections from a single IP address.
+    pub max_inbound_connections_per_ip: Option<usize>,
+
+    /// Time allowed to inbound connections to complete the handshake.
+    pub handshake_timeout: Duration,
+
+    /// Maximum number of inbound connections in the handshake phase.
+    pub max_half_open_connections: usize,
+
+    /// Maximum number of outbound connections being dialled at a time.
+    pub max_concurrent_dials: usize,
+
+    /// Time after which inactive inbound connections are closed.
+    pub idle_connection_timeout: Duration,
+
+    /// Delay before dialling again the peers that could not be connected to.
+    pub reconnect_backoff: ReconnectBackoff,
+
+    /// Keepalive on the idle client connections.
+    pub keepalive: Keepalive,
+
+    /// Limit on the rate, in bytes per second, of the blocks streamed to peers.
+    pub outbound_blocks_rate_limit: Option<u64>,
+
+    /// Limit on the rate, in bytes per second, of the blocks uploaded by peers.
+    pub inbound_blocks_rate_limit: Option<u64>,
+
+    /// Limit on the rate of the fragments received from each peer.
+    pub fragment_rate_limit: Option<FragmentRateLimit>,
+
+    /// Depth of the chain, back from the tip, served to the peers other
+    /// than the trusted peers, if limited.
+    pub max_untrusted_pull_depth: Option<u64>,
+
     /// the default value for the timeout for inactive connection
     pub timeout: Duration,
 
     pub policy: PolicyConfig,
 
-    /// Whether to allow non-public IP addresses in gossip
-    pub allow_private_addresses: bool,
+    /// Key to sign the node's gossip with.
+    /// The node identifier is derived from its public key.
+    pub gossip_key: SecretKey<Ed25519>,
+
+    /// Whether to accept the gossip entries not signed by the node they describe.
+    pub accept_unsigned_gossip: bool,
+
+    /// End of the window during which the items in the previous wire
+    /// format are accepted, if limited.
+    pub legacy_wire_until: Option<SystemTime>,
+
+    /// Kinds of non-public IP addresses allowed in gossip
+    pub address_filter: AddressFilter,
+
+    /// P2P topics the node subscribes to and relays
+    pub topics: Topics,
 
     pub max_unreachable_nodes_to_connect_per_event: Option<usize>,
 
     pub gossip_interval: Duration,
 
+    /// Bounds of the gossip interval, if it adapts to the topology.
+    pub adaptive_gossip: Option<AdaptiveGossip>,
+
     pub topology_force_reset_interval: Option<Duration>,
 
+    /// Interval between the batches of received gossip applied to the topology.
+    pub topology_update_interval: Duration,
+
     pub max_bootstrap_attempts: Option<usize>,
+
+    /// Number of trusted peers the headers are pulled from when catching up.
+    pub sync_peers: usize,
+
+    /// Known good blocks the chain is checked against when catching up,
+    /// by increasing chain length.
+    pub trusted_checkpoints: Vec<TrustedCheckpoint>,
+
+    /// Source of the random choices of the network tasks.
+    pub rng: RngProvider,
+
+    /// Time allowed for the block streams in flight to complete on shutdown.
+    pub drain_timeout: Duration,
+}
+
+/// Kinds of non-public addresses accepted from gossip. Gossip entries
+/// advertising any other non-public address are dropped before they
+/// reach the topology.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct AddressFilter {
+    pub allow_loopback: bool,
+    pub allow_private: bool,
+    pub allow_link_local: bool,
+}
+
+impl AddressFilter {
+    pub fn allow_all() -> Self {
+        AddressFilter {
+            allow_loopback: true,
+            allow_private: true,
+            allow_link_local: true,
+        }
+    }
+
+    pub fn allows(&self, scope: AddressScope) -> bool {
+        match scope {
+            AddressScope::Global => true,
+            AddressScope::Loopback => self.allow_loopback,
+            AddressScope::Private => self.allow_private,
+            AddressScope::LinkLocal => self.allow_link_local,
+        }
+    }
+}
+
+/// P2P topics the node subscribes to. The node advertises its interest
+/// only in these topics and neither accepts nor propagates the items
+/// of the other topics.
+#[derive(Clone, Copy, Debug)]
+pub struct Topics {
+    pub messages: bool,
+    pub blocks: bool,
+}
+
+impl Topics {
+    pub fn all() -> Self {
+        Topics {
+            messages: true,
+            blocks: true,
+        }
+    }
+
+    pub fn contains(&self, topic: poldercast::Topic) -> bool {
+        if topic == topic::MESSAGES {
+            self.messages
+        } else if topic == topic::BLOCKS {
+            self.blocks
+        } else {
+            false
+        }
+    }
+}
+
+/// Bounds of the delay before dialling again a peer after failures to
+/// connect to it. The delay doubles with every consecutive failure.
+#[derive(Clone, Copy, Debug)]
+pub struct ReconnectBackoff {
+    pub initial_delay: Duration,
+    pub max_delay: Duration,
+}
+
+/// Keepalive on the idle client connections: a request is sent to the
+/// peer after `interval` without activity, and the connection is closed
+/// after `timeout` without activity.
+#[derive(Clone, Copy, Debug)]
+pub struct Keepalive {
+    pub interval: Duration,
+    pub timeout: Duration,
+}
+
+/// Bounds within which the gossip interval is adapted.
+#[derive(Clone, Debug)]
+pub struct AdaptiveGossip {
+    pub min_interval: Duration,
+    pub max_interval: Duration,
+}
+
+/// Comparison of the chain tip with the ones of reference nodes.
+#[derive(Clone, Debug)]
+pub struct DivergenceCheck {
+    pub reference_nodes: Vec<SocketAddr>,
+    pub check_interval: Duration,
+    /// number of slots the tip may diverge from the reference nodes
+    /// before an alert is raised
+    pub max_divergence_slots: u64,
 }
 
 #[derive(Clone)]
@@ -98,6 +332,22 @@ impl From<super::config::TrustedPeer> for TrustedPeer {
     }
 }
 
+/// A block of the chain known to be good.
+#[derive(Clone, Copy, Debug)]
+pub struct TrustedCheckpoint {
+    pub chain_length: u32,
+    pub hash: HeaderHash,
+}
+
+impl From<super::config::TrustedCheckpoint> for TrustedCheckpoint {
+    fn from(checkpoint: super::config::TrustedCheckpoint) -> Self {
+        TrustedCheckpoint {
+            chain_length: checkpoint.chain_length,
+            hash: checkpoint.hash.into_hash(),
+        }
+    }
+}
+
 impl Peer {
     pub fn new(connection: SocketAddr, protocol: Protocol) -> Self {
         Peer {
@@ -130,9 +380,21 @@ impl Configuration {
         (*self.profile.id()).into()
     }
 
+    /// Whether the items decoded from the previous wire format are
+    /// currently accepted.
+    pub fn accepts_legacy_wire(&self) -> bool {
+        match self.legacy_wire_until {
+            None => true,
+            Some(until) => SystemTime::now() < until,
+        }
+    }
+
     /// Returns the listener configuration, if the options defining it
-    /// were set.
+    /// were set and the node is not private.
     pub fn listen(&self) -> Option<Listen> {
+        if self.private {
+            return None;
+        }
         self.listen_address
             .or(self
                 .profile
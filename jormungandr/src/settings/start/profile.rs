@@ -0,0 +1,95 @@
+use crate::settings::start::config::{
+    Config, Explorer, InterestLevel, P2pConfig, Rest, TopicsOfInterest,
+};
+use jormungandr_lib::interfaces::Mempool;
+use serde::{Deserialize, Serialize};
+use std::net::{Ipv4Addr, SocketAddr};
+
+/// the address the REST interface listens to when enabled by a profile
+const PROFILE_REST_LISTEN: (Ipv4Addr, u16) = (Ipv4Addr::LOCALHOST, 8443);
+
+/// Presets of the settings for the common roles of a node.
+///
+/// A profile only provides the defaults: every setting given explicitly
+/// in the configuration file takes precedence over the one of the profile.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Profile {
+    /// a stake pool or a BFT leader: high interest in both the fragments
+    /// and the blocks, and a large mempool
+    Producer,
+    /// a public node distributing the blocks to many peers
+    Relay,
+    /// a node running the explorer, with the REST interface enabled
+    /// on the local host
+    Explorer,
+    /// a node keeping its resource usage low
+    Minimal,
+}
+
+impl Profile {
+    /// fill in the settings left out of the configuration
+    pub fn apply(self, config: &mut Config) {
+        if config.mempool.is_none() {
+            config.mempool = Some(self.mempool());
+        }
+        if config.explorer.is_none() {
+            config.explorer = Some(Explorer {
+                enabled: self == Profile::Explorer,
+            });
+        }
+        if config.rest.is_none() && self == Profile::Explorer {
+            config.rest = Some(Rest {
+                listen: SocketAddr::from(PROFILE_REST_LISTEN),
+                tls: None,
+                cors: None,
+                admin_token_file: None,
+            });
+        }
+        self.apply_p2p(&mut config.p2p);
+    }
+
+    fn apply_p2p(self, p2p: &mut P2pConfig) {
+        use poldercast::InterestLevel::{High, Low, Normal};
+
+        let (messages, blocks) = match self {
+            Profile::Producer => (High, High),
+            Profile::Relay => (Normal, High),
+            Profile::Explorer => (Low, Normal),
+            Profile::Minimal => (Low, Low),
+        };
+        if p2p.topics_of_interest.is_none() {
+            p2p.topics_of_interest = Some(TopicsOfInterest {
+                messages: InterestLevel(messages),
+                blocks: InterestLevel(blocks),
+            });
+        }
+
+        let (max_connections, max_client_connections, min_client_connections) = match self {
+            Profile::Producer => (256, 16, Some(4)),
+            Profile::Relay => (1024, 16, Some(8)),
+            Profile::Explorer => (256, 8, None),
+            Profile::Minimal => (32, 4, None),
+        };
+        p2p.max_connections.get_or_insert(max_connections);
+        p2p.max_client_connections
+            .get_or_insert(max_client_connections);
+        if p2p.min_client_connections.is_none() {
+            p2p.min_client_connections = min_client_connections;
+        }
+    }
+
+    fn mempool(self) -> Mempool {
+        let (pool_max_entries, log_max_entries) = match self {
+            Profile::Producer => (50_000, 100_000),
+            Profile::Relay => (10_000, 100_000),
+            Profile::Explorer => (10_000, 100_000),
+            Profile::Minimal => (1_000, 10_000),
+        };
+        Mempool {
+            pool_max_entries: pool_max_entries.into(),
+            log_max_entries: log_max_entries.into(),
+            ..Mempool::default()
+        }
+    }
+}
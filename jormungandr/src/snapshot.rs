@@ -0,0 +1,63 @@
+//! Periodic snapshot of the blockchain, served over the REST interface
+//! to the nodes bootstrapping from this one as a block gateway.
+//!
+//! The snapshot is an archive of the main branch in the format of
+//! `--export-chain`, replaced as a whole at every interval. It is written
+//! on a thread of its own, as reading the whole chain from the storage
+//! and writing the archive takes a while.
+
+use crate::{
+    blockcfg::HeaderHash,
+    blockchain::{Storage, Tip},
+    settings::start::ChainSnapshot,
+    start_up::chain_archive,
+    utils::task::TokioServiceInfo,
+};
+use chain_storage::error::Error as StorageError;
+use futures::{future, prelude::*};
+use std::time::Instant;
+use tokio::timer::Interval;
+use tokio_threadpool::Builder;
+
+pub fn write_periodically(
+    service_info: TokioServiceInfo,
+    storage: Storage,
+    blockchain_tip: Tip,
+    block0_hash: HeaderHash,
+    snapshot: ChainSnapshot,
+) -> impl Future<Item = (), Error = ()> {
+    let logger = service_info.logger().clone();
+    let err_logger = logger.clone();
+    let pool = Builder::new()
+        .pool_size(1)
+        .name_prefix("chain-snapshot")
+        .build();
+
+    // the first snapshot is written right away, for a node that has
+    // just been set up as a gateway to have one to serve
+    Interval::new(Instant::now(), snapshot.interval)
+        .map_err(move |e| error!(err_logger, "timer error: {}", e))
+        .and_then(move |_| blockchain_tip.get_ref())
+        .for_each(move |tip| {
+            let storage = storage.clone();
+            let path = snapshot.path.clone();
+            let logger = logger.clone();
+            pool.spawn_handle(future::lazy(move || {
+                let written = storage
+                    .get(block0_hash)
+                    .wait()
+                    .and_then(|block0| block0.ok_or(StorageError::BlockNotFound))
+                    .map_err(Into::into)
+                    .and_then(|block0| {
+                        chain_archive::write_archive(&storage, &block0, Some(tip.hash()), &path)
+                    });
+                match written {
+                    Ok(count) => {
+                        info!(logger, "chain snapshot written"; "blocks" => count, "tip" => %tip.hash())
+                    }
+                    Err(e) => warn!(logger, "failed to write the chain snapshot"; "reason" => %e),
+                }
+                Ok::<_, ()>(())
+            }))
+        })
+}
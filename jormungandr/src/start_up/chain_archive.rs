@@ -0,0 +1,254 @@
+//! export of the main branch of the stored blockchain to an archive file,
+//! and import of such an archive into the storage of another node
+//!
+//! The archive starts with a magic number and holds the blocks from the
+//! genesis block to the tip, each serialized and prefixed by its length
+//! as a big-endian 32-bit integer. The imported blocks are applied on
+//! the stored blockchain as the blocks received from the network, and the
+//! tip is only moved once all of them are valid.
+
+use super::{stored_block0_hash, Error, ErrorKind, NodeStorage};
+use crate::{
+    blockcfg::{Block, HeaderHash},
+    blockchain::{
+        BlockEventLog, Blockchain, Branch, ErrorKind as BlockchainError, LoadProgress,
+        PreCheckedHeader, Storage, MAIN_BRANCH_TAG,
+    },
+    settings::start::BlockCache,
+};
+use chain_core::property::{Deserialize as _, Serialize as _};
+use chain_storage::store::BlockStore;
+use slog::Logger;
+use std::{
+    convert::TryFrom,
+    fs::File,
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::{Path, PathBuf},
+};
+use tokio::prelude::*;
+
+/// identifies the archive files, ends with the version of the format
+const MAGIC: &[u8; 8] = b"JORMCHA\x01";
+
+/// bound of the length of a block read from an archive, well above the
+/// size of the blocks of the blockchain, so a corrupted length is not
+/// allocated
+const MAX_BLOCK_SIZE: u32 = 16 * 1024 * 1024;
+
+/// write the blocks of the main branch, from `block0` to the tip,
+/// to the archive file at `path`. Returns the number of blocks written.
+pub fn export(
+    storage: &NodeStorage,
+    block0: &Block,
+    path: &Path,
+    logger: &Logger,
+) -> Result<u64, Error> {
+    check_block0(storage, block0.header.hash())?;
+
+    let head = storage.get_tag(MAIN_BRANCH_TAG)?;
+    let count = write_archive(&Storage::new(storage.clone()), block0, head, path)?;
+
+    info!(logger, "exported the blockchain"; "blocks" => count, "archive" => ?path);
+    Ok(count)
+}
+
+/// write the blocks from `block0` to `tip` to the archive file at `path`.
+/// The archive is written to a scratch file renamed once complete, so
+/// an archive being read, e.g. a snapshot served to a bootstrapping
+/// node, is never overwritten. Returns the number of blocks written.
+pub fn write_archive(
+    storage: &Storage,
+    block0: &Block,
+    tip: Option<HeaderHash>,
+    path: &Path,
+) -> Result<u64, Error> {
+    let mut scratch = path.as_os_str().to_owned();
+    scratch.push(".new");
+    let scratch = PathBuf::from(scratch);
+
+    let file = File::create(&scratch).map_err(archive_io_error)?;
+    let mut writer = BufWriter::new(file);
+    writer.write_all(MAGIC).map_err(archive_io_error)?;
+    write_block(&mut writer, block0)?;
+    let mut count = 1;
+
+    if let Some(tip) = tip {
+        let blocks = storage.stream_from_to(block0.header.hash(), tip).wait()?;
+        for block in blocks.wait() {
+            write_block(&mut writer, &block?)?;
+            count += 1;
+        }
+    }
+    writer.flush().map_err(archive_io_error)?;
+    std::mem::drop(writer);
+    std::fs::rename(&scratch, path).map_err(archive_io_error)?;
+    Ok(count)
+}
+
+/// apply and store the blocks of the archive file at `path`, which must
+/// start with `block0`, and move the tip of the main branch to the last of
+/// them if the branch is longer than the one in the storage. The tip is
+/// left as it is if any of the blocks is not valid.
+/// Returns the number of blocks added to the storage.
+pub fn import(
+    storage: &mut NodeStorage,
+    block0: &Block,
+    block_cache: &BlockCache,
+    path: &Path,
+    logger: &Logger,
+) -> Result<u64, Error> {
+    let block0_hash = block0.header.hash();
+    check_block0(storage, block0_hash)?;
+
+    let file = File::open(path).map_err(archive_io_error)?;
+    let mut reader = BufReader::new(file);
+    let mut magic = [0; 8];
+    reader.read_exact(&mut magic).map_err(archive_parse_error)?;
+    if &magic != MAGIC {
+        return Err(archive_parse_error(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "not a blockchain archive",
+        )));
+    }
+
+    match read_block(&mut reader)? {
+        Some(block) if block.header.hash() == block0_hash => {}
+        Some(block) => {
+            return Err(Error::ArchiveBlock0Mismatch {
+                expected: block0_hash,
+                archived: block.header.hash(),
+            })
+        }
+        None => return Ok(0),
+    }
+
+    // the blocks are applied on the states of the stored blockchain,
+    // loaded as on start
+    let blockchain = Blockchain::new(
+        block0_hash,
+        storage.clone(),
+        BlockEventLog::memory(),
+        block_cache.ttl,
+        block_cache.max_entries,
+        block_cache.pinned_epochs,
+        None,
+    );
+    let _: Branch = match blockchain.load_from_block0(block0.clone()).wait() {
+        Err(error) => match error.kind() {
+            BlockchainError::Block0AlreadyInStorage => blockchain
+                .load_from_storage(block0.clone(), LoadProgress::default(), logger)
+                .wait(),
+            _ => Err(error),
+        },
+        Ok(branch) => Ok(branch),
+    }?;
+
+    let mut last = block0_hash;
+    let mut count = 0;
+    while let Some(block) = read_block(&mut reader)? {
+        last = block.header.hash();
+        if apply_block(&blockchain, storage, block)? {
+            count += 1;
+        }
+    }
+
+    let longer = match storage.get_tag(MAIN_BRANCH_TAG)? {
+        None => true,
+        Some(head) => storage.get_block_info(&last)?.depth > storage.get_block_info(&head)?.depth,
+    };
+    if longer {
+        storage.put_tag(MAIN_BRANCH_TAG, &last)?;
+    }
+
+    info!(logger, "imported the blockchain"; "new_blocks" => count, "archive" => ?path);
+    Ok(count)
+}
+
+// Checks the block and stores it, returns whether it was not stored yet.
+fn apply_block(
+    blockchain: &Blockchain,
+    storage: &NodeStorage,
+    block: Block,
+) -> Result<bool, Error> {
+    let hash = block.header.hash();
+    let invalid = |source| Error::ArchiveInvalidBlock {
+        block: hash,
+        source,
+    };
+    match blockchain
+        .pre_check_header(block.header.clone(), false)
+        .wait()
+        .map_err(invalid)?
+    {
+        PreCheckedHeader::AlreadyPresent { .. } => Ok(false),
+        // a block stored before, off the loaded branch
+        PreCheckedHeader::MissingParent { .. } if storage.block_exists(&hash)? => Ok(false),
+        PreCheckedHeader::MissingParent { .. } => Err(Error::ArchiveMissingParent { block: hash }),
+        PreCheckedHeader::HeaderWithCache { header, parent_ref } => {
+            let post_checked = blockchain
+                .post_check_header(header, parent_ref)
+                .wait()
+                .map_err(invalid)?;
+            let applied = blockchain
+                .apply_and_store_block(post_checked, block)
+                .wait()
+                .map_err(invalid)?;
+            Ok(applied.new_ref().is_some())
+        }
+    }
+}
+
+fn check_block0(storage: &NodeStorage, block0_hash: HeaderHash) -> Result<(), Error> {
+    match stored_block0_hash(storage)? {
+        Some(stored) if stored != block0_hash => Err(Error::StorageBlock0Mismatch {
+            expected: block0_hash,
+            stored,
+        }),
+        _ => Ok(()),
+    }
+}
+
+fn write_block<W: Write>(writer: &mut W, block: &Block) -> Result<(), Error> {
+    let bytes = block.serialize_as_vec().map_err(archive_io_error)?;
+    let len = u32::try_from(bytes.len()).expect("block size overflows the archive format");
+    writer
+        .write_all(&len.to_be_bytes())
+        .and_then(|()| writer.write_all(&bytes))
+        .map_err(archive_io_error)
+}
+
+// `None` at the end of the archive
+fn read_block<R: Read>(reader: &mut R) -> Result<Option<Block>, Error> {
+    let mut len = [0; 4];
+    match reader.read_exact(&mut len) {
+        Ok(()) => {}
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(archive_io_error(e)),
+    }
+    let len = u32::from_be_bytes(len);
+    if len > MAX_BLOCK_SIZE {
+        return Err(archive_parse_error(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("block of {} bytes exceeds the limit", len),
+        )));
+    }
+    let mut bytes = vec![0; len as usize];
+    reader.read_exact(&mut bytes).map_err(archive_parse_error)?;
+    Block::deserialize(bytes.as_slice())
+        .map(Some)
+        .map_err(archive_parse_error)
+}
+
+fn archive_io_error(source: io::Error) -> Error {
+    Error::IO {
+        source,
+        reason: ErrorKind::ChainArchive,
+    }
+}
+
+fn archive_parse_error(source: io::Error) -> Error {
+    Error::ParseError {
+        source,
+        reason: ErrorKind::ChainArchive,
+    }
+}
@@ -3,6 +3,7 @@ use crate::{
     diagnostic::DiagnosticError,
     explorer, network, secure,
     settings::{self, logging},
+    start_up::{rollback::RollbackTarget, verify_storage},
 };
 use chain_storage::error::Error as StorageError;
 use std::io;
@@ -14,6 +15,12 @@ pub enum ErrorKind {
     SQLite,
     #[error("Block0")]
     Block0,
+    #[error("leadership log archive")]
+    LeadershipLogs,
+    #[error("storage archive")]
+    StorageArchive,
+    #[error("blockchain archive file")]
+    ChainArchive,
 }
 
 #[derive(Debug, Error)]
@@ -46,6 +53,20 @@ pub enum Error {
     NetworkBootstrapError(#[from] network::BootstrapError),
     #[error("Error while loading the node's secrets.")]
     NodeSecrets(#[from] secure::NodeSecretFromFileError),
+    #[error(
+        "The genesis block received from the network has hash {received}, expected {expected}"
+    )]
+    Block0HashMismatch {
+        expected: blockcfg::HeaderHash,
+        received: blockcfg::HeaderHash,
+    },
+    #[error(
+        "The storage holds the blockchain of genesis block {stored}, not of the configured genesis block {expected}"
+    )]
+    StorageBlock0Mismatch {
+        expected: blockcfg::HeaderHash,
+        stored: blockcfg::HeaderHash,
+    },
     #[error("Block 0 is set to start in the future")]
     Block0InFuture,
     #[error("Error while loading the explorer from storage")]
@@ -54,9 +75,93 @@ pub enum Error {
     ServiceTerminatedWithError,
     #[error("Unable to get system limits: {0}")]
     DiagnosticError(#[from] DiagnosticError),
+    #[error("The self-test failed")]
+    SelfTestFailed,
+    #[error(
+        "The archive holds the blockchain of genesis block {archived}, not of the configured genesis block {expected}"
+    )]
+    ArchiveBlock0Mismatch {
+        expected: blockcfg::HeaderHash,
+        archived: blockcfg::HeaderHash,
+    },
+    #[error("The parent of block {block} is neither in the archive nor in the storage")]
+    ArchiveMissingParent { block: blockcfg::HeaderHash },
+    #[error("Block {block} of the archive is not valid")]
+    ArchiveInvalidBlock {
+        block: blockcfg::HeaderHash,
+        #[source]
+        source: blockchain::Error,
+    },
+    #[error("The stored blockchain is corrupted at block {block}: {problem}")]
+    StorageCorrupted {
+        block: blockcfg::HeaderHash,
+        problem: verify_storage::Problem,
+    },
+    #[error("Cannot read or migrate the schema version of the storage")]
+    StorageSchema {
+        #[source]
+        source: rusqlite::Error,
+    },
+    #[error("Cannot load the block events from the storage")]
+    BlockEventLog {
+        #[source]
+        source: rusqlite::Error,
+    },
+    #[error(
+        "The storage has version {found}, this version of the node supports up to version {supported}"
+    )]
+    StorageVersionTooNew { found: u32, supported: u32 },
+    #[error(
+        "The storage has version {found} and needs to be migrated to version {current}, but migrations are disabled"
+    )]
+    StorageMigrationRequired { found: u32, current: u32 },
+    #[error("The node is read-only and there is no block storage at {path}")]
+    ReadOnlyStorageMissing { path: std::path::PathBuf },
+    #[error("The block {target} to roll back to is not on the main branch of the storage")]
+    RollbackTargetNotFound { target: RollbackTarget },
+    #[error(
+        "`block_cache.max_entries` is {max_entries}, below the {slots_per_epoch} slots of an epoch"
+    )]
+    BlockCacheTooSmall {
+        max_entries: usize,
+        slots_per_epoch: u32,
+    },
+}
+
+/// Category of a start-up failure, telling a supervisor whether
+/// starting the node again as it is may succeed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorCategory {
+    /// the configuration or the command line must be fixed
+    Configuration,
+    /// the block storage is unusable or corrupted
+    Storage,
+    /// the genesis block is invalid or does not match the storage
+    Genesis,
+    /// the peers could not be reached, trying again later may succeed
+    Network,
+    /// the host environment does not meet the needs of the node
+    Environment,
+    /// a service of the running node has failed
+    Runtime,
+}
+
+impl ErrorCategory {
+    /// the exit code of the process failing with an error of this category
+    pub fn exit_code(self) -> i32 {
+        match self {
+            ErrorCategory::Configuration => 2,
+            ErrorCategory::Storage => 3,
+            ErrorCategory::Genesis => 4,
+            ErrorCategory::Network => 5,
+            ErrorCategory::Environment => 6,
+            ErrorCategory::Runtime => 7,
+        }
+    }
 }
 
 impl Error {
+    /// the stable code identifying the error
     #[inline]
     pub fn code(&self) -> i32 {
         match self {
@@ -68,12 +173,166 @@ impl Error {
             Error::Blockchain { .. } => 6,
             Error::Block0 { .. } => 7,
             Error::Block0InFuture => 7,
+            Error::Block0HashMismatch { .. } => 15,
             Error::NodeSecrets { .. } => 8,
             Error::FetchBlock0 { .. } => 9,
             Error::NetworkBootstrapError { .. } => 10,
             Error::ExplorerBootstrapError { .. } => 11,
             Error::ServiceTerminatedWithError => 12,
             Error::DiagnosticError { .. } => 13,
+            Error::SelfTestFailed => 14,
+            Error::StorageBlock0Mismatch { .. } => 16,
+            Error::ArchiveBlock0Mismatch { .. } => 17,
+            Error::ArchiveMissingParent { .. } => 18,
+            Error::StorageCorrupted { .. } => 19,
+            Error::StorageVersionTooNew { .. } => 20,
+            Error::StorageMigrationRequired { .. } => 21,
+            Error::ReadOnlyStorageMissing { .. } => 22,
+            Error::RollbackTargetNotFound { .. } => 23,
+            Error::BlockCacheTooSmall { .. } => 24,
+            Error::StorageSchema { .. } => 25,
+            Error::ArchiveInvalidBlock { .. } => 26,
+            Error::BlockEventLog { .. } => 27,
+        }
+    }
+
+    /// the category of the error, deciding the exit code of the process
+    pub fn category(&self) -> ErrorCategory {
+        match self {
+            Error::LoggingInitializationError { .. } => ErrorCategory::Configuration,
+            Error::ConfigurationError { .. } => ErrorCategory::Configuration,
+            Error::IO { reason, .. } | Error::ParseError { reason, .. } => match reason {
+                ErrorKind::SQLite | ErrorKind::LeadershipLogs | ErrorKind::StorageArchive => {
+                    ErrorCategory::Storage
+                }
+                ErrorKind::Block0 => ErrorCategory::Genesis,
+                ErrorKind::ChainArchive => ErrorCategory::Configuration,
+            },
+            Error::StorageError { .. } => ErrorCategory::Storage,
+            Error::Blockchain { .. } => ErrorCategory::Storage,
+            Error::Block0 { .. } => ErrorCategory::Genesis,
+            Error::Block0InFuture => ErrorCategory::Genesis,
+            Error::Block0HashMismatch { .. } => ErrorCategory::Genesis,
+            Error::StorageBlock0Mismatch { .. } => ErrorCategory::Genesis,
+            Error::NodeSecrets { .. } => ErrorCategory::Configuration,
+            Error::FetchBlock0 { .. } => ErrorCategory::Network,
+            Error::NetworkBootstrapError { .. } => ErrorCategory::Network,
+            Error::ExplorerBootstrapError { .. } => ErrorCategory::Storage,
+            Error::ServiceTerminatedWithError => ErrorCategory::Runtime,
+            Error::DiagnosticError { .. } => ErrorCategory::Environment,
+            Error::SelfTestFailed => ErrorCategory::Environment,
+            Error::ArchiveBlock0Mismatch { .. } => ErrorCategory::Genesis,
+            Error::ArchiveMissingParent { .. } => ErrorCategory::Configuration,
+            Error::ArchiveInvalidBlock { .. } => ErrorCategory::Configuration,
+            Error::StorageCorrupted { .. } => ErrorCategory::Storage,
+            Error::StorageSchema { .. } => ErrorCategory::Storage,
+            Error::BlockEventLog { .. } => ErrorCategory::Storage,
+            Error::StorageVersionTooNew { .. } => ErrorCategory::Storage,
+            Error::StorageMigrationRequired { .. } => ErrorCategory::Configuration,
+            Error::ReadOnlyStorageMissing { .. } => ErrorCategory::Configuration,
+            Error::RollbackTargetNotFound { .. } => ErrorCategory::Configuration,
+            Error::BlockCacheTooSmall { .. } => ErrorCategory::Configuration,
+        }
+    }
+
+    /// what the operator may do to fix the error
+    pub fn remediation(&self) -> &'static str {
+        match self {
+            Error::LoggingInitializationError { .. } => {
+                "check the `log` section of the node configuration and the \
+                 `--log-*` command line options"
+            }
+            Error::ConfigurationError { .. } => {
+                "check the node configuration file and the command line options"
+            }
+            Error::IO { reason, .. } | Error::ParseError { reason, .. } => match reason {
+                ErrorKind::SQLite | ErrorKind::LeadershipLogs | ErrorKind::StorageArchive => {
+                    "check that the storage directory exists and is writable \
+                     by the node, and that the disk is not full"
+                }
+                ErrorKind::Block0 => {
+                    "check that `--genesis-block` points to a genesis block file \
+                     encoded with `jcli genesis encode`"
+                }
+                ErrorKind::ChainArchive => {
+                    "check the path of the archive file, and that it has been \
+                     written by `--export-chain`"
+                }
+            },
+            Error::StorageError { .. } | Error::Blockchain { .. } => {
+                "the storage may be corrupted or belong to another blockchain; \
+                 move the storage directory away to start from scratch"
+            }
+            Error::Block0 { .. } => {
+                "check that the genesis block is the one of the blockchain to join"
+            }
+            Error::Block0HashMismatch { .. } => {
+                "check `--genesis-block-hash`, and that the trusted peers \
+                 belong to the blockchain to join"
+            }
+            Error::StorageBlock0Mismatch { .. } => {
+                "check the genesis block of the node, or start with \
+                 `--reinitialize-storage` to archive the stored blockchain"
+            }
+            Error::Block0InFuture => {
+                "check the system clock, or wait for the start time of the blockchain"
+            }
+            Error::NodeSecrets { .. } => {
+                "check the files given with `--secret` and the passphrase of the encrypted ones"
+            }
+            Error::FetchBlock0 { .. } | Error::NetworkBootstrapError { .. } => {
+                "check the network connectivity and the trusted peers, \
+                 or try again later"
+            }
+            Error::ExplorerBootstrapError { .. } => {
+                "the storage may be corrupted; move the storage directory away \
+                 to start from scratch"
+            }
+            Error::ServiceTerminatedWithError => {
+                "check the log for the service that failed, \
+                 e.g. the P2P listen address may be in use"
+            }
+            Error::DiagnosticError { .. } => {
+                "check the permissions of the node to query the system limits"
+            }
+            Error::SelfTestFailed => "fix the checks reported as failed",
+            Error::ArchiveBlock0Mismatch { .. } => {
+                "import the archive exported by a node of the same blockchain"
+            }
+            Error::ArchiveMissingParent { .. } => {
+                "the archive is corrupted; export the blockchain again"
+            }
+            Error::ArchiveInvalidBlock { .. } => {
+                "export the blockchain again from a node following the consensus"
+            }
+            Error::StorageCorrupted { .. } => {
+                "start with `--truncate-corrupted-storage` to move the tip back \
+                 below the corrupted block and download the rest again"
+            }
+            Error::StorageSchema { .. } | Error::BlockEventLog { .. } => {
+                "check that the storage directory is writable by the node; \
+                 the block storage may be corrupted, move the storage directory \
+                 away to start from scratch"
+            }
+            Error::StorageVersionTooNew { .. } => {
+                "upgrade the node to the version that wrote the storage, or move \
+                 the storage directory away to start from scratch"
+            }
+            Error::StorageMigrationRequired { .. } => {
+                "back up the storage directory, then start without `--no-migrate`"
+            }
+            Error::ReadOnlyStorageMissing { .. } => {
+                "check `--storage`, it must point to the storage directory of \
+                 another node, e.g. a copy of the one of a producer"
+            }
+            Error::RollbackTargetNotFound { .. } => {
+                "check `--rollback-to`, it must give a block of the main branch \
+                 by its hash or its chain length"
+            }
+            Error::BlockCacheTooSmall { .. } => {
+                "raise `block_cache.max_entries` to at least the number of slots \
+                 of an epoch, or remove it to leave the cache unbounded"
+            }
         }
     }
 }
@@ -0,0 +1,149 @@
+//! versioning of the block storage and migration of the storages
+//! written by previous versions of the node
+//!
+//! The version of the schema of the block storage is recorded in a table
+//! of the SQLite database. A storage of a previous version is brought up
+//! to date by running the migrations from its version in turn, each of
+//! them in a transaction updating the version as well, so an interrupted
+//! upgrade resumes from the last completed migration. A storage written
+//! by a newer version of the node is refused rather than misread.
+
+use super::{Error, BLOCK_STORAGE_FILE};
+use rusqlite::{params, Connection, OpenFlags, Transaction, NO_PARAMS};
+use slog::Logger;
+use std::path::Path;
+
+/// version of the storage written by this version of the node
+pub const CURRENT_VERSION: u32 = 2;
+
+struct Migration {
+    /// the version the migration upgrades from, to the next one
+    from: u32,
+    description: &'static str,
+    run: fn(&Transaction) -> rusqlite::Result<()>,
+}
+
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        from: 0,
+        description: "create the schema version table",
+        run: create_version_table,
+    },
+    Migration {
+        from: 1,
+        description: "create the block events table",
+        run: create_block_events_table,
+    },
+];
+
+// The storages predating the versioning have the blocks tables
+// of version 1 already.
+fn create_version_table(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "CREATE TABLE schema_version (version INTEGER NOT NULL);
+         INSERT INTO schema_version (version) VALUES (0);",
+    )
+}
+
+// The records of the block event log, one JSON object each, in the
+// order they are added.
+fn create_block_events_table(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "CREATE TABLE block_events (
+             seq INTEGER PRIMARY KEY AUTOINCREMENT,
+             hash BLOB NOT NULL UNIQUE,
+             record TEXT NOT NULL
+         );",
+    )
+}
+
+/// check the version of the storage in `dir` and run the pending
+/// migrations, unless `no_migrate` is set, in which case a storage
+/// needing migrations is refused and the storage is not written to.
+pub fn migrate(dir: &Path, no_migrate: bool, logger: &Logger) -> Result<(), Error> {
+    let path = dir.join(BLOCK_STORAGE_FILE);
+    if !path.exists() {
+        // a new storage gets every table of the current version at once
+        let mut conn = Connection::open(&path).map_err(schema_error)?;
+        let tx = conn.transaction().map_err(schema_error)?;
+        MIGRATIONS
+            .iter()
+            .try_for_each(|migration| (migration.run)(&tx))
+            .and_then(|()| set_version(&tx, CURRENT_VERSION))
+            .and_then(|()| tx.commit())
+            .map_err(schema_error)?;
+        return Ok(());
+    }
+
+    let flags = if no_migrate {
+        OpenFlags::SQLITE_OPEN_READ_ONLY
+    } else {
+        OpenFlags::SQLITE_OPEN_READ_WRITE
+    };
+    let mut conn = Connection::open_with_flags(&path, flags).map_err(schema_error)?;
+    let mut version = read_version(&conn).map_err(schema_error)?;
+
+    if version > CURRENT_VERSION {
+        return Err(Error::StorageVersionTooNew {
+            found: version,
+            supported: CURRENT_VERSION,
+        });
+    }
+    if version == CURRENT_VERSION {
+        return Ok(());
+    }
+    if no_migrate {
+        return Err(Error::StorageMigrationRequired {
+            found: version,
+            current: CURRENT_VERSION,
+        });
+    }
+
+    info!(
+        logger,
+        "migrating the storage";
+        "from_version" => version,
+        "to_version" => CURRENT_VERSION,
+    );
+    for migration in MIGRATIONS.iter().filter(|m| m.from >= version) {
+        info!(
+            logger,
+            "running storage migration";
+            "from_version" => migration.from,
+            "migration" => migration.description,
+        );
+        let tx = conn.transaction().map_err(schema_error)?;
+        (migration.run)(&tx)
+            .and_then(|()| set_version(&tx, migration.from + 1))
+            .and_then(|()| tx.commit())
+            .map_err(schema_error)?;
+        version = migration.from + 1;
+    }
+    debug_assert_eq!(version, CURRENT_VERSION);
+    info!(logger, "storage migrated"; "version" => version);
+    Ok(())
+}
+
+// A storage without the version table predates the versioning.
+fn read_version(conn: &Connection) -> rusqlite::Result<u32> {
+    let tables: u32 = conn.query_row(
+        "SELECT count(*) FROM sqlite_master WHERE type = 'table' AND name = 'schema_version'",
+        NO_PARAMS,
+        |row| row.get(0),
+    )?;
+    if tables == 0 {
+        return Ok(0);
+    }
+    conn.query_row("SELECT version FROM schema_version", NO_PARAMS, |row| {
+        row.get(0)
+    })
+}
+
+fn set_version(tx: &Transaction, version: u32) -> rusqlite::Result<()> {
+    tx.execute("UPDATE schema_version SET version = ?1", params![version])
+        .map(|_| ())
+}
+
+fn schema_error(source: rusqlite::Error) -> Error {
+    Error::StorageSchema { source }
+}
@@ -1,19 +1,39 @@
+pub mod chain_archive;
 mod error;
+pub(crate) mod migration;
+pub mod rollback;
+pub mod self_test;
+pub mod verify_storage;
 
-pub use self::error::{Error, ErrorKind};
+pub use self::error::{Error, ErrorCategory, ErrorKind};
 use crate::{
-    blockcfg::Block,
-    blockchain::{Blockchain, Branch, ErrorKind as BlockchainError, Tip},
+    blockcfg::{Block, Block0DataSource as _, HeaderHash},
+    blockchain::{
+        BlockEventLog, Blockchain, Branch, ErrorKind as BlockchainError, LoadProgress, Tip,
+        MAIN_BRANCH_TAG,
+    },
     network,
-    settings::start::Settings,
+    settings::start::{BlockCache, Settings},
 };
-use chain_storage::store::BlockStore;
+use chain_storage::store::{for_path_to_nth_ancestor, BlockStore};
 use chain_storage_sqlite_old::SQLiteBlockStore;
 use slog::Logger;
-use std::time::Duration;
+use std::{
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
 
 pub type NodeStorage = SQLiteBlockStore<Block>;
 
+/// name of the file, in the storage directory, holding the blocks
+const BLOCK_STORAGE_FILE: &str = "blocks.sqlite";
+
+/// name of the file the chain snapshot of a block gateway is downloaded to
+const DOWNLOADED_CHAIN_SNAPSHOT_FILE: &str = "chain.snapshot.download";
+
+/// suffixes of the files SQLite keeps next to the database file
+const SQLITE_SIDE_FILE_SUFFIXES: [&str; 3] = ["-wal", "-shm", "-journal"];
+
 /// prepare the block storage from the given settings
 ///
 pub fn prepare_storage(setting: &Settings, logger: &Logger) -> Result<NodeStorage, Error> {
@@ -23,18 +43,146 @@ pub fn prepare_storage(setting: &Settings, logger: &Logger) -> Result<NodeStorag
             Ok(SQLiteBlockStore::memory())
         }
         Some(dir) => {
-            std::fs::create_dir_all(dir).map_err(|err| Error::IO {
-                source: err,
-                reason: ErrorKind::SQLite,
-            })?;
             let mut sqlite = dir.clone();
-            sqlite.push("blocks.sqlite");
+            sqlite.push(BLOCK_STORAGE_FILE);
+            if setting.read_only {
+                if !sqlite.exists() {
+                    return Err(Error::ReadOnlyStorageMissing { path: sqlite });
+                }
+            } else {
+                std::fs::create_dir_all(dir).map_err(|err| Error::IO {
+                    source: err,
+                    reason: ErrorKind::SQLite,
+                })?;
+            }
+            // a read-only node leaves the storage as it is
+            migration::migrate(dir, setting.no_migrate || setting.read_only, logger)?;
             info!(logger, "storing blockchain in '{:?}'", sqlite);
             Ok(SQLiteBlockStore::file(sqlite))
         }
     }
 }
 
+/// prepare the block event log from the given settings. The log is
+/// stored in the block storage, which must have been prepared already.
+///
+pub fn prepare_block_event_log(
+    setting: &Settings,
+    logger: &Logger,
+) -> Result<BlockEventLog, Error> {
+    match &setting.storage {
+        None => Ok(BlockEventLog::memory()),
+        Some(dir) => BlockEventLog::open(dir.join(BLOCK_STORAGE_FILE), setting.read_only, logger)
+            .map_err(|source| Error::BlockEventLog { source }),
+    }
+}
+
+/// move the blocks and the block events of a storage holding another
+/// blockchain than the one starting from `block0_hash` to an archive
+/// directory inside the storage directory, and open a fresh storage.
+///
+/// Nothing is moved if the storage is empty, in memory or already holds
+/// the blockchain of `block0_hash`.
+pub fn reinitialize_storage(
+    settings: &Settings,
+    storage: NodeStorage,
+    event_log: BlockEventLog,
+    block0_hash: HeaderHash,
+    logger: &Logger,
+) -> Result<(NodeStorage, BlockEventLog), Error> {
+    let dir = match &settings.storage {
+        None => return Ok((storage, event_log)),
+        Some(dir) => dir,
+    };
+    let stored = match stored_block0_hash(&storage)? {
+        Some(stored) if stored != block0_hash => stored,
+        _ => return Ok((storage, event_log)),
+    };
+
+    // close the files before moving them away
+    std::mem::drop(storage);
+    std::mem::drop(event_log);
+
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let archive = dir.join(format!("archive-{}", timestamp));
+    warn!(
+        logger,
+        "storage holds the blockchain of another genesis block, archiving it";
+        "stored_block0" => %stored,
+        "block0" => %block0_hash,
+        "archive" => ?archive,
+    );
+    archive_storage_files(dir, &archive).map_err(|err| Error::IO {
+        source: err,
+        reason: ErrorKind::StorageArchive,
+    })?;
+
+    let storage = prepare_storage(settings, logger)?;
+    let event_log = prepare_block_event_log(settings, logger)?;
+    Ok((storage, event_log))
+}
+
+fn archive_storage_files(dir: &Path, archive: &Path) -> std::io::Result<()> {
+    std::fs::create_dir_all(archive)?;
+    let files = SQLITE_SIDE_FILE_SUFFIXES
+        .iter()
+        .map(|suffix| format!("{}{}", BLOCK_STORAGE_FILE, suffix))
+        .chain(std::iter::once(BLOCK_STORAGE_FILE.to_owned()));
+    for file in files {
+        let path = dir.join(&file);
+        if path.exists() {
+            std::fs::rename(path, archive.join(&file))?;
+        }
+    }
+    Ok(())
+}
+
+/// store the blocks of the chain snapshot of a block gateway, if the
+/// storage is empty, sparing the node the download of the blocks one by
+/// one. Failures are only logged, the node then bootstraps as usual.
+pub fn import_chain_snapshot(
+    settings: &Settings,
+    storage: &mut NodeStorage,
+    block0: &Block,
+    logger: &Logger,
+) -> Result<(), Error> {
+    if settings.read_only
+        || settings.network.block_gateways.is_empty()
+        || storage.get_tag(MAIN_BRANCH_TAG)?.is_some()
+    {
+        return Ok(());
+    }
+    let path = settings
+        .storage
+        .clone()
+        .unwrap_or_else(std::env::temp_dir)
+        .join(DOWNLOADED_CHAIN_SNAPSHOT_FILE);
+    if !network::fetch_chain_snapshot(&settings.network, &path, logger) {
+        return Ok(());
+    }
+    let imported = chain_archive::import(storage, block0, &settings.block_cache, &path, logger);
+    let _ = std::fs::remove_file(&path);
+    if let Err(e) = imported {
+        warn!(logger, "failed to import the chain snapshot"; "reason" => %e);
+    }
+    Ok(())
+}
+
+/// the hash of the genesis block of the blockchain in the storage, found
+/// from the head of the main branch. `None` if the storage is empty.
+fn stored_block0_hash(storage: &NodeStorage) -> Result<Option<HeaderHash>, Error> {
+    let head = match storage.get_tag(MAIN_BRANCH_TAG)? {
+        None => return Ok(None),
+        Some(head) => head,
+    };
+    let head_info = storage.get_block_info(&head)?;
+    let block0_info = for_path_to_nth_ancestor(storage, &head, head_info.depth - 1, |_| {})?;
+    Ok(Some(block0_info.block_hash))
+}
+
 /// loading the block 0 is not as trivial as it seems,
 /// there are different cases that we may encounter:
 ///
@@ -75,7 +223,20 @@ pub fn prepare_block_0(
                     logger,
                     "retrieving block0 from network with hash {}", block0_id
                 );
-                network::fetch_block(&settings.network, *block0_id, logger).map_err(|e| e.into())
+                let block0 = network::fetch_block(
+                    &settings.network,
+                    &settings.network.trusted_peers,
+                    *block0_id,
+                    logger,
+                )?;
+                let received = block0.header.hash();
+                if received != *block0_id {
+                    return Err(Error::Block0HashMismatch {
+                        expected: *block0_id,
+                        received,
+                    });
+                }
+                Ok(block0)
             }
         }
     }
@@ -84,19 +245,57 @@ pub fn prepare_block_0(
 pub fn load_blockchain(
     block0: Block,
     storage: NodeStorage,
-    block_cache_ttl: Duration,
+    event_log: BlockEventLog,
+    block_cache: &BlockCache,
+    max_rollback_depth: Option<u32>,
+    progress: LoadProgress,
     logger: &Logger,
 ) -> Result<(Blockchain, Tip), Error> {
     use tokio::prelude::*;
 
-    let blockchain = Blockchain::new(block0.header.hash(), storage, block_cache_ttl);
+    // the states of the blocks of the current epoch are needed to apply
+    // the blocks following them
+    if let Some(max_entries) = block_cache.max_entries {
+        let slots_per_epoch = block0.slots_per_epoch()?;
+        if max_entries < slots_per_epoch as usize {
+            return Err(Error::BlockCacheTooSmall {
+                max_entries,
+                slots_per_epoch,
+            });
+        }
+    }
+
+    let block0_hash = block0.header.hash();
+    if let Some(stored) = stored_block0_hash(&storage)? {
+        if stored != block0_hash {
+            return Err(Error::StorageBlock0Mismatch {
+                expected: block0_hash,
+                stored,
+            });
+        }
+    }
+
+    if let Some(head) = storage.get_tag(MAIN_BRANCH_TAG)? {
+        // the genesis block has depth 1 and is not counted
+        progress.start(storage.get_block_info(&head)?.depth - 1);
+    }
+
+    let blockchain = Blockchain::new(
+        block0_hash,
+        storage,
+        event_log,
+        block_cache.ttl,
+        block_cache.max_entries,
+        block_cache.pinned_epochs,
+        max_rollback_depth,
+    );
 
     info!(logger, "Loading from storage");
     let main_branch: Branch = match blockchain.load_from_block0(block0.clone()).wait() {
         Err(error) => match error.kind() {
-            BlockchainError::Block0AlreadyInStorage => {
-                blockchain.load_from_storage(block0, logger).wait()
-            }
+            BlockchainError::Block0AlreadyInStorage => blockchain
+                .load_from_storage(block0, progress, logger)
+                .wait(),
             _ => Err(error),
         },
         Ok(branch) => Ok(branch),
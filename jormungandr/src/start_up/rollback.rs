@@ -0,0 +1,79 @@
+//! rollback of the main branch of the block storage, for the maintenance
+//! of a stopped node
+//!
+//! The tip of the main branch is moved back to one of its blocks, given by
+//! hash or by chain length. The blocks above it stay in the storage, off
+//! the main branch: they are not loaded when the node starts, and the node
+//! applies them again if it receives them from the network.
+
+use super::{Error, NodeStorage};
+use crate::{blockcfg::HeaderHash, blockchain::MAIN_BRANCH_TAG};
+use chain_storage::{error::Error as StorageError, store::BlockStore};
+use slog::Logger;
+use std::{fmt, str::FromStr};
+
+/// The block of the main branch to roll back to.
+#[derive(Debug, Clone, Copy)]
+pub enum RollbackTarget {
+    Hash(HeaderHash),
+    ChainLength(u32),
+}
+
+impl FromStr for RollbackTarget {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Ok(length) = s.parse() {
+            return Ok(RollbackTarget::ChainLength(length));
+        }
+        s.parse()
+            .map(RollbackTarget::Hash)
+            .map_err(|_| format!("`{}` is neither a block hash nor a chain length", s))
+    }
+}
+
+impl fmt::Display for RollbackTarget {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RollbackTarget::Hash(hash) => write!(f, "{}", hash),
+            RollbackTarget::ChainLength(length) => write!(f, "at chain length {}", length),
+        }
+    }
+}
+
+/// move the tip of the main branch back to `target`. Returns the new tip.
+pub fn run(
+    storage: &mut NodeStorage,
+    target: RollbackTarget,
+    logger: &Logger,
+) -> Result<HeaderHash, Error> {
+    let not_found = || Error::RollbackTargetNotFound { target };
+    let head = storage.get_tag(MAIN_BRANCH_TAG)?.ok_or_else(not_found)?;
+
+    let new_tip = match target {
+        RollbackTarget::Hash(hash) => match storage.is_ancestor(&hash, &head) {
+            Ok(Some(_)) => hash,
+            Ok(None) | Err(StorageError::BlockNotFound) => return Err(not_found()),
+            Err(e) => return Err(e.into()),
+        },
+        RollbackTarget::ChainLength(length) => {
+            let (head_block, _) = storage.get_block(&head)?;
+            let head_length = u32::from(head_block.header.chain_length());
+            let distance = head_length.checked_sub(length).ok_or_else(not_found)?;
+            let mut hash = head;
+            for _ in 0..distance {
+                hash = storage.get_block_info(&hash)?.parent_id();
+            }
+            hash
+        }
+    };
+
+    storage.put_tag(MAIN_BRANCH_TAG, &new_tip)?;
+    info!(
+        logger,
+        "rolled the blockchain back";
+        "previous_tip" => %head,
+        "tip" => %new_tip,
+    );
+    Ok(new_tip)
+}
@@ -0,0 +1,209 @@
+//! quick checks of the node's environment, run instead of starting the node
+//!
+//! The self-test exercises what the node needs to start, without joining
+//! the network: the block storage, the secret key files, the ports to
+//! listen on, and the addresses of the trusted peers. The outcome of every
+//! check is reported, so a deployment pipeline can tell what is wrong.
+
+use crate::{
+    blockcfg::{
+        Block, BlockDate, BlockVersion, ChainLength, Contents, ContentsBuilder, HeaderBuilderNew,
+        HeaderHash,
+    },
+    rest,
+    secure::{NodeSecret, Passphrase},
+    settings::start::Settings,
+};
+use chain_storage::store::BlockStore;
+use chain_storage_sqlite_old::SQLiteBlockStore;
+use slog::Logger;
+use std::{
+    fmt,
+    net::{SocketAddr, TcpListener, ToSocketAddrs},
+    path::{Path, PathBuf},
+};
+
+/// name of the scratch storage file created in the storage directory
+const SCRATCH_STORAGE_FILE: &str = "self-test.sqlite";
+
+/// The outcome of the self-test checks.
+pub struct Report {
+    checks: Vec<Check>,
+}
+
+struct Check {
+    name: String,
+    result: Result<(), String>,
+}
+
+impl Report {
+    fn new() -> Self {
+        Report { checks: Vec::new() }
+    }
+
+    fn add(&mut self, name: impl Into<String>, result: Result<(), String>) {
+        self.checks.push(Check {
+            name: name.into(),
+            result,
+        });
+    }
+
+    pub fn passed(&self) -> bool {
+        self.checks.iter().all(|check| check.result.is_ok())
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for check in &self.checks {
+            match &check.result {
+                Ok(()) => writeln!(f, "[ OK ] {}", check.name)?,
+                Err(reason) => writeln!(f, "[FAIL] {}: {}", check.name, reason)?,
+            }
+        }
+        if self.passed() {
+            writeln!(f, "self-test passed")
+        } else {
+            writeln!(f, "self-test failed")
+        }
+    }
+}
+
+/// run the checks against the given settings
+pub fn run(settings: &Settings, logger: &Logger) -> Report {
+    let mut report = Report::new();
+
+    info!(logger, "self-test: checking the block storage");
+    report.add("block storage", check_storage(settings.storage.as_ref()));
+
+    let mut passphrase = Passphrase::new(settings.secret_passphrase.clone());
+    for path in &settings.secrets {
+        report.add(
+            format!("secret key file {}", path.display()),
+            check_secret(path, &mut passphrase),
+        );
+    }
+    if let Some(tls) = settings.rest.as_ref().and_then(|rest| rest.tls.clone()) {
+        report.add(
+            "REST TLS certificate and key",
+            rest::load_rustls_config(tls)
+                .map(|_| ())
+                .map_err(|e| e.to_string()),
+        );
+    }
+
+    info!(logger, "self-test: checking the listen ports");
+    if let Some(listen) = settings.network.listen() {
+        report.add(
+            format!("P2P listen address {}", listen.connection),
+            check_bind(&listen.connection),
+        );
+    }
+    if let Some(rest) = &settings.rest {
+        report.add(
+            format!("REST listen address {}", rest.listen),
+            check_bind(&rest.listen),
+        );
+    }
+
+    info!(logger, "self-test: resolving the trusted peers");
+    for peer in &settings.network.trusted_peers {
+        report.add(
+            format!("trusted peer {}", peer.address),
+            check_peer_address(&peer.address),
+        );
+    }
+
+    report
+}
+
+// A dummy block is written to scratch storage in the storage directory,
+// so the blocks of the node are left untouched, and read back.
+fn check_storage(dir: Option<&PathBuf>) -> Result<(), String> {
+    let scratch = match dir {
+        None => None,
+        Some(dir) => {
+            std::fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+            Some(dir.join(SCRATCH_STORAGE_FILE))
+        }
+    };
+    let mut storage = match &scratch {
+        None => SQLiteBlockStore::memory(),
+        Some(path) => SQLiteBlockStore::file(path.clone()),
+    };
+    let block = dummy_block();
+    let id = block.header.hash();
+    let result = storage
+        .put_block(&block)
+        .and_then(|()| storage.get_block(&id))
+        .map_err(|e| e.to_string())
+        .and_then(|(read, _)| {
+            if read.header.hash() == id {
+                Ok(())
+            } else {
+                Err("the block read back differs from the block written".to_owned())
+            }
+        });
+    drop(storage);
+    if let Some(path) = scratch {
+        let _ = std::fs::remove_file(path);
+    }
+    result
+}
+
+fn dummy_block() -> Block {
+    let contents: Contents = ContentsBuilder::new().into();
+    let header = HeaderBuilderNew::new(BlockVersion::Genesis, &contents)
+        .set_parent(&HeaderHash::zero_hash(), ChainLength::from(0))
+        .set_date(BlockDate::first())
+        .to_unsigned_header()
+        .expect("Valid Header Builder")
+        .generalize();
+    Block { header, contents }
+}
+
+fn check_secret(path: &Path, passphrase: &mut Passphrase) -> Result<(), String> {
+    let secret = NodeSecret::load_from_file(path, passphrase).map_err(|e| e.to_string())?;
+    if secret.bft.is_none() && secret.genesis.is_none() {
+        return Err("no BFT or genesis leader key in the file".to_owned());
+    }
+    Ok(())
+}
+
+fn check_bind(addr: &SocketAddr) -> Result<(), String> {
+    TcpListener::bind(addr)
+        .map(|_| ())
+        .map_err(|e| e.to_string())
+}
+
+// The addresses given with a host name, e.g. `/dns4/example.com/tcp/3000`,
+// are resolved with the resolver of the system.
+fn check_peer_address(address: &poldercast::Address) -> Result<(), String> {
+    if address.to_socketaddr().is_some() {
+        return Ok(());
+    }
+    let address = address.to_string();
+    let components: Vec<&str> = address.split('/').skip(1).collect();
+    let (protocol, host, port) = match components.as_slice() {
+        [protocol, host, "tcp", port] => (*protocol, *host, *port),
+        _ => return Err("not an address of the form /<protocol>/<host>/tcp/<port>".to_owned()),
+    };
+    let port: u16 = port
+        .parse()
+        .map_err(|_| format!("invalid TCP port `{}`", port))?;
+    let in_family: fn(&SocketAddr) -> bool = match protocol {
+        "dns" => |_| true,
+        "dns4" => SocketAddr::is_ipv4,
+        "dns6" => SocketAddr::is_ipv6,
+        _ => return Err(format!("unsupported protocol `{}`", protocol)),
+    };
+    let mut resolved = (host, port).to_socket_addrs().map_err(|e| e.to_string())?;
+    if resolved.any(|addr| in_family(&addr)) {
+        Ok(())
+    } else {
+        Err(format!(
+            "`{}` has no address usable with /{}",
+            host, protocol
+        ))
+    }
+}
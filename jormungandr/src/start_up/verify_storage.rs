@@ -0,0 +1,336 @@
+//! verification of the main branch of the block storage
+//!
+//! The chain is walked from the tip of the main branch down to the genesis
+//! block, checking that every block is stored, that it hashes to the hash
+//! it is stored under, and that it links to the parent recorded for it.
+//! A corrupted storage is thus reported before the node fails to load it,
+//! and the tip can be moved back to the last sound block below the first
+//! corruption, the node downloading the rest again from the network.
+
+use super::{Error, NodeStorage};
+use crate::{
+    blockcfg::{Block, HeaderHash},
+    blockchain::MAIN_BRANCH_TAG,
+};
+use chain_storage::{error::Error as StorageError, store::BlockStore};
+use slog::Logger;
+use std::fmt;
+
+/// What is wrong with a block of the main branch.
+#[derive(Debug, Clone, Copy)]
+pub enum Problem {
+    /// the block is not in the storage
+    Missing,
+    /// the block does not hash to the hash it is stored under
+    HashMismatch,
+    /// the block has another parent than the one recorded for it
+    ParentMismatch,
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Problem::Missing => f.write_str("the block is missing"),
+            Problem::HashMismatch => f.write_str("the block content does not match its hash"),
+            Problem::ParentMismatch => {
+                f.write_str("the block does not link to the parent recorded for it")
+            }
+        }
+    }
+}
+
+/// The first corruption found on the main branch, from the genesis block.
+#[derive(Debug, Clone)]
+pub struct Corruption {
+    pub block: HeaderHash,
+    pub problem: Problem,
+    /// the sound block the tip can be moved back to
+    last_sound: HeaderHash,
+}
+
+/// The outcome of the verification.
+pub struct Report {
+    /// number of blocks checked, not counting the genesis block
+    pub checked: u64,
+    pub corruption: Option<Corruption>,
+    /// the tip has been moved back below the corruption
+    pub truncated: bool,
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "blocks checked: {}", self.checked)?;
+        match &self.corruption {
+            None => writeln!(f, "storage verification passed"),
+            Some(corruption) => {
+                writeln!(f, "block {}: {}", corruption.block, corruption.problem)?;
+                if self.truncated {
+                    writeln!(f, "tip moved back to block {}", corruption.last_sound)?;
+                }
+                writeln!(f, "storage verification failed")
+            }
+        }
+    }
+}
+
+/// walk the main branch of the storage. If `truncate` is set, the tip is
+/// moved back to the last sound block when a corruption is found.
+pub fn run(
+    storage: &mut NodeStorage,
+    block0: &Block,
+    truncate: bool,
+    logger: &Logger,
+) -> Result<Report, Error> {
+    let block0_hash = block0.header.hash();
+    let mut report = Report {
+        checked: 0,
+        corruption: None,
+        truncated: false,
+    };
+    let head = match storage.get_tag(MAIN_BRANCH_TAG)? {
+        None => return Ok(report),
+        Some(head) => head,
+    };
+
+    info!(logger, "verifying the stored blockchain"; "tip" => %head);
+    let mut hash = head;
+    while hash != block0_hash {
+        let (block, info) = match storage.get_block(&hash) {
+            Ok(found) => found,
+            Err(StorageError::BlockNotFound) => {
+                // The blocks below cannot be reached from the tip anymore.
+                report.corruption = Some(Corruption {
+                    block: hash,
+                    problem: Problem::Missing,
+                    last_sound: block0_hash,
+                });
+                break;
+            }
+            Err(e) => return Err(e.into()),
+        };
+        report.checked += 1;
+        let problem = if block.header.hash() != hash {
+            Some(Problem::HashMismatch)
+        } else if block.header.block_parent_hash() != info.parent_id() {
+            Some(Problem::ParentMismatch)
+        } else {
+            None
+        };
+        let parent = info.parent_id();
+        // A corruption found further down the walk supersedes this one.
+        if let Some(problem) = problem {
+            report.corruption = Some(Corruption {
+                block: hash,
+                problem,
+                last_sound: parent,
+            });
+        }
+        hash = parent;
+    }
+
+    if let Some(corruption) = &report.corruption {
+        warn!(
+            logger,
+            "the stored blockchain is corrupted";
+            "block" => %corruption.block,
+            "problem" => %corruption.problem,
+        );
+        if truncate {
+            storage.put_tag(MAIN_BRANCH_TAG, &corruption.last_sound)?;
+            report.truncated = true;
+            warn!(
+                logger,
+                "moved the tip back below the corruption";
+                "tip" => %corruption.last_sound,
+            );
+        }
+    }
+    Ok(report)
+}
+
+impl Report {
+    /// the error to fail with if the storage cannot be used
+    pub fn into_result(self) -> Result<(), Error> {
+        match self.corruption {
+            Some(corruption) if !self.truncated => Err(Error::StorageCorrupted {
+                block: corruption.block,
+                problem: corruption.problem,
+            }),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockcfg::{
+        BlockDate, BlockVersion, ChainLength, Contents, ContentsBuilder, HeaderBuilderNew,
+    };
+    use chain_storage::store::BlockInfo;
+    use chain_storage_sqlite_old::SQLiteBlockStore;
+
+    fn block(parent: &HeaderHash, chain_length: u32, epoch: u32) -> Block {
+        let contents: Contents = ContentsBuilder::new().into();
+        let header = HeaderBuilderNew::new(BlockVersion::Genesis, &contents)
+            .set_parent(parent, ChainLength::from(chain_length))
+            .set_date(BlockDate {
+                epoch,
+                slot_id: chain_length,
+            })
+            .to_unsigned_header()
+            .expect("Valid Header Builder")
+            .generalize();
+        Block { header, contents }
+    }
+
+    // the genesis block and the `length` blocks following it
+    fn chain(length: u32) -> Vec<Block> {
+        let mut blocks = vec![block(&HeaderHash::zero_hash(), 0, 0)];
+        for chain_length in 1..=length {
+            let parent = blocks.last().unwrap().header.hash();
+            blocks.push(block(&parent, chain_length, 0));
+        }
+        blocks
+    }
+
+    // stores `block` under `hash`, recorded with the given parent
+    fn store(storage: &mut NodeStorage, block: &Block, hash: HeaderHash, parent: HeaderHash) {
+        let depth = u32::from(block.header.chain_length()) as u64;
+        storage
+            .put_block_internal(block, BlockInfo::new(hash, parent, depth))
+            .unwrap();
+    }
+
+    // stores the blocks after the genesis block, but the ones for which
+    // `skip` is true, and tags the last one as the tip
+    fn storage(blocks: &[Block], skip: impl Fn(usize) -> bool) -> NodeStorage {
+        let mut storage = SQLiteBlockStore::memory();
+        for (n, block) in blocks.iter().enumerate().skip(1) {
+            if !skip(n) {
+                store(
+                    &mut storage,
+                    block,
+                    block.header.hash(),
+                    block.header.block_parent_hash(),
+                );
+            }
+        }
+        let tip = blocks.last().unwrap().header.hash();
+        storage.put_tag(MAIN_BRANCH_TAG, &tip).unwrap();
+        storage
+    }
+
+    fn verify(storage: &mut NodeStorage, blocks: &[Block], truncate: bool) -> Report {
+        let logger = Logger::root(slog::Discard, o!());
+        run(storage, &blocks[0], truncate, &logger).unwrap()
+    }
+
+    fn corruption(report: &Report) -> (HeaderHash, &'static str, HeaderHash) {
+        let corruption = report.corruption.as_ref().unwrap();
+        let problem = match corruption.problem {
+            Problem::Missing => "missing",
+            Problem::HashMismatch => "hash mismatch",
+            Problem::ParentMismatch => "parent mismatch",
+        };
+        (
+            corruption.block.clone(),
+            problem,
+            corruption.last_sound.clone(),
+        )
+    }
+
+    fn hash(blocks: &[Block], n: usize) -> HeaderHash {
+        blocks[n].header.hash()
+    }
+
+    #[test]
+    fn sound_storage_passes() {
+        let blocks = chain(5);
+        let mut storage = storage(&blocks, |_| false);
+        let report = verify(&mut storage, &blocks, false);
+        assert_eq!(report.checked, 5);
+        assert!(report.corruption.is_none());
+        assert!(report.into_result().is_ok());
+    }
+
+    #[test]
+    fn empty_storage_passes() {
+        let blocks = chain(0);
+        let mut storage = SQLiteBlockStore::memory();
+        let report = verify(&mut storage, &blocks, false);
+        assert_eq!(report.checked, 0);
+        assert!(report.corruption.is_none());
+    }
+
+    #[test]
+    fn missing_block_leaves_only_the_genesis_block_sound() {
+        let blocks = chain(5);
+        let mut storage = storage(&blocks, |n| n == 3);
+        let report = verify(&mut storage, &blocks, false);
+        assert_eq!(report.checked, 2);
+        assert_eq!(
+            corruption(&report),
+            (hash(&blocks, 3), "missing", hash(&blocks, 0))
+        );
+        assert!(report.into_result().is_err());
+    }
+
+    #[test]
+    fn block_not_matching_its_hash_is_found() {
+        let blocks = chain(5);
+        let mut storage = storage(&blocks, |n| n == 2);
+        let other = block(&hash(&blocks, 1), 2, 1);
+        store(&mut storage, &other, hash(&blocks, 2), hash(&blocks, 1));
+        let report = verify(&mut storage, &blocks, false);
+        assert_eq!(report.checked, 5);
+        assert_eq!(
+            corruption(&report),
+            (hash(&blocks, 2), "hash mismatch", hash(&blocks, 1))
+        );
+    }
+
+    #[test]
+    fn block_not_linking_to_its_recorded_parent_is_found() {
+        let blocks = chain(5);
+        let mut storage = storage(&blocks, |n| n == 3);
+        // recorded as a child of block 1, skipping block 2
+        store(&mut storage, &blocks[3], hash(&blocks, 3), hash(&blocks, 1));
+        let report = verify(&mut storage, &blocks, false);
+        assert_eq!(report.checked, 4);
+        assert_eq!(
+            corruption(&report),
+            (hash(&blocks, 3), "parent mismatch", hash(&blocks, 1))
+        );
+    }
+
+    #[test]
+    fn corruption_closest_to_the_genesis_block_is_reported() {
+        let blocks = chain(5);
+        let mut storage = storage(&blocks, |n| n == 2 || n == 4);
+        // block 4 recorded as a child of block 2, which is corrupted too
+        store(&mut storage, &blocks[4], hash(&blocks, 4), hash(&blocks, 2));
+        let other = block(&hash(&blocks, 1), 2, 1);
+        store(&mut storage, &other, hash(&blocks, 2), hash(&blocks, 1));
+        let report = verify(&mut storage, &blocks, false);
+        assert_eq!(report.checked, 4);
+        assert_eq!(
+            corruption(&report),
+            (hash(&blocks, 2), "hash mismatch", hash(&blocks, 1))
+        );
+    }
+
+    #[test]
+    fn tip_is_moved_back_to_the_last_sound_block() {
+        let blocks = chain(5);
+        let mut storage = storage(&blocks, |n| n == 3);
+        store(&mut storage, &blocks[3], hash(&blocks, 3), hash(&blocks, 1));
+        let report = verify(&mut storage, &blocks, true);
+        assert!(report.truncated);
+        assert_eq!(
+            storage.get_tag(MAIN_BRANCH_TAG).unwrap(),
+            Some(hash(&blocks, 1))
+        );
+        assert!(report.into_result().is_ok());
+    }
+}
@@ -1,10 +1,14 @@
+use crate::utils::stats::{Signal, Summary};
 use jormungandr_lib::time::SecondsSinceUnixEpoch;
 use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
 use std::sync::Arc;
-use std::time::Instant;
+use std::time::{Duration, Instant};
 
 const SLOT_START_TIME_UNDEFINED: u64 = u64::max_value();
 
+/// number of samples the smoothed signals are computed over
+const SIGNAL_PERIOD: usize = 64;
+
 #[derive(Clone, Debug, Default)]
 pub struct StatsCounter {
     stats: Arc<StatsCounterImpl>,
@@ -16,6 +20,9 @@ struct StatsCounterImpl {
     block_recv_cnt: AtomicUsize,
     start_time: Instant,
     slot_start_time: AtomicU64,
+    peer_response_time: Signal,
+    mempool_usage: Signal,
+    topology_update_time: Signal,
 }
 
 impl Default for StatsCounterImpl {
@@ -25,6 +32,9 @@ impl Default for StatsCounterImpl {
             block_recv_cnt: AtomicUsize::default(),
             start_time: Instant::now(),
             slot_start_time: AtomicU64::new(SLOT_START_TIME_UNDEFINED),
+            peer_response_time: Signal::new(SIGNAL_PERIOD),
+            mempool_usage: Signal::new(SIGNAL_PERIOD),
+            topology_update_time: Signal::new(SIGNAL_PERIOD),
         }
     }
 }
@@ -69,4 +79,38 @@ impl StatsCounter {
         }
         .map(SecondsSinceUnixEpoch::from_secs)
     }
+
+    /// record the time a peer took to start responding to a request
+    pub fn observe_peer_response_time(&self, time: Duration) {
+        self.stats
+            .peer_response_time
+            .observe(time.as_secs_f64() * 1000.0);
+    }
+
+    /// the time, in milliseconds, the peers take to start responding
+    pub fn peer_response_time(&self) -> Option<Summary> {
+        self.stats.peer_response_time.summary()
+    }
+
+    /// record the number of fragments in the mempool
+    pub fn observe_mempool_usage(&self, entries: usize) {
+        self.stats.mempool_usage.observe(entries as f64);
+    }
+
+    pub fn mempool_usage(&self) -> Option<Summary> {
+        self.stats.mempool_usage.summary()
+    }
+
+    /// record the time a batch of gossip took to be applied to the topology
+    pub fn observe_topology_update_time(&self, time: Duration) {
+        self.stats
+            .topology_update_time
+            .observe(time.as_secs_f64() * 1_000_000.0);
+    }
+
+    /// the time, in microseconds, the batches of gossip take to be
+    /// applied to the topology
+    pub fn topology_update_time(&self) -> Option<Summary> {
+        self.stats.topology_update_time.summary()
+    }
 }
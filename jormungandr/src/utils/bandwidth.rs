@@ -0,0 +1,314 @@
+//! Limiting the rate at which bytes of data are streamed.
+//!
+//! A [`BandwidthLimiter`] is a token bucket refilled at a fixed number
+//! of bytes per second. It is shared between all the streams that have
+//! to fit within the same budget, each of them being wrapped into a
+//! [`Throttled`] adapter which waits for the budget to be replenished
+//! before letting the next item through.
+//!
+//! [`BandwidthLimiter`]: ./struct.BandwidthLimiter.html
+//! [`Throttled`]: ./struct.Throttled.html
+
+use crate::blockcfg::{Block, Fragment, Header};
+use chain_core::property::Serialize;
+use futures::prelude::*;
+use tokio::timer::Delay;
+
+use std::{
+    io,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+/// Size of an item once serialized for the network.
+pub trait WireSize {
+    fn wire_size(&self) -> usize;
+}
+
+/// The number of bytes the item is serialized into.
+pub fn serialized_size<T: Serialize>(item: &T) -> usize {
+    let mut counter = ByteCounter(0);
+    // writing to the counter never fails
+    let _ = item.serialize(&mut counter);
+    counter.0
+}
+
+impl WireSize for Block {
+    fn wire_size(&self) -> usize {
+        serialized_size(self)
+    }
+}
+
+impl WireSize for Header {
+    fn wire_size(&self) -> usize {
+        serialized_size(self)
+    }
+}
+
+impl WireSize for Fragment {
+    fn wire_size(&self) -> usize {
+        serialized_size(self)
+    }
+}
+
+impl<T: WireSize, E> WireSize for Result<T, E> {
+    fn wire_size(&self) -> usize {
+        match self {
+            Ok(item) => item.wire_size(),
+            Err(_) => 0,
+        }
+    }
+}
+
+struct ByteCounter(usize);
+
+impl io::Write for ByteCounter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0 += buf.len();
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Token bucket limiting the number of bytes per second, shared
+/// between all the clones.
+///
+/// The bucket holds at most one second worth of bytes. An item is
+/// let through as long as there is some allowance left, even if it is
+/// larger than the remaining allowance: the bucket then goes into debt
+/// and the following items wait until it is paid back.
+#[derive(Clone)]
+pub struct BandwidthLimiter {
+    bucket: Arc<Mutex<Bucket>>,
+}
+
+struct Bucket {
+    bytes_per_sec: u64,
+    allowance: i64,
+    last_update: Instant,
+}
+
+impl BandwidthLimiter {
+    pub fn new(bytes_per_sec: u64) -> Self {
+        Self::new_at(bytes_per_sec, Instant::now())
+    }
+
+    fn new_at(bytes_per_sec: u64, now: Instant) -> Self {
+        assert!(bytes_per_sec > 0, "bandwidth limit cannot be 0");
+        BandwidthLimiter {
+            bucket: Arc::new(Mutex::new(Bucket {
+                bytes_per_sec,
+                allowance: bytes_per_sec as i64,
+                last_update: now,
+            })),
+        }
+    }
+
+    /// wrap the stream or sink so items are only passed at the rate
+    /// allowed by this limiter
+    pub fn throttle<T>(&self, inner: T) -> Throttled<T> {
+        Throttled {
+            inner,
+            limiter: self.clone(),
+            delay: None,
+        }
+    }
+
+    /// returns `None` if some bytes can be sent at `now`, or the
+    /// instant at which the allowance will be replenished.
+    fn available_at(&self, now: Instant) -> Option<Instant> {
+        let mut bucket = self.bucket.lock().unwrap();
+        bucket.refill(now);
+        if bucket.allowance > 0 {
+            None
+        } else {
+            let deficit = (1 - bucket.allowance) as u64;
+            let wait_nanos = deficit.saturating_mul(1_000_000_000) / bucket.bytes_per_sec;
+            Some(now + Duration::from_nanos(wait_nanos))
+        }
+    }
+
+    fn consume(&self, bytes: usize) {
+        let mut bucket = self.bucket.lock().unwrap();
+        bucket.allowance = bucket.allowance.saturating_sub(bytes as i64);
+    }
+}
+
+impl Bucket {
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.duration_since(self.last_update);
+        let refill = elapsed.as_nanos() * self.bytes_per_sec as u128 / 1_000_000_000;
+        if refill > 0 {
+            // up to a full bucket, paying back the debt first
+            let allowance = i128::from(self.allowance);
+            let room = (i128::from(self.bytes_per_sec) - allowance) as u128;
+            self.allowance = (allowance + std::cmp::min(refill, room) as i128) as i64;
+            self.last_update = now;
+        }
+    }
+}
+
+/// Stream or sink adapter passing the items at the rate allowed by
+/// a [`BandwidthLimiter`].
+///
+/// [`BandwidthLimiter`]: ./struct.BandwidthLimiter.html
+pub struct Throttled<T> {
+    inner: T,
+    limiter: BandwidthLimiter,
+    delay: Option<Delay>,
+}
+
+impl<T> Throttled<T> {
+    fn poll_allowance(&mut self) -> Async<()> {
+        loop {
+            if let Some(delay) = self.delay.as_mut() {
+                match delay.poll() {
+                    Ok(Async::NotReady) => return Async::NotReady,
+                    Ok(Async::Ready(())) => self.delay = None,
+                    // Failing the stream because of the timer is not
+                    // worth it, let the item through.
+                    Err(_) => {
+                        self.delay = None;
+                        return Async::Ready(());
+                    }
+                }
+            }
+            match self.limiter.available_at(Instant::now()) {
+                None => return Async::Ready(()),
+                Some(deadline) => self.delay = Some(Delay::new(deadline)),
+            }
+        }
+    }
+}
+
+impl<T> Stream for Throttled<T>
+where
+    T: Stream,
+    T::Item: WireSize,
+{
+    type Item = T::Item;
+    type Error = T::Error;
+
+    fn poll(&mut self) -> Poll<Option<T::Item>, T::Error> {
+        if let Async::NotReady = self.poll_allowance() {
+            return Ok(Async::NotReady);
+        }
+        let item = try_ready!(self.inner.poll());
+        if let Some(item) = &item {
+            self.limiter.consume(item.wire_size());
+        }
+        Ok(Async::Ready(item))
+    }
+}
+
+impl<T> Sink for Throttled<T>
+where
+    T: Sink,
+    T::SinkItem: WireSize,
+{
+    type SinkItem = T::SinkItem;
+    type SinkError = T::SinkError;
+
+    fn start_send(&mut self, item: T::SinkItem) -> StartSend<T::SinkItem, T::SinkError> {
+        if let Async::NotReady = self.poll_allowance() {
+            return Ok(AsyncSink::NotReady(item));
+        }
+        let size = item.wire_size();
+        let res = self.inner.start_send(item)?;
+        if let AsyncSink::Ready = res {
+            self.limiter.consume(size);
+        }
+        Ok(res)
+    }
+
+    fn poll_complete(&mut self) -> Poll<(), T::SinkError> {
+        self.inner.poll_complete()
+    }
+
+    fn close(&mut self) -> Poll<(), T::SinkError> {
+        self.inner.close()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn millis(ms: u64) -> Duration {
+        Duration::from_millis(ms)
+    }
+
+    fn allowance(limiter: &BandwidthLimiter) -> i64 {
+        limiter.bucket.lock().unwrap().allowance
+    }
+
+    #[test]
+    fn full_bucket_lets_bytes_through() {
+        let start = Instant::now();
+        let limiter = BandwidthLimiter::new_at(1000, start);
+        assert_eq!(limiter.available_at(start), None);
+        limiter.consume(999);
+        assert_eq!(limiter.available_at(start), None);
+    }
+
+    #[test]
+    fn empty_bucket_waits_for_the_refill() {
+        let start = Instant::now();
+        let limiter = BandwidthLimiter::new_at(1000, start);
+        limiter.consume(1000);
+        // one byte is due after a millisecond
+        assert_eq!(limiter.available_at(start), Some(start + millis(1)));
+        assert_eq!(limiter.available_at(start + millis(1)), None);
+    }
+
+    #[test]
+    fn large_item_puts_the_bucket_in_debt() {
+        let start = Instant::now();
+        let limiter = BandwidthLimiter::new_at(1000, start);
+        limiter.consume(1500);
+        assert_eq!(allowance(&limiter), -500);
+        // the debt is paid back before the next byte is let through
+        assert_eq!(limiter.available_at(start), Some(start + millis(501)));
+        assert_eq!(
+            limiter.available_at(start + millis(500)),
+            Some(start + millis(501))
+        );
+        assert_eq!(limiter.available_at(start + millis(501)), None);
+        assert_eq!(allowance(&limiter), 1);
+    }
+
+    #[test]
+    fn bucket_refills_at_the_rate() {
+        let start = Instant::now();
+        let limiter = BandwidthLimiter::new_at(1000, start);
+        limiter.consume(1000);
+        limiter.available_at(start + millis(250));
+        assert_eq!(allowance(&limiter), 250);
+        limiter.available_at(start + millis(750));
+        assert_eq!(allowance(&limiter), 750);
+    }
+
+    #[test]
+    fn bucket_holds_at_most_one_second_of_bytes() {
+        let start = Instant::now();
+        let limiter = BandwidthLimiter::new_at(1000, start);
+        limiter.consume(1000);
+        limiter.available_at(start + millis(10_000));
+        assert_eq!(allowance(&limiter), 1000);
+    }
+
+    #[test]
+    fn deep_debt_is_repaid_at_the_rate() {
+        let start = Instant::now();
+        let limiter = BandwidthLimiter::new_at(1000, start);
+        limiter.consume(5000);
+        assert_eq!(limiter.available_at(start), Some(start + millis(4001)));
+        limiter.available_at(start + millis(2000));
+        assert_eq!(allowance(&limiter), -2000);
+        limiter.available_at(start + millis(4001));
+        assert_eq!(allowance(&limiter), 1);
+    }
+}
@@ -1,4 +1,8 @@
 pub mod async_msg;
+pub mod bandwidth;
 pub mod borrow;
 pub mod fire_forget_scheduler;
+pub mod rate_limit;
+pub mod rng;
+pub mod stats;
 pub mod task;
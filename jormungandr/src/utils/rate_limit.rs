@@ -0,0 +1,171 @@
+//! Limiting the rate at which fragments are accepted from each source.
+//!
+//! A [`RateLimiter`] keeps a token bucket for every source, such as the
+//! IP address of a REST client or of a peer, refilled at a fixed number
+//! of fragments per second up to a burst capacity. The fragments in
+//! excess are dropped before they reach the pool.
+//!
+//! [`RateLimiter`]: ./struct.RateLimiter.html
+
+use std::{
+    collections::HashMap,
+    hash::Hash,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+/// how often the full buckets are forgotten, as a new bucket for the same
+/// source would be full as well
+const PRUNE_INTERVAL: Duration = Duration::from_secs(60);
+
+const NANOS_PER_SEC: u128 = 1_000_000_000;
+
+/// Token buckets limiting the number of fragments per second of each
+/// source, shared between all the clones.
+#[derive(Clone)]
+pub struct RateLimiter<K> {
+    inner: Arc<Mutex<Inner<K>>>,
+}
+
+struct Inner<K> {
+    per_sec: u64,
+    burst: u64,
+    buckets: HashMap<K, Bucket>,
+    next_prune: Instant,
+}
+
+struct Bucket {
+    allowance: u64,
+    last_update: Instant,
+}
+
+impl<K: Hash + Eq> RateLimiter<K> {
+    pub fn new(per_sec: u32, burst: u32) -> Self {
+        assert!(per_sec > 0, "fragment rate limit cannot be 0");
+        RateLimiter {
+            inner: Arc::new(Mutex::new(Inner {
+                per_sec: per_sec.into(),
+                // at least one fragment has to fit in the bucket
+                burst: std::cmp::max(burst, 1).into(),
+                buckets: HashMap::new(),
+                next_prune: Instant::now() + PRUNE_INTERVAL,
+            })),
+        }
+    }
+
+    /// take the allowance for `count` fragments from the bucket of the
+    /// source, returning the number of them that can be accepted
+    pub fn admit(&self, source: K, count: usize) -> usize {
+        self.admit_at(source, count, Instant::now())
+    }
+
+    fn admit_at(&self, source: K, count: usize, now: Instant) -> usize {
+        let mut inner = self.inner.lock().unwrap();
+        if now >= inner.next_prune {
+            inner.prune(now);
+            inner.next_prune = now + PRUNE_INTERVAL;
+        }
+        let (per_sec, burst) = (inner.per_sec, inner.burst);
+        let bucket = inner.buckets.entry(source).or_insert(Bucket {
+            allowance: burst,
+            last_update: now,
+        });
+        bucket.refill(now, per_sec, burst);
+        let admitted = std::cmp::min(bucket.allowance, count as u64);
+        bucket.allowance -= admitted;
+        admitted as usize
+    }
+}
+
+impl<K: Hash + Eq> Inner<K> {
+    fn prune(&mut self, now: Instant) {
+        let (per_sec, burst) = (self.per_sec, self.burst);
+        self.buckets.retain(|_, bucket| {
+            bucket.refill(now, per_sec, burst);
+            bucket.allowance < burst
+        });
+    }
+}
+
+impl Bucket {
+    fn refill(&mut self, now: Instant, per_sec: u64, burst: u64) {
+        if self.allowance >= burst {
+            // a full bucket does not save up the time passing
+            self.last_update = now;
+            return;
+        }
+        let elapsed = now.duration_since(self.last_update);
+        let refill = elapsed.as_nanos() * u128::from(per_sec) / NANOS_PER_SEC;
+        if refill >= u128::from(burst - self.allowance) {
+            self.allowance = burst;
+            self.last_update = now;
+        } else if refill > 0 {
+            self.allowance += refill as u64;
+            // the time left over counts toward the next fragment
+            let spent = refill * NANOS_PER_SEC / u128::from(per_sec);
+            self.last_update += Duration::from_nanos(spent as u64);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn millis(ms: u64) -> Duration {
+        Duration::from_millis(ms)
+    }
+
+    #[test]
+    fn burst_then_rate() {
+        let limiter = RateLimiter::new(2, 5);
+        let start = Instant::now();
+        assert_eq!(limiter.admit_at(1, 10, start), 5);
+        assert_eq!(limiter.admit_at(1, 1, start), 0);
+        assert_eq!(limiter.admit_at(1, 10, start + millis(1000)), 2);
+    }
+
+    #[test]
+    fn sources_have_their_own_bucket() {
+        let limiter = RateLimiter::new(1, 1);
+        let start = Instant::now();
+        assert_eq!(limiter.admit_at(1, 1, start), 1);
+        assert_eq!(limiter.admit_at(1, 1, start), 0);
+        assert_eq!(limiter.admit_at(2, 1, start), 1);
+    }
+
+    #[test]
+    fn fractional_refill_is_kept() {
+        let limiter = RateLimiter::new(3, 10);
+        let start = Instant::now();
+        assert_eq!(limiter.admit_at(1, 10, start), 10);
+        // 1.5 fragments are due, one is given and the half is kept
+        assert_eq!(limiter.admit_at(1, 10, start + millis(500)), 1);
+        assert_eq!(limiter.admit_at(1, 10, start + millis(1000)), 2);
+    }
+
+    #[test]
+    fn full_bucket_does_not_save_up() {
+        let limiter = RateLimiter::new(1, 2);
+        let start = Instant::now();
+        assert_eq!(limiter.admit_at(1, 1, start), 1);
+        // ten seconds only fill up the bucket
+        assert_eq!(limiter.admit_at(1, 1, start + millis(10_000)), 1);
+        assert_eq!(limiter.admit_at(1, 10, start + millis(10_000)), 1);
+        assert_eq!(limiter.admit_at(1, 10, start + millis(10_500)), 0);
+    }
+
+    #[test]
+    fn full_buckets_are_pruned_periodically() {
+        let limiter = RateLimiter::new(1, 1);
+        let start = Instant::now();
+        for source in 0..100 {
+            limiter.admit_at(source, 1, start);
+        }
+        // the buckets have refilled, but are only pruned at the interval
+        assert_eq!(limiter.admit_at(0, 0, start + millis(1000)), 0);
+        assert_eq!(limiter.inner.lock().unwrap().buckets.len(), 100);
+        limiter.admit_at(0, 0, start + PRUNE_INTERVAL + millis(1000));
+        assert_eq!(limiter.inner.lock().unwrap().buckets.len(), 1);
+    }
+}
@@ -0,0 +1,60 @@
+//! Source of the random choices of the node.
+//!
+//! The node draws from the thread-local generator by default. A seed can be
+//! given in the configuration, for the tests only: all the choices are then
+//! drawn from a single generator seeded with it, so that a run of the node
+//! in the simulation harness can be reproduced.
+
+use rand::{rngs::StdRng, RngCore, SeedableRng};
+use std::sync::{Arc, Mutex};
+
+#[derive(Clone, Default)]
+pub struct RngProvider {
+    seeded: Option<Arc<Mutex<StdRng>>>,
+}
+
+impl RngProvider {
+    /// a provider of the thread-local generator
+    pub fn random() -> Self {
+        RngProvider { seeded: None }
+    }
+
+    /// a provider of a single generator, seeded with `seed`
+    pub fn seeded(seed: u64) -> Self {
+        RngProvider {
+            seeded: Some(Arc::new(Mutex::new(StdRng::seed_from_u64(seed)))),
+        }
+    }
+
+    pub fn with_rng<F, T>(&self, f: F) -> T
+    where
+        F: FnOnce(&mut dyn RngCore) -> T,
+    {
+        match &self.seeded {
+            Some(rng) => f(&mut *rng.lock().unwrap()),
+            None => f(&mut rand::thread_rng()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::Rng;
+
+    #[test]
+    fn seeded_providers_draw_the_same_values() {
+        let draw = |provider: &RngProvider| {
+            (0..8)
+                .map(|_| provider.with_rng(|rng| rng.gen_range(0u32, 1000)))
+                .collect::<Vec<_>>()
+        };
+        let first = RngProvider::seeded(42);
+        let second = RngProvider::seeded(42);
+        assert_eq!(draw(&first), draw(&second));
+
+        // clones share the generator, they continue the same sequence
+        let clone = first.clone();
+        assert_ne!(draw(&first), draw(&clone));
+    }
+}
@@ -0,0 +1,150 @@
+//! Smoothed statistics over noisy signals.
+//!
+//! The adaptive parts of the node react to signals that vary a lot from
+//! one sample to the next (view churn, response times of the peers,
+//! mempool usage...). The types here all take samples with `observe` and
+//! give a smoothed view of the signal, `None` until the first sample.
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+/// Exponential moving average.
+#[derive(Clone, Debug)]
+pub struct Ema {
+    alpha: f64,
+    value: Option<f64>,
+}
+
+impl Ema {
+    /// an average giving the weight `alpha` to every new sample,
+    /// `alpha` being between 0 (exclusive) and 1 (inclusive).
+    pub fn new(alpha: f64) -> Self {
+        assert!(alpha > 0.0 && alpha <= 1.0, "EMA weight out of range");
+        Ema { alpha, value: None }
+    }
+
+    /// an average with roughly the same smoothing as a simple moving
+    /// average over `period` samples.
+    pub fn with_period(period: usize) -> Self {
+        Self::new(2.0 / (period as f64 + 1.0))
+    }
+
+    pub fn observe(&mut self, sample: f64) {
+        self.value = Some(match self.value {
+            None => sample,
+            Some(value) => value + self.alpha * (sample - value),
+        });
+    }
+
+    pub fn value(&self) -> Option<f64> {
+        self.value
+    }
+}
+
+/// Quantiles over a window of the latest samples.
+#[derive(Clone, Debug)]
+pub struct Quantiles {
+    window: VecDeque<f64>,
+    capacity: usize,
+}
+
+impl Quantiles {
+    pub fn new(capacity: usize) -> Self {
+        assert!(capacity > 0, "quantile window must not be empty");
+        Quantiles {
+            window: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    pub fn observe(&mut self, sample: f64) {
+        if self.window.len() == self.capacity {
+            self.window.pop_front();
+        }
+        self.window.push_back(sample);
+    }
+
+    /// the `q` quantile of the samples in the window, `q` being
+    /// between 0 and 1.
+    pub fn quantile(&self, q: f64) -> Option<f64> {
+        if self.window.is_empty() {
+            return None;
+        }
+        let mut sorted: Vec<f64> = self.window.iter().cloned().collect();
+        sorted.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+        let rank = (q.max(0.0).min(1.0) * (sorted.len() - 1) as f64).round() as usize;
+        Some(sorted[rank])
+    }
+}
+
+/// Summary of a signal, as given by `Signal::summary`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Summary {
+    pub average: f64,
+    pub median: f64,
+    pub p90: f64,
+}
+
+/// Both the moving average and the quantiles of a signal, shared between
+/// the tasks sampling it and the ones reading it.
+#[derive(Clone, Debug)]
+pub struct Signal {
+    inner: Arc<Mutex<(Ema, Quantiles)>>,
+}
+
+impl Signal {
+    pub fn new(period: usize) -> Self {
+        Signal {
+            inner: Arc::new(Mutex::new((
+                Ema::with_period(period),
+                Quantiles::new(period),
+            ))),
+        }
+    }
+
+    pub fn observe(&self, sample: f64) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.0.observe(sample);
+        inner.1.observe(sample);
+    }
+
+    pub fn summary(&self) -> Option<Summary> {
+        let inner = self.inner.lock().unwrap();
+        Some(Summary {
+            average: inner.0.value()?,
+            median: inner.1.quantile(0.5)?,
+            p90: inner.1.quantile(0.9)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ema_starts_at_first_sample_and_converges() {
+        let mut ema = Ema::new(0.5);
+        assert_eq!(ema.value(), None);
+        ema.observe(10.0);
+        assert_eq!(ema.value(), Some(10.0));
+        ema.observe(20.0);
+        assert_eq!(ema.value(), Some(15.0));
+        for _ in 0..50 {
+            ema.observe(20.0);
+        }
+        assert!((ema.value().unwrap() - 20.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn quantiles_only_consider_the_window() {
+        let mut quantiles = Quantiles::new(5);
+        assert_eq!(quantiles.quantile(0.5), None);
+        for sample in &[100.0, 1.0, 2.0, 3.0, 4.0, 5.0] {
+            quantiles.observe(*sample);
+        }
+        assert_eq!(quantiles.quantile(0.0), Some(1.0));
+        assert_eq!(quantiles.quantile(0.5), Some(3.0));
+        assert_eq!(quantiles.quantile(1.0), Some(5.0));
+    }
+}
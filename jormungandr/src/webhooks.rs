@@ -0,0 +1,227 @@
+//! Notification of the chain events to HTTP endpoints.
+//!
+//! Every event is POSTed as a JSON object to each of the configured URLs:
+//! a new tip, the first tip of a new epoch, and a switch to another branch
+//! rolling back at least the configured number of blocks. The events are
+//! delivered to a URL one at a time, in the order they happen. A failed
+//! delivery is retried after a delay doubling at every attempt, and the
+//! event is dropped after the configured number of attempts. The events
+//! are also dropped while too many of them wait for the delivery to a URL.
+
+use crate::{
+    blockcfg::Header,
+    blockchain::{Storage, Tip},
+    settings::start::Webhooks,
+    utils::task::TokioServiceInfo,
+};
+use futures::{
+    future::{Either, Loop},
+    sync::mpsc,
+};
+use reqwest::{r#async::Client, Url};
+use slog::Logger;
+use std::{
+    cmp,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::{prelude::*, timer::Delay};
+use tokio_threadpool::Builder;
+
+/// number of events waiting for the delivery to a URL, the new events are
+/// dropped past it
+const QUEUE_LEN: usize = 1024;
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+const INITIAL_RETRY_DELAY: Duration = Duration::from_secs(1);
+
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(60);
+
+#[derive(Serialize)]
+#[serde(tag = "event", rename_all = "camelCase")]
+enum Event {
+    #[serde(rename_all = "camelCase")]
+    NewTip {
+        hash: String,
+        parent: String,
+        chain_length: u32,
+        date: String,
+    },
+    #[serde(rename_all = "camelCase")]
+    EpochTransition { epoch: u32, tip: String },
+    #[serde(rename_all = "camelCase")]
+    DeepReorg {
+        depth: u64,
+        previous_tip: String,
+        tip: String,
+    },
+}
+
+pub fn notify(
+    service_info: TokioServiceInfo,
+    storage: Storage,
+    blockchain_tip: Tip,
+    webhooks: Webhooks,
+) -> impl Future<Item = (), Error = ()> {
+    let logger = service_info.logger().clone();
+    let client = match Client::builder().timeout(REQUEST_TIMEOUT).build() {
+        Ok(client) => client,
+        Err(e) => {
+            error!(logger, "cannot initialize the HTTP client"; "reason" => %e);
+            return Either::A(future::err(()));
+        }
+    };
+
+    let max_attempts = webhooks.max_attempts;
+    let deep_reorg_depth = u64::from(webhooks.deep_reorg_depth);
+    let senders = webhooks
+        .urls
+        .into_iter()
+        .map(|url| {
+            let (sender, receiver) = mpsc::channel(QUEUE_LEN);
+            let client = client.clone();
+            let logger = logger.new(o!("webhook" => url.to_string()));
+            service_info.spawn(
+                "webhook delivery",
+                receiver.for_each({
+                    let logger = logger.clone();
+                    move |event| deliver(&client, &url, event, max_attempts, logger.clone())
+                }),
+            );
+            (sender, logger)
+        })
+        .collect::<Vec<_>>();
+
+    // the depth of a branch switch is found by walking the storage, out
+    // of the executor threads
+    let pool = Builder::new().pool_size(1).name_prefix("webhooks").build();
+
+    // subscribed before the current tip is read, for no update to be missed
+    let updates = blockchain_tip.subscribe();
+    Either::B(blockchain_tip.get_ref().and_then(move |tip| {
+        let mut previous = tip.header().clone();
+        let mut senders = senders;
+        updates
+            .map(move |header| (std::mem::replace(&mut previous, header.clone()), header))
+            .and_then(move |(previous, header)| {
+                let storage = storage.clone();
+                let logger = logger.clone();
+                pool.spawn_handle(future::lazy(move || {
+                    Ok(events(
+                        &storage,
+                        &previous,
+                        &header,
+                        deep_reorg_depth,
+                        &logger,
+                    ))
+                }))
+            })
+            .for_each(move |events| {
+                for event in events {
+                    let event = Arc::new(event);
+                    for (sender, logger) in senders.iter_mut() {
+                        // the delivery task only ends with the node, the
+                        // queue is full
+                        if sender.try_send(event.clone()).is_err() {
+                            warn!(
+                                logger,
+                                "too many chain events waiting for delivery, dropping one"
+                            );
+                        }
+                    }
+                }
+                Ok(())
+            })
+    }))
+}
+
+fn events(
+    storage: &Storage,
+    previous: &Header,
+    tip: &Header,
+    deep_reorg_depth: u64,
+    logger: &Logger,
+) -> Vec<Event> {
+    let mut events = vec![Event::NewTip {
+        hash: tip.hash().to_string(),
+        parent: tip.block_parent_hash().to_string(),
+        chain_length: tip.chain_length().into(),
+        date: tip.block_date().to_string(),
+    }];
+    if tip.block_date().epoch > previous.block_date().epoch {
+        events.push(Event::EpochTransition {
+            epoch: tip.block_date().epoch,
+            tip: tip.hash().to_string(),
+        });
+    }
+    if tip.block_parent_hash() != previous.hash() {
+        // the walk stops past the depth to report, a deeper switch is
+        // reported with that depth
+        match storage
+            .rollback_depth(previous.hash(), tip.hash(), deep_reorg_depth)
+            .wait()
+        {
+            Ok(depth) if depth >= deep_reorg_depth => events.push(Event::DeepReorg {
+                depth: deep_reorg_depth,
+                previous_tip: previous.hash().to_string(),
+                tip: tip.hash().to_string(),
+            }),
+            Ok(_) => {}
+            Err(e) => warn!(logger, "cannot find the depth of the branch switch"; "reason" => %e),
+        }
+    }
+    events
+}
+
+fn deliver(
+    client: &Client,
+    url: &Url,
+    event: Arc<Event>,
+    max_attempts: u32,
+    logger: Logger,
+) -> impl Future<Item = (), Error = ()> {
+    let client = client.clone();
+    let url = url.clone();
+    future::loop_fn(1, move |attempt| {
+        let logger = logger.clone();
+        client
+            .post(url.clone())
+            .json(&*event)
+            .send()
+            .and_then(|response| response.error_for_status())
+            .then(move |res| match res {
+                Ok(_) => Either::A(future::ok(Loop::Break(()))),
+                Err(e) if attempt >= max_attempts => {
+                    warn!(
+                        logger,
+                        "giving up the delivery of a chain event";
+                        "attempts" => attempt,
+                        "reason" => %e,
+                    );
+                    Either::A(future::ok(Loop::Break(())))
+                }
+                Err(e) => {
+                    let delay = retry_delay(attempt);
+                    debug!(
+                        logger,
+                        "failed to deliver a chain event";
+                        "attempt" => attempt,
+                        "retry_in" => ?delay,
+                        "reason" => %e,
+                    );
+                    Either::B(
+                        Delay::new(Instant::now() + delay)
+                            .map(move |()| Loop::Continue(attempt + 1))
+                            .map_err(move |e| error!(logger, "timer error: {}", e)),
+                    )
+                }
+            })
+    })
+}
+
+// the delay doubles at every failed attempt, up to the maximum
+fn retry_delay(attempt: u32) -> Duration {
+    let doublings = cmp::min(attempt.saturating_sub(1), 16);
+    cmp::min(INITIAL_RETRY_DELAY * 2u32.pow(doublings), MAX_RETRY_DELAY)
+}